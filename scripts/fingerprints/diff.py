@@ -0,0 +1,119 @@
+from __future__ import print_function
+import argparse
+import socket
+import sys
+import textwrap
+
+
+DEFAULT_TIMEOUT = 30.0  # seconds, a full export of a large instance is slow
+
+
+def parse(lines):
+  """parse the lines of an export into a dict of key hash -> (value hash, ttl bucket)"""
+  fingerprints = {}
+  for line in lines:
+    line = line.strip()
+    if not line:
+      continue
+    if line == 'END':
+      return fingerprints
+    fields = line.split()
+    if len(fields) != 4 or fields[0] != 'FINGERPRINT':
+      raise ValueError('unexpected line in export: {}'.format(line))
+    fingerprints[fields[1]] = (fields[2], int(fields[3]))
+  raise ValueError('export is truncated, it does not end with END')
+
+
+def fetch(address, sample, timeout):
+  """request an export from the admin port of a running instance"""
+  host, port = address.rsplit(':', 1)
+  command = 'fingerprints\r\n' if sample <= 1 else 'fingerprints sample {}\r\n'.format(sample)
+  conn = socket.create_connection((host, int(port)), timeout=timeout)
+  try:
+    conn.sendall(command.encode())
+    data = b''
+    while not data.endswith(b'END\r\n'):
+      chunk = conn.recv(65536)
+      if not chunk:
+        break
+      data += chunk
+  finally:
+    conn.close()
+  response = data.decode()
+  if response.startswith(('ERROR', 'CLIENT_ERROR', 'SERVER_ERROR')):
+    raise ValueError('{} replied: {}'.format(address, response.strip()))
+  return parse(response.splitlines())
+
+
+def load(source, sample, timeout):
+  """a source is either the path of a saved export or host:port of an admin port"""
+  try:
+    with open(source) as f:
+      return parse(f)
+  except (IOError, OSError):
+    if ':' not in source:
+      raise
+  return fetch(source, sample, timeout)
+
+
+def diff(left, right, ignore_ttl):
+  """compare two exports, returning the key hashes which differ by kind"""
+  result = {'missing_left': [], 'missing_right': [], 'value': [], 'ttl': []}
+  for key, (value, bucket) in left.items():
+    if key not in right:
+      result['missing_right'].append(key)
+    elif right[key][0] != value:
+      result['value'].append(key)
+    elif right[key][1] != bucket and not ignore_ttl:
+      result['ttl'].append(key)
+  result['missing_left'] = [key for key in right if key not in left]
+  for keys in result.values():
+    keys.sort()
+  return result
+
+
+def report(names, left, right, result, verbose):
+  print('{} fingerprints in {}, {} in {}'.format(len(left), names[0], len(right), names[1]))
+  descriptions = [
+    ('missing_right', 'only in {}'.format(names[0])),
+    ('missing_left', 'only in {}'.format(names[1])),
+    ('value', 'with different values'),
+    ('ttl', 'with different ttl buckets'),
+  ]
+  for kind, description in descriptions:
+    print('{:>10} keys {}'.format(len(result[kind]), description))
+    if verbose:
+      for key in result[kind]:
+        print('  {}'.format(key))
+
+
+parser = argparse.ArgumentParser(
+  formatter_class=argparse.RawDescriptionHelpFormatter,
+  description=textwrap.dedent("""
+    This script compares the item fingerprints exported by two pelikan instances, to check
+    that a replica or the destination of a migration has converged with its source.\n
+
+    Each side is either a file saved from the `fingerprints` admin command, or the host:port
+    of an admin port to fetch the export from. Both sides must use the same sample rate.
+    Exits with status 1 if the exports differ.
+    """),
+  usage='%(prog)s [options] left right')
+
+parser.add_argument('left', help='export file or admin host:port')
+parser.add_argument('right', help='export file or admin host:port')
+parser.add_argument('--sample', dest='sample', type=int, default=1,
+                    help='fetch one in every SAMPLE keys from an admin port, default all keys')
+parser.add_argument('--ignore-ttl', dest='ignore_ttl', action='store_true',
+                    help='do not report keys which differ only in their ttl bucket')
+parser.add_argument('--timeout', dest='timeout', type=float, default=DEFAULT_TIMEOUT,
+                    help='socket timeout in seconds when fetching from an admin port')
+parser.add_argument('--verbose', '-v', dest='verbose', action='store_true',
+                    help='list the hash of every key which differs')
+
+if __name__ == "__main__":
+  args = parser.parse_args()
+  left = load(args.left, args.sample, args.timeout)
+  right = load(args.right, args.sample, args.timeout)
+  result = diff(left, right, args.ignore_ttl)
+  report((args.left, args.right), left, right, result, args.verbose)
+  sys.exit(1 if any(result.values()) else 0)
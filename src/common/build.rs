@@ -0,0 +1,119 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Embeds information about the build, which is reported by the `buildinfo`
+//! admin command. See `src/buildinfo.rs`.
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // a build from a source archive has no repository, so the commit may be
+    // given by the environment instead
+    let commit = std::env::var("PELIKAN_GIT_COMMIT")
+        .ok()
+        .or_else(git_commit)
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc = output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+
+    let target = std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_string());
+    let profile = std::env::var("PROFILE").unwrap_or_else(|_| "unknown".to_string());
+
+    // the timestamp is fixed for reproducible builds
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        });
+
+    println!("cargo:rustc-env=PELIKAN_BUILD_COMMIT={}", commit);
+    println!("cargo:rustc-env=PELIKAN_BUILD_RUSTC={}", rustc);
+    println!("cargo:rustc-env=PELIKAN_BUILD_TARGET={}", target);
+    println!("cargo:rustc-env=PELIKAN_BUILD_PROFILE={}", profile);
+    println!(
+        "cargo:rustc-env=PELIKAN_BUILD_TIMESTAMP={}",
+        rfc3339(timestamp)
+    );
+
+    println!("cargo:rerun-if-env-changed=PELIKAN_GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    // rebuild when a commit is made or another branch is checked out. A path
+    // which does not exist would cause a rebuild every time, so only the
+    // files which do are watched.
+    watch(output("git", &["rev-parse", "--git-path", "HEAD"]));
+    if let Some(head) = output("git", &["symbolic-ref", "-q", "HEAD"]) {
+        watch(output("git", &["rev-parse", "--git-path", &head]));
+    }
+}
+
+// the commit which is checked out, marked as dirty if there were changes to
+// tracked files which had not been committed when this crate was last built
+fn git_commit() -> Option<String> {
+    let commit = output("git", &["rev-parse", "HEAD"])?;
+    let clean = Command::new("git")
+        .args(["diff-index", "--quiet", "HEAD", "--"])
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(true);
+
+    if clean {
+        Some(commit)
+    } else {
+        Some(format!("{}-dirty", commit))
+    }
+}
+
+// runs the command, returning its output if it succeeded
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let output = String::from_utf8(output.stdout).ok()?;
+    Some(output.trim().to_string())
+}
+
+fn watch(path: Option<String>) {
+    if let Some(path) = path {
+        if Path::new(&path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
+
+// formats seconds since the unix epoch as a UTC time, such as
+// `2022-09-01T17:30:00Z`, using the days-to-civil algorithm from
+// http://howardhinnant.github.io/date_algorithms.html
+fn rfc3339(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,36 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Information about the build which produced the binary, embedded at compile
+//! time by the build script. The version of a service alone does not identify
+//! which source and toolchain a binary was built from.
+
+/// The git commit which was built, with a `-dirty` suffix if there were
+/// uncommitted changes, or `unknown` if it was built outside a repository.
+pub const COMMIT: &str = env!("PELIKAN_BUILD_COMMIT");
+
+/// The time of the build in UTC, as an RFC 3339 timestamp. This is taken from
+/// `SOURCE_DATE_EPOCH` when it is set.
+pub const TIMESTAMP: &str = env!("PELIKAN_BUILD_TIMESTAMP");
+
+/// The target triple, such as `x86_64-unknown-linux-gnu`.
+pub const TARGET: &str = env!("PELIKAN_BUILD_TARGET");
+
+/// The cargo profile, which is `release` or `debug`.
+pub const PROFILE: &str = env!("PELIKAN_BUILD_PROFILE");
+
+/// The output of `rustc --version` for the compiler which was used.
+pub const RUSTC: &str = env!("PELIKAN_BUILD_RUSTC");
+
+/// Each field of the build information with its name, in the order they are
+/// reported.
+pub fn fields() -> [(&'static str, &'static str); 5] {
+    [
+        ("commit", COMMIT),
+        ("timestamp", TIMESTAMP),
+        ("target", TARGET),
+        ("profile", PROFILE),
+        ("rustc", RUSTC),
+    ]
+}
@@ -0,0 +1,177 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Classic daemon process management for the server binaries: detaching from
+//! the controlling terminal, redirecting the standard output and error streams
+//! to files, and writing a pidfile. These must be applied before any threads
+//! are started, as only the calling thread is carried over by a fork.
+//!
+//! The working directory is not changed, so that relative paths in the
+//! configuration continue to refer to the same files.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Error, Result, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// The daemon options for a process, which are applied by `start()`.
+#[derive(Default)]
+pub struct Daemon {
+    daemonize: bool,
+    pid_filename: Option<PathBuf>,
+    stdout: Option<PathBuf>,
+    stderr: Option<PathBuf>,
+}
+
+impl Daemon {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Detach from the controlling terminal and continue in the background.
+    pub fn daemonize(mut self, daemonize: bool) -> Self {
+        self.daemonize = daemonize;
+        self
+    }
+
+    /// Write the id of the process to this file once it has started.
+    pub fn pid_filename<T: AsRef<Path>>(mut self, path: Option<T>) -> Self {
+        self.pid_filename = path.map(|p| p.as_ref().to_owned());
+        self
+    }
+
+    /// Append the standard output stream to this file.
+    pub fn stdout<T: AsRef<Path>>(mut self, path: Option<T>) -> Self {
+        self.stdout = path.map(|p| p.as_ref().to_owned());
+        self
+    }
+
+    /// Append the standard error stream to this file.
+    pub fn stderr<T: AsRef<Path>>(mut self, path: Option<T>) -> Self {
+        self.stderr = path.map(|p| p.as_ref().to_owned());
+        self
+    }
+
+    /// Applies the options to the current process. Returns the pidfile if one
+    /// was written, which should be held until the process exits as it is
+    /// removed when dropped.
+    pub fn start(self) -> Result<Option<PidFile>> {
+        if self.daemonize {
+            detach()?;
+        }
+
+        if let Some(path) = &self.stdout {
+            redirect(path, libc::STDOUT_FILENO)?;
+        }
+
+        if let Some(path) = &self.stderr {
+            redirect(path, libc::STDERR_FILENO)?;
+        }
+
+        match self.pid_filename {
+            Some(path) => PidFile::create(path).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// A file which holds the id of the current process. The file is removed when
+/// this is dropped.
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the id of the current process to the file at the path, replacing
+    /// any existing content.
+    pub fn create<T: AsRef<Path>>(path: T) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let mut file = File::create(&path)?;
+        writeln!(file, "{}", std::process::id())?;
+
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// Forks twice, with the session leader in between, so that the process is
+// reparented and can never reacquire a controlling terminal. The standard
+// streams are then redirected to `/dev/null`, until any of them are redirected
+// to files.
+fn detach() -> Result<()> {
+    fork()?;
+
+    if unsafe { libc::setsid() } < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    fork()?;
+
+    let null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in &[libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::dup2(null.as_raw_fd(), *fd) } < 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+// Forks the process, with the parent exiting immediately and the child
+// continuing on.
+fn fork() -> Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => Err(Error::last_os_error()),
+        0 => Ok(()),
+        _ => std::process::exit(0),
+    }
+}
+
+// Replaces the file descriptor with the file at the path, which is opened for
+// appending so that output is kept across restarts.
+fn redirect(path: &Path, fd: libc::c_int) -> Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    if unsafe { libc::dup2(file.as_raw_fd(), fd) } < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pidfile() {
+        let path = std::env::temp_dir().join(format!("pelikan-{}.pid", std::process::id()));
+
+        let pidfile = Daemon::new()
+            .pid_filename(Some(&path))
+            .start()
+            .expect("failed to start")
+            .expect("no pidfile");
+        assert_eq!(pidfile.path(), path);
+
+        let content = std::fs::read_to_string(&path).expect("failed to read pidfile");
+        assert_eq!(content, format!("{}\n", std::process::id()));
+
+        drop(pidfile);
+        assert!(!path.exists());
+
+        assert!(Daemon::new().start().expect("failed to start").is_none());
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A lightweight bus for notifications which cut across subsystems. Modules
+//! publish events as they happen, such as an item being evicted or a session
+//! being closed, and other subsystems subscribe to the events they care about
+//! instead of being called directly by the module which produced them.
+//!
+//! Subscribers are called synchronously on the thread which published the
+//! event, so they must be cheap and must not block. A subscriber which needs to
+//! do more work should hand the event off to its own thread. No lock is held
+//! while subscribers are called, so a subscriber may itself subscribe,
+//! unsubscribe, or publish. When there are no subscribers, publishing an event
+//! costs a single atomic load.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// A notification which is published on the bus.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event<'a> {
+    /// An item was removed from storage to make room for new items.
+    ItemEvicted { key: &'a [u8] },
+    /// A segment has been filled and no more items will be written to it.
+    SegmentSealed { id: u32 },
+    /// A session was opened on the data port.
+    SessionOpened { id: u64 },
+    /// A session on the data port was closed.
+    SessionClosed { id: u64 },
+    /// The configuration was reloaded and any changed settings applied.
+    ConfigReloaded,
+}
+
+/// A function which is called with each event that is published.
+pub type Subscriber = Arc<dyn Fn(&Event) + Send + Sync>;
+
+/// Identifies a subscription, so that it can be removed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SubscriptionId(u64);
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+type Subscribers = Arc<Vec<(SubscriptionId, Subscriber)>>;
+
+// the list is replaced rather than changed in place, so that publishing only
+// holds the lock long enough to take a reference to the current list
+static SUBSCRIBERS: RwLock<Option<Subscribers>> = RwLock::new(None);
+
+/// Adds a subscriber which will be called with every event published after
+/// this returns.
+pub fn subscribe(subscriber: Subscriber) -> SubscriptionId {
+    let id = SubscriptionId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+
+    let mut subscribers = SUBSCRIBERS.write().unwrap();
+    let mut updated = subscribers.as_deref().cloned().unwrap_or_default();
+    updated.push((id, subscriber));
+    *subscribers = Some(Arc::new(updated));
+    ACTIVE.store(true, Ordering::Relaxed);
+
+    id
+}
+
+/// Removes a subscriber. Returns true if the subscription existed.
+pub fn unsubscribe(id: SubscriptionId) -> bool {
+    let mut subscribers = SUBSCRIBERS.write().unwrap();
+    let current = match subscribers.as_deref() {
+        Some(current) => current,
+        None => {
+            return false;
+        }
+    };
+
+    let updated: Vec<_> = current.iter().filter(|(s, _)| *s != id).cloned().collect();
+    let removed = updated.len() != current.len();
+    ACTIVE.store(!updated.is_empty(), Ordering::Relaxed);
+    *subscribers = if updated.is_empty() {
+        None
+    } else {
+        Some(Arc::new(updated))
+    };
+
+    removed
+}
+
+/// Calls each subscriber with the event.
+pub fn publish(event: Event) {
+    if !ACTIVE.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let subscribers = match SUBSCRIBERS.read().unwrap().clone() {
+        Some(subscribers) => subscribers,
+        None => {
+            return;
+        }
+    };
+
+    for (_, subscriber) in subscribers.iter() {
+        subscriber(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn subscriptions() {
+        let evictions = Arc::new(AtomicUsize::new(0));
+        let counter = evictions.clone();
+        let id = subscribe(Arc::new(move |event| {
+            if let Event::ItemEvicted { key } = event {
+                assert_eq!(*key, b"key");
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }));
+
+        publish(Event::ItemEvicted { key: b"key" });
+        publish(Event::SessionOpened { id: 1 });
+        publish(Event::ItemEvicted { key: b"key" });
+        assert_eq!(evictions.load(Ordering::Relaxed), 2);
+
+        assert!(unsubscribe(id));
+        assert!(!unsubscribe(id));
+
+        publish(Event::ItemEvicted { key: b"key" });
+        assert_eq!(evictions.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn reentrant() {
+        // a subscriber which removes itself when called, which would deadlock
+        // if the subscribers were called with the lock held
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let slot: Arc<RwLock<Option<SubscriptionId>>> = Arc::new(RwLock::new(None));
+        let own = slot.clone();
+        let id = subscribe(Arc::new(move |event| {
+            if let Event::SegmentSealed { id: u32::MAX } = event {
+                counter.fetch_add(1, Ordering::Relaxed);
+                if let Some(id) = *own.read().unwrap() {
+                    assert!(unsubscribe(id));
+                }
+            }
+        }));
+        *slot.write().unwrap() = Some(id);
+
+        publish(Event::SegmentSealed { id: u32::MAX });
+        publish(Event::SegmentSealed { id: u32::MAX });
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
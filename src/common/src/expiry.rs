@@ -7,6 +7,10 @@ use serde::{Deserialize, Serialize};
 
 use std::time::SystemTime;
 
+/// Memcache expiration times which are greater than this number of seconds
+/// (30 days) are treated as absolute UNIX timestamps rather than durations.
+pub const MEMCACHE_MAX_DELTA: u32 = 60 * 60 * 24 * 30;
+
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum TimeType {
     Unix = 0,
@@ -25,30 +29,29 @@ impl Expiry {
     }
 
     // TODO(bmartin): this conversion can be made more efficient
+    /// Returns the expiry as a number of seconds from now. Absolute times
+    /// which are in the past return zero.
     pub fn as_secs(&self) -> u32 {
         match self.time_type {
-            TimeType::Unix => {
-                let epoch = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs() as u32;
-                self.expiry.wrapping_sub(epoch)
-            }
+            TimeType::Unix => self.expiry.saturating_sub(Self::epoch()),
             TimeType::Delta => self.expiry,
             TimeType::Memcache => {
-                if self.expiry < 60 * 60 * 24 * 30 {
+                if self.expiry <= MEMCACHE_MAX_DELTA {
                     self.expiry
                 } else {
-                    let epoch = SystemTime::now()
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs() as u32;
-                    self.expiry.wrapping_sub(epoch)
+                    self.expiry.saturating_sub(Self::epoch())
                 }
             }
         }
     }
 
+    fn epoch() -> u32 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32
+    }
+
     pub fn from_memcache(expiry: u32) -> Expiry {
         Self {
             expiry,
@@ -0,0 +1,92 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Fingerprints of stored items, which summarize an item without its value
+//! so that the contents of two instances can be compared, for example to check
+//! that a replica or the destination of a migration has converged.
+//!
+//! The hashes must match across processes, builds, and hosts, so they use
+//! 64-bit FNV-1a rather than a hasher with a random or per-platform state.
+//! Sampling is decided by the hash of the key, so that instances with the same
+//! keys sample the same keys.
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// The fingerprint of a stored item.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fingerprint {
+    /// The hash of the key
+    pub key: u64,
+    /// The hash of the value, as it would be returned to a client, and of the
+    /// flags
+    pub value: u64,
+    /// The index of the TTL bucket which holds the item
+    pub ttl_bucket: usize,
+}
+
+impl Fingerprint {
+    pub fn new(key: &[u8], value: &[u8], flags: u32, ttl_bucket: usize) -> Self {
+        Self {
+            key: hash(key),
+            value: hash_parts(&[value, &flags.to_be_bytes()]),
+            ttl_bucket,
+        }
+    }
+}
+
+/// Returns the 64-bit FNV-1a hash of the bytes.
+pub fn hash(bytes: &[u8]) -> u64 {
+    hash_parts(&[bytes])
+}
+
+// each part is preceded by its length, so that moving bytes from one part to
+// the next changes the hash
+fn hash_parts(parts: &[&[u8]]) -> u64 {
+    let mut hash = FNV_OFFSET;
+    for part in parts {
+        for byte in (part.len() as u64).to_be_bytes().iter().chain(part.iter()) {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+/// Returns true if the key with the given hash is in a sample of one in every
+/// `rate` keys. A rate of one or zero samples every key.
+pub fn sampled(key_hash: u64, rate: u64) -> bool {
+    rate <= 1 || key_hash % rate == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint() {
+        // the hashes are fixed, so that they can be compared across builds
+        assert_eq!(hash(b""), 0xa8c7f832281a39c5);
+        assert_eq!(hash(b"coffee"), hash(b"coffee"));
+        assert_ne!(hash(b"coffee"), hash(b"tea"));
+
+        let fingerprint = Fingerprint::new(b"drink", b"coffee", 0, 3);
+        assert_eq!(fingerprint.key, hash(b"drink"));
+        assert_eq!(fingerprint.ttl_bucket, 3);
+
+        // the flags are part of the value
+        assert_ne!(
+            fingerprint.value,
+            Fingerprint::new(b"drink", b"coffee", 1, 3).value
+        );
+
+        // every key is sampled at a rate of one
+        assert!(sampled(fingerprint.key, 0));
+        assert!(sampled(fingerprint.key, 1));
+        let count = (0..1000u64)
+            .filter(|i| sampled(hash(&i.to_be_bytes()), 10))
+            .count();
+        assert!((50..150).contains(&count), "count: {}", count);
+    }
+}
@@ -2,9 +2,17 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+pub mod buildinfo;
 pub mod bytes;
+pub mod daemon;
+pub mod events;
 pub mod expiry;
+pub mod fingerprint;
+pub mod listener;
 pub mod metrics;
+pub mod namespace;
+pub mod rules;
+pub mod rusage;
 pub mod signal;
 pub mod ssl;
 pub mod time;
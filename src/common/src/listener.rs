@@ -0,0 +1,147 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Per-listener statistics. A process may serve requests on more than one
+//! listener, such as the data port alongside the async, QUIC, or gRPC front
+//! ends, and the protocol metrics are shared by all of them. Each listener
+//! registers its name and records the requests it receives, the responses it
+//! sends, and the request latency, so that the traffic of each front end can
+//! be monitored on its own.
+//!
+//! Listener names are only known from the configuration, so these statistics
+//! are kept in a registry which is separate from the statically declared
+//! metrics. Listeners which register the same name share their statistics.
+
+use core::time::Duration;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// The number of buckets in the latency distribution. Bucket `i` counts the
+/// requests which took less than `2^i` microseconds, so the last bucket covers
+/// everything from about 18 minutes on.
+const LATENCY_BUCKETS: usize = 32;
+
+static LISTENERS: RwLock<BTreeMap<String, Arc<ListenerStats>>> = RwLock::new(BTreeMap::new());
+
+/// Counters which are tracked for a single listener.
+#[derive(Default)]
+pub struct ListenerStats {
+    request: AtomicU64,
+    response: AtomicU64,
+    latency: [AtomicU64; LATENCY_BUCKETS],
+}
+
+impl ListenerStats {
+    /// Record a request which was received.
+    pub fn record_request(&self) {
+        self.request.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a response which was sent, with the time between reading the
+    /// request and sending the response.
+    pub fn record_response(&self, latency: Duration) {
+        self.response.fetch_add(1, Ordering::Relaxed);
+        let micros = latency.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = (64 - micros.leading_zeros() as usize).min(LATENCY_BUCKETS - 1);
+        self.latency[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The number of requests which were received.
+    pub fn requests(&self) -> u64 {
+        self.request.load(Ordering::Relaxed)
+    }
+
+    /// The number of responses which were sent.
+    pub fn responses(&self) -> u64 {
+        self.response.load(Ordering::Relaxed)
+    }
+
+    /// An upper bound on the given percentile of the latency, which is zero if
+    /// no responses have been recorded.
+    pub fn latency(&self, percentile: f64) -> Duration {
+        let counts: Vec<u64> = self
+            .latency
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return Duration::ZERO;
+        }
+
+        let target = ((total as f64 * percentile / 100.0).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return Duration::from_micros(1 << bucket);
+            }
+        }
+        Duration::from_micros(1 << (LATENCY_BUCKETS - 1))
+    }
+}
+
+/// Returns the statistics for the listener with the given name, registering
+/// it if it is not yet tracked.
+pub fn register(name: &str) -> Arc<ListenerStats> {
+    if let Some(stats) = LISTENERS.read().unwrap().get(name) {
+        return stats.clone();
+    }
+
+    LISTENERS
+        .write()
+        .unwrap()
+        .entry(name.to_string())
+        .or_default()
+        .clone()
+}
+
+/// Returns a snapshot of all registered listeners, sorted by name.
+pub fn snapshot() -> Vec<(String, Arc<ListenerStats>)> {
+    LISTENERS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, stats)| (name.clone(), stats.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listeners() {
+        let data = register("test_data");
+        let grpc = register("test_grpc");
+
+        for _ in 0..4 {
+            data.record_request();
+        }
+        data.record_response(Duration::from_micros(3));
+        data.record_response(Duration::from_micros(100));
+        register("test_data").record_response(Duration::from_micros(5));
+        grpc.record_request();
+        grpc.record_response(Duration::from_secs(1));
+
+        assert_eq!(data.requests(), 4);
+        assert_eq!(data.responses(), 3);
+        assert_eq!(grpc.requests(), 1);
+        assert_eq!(grpc.responses(), 1);
+
+        // the bounds are the next power of two microseconds
+        assert_eq!(data.latency(50.0), Duration::from_micros(8));
+        assert_eq!(data.latency(100.0), Duration::from_micros(128));
+        assert_eq!(grpc.latency(50.0), Duration::from_micros(1 << 20));
+        assert_eq!(register("test_idle").latency(99.0), Duration::ZERO);
+
+        let names: Vec<String> = snapshot()
+            .into_iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.starts_with("test_"))
+            .collect();
+        assert_eq!(names, vec!["test_data", "test_grpc", "test_idle"]);
+    }
+}
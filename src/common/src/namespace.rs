@@ -0,0 +1,447 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Per-namespace statistics. Keys which belong to a namespace are prefixed with
+//! the namespace followed by a separator, which is [`NAMESPACE_SEPARATOR`]
+//! unless configured otherwise. When enabled, request and storage activity is
+//! tracked for each namespace so that usage can be attributed to the owners of
+//! the namespace.
+//!
+//! To keep the overhead low on busy workers, only one in every `sample_rate`
+//! requests may be recorded, with each recorded request counted `sample_rate`
+//! times. The counters are then estimates rather than exact counts.
+//!
+//! Namespaces are not known in advance, so these statistics are kept in a
+//! registry which is separate from the statically declared metrics. To bound
+//! the memory used, at most [`MAX_NAMESPACES`] namespaces are tracked and any
+//! activity for additional namespaces is not recorded.
+
+use crate::events::{self, Event};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Once, RwLock};
+
+/// Keys which belong to a namespace are prefixed with the namespace followed
+/// by this separator by default.
+pub const NAMESPACE_SEPARATOR: u8 = b':';
+
+/// The maximum number of namespaces which will be tracked.
+pub const MAX_NAMESPACES: usize = 1024;
+
+thread_local! {
+    // the number of requests to skip on this thread before the next sample
+    static COUNTDOWN: Cell<u64> = Cell::new(0);
+}
+
+static REGISTRY: Registry = Registry::new();
+
+// evictions are learned of from the event bus, which is subscribed to once
+// tracking is first enabled
+static SUBSCRIBE: Once = Once::new();
+
+/// Counters which are tracked for a single namespace.
+#[derive(Default)]
+pub struct NamespaceStats {
+    requests: AtomicU64,
+    reads: AtomicU64,
+    hits: AtomicU64,
+    writes: AtomicU64,
+    deletes: AtomicU64,
+    bytes_stored: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl NamespaceStats {
+    /// The number of keys requested, for both reads and writes.
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// The number of keys requested by reads.
+    pub fn reads(&self) -> u64 {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    /// The number of keys which were found by a read.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// The number of keys which were not found by a read.
+    pub fn misses(&self) -> u64 {
+        self.reads().saturating_sub(self.hits())
+    }
+
+    /// The number of keys requested by writes, other than deletes.
+    pub fn writes(&self) -> u64 {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    /// The number of keys requested by deletes.
+    pub fn deletes(&self) -> u64 {
+        self.deletes.load(Ordering::Relaxed)
+    }
+
+    /// The total number of value bytes which have been stored.
+    pub fn bytes_stored(&self) -> u64 {
+        self.bytes_stored.load(Ordering::Relaxed)
+    }
+
+    /// The number of items which were evicted.
+    pub fn evictions(&self) -> u64 {
+        self.evictions.load(Ordering::Relaxed)
+    }
+}
+
+// The settings and the tracked namespaces. The process uses a single registry,
+// which the functions in this module operate on.
+struct Registry {
+    enabled: AtomicBool,
+    separator: AtomicU8,
+    sample_rate: AtomicU64,
+    namespaces: RwLock<BTreeMap<Box<[u8]>, Arc<NamespaceStats>>>,
+}
+
+impl Registry {
+    const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            separator: AtomicU8::new(NAMESPACE_SEPARATOR),
+            sample_rate: AtomicU64::new(1),
+            namespaces: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn set_separator(&self, separator: u8) {
+        self.separator.store(separator, Ordering::Relaxed);
+    }
+
+    fn separator(&self) -> u8 {
+        self.separator.load(Ordering::Relaxed)
+    }
+
+    fn set_sample_rate(&self, rate: u64) {
+        self.sample_rate.store(rate.max(1), Ordering::Relaxed);
+    }
+
+    fn sample_rate(&self) -> u64 {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+
+    fn sample(&self) -> bool {
+        if !self.enabled() {
+            return false;
+        }
+
+        COUNTDOWN.with(|countdown| {
+            if countdown.get() == 0 {
+                countdown.set(self.sample_rate() - 1);
+                true
+            } else {
+                countdown.set(countdown.get() - 1);
+                false
+            }
+        })
+    }
+
+    fn namespace<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        let separator = self.separator();
+        key.iter()
+            .position(|b| *b == separator)
+            .map(|end| &key[..end])
+    }
+
+    fn record_read(&self, key: &[u8], hit: bool) {
+        let weight = self.sample_rate();
+        self.record(key, |stats| {
+            stats.requests.fetch_add(weight, Ordering::Relaxed);
+            stats.reads.fetch_add(weight, Ordering::Relaxed);
+            if hit {
+                stats.hits.fetch_add(weight, Ordering::Relaxed);
+            }
+        })
+    }
+
+    fn record_write(&self, key: &[u8], bytes: usize) {
+        let weight = self.sample_rate();
+        self.record(key, |stats| {
+            stats.requests.fetch_add(weight, Ordering::Relaxed);
+            stats.writes.fetch_add(weight, Ordering::Relaxed);
+            stats
+                .bytes_stored
+                .fetch_add(bytes as u64 * weight, Ordering::Relaxed);
+        })
+    }
+
+    fn record_delete(&self, key: &[u8]) {
+        let weight = self.sample_rate();
+        self.record(key, |stats| {
+            stats.requests.fetch_add(weight, Ordering::Relaxed);
+            stats.deletes.fetch_add(weight, Ordering::Relaxed);
+        })
+    }
+
+    fn record_eviction(&self, key: &[u8]) {
+        self.record(key, |stats| {
+            stats.evictions.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    fn snapshot(&self) -> Vec<(Box<[u8]>, Arc<NamespaceStats>)> {
+        self.namespaces
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(namespace, stats)| (namespace.clone(), stats.clone()))
+            .collect()
+    }
+
+    // Update the stats for the key's namespace, registering the namespace if it
+    // is not yet tracked and there is room to do so.
+    fn record<F: FnOnce(&NamespaceStats)>(&self, key: &[u8], update: F) {
+        if !self.enabled() {
+            return;
+        }
+
+        let namespace = match self.namespace(key) {
+            Some(namespace) => namespace,
+            None => {
+                return;
+            }
+        };
+
+        if let Some(stats) = self.namespaces.read().unwrap().get(namespace) {
+            update(stats);
+            return;
+        }
+
+        let mut namespaces = self.namespaces.write().unwrap();
+        if namespaces.len() >= MAX_NAMESPACES && !namespaces.contains_key(namespace) {
+            return;
+        }
+        update(
+            namespaces
+                .entry(namespace.to_vec().into_boxed_slice())
+                .or_default(),
+        );
+    }
+}
+
+/// Enable or disable tracking of per-namespace statistics.
+pub fn set_enabled(enabled: bool) {
+    REGISTRY.set_enabled(enabled);
+
+    if enabled {
+        SUBSCRIBE.call_once(|| {
+            events::subscribe(Arc::new(|event| {
+                if let Event::ItemEvicted { key } = event {
+                    record_eviction(key);
+                }
+            }));
+        });
+    }
+}
+
+/// Returns true if per-namespace statistics are being tracked.
+pub fn enabled() -> bool {
+    REGISTRY.enabled()
+}
+
+/// Set the byte which separates the namespace from the rest of the key.
+pub fn set_separator(separator: u8) {
+    REGISTRY.set_separator(separator)
+}
+
+/// Returns the byte which separates the namespace from the rest of the key.
+pub fn separator() -> u8 {
+    REGISTRY.separator()
+}
+
+/// Set the number of requests per sample. A rate of one records every
+/// request, and zero is treated as one.
+pub fn set_sample_rate(rate: u64) {
+    REGISTRY.set_sample_rate(rate)
+}
+
+/// Returns the number of requests per sample.
+pub fn sample_rate() -> u64 {
+    REGISTRY.sample_rate()
+}
+
+/// Returns true if tracking is enabled and the current request should be
+/// recorded. Callers should only record the keys of a request if this returns
+/// true, as the recorded counts are scaled by the sample rate.
+pub fn sample() -> bool {
+    REGISTRY.sample()
+}
+
+/// Returns the namespace for a key, or `None` if the key does not belong to a
+/// namespace.
+pub fn namespace(key: &[u8]) -> Option<&[u8]> {
+    REGISTRY.namespace(key)
+}
+
+/// Record a sampled read of the key, and whether it was a hit.
+pub fn record_read(key: &[u8], hit: bool) {
+    REGISTRY.record_read(key, hit)
+}
+
+/// Record a sampled write to the key, along with the number of value bytes
+/// stored.
+pub fn record_write(key: &[u8], bytes: usize) {
+    REGISTRY.record_write(key, bytes)
+}
+
+/// Record a sampled delete of the key.
+pub fn record_delete(key: &[u8]) {
+    REGISTRY.record_delete(key)
+}
+
+/// Record that the item with the given key was evicted. Evictions are not
+/// sampled. Evictions which are published as events are recorded without
+/// calling this.
+pub fn record_eviction(key: &[u8]) {
+    REGISTRY.record_eviction(key)
+}
+
+/// Returns a snapshot of all tracked namespaces, sorted by namespace.
+pub fn snapshot() -> Vec<(Box<[u8]>, Arc<NamespaceStats>)> {
+    REGISTRY.snapshot()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the counters of the only tracked namespace, which must be the given one
+    fn only(registry: &Registry, expected: &[u8]) -> Arc<NamespaceStats> {
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        let (namespace, stats) = &snapshot[0];
+        assert_eq!(&namespace[..], expected);
+        stats.clone()
+    }
+
+    #[test]
+    fn namespaces() {
+        let registry = Registry::new();
+
+        assert_eq!(registry.namespace(b"team:key"), Some(&b"team"[..]));
+        assert_eq!(registry.namespace(b"team:key:suffix"), Some(&b"team"[..]));
+        assert_eq!(registry.namespace(b"key"), None);
+
+        // nothing is recorded until tracking is enabled
+        registry.record_read(b"team:a", true);
+        assert!(registry.snapshot().is_empty());
+
+        registry.set_enabled(true);
+
+        registry.record_read(b"team:a", true);
+        registry.record_read(b"team:b", false);
+        registry.record_write(b"team:c", 42);
+        registry.record_eviction(b"team:c");
+        registry.record_write(b"untracked", 42);
+        registry.record_delete(b"team:d");
+
+        let stats = only(&registry, b"team");
+        assert_eq!(stats.requests(), 4);
+        assert_eq!(stats.reads(), 2);
+        assert_eq!(stats.hits(), 1);
+        assert_eq!(stats.misses(), 1);
+        assert_eq!(stats.writes(), 1);
+        assert_eq!(stats.deletes(), 1);
+        assert_eq!(stats.bytes_stored(), 42);
+        assert_eq!(stats.evictions(), 1);
+
+        // disabling tracking leaves the counters as they were
+        registry.set_enabled(false);
+        registry.record_read(b"team:a", true);
+        assert_eq!(stats.requests(), 4);
+        assert_eq!(stats.reads(), 2);
+    }
+
+    #[test]
+    fn sampling() {
+        let registry = Registry::new();
+
+        // nothing is sampled while tracking is disabled
+        assert!(!registry.sample());
+
+        registry.set_enabled(true);
+        assert_eq!(registry.sample_rate(), 1);
+        assert!((0..4).all(|_| registry.sample()));
+
+        // one in four requests is sampled, starting with the first
+        registry.set_sample_rate(4);
+        assert_eq!(registry.sample_rate(), 4);
+        let sampled: Vec<bool> = (0..8).map(|_| registry.sample()).collect();
+        assert_eq!(
+            sampled,
+            [true, false, false, false, true, false, false, false]
+        );
+
+        // and each sample counts four times
+        registry.record_write(b"team:a", 10);
+        registry.record_read(b"team:a", true);
+        let stats = only(&registry, b"team");
+        assert_eq!(stats.requests(), 8);
+        assert_eq!(stats.writes(), 4);
+        assert_eq!(stats.bytes_stored(), 40);
+        assert_eq!(stats.reads(), 4);
+        assert_eq!(stats.hits(), 4);
+
+        // evictions are not sampled, so they count once
+        registry.record_eviction(b"team:a");
+        assert_eq!(stats.evictions(), 1);
+
+        // a rate of zero is treated as one
+        registry.set_sample_rate(0);
+        assert_eq!(registry.sample_rate(), 1);
+    }
+
+    #[test]
+    fn separator() {
+        let registry = Registry::new();
+        assert_eq!(registry.separator(), NAMESPACE_SEPARATOR);
+
+        registry.set_separator(b'/');
+        assert_eq!(registry.separator(), b'/');
+        assert_eq!(registry.namespace(b"team/key:suffix"), Some(&b"team"[..]));
+        assert_eq!(registry.namespace(b"team:key"), None);
+    }
+
+    #[test]
+    fn limit() {
+        let registry = Registry::new();
+        registry.set_enabled(true);
+
+        for i in 0..MAX_NAMESPACES {
+            registry.record_read(format!("{}:key", i).as_bytes(), true);
+        }
+        assert_eq!(registry.snapshot().len(), MAX_NAMESPACES);
+
+        // further namespaces are not tracked, but tracked ones still are
+        registry.record_read(b"extra:key", true);
+        assert_eq!(registry.snapshot().len(), MAX_NAMESPACES);
+        assert!(registry
+            .snapshot()
+            .iter()
+            .all(|(namespace, _)| &namespace[..] != b"extra"));
+
+        registry.record_read(b"0:key", false);
+        let (namespace, stats) = &registry.snapshot()[0];
+        assert_eq!(&namespace[..], b"0");
+        assert_eq!(stats.reads(), 2);
+        assert_eq!(stats.hits(), 1);
+    }
+}
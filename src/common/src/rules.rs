@@ -0,0 +1,30 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Hit counts for the caching rules. Rules are defined in the config, so their
+//! counters cannot be declared as static metrics. Instead they are registered
+//! as dynamic metrics, named `rule_<name>_hit`, and are reported along with
+//! all of the other metrics.
+
+use rustcommon_metrics::{Counter, DynBoxedMetric};
+use std::sync::{Arc, RwLock};
+
+static RULES: RwLock<Vec<(String, Arc<DynBoxedMetric<Counter>>)>> = RwLock::new(Vec::new());
+
+/// Returns the hit counter for the named rule, registering the rule if it is
+/// not yet known. Each storage instance which registers the same rule shares
+/// a single counter.
+pub fn register(name: &str) -> Arc<DynBoxedMetric<Counter>> {
+    let mut rules = RULES.write().unwrap();
+    if let Some((_, hits)) = rules.iter().find(|(rule, _)| rule == name) {
+        return hits.clone();
+    }
+
+    let hits = Arc::new(DynBoxedMetric::new(
+        Counter::new(),
+        format!("rule_{}_hit", name),
+    ));
+    rules.push((name.to_string(), hits.clone()));
+    hits
+}
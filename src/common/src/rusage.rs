@@ -0,0 +1,178 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Resource usage of each thread, which shows which thread is burning CPU or
+//! faulting when only the usage of the whole process is known. Each thread
+//! samples its own usage from its event loop, as `RUSAGE_THREAD` only reports
+//! on the calling thread. This is only supported on Linux, and sampling is a
+//! no-op elsewhere.
+//!
+//! Threads are not known in advance, so the usage is kept in a registry which
+//! is separate from the statically declared metrics, with each thread
+//! identified by its name.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Each thread samples its usage at most once per interval.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The name and description of each field of the usage, in the order of
+/// [`ThreadUsage::values`].
+pub const FIELDS: [(&str, &str); 8] = [
+    ("utime", "user CPU time in nanoseconds"),
+    ("stime", "system CPU time in nanoseconds"),
+    ("minflt", "page faults serviced without any I/O"),
+    ("majflt", "page faults which required I/O"),
+    ("inblock", "block input operations"),
+    ("oublock", "block output operations"),
+    ("nvcsw", "voluntary context switches"),
+    ("nivcsw", "involuntary context switches"),
+];
+
+static THREADS: RwLock<Vec<(String, Arc<ThreadUsage>)>> = RwLock::new(Vec::new());
+
+thread_local! {
+    // the usage of this thread, and when it was last sampled
+    static CURRENT: RefCell<Option<(Arc<ThreadUsage>, Instant)>> = RefCell::new(None);
+}
+
+/// The resource usage of a single thread, as of its last sample.
+#[derive(Default)]
+pub struct ThreadUsage {
+    utime: AtomicU64,
+    stime: AtomicU64,
+    minflt: AtomicU64,
+    majflt: AtomicU64,
+    inblock: AtomicU64,
+    oublock: AtomicU64,
+    nvcsw: AtomicU64,
+    nivcsw: AtomicU64,
+}
+
+impl ThreadUsage {
+    /// The value of each field of the usage, which are named by [`FIELDS`].
+    pub fn values(&self) -> [u64; 8] {
+        [
+            self.utime.load(Ordering::Relaxed),
+            self.stime.load(Ordering::Relaxed),
+            self.minflt.load(Ordering::Relaxed),
+            self.majflt.load(Ordering::Relaxed),
+            self.inblock.load(Ordering::Relaxed),
+            self.oublock.load(Ordering::Relaxed),
+            self.nvcsw.load(Ordering::Relaxed),
+            self.nivcsw.load(Ordering::Relaxed),
+        ]
+    }
+
+    #[cfg(target_os = "linux")]
+    fn update(&self) {
+        // SAFETY: the struct is plain data, for which all zeros is valid
+        let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut rusage) } != 0 {
+            return;
+        }
+
+        let nanos =
+            |time: libc::timeval| time.tv_sec as u64 * 1_000_000_000 + time.tv_usec as u64 * 1_000;
+        self.utime.store(nanos(rusage.ru_utime), Ordering::Relaxed);
+        self.stime.store(nanos(rusage.ru_stime), Ordering::Relaxed);
+        self.minflt
+            .store(rusage.ru_minflt as u64, Ordering::Relaxed);
+        self.majflt
+            .store(rusage.ru_majflt as u64, Ordering::Relaxed);
+        self.inblock
+            .store(rusage.ru_inblock as u64, Ordering::Relaxed);
+        self.oublock
+            .store(rusage.ru_oublock as u64, Ordering::Relaxed);
+        self.nvcsw.store(rusage.ru_nvcsw as u64, Ordering::Relaxed);
+        self.nivcsw
+            .store(rusage.ru_nivcsw as u64, Ordering::Relaxed);
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn update(&self) {}
+}
+
+/// Samples the usage of the calling thread, unless it was sampled within the
+/// interval. The thread is registered under its name when first sampled.
+pub fn sample() {
+    if !cfg!(target_os = "linux") {
+        return;
+    }
+
+    let now = Instant::now();
+    CURRENT.with(|current| {
+        let mut current = current.borrow_mut();
+        match current.as_mut() {
+            Some((_, sampled)) if now.duration_since(*sampled) < SAMPLE_INTERVAL => {}
+            Some((usage, sampled)) => {
+                *sampled = now;
+                usage.update();
+            }
+            None => {
+                let usage = register();
+                usage.update();
+                *current = Some((usage, now));
+            }
+        }
+    })
+}
+
+/// Returns the usage of each thread which has been sampled, in the order the
+/// threads were registered.
+pub fn snapshot() -> Vec<(String, Arc<ThreadUsage>)> {
+    THREADS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(name, usage)| (name.clone(), usage.clone()))
+        .collect()
+}
+
+// Registers the calling thread. Threads with the same name, such as those of
+// two instances within a process, share their usage.
+fn register() -> Arc<ThreadUsage> {
+    let thread = std::thread::current();
+    let name = thread.name().unwrap_or("unnamed");
+
+    let mut threads = THREADS.write().unwrap();
+    if let Some((_, usage)) = threads.iter().find(|(thread, _)| thread == name) {
+        return usage.clone();
+    }
+
+    let usage = Arc::new(ThreadUsage::default());
+    threads.push((name.to_string(), usage.clone()));
+    usage
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_thread() {
+        std::thread::Builder::new()
+            .name("rusage_test".to_string())
+            .spawn(|| {
+                // spin so that the thread accrues some user time
+                let start = Instant::now();
+                while start.elapsed() < Duration::from_millis(50) {}
+                sample();
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        let snapshot = snapshot();
+        let (_, usage) = snapshot
+            .iter()
+            .find(|(name, _)| name == "rusage_test")
+            .expect("thread was not registered");
+        assert_eq!(FIELDS[0].0, "utime");
+        assert!(usage.values()[0] > 0);
+    }
+}
@@ -2,8 +2,399 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
+use crate::fingerprint::Fingerprint;
+use net::TlsTcpAcceptor;
+use std::net::SocketAddr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, SystemTime};
+
 #[derive(Clone)]
 pub enum Signal {
     FlushAll,
+    /// Remove all items once the wall clock reaches the given time
+    FlushAllAt(SystemTime),
+    /// Remove only the items in the given namespace
+    FlushNamespace(Vec<u8>),
+    /// Remove only the items in the TTL bucket with the given index
+    FlushTtlBucket(usize),
+    /// Apply the settings from a reloaded configuration
+    Reload(Reload),
+    /// Change a setting of the event loop, if the thread runs an event loop of
+    /// the given kind
+    Tune(EventLoop, Tunable),
+    /// Use the given acceptor for new TLS sessions on the data port, after the
+    /// certificate has been reloaded
+    ReloadTls(TlsTcpAcceptor),
+    /// Reply with a description of each session on the data port which is
+    /// handled by the thread
+    ListSessions,
+    /// Close the session with the given id, if it is handled by the thread
+    KillSession(u64),
+    /// Reply with a summary of each TTL bucket in storage, if storage is held
+    /// by the thread
+    SegmentStats,
+    /// Reply with a summary of the occupancy of the hashtable, if storage is
+    /// held by the thread
+    HashTableStats,
+    /// Run a compaction pass over storage now and reply with the number of
+    /// segments freed, if storage is held by the thread
+    Compact,
+    /// Start or stop serving items past their expiry while the origin is
+    /// degraded, if storage is held by the thread
+    ServeStale(bool),
+    /// Reply with the state of the thread for a health or readiness probe
+    Health,
+    /// Apply a step of a live migration and reply with its result, if storage
+    /// is held by the thread
+    Migrate(MigrateStep),
+    /// Reply with the fingerprints of a sample of one in every `rate` items in
+    /// a part of storage, starting at the cursor, along with the cursor to
+    /// continue from, if storage is held by the thread. The walk is complete
+    /// once the returned cursor is zero again.
+    Fingerprints {
+        rate: u64,
+        cursor: usize,
+    },
+    /// Reply once no more events will be handled, and then wait for the gate
+    /// to open, so that the admin thread can read every metric at a single
+    /// point in time
+    Pause(Gate),
     Shutdown,
 }
+
+impl Signal {
+    /// Replies to the signal as a thread which holds neither storage nor any
+    /// session on the data port. Every thread replies to the signals which the
+    /// admin thread waits on, so each event loop handles the signals which
+    /// apply to it and passes the rest here. A [`Signal::Pause`] is replied to
+    /// before waiting on its gate.
+    pub fn reply_default(self, reply: impl FnOnce(Reply)) {
+        match self {
+            Signal::FlushAll
+            | Signal::FlushAllAt(_)
+            | Signal::Migrate(_)
+            | Signal::Fingerprints { .. } => reply(Reply::Ack),
+            Signal::ListSessions => reply(Reply::Sessions(Vec::new())),
+            Signal::KillSession(_) => reply(Reply::Killed(false)),
+            Signal::SegmentStats => reply(Reply::Segments(Vec::new())),
+            Signal::HashTableStats => reply(Reply::HashTable(None)),
+            Signal::Compact => reply(Reply::Compacted(0)),
+            Signal::ServeStale(_) => reply(Reply::ServeStale(Duration::ZERO)),
+            Signal::Health => reply(Reply::Health(ThreadHealth::default())),
+            Signal::Pause(gate) => {
+                reply(Reply::Ack);
+                gate.wait();
+            }
+            Signal::FlushNamespace(_)
+            | Signal::FlushTtlBucket(_)
+            | Signal::Reload(_)
+            | Signal::Tune(..)
+            | Signal::ReloadTls(_)
+            | Signal::Shutdown => {}
+        }
+    }
+}
+
+/// A reply to the admin thread for a signal which it waits on.
+#[derive(Clone, Debug)]
+pub enum Reply {
+    /// The signal has been applied
+    Ack,
+    /// The sessions handled by the thread
+    Sessions(Vec<SessionInfo>),
+    /// Whether the thread closed the session with the requested id
+    Killed(bool),
+    /// The TTL buckets of the storage held by the thread
+    Segments(Vec<TtlBucketInfo>),
+    /// The occupancy of the hashtable of the storage held by the thread, if
+    /// there is one
+    HashTable(Option<HashTableInfo>),
+    /// The number of segments freed by compaction of the storage held by the
+    /// thread
+    Compacted(usize),
+    /// The time for which the storage held by the thread serves items past
+    /// their expiry, which is zero if it does not
+    ServeStale(Duration),
+    /// The state of the thread for a health or readiness probe
+    Health(ThreadHealth),
+    /// The result of a step of a live migration by the storage held by the
+    /// thread
+    Migrate(MigrateReply),
+    /// The fingerprints of the sampled items in a part of the storage held by
+    /// the thread, and the cursor for the next part
+    Fingerprints(Vec<Fingerprint>, usize),
+}
+
+/// Holds the threads which received a [`Signal::Pause`] until it is opened by
+/// the admin thread. A thread waits no longer than the limit given when the
+/// gate was created, so that the data path is never stalled for long.
+#[derive(Clone, Debug)]
+pub struct Gate {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+    limit: Duration,
+}
+
+impl Gate {
+    pub fn new(limit: Duration) -> Self {
+        Self {
+            inner: Arc::new((Mutex::new(false), Condvar::new())),
+            limit,
+        }
+    }
+
+    /// Blocks until the gate is opened or the limit has elapsed.
+    pub fn wait(&self) {
+        let (open, condvar) = &*self.inner;
+        if let Ok(guard) = open.lock() {
+            let _ = condvar.wait_timeout_while(guard, self.limit, |open| !*open);
+        }
+    }
+
+    /// Opens the gate, releasing every thread which waits on it.
+    pub fn open(&self) {
+        let (open, condvar) = &*self.inner;
+        if let Ok(mut guard) = open.lock() {
+            *guard = true;
+        }
+        condvar.notify_all();
+    }
+}
+
+/// A step of a live migration, which copies the items under a prefix to
+/// another instance. The admin thread drives the migration by sending a step at
+/// a time to the thread which holds storage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MigrateStep {
+    /// Reply with the keys which begin with the prefix among the items in a
+    /// part of storage, starting at the cursor, along with the cursor to
+    /// continue from. The scan starts with a cursor of zero and is complete
+    /// once the returned cursor is zero again.
+    Keys { prefix: Vec<u8>, cursor: usize },
+    /// Reply with the number of keys which begin with the prefix among the
+    /// items in a part of storage, scanning in the same steps as `Keys`.
+    Count { prefix: Vec<u8>, cursor: usize },
+    /// Reply with a copy of each item which is still stored
+    Export(Vec<Box<[u8]>>),
+    /// Remove each item which has not changed since it was exported with the
+    /// given cas value, and reply with the number of items removed
+    Remove(Vec<(Box<[u8]>, u64)>),
+}
+
+/// The result of a [`MigrateStep`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MigrateReply {
+    /// The keys found by a step of the scan, and the cursor for the next step
+    Keys(Vec<Box<[u8]>>, usize),
+    /// The number of keys counted by a step of the scan, and the cursor for
+    /// the next step
+    Count(usize, usize),
+    Items(Vec<MigrationItem>),
+    Removed(usize),
+}
+
+/// A copy of an item which is being migrated to another instance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigrationItem {
+    pub key: Box<[u8]>,
+    /// The value as it would be returned to a client
+    pub value: Vec<u8>,
+    pub flags: u32,
+    /// The time remaining until the item expires
+    pub ttl: Duration,
+    /// The cas value when the item was exported, so that it is only removed
+    /// if it was not changed since
+    pub cas: u64,
+}
+
+/// The state of a thread as reported for a health or readiness probe.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ThreadHealth {
+    /// The thread has a data listener which is registered for events
+    pub listening: bool,
+    /// The thread holds storage which has been initialized. For a proxy, this
+    /// means the thread is connected to at least one backend
+    pub storage: bool,
+    /// The storage held by the thread is still warming up after startup
+    pub warming_up: bool,
+}
+
+/// A description of a client session on the data port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// The id of the session, which is unique within the process
+    pub id: u64,
+    /// The address of the client, if it could be determined
+    pub peer_addr: Option<SocketAddr>,
+    /// The time since the session was established
+    pub age: Duration,
+    /// The total number of bytes read from the client
+    pub bytes_read: u64,
+    /// The total number of bytes written to the client
+    pub bytes_written: u64,
+    /// The number of bytes read which have not yet been handled
+    pub read_pending: usize,
+    /// The number of bytes waiting to be written to the client
+    pub write_pending: usize,
+}
+
+/// A summary of the segments in one TTL bucket of segment-structured storage.
+/// Byte counts include the item headers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TtlBucketInfo {
+    /// The index of the bucket, as used by `flush ttl_bucket`
+    pub index: usize,
+    /// The TTL of the items stored in the bucket
+    pub ttl: Duration,
+    /// The number of segments in the bucket
+    pub segments: usize,
+    /// The number of items which may still be read
+    pub live_items: u64,
+    /// The number of bytes used by items which may still be read
+    pub live_bytes: u64,
+    /// The number of bytes used by items which have been removed, replaced,
+    /// or flushed, which is reclaimed when their segment is
+    pub dead_bytes: u64,
+    /// The age of the oldest segment in the bucket
+    pub oldest: Duration,
+    /// The number of segments which have been merged with their neighbors
+    pub merged: usize,
+}
+
+/// A summary of the occupancy of the hashtable used to look up items, such as
+/// in segment-structured storage.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HashTableInfo {
+    /// The hash power, which sets the number of primary buckets
+    pub power: u8,
+    /// The number of primary buckets, which keys hash into
+    pub buckets: usize,
+    /// The number of overflow buckets, which are chained onto full primary
+    /// buckets
+    pub overflow_buckets: usize,
+    /// The number of overflow buckets which have been chained
+    pub overflow_used: usize,
+    /// The number of slots which hold an item
+    pub items: u64,
+    /// The number of slots which may hold an item
+    pub slots: u64,
+    /// The number of primary buckets with each number of chained overflow
+    /// buckets, indexed by the chain length
+    pub chain_lengths: Vec<u64>,
+    /// The number of primary buckets which hold each number of items, indexed
+    /// by the number of items. Trailing zeros are omitted.
+    pub occupancy: Vec<u64>,
+    /// The number of lookups which found a partial hash match for another key
+    pub tag_collisions: u64,
+    /// The number of lookups
+    pub lookups: u64,
+}
+
+impl HashTableInfo {
+    /// The fraction of all slots which hold an item.
+    pub fn load_factor(&self) -> f64 {
+        if self.slots == 0 {
+            0.0
+        } else {
+            self.items as f64 / self.slots as f64
+        }
+    }
+
+    /// The fraction of lookups which encountered a tag collision.
+    pub fn collision_rate(&self) -> f64 {
+        if self.lookups == 0 {
+            0.0
+        } else {
+            self.tag_collisions as f64 / self.lookups as f64
+        }
+    }
+}
+
+/// The kinds of event loop whose settings can be changed at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventLoop {
+    Admin,
+    /// The listener, which accepts sessions on the data port
+    Server,
+    /// The workers, including the storage thread
+    Worker,
+}
+
+/// A setting of an event loop which can be changed at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tunable {
+    /// The maximum number of events per call to poll
+    Nevent(usize),
+    /// The timeout for each call to poll
+    Timeout(Duration),
+}
+
+/// The settings of the listener and worker threads which can be changed
+/// without a restart. These are sent to each thread when the configuration is
+/// reloaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reload {
+    /// The maximum number of events per call to poll for the listener
+    pub server_nevent: usize,
+    /// The timeout for each call to poll for the listener
+    pub server_timeout: Duration,
+    /// The maximum number of events per call to poll for the workers
+    pub worker_nevent: usize,
+    /// The timeout for each call to poll for the workers
+    pub worker_timeout: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn gate() {
+        // an open gate releases the threads which wait on it
+        let gate = Gate::new(Duration::from_secs(60));
+        let waiting = gate.clone();
+        let thread = std::thread::spawn(move || waiting.wait());
+        gate.open();
+        thread.join().unwrap();
+
+        // and threads which wait after it was opened are not held at all
+        gate.wait();
+
+        // a gate which is never opened holds threads only up to its limit
+        let gate = Gate::new(Duration::from_millis(10));
+        let start = Instant::now();
+        gate.wait();
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+
+    #[test]
+    fn reply_default() {
+        fn reply(signal: Signal) -> Option<Reply> {
+            let mut replied = None;
+            signal.reply_default(|reply| replied = Some(reply));
+            replied
+        }
+
+        // the signals which the admin thread waits on are always replied to
+        assert!(matches!(reply(Signal::FlushAll), Some(Reply::Ack)));
+        assert!(matches!(
+            reply(Signal::KillSession(1)),
+            Some(Reply::Killed(false))
+        ));
+        assert!(matches!(
+            reply(Signal::HashTableStats),
+            Some(Reply::HashTable(None))
+        ));
+        assert!(
+            matches!(reply(Signal::Health), Some(Reply::Health(health)) if health == ThreadHealth::default())
+        );
+        assert!(matches!(
+            reply(Signal::Pause(Gate::new(Duration::ZERO))),
+            Some(Reply::Ack)
+        ));
+
+        // and the rest are not
+        assert!(reply(Signal::FlushTtlBucket(0)).is_none());
+        assert!(reply(Signal::Shutdown).is_none());
+    }
+}
@@ -15,6 +15,10 @@ pub trait TlsConfig {
     fn certificate(&self) -> Option<String>;
 
     fn ca_file(&self) -> Option<String>;
+
+    /// Whether clients must present a certificate which is signed by the CA
+    /// in the `ca_file`.
+    fn verify_client(&self) -> bool;
 }
 
 /// Create an `TlsTcpAcceptor` from the given `TlsConfig`. Returns an error if
@@ -48,6 +52,17 @@ pub fn tls_acceptor(config: &dyn TlsConfig) -> Result<Option<TlsTcpAcceptor>, st
         builder = builder.ca_file(f);
     }
 
+    // client certificates can only be verified against the ca file
+    if config.verify_client() {
+        if config.ca_file().is_none() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "verify_client requires a ca_file",
+            ));
+        }
+        builder = builder.verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+
     if let Some(f) = config.certificate() {
         builder = builder.certificate_file(f);
     }
@@ -1,3 +1,58 @@
 pub use rustcommon_time::{
     refresh_clock, DateTime, Duration, Instant, Nanoseconds, Seconds, SecondsFormat, UnixInstant,
 };
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A reference reading of both the wall clock and the monotonic clock, taken
+/// together. Comparing how much time has elapsed on each clock since the
+/// reference reading reveals steps or slewing of the wall clock, such as
+/// those applied by NTP, which affect the handling of absolute expiry times.
+#[derive(Clone, Copy, Debug)]
+pub struct Clock {
+    wall: SystemTime,
+    monotonic: std::time::Instant,
+}
+
+impl Clock {
+    /// Take a new reference reading of both clocks.
+    pub fn new() -> Self {
+        Self {
+            wall: SystemTime::now(),
+            monotonic: std::time::Instant::now(),
+        }
+    }
+
+    /// Returns the current wall clock time as a duration since the UNIX
+    /// epoch.
+    pub fn unix_time(&self) -> std::time::Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    }
+
+    /// Returns the time elapsed on the monotonic clock since the reference
+    /// reading.
+    pub fn uptime(&self) -> std::time::Duration {
+        self.monotonic.elapsed()
+    }
+
+    /// Returns the difference, in microseconds, between the time elapsed on
+    /// the wall clock and the time elapsed on the monotonic clock since the
+    /// reference reading. A positive value means the wall clock has moved
+    /// ahead of the monotonic clock.
+    pub fn drift(&self) -> i64 {
+        let monotonic = self.uptime().as_micros() as i64;
+        let wall = match SystemTime::now().duration_since(self.wall) {
+            Ok(elapsed) => elapsed.as_micros() as i64,
+            Err(e) => -(e.duration().as_micros() as i64),
+        };
+        wall - monotonic
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -2,13 +2,16 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-use std::net::{AddrParseError, SocketAddr};
+use crate::resolve::resolve;
+use crate::Tls;
+use std::net::SocketAddr;
 
 use serde::{Deserialize, Serialize};
 
 // constants to define default values
 const ADMIN_HOST: &str = "127.0.0.1";
 const ADMIN_PORT: &str = "9999";
+const ADMIN_SOCKET_MODE: u32 = 0o600;
 const ADMIN_HTTP_ENABLED: bool = false;
 const ADMIN_HTTP_HOST: &str = "127.0.0.1";
 const ADMIN_HTTP_PORT: &str = "9998";
@@ -18,6 +21,48 @@ const ADMIN_TW_TICK: usize = 10;
 const ADMIN_TW_CAP: usize = 1000;
 const ADMIN_TW_NTICK: usize = 100;
 const ADMIN_USE_TLS: bool = false;
+const ADMIN_FLUSH_ENABLED: bool = true;
+const ADMIN_FLUSH_TIMEOUT: usize = 1000;
+const ADMIN_PROFILE_DIR: &str = "/tmp";
+const ADMIN_COMPOSE_LIMIT: usize = 64 * 1024; // 64KB
+const ADMIN_SNAPSHOT_TIMEOUT: usize = 0;
+const ADMIN_MAX_SESSIONS: usize = 64;
+const ADMIN_COMMAND_RATE: u64 = 100;
+const ADMIN_CONFIRM_DESTRUCTIVE: bool = false;
+const ADMIN_CONFIRM_TIMEOUT: usize = 60;
+
+// the counters reported by `stats rates`: requests, hits, misses, writes, and
+// evictions
+const ADMIN_RATE_METRICS: &[&str] = &[
+    "process_req",
+    "get_key_hit",
+    "get_key_miss",
+    "set",
+    "segment_evict",
+];
+
+// commands which change the state of the process or expose the cached data
+// are gated by default
+const ADMIN_AUTH_COMMANDS: &[&str] = &[
+    "fingerprints",
+    "flush_all",
+    "flush",
+    "loglevel",
+    "maintenance",
+    "migrate",
+    "profile",
+    "reload",
+    "sessions",
+    "shutdown",
+    "stale",
+    "stats_reset",
+    "tune",
+];
+
+// NOTE: the admin listener is configured entirely by this section and does not
+// inherit any settings, including TLS, from the data listener. This allows the
+// admin port to use a different interface, address family, and security policy
+// than the data port.
 
 // TODO(bmartin): we will eventually migrate to HTTP by default and make the
 // legacy admin port as optional. At that time, we should consider consolidating
@@ -35,6 +80,10 @@ fn port() -> String {
     ADMIN_PORT.to_string()
 }
 
+fn socket_mode() -> u32 {
+    ADMIN_SOCKET_MODE
+}
+
 fn http_enabled() -> bool {
     ADMIN_HTTP_ENABLED
 }
@@ -71,6 +120,50 @@ fn use_tls() -> bool {
     ADMIN_USE_TLS
 }
 
+fn flush_enabled() -> bool {
+    ADMIN_FLUSH_ENABLED
+}
+
+fn flush_timeout() -> usize {
+    ADMIN_FLUSH_TIMEOUT
+}
+
+fn profile_dir() -> String {
+    ADMIN_PROFILE_DIR.to_string()
+}
+
+fn compose_limit() -> usize {
+    ADMIN_COMPOSE_LIMIT
+}
+
+fn snapshot_timeout() -> usize {
+    ADMIN_SNAPSHOT_TIMEOUT
+}
+
+fn max_sessions() -> usize {
+    ADMIN_MAX_SESSIONS
+}
+
+fn confirm_destructive() -> bool {
+    ADMIN_CONFIRM_DESTRUCTIVE
+}
+
+fn confirm_timeout() -> usize {
+    ADMIN_CONFIRM_TIMEOUT
+}
+
+fn command_rate() -> u64 {
+    ADMIN_COMMAND_RATE
+}
+
+fn rate_metrics() -> Vec<String> {
+    ADMIN_RATE_METRICS.iter().map(|m| m.to_string()).collect()
+}
+
+fn auth_commands() -> Vec<String> {
+    ADMIN_AUTH_COMMANDS.iter().map(|c| c.to_string()).collect()
+}
+
 // definitions
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Admin {
@@ -78,6 +171,10 @@ pub struct Admin {
     host: String,
     #[serde(default = "port")]
     port: String,
+    #[serde(default)]
+    socket: Option<String>,
+    #[serde(default = "socket_mode")]
+    socket_mode: u32,
     #[serde(default = "http_enabled")]
     http_enabled: bool,
     #[serde(default = "http_host")]
@@ -96,6 +193,32 @@ pub struct Admin {
     tw_ntick: usize,
     #[serde(default = "use_tls")]
     use_tls: bool,
+    #[serde(default = "flush_enabled")]
+    flush_enabled: bool,
+    #[serde(default = "flush_timeout")]
+    flush_timeout: usize,
+    #[serde(default = "profile_dir")]
+    profile_dir: String,
+    #[serde(default = "compose_limit")]
+    compose_limit: usize,
+    #[serde(default = "snapshot_timeout")]
+    snapshot_timeout: usize,
+    #[serde(default = "max_sessions")]
+    max_sessions: usize,
+    #[serde(default = "command_rate")]
+    command_rate: u64,
+    #[serde(default = "confirm_destructive")]
+    confirm_destructive: bool,
+    #[serde(default = "confirm_timeout")]
+    confirm_timeout: usize,
+    #[serde(default = "rate_metrics")]
+    rate_metrics: Vec<String>,
+    #[serde(default)]
+    auth_token: Option<String>,
+    #[serde(default = "auth_commands")]
+    auth_commands: Vec<String>,
+    #[serde(default)]
+    tls: Tls,
 }
 
 // implementation
@@ -108,12 +231,28 @@ impl Admin {
         self.port.clone()
     }
 
+    /// The path of a unix domain socket for the admin listener. When set, the
+    /// admin listener is bound to this socket instead of the host and port, so
+    /// that it is not reachable over the network.
+    pub fn socket(&self) -> Option<&str> {
+        self.socket.as_deref()
+    }
+
+    /// The permissions of the unix domain socket file, which default to
+    /// `0o600` so that only the owner of the process may connect.
+    pub fn socket_mode(&self) -> u32 {
+        self.socket_mode
+    }
+
+    /// Whether the http admin listener is enabled. It is never enabled when
+    /// the admin listener is bound to a unix domain socket, since it would
+    /// still be reachable over the network.
     pub fn http_enabled(&self) -> bool {
-        self.http_enabled
+        self.http_enabled && self.socket.is_none()
     }
 
-    pub fn http_socket_addr(&self) -> Result<SocketAddr, AddrParseError> {
-        format!("{}:{}", self.http_host, self.http_port).parse()
+    pub fn http_socket_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        resolve(&format!("{}:{}", self.http_host, self.http_port))
     }
 
     pub fn timeout(&self) -> usize {
@@ -136,15 +275,102 @@ impl Admin {
         self.tw_ntick
     }
 
-    /// Return the result of parsing the host and port
-    pub fn socket_addr(&self) -> Result<SocketAddr, AddrParseError> {
-        format!("{}:{}", self.host(), self.port()).parse()
+    /// Return the result of resolving the host and port. The host may be an
+    /// IP address or a hostname.
+    pub fn socket_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        resolve(&format!("{}:{}", self.host(), self.port()))
     }
 
-    /// If TLS is configured, the admin port should also use TLS
+    /// Whether the admin port should use TLS. The certificate and key are
+    /// provided by the admin TLS config and not the data port TLS config.
     pub fn use_tls(&self) -> bool {
         self.use_tls
     }
+
+    /// Whether the flush commands, `flush_all` and `flush`, are enabled on the
+    /// admin port.
+    pub fn flush_enabled(&self) -> bool {
+        self.flush_enabled
+    }
+
+    /// The time in milliseconds to wait for all threads to apply a
+    /// `flush_all`, or to finish a `maintenance compact`, before replying with
+    /// an error.
+    pub fn flush_timeout(&self) -> usize {
+        self.flush_timeout
+    }
+
+    /// The directory which CPU profiles are written to by the `profile stop`
+    /// command.
+    pub fn profile_dir(&self) -> &str {
+        &self.profile_dir
+    }
+
+    /// Responses larger than this many bytes, such as `stats` with many
+    /// metrics, are composed and flushed across multiple event loop
+    /// iterations. This applies to both the ascii and http listeners.
+    pub fn compose_limit(&self) -> usize {
+        self.compose_limit
+    }
+
+    /// The maximum time, in milliseconds, for which the other threads are
+    /// paused while the metrics for a stats response are read, so that the
+    /// response reflects a single point in time. Zero, the default, disables
+    /// the pause, in which case metrics may change while they are read.
+    pub fn snapshot_timeout(&self) -> usize {
+        self.snapshot_timeout
+    }
+
+    /// The maximum number of concurrent sessions on each admin listener.
+    /// Sessions accepted beyond this are sent an error and closed.
+    pub fn max_sessions(&self) -> usize {
+        self.max_sessions
+    }
+
+    /// The number of commands per second which each admin session may issue,
+    /// with any excess rejected with an error. A value of zero disables the
+    /// limit.
+    pub fn command_rate(&self) -> u64 {
+        self.command_rate
+    }
+
+    /// Whether the destructive commands, `flush_all`, `flush`, and `shutdown`,
+    /// must be confirmed. When set, these commands only describe their impact
+    /// and return a token, and they run when repeated with `--confirm <token>`.
+    /// A dry run with `--dry-run` is available either way.
+    pub fn confirm_destructive(&self) -> bool {
+        self.confirm_destructive
+    }
+
+    /// The number of seconds for which the token returned by a dry run may be
+    /// used to confirm the command.
+    pub fn confirm_timeout(&self) -> usize {
+        self.confirm_timeout
+    }
+
+    /// The names of the counters for which `stats rates` reports the rate of
+    /// change per second, as they appear in the `stats` response.
+    pub fn rate_metrics(&self) -> &[String] {
+        &self.rate_metrics
+    }
+
+    /// The shared secret which a session must present with the `auth` command
+    /// before issuing any of the `auth_commands`. If not set, no commands
+    /// require authentication.
+    pub fn auth_token(&self) -> Option<&str> {
+        self.auth_token.as_deref()
+    }
+
+    /// The names of the commands which require an authenticated session when
+    /// an `auth_token` is set, such as `flush_all` or `reload`.
+    pub fn auth_commands(&self) -> &[String] {
+        &self.auth_commands
+    }
+
+    /// TLS config for the admin port, specified in the `[admin.tls]` section.
+    pub fn tls(&self) -> &Tls {
+        &self.tls
+    }
 }
 
 // trait implementations
@@ -153,6 +379,8 @@ impl Default for Admin {
         Self {
             host: host(),
             port: port(),
+            socket: None,
+            socket_mode: socket_mode(),
             http_enabled: http_enabled(),
             http_host: http_host(),
             http_port: http_port(),
@@ -162,6 +390,19 @@ impl Default for Admin {
             tw_cap: tw_cap(),
             tw_ntick: tw_ntick(),
             use_tls: use_tls(),
+            flush_enabled: flush_enabled(),
+            flush_timeout: flush_timeout(),
+            profile_dir: profile_dir(),
+            compose_limit: compose_limit(),
+            snapshot_timeout: snapshot_timeout(),
+            max_sessions: max_sessions(),
+            command_rate: command_rate(),
+            confirm_destructive: confirm_destructive(),
+            confirm_timeout: confirm_timeout(),
+            rate_metrics: rate_metrics(),
+            auth_token: None,
+            auth_commands: auth_commands(),
+            tls: Default::default(),
         }
     }
 }
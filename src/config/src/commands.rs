@@ -0,0 +1,97 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use serde::{Deserialize, Serialize};
+
+// constants to define default values
+const COMMANDS_ARITHMETIC: bool = true;
+const COMMANDS_FLUSH: bool = true;
+const COMMANDS_META: bool = true;
+const COMMANDS_STATS: bool = true;
+const COMMANDS_STATS_SIZES: bool = false;
+
+// helper functions
+fn arithmetic() -> bool {
+    COMMANDS_ARITHMETIC
+}
+
+fn flush() -> bool {
+    COMMANDS_FLUSH
+}
+
+fn meta() -> bool {
+    COMMANDS_META
+}
+
+fn stats() -> bool {
+    COMMANDS_STATS
+}
+
+fn stats_sizes() -> bool {
+    COMMANDS_STATS_SIZES
+}
+
+// definitions
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Commands {
+    #[serde(default = "arithmetic")]
+    arithmetic: bool,
+    #[serde(default = "flush")]
+    flush: bool,
+    #[serde(default = "meta")]
+    meta: bool,
+    #[serde(default = "stats")]
+    stats: bool,
+    #[serde(default = "stats_sizes")]
+    stats_sizes: bool,
+}
+
+// implementation
+impl Commands {
+    /// Whether the arithmetic commands, `incr`, `decr`, and `ma`, are enabled.
+    pub fn arithmetic(&self) -> bool {
+        self.arithmetic
+    }
+
+    /// Whether `flush_all` is enabled on the data port.
+    pub fn flush(&self) -> bool {
+        self.flush
+    }
+
+    /// Whether the meta commands, `mg`, `ms`, `md`, `mn`, and `ma`, are
+    /// enabled.
+    pub fn meta(&self) -> bool {
+        self.meta
+    }
+
+    /// Whether `stats` and its groups are enabled on the data port.
+    pub fn stats(&self) -> bool {
+        self.stats
+    }
+
+    /// Whether `stats sizes` is enabled on the data port. It reads every item
+    /// in storage, so it is disabled unless asked for, even when `stats` is
+    /// enabled.
+    pub fn stats_sizes(&self) -> bool {
+        self.stats_sizes
+    }
+}
+
+// trait implementations
+impl Default for Commands {
+    fn default() -> Self {
+        Self {
+            arithmetic: arithmetic(),
+            flush: flush(),
+            meta: meta(),
+            stats: stats(),
+            stats_sizes: stats_sizes(),
+        }
+    }
+}
+
+// trait definitions
+pub trait CommandsConfig {
+    fn commands(&self) -> &Commands;
+}
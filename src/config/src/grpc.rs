@@ -0,0 +1,114 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::resolve::resolve;
+use std::net::SocketAddr;
+
+use serde::{Deserialize, Serialize};
+
+// constants to define default values
+const GRPC_ENABLED: bool = false;
+const GRPC_HOST: &str = "0.0.0.0";
+const GRPC_PORT: &str = "12322";
+const GRPC_THREADS: usize = 1;
+const GRPC_TIMEOUT: usize = 1000;
+const GRPC_REQUIRE_NAMESPACE: bool = false;
+
+// helper functions
+fn enabled() -> bool {
+    GRPC_ENABLED
+}
+
+fn host() -> String {
+    GRPC_HOST.to_string()
+}
+
+fn port() -> String {
+    GRPC_PORT.to_string()
+}
+
+fn threads() -> usize {
+    GRPC_THREADS
+}
+
+fn timeout() -> usize {
+    GRPC_TIMEOUT
+}
+
+fn require_namespace() -> bool {
+    GRPC_REQUIRE_NAMESPACE
+}
+
+// definitions
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Grpc {
+    #[serde(default = "enabled")]
+    enabled: bool,
+    #[serde(default = "host")]
+    host: String,
+    #[serde(default = "port")]
+    port: String,
+    #[serde(default = "threads")]
+    threads: usize,
+    #[serde(default = "timeout")]
+    timeout: usize,
+    #[serde(default = "require_namespace")]
+    require_namespace: bool,
+}
+
+// implementation
+impl Grpc {
+    /// Whether the gRPC front end is served alongside the data port. It is
+    /// only available in builds with the `grpc` feature.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn host(&self) -> String {
+        self.host.clone()
+    }
+
+    pub fn port(&self) -> String {
+        self.port.clone()
+    }
+
+    pub fn socket_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        resolve(&format!("{}:{}", self.host(), self.port()))
+    }
+
+    /// The number of threads of the runtime which handles the RPCs.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// The maximum time in milliseconds for handling an RPC.
+    pub fn timeout(&self) -> usize {
+        self.timeout
+    }
+
+    /// Whether every RPC must name a namespace. Otherwise, RPCs without one
+    /// use the keys which are not in any namespace.
+    pub fn require_namespace(&self) -> bool {
+        self.require_namespace
+    }
+}
+
+// trait definitions
+pub trait GrpcConfig {
+    fn grpc(&self) -> &Grpc;
+}
+
+// trait implementations
+impl Default for Grpc {
+    fn default() -> Self {
+        Self {
+            enabled: enabled(),
+            host: host(),
+            port: port(),
+            threads: threads(),
+            timeout: timeout(),
+            require_namespace: require_namespace(),
+        }
+    }
+}
@@ -5,18 +5,23 @@
 #[macro_use]
 extern crate log;
 
+mod access_log;
 mod admin;
 mod array;
 mod buf;
+mod commands;
 mod dbuf;
 mod debug;
+mod grpc;
 mod klog;
 pub mod momento_proxy;
 mod pingproxy;
 mod pingserver;
 pub mod proxy;
+pub mod resolve;
+mod rules;
 pub mod seg;
-mod segcache;
+pub mod segcache;
 mod server;
 mod sockio;
 mod stats_log;
@@ -26,15 +31,19 @@ mod tls;
 mod units;
 mod worker;
 
+pub use access_log::{AccessLog, AccessLogConfig};
 pub use admin::{Admin, AdminConfig};
 pub use array::ArrayConfig;
 pub use buf::{Buf, BufConfig};
+pub use commands::{Commands, CommandsConfig};
 pub use dbuf::DbufConfig;
 pub use debug::{Debug, DebugConfig};
+pub use grpc::{Grpc, GrpcConfig};
 pub use klog::{Klog, KlogConfig};
 pub use momento_proxy::MomentoProxyConfig;
 pub use pingproxy::PingproxyConfig;
 pub use pingserver::PingserverConfig;
+pub use rules::{Rule, RulesConfig};
 pub use seg::{Seg, SegConfig};
 pub use segcache::SegcacheConfig;
 pub use server::{Server, ServerConfig};
@@ -43,4 +52,22 @@ pub use stats_log::StatsLogConfig;
 pub use tcp::{Tcp, TcpConfig};
 pub use time::{Time, TimeConfig, TimeType};
 pub use tls::{Tls, TlsConfig};
-pub use worker::{Worker, WorkerConfig};
+pub use worker::{ProtocolErrorPolicy, WakeStrategy, Worker, WorkerConfig};
+
+/// The configuration sections with settings which can be applied to a running
+/// process when its configuration file is reloaded.
+pub trait ReloadConfig: AdminConfig + DebugConfig + ServerConfig + WorkerConfig + Send {}
+
+impl<T: AdminConfig + DebugConfig + ServerConfig + WorkerConfig + Send> ReloadConfig for T {}
+
+/// The settings which control how a binary detaches from the terminal and
+/// redirects its output before it launches any threads.
+pub trait DaemonConfig {
+    fn daemonize(&self) -> bool;
+
+    fn pid_filename(&self) -> Option<String>;
+
+    fn stdout(&self) -> Option<String>;
+
+    fn stderr(&self) -> Option<String>;
+}
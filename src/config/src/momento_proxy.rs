@@ -1,6 +1,5 @@
-use crate::{Admin, AdminConfig, Debug, DebugConfig, Klog, KlogConfig};
+use crate::{AccessLog, AccessLogConfig, Admin, AdminConfig, Debug, DebugConfig, Klog, KlogConfig};
 use core::num::NonZeroU64;
-use std::net::AddrParseError;
 use std::net::SocketAddr;
 
 use serde::{Deserialize, Serialize};
@@ -34,6 +33,8 @@ pub struct MomentoProxyConfig {
     debug: Debug,
     #[serde(default)]
     klog: Klog,
+    #[serde(default)]
+    access_log: AccessLog,
 }
 
 #[derive(Default, Clone, Copy, Serialize, Deserialize, Debug)]
@@ -64,9 +65,10 @@ impl Cache {
         self.port.clone()
     }
 
-    /// Return the result of parsing the host and port
-    pub fn socket_addr(&self) -> Result<SocketAddr, AddrParseError> {
-        format!("{}:{}", self.host(), self.port()).parse()
+    /// Return the result of resolving the host and port. The host may be an
+    /// IP address or a hostname.
+    pub fn socket_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        crate::resolve::resolve(&format!("{}:{}", self.host(), self.port()))
     }
 
     /// Returns the name of the momento cache that requests will be sent to
@@ -128,3 +130,9 @@ impl KlogConfig for MomentoProxyConfig {
         &self.klog
     }
 }
+
+impl AccessLogConfig for MomentoProxyConfig {
+    fn access_log(&self) -> &AccessLog {
+        &self.access_log
+    }
+}
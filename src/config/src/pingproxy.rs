@@ -12,6 +12,8 @@ use std::io::Read;
 // constants to define default values
 const DAEMONIZE: bool = false;
 const PID_FILENAME: Option<String> = None;
+const STDOUT: Option<String> = None;
+const STDERR: Option<String> = None;
 const DLOG_INTERVAL: usize = 500;
 
 // helper functions
@@ -23,6 +25,14 @@ fn pid_filename() -> Option<String> {
     PID_FILENAME
 }
 
+fn stdout() -> Option<String> {
+    STDOUT
+}
+
+fn stderr() -> Option<String> {
+    STDERR
+}
+
 fn dlog_interval() -> usize {
     DLOG_INTERVAL
 }
@@ -35,6 +45,10 @@ pub struct PingproxyConfig {
     daemonize: bool,
     #[serde(default = "pid_filename")]
     pid_filename: Option<String>,
+    #[serde(default = "stdout")]
+    stdout: Option<String>,
+    #[serde(default = "stderr")]
+    stderr: Option<String>,
     #[serde(default = "dlog_interval")]
     dlog_interval: usize,
 
@@ -61,6 +75,8 @@ pub struct PingproxyConfig {
     #[serde(default)]
     klog: Klog,
     #[serde(default)]
+    access_log: AccessLog,
+    #[serde(default)]
     sockio: Sockio,
     #[serde(default)]
     tcp: Tcp,
@@ -90,6 +106,12 @@ impl KlogConfig for PingproxyConfig {
     }
 }
 
+impl AccessLogConfig for PingproxyConfig {
+    fn access_log(&self) -> &AccessLog {
+        &self.access_log
+    }
+}
+
 impl ListenerConfig for PingproxyConfig {
     fn listener(&self) -> &Listener {
         &self.listener
@@ -158,6 +180,16 @@ impl PingproxyConfig {
         self.pid_filename.clone()
     }
 
+    /// The file to which standard output is appended, if it is redirected.
+    pub fn stdout(&self) -> Option<String> {
+        self.stdout.clone()
+    }
+
+    /// The file to which standard error is appended, if it is redirected.
+    pub fn stderr(&self) -> Option<String> {
+        self.stderr.clone()
+    }
+
     pub fn dlog_interval(&self) -> usize {
         self.dlog_interval
     }
@@ -169,6 +201,8 @@ impl Default for PingproxyConfig {
         Self {
             daemonize: daemonize(),
             pid_filename: pid_filename(),
+            stdout: stdout(),
+            stderr: stderr(),
             dlog_interval: dlog_interval(),
 
             admin: Default::default(),
@@ -181,6 +215,7 @@ impl Default for PingproxyConfig {
             buf: Default::default(),
             debug: Default::default(),
             klog: Default::default(),
+            access_log: Default::default(),
             sockio: Default::default(),
             tcp: Default::default(),
             tls: Default::default(),
@@ -11,6 +11,8 @@ use std::io::Read;
 // constants to define default values
 const DAEMONIZE: bool = false;
 const PID_FILENAME: Option<String> = None;
+const STDOUT: Option<String> = None;
+const STDERR: Option<String> = None;
 const DLOG_INTERVAL: usize = 500;
 
 // helper functions
@@ -22,6 +24,14 @@ fn pid_filename() -> Option<String> {
     PID_FILENAME
 }
 
+fn stdout() -> Option<String> {
+    STDOUT
+}
+
+fn stderr() -> Option<String> {
+    STDERR
+}
+
 fn dlog_interval() -> usize {
     DLOG_INTERVAL
 }
@@ -34,6 +44,10 @@ pub struct PingserverConfig {
     daemonize: bool,
     #[serde(default = "pid_filename")]
     pid_filename: Option<String>,
+    #[serde(default = "stdout")]
+    stdout: Option<String>,
+    #[serde(default = "stderr")]
+    stderr: Option<String>,
     #[serde(default = "dlog_interval")]
     dlog_interval: usize,
 
@@ -57,6 +71,8 @@ pub struct PingserverConfig {
     #[serde(default)]
     klog: Klog,
     #[serde(default)]
+    access_log: AccessLog,
+    #[serde(default)]
     sockio: Sockio,
     #[serde(default)]
     tcp: Tcp,
@@ -74,6 +90,24 @@ impl BufConfig for PingserverConfig {
     }
 }
 
+impl DaemonConfig for PingserverConfig {
+    fn daemonize(&self) -> bool {
+        self.daemonize
+    }
+
+    fn pid_filename(&self) -> Option<String> {
+        self.pid_filename.clone()
+    }
+
+    fn stdout(&self) -> Option<String> {
+        self.stdout.clone()
+    }
+
+    fn stderr(&self) -> Option<String> {
+        self.stderr.clone()
+    }
+}
+
 impl DebugConfig for PingserverConfig {
     fn debug(&self) -> &Debug {
         &self.debug
@@ -86,6 +120,12 @@ impl KlogConfig for PingserverConfig {
     }
 }
 
+impl AccessLogConfig for PingserverConfig {
+    fn access_log(&self) -> &AccessLog {
+        &self.access_log
+    }
+}
+
 impl ServerConfig for PingserverConfig {
     fn server(&self) -> &Server {
         &self.server
@@ -152,6 +192,16 @@ impl PingserverConfig {
         self.pid_filename.clone()
     }
 
+    /// The file to which standard output is appended, if it is redirected.
+    pub fn stdout(&self) -> Option<String> {
+        self.stdout.clone()
+    }
+
+    /// The file to which standard error is appended, if it is redirected.
+    pub fn stderr(&self) -> Option<String> {
+        self.stderr.clone()
+    }
+
     pub fn dlog_interval(&self) -> usize {
         self.dlog_interval
     }
@@ -163,6 +213,8 @@ impl Default for PingserverConfig {
         Self {
             daemonize: daemonize(),
             pid_filename: pid_filename(),
+            stdout: stdout(),
+            stderr: stderr(),
             dlog_interval: dlog_interval(),
 
             admin: Default::default(),
@@ -173,6 +225,7 @@ impl Default for PingserverConfig {
             buf: Default::default(),
             debug: Default::default(),
             klog: Default::default(),
+            access_log: Default::default(),
             sockio: Default::default(),
             tcp: Default::default(),
             tls: Default::default(),
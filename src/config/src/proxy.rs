@@ -6,8 +6,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use zookeeper::{WatchedEvent, Watcher, ZooKeeper};
 
+use crate::resolve::{resolve, resolve_all};
 use core::time::Duration;
-use std::net::{AddrParseError, SocketAddr, ToSocketAddrs};
+use std::net::{SocketAddr, ToSocketAddrs};
 
 // constants to define default values
 const LISTEN_ADDRESS: &str = "0.0.0.0:12322";
@@ -16,6 +17,12 @@ const NEVENT_MAX: usize = 1024;
 const FRONTEND_THREADS: usize = 1;
 const BACKEND_THREADS: usize = 1;
 const BACKEND_POOLSIZE: usize = 1;
+const BACKEND_RESOLVE_INTERVAL_MS: usize = 30_000;
+const BACKEND_CONNECT_TIMEOUT_MS: usize = 1_000;
+const BACKEND_FALLBACK_DELAY_MS: usize = 250;
+const BACKEND_BACKOFF_MIN_MS: usize = 10;
+const BACKEND_BACKOFF_MAX_MS: usize = 5_000;
+const BACKEND_FAILOVER_DELAY_MS: usize = 5_000;
 
 // helper functions
 fn address() -> String {
@@ -42,6 +49,30 @@ fn backend_poolsize() -> usize {
     BACKEND_POOLSIZE
 }
 
+fn backend_resolve_interval() -> usize {
+    BACKEND_RESOLVE_INTERVAL_MS
+}
+
+fn backend_connect_timeout() -> usize {
+    BACKEND_CONNECT_TIMEOUT_MS
+}
+
+fn backend_fallback_delay() -> usize {
+    BACKEND_FALLBACK_DELAY_MS
+}
+
+fn backend_backoff_min() -> usize {
+    BACKEND_BACKOFF_MIN_MS
+}
+
+fn backend_backoff_max() -> usize {
+    BACKEND_BACKOFF_MAX_MS
+}
+
+fn backend_failover_delay() -> usize {
+    BACKEND_FAILOVER_DELAY_MS
+}
+
 // definitions
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Listener {
@@ -73,7 +104,21 @@ pub struct Backend {
     threads: usize,
     #[serde(default = "backend_poolsize")]
     poolsize: usize,
+    #[serde(default = "backend_resolve_interval")]
+    resolve_interval: usize,
+    #[serde(default = "backend_connect_timeout")]
+    connect_timeout: usize,
+    #[serde(default = "backend_fallback_delay")]
+    fallback_delay: usize,
+    #[serde(default = "backend_backoff_min")]
+    backoff_min: usize,
+    #[serde(default = "backend_backoff_max")]
+    backoff_max: usize,
+    #[serde(default = "backend_failover_delay")]
+    failover_delay: usize,
     endpoints: Vec<String>,
+    #[serde(default)]
+    standby: Vec<String>,
     zk_server: Option<String>,
     zk_path: Option<String>,
     zk_endpoint: Option<String>,
@@ -81,9 +126,10 @@ pub struct Backend {
 
 // implementation
 impl Listener {
-    /// Return the result of parsing the host and port
-    pub fn socket_addr(&self) -> Result<SocketAddr, AddrParseError> {
-        self.address.parse()
+    /// Return the result of resolving the address. The host may be an IP
+    /// address or a hostname.
+    pub fn socket_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        resolve(&self.address)
     }
 
     /// The poll timeout in milliseconds
@@ -135,6 +181,53 @@ impl Backend {
         self.nevent
     }
 
+    /// The interval in milliseconds between re-resolving the endpoints. A
+    /// value of zero disables re-resolution.
+    pub fn resolve_interval(&self) -> usize {
+        self.resolve_interval
+    }
+
+    /// The time in milliseconds allowed for a connection attempt to complete
+    pub fn connect_timeout(&self) -> usize {
+        self.connect_timeout
+    }
+
+    /// The time in milliseconds to wait for a connection attempt before also
+    /// trying the next resolved address of an endpoint
+    pub fn fallback_delay(&self) -> usize {
+        self.fallback_delay
+    }
+
+    /// The initial delay in milliseconds before reconnecting to an endpoint
+    /// after all of its addresses have failed
+    pub fn backoff_min(&self) -> usize {
+        self.backoff_min
+    }
+
+    /// The maximum delay in milliseconds before reconnecting to an endpoint
+    pub fn backoff_max(&self) -> usize {
+        self.backoff_max
+    }
+
+    /// The time in milliseconds for which the primary endpoints must all be
+    /// unreachable before the standby endpoints are promoted
+    pub fn failover_delay(&self) -> usize {
+        self.failover_delay
+    }
+
+    /// The statically configured endpoints in `host:port` form. The host may
+    /// be an IP address or a hostname.
+    pub fn endpoints(&self) -> &[String] {
+        &self.endpoints
+    }
+
+    /// The standby endpoints in `host:port` form, which are kept connected
+    /// and take over from the primary endpoints if those fail. No failover
+    /// happens if this is empty.
+    pub fn standby(&self) -> &[String] {
+        &self.standby
+    }
+
     // TODO(bmartin): the handling of ZK service discovery is based on how
     // Aurora serversets work and needs to be factored out into some more
     // general way of handling service discovery. We may want to allow for
@@ -144,21 +237,7 @@ impl Backend {
         if !self.endpoints.is_empty() {
             let mut endpoints = Vec::new();
             for endpoint in &self.endpoints {
-                match endpoint.to_socket_addrs() {
-                    Ok(mut addrs) => {
-                        if let Some(addr) = addrs.next() {
-                            endpoints.push(addr)
-                        } else {
-                            return Err(std::io::Error::new(
-                                std::io::ErrorKind::Other,
-                                "failed to resolve endpoint address",
-                            ));
-                        }
-                    }
-                    Err(e) => {
-                        return Err(e);
-                    }
-                }
+                endpoints.push(resolve_all(endpoint)?[0]);
             }
             Ok(endpoints)
         } else if let (Some(server), Some(path), endpoint) = (
@@ -271,10 +350,17 @@ impl Default for Backend {
             nevent: nevent(),
             threads: backend_threads(),
             endpoints: Vec::new(),
+            standby: Vec::new(),
             zk_server: None,
             zk_path: None,
             zk_endpoint: None,
             poolsize: backend_poolsize(),
+            resolve_interval: backend_resolve_interval(),
+            connect_timeout: backend_connect_timeout(),
+            fallback_delay: backend_fallback_delay(),
+            backoff_min: backend_backoff_min(),
+            backoff_max: backend_backoff_max(),
+            failover_delay: backend_failover_delay(),
         }
     }
 }
@@ -0,0 +1,66 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Helpers for resolving addresses from the config. Addresses may be given as
+//! IP literals or as hostnames which are resolved using the system resolver.
+
+use std::io::{Error, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs};
+
+/// Resolve an address in `host:port` form to all of its socket addresses, in
+/// the order returned by the resolver.
+pub fn resolve_all(address: &str) -> Result<Vec<SocketAddr>, Error> {
+    // avoid a resolver lookup for IP literals
+    if let Ok(addr) = address.parse() {
+        return Ok(vec![addr]);
+    }
+
+    let addrs: Vec<SocketAddr> = address.to_socket_addrs()?.collect();
+
+    if addrs.is_empty() {
+        Err(Error::new(
+            ErrorKind::Other,
+            format!("failed to resolve address: {}", address),
+        ))
+    } else {
+        Ok(addrs)
+    }
+}
+
+/// Resolve an address in `host:port` form to the first socket address
+/// returned by the resolver.
+pub fn resolve(address: &str) -> Result<SocketAddr, Error> {
+    resolve_all(address).map(|addrs| addrs[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ip_literal() {
+        assert_eq!(
+            resolve("127.0.0.1:12321").unwrap(),
+            "127.0.0.1:12321".parse().unwrap()
+        );
+        assert_eq!(
+            resolve("[::1]:12321").unwrap(),
+            "[::1]:12321".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn hostname() {
+        let addrs = resolve_all("localhost:12321").unwrap();
+        assert!(addrs
+            .iter()
+            .all(|a| a.ip().is_loopback() && a.port() == 12321));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(resolve("localhost").is_err());
+        assert!(resolve("127.0.0.1:port").is_err());
+    }
+}
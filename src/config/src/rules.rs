@@ -0,0 +1,71 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use serde::{Deserialize, Serialize};
+
+// definitions
+/// A caching rule, which applies a policy to the writes whose key matches its
+/// pattern. Rules are defined as a list, and the first rule which matches a
+/// key decides the policy for the write.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Rule {
+    name: String,
+    #[serde(default)]
+    prefix: Option<String>,
+    #[serde(default)]
+    regex: Option<String>,
+    #[serde(default)]
+    ttl: Option<u32>,
+    #[serde(default)]
+    namespace: Option<String>,
+    #[serde(default)]
+    deny: bool,
+    #[serde(default)]
+    compress: bool,
+}
+
+// implementation
+impl Rule {
+    /// The name of the rule, which identifies it in the stats.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Keys which begin with this prefix match the rule.
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    /// Keys which match this regular expression match the rule. If a prefix
+    /// is also given, a key must match both.
+    pub fn regex(&self) -> Option<&str> {
+        self.regex.as_deref()
+    }
+
+    /// Overrides the TTL of matching writes, in seconds.
+    pub fn ttl(&self) -> Option<u32> {
+        self.ttl
+    }
+
+    /// Attributes matching writes to this namespace in the per-namespace
+    /// stats.
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// Whether matching writes are rejected.
+    pub fn deny(&self) -> bool {
+        self.deny
+    }
+
+    /// Whether the values of matching writes are stored compressed.
+    pub fn compress(&self) -> bool {
+        self.compress
+    }
+}
+
+// trait definitions
+pub trait RulesConfig {
+    fn rules(&self) -> &[Rule];
+}
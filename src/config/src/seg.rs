@@ -24,9 +24,43 @@ const COMPACT_TARGET: usize = 2;
 const MERGE_TARGET: usize = 4;
 const MERGE_MAX: usize = 8;
 
+// default admission policy, which admits all writes
+const ADMISSION: Admission = Admission::Always;
+
+// related to probabilistic and frequency admission
+const ADMISSION_MIN_SIZE: usize = 64 * 1024;
+const ADMISSION_PROBABILITY: f64 = 0.1;
+const ADMISSION_THRESHOLD: u8 = 2;
+
+// duplicate write suppression, disabled by default
+const DEDUP_WINDOW: u64 = 0;
+const DEDUP_ENTRIES: usize = 4096;
+
 // datapool
 const DATAPOOL_PATH: Option<&str> = None;
 
+// per-namespace statistics
+const NAMESPACE_STATS: bool = false;
+const NAMESPACE_SEPARATOR: char = ':';
+const NAMESPACE_SAMPLE_RATE: u64 = 1;
+
+// keyspace cardinality estimation, disabled by default
+const CARDINALITY_INTERVAL: u64 = 0;
+
+// key and value size sampling, disabled by default
+const SIZE_SAMPLE_RATE: u64 = 0;
+
+// warm-up after startup, disabled by default
+const WARMUP: u64 = 0;
+const WARMUP_SHED_RATIO: f64 = 0.0;
+
+// serving expired items while the origin is degraded, disabled by default
+const STALE_GRACE: u64 = 0;
+
+// arithmetic overflow, which matches memcached by default
+const INCR_OVERFLOW: Overflow = Overflow::Wrap;
+const DECR_UNDERFLOW: Overflow = Overflow::Saturate;
+
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum Eviction {
     None,
@@ -38,6 +72,22 @@ pub enum Eviction {
     Merge,
 }
 
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Admission {
+    Always,
+    Probabilistic,
+    Frequency,
+}
+
+/// How `incr` and `decr` handle a result which is out of the range of an
+/// unsigned 64-bit value.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Overflow {
+    Wrap,
+    Saturate,
+    Error,
+}
+
 // helper functions for default values
 fn hash_power() -> u8 {
     HASH_POWER
@@ -71,10 +121,74 @@ fn compact_target() -> usize {
     COMPACT_TARGET
 }
 
+fn admission() -> Admission {
+    ADMISSION
+}
+
+fn admission_min_size() -> usize {
+    ADMISSION_MIN_SIZE
+}
+
+fn admission_probability() -> f64 {
+    ADMISSION_PROBABILITY
+}
+
+fn admission_threshold() -> u8 {
+    ADMISSION_THRESHOLD
+}
+
+fn dedup_window() -> u64 {
+    DEDUP_WINDOW
+}
+
+fn dedup_entries() -> usize {
+    DEDUP_ENTRIES
+}
+
 fn datapool_path() -> Option<String> {
     DATAPOOL_PATH.map(|v| v.to_string())
 }
 
+fn namespace_stats() -> bool {
+    NAMESPACE_STATS
+}
+
+fn namespace_separator() -> char {
+    NAMESPACE_SEPARATOR
+}
+
+fn namespace_sample_rate() -> u64 {
+    NAMESPACE_SAMPLE_RATE
+}
+
+fn cardinality_interval() -> u64 {
+    CARDINALITY_INTERVAL
+}
+
+fn size_sample_rate() -> u64 {
+    SIZE_SAMPLE_RATE
+}
+
+fn warmup() -> u64 {
+    WARMUP
+}
+
+fn warmup_shed_ratio() -> f64 {
+    WARMUP_SHED_RATIO
+}
+
+fn stale_grace() -> u64 {
+    STALE_GRACE
+}
+
+fn incr_overflow() -> Overflow {
+    INCR_OVERFLOW
+}
+
+fn decr_underflow() -> Overflow {
+    DECR_UNDERFLOW
+}
+
 // definitions
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Seg {
@@ -94,8 +208,40 @@ pub struct Seg {
     merge_max: usize,
     #[serde(default = "compact_target")]
     compact_target: usize,
+    #[serde(default = "admission")]
+    admission: Admission,
+    #[serde(default = "admission_min_size")]
+    admission_min_size: usize,
+    #[serde(default = "admission_probability")]
+    admission_probability: f64,
+    #[serde(default = "admission_threshold")]
+    admission_threshold: u8,
+    #[serde(default = "dedup_window")]
+    dedup_window: u64,
+    #[serde(default = "dedup_entries")]
+    dedup_entries: usize,
     #[serde(default = "datapool_path")]
     datapool_path: Option<String>,
+    #[serde(default = "namespace_stats")]
+    namespace_stats: bool,
+    #[serde(default = "namespace_separator")]
+    namespace_separator: char,
+    #[serde(default = "namespace_sample_rate")]
+    namespace_sample_rate: u64,
+    #[serde(default = "cardinality_interval")]
+    cardinality_interval: u64,
+    #[serde(default = "size_sample_rate")]
+    size_sample_rate: u64,
+    #[serde(default = "warmup")]
+    warmup: u64,
+    #[serde(default = "warmup_shed_ratio")]
+    warmup_shed_ratio: f64,
+    #[serde(default = "stale_grace")]
+    stale_grace: u64,
+    #[serde(default = "incr_overflow")]
+    incr_overflow: Overflow,
+    #[serde(default = "decr_underflow")]
+    decr_underflow: Overflow,
 }
 
 impl Default for Seg {
@@ -109,7 +255,23 @@ impl Default for Seg {
             merge_target: merge_target(),
             merge_max: merge_max(),
             compact_target: compact_target(),
+            admission: admission(),
+            admission_min_size: admission_min_size(),
+            admission_probability: admission_probability(),
+            admission_threshold: admission_threshold(),
+            dedup_window: dedup_window(),
+            dedup_entries: dedup_entries(),
             datapool_path: datapool_path(),
+            namespace_stats: namespace_stats(),
+            namespace_separator: namespace_separator(),
+            namespace_sample_rate: namespace_sample_rate(),
+            cardinality_interval: cardinality_interval(),
+            size_sample_rate: size_sample_rate(),
+            warmup: warmup(),
+            warmup_shed_ratio: warmup_shed_ratio(),
+            stale_grace: stale_grace(),
+            incr_overflow: incr_overflow(),
+            decr_underflow: decr_underflow(),
         }
     }
 }
@@ -148,9 +310,102 @@ impl Seg {
         self.compact_target
     }
 
+    pub fn admission(&self) -> Admission {
+        self.admission
+    }
+
+    /// Returns the smallest item size, in bytes, which is subject to
+    /// probabilistic admission.
+    pub fn admission_min_size(&self) -> usize {
+        self.admission_min_size
+    }
+
+    /// Returns the probability that a write of a new item is admitted with
+    /// probabilistic admission.
+    pub fn admission_probability(&self) -> f64 {
+        self.admission_probability
+    }
+
+    /// Returns the number of recent accesses of a key which are required for
+    /// a new item to be admitted with frequency admission.
+    pub fn admission_threshold(&self) -> u8 {
+        self.admission_threshold
+    }
+
+    /// Returns the window, in milliseconds, in which a write identical to a
+    /// recent write is acknowledged without being applied. Zero disables
+    /// duplicate suppression.
+    pub fn dedup_window(&self) -> u64 {
+        self.dedup_window
+    }
+
+    /// Returns the number of recent writes which are remembered for duplicate
+    /// suppression.
+    pub fn dedup_entries(&self) -> usize {
+        self.dedup_entries
+    }
+
     pub fn datapool_path(&self) -> Option<PathBuf> {
         self.datapool_path.as_ref().map(|v| Path::new(v).to_owned())
     }
+
+    /// Returns true if statistics should be tracked for each namespace.
+    pub fn namespace_stats(&self) -> bool {
+        self.namespace_stats
+    }
+
+    /// Returns the character which separates the namespace from the rest of
+    /// the key. It must be an ASCII character.
+    pub fn namespace_separator(&self) -> char {
+        self.namespace_separator
+    }
+
+    /// Returns the number of requests per sample of the per-namespace
+    /// statistics. One records every request.
+    pub fn namespace_sample_rate(&self) -> u64 {
+        self.namespace_sample_rate
+    }
+
+    /// Returns the interval, in seconds, at which the estimated number of
+    /// distinct keys read and written is reported. Zero disables estimation.
+    pub fn cardinality_interval(&self) -> u64 {
+        self.cardinality_interval
+    }
+
+    /// Returns the number of writes per sample of the key and value sizes.
+    /// Zero disables sampling.
+    pub fn size_sample_rate(&self) -> u64 {
+        self.size_sample_rate
+    }
+
+    /// Returns the duration, in seconds, of the warm-up period after startup.
+    /// Zero disables the warm-up period.
+    pub fn warmup(&self) -> u64 {
+        self.warmup
+    }
+
+    /// Returns the fraction of reads which are rejected with a retryable
+    /// error during the warm-up period.
+    pub fn warmup_shed_ratio(&self) -> f64 {
+        self.warmup_shed_ratio
+    }
+
+    /// Returns the time, in seconds, for which items are served past their
+    /// expiry while serving stale items is enabled by the admin command. Zero
+    /// means that stale items are never served.
+    pub fn stale_grace(&self) -> u64 {
+        self.stale_grace
+    }
+
+    /// Returns how an increment which exceeds the largest value is handled.
+    pub fn incr_overflow(&self) -> Overflow {
+        self.incr_overflow
+    }
+
+    /// Returns how a decrement below zero is handled.
+    pub fn decr_underflow(&self) -> Overflow {
+        self.decr_underflow
+    }
 }
 
 // trait definitions
@@ -11,6 +11,8 @@ use std::io::Read;
 // constants to define default values
 const DAEMONIZE: bool = false;
 const PID_FILENAME: Option<String> = None;
+const STDOUT: Option<String> = None;
+const STDERR: Option<String> = None;
 const DLOG_INTERVAL: usize = 500;
 
 // helper functions
@@ -22,10 +24,32 @@ fn pid_filename() -> Option<String> {
     PID_FILENAME
 }
 
+fn stdout() -> Option<String> {
+    STDOUT
+}
+
+fn stderr() -> Option<String> {
+    STDERR
+}
+
 fn dlog_interval() -> usize {
     DLOG_INTERVAL
 }
 
+/// The protocol served on the data port.
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Protocol {
+    Memcache,
+    Http,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Self::Memcache
+    }
+}
+
 // struct definitions
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SegcacheConfig {
@@ -34,8 +58,14 @@ pub struct SegcacheConfig {
     daemonize: bool,
     #[serde(default = "pid_filename")]
     pid_filename: Option<String>,
+    #[serde(default = "stdout")]
+    stdout: Option<String>,
+    #[serde(default = "stderr")]
+    stderr: Option<String>,
     #[serde(default = "dlog_interval")]
     dlog_interval: usize,
+    #[serde(default)]
+    protocol: Protocol,
 
     // application modules
     #[serde(default)]
@@ -50,6 +80,13 @@ pub struct SegcacheConfig {
     tls: Tls,
     #[serde(default)]
     seg: Seg,
+    #[serde(default)]
+    grpc: Grpc,
+    #[serde(default)]
+    commands: Commands,
+    // an empty list would be rendered as a value after the tables
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    rules: Vec<Rule>,
 
     // ccommon
     #[serde(default)]
@@ -59,6 +96,8 @@ pub struct SegcacheConfig {
     #[serde(default)]
     klog: Klog,
     #[serde(default)]
+    access_log: AccessLog,
+    #[serde(default)]
     sockio: Sockio,
     #[serde(default)]
     tcp: Tcp,
@@ -90,10 +129,24 @@ impl SegcacheConfig {
         self.pid_filename.clone()
     }
 
+    /// The file to which standard output is appended, if it is redirected.
+    pub fn stdout(&self) -> Option<String> {
+        self.stdout.clone()
+    }
+
+    /// The file to which standard error is appended, if it is redirected.
+    pub fn stderr(&self) -> Option<String> {
+        self.stderr.clone()
+    }
+
     pub fn dlog_interval(&self) -> usize {
         self.dlog_interval
     }
 
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
     /// Prints the configuration
     pub fn print(&self) {
         let config_toml = self.render_config();
@@ -118,18 +171,60 @@ impl BufConfig for SegcacheConfig {
     }
 }
 
+impl CommandsConfig for SegcacheConfig {
+    fn commands(&self) -> &Commands {
+        &self.commands
+    }
+}
+
+impl RulesConfig for SegcacheConfig {
+    fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+}
+
+impl DaemonConfig for SegcacheConfig {
+    fn daemonize(&self) -> bool {
+        self.daemonize
+    }
+
+    fn pid_filename(&self) -> Option<String> {
+        self.pid_filename.clone()
+    }
+
+    fn stdout(&self) -> Option<String> {
+        self.stdout.clone()
+    }
+
+    fn stderr(&self) -> Option<String> {
+        self.stderr.clone()
+    }
+}
+
 impl DebugConfig for SegcacheConfig {
     fn debug(&self) -> &Debug {
         &self.debug
     }
 }
 
+impl GrpcConfig for SegcacheConfig {
+    fn grpc(&self) -> &Grpc {
+        &self.grpc
+    }
+}
+
 impl KlogConfig for SegcacheConfig {
     fn klog(&self) -> &Klog {
         &self.klog
     }
 }
 
+impl AccessLogConfig for SegcacheConfig {
+    fn access_log(&self) -> &AccessLog {
+        &self.access_log
+    }
+}
+
 impl SegConfig for SegcacheConfig {
     fn seg(&self) -> &Seg {
         &self.seg
@@ -182,17 +277,24 @@ impl Default for SegcacheConfig {
         Self {
             daemonize: daemonize(),
             pid_filename: pid_filename(),
+            stdout: stdout(),
+            stderr: stderr(),
             dlog_interval: dlog_interval(),
+            protocol: Default::default(),
 
             admin: Default::default(),
             server: Default::default(),
             worker: Default::default(),
             time: Default::default(),
             seg: Default::default(),
+            grpc: Default::default(),
+            commands: Default::default(),
+            rules: Vec::new(),
 
             buf: Default::default(),
             debug: Default::default(),
             klog: Default::default(),
+            access_log: Default::default(),
             sockio: Default::default(),
             tcp: Default::default(),
             tls: Default::default(),
@@ -222,4 +324,26 @@ mod test {
             assert!(rendered_config.contains(key));
         }
     }
+
+    #[test]
+    fn it_should_parse_the_caching_rules_in_order() {
+        use crate::RulesConfig;
+
+        let config: SegcacheConfig = toml::from_str(
+            "[[rules]]\nname = \"sessions\"\nprefix = \"session:\"\nttl = 60\n\n\
+            [[rules]]\nname = \"blocked\"\nregex = \"^tmp\"\ndeny = true\n",
+        )
+        .expect("failed to parse config");
+
+        let rules = config.rules();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].name(), "sessions");
+        assert_eq!(rules[0].prefix(), Some("session:"));
+        assert_eq!(rules[0].ttl(), Some(60));
+        assert!(!rules[0].deny());
+        assert_eq!(rules[1].regex(), Some("^tmp"));
+        assert!(rules[1].deny());
+
+        assert!(config.render_config().contains("[[rules]]"));
+    }
 }
@@ -4,15 +4,27 @@
 
 use serde::{Deserialize, Serialize};
 
-use std::net::{AddrParseError, SocketAddr};
+use crate::resolve::resolve;
+use std::net::SocketAddr;
 
 // constants to define default values
+const SERVER_NAME: &str = "data";
 const SERVER_HOST: &str = "0.0.0.0";
 const SERVER_PORT: &str = "12321";
 const SERVER_TIMEOUT: usize = 100;
 const SERVER_NEVENT: usize = 1024;
+const SERVER_STRICT_PROTOCOL: bool = false;
+const SERVER_MAX_HANDSHAKES: usize = 0;
+const SERVER_HANDSHAKE_TIMEOUT: usize = 0;
+const SERVER_SHAPING_LATENCY: usize = 0;
+const SERVER_SHAPING_JITTER: usize = 0;
+const SERVER_SHAPING_BANDWIDTH: u64 = 0;
 
 // helper functions
+fn name() -> String {
+    SERVER_NAME.to_string()
+}
+
 fn host() -> String {
     SERVER_HOST.to_string()
 }
@@ -29,9 +41,35 @@ fn nevent() -> usize {
     SERVER_NEVENT
 }
 
+fn strict_protocol() -> bool {
+    SERVER_STRICT_PROTOCOL
+}
+
+fn max_handshakes() -> usize {
+    SERVER_MAX_HANDSHAKES
+}
+
+fn handshake_timeout() -> usize {
+    SERVER_HANDSHAKE_TIMEOUT
+}
+
+fn shaping_latency() -> usize {
+    SERVER_SHAPING_LATENCY
+}
+
+fn shaping_jitter() -> usize {
+    SERVER_SHAPING_JITTER
+}
+
+fn shaping_bandwidth() -> u64 {
+    SERVER_SHAPING_BANDWIDTH
+}
+
 // definitions
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Server {
+    #[serde(default = "name")]
+    name: String,
     #[serde(default = "host")]
     host: String,
     #[serde(default = "port")]
@@ -40,10 +78,28 @@ pub struct Server {
     timeout: usize,
     #[serde(default = "nevent")]
     nevent: usize,
+    #[serde(default = "strict_protocol")]
+    strict_protocol: bool,
+    #[serde(default = "max_handshakes")]
+    max_handshakes: usize,
+    #[serde(default = "handshake_timeout")]
+    handshake_timeout: usize,
+    #[serde(default = "shaping_latency")]
+    shaping_latency: usize,
+    #[serde(default = "shaping_jitter")]
+    shaping_jitter: usize,
+    #[serde(default = "shaping_bandwidth")]
+    shaping_bandwidth: u64,
 }
 
 // implementation
 impl Server {
+    /// The name under which the requests served by this listener are reported
+    /// by the `stats listeners` admin command
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
     /// Host address to listen on
     pub fn host(&self) -> String {
         self.host.clone()
@@ -54,9 +110,10 @@ impl Server {
         self.port.clone()
     }
 
-    /// Return the result of parsing the host and port
-    pub fn socket_addr(&self) -> Result<SocketAddr, AddrParseError> {
-        format!("{}:{}", self.host(), self.port()).parse()
+    /// Return the result of resolving the host and port. The host may be an
+    /// IP address or a hostname.
+    pub fn socket_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        resolve(&format!("{}:{}", self.host(), self.port()))
     }
 
     /// The poll timeout in milliseconds
@@ -68,16 +125,63 @@ impl Server {
     pub fn nevent(&self) -> usize {
         self.nevent
     }
+
+    /// Reject requests on this listener which are tolerated by the lenient
+    /// parser but are not strictly valid for the protocol
+    pub fn strict_protocol(&self) -> bool {
+        self.strict_protocol
+    }
+
+    /// The number of TLS handshakes in progress at which accepting new
+    /// sessions is deferred until some have completed. Zero means no limit. Only
+    /// applied in builds with the `handshake-backoff` feature.
+    pub fn max_handshakes(&self) -> usize {
+        self.max_handshakes
+    }
+
+    /// The time in milliseconds after which an incomplete TLS handshake is
+    /// abandoned and its session closed. Zero means no timeout. Only applied in
+    /// builds with the `handshake-backoff` feature.
+    pub fn handshake_timeout(&self) -> usize {
+        self.handshake_timeout
+    }
+
+    /// The time in milliseconds for which each response is held before it is
+    /// sent. Only applies to servers built with the `shaping` feature.
+    pub fn shaping_latency(&self) -> usize {
+        self.shaping_latency
+    }
+
+    /// The upper bound in milliseconds of a random delay which is added to
+    /// the latency of each response. Only applies to servers built with the
+    /// `shaping` feature.
+    pub fn shaping_jitter(&self) -> usize {
+        self.shaping_jitter
+    }
+
+    /// The maximum rate in bytes per second at which responses are sent on
+    /// each session. Zero means no limit. Only applies to servers built with
+    /// the `shaping` feature.
+    pub fn shaping_bandwidth(&self) -> u64 {
+        self.shaping_bandwidth
+    }
 }
 
 // trait implementations
 impl Default for Server {
     fn default() -> Self {
         Self {
+            name: name(),
             host: host(),
             port: port(),
             timeout: timeout(),
             nevent: nevent(),
+            strict_protocol: strict_protocol(),
+            max_handshakes: max_handshakes(),
+            handshake_timeout: handshake_timeout(),
+            shaping_latency: shaping_latency(),
+            shaping_jitter: shaping_jitter(),
+            shaping_bandwidth: shaping_bandwidth(),
         }
     }
 }
@@ -5,7 +5,7 @@
 use serde::{Deserialize, Serialize};
 
 // definitions
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
 pub struct Tls {
     #[serde(default)]
     certificate_chain: Option<String>,
@@ -15,6 +15,8 @@ pub struct Tls {
     certificate: Option<String>,
     #[serde(default)]
     ca_file: Option<String>,
+    #[serde(default)]
+    verify_client: bool,
 }
 
 // implementation
@@ -34,6 +36,10 @@ impl common::ssl::TlsConfig for Tls {
     fn ca_file(&self) -> Option<String> {
         self.ca_file.clone()
     }
+
+    fn verify_client(&self) -> bool {
+        self.verify_client
+    }
 }
 
 // trait definitions
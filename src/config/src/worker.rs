@@ -8,6 +8,15 @@ use serde::{Deserialize, Serialize};
 const WORKER_TIMEOUT: usize = 100;
 const WORKER_NEVENT: usize = 1024;
 const WORKER_THREADS: usize = 1;
+const WORKER_COMPOSE_LIMIT: usize = 1024 * 1024; // 1MB
+const WORKER_STORAGE_QUEUE_DEPTH: usize = 0; // unlimited
+const WORKER_STORAGE_DEADLINE: usize = 0; // disabled
+const WORKER_MISS_FILTER_SIZE: usize = 0; // disabled
+const WORKER_MISS_FILTER_INTERVAL: usize = 60;
+const WORKER_AUTOTUNE_NEVENT_MIN: usize = 64;
+const WORKER_AUTOTUNE_NEVENT_MAX: usize = 8192;
+const WORKER_AUTOTUNE_TIMEOUT_MIN: usize = 1;
+const WORKER_AUTOTUNE_TIMEOUT_MAX: usize = 1000;
 
 // helper functions
 fn timeout() -> usize {
@@ -22,7 +31,82 @@ fn threads() -> usize {
     WORKER_THREADS
 }
 
+fn compose_limit() -> usize {
+    WORKER_COMPOSE_LIMIT
+}
+
+fn storage_queue_depth() -> usize {
+    WORKER_STORAGE_QUEUE_DEPTH
+}
+
+fn storage_deadline() -> usize {
+    WORKER_STORAGE_DEADLINE
+}
+
+fn miss_filter_size() -> usize {
+    WORKER_MISS_FILTER_SIZE
+}
+
+fn miss_filter_interval() -> usize {
+    WORKER_MISS_FILTER_INTERVAL
+}
+
+fn autotune_nevent_min() -> usize {
+    WORKER_AUTOTUNE_NEVENT_MIN
+}
+
+fn autotune_nevent_max() -> usize {
+    WORKER_AUTOTUNE_NEVENT_MAX
+}
+
+fn autotune_timeout_min() -> usize {
+    WORKER_AUTOTUNE_TIMEOUT_MIN
+}
+
+fn autotune_timeout_max() -> usize {
+    WORKER_AUTOTUNE_TIMEOUT_MAX
+}
+
 // definitions
+
+/// Determines how a session is handled when the client sends input which
+/// cannot be parsed.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtocolErrorPolicy {
+    /// Close the connection immediately.
+    Close,
+    /// Send an error response, skip the input up to the end of the current
+    /// line, and continue processing requests. Protocols which cannot
+    /// resynchronize on line boundaries always close the connection.
+    Error,
+}
+
+impl Default for ProtocolErrorPolicy {
+    fn default() -> Self {
+        Self::Close
+    }
+}
+
+/// Determines when a thread which pushes to the queue of another thread
+/// signals that thread to wake up.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WakeStrategy {
+    /// Signal the thread only if no wakeup is already pending, so that many
+    /// pushes between two iterations of its event loop cost a single signal.
+    Coalesce,
+    /// Signal the thread on every wakeup, which may reduce latency at the cost
+    /// of a syscall for each one.
+    Immediate,
+}
+
+impl Default for WakeStrategy {
+    fn default() -> Self {
+        Self::Coalesce
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Worker {
     #[serde(default = "timeout")]
@@ -31,6 +115,30 @@ pub struct Worker {
     nevent: usize,
     #[serde(default = "threads")]
     threads: usize,
+    #[serde(default)]
+    protocol_error: ProtocolErrorPolicy,
+    #[serde(default = "compose_limit")]
+    compose_limit: usize,
+    #[serde(default = "storage_queue_depth")]
+    storage_queue_depth: usize,
+    #[serde(default = "storage_deadline")]
+    storage_deadline: usize,
+    #[serde(default)]
+    wake_strategy: WakeStrategy,
+    #[serde(default = "miss_filter_size")]
+    miss_filter_size: usize,
+    #[serde(default = "miss_filter_interval")]
+    miss_filter_interval: usize,
+    #[serde(default)]
+    autotune: bool,
+    #[serde(default = "autotune_nevent_min")]
+    autotune_nevent_min: usize,
+    #[serde(default = "autotune_nevent_max")]
+    autotune_nevent_max: usize,
+    #[serde(default = "autotune_timeout_min")]
+    autotune_timeout_min: usize,
+    #[serde(default = "autotune_timeout_max")]
+    autotune_timeout_max: usize,
 }
 
 // implementation
@@ -47,6 +155,82 @@ impl Worker {
         self.threads
     }
 
+    pub fn protocol_error(&self) -> ProtocolErrorPolicy {
+        self.protocol_error
+    }
+
+    /// Responses larger than this many bytes are composed and flushed across
+    /// multiple event loop iterations.
+    pub fn compose_limit(&self) -> usize {
+        self.compose_limit
+    }
+
+    /// When multiple worker threads are used, requests are rejected with a
+    /// retryable busy error once this many requests are queued for the storage
+    /// thread. Zero means the depth is not limited.
+    pub fn storage_queue_depth(&self) -> usize {
+        self.storage_queue_depth
+    }
+
+    /// When multiple worker threads are used, requests which have been queued
+    /// for the storage thread for longer than this many milliseconds are
+    /// rejected with a retryable busy error instead of being executed. Zero
+    /// means that requests have no deadline.
+    pub fn storage_deadline(&self) -> usize {
+        self.storage_deadline
+    }
+
+    /// How the worker and storage threads are woken when another thread has
+    /// queued messages for them.
+    pub fn wake_strategy(&self) -> WakeStrategy {
+        self.wake_strategy
+    }
+
+    /// When multiple worker threads are used, the size in bytes of a filter
+    /// of the keys which may be stored. Workers answer reads of keys which
+    /// are not in the filter as misses, without queueing them for the storage
+    /// thread. About one byte per stored key keeps false positives to a few
+    /// percent. The storage thread keeps a second filter of the same size,
+    /// which it rebuilds. Zero means that there is no filter.
+    pub fn miss_filter_size(&self) -> usize {
+        self.miss_filter_size
+    }
+
+    /// The number of seconds between rebuilds of the miss filter from the
+    /// keys in storage, which drops the keys of items which have since been
+    /// removed. The storage thread lists the keys a part at a time, between
+    /// the requests it handles, to do so.
+    pub fn miss_filter_interval(&self) -> usize {
+        self.miss_filter_interval
+    }
+
+    /// Whether each worker thread adjusts its `nevent` and `timeout` to its
+    /// load, starting from the configured values and staying within the
+    /// bounds below. Setting either value by hand stops autotuning.
+    pub fn autotune(&self) -> bool {
+        self.autotune
+    }
+
+    /// The fewest events per poll which autotuning may choose.
+    pub fn autotune_nevent_min(&self) -> usize {
+        self.autotune_nevent_min
+    }
+
+    /// The most events per poll which autotuning may choose.
+    pub fn autotune_nevent_max(&self) -> usize {
+        self.autotune_nevent_max
+    }
+
+    /// The shortest poll timeout in milliseconds which autotuning may choose.
+    pub fn autotune_timeout_min(&self) -> usize {
+        self.autotune_timeout_min
+    }
+
+    /// The longest poll timeout in milliseconds which autotuning may choose.
+    pub fn autotune_timeout_max(&self) -> usize {
+        self.autotune_timeout_max
+    }
+
     pub fn set_threads(&mut self, threads: usize) {
         self.threads = threads
     }
@@ -59,6 +243,18 @@ impl Default for Worker {
             timeout: timeout(),
             nevent: nevent(),
             threads: threads(),
+            protocol_error: Default::default(),
+            compose_limit: compose_limit(),
+            storage_queue_depth: storage_queue_depth(),
+            storage_deadline: storage_deadline(),
+            wake_strategy: Default::default(),
+            miss_filter_size: miss_filter_size(),
+            miss_filter_interval: miss_filter_interval(),
+            autotune: false,
+            autotune_nevent_min: autotune_nevent_min(),
+            autotune_nevent_max: autotune_nevent_max(),
+            autotune_timeout_min: autotune_timeout_min(),
+            autotune_timeout_max: autotune_timeout_max(),
         }
     }
 }
@@ -0,0 +1,108 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Shared-secret authentication for the admin port. When a token is
+//! configured, a session must present it with the `auth` command before it may
+//! issue any of the gated commands. Other commands remain available to every
+//! session.
+
+use crate::*;
+
+use std::collections::HashSet;
+
+counter!(
+    ADMIN_AUTH_FAIL,
+    "number of auth commands which presented the wrong token"
+);
+counter!(
+    ADMIN_AUTH_REJECT,
+    "number of admin requests rejected because the session was not authenticated"
+);
+
+pub(crate) struct Auth {
+    token: Option<Box<[u8]>>,
+    commands: Vec<String>,
+    authenticated: HashSet<Token>,
+}
+
+impl Auth {
+    pub fn new(config: &config::Admin) -> Self {
+        Self {
+            token: config
+                .auth_token()
+                .map(|t| t.as_bytes().to_vec().into_boxed_slice()),
+            commands: config.auth_commands().to_vec(),
+            authenticated: HashSet::new(),
+        }
+    }
+
+    /// Returns true if the session may issue the request.
+    pub fn allowed(&self, session: Token, request: &AdminRequest) -> bool {
+        if self.token.is_none() || self.authenticated.contains(&session) {
+            return true;
+        }
+
+        let command = request.command();
+        let allowed = command == "auth" || !self.commands.iter().any(|c| c == command);
+        if !allowed {
+            ADMIN_AUTH_REJECT.increment();
+        }
+        allowed
+    }
+
+    /// Returns true if a request for the command may proceed with the token it
+    /// presented. This is used where each request carries its own token, such
+    /// as on the HTTP admin listener, rather than authenticating the session.
+    pub fn permits(&self, command: &str, presented: Option<&[u8]>) -> bool {
+        let token = match &self.token {
+            Some(token) => token,
+            None => {
+                return true;
+            }
+        };
+
+        if !self.commands.iter().any(|c| c == command) {
+            return true;
+        }
+
+        let permitted = presented
+            .map(|p| constant_time_eq(token, p))
+            .unwrap_or(false);
+        if !permitted {
+            ADMIN_AUTH_REJECT.increment();
+        }
+        permitted
+    }
+
+    /// Marks the session as authenticated if the presented token matches.
+    pub fn authenticate(&mut self, session: Token, presented: &[u8]) -> bool {
+        let matched = match &self.token {
+            Some(token) => constant_time_eq(token, presented),
+            None => true,
+        };
+
+        if matched {
+            self.authenticated.insert(session);
+        } else {
+            ADMIN_AUTH_FAIL.increment();
+        }
+
+        matched
+    }
+
+    /// Forgets the session, so that its token may be reused by a new session.
+    pub fn remove(&mut self, session: Token) {
+        self.authenticated.remove(&session);
+    }
+}
+
+// compares without returning early so the time taken does not reveal how much
+// of the token was guessed correctly
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
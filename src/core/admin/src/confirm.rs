@@ -0,0 +1,259 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Two-step confirmation of the destructive commands, which remove items or
+//! stop the process. A dry run describes what the command would affect, such
+//! as the number of items and bytes it would remove, and returns a token.
+//! Repeating the command with the token runs it, as long as the token has not
+//! expired or already been used. When confirmation is required, a destructive
+//! command which is sent without a token is answered as a dry run, so that a
+//! mistyped command cannot wipe the cache.
+
+use crate::*;
+
+use common::signal::{MigrateReply, MigrateStep};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+counter!(
+    ADMIN_DRY_RUN,
+    "number of dry runs of destructive admin commands"
+);
+counter!(
+    ADMIN_CONFIRM_EX,
+    "number of destructive admin commands rejected because the confirmation token was invalid or expired"
+);
+
+// the number of dry runs awaiting confirmation, beyond which the oldest token
+// is forgotten
+const MAX_PENDING: usize = 16;
+
+struct Pending {
+    token: String,
+    request: AdminRequest,
+    expires: Instant,
+}
+
+pub(crate) struct Confirmations {
+    required: bool,
+    timeout: Duration,
+    pending: VecDeque<Pending>,
+    random: RandomState,
+    issued: u64,
+}
+
+impl Confirmations {
+    pub fn new(config: &config::Admin) -> Self {
+        Self {
+            required: config.confirm_destructive(),
+            timeout: Duration::from_secs(config.confirm_timeout() as u64),
+            pending: VecDeque::new(),
+            random: RandomState::new(),
+            issued: 0,
+        }
+    }
+
+    /// Returns true if the destructive commands only run once confirmed.
+    pub fn required(&self) -> bool {
+        self.required
+    }
+
+    /// Describes what the request would affect and returns a token which
+    /// confirms it.
+    pub fn dry_run(
+        &mut self,
+        signal_queue_tx: &mut Queues<Signal, Reply>,
+        request: AdminRequest,
+    ) -> DryRun {
+        ADMIN_DRY_RUN.increment();
+
+        let impact = impact(signal_queue_tx, &request);
+
+        // the tokens are unpredictable, so that one cannot be guessed from
+        // the tokens which were returned to other sessions
+        self.issued += 1;
+        let mut hasher = self.random.build_hasher();
+        hasher.write_u64(self.issued);
+        let token = format!("{:016x}", hasher.finish());
+
+        let now = Instant::now();
+        self.pending.retain(|pending| pending.expires > now);
+        if self.pending.len() >= MAX_PENDING {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(Pending {
+            token: token.clone(),
+            request,
+            expires: now + self.timeout,
+        });
+
+        DryRun {
+            token,
+            expires: self.timeout,
+            impact,
+        }
+    }
+
+    /// Returns true if the token was returned by a dry run of the same request
+    /// and has not expired. Each token confirms a single request.
+    pub fn confirm(&mut self, request: &AdminRequest, token: &str) -> bool {
+        let now = Instant::now();
+        self.pending.retain(|pending| pending.expires > now);
+
+        match self
+            .pending
+            .iter()
+            .position(|pending| pending.token == token && &pending.request == request)
+        {
+            Some(index) => {
+                self.pending.remove(index);
+                true
+            }
+            None => {
+                ADMIN_CONFIRM_EX.increment();
+                false
+            }
+        }
+    }
+}
+
+/// Estimates what a destructive command would affect. Commands which remove
+/// items report the number of items and bytes, and a shutdown also reports
+/// the number of connections which would be closed.
+fn impact(
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+    request: &AdminRequest,
+) -> Vec<(&'static str, u64)> {
+    let items = gauge("item_current");
+    let bytes = gauge("item_current_bytes");
+
+    match request {
+        AdminRequest::FlushAll => vec![("items", items), ("bytes", bytes)],
+        AdminRequest::FlushAllDelayed(delay) => vec![
+            ("items", items),
+            ("bytes", bytes),
+            ("delay", Expiry::from_memcache(*delay).as_secs().into()),
+        ],
+        AdminRequest::FlushNamespace(namespace) => {
+            let mut prefix = namespace.clone();
+            prefix.push(common::namespace::separator());
+
+            // the keys are counted by the storage thread a part of storage at
+            // a time, so that they are not copied to this thread
+            let mut keys = 0;
+            let mut cursor = 0;
+            loop {
+                let step = broadcast(
+                    signal_queue_tx,
+                    Signal::Migrate(MigrateStep::Count {
+                        prefix: prefix.clone(),
+                        cursor,
+                    }),
+                    SIGNAL_TIMEOUT,
+                )
+                .ok()
+                .and_then(|replies| {
+                    replies.into_iter().find_map(|reply| match reply {
+                        Reply::Migrate(MigrateReply::Count(found, next)) => Some((found, next)),
+                        _ => None,
+                    })
+                });
+                match step {
+                    Some((found, next)) => {
+                        keys += found as u64;
+                        cursor = next;
+                    }
+                    None => break,
+                }
+                if cursor == 0 {
+                    break;
+                }
+            }
+
+            // the size of each item is not counted, so the bytes are estimated
+            // from the average item size
+            let estimate = if items > 0 { keys * bytes / items } else { 0 };
+            vec![("items", keys), ("bytes", estimate)]
+        }
+        AdminRequest::FlushTtlBucket(index) => {
+            let bucket = broadcast(signal_queue_tx, Signal::SegmentStats, SIGNAL_TIMEOUT)
+                .ok()
+                .and_then(|replies| {
+                    replies.into_iter().find_map(|reply| match reply {
+                        Reply::Segments(buckets) => {
+                            buckets.into_iter().find(|bucket| bucket.index == *index)
+                        }
+                        _ => None,
+                    })
+                });
+
+            match bucket {
+                Some(bucket) => vec![("items", bucket.live_items), ("bytes", bucket.live_bytes)],
+                None => vec![("items", 0), ("bytes", 0)],
+            }
+        }
+        AdminRequest::Shutdown => vec![
+            ("items", items),
+            ("bytes", bytes),
+            ("connections", gauge("tcp_conn_curr")),
+        ],
+        _ => Vec::new(),
+    }
+}
+
+// the value of the named gauge, or zero if there is none, as is the case for
+// storage metrics in a process which has no storage
+fn gauge(name: &str) -> u64 {
+    for metric in &rustcommon_metrics::metrics() {
+        if metric.name() != name {
+            continue;
+        }
+        if let Some(gauge) = metric.as_any().and_then(|any| any.downcast_ref::<Gauge>()) {
+            return gauge.value().max(0) as u64;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn confirmations() -> Confirmations {
+        Confirmations {
+            required: true,
+            timeout: Duration::from_secs(60),
+            pending: VecDeque::new(),
+            random: RandomState::new(),
+            issued: 0,
+        }
+    }
+
+    #[test]
+    fn confirm() {
+        let mut confirmations = confirmations();
+        let request = AdminRequest::FlushNamespace(b"tenant".to_vec());
+        let token = "0123456789abcdef".to_string();
+        confirmations.pending.push_back(Pending {
+            token: token.clone(),
+            request: request.clone(),
+            expires: Instant::now() + Duration::from_secs(60),
+        });
+
+        // the token only confirms the request it was issued for, and only once
+        assert!(!confirmations.confirm(&AdminRequest::FlushAll, &token));
+        assert!(!confirmations.confirm(&request, "fedcba9876543210"));
+        assert!(confirmations.confirm(&request, &token));
+        assert!(!confirmations.confirm(&request, &token));
+
+        // expired tokens are forgotten
+        confirmations.pending.push_back(Pending {
+            token: token.clone(),
+            request: request.clone(),
+            expires: Instant::now(),
+        });
+        assert!(!confirmations.confirm(&request, &token));
+        assert!(confirmations.pending.is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The HTTP admin listener. This runs on the admin thread alongside the ASCII
+//! admin listener and shares its event loop. Session tokens are offset so that
+//! they can be told apart from those of the ASCII admin sessions.
+
+use crate::*;
+
+use std::collections::HashSet;
+
+counter!(
+    ADMIN_HTTP_REQUEST,
+    "number of requests received on the http admin listener"
+);
+counter!(
+    ADMIN_HTTP_SESSION_ACCEPT,
+    "number of sessions accepted on the http admin listener"
+);
+counter!(
+    ADMIN_HTTP_SESSION_REJECT,
+    "number of sessions closed on accept because the session limit was reached"
+);
+counter!(
+    ADMIN_HTTP_SESSION_CLOSE,
+    "number of sessions closed on the http admin listener"
+);
+
+pub(crate) const HTTP_LISTENER_TOKEN: Token = Token(usize::MAX - 2);
+// tokens for http sessions start here, which leaves the lower half of the token
+// space for the ascii admin sessions
+pub(crate) const HTTP_SESSION_OFFSET: usize = usize::MAX / 2;
+
+pub(crate) struct HttpAdmin {
+    auth: Auth,
+    /// Sessions which asked to be closed, which are closed once their last
+    /// response has been written
+    closing: HashSet<Token>,
+    compose_limit: usize,
+    /// The dry runs of `/flush_all` which await confirmation
+    confirmations: Confirmations,
+    flush_enabled: bool,
+    flush_timeout: Duration,
+    /// Limits the rate of requests on each session, as on the ASCII admin
+    /// listener
+    limits: Limits,
+    listener: ::net::Listener,
+    max_sessions: usize,
+    sessions: Slab<ServerSession<HttpAdminRequestParser, HttpAdminResponse, HttpAdminRequest>>,
+    snapshot_timeout: Duration,
+    version: String,
+}
+
+impl HttpAdmin {
+    pub fn new(addr: SocketAddr, poll: &Poll, config: &config::Admin) -> Result<Self> {
+        let mut listener = ::net::Listener::from(TcpListener::bind(addr)?);
+        listener.register(poll.registry(), HTTP_LISTENER_TOKEN, Interest::READABLE)?;
+
+        Ok(Self {
+            auth: Auth::new(config),
+            closing: HashSet::new(),
+            compose_limit: config.compose_limit(),
+            confirmations: Confirmations::new(config),
+            flush_enabled: flush_enabled(config),
+            flush_timeout: Duration::from_millis(config.flush_timeout() as u64),
+            limits: Limits::new(config),
+            listener,
+            max_sessions: config.max_sessions(),
+            sessions: Slab::new(),
+            snapshot_timeout: Duration::from_millis(config.snapshot_timeout() as u64),
+            version: "unknown".to_string(),
+        })
+    }
+
+    pub fn version(&mut self, version: &str) {
+        self.version = version.to_string();
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Returns true if the token belongs to an http session.
+    pub fn is_session(token: Token) -> bool {
+        token.0 >= HTTP_SESSION_OFFSET && token != HTTP_LISTENER_TOKEN
+    }
+
+    /// Call accept one time. Returns true if a session was accepted, in which
+    /// case there may be more sessions waiting.
+    pub fn accept(&mut self, poll: &Poll) -> bool {
+        let mut session = match self.listener.accept() {
+            Ok(session) => {
+                ServerSession::new(Session::from(session), HttpAdminRequestParser::default())
+                    .compose_limit(self.compose_limit)
+            }
+            Err(_) => {
+                return false;
+            }
+        };
+
+        // sessions beyond the limit are closed immediately
+        if self.sessions.len() >= self.max_sessions {
+            ADMIN_HTTP_SESSION_REJECT.increment();
+            return true;
+        }
+
+        let s = self.sessions.vacant_entry();
+        let token = Token(s.key() + HTTP_SESSION_OFFSET);
+        let interest = session.interest();
+        if session.register(poll.registry(), token, interest).is_ok() {
+            ADMIN_HTTP_SESSION_ACCEPT.increment();
+            s.insert(session);
+        }
+
+        true
+    }
+
+    /// Handle a single session event.
+    pub fn session_event(
+        &mut self,
+        poll: &Poll,
+        signal_queue_tx: &mut Queues<Signal, Reply>,
+        event: &Event,
+    ) {
+        let token = event.token();
+
+        let result = if event.is_error() {
+            Err(Error::new(ErrorKind::Other, "error event"))
+        } else if event.is_writable() || event.is_readable() {
+            self.handle(poll, signal_queue_tx, token, event.is_readable())
+        } else {
+            Ok(())
+        };
+
+        if result.is_err() {
+            self.close(token);
+        }
+    }
+
+    fn handle(
+        &mut self,
+        poll: &Poll,
+        signal_queue_tx: &mut Queues<Signal, Reply>,
+        token: Token,
+        readable: bool,
+    ) -> Result<()> {
+        let session = self
+            .sessions
+            .get_mut(token.0 - HTTP_SESSION_OFFSET)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "non-existant session"))?;
+
+        if readable {
+            match session.fill() {
+                Ok(0) => Err(Error::new(ErrorKind::Other, "client hangup")),
+                r => r,
+            }?;
+        }
+
+        loop {
+            // a large response is composed as the write buffer drains, and
+            // further requests are only handled once it is complete
+            if session.is_composing() {
+                session.compose_next();
+                match session.flush() {
+                    Ok(_) => Ok(()),
+                    Err(e) => map_err(e),
+                }?;
+                if session.is_composing() {
+                    break;
+                }
+            }
+
+            if self.closing.contains(&token) {
+                break;
+            }
+
+            let request = match session.receive() {
+                Ok(request) => request,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            };
+
+            ADMIN_HTTP_REQUEST.increment();
+
+            let close = request.close();
+            let response = match request {
+                _ if !self.limits.allowed(token) => {
+                    HttpAdminResponse::text(HttpStatus::TooManyRequests, "rate limit exceeded")
+                }
+                HttpAdminRequest::Metrics { .. } => snapshot(
+                    signal_queue_tx,
+                    self.snapshot_timeout,
+                    HttpAdminResponse::metrics,
+                ),
+                HttpAdminRequest::Health { .. } => {
+                    HttpAdminResponse::probe(probe(signal_queue_tx, false, SIGNAL_TIMEOUT))
+                }
+                HttpAdminRequest::Ready { .. } => {
+                    HttpAdminResponse::probe(probe(signal_queue_tx, true, SIGNAL_TIMEOUT))
+                }
+                HttpAdminRequest::Stats { .. } => snapshot(
+                    signal_queue_tx,
+                    self.snapshot_timeout,
+                    HttpAdminResponse::stats,
+                ),
+                HttpAdminRequest::Version { .. } => HttpAdminResponse::version(&self.version),
+                HttpAdminRequest::BuildInfo { .. } => HttpAdminResponse::build_info(&self.version),
+                HttpAdminRequest::FlushAll { .. } if !self.flush_enabled => {
+                    HttpAdminResponse::text(HttpStatus::Forbidden, "command disabled")
+                }
+                HttpAdminRequest::FlushAll { token, .. }
+                    if !self.auth.permits("flush_all", token.as_deref()) =>
+                {
+                    HttpAdminResponse::status(HttpStatus::Unauthorized)
+                }
+                HttpAdminRequest::FlushAll {
+                    confirm: Some(confirm),
+                    ..
+                } if !self
+                    .confirmations
+                    .confirm(&AdminRequest::FlushAll, &confirm) =>
+                {
+                    HttpAdminResponse::text(
+                        HttpStatus::Forbidden,
+                        "invalid or expired confirmation token",
+                    )
+                }
+                HttpAdminRequest::FlushAll {
+                    dry_run, confirm, ..
+                } if dry_run || (confirm.is_none() && self.confirmations.required()) => {
+                    HttpAdminResponse::dry_run(
+                        &self
+                            .confirmations
+                            .dry_run(signal_queue_tx, AdminRequest::FlushAll),
+                    )
+                }
+                HttpAdminRequest::FlushAll { .. } => {
+                    match flush_all(signal_queue_tx, None, self.flush_timeout) {
+                        Ok(()) => HttpAdminResponse::text(HttpStatus::Ok, "ok"),
+                        Err(e) => HttpAdminResponse::text(HttpStatus::InternalServerError, &e),
+                    }
+                }
+                HttpAdminRequest::LogLevel { .. } => {
+                    HttpAdminResponse::log_level(logger::log_level())
+                }
+                HttpAdminRequest::SetLogLevel { token, .. }
+                    if !self.auth.permits("loglevel", token.as_deref()) =>
+                {
+                    HttpAdminResponse::status(HttpStatus::Unauthorized)
+                }
+                HttpAdminRequest::SetLogLevel { level, .. } => {
+                    info!("admin changed log level to {}", level);
+                    logger::set_log_level(level);
+                    HttpAdminResponse::text(HttpStatus::Ok, "ok")
+                }
+                HttpAdminRequest::Invalid { status, .. } => HttpAdminResponse::status(status),
+            };
+            session.send(response.close(close))?;
+
+            if close {
+                self.closing.insert(token);
+            }
+        }
+
+        match session.flush() {
+            Ok(_) => Ok(()),
+            Err(e) => map_err(e),
+        }?;
+
+        // the session is only closed once the response has been written in
+        // full, as a single flush may not write all of a large response
+        if self.closing.contains(&token) && !session.is_composing() && session.write_pending() == 0
+        {
+            return Err(Error::new(ErrorKind::Other, "should hangup"));
+        }
+
+        let interest = session.interest();
+        session.reregister(poll.registry(), token, interest)
+    }
+
+    fn close(&mut self, token: Token) {
+        let key = token.0 - HTTP_SESSION_OFFSET;
+        self.closing.remove(&token);
+        self.limits.remove(token);
+        if self.sessions.contains(key) {
+            ADMIN_HTTP_SESSION_CLOSE.increment();
+            let mut session = self.sessions.remove(key);
+            let _ = session.flush();
+        }
+    }
+}
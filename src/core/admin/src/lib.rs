@@ -4,22 +4,45 @@
 
 use ::net::event::{Event, Source};
 use ::net::*;
-use common::signal::Signal;
-use common::ssl::tls_acceptor;
-use config::{AdminConfig, TlsConfig};
+use common::expiry::Expiry;
+use common::fingerprint::Fingerprint;
+use common::signal::{
+    EventLoop, Gate, Reload, Reply, SessionInfo, Signal, ThreadHealth, TtlBucketInfo, Tunable,
+};
+use common::ssl::{tls_acceptor, TlsConfig};
+use common::time::Clock;
+use config::{AdminConfig, DebugConfig, ReloadConfig, ServerConfig, Tls, WorkerConfig};
 use crossbeam_channel::Receiver;
 use logger::*;
 use protocol_admin::*;
-use queues::Queues;
+use queues::{Queues, Waiter};
 use rustcommon_metrics::*;
 use session::{Buf, ServerSession, Session};
 use slab::Slab;
 use std::collections::VecDeque;
 use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use waker::Waker;
 
+mod auth;
+mod confirm;
+mod http;
+mod limit;
+mod migrate;
+mod profile;
+mod rates;
+
+use auth::Auth;
+use confirm::Confirmations;
+use http::*;
+use limit::Limits;
+use migrate::Migration;
+use profile::*;
+use rates::Rates;
+
 counter!(ADMIN_REQUEST_PARSE);
 counter!(ADMIN_RESPONSE_COMPOSE);
 counter!(ADMIN_EVENT_ERROR);
@@ -45,6 +68,11 @@ counter!(RU_NSIGNALS);
 counter!(RU_NVCSW);
 counter!(RU_NIVCSW);
 
+gauge!(
+    CLOCK_DRIFT,
+    "difference in microseconds between the time elapsed on the wall clock and the monotonic clock since startup"
+);
+
 counter!(
     ADMIN_SESSION_ACCEPT,
     "total number of attempts to accept a session"
@@ -65,11 +93,55 @@ counter!(
 
 gauge!(ADMIN_SESSION_CURR, "current number of admin sessions");
 
+counter!(
+    ADMIN_FLUSH_INCOMPLETE,
+    "number of times a flush_all was not acknowledged by every thread in time"
+);
+
+counter!(
+    ADMIN_SNAPSHOT_INCOMPLETE,
+    "number of times metrics were read for a stats response without every thread paused"
+);
+
+counter!(
+    ADMIN_TLS_RELOAD,
+    "total number of attempts to reload the tls certificates"
+);
+counter!(
+    ADMIN_TLS_RELOAD_EX,
+    "number of times reloading the tls certificates failed"
+);
+
+counter!(
+    ADMIN_RELOAD,
+    "total number of attempts to reload the configuration"
+);
+counter!(
+    ADMIN_RELOAD_EX,
+    "number of times reloading the configuration failed"
+);
+counter!(
+    ADMIN_SHUTDOWN,
+    "number of times the shutdown command was received"
+);
+gauge!(
+    STATS_RESET_TIMESTAMP,
+    "unix time in seconds at which the counters were last reset by stats reset, or zero if they never were"
+);
+
+counter!(
+    ADMIN_TUNE,
+    "number of event loop settings changed with the tune command"
+);
+
 // consts
 
 const LISTENER_TOKEN: Token = Token(usize::MAX - 1);
 const WAKER_TOKEN: Token = Token(usize::MAX);
 
+// how long to wait for all threads to reply to a session command
+const SIGNAL_TIMEOUT: Duration = Duration::from_secs(1);
+
 const KB: u64 = 1024; // one kilobyte in bytes
 const S: u64 = 1_000_000_000; // one second in nanoseconds
 const US: u64 = 1_000; // one microsecond in nanoseconds
@@ -83,25 +155,643 @@ fn map_err(e: std::io::Error) -> Result<()> {
     }
 }
 
+/// Sends a signal to all sibling threads without waiting for their replies.
+/// Returns the sequence number which the replies carry and the number of
+/// replies to expect.
+fn signal(signal_queue_tx: &mut Queues<Signal, Reply>, signal: Signal) -> Result<(u64, usize)> {
+    let threads = signal_queue_tx.receivers();
+    let seq = match signal_queue_tx.try_request_all(signal) {
+        Ok(seq) => seq,
+        Err(_) => {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "failed to send signal to all threads",
+            ));
+        }
+    };
+    let _ = signal_queue_tx.wake();
+
+    Ok((seq, threads))
+}
+
+/// Sends a signal to all sibling threads and collects their replies, waiting
+/// up to the timeout for each of them to reply.
+fn broadcast(
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+    signal: Signal,
+    timeout: Duration,
+) -> Result<Vec<Reply>> {
+    let (seq, threads) = self::signal(signal_queue_tx, signal)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut replies = Vec::with_capacity(threads);
+    while replies.len() < threads {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match signal_queue_tx.recv_timeout(timeout) {
+            Some(reply) if reply.seq() == seq => {
+                replies.push(reply.into_inner());
+            }
+            // discard any replies which arrived late for an earlier signal
+            Some(_) => {}
+            None => {
+                break;
+            }
+        }
+    }
+
+    Ok(replies)
+}
+
+/// Pauses all sibling threads while `read` reads the metrics, so that they
+/// reflect a single point in time, and resumes them once it returns. With a
+/// zero timeout the metrics are read without pausing. If a thread does not
+/// pause within the timeout, the metrics are read regardless.
+fn snapshot<T>(
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+    timeout: Duration,
+    read: impl FnOnce() -> T,
+) -> T {
+    if timeout.is_zero() {
+        return read();
+    }
+
+    // the gate releases the threads by itself after the timeout, so that the
+    // data path is not stalled for long if the admin thread falls behind
+    let gate = Gate::new(timeout);
+    let threads = signal_queue_tx.receivers();
+    match broadcast(signal_queue_tx, Signal::Pause(gate.clone()), timeout) {
+        Ok(replies) if replies.len() == threads => {}
+        _ => {
+            ADMIN_SNAPSHOT_INCOMPLETE.increment();
+        }
+    }
+
+    let result = read();
+    gate.open();
+    result
+}
+
+/// Sends a flush to all sibling threads and waits, up to the timeout, for each
+/// of them to acknowledge that it has been applied. A flush with a time is
+/// acknowledged once it has been scheduled. Returns a description of the
+/// failure if any thread did not acknowledge it in time.
+fn flush_all(
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+    at: Option<SystemTime>,
+    timeout: Duration,
+) -> std::result::Result<(), String> {
+    let signal = match at {
+        Some(time) => Signal::FlushAllAt(time),
+        None => Signal::FlushAll,
+    };
+
+    let threads = signal_queue_tx.receivers();
+    let acknowledged = match broadcast(signal_queue_tx, signal, timeout) {
+        Ok(replies) => replies.len(),
+        Err(_) => {
+            ADMIN_FLUSH_INCOMPLETE.increment();
+            return Err("failed to send flush_all to all threads".to_string());
+        }
+    };
+
+    if acknowledged < threads {
+        ADMIN_FLUSH_INCOMPLETE.increment();
+        warn!(
+            "flush_all was applied by only {} of {} threads",
+            acknowledged, threads
+        );
+        return Err(format!(
+            "flush_all applied by {} of {} threads",
+            acknowledged, threads
+        ));
+    }
+
+    Ok(())
+}
+
+/// Whether the flush commands are enabled on the admin listeners. Builds
+/// without the `flush` feature never allow them.
+fn flush_enabled(config: &config::Admin) -> bool {
+    config.flush_enabled() && cfg!(feature = "flush")
+}
+
+/// Reports the counters from zero, so that a short experiment can read its
+/// totals directly, and records the time of the reset.
+fn reset_stats() {
+    reset_counters();
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    STATS_RESET_TIMESTAMP.set(now as i64);
+}
+
+/// Collects the sessions on the data port from all sibling threads, ordered by
+/// session id.
+fn list_sessions(signal_queue_tx: &mut Queues<Signal, Reply>, timeout: Duration) -> AdminResponse {
+    let threads = signal_queue_tx.receivers();
+    let replies = match broadcast(signal_queue_tx, Signal::ListSessions, timeout) {
+        Ok(replies) => replies,
+        Err(e) => {
+            return AdminResponse::server_error(e);
+        }
+    };
+
+    if replies.len() < threads {
+        return AdminResponse::server_error(format!(
+            "sessions listed by {} of {} threads",
+            replies.len(),
+            threads
+        ));
+    }
+
+    let mut sessions: Vec<SessionInfo> = replies
+        .into_iter()
+        .flat_map(|reply| match reply {
+            Reply::Sessions(sessions) => sessions,
+            _ => Vec::new(),
+        })
+        .collect();
+    sessions.sort_by_key(|session| session.id);
+
+    AdminResponse::sessions(sessions)
+}
+
+/// Collects the occupancy of the ttl buckets from all sibling threads. Only
+/// threads which hold storage report any buckets.
+fn segment_stats(signal_queue_tx: &mut Queues<Signal, Reply>, timeout: Duration) -> AdminResponse {
+    let threads = signal_queue_tx.receivers();
+    let replies = match broadcast(signal_queue_tx, Signal::SegmentStats, timeout) {
+        Ok(replies) => replies,
+        Err(e) => {
+            return AdminResponse::server_error(e);
+        }
+    };
+
+    if replies.len() < threads {
+        return AdminResponse::server_error(format!(
+            "segments reported by {} of {} threads",
+            replies.len(),
+            threads
+        ));
+    }
+
+    let mut buckets: Vec<TtlBucketInfo> = replies
+        .into_iter()
+        .flat_map(|reply| match reply {
+            Reply::Segments(buckets) => buckets,
+            _ => Vec::new(),
+        })
+        .collect();
+    buckets.sort_by_key(|bucket| bucket.index);
+
+    AdminResponse::stats_segments(buckets)
+}
+
+/// Fingerprints a sample of the items in a part of storage, starting at the
+/// cursor, and returns them with the cursor for the next part. Only threads
+/// which hold storage report any items, so none are found if no thread does.
+fn fingerprints(
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+    rate: u64,
+    cursor: usize,
+) -> std::result::Result<(Vec<Fingerprint>, usize), String> {
+    let threads = signal_queue_tx.receivers();
+    let replies = broadcast(
+        signal_queue_tx,
+        Signal::Fingerprints { rate, cursor },
+        SIGNAL_TIMEOUT,
+    )
+    .map_err(|e| e.to_string())?;
+
+    if replies.len() < threads {
+        return Err(format!(
+            "fingerprints reported by {} of {} threads",
+            replies.len(),
+            threads
+        ));
+    }
+
+    Ok(replies
+        .into_iter()
+        .find_map(|reply| match reply {
+            Reply::Fingerprints(fingerprints, cursor) => Some((fingerprints, cursor)),
+            _ => None,
+        })
+        .unwrap_or((Vec::new(), 0)))
+}
+
+/// Collects the occupancy of the hashtable from the sibling thread which holds
+/// storage.
+fn hashtable_stats(
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+    timeout: Duration,
+) -> AdminResponse {
+    let replies = match broadcast(signal_queue_tx, Signal::HashTableStats, timeout) {
+        Ok(replies) => replies,
+        Err(e) => {
+            return AdminResponse::server_error(e);
+        }
+    };
+
+    let hashtable = replies.into_iter().find_map(|reply| match reply {
+        Reply::HashTable(hashtable) => hashtable,
+        _ => None,
+    });
+
+    match hashtable {
+        Some(hashtable) => AdminResponse::stats_hashtable(hashtable),
+        None => AdminResponse::server_error("no hashtable reported"),
+    }
+}
+
+/// Asks all sibling threads to compact the storage they hold and waits, up to
+/// the timeout, for the number of segments each of them freed.
+fn compact(signal_queue_tx: &mut Queues<Signal, Reply>, timeout: Duration) -> AdminResponse {
+    let threads = signal_queue_tx.receivers();
+    let replies = match broadcast(signal_queue_tx, Signal::Compact, timeout) {
+        Ok(replies) => replies,
+        Err(e) => {
+            return AdminResponse::server_error(e);
+        }
+    };
+
+    if replies.len() < threads {
+        return AdminResponse::server_error(format!(
+            "compaction finished by {} of {} threads",
+            replies.len(),
+            threads
+        ));
+    }
+
+    let compacted = replies
+        .iter()
+        .map(|reply| match reply {
+            Reply::Compacted(segments) => *segments,
+            _ => 0,
+        })
+        .sum();
+    info!("admin compaction freed {} segments", compacted);
+
+    AdminResponse::compacted(compacted)
+}
+
+/// Asks all sibling threads to start or stop serving items past their expiry,
+/// and waits, up to the timeout, for the storage to confirm.
+fn serve_stale(
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+    enabled: bool,
+    timeout: Duration,
+) -> AdminResponse {
+    let replies = match broadcast(signal_queue_tx, Signal::ServeStale(enabled), timeout) {
+        Ok(replies) => replies,
+        Err(e) => {
+            return AdminResponse::server_error(e);
+        }
+    };
+
+    if !enabled {
+        info!("admin stopped serving stale items");
+        return AdminResponse::Ok;
+    }
+
+    let grace = replies
+        .iter()
+        .filter_map(|reply| match reply {
+            Reply::ServeStale(grace) => Some(*grace),
+            _ => None,
+        })
+        .max()
+        .unwrap_or_default();
+
+    if grace.is_zero() {
+        return AdminResponse::client_error("serving stale items is not configured");
+    }
+
+    info!(
+        "admin started serving items up to {}s past their expiry",
+        grace.as_secs()
+    );
+    AdminResponse::Ok
+}
+
+/// Probes all sibling threads. The liveness probe only checks that every
+/// thread replies in time, while the readiness probe also checks that the data
+/// listener is registered and storage has been initialized and is not still
+/// warming up.
+fn probe(signal_queue_tx: &mut Queues<Signal, Reply>, ready: bool, timeout: Duration) -> Probe {
+    let threads = signal_queue_tx.receivers();
+    let health: Vec<ThreadHealth> = match broadcast(signal_queue_tx, Signal::Health, timeout) {
+        Ok(replies) => replies
+            .into_iter()
+            .filter_map(|reply| match reply {
+                Reply::Health(health) => Some(health),
+                _ => None,
+            })
+            .collect(),
+        Err(_) => {
+            return Probe::Unresponsive;
+        }
+    };
+
+    if health.len() < threads {
+        Probe::Unresponsive
+    } else if !ready {
+        Probe::Ok
+    } else if !health.iter().any(|h| h.listening) {
+        Probe::NotListening
+    } else if !health.iter().any(|h| h.storage) {
+        Probe::NoStorage
+    } else if health.iter().any(|h| h.warming_up) {
+        Probe::WarmingUp
+    } else {
+        Probe::Ok
+    }
+}
+
+/// Asks all sibling threads to close the session with the given id.
+fn kill_session(
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+    id: u64,
+    timeout: Duration,
+) -> AdminResponse {
+    let threads = signal_queue_tx.receivers();
+    let replies = match broadcast(signal_queue_tx, Signal::KillSession(id), timeout) {
+        Ok(replies) => replies,
+        Err(e) => {
+            return AdminResponse::server_error(e);
+        }
+    };
+
+    if replies
+        .iter()
+        .any(|reply| matches!(reply, Reply::Killed(true)))
+    {
+        info!("admin killed session {}", id);
+        AdminResponse::Ok
+    } else if replies.len() < threads {
+        AdminResponse::server_error(format!(
+            "session {} not found, only {} of {} threads replied",
+            id,
+            replies.len(),
+            threads
+        ))
+    } else {
+        AdminResponse::not_found()
+    }
+}
+
+/// Loads the TLS certificates again from the configured files. All of the
+/// certificates are loaded before any are replaced, so an error leaves the
+/// listeners using their current certificates. The admin listener is updated
+/// here and the acceptor for the data listener is sent to its thread.
+fn reload_tls(
+    tls: &TlsFiles,
+    listener: &mut ::net::Listener,
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+) -> Result<()> {
+    ADMIN_TLS_RELOAD.increment();
+
+    let result = swap_tls(tls, listener, signal_queue_tx);
+
+    match &result {
+        Ok(()) => info!("reloaded tls certificates"),
+        Err(e) => {
+            ADMIN_TLS_RELOAD_EX.increment();
+            error!("failed to reload tls certificates: {}", e);
+        }
+    }
+
+    result
+}
+
+// loads the certificates and replaces the acceptors of the listeners
+fn swap_tls(
+    tls: &TlsFiles,
+    listener: &mut ::net::Listener,
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+) -> Result<()> {
+    let admin = match &tls.admin {
+        Some(config) => Some(
+            tls_acceptor(config)?
+                .ok_or_else(|| Error::new(ErrorKind::Other, "missing admin tls config"))?,
+        ),
+        None => None,
+    };
+    let data = tls_acceptor(&tls.data)?;
+
+    if admin.is_none() && data.is_none() {
+        return Err(Error::new(ErrorKind::Other, "tls is not enabled"));
+    }
+
+    if let Some(acceptor) = admin {
+        listener.set_tls_acceptor(acceptor)?;
+    }
+
+    if let Some(acceptor) = data {
+        if signal_queue_tx
+            .try_send_all(Signal::ReloadTls(acceptor))
+            .is_err()
+        {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "failed to send tls reload to all threads",
+            ));
+        }
+        let _ = signal_queue_tx.wake();
+    }
+
+    Ok(())
+}
+
+/// Reloads the configuration. The settings for the admin thread and the log
+/// level are applied here, while the settings for the listener and workers are
+/// broadcast to those threads.
+fn reload(
+    loader: Option<&ConfigLoader>,
+    nevent: &mut usize,
+    timeout: &mut Duration,
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+) -> Result<()> {
+    ADMIN_RELOAD.increment();
+
+    let loader = loader.ok_or_else(|| {
+        ADMIN_RELOAD_EX.increment();
+        Error::new(ErrorKind::Other, "reload not supported")
+    })?;
+
+    let config = loader().map_err(|e| {
+        ADMIN_RELOAD_EX.increment();
+        error!("failed to reload config: {}", e);
+        e
+    })?;
+
+    *nevent = config.admin().nevent();
+    *timeout = Duration::from_millis(config.admin().timeout() as u64);
+    logger::set_log_level(config.debug().log_level());
+
+    let reload = Reload {
+        server_nevent: config.server().nevent(),
+        server_timeout: Duration::from_millis(config.server().timeout() as u64),
+        worker_nevent: config.worker().nevent(),
+        worker_timeout: Duration::from_millis(config.worker().timeout() as u64),
+    };
+
+    if signal_queue_tx
+        .try_send_all(Signal::Reload(reload))
+        .is_err()
+    {
+        ADMIN_RELOAD_EX.increment();
+        return Err(Error::new(
+            ErrorKind::Other,
+            "failed to send reload to all threads",
+        ));
+    }
+    let _ = signal_queue_tx.wake();
+
+    info!("reloaded config");
+    common::events::publish(common::events::Event::ConfigReloaded);
+    Ok(())
+}
+
+/// Changes a setting of an event loop without a restart. The admin event loop
+/// is changed here, while a change to the listener or the workers is sent to
+/// every thread and applied by those which run that kind of event loop. The
+/// change lasts until the next restart or `reload`.
+fn tune(
+    target: EventLoop,
+    tunable: Tunable,
+    nevent: &mut usize,
+    timeout: &mut Duration,
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+) -> Result<()> {
+    ADMIN_TUNE.increment();
+
+    match (target, tunable) {
+        (EventLoop::Admin, Tunable::Nevent(value)) => *nevent = value,
+        (EventLoop::Admin, Tunable::Timeout(value)) => *timeout = value,
+        _ => {
+            if signal_queue_tx
+                .try_send_all(Signal::Tune(target, tunable))
+                .is_err()
+            {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "failed to send tune to all threads",
+                ));
+            }
+            let _ = signal_queue_tx.wake();
+        }
+    }
+
+    info!("tuned {:?} event loop: {:?}", target, tunable);
+    Ok(())
+}
+
+// set by the SIGHUP handler and cleared once the admin thread has reloaded the
+// tls certificates
+static SIGHUP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sighup(_: libc::c_int) {
+    SIGHUP.store(true, Ordering::Relaxed);
+}
+
+/// The TLS configuration of the admin and data listeners, which is kept so that
+/// the certificates can be loaded again when they are renewed.
+struct TlsFiles {
+    /// The admin listener TLS config, if the admin listener uses TLS
+    admin: Option<Tls>,
+    /// The data listener TLS config
+    data: Tls,
+}
+
+impl TlsFiles {
+    /// Returns true if either listener uses TLS, so that there are
+    /// certificates which can be reloaded.
+    fn enabled(&self) -> bool {
+        self.admin.is_some() || self.data.private_key().is_some()
+    }
+}
+
+/// A `stats diff` request which is waiting for its interval to elapse. Other
+/// requests from the same session are not handled until it has completed, so
+/// that responses are sent in order.
+struct PendingDiff {
+    token: Token,
+    due: Instant,
+    snapshot: StatsSnapshot,
+}
+
+/// A `fingerprints` request which is being answered. Storage is walked a part
+/// at a time and the fingerprints of each part are sent once the previous part
+/// has been written, so that the fingerprints of the whole keyspace are never
+/// held at once. Other requests from the same session are not handled until
+/// the export has completed.
+struct PendingExport {
+    token: Token,
+    rate: u64,
+    cursor: usize,
+}
+
+/// Loads the configuration again from its original source, so that the
+/// `reload` command can apply any changed settings.
+pub type ConfigLoader = Box<dyn Fn() -> Result<Box<dyn ReloadConfig>> + Send>;
+
 pub struct Admin {
-    /// A backlog of tokens that need to be handled
+    /// Tracks which sessions have authenticated
+    auth: Auth,
+    /// A backlog of tokens that need to be handled, either the listener or
+    /// sessions with buffered requests
     backlog: VecDeque<Token>,
+    /// Reference reading of the clocks used to track drift
+    clock: Clock,
+    /// The dry runs of destructive commands which await confirmation
+    confirmations: Confirmations,
     /// The actual network listener for the ASCII Admin Endpoint
     listener: ::net::Listener,
+    /// The listener and sessions for the HTTP Admin Endpoint, if enabled
+    http: Option<HttpAdmin>,
+    /// Caps the number of sessions and the rate of commands for each
+    limits: Limits,
     /// The drain handle for the logger
     log_drain: Box<dyn Drain>,
+    /// The TLS configuration used to reload the certificates
+    tls: TlsFiles,
+    /// Loads the configuration for the `reload` command, if supported
+    loader: Option<ConfigLoader>,
     /// The maximum number of events to process per call to poll
     nevent: usize,
+    /// Responses larger than this are composed across event loop iterations
+    compose_limit: usize,
     /// The actual poll instantance
     poll: Poll,
+    /// Samples the CPU profile for the `profile` commands
+    profiler: Profiler,
+    /// The history of the counters reported by `stats rates`
+    rates: Rates,
     /// The sessions which have been opened
     sessions: Slab<ServerSession<AdminRequestParser, AdminResponse, AdminRequest>>,
     /// A queue for receiving signals from the parent thread
     signal_queue_rx: Receiver<Signal>,
     /// A set of queues for sending signals to sibling threads
-    signal_queue_tx: Queues<Signal, ()>,
+    signal_queue_tx: Queues<Signal, Reply>,
     /// The timeout for each call to poll
     timeout: Duration,
+    /// How long to wait for all threads to apply a flush
+    flush_timeout: Duration,
+    /// Whether the flush commands are enabled
+    flush_enabled: bool,
+    /// How long other threads may be paused while metrics are read
+    snapshot_timeout: Duration,
+    /// The `stats diff` requests which are waiting for their interval
+    diffs: Vec<PendingDiff>,
+    /// The `fingerprints` requests which are being answered
+    exports: Vec<PendingExport>,
+    /// The migration which is in progress, or the most recent one
+    migration: Option<Migration>,
+    /// Set by the `shutdown` command, once its reply has been sent
+    shutdown_pending: bool,
     /// The version of the service
     version: String,
     /// The waker for this thread
@@ -109,85 +799,216 @@ pub struct Admin {
 }
 
 pub struct AdminBuilder {
+    auth: Auth,
     backlog: VecDeque<Token>,
+    confirmations: Confirmations,
     listener: ::net::Listener,
+    http: Option<HttpAdmin>,
+    limits: Limits,
+    tls: TlsFiles,
+    loader: Option<ConfigLoader>,
     nevent: usize,
+    compose_limit: usize,
     poll: Poll,
+    profiler: Profiler,
+    rates: Rates,
     sessions: Slab<ServerSession<AdminRequestParser, AdminResponse, AdminRequest>>,
     timeout: Duration,
+    flush_timeout: Duration,
+    flush_enabled: bool,
+    snapshot_timeout: Duration,
     version: String,
+    waiter: Waiter,
     waker: Arc<Waker>,
 }
 
 impl AdminBuilder {
-    pub fn new<T: AdminConfig + TlsConfig>(config: &T) -> Result<Self> {
-        let tls_config = config.tls();
+    pub fn new<T: AdminConfig>(config: &T) -> Result<Self> {
         let config = config.admin();
 
-        let addr = config.socket_addr().map_err(|e| {
-            error!("{}", e);
-            std::io::Error::new(std::io::ErrorKind::Other, "Bad listen address")
-        })?;
-
-        let tcp_listener = TcpListener::bind(addr)?;
+        let mut listener = if let Some(path) = config.socket() {
+            if config.use_tls() {
+                error!("admin tls is not supported on a unix domain socket");
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "admin tls is not supported on a unix domain socket",
+                ));
+            }
 
-        let mut listener = match (config.use_tls(), tls_acceptor(tls_config)?) {
-            (true, Some(tls_acceptor)) => ::net::Listener::from((tcp_listener, tls_acceptor)),
-            _ => ::net::Listener::from(tcp_listener),
+            ::net::Listener::from(UnixListener::bind(path, config.socket_mode()).map_err(|e| {
+                error!("failed to bind admin socket {}: {}", path, e);
+                e
+            })?)
+        } else {
+            let addr = config.socket_addr().map_err(|e| {
+                error!("{}", e);
+                std::io::Error::new(std::io::ErrorKind::Other, "Bad listen address")
+            })?;
+
+            let tcp_listener = TcpListener::bind(addr)?;
+
+            // the admin port only uses the admin tls config, it never falls
+            // back to the tls config for the data port
+            match (config.use_tls(), tls_acceptor(config.tls())?) {
+                (true, Some(tls_acceptor)) => ::net::Listener::from((tcp_listener, tls_acceptor)),
+                (true, None) => {
+                    error!("admin tls is enabled but no admin certificate is configured");
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        "missing admin tls config",
+                    ));
+                }
+                (false, _) => ::net::Listener::from(tcp_listener),
+            }
         };
 
         let poll = Poll::new()?;
         listener.register(poll.registry(), LISTENER_TOKEN, Interest::READABLE)?;
 
+        let http = if config.http_enabled() {
+            let addr = config.http_socket_addr().map_err(|e| {
+                error!("{}", e);
+                std::io::Error::new(std::io::ErrorKind::Other, "Bad http listen address")
+            })?;
+            Some(HttpAdmin::new(addr, &poll, config)?)
+        } else {
+            None
+        };
+
         let waker = Arc::new(Waker::from(
             ::net::Waker::new(poll.registry(), WAKER_TOKEN).unwrap(),
         ));
 
+        // replies to signals are waited for on their own event loop, so that
+        // the events for the sessions are left for the main event loop
+        let waiter = Waiter::new()?;
+
         let nevent = config.nevent();
+        let compose_limit = config.compose_limit();
         let timeout = Duration::from_millis(config.timeout() as u64);
+        let flush_timeout = Duration::from_millis(config.flush_timeout() as u64);
+        let flush_enabled = flush_enabled(config);
+        let snapshot_timeout = Duration::from_millis(config.snapshot_timeout() as u64);
 
         let sessions = Slab::new();
 
         let version = "unknown".to_string();
 
+        let tls = TlsFiles {
+            admin: config.use_tls().then(|| config.tls().clone()),
+            data: Tls::default(),
+        };
+
         let backlog = VecDeque::new();
 
+        let auth = Auth::new(config);
+
+        let confirmations = Confirmations::new(config);
+
+        let limits = Limits::new(config);
+
+        let profiler = Profiler::new(config);
+
+        let rates = Rates::new(config);
+
         Ok(Self {
+            auth,
             backlog,
+            confirmations,
             listener,
+            http,
+            limits,
+            tls,
+            loader: None,
             nevent,
+            compose_limit,
             poll,
+            profiler,
+            rates,
             sessions,
             timeout,
+            flush_timeout,
+            flush_enabled,
+            snapshot_timeout,
             version,
+            waiter,
             waker,
         })
     }
 
     pub fn version(&mut self, version: &str) {
         self.version = version.to_string();
+        if let Some(http) = self.http.as_mut() {
+            http.version(version);
+        }
+    }
+
+    /// Sets the TLS config of the data listener, so that its certificate is
+    /// also loaded again when the admin reloads the TLS certificates.
+    pub fn data_tls(&mut self, tls: &Tls) {
+        self.tls.data = tls.clone();
+    }
+
+    /// Enables the `reload` command, which uses the loader to read the
+    /// configuration again.
+    pub fn config_loader(&mut self, loader: ConfigLoader) {
+        self.loader = Some(loader);
     }
 
     pub fn waker(&self) -> Arc<Waker> {
         self.waker.clone()
     }
 
+    /// The waker for the replies to signals, which must be used for the admin
+    /// side of the signal queues.
+    pub fn signal_waker(&self) -> Arc<Waker> {
+        self.waiter.waker()
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// The address of the HTTP admin listener, if it is enabled.
+    pub fn http_local_addr(&self) -> Option<Result<SocketAddr>> {
+        self.http.as_ref().map(|http| http.local_addr())
+    }
+
     pub fn build(
         self,
         log_drain: Box<dyn Drain>,
         signal_queue_rx: Receiver<Signal>,
-        signal_queue_tx: Queues<Signal, ()>,
+        mut signal_queue_tx: Queues<Signal, Reply>,
     ) -> Admin {
+        signal_queue_tx.set_waiter(self.waiter);
+
         Admin {
+            auth: self.auth,
             backlog: self.backlog,
+            clock: Clock::new(),
+            confirmations: self.confirmations,
             listener: self.listener,
+            http: self.http,
+            limits: self.limits,
             log_drain,
+            tls: self.tls,
+            loader: self.loader,
             nevent: self.nevent,
+            compose_limit: self.compose_limit,
             poll: self.poll,
+            profiler: self.profiler,
+            rates: self.rates,
             sessions: self.sessions,
             signal_queue_rx,
             signal_queue_tx,
             timeout: self.timeout,
+            flush_timeout: self.flush_timeout,
+            flush_enabled: self.flush_enabled,
+            snapshot_timeout: self.snapshot_timeout,
+            diffs: Vec::new(),
+            exports: Vec::new(),
+            migration: None,
+            shutdown_pending: false,
             version: self.version,
             waker: self.waker,
         }
@@ -245,11 +1066,18 @@ impl Admin {
     fn accept(&mut self) {
         ADMIN_SESSION_ACCEPT.increment();
 
-        match self
-            .listener
-            .accept()
-            .map(|v| ServerSession::new(Session::from(v), AdminRequestParser::default()))
-        {
+        match self.listener.accept().map(|v| {
+            ServerSession::new(Session::from(v), AdminRequestParser::default())
+                .compose_limit(self.compose_limit)
+        }) {
+            Ok(mut session) if !self.limits.accept(self.sessions.len()) => {
+                // best effort to tell the client why it is being closed
+                let _ = session.send(AdminResponse::server_error("too many sessions"));
+                let _ = session.flush();
+
+                self.backlog.push_back(LISTENER_TOKEN);
+                let _ = self.waker.wake();
+            }
             Ok(mut session) => {
                 let s = self.sessions.vacant_entry();
                 let interest = session.interest();
@@ -291,21 +1119,331 @@ impl Admin {
             r => r,
         }?;
 
+        self.handle(token)
+    }
+
+    /// Handle the next request which is buffered for the session, if any.
+    fn handle(&mut self, token: Token) -> Result<()> {
+        let session = self
+            .sessions
+            .get_mut(token.0)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "non-existant session"))?;
+
+        // requests are left in the buffer while a diff or an export is
+        // pending, and are handled once its response has been sent
+        if self.diffs.iter().any(|diff| diff.token == token)
+            || self.exports.iter().any(|export| export.token == token)
+        {
+            return Ok(());
+        }
+
         match session.receive() {
             Ok(request) => {
                 ADMIN_REQUEST_PARSE.increment();
 
+                // a request with a valid confirmation token is handled as if
+                // it had been sent on its own
+                let (request, confirmed) = match request {
+                    AdminRequest::Confirmed { request, token }
+                        if self.confirmations.confirm(&request, &token) =>
+                    {
+                        (*request, true)
+                    }
+                    request => (request, false),
+                };
+
                 // do some request handling
                 match request {
+                    _ if !self.limits.allowed(token) => {
+                        session.send(AdminResponse::client_error("rate limit exceeded"))?;
+                    }
+                    request
+                        if !self.flush_enabled
+                            && matches!(request.command(), "flush_all" | "flush") =>
+                    {
+                        session.send(AdminResponse::client_error("command disabled"))?;
+                    }
+                    request if !self.auth.allowed(token, &request) => {
+                        session.send(AdminResponse::client_error("authentication required"))?;
+                    }
+                    AdminRequest::Auth(secret) => {
+                        if self.auth.authenticate(token, &secret) {
+                            session.send(AdminResponse::Ok)?;
+                        } else {
+                            session.send(AdminResponse::client_error("authentication failed"))?;
+                        }
+                    }
+                    AdminRequest::Confirmed { .. } => {
+                        session.send(AdminResponse::client_error(
+                            "invalid or expired confirmation token",
+                        ))?;
+                    }
+                    AdminRequest::DryRun(request) => {
+                        let dry_run = self
+                            .confirmations
+                            .dry_run(&mut self.signal_queue_tx, *request);
+                        session.send(AdminResponse::dry_run(&dry_run))?;
+                    }
+                    request
+                        if request.is_destructive()
+                            && self.confirmations.required()
+                            && !confirmed =>
+                    {
+                        let dry_run = self
+                            .confirmations
+                            .dry_run(&mut self.signal_queue_tx, request);
+                        session.send(AdminResponse::dry_run(&dry_run))?;
+                    }
+                    AdminRequest::Custom { verb, args } => {
+                        session.send(handle_command(verb, &args))?;
+                    }
                     AdminRequest::FlushAll => {
-                        let _ = self.signal_queue_tx.try_send_all(Signal::FlushAll);
+                        let response =
+                            match flush_all(&mut self.signal_queue_tx, None, self.flush_timeout) {
+                                Ok(()) => AdminResponse::Ok,
+                                Err(e) => AdminResponse::server_error(e),
+                            };
+                        session.send(response)?;
+                    }
+                    AdminRequest::FlushAllDelayed(delay) => {
+                        let delay = Expiry::from_memcache(delay).as_secs();
+                        let time = SystemTime::now() + Duration::from_secs(delay.into());
+                        info!("admin scheduled flush_all in {} seconds", delay);
+                        let response = match flush_all(
+                            &mut self.signal_queue_tx,
+                            Some(time),
+                            self.flush_timeout,
+                        ) {
+                            Ok(()) => AdminResponse::Ok,
+                            Err(e) => AdminResponse::server_error(e),
+                        };
+                        session.send(response)?;
+                    }
+                    AdminRequest::FlushNamespace(namespace) => {
+                        let _ = self
+                            .signal_queue_tx
+                            .try_send_all(Signal::FlushNamespace(namespace));
                         session.send(AdminResponse::Ok)?;
                     }
+                    AdminRequest::FlushTtlBucket(bucket) => {
+                        let _ = self
+                            .signal_queue_tx
+                            .try_send_all(Signal::FlushTtlBucket(bucket));
+                        session.send(AdminResponse::Ok)?;
+                    }
+                    AdminRequest::Fingerprints(rate) => {
+                        // the response is sent a part at a time
+                        self.exports.push(PendingExport {
+                            token,
+                            rate,
+                            cursor: 0,
+                        });
+                    }
+                    AdminRequest::Health => {
+                        let probe = probe(&mut self.signal_queue_tx, false, SIGNAL_TIMEOUT);
+                        session.send(AdminResponse::probe(probe))?;
+                    }
+                    AdminRequest::LogLevel => {
+                        session.send(AdminResponse::log_level(logger::log_level()))?;
+                    }
+                    AdminRequest::SetLogLevel(level) => {
+                        info!("admin changed log level to {}", level);
+                        logger::set_log_level(level);
+                        session.send(AdminResponse::Ok)?;
+                    }
+                    AdminRequest::MaintenanceCompact => {
+                        // compaction visits every segment in use, so it is given
+                        // as long to finish as a flush
+                        let response = compact(&mut self.signal_queue_tx, self.flush_timeout);
+                        session.send(response)?;
+                    }
+                    AdminRequest::MetricsDescribe => {
+                        session.send(AdminResponse::metrics_describe())?;
+                    }
+                    AdminRequest::ServeStale(enabled) => {
+                        let response =
+                            serve_stale(&mut self.signal_queue_tx, enabled, SIGNAL_TIMEOUT);
+                        session.send(response)?;
+                    }
+                    AdminRequest::Migrate {
+                        prefix,
+                        destination,
+                        rate,
+                        delete,
+                    } => {
+                        let response = if self
+                            .migration
+                            .as_ref()
+                            .map(|migration| migration.is_running())
+                            .unwrap_or(false)
+                        {
+                            AdminResponse::client_error("migration already in progress")
+                        } else {
+                            match Migration::start(
+                                prefix,
+                                destination,
+                                rate,
+                                delete,
+                                self.waker.clone(),
+                            ) {
+                                Ok(migration) => {
+                                    self.migration = Some(migration);
+                                    AdminResponse::Ok
+                                }
+                                Err(e) => AdminResponse::server_error(e),
+                            }
+                        };
+                        session.send(response)?;
+                    }
+                    AdminRequest::MigrateCancel => {
+                        let response = match self.migration.as_mut() {
+                            Some(migration) if migration.is_running() => {
+                                migration.cancel();
+                                AdminResponse::Ok
+                            }
+                            _ => AdminResponse::client_error("no migration in progress"),
+                        };
+                        session.send(response)?;
+                    }
+                    AdminRequest::MigrateStatus => {
+                        let response = match self.migration.as_ref() {
+                            Some(migration) => AdminResponse::lines(migration.status()),
+                            None => AdminResponse::NotFound,
+                        };
+                        session.send(response)?;
+                    }
+                    AdminRequest::ProfileStart(frequency) => {
+                        let response = match self.profiler.start(frequency) {
+                            Ok(()) => AdminResponse::Ok,
+                            Err(e) => AdminResponse::server_error(e),
+                        };
+                        session.send(response)?;
+                    }
+                    AdminRequest::ProfileStop => {
+                        let response = match self.profiler.stop() {
+                            Ok(path) => AdminResponse::profile(path.display()),
+                            Err(e) => AdminResponse::server_error(e),
+                        };
+                        session.send(response)?;
+                    }
+                    AdminRequest::ProfileHeap => {
+                        session.send(AdminResponse::heap_stats(heap_stats()))?;
+                    }
                     AdminRequest::Quit => {
                         return Err(Error::new(ErrorKind::Other, "should hangup"));
                     }
-                    AdminRequest::Stats => {
-                        session.send(AdminResponse::Stats)?;
+                    AdminRequest::Ready => {
+                        let probe = probe(&mut self.signal_queue_tx, true, SIGNAL_TIMEOUT);
+                        session.send(AdminResponse::probe(probe))?;
+                    }
+                    AdminRequest::Reload => {
+                        let response = match reload(
+                            self.loader.as_ref(),
+                            &mut self.nevent,
+                            &mut self.timeout,
+                            &mut self.signal_queue_tx,
+                        ) {
+                            Ok(()) => AdminResponse::Ok,
+                            Err(e) => AdminResponse::server_error(e),
+                        };
+                        session.send(response)?;
+                    }
+                    AdminRequest::Tune(target, tunable) => {
+                        let response = match tune(
+                            target,
+                            tunable,
+                            &mut self.nevent,
+                            &mut self.timeout,
+                            &mut self.signal_queue_tx,
+                        ) {
+                            Ok(()) => AdminResponse::Ok,
+                            Err(e) => AdminResponse::server_error(e),
+                        };
+                        session.send(response)?;
+                    }
+                    AdminRequest::ReloadTls => {
+                        let response = match reload_tls(
+                            &self.tls,
+                            &mut self.listener,
+                            &mut self.signal_queue_tx,
+                        ) {
+                            Ok(()) => AdminResponse::Ok,
+                            Err(e) => AdminResponse::server_error(e),
+                        };
+                        session.send(response)?;
+                    }
+                    AdminRequest::SessionsList => {
+                        let response = list_sessions(&mut self.signal_queue_tx, SIGNAL_TIMEOUT);
+                        session.send(response)?;
+                    }
+                    AdminRequest::SessionsKill(id) => {
+                        let response = kill_session(&mut self.signal_queue_tx, id, SIGNAL_TIMEOUT);
+                        session.send(response)?;
+                    }
+                    AdminRequest::Shutdown => {
+                        // the shutdown happens once the reply is flushed, so
+                        // that the client knows it was accepted
+                        ADMIN_SHUTDOWN.increment();
+                        info!("admin requested shutdown");
+                        self.shutdown_pending = true;
+                        session.send(AdminResponse::Ok)?;
+                    }
+                    AdminRequest::Stats(format) => {
+                        let response =
+                            snapshot(&mut self.signal_queue_tx, self.snapshot_timeout, || {
+                                AdminResponse::stats(format)
+                            });
+                        session.send(response)?;
+                    }
+                    AdminRequest::StatsDiff(interval) => {
+                        // the response is sent once the interval has elapsed
+                        self.diffs.push(PendingDiff {
+                            token,
+                            due: Instant::now() + interval,
+                            snapshot: snapshot(
+                                &mut self.signal_queue_tx,
+                                self.snapshot_timeout,
+                                StatsSnapshot::capture,
+                            ),
+                        });
+                    }
+                    AdminRequest::StatsRates => {
+                        session.send(AdminResponse::stats_rates(self.rates.rates()))?;
+                    }
+                    AdminRequest::StatsReset => {
+                        // the threads are paused so that no counter is reset
+                        // in the middle of an update to a related one
+                        snapshot(
+                            &mut self.signal_queue_tx,
+                            self.snapshot_timeout,
+                            reset_stats,
+                        );
+                        info!("admin reset the counters");
+                        session.send(AdminResponse::ok())?;
+                    }
+                    AdminRequest::StatsDetail(enabled) => {
+                        common::namespace::set_enabled(enabled);
+                        session.send(AdminResponse::ok())?;
+                    }
+                    AdminRequest::StatsDetailDump => {
+                        session.send(AdminResponse::stats_detail_dump())?;
+                    }
+                    AdminRequest::StatsListeners => {
+                        session.send(AdminResponse::stats_listeners())?;
+                    }
+                    AdminRequest::StatsNamespaces => {
+                        session.send(AdminResponse::stats_namespaces())?;
+                    }
+                    AdminRequest::StatsHashTable => {
+                        let response = hashtable_stats(&mut self.signal_queue_tx, SIGNAL_TIMEOUT);
+                        session.send(response)?;
+                    }
+                    AdminRequest::StatsSegments => {
+                        let response = segment_stats(&mut self.signal_queue_tx, SIGNAL_TIMEOUT);
+                        session.send(response)?;
+                    }
+                    AdminRequest::BuildInfo => {
+                        session.send(AdminResponse::build_info(&self.version))?;
                     }
                     AdminRequest::Version => {
                         session.send(AdminResponse::version(self.version.clone()))?;
@@ -319,7 +1457,10 @@ impl Admin {
                     Err(e) => map_err(e),
                 }?;
 
-                if session.write_pending() > 0 || session.remaining() > 0 {
+                // a large response which is still being composed also needs
+                // writable events to continue
+                if session.write_pending() > 0 || session.remaining() > 0 || session.is_composing()
+                {
                     let interest = session.interest();
                     if session
                         .reregister(self.poll.registry(), token, interest)
@@ -349,7 +1490,30 @@ impl Admin {
                 ErrorKind::WouldBlock => Ok(()),
                 _ => Err(e),
             },
+        }?;
+
+        // compose the next part of a large response now that the write buffer
+        // has drained, so that it is never buffered all at once
+        if session.is_composing() {
+            session.compose_next();
+
+            match session.flush() {
+                Ok(_) => Ok(()),
+                Err(e) => map_err(e),
+            }?;
+
+            let interest = session.interest();
+            session.reregister(self.poll.registry(), token, interest)?;
+
+            // once the response is complete, resume handling any requests
+            // which are already buffered
+            if !session.is_composing() && session.remaining() > 0 {
+                self.backlog.push_back(token);
+                let _ = self.waker.wake();
+            }
         }
+
+        Ok(())
     }
 
     /// Closes the session with the given token
@@ -358,11 +1522,128 @@ impl Admin {
             ADMIN_SESSION_CLOSE.increment();
             ADMIN_SESSION_CURR.decrement();
 
+            self.auth.remove(token);
+            self.limits.remove(token);
+            self.diffs.retain(|diff| diff.token != token);
+            self.exports.retain(|export| export.token != token);
             let mut session = self.sessions.remove(token.0);
             let _ = session.flush();
         }
     }
 
+    /// Broadcasts a shutdown to all sibling threads, which stops the listener
+    /// from accepting and the workers once their pending writes are flushed.
+    /// The admin sessions and the log are flushed before the event loop stops.
+    fn shutdown(&mut self) {
+        info!("shutting down");
+        let _ = self.signal_queue_tx.try_send_all(Signal::Shutdown);
+        if self.signal_queue_tx.wake().is_err() {
+            fatal!("error waking threads for shutdown");
+        }
+
+        let tokens: Vec<Token> = self.sessions.iter().map(|(key, _)| Token(key)).collect();
+        for token in tokens {
+            self.close(token);
+        }
+
+        let _ = self.log_drain.flush();
+    }
+
+    /// Sends the response for each `stats diff` whose interval has elapsed.
+    fn complete_diffs(&mut self) {
+        let now = Instant::now();
+        let (due, pending): (Vec<PendingDiff>, Vec<PendingDiff>) =
+            self.diffs.drain(..).partition(|diff| diff.due <= now);
+        self.diffs = pending;
+
+        for diff in due {
+            if self.send_diff(&diff).is_err() {
+                self.close(diff.token);
+            }
+        }
+    }
+
+    fn send_diff(&mut self, diff: &PendingDiff) -> Result<()> {
+        let session = self
+            .sessions
+            .get_mut(diff.token.0)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "non-existant session"))?;
+
+        let later = snapshot(
+            &mut self.signal_queue_tx,
+            self.snapshot_timeout,
+            StatsSnapshot::capture,
+        );
+        session.send(AdminResponse::stats_diff(&diff.snapshot, &later))?;
+        ADMIN_RESPONSE_COMPOSE.increment();
+
+        match session.flush() {
+            Ok(_) => Ok(()),
+            Err(e) => map_err(e),
+        }?;
+
+        // requests may have arrived while the diff was pending
+        let interest = session.interest();
+        session.reregister(self.poll.registry(), diff.token, interest)
+    }
+
+    /// Sends the next part of each fingerprint export whose previous part has
+    /// been written.
+    fn continue_exports(&mut self) {
+        for mut export in std::mem::take(&mut self.exports) {
+            match self.send_export(&mut export) {
+                Ok(true) => self.exports.push(export),
+                Ok(false) => {}
+                Err(_) => self.close(export.token),
+            }
+        }
+    }
+
+    // sends the fingerprints of the next part of storage, returning true if
+    // there are more parts to send
+    fn send_export(&mut self, export: &mut PendingExport) -> Result<bool> {
+        let session = self
+            .sessions
+            .get_mut(export.token.0)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "non-existant session"))?;
+
+        // the next part waits until the previous one has been written
+        if session.is_composing() || session.write_pending() > 0 {
+            return Ok(true);
+        }
+
+        let more = match fingerprints(&mut self.signal_queue_tx, export.rate, export.cursor) {
+            Ok((fingerprints, cursor)) => {
+                export.cursor = cursor;
+                session.send(AdminResponse::fingerprints(fingerprints, cursor == 0))?;
+                cursor != 0
+            }
+            Err(e) => {
+                session.send(AdminResponse::server_error(e))?;
+                false
+            }
+        };
+        if !more {
+            ADMIN_RESPONSE_COMPOSE.increment();
+        }
+
+        match session.flush() {
+            Ok(_) => Ok(()),
+            Err(e) => map_err(e),
+        }?;
+
+        let interest = session.interest();
+        session.reregister(self.poll.registry(), export.token, interest)?;
+
+        // requests may have arrived while the export was in progress
+        if !more && session.remaining() > 0 {
+            self.backlog.push_back(export.token);
+            let _ = self.waker.wake();
+        }
+
+        Ok(more)
+    }
+
     fn handshake(&mut self, token: Token) -> Result<()> {
         let session = self
             .sessions
@@ -426,20 +1707,73 @@ impl Admin {
     pub fn run(&mut self) {
         info!(
             "running admin on: {}",
-            self.listener
-                .local_addr()
-                .map(|v| format!("{v}"))
-                .unwrap_or_else(|_| "unknown address".to_string())
+            match self.listener.local_path() {
+                Some(path) => path.display().to_string(),
+                None => self
+                    .listener
+                    .local_addr()
+                    .map(|v| format!("{v}"))
+                    .unwrap_or_else(|_| "unknown address".to_string()),
+            }
         );
 
-        let mut events = Events::with_capacity(self.nevent);
+        if let Some(Ok(addr)) = self.http.as_ref().map(|http| http.local_addr()) {
+            info!("running http admin on: {}", addr);
+        }
+
+        // SIGHUP reloads the tls certificates, which is checked on each
+        // iteration of the event loop. without tls there is nothing to reload,
+        // and the signal keeps its default behavior
+        if self.tls.enabled() {
+            unsafe {
+                libc::signal(
+                    libc::SIGHUP,
+                    on_sighup as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                );
+            }
+        }
+
+        let mut nevent = self.nevent;
+        let mut events = Events::with_capacity(nevent);
 
         loop {
             ADMIN_EVENT_LOOP.increment();
+            common::rusage::sample();
 
-            get_rusage();
+            if SIGHUP.swap(false, Ordering::Relaxed) {
+                info!("received SIGHUP, reloading tls certificates");
+                let _ = reload_tls(&self.tls, &mut self.listener, &mut self.signal_queue_tx);
+            }
+
+            // a reload may have changed the number of events per poll
+            if self.nevent != nevent {
+                nevent = self.nevent;
+                events = Events::with_capacity(nevent);
+            }
 
-            if self.poll.poll(&mut events, Some(self.timeout)).is_err() {
+            get_rusage();
+            CLOCK_DRIFT.set(self.clock.drift());
+            self.rates.sample(Instant::now());
+
+            // wake up in time to respond to the next pending diff, and
+            // without waiting while an export has a part ready to send
+            let timeout = if self.exports.iter().any(|export| {
+                self.sessions
+                    .get(export.token.0)
+                    .map(|session| !session.is_composing() && session.write_pending() == 0)
+                    .unwrap_or(false)
+            }) {
+                Duration::ZERO
+            } else {
+                self.diffs
+                    .iter()
+                    .map(|diff| diff.due.saturating_duration_since(Instant::now()))
+                    .min()
+                    .map(|due| due.min(self.timeout))
+                    .unwrap_or(self.timeout)
+            };
+
+            if self.poll.poll(&mut events, Some(timeout)).is_err() {
                 error!("Error polling");
             }
 
@@ -451,40 +1785,72 @@ impl Admin {
                     LISTENER_TOKEN => {
                         self.accept();
                     }
+                    HTTP_LISTENER_TOKEN => {
+                        if let Some(http) = self.http.as_mut() {
+                            while http.accept(&self.poll) {}
+                        }
+                    }
                     WAKER_TOKEN => {
                         self.waker.reset();
                         let tokens: Vec<Token> = self.backlog.drain(..).collect();
                         for token in tokens {
                             if token == LISTENER_TOKEN {
                                 self.accept();
+                            } else if self.handle(token).is_err() {
+                                self.close(token);
                             }
                         }
                     }
+                    token if HttpAdmin::is_session(token) => {
+                        if let Some(http) = self.http.as_mut() {
+                            http.session_event(&self.poll, &mut self.signal_queue_tx, event);
+                        }
+                    }
                     _ => {
                         self.session_event(event);
                     }
                 }
             }
 
+            self.complete_diffs();
+            self.continue_exports();
+
+            if let Some(migration) = self.migration.as_mut() {
+                migration.step(&mut self.signal_queue_tx);
+            }
+
             // handle all signals
             while let Ok(signal) = self.signal_queue_rx.try_recv() {
                 match signal {
-                    Signal::FlushAll => {}
+                    Signal::FlushAll
+                    | Signal::FlushAllAt(_)
+                    | Signal::FlushNamespace(_)
+                    | Signal::FlushTtlBucket(_)
+                    | Signal::Reload(_)
+                    | Signal::Tune(..)
+                    | Signal::ReloadTls(_)
+                    | Signal::ListSessions
+                    | Signal::KillSession(_)
+                    | Signal::SegmentStats
+                    | Signal::HashTableStats
+                    | Signal::Compact
+                    | Signal::ServeStale(_)
+                    | Signal::Health
+                    | Signal::Migrate(_)
+                    | Signal::Fingerprints { .. }
+                    | Signal::Pause(_) => {}
                     Signal::Shutdown => {
-                        // if a shutdown is received from any
-                        // thread, we will broadcast it to all
-                        // sibling threads and stop our event loop
-                        info!("shutting down");
-                        let _ = self.signal_queue_tx.try_send_all(Signal::Shutdown);
-                        if self.signal_queue_tx.wake().is_err() {
-                            fatal!("error waking threads for shutdown");
-                        }
-                        let _ = self.log_drain.flush();
+                        self.shutdown();
                         return;
                     }
                 }
             }
 
+            if self.shutdown_pending {
+                self.shutdown();
+                return;
+            }
+
             // flush pending log entries to log destinations
             let _ = self.log_drain.flush();
         }
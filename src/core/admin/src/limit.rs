@@ -0,0 +1,88 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Limits on admin sessions, so that a misbehaving client such as a buggy
+//! monitoring agent cannot exhaust the admin thread. The number of concurrent
+//! sessions is capped, and each session may issue a limited number of commands
+//! per second. Anything beyond either limit is rejected with an error.
+
+use crate::*;
+
+use std::collections::HashMap;
+
+counter!(
+    ADMIN_SESSION_REJECT,
+    "number of admin sessions closed on accept because the session limit was reached"
+);
+counter!(
+    ADMIN_REQUEST_RATELIMIT,
+    "number of admin requests rejected by the per-session command rate limit"
+);
+
+// how long each session's command count is accumulated for
+const WINDOW: Duration = Duration::from_secs(1);
+
+pub(crate) struct Limits {
+    max_sessions: usize,
+    rate: u64,
+    windows: HashMap<Token, Window>,
+}
+
+// the number of commands issued by a session since the start of its window
+struct Window {
+    start: Instant,
+    count: u64,
+}
+
+impl Limits {
+    pub fn new(config: &config::Admin) -> Self {
+        Self {
+            max_sessions: config.max_sessions(),
+            rate: config.command_rate(),
+            windows: HashMap::new(),
+        }
+    }
+
+    /// Returns true if another session may be opened while this many sessions
+    /// are open.
+    pub fn accept(&self, sessions: usize) -> bool {
+        let allowed = sessions < self.max_sessions;
+        if !allowed {
+            ADMIN_SESSION_REJECT.increment();
+        }
+        allowed
+    }
+
+    /// Counts a command for the session, returning true if it is within the
+    /// session's command rate.
+    pub fn allowed(&mut self, session: Token) -> bool {
+        if self.rate == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        let window = self.windows.entry(session).or_insert(Window {
+            start: now,
+            count: 0,
+        });
+
+        if now - window.start >= WINDOW {
+            window.start = now;
+            window.count = 0;
+        }
+
+        window.count += 1;
+
+        let allowed = window.count <= self.rate;
+        if !allowed {
+            ADMIN_REQUEST_RATELIMIT.increment();
+        }
+        allowed
+    }
+
+    /// Forgets the session, so that its token may be reused by a new session.
+    pub fn remove(&mut self, session: Token) {
+        self.windows.remove(&session);
+    }
+}
@@ -0,0 +1,487 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Live migration of the items under a prefix to another instance, so that a
+//! tenant can be moved between shared instances without a cold cache. The
+//! admin thread reads the items from storage a batch at a time, and a client
+//! thread sends them to the destination over the memcache protocol, throttled
+//! to the requested rate. Once the destination has stored an item, it may be
+//! removed locally unless it was changed in the meantime.
+//!
+//! Storage is never blocked on the destination: the admin thread only hands
+//! the client thread a new batch once it has caught up, and it is woken as
+//! each batch completes. Nor is storage blocked for long by the migration: the
+//! keys under the prefix are found by scanning a part of the hashtable at a
+//! time, as batches are needed, and the destination is resolved by the client
+//! thread.
+
+use crate::*;
+
+use common::signal::{MigrateReply, MigrateStep, MigrationItem};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+counter!(ADMIN_MIGRATE, "number of migrations which were started");
+gauge!(MIGRATE_ACTIVE, "set to 1 while a migration is in progress");
+counter!(
+    MIGRATE_ITEM,
+    "number of items stored by the destination of a migration"
+);
+counter!(
+    MIGRATE_ITEM_EX,
+    "number of items which the destination of a migration did not store"
+);
+counter!(
+    MIGRATE_REMOVE,
+    "number of items removed from storage after being migrated"
+);
+
+// the number of items exported from storage at a time
+const BATCH_SIZE: usize = 64;
+
+// the number of batches which may be waiting to be sent by the client thread
+const MAX_IN_FLIGHT: usize = 2;
+
+// the number of steps of the scan for keys under the prefix which may be taken
+// each time the migration is moved forward, so that a prefix which matches few
+// keys does not hold up the admin thread
+const SCAN_STEPS: usize = 16;
+
+// how long to wait to connect to, write to, or read from the destination
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+// memcache reads an expiry of more than 30 days as a UNIX timestamp, so items
+// with a longer ttl are sent with the time at which they expire
+const MAX_RELATIVE_EXPIRY: u64 = 60 * 60 * 24 * 30;
+
+/// The state of a migration, as reported by `migrate status`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum State {
+    Running,
+    Done,
+    Cancelled,
+    Failed(String),
+}
+
+// the outcome of sending a batch, reported by the client thread
+enum Sent {
+    /// The keys and cas values of the items which were stored, and the number
+    /// of items which were not
+    Batch(Vec<(Box<[u8]>, u64)>, usize),
+    /// The destination could not be reached, which ends the migration
+    Failed(String),
+}
+
+pub(crate) struct Migration {
+    prefix: Vec<u8>,
+    destination: String,
+    delete: bool,
+    state: State,
+    /// The cursor to continue the scan for keys under the prefix from, until
+    /// the scan is complete
+    cursor: Option<usize>,
+    /// The keys which were found and have yet to be exported from storage
+    keys: Vec<Box<[u8]>>,
+    total: usize,
+    in_flight: usize,
+    migrated: usize,
+    failed: usize,
+    removed: usize,
+    items: Option<Sender<Vec<MigrationItem>>>,
+    sent: Receiver<Sent>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Migration {
+    /// Starts the client thread which sends the items under the prefix to the
+    /// destination. The destination is not waited on, and a failure to reach
+    /// it is reported by the status of the migration.
+    pub fn start(
+        prefix: Vec<u8>,
+        destination: String,
+        rate: u32,
+        delete: bool,
+        waker: Arc<Waker>,
+    ) -> std::result::Result<Self, String> {
+        let (items, batches) = channel();
+        let (reports, sent) = channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let client = Client {
+            destination: destination.clone(),
+            interval: Duration::from_secs(1) / rate,
+            cancel: cancel.clone(),
+            waker,
+        };
+        std::thread::Builder::new()
+            .name(format!("{}_migrate", env!("CARGO_PKG_NAME")))
+            .spawn(move || client.run(batches, reports))
+            .map_err(|e| format!("failed to start migration: {}", e))?;
+
+        ADMIN_MIGRATE.increment();
+        MIGRATE_ACTIVE.set(1);
+
+        info!(
+            "migrating items with prefix {} to {}",
+            String::from_utf8_lossy(&prefix),
+            destination
+        );
+
+        Ok(Self {
+            prefix,
+            destination,
+            delete,
+            state: State::Running,
+            cursor: Some(0),
+            total: 0,
+            keys: Vec::new(),
+            in_flight: 0,
+            migrated: 0,
+            failed: 0,
+            removed: 0,
+            items: Some(items),
+            sent,
+            cancel,
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state == State::Running
+    }
+
+    /// Stops the migration once the item being sent has been stored. Items
+    /// which were already stored by the destination are kept there.
+    pub fn cancel(&mut self) {
+        if self.is_running() {
+            self.cancel.store(true, Ordering::Relaxed);
+            self.finish(State::Cancelled);
+        }
+    }
+
+    /// Moves the migration forward without waiting on the destination. The
+    /// items which the destination stored are removed, if requested, and the
+    /// next batch is found and exported once the client thread has room for
+    /// it.
+    pub fn step(&mut self, signal_queue_tx: &mut Queues<Signal, Reply>) {
+        if self.state != State::Running {
+            return;
+        }
+
+        loop {
+            match self.sent.try_recv() {
+                Ok(Sent::Batch(stored, failed)) => {
+                    self.in_flight -= 1;
+                    self.record(signal_queue_tx, stored, failed);
+                }
+                Ok(Sent::Failed(e)) => {
+                    self.finish(State::Failed(e));
+                    return;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finish(State::Failed("client thread exited".to_string()));
+                    return;
+                }
+            }
+        }
+
+        let mut scans = 0;
+        while self.in_flight < MAX_IN_FLIGHT {
+            // the scan continues until there are enough keys for a batch
+            if let Some(cursor) = self.cursor {
+                if self.keys.len() < BATCH_SIZE && scans < SCAN_STEPS {
+                    scans += 1;
+                    match scan(signal_queue_tx, &self.prefix, cursor) {
+                        Ok((keys, cursor)) => {
+                            self.total += keys.len();
+                            self.keys.extend(keys);
+                            self.cursor = if cursor == 0 { None } else { Some(cursor) };
+                        }
+                        Err(e) => {
+                            self.finish(State::Failed(e));
+                            return;
+                        }
+                    }
+                    continue;
+                }
+            }
+
+            if self.keys.is_empty() {
+                break;
+            }
+
+            let keys = self
+                .keys
+                .split_off(self.keys.len().saturating_sub(BATCH_SIZE));
+            let items = match export(signal_queue_tx, keys) {
+                Ok(items) => items,
+                Err(e) => {
+                    self.finish(State::Failed(e));
+                    return;
+                }
+            };
+
+            // items removed since the keys were listed are skipped
+            if items.is_empty() {
+                continue;
+            }
+
+            if let Some(sender) = self.items.as_ref() {
+                if sender.send(items).is_err() {
+                    self.finish(State::Failed("client thread exited".to_string()));
+                    return;
+                }
+                self.in_flight += 1;
+            }
+        }
+
+        if self.in_flight == 0 && self.keys.is_empty() && self.cursor.is_none() {
+            self.finish(State::Done);
+        }
+    }
+
+    // counts the items sent in a batch and removes the stored items locally,
+    // if requested
+    fn record(
+        &mut self,
+        signal_queue_tx: &mut Queues<Signal, Reply>,
+        stored: Vec<(Box<[u8]>, u64)>,
+        failed: usize,
+    ) {
+        self.migrated += stored.len();
+        self.failed += failed;
+        MIGRATE_ITEM.add(stored.len() as _);
+        MIGRATE_ITEM_EX.add(failed as _);
+
+        if !self.delete || stored.is_empty() {
+            return;
+        }
+
+        let replies = match broadcast(
+            signal_queue_tx,
+            Signal::Migrate(MigrateStep::Remove(stored)),
+            SIGNAL_TIMEOUT,
+        ) {
+            Ok(replies) => replies,
+            Err(e) => {
+                warn!("failed to remove migrated items: {}", e);
+                return;
+            }
+        };
+        let removed = replies
+            .into_iter()
+            .find_map(|reply| match reply {
+                Reply::Migrate(MigrateReply::Removed(removed)) => Some(removed),
+                _ => None,
+            })
+            .unwrap_or(0);
+
+        self.removed += removed;
+        MIGRATE_REMOVE.add(removed as _);
+    }
+
+    fn finish(&mut self, state: State) {
+        // dropping the sender lets the client thread exit once it is idle
+        self.items = None;
+        self.cursor = None;
+        self.keys.clear();
+        MIGRATE_ACTIVE.set(0);
+
+        match &state {
+            State::Failed(e) => error!("migration to {} failed: {}", self.destination, e),
+            _ => info!(
+                "migration to {} finished: {} of {} items migrated, {} removed",
+                self.destination, self.migrated, self.total, self.removed
+            ),
+        }
+
+        self.state = state;
+    }
+
+    /// Describes the progress of the migration, one `name value` per line.
+    pub fn status(&self) -> Vec<String> {
+        let state = match &self.state {
+            State::Running if self.cursor.is_some() => "scanning".to_string(),
+            State::Running => "running".to_string(),
+            State::Done => "done".to_string(),
+            State::Cancelled => "cancelled".to_string(),
+            State::Failed(e) => format!("failed {}", e),
+        };
+
+        vec![
+            format!("prefix {}", String::from_utf8_lossy(&self.prefix)),
+            format!("destination {}", self.destination),
+            format!("state {}", state),
+            format!("keys {}", self.total),
+            format!("migrated {}", self.migrated),
+            format!("failed {}", self.failed),
+            format!("removed {}", self.removed),
+        ]
+    }
+}
+
+// finds the keys under the prefix in a part of storage, starting from the
+// cursor, and returns them with the cursor to continue from
+fn scan(
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+    prefix: &[u8],
+    cursor: usize,
+) -> std::result::Result<(Vec<Box<[u8]>>, usize), String> {
+    let replies = broadcast(
+        signal_queue_tx,
+        Signal::Migrate(MigrateStep::Keys {
+            prefix: prefix.to_vec(),
+            cursor,
+        }),
+        SIGNAL_TIMEOUT,
+    )
+    .map_err(|e| e.to_string())?;
+
+    replies
+        .into_iter()
+        .find_map(|reply| match reply {
+            Reply::Migrate(MigrateReply::Keys(keys, cursor)) => Some((keys, cursor)),
+            _ => None,
+        })
+        .ok_or_else(|| "storage did not list the keys in time".to_string())
+}
+
+// copies the items with the keys from storage
+fn export(
+    signal_queue_tx: &mut Queues<Signal, Reply>,
+    keys: Vec<Box<[u8]>>,
+) -> std::result::Result<Vec<MigrationItem>, String> {
+    let replies = broadcast(
+        signal_queue_tx,
+        Signal::Migrate(MigrateStep::Export(keys)),
+        SIGNAL_TIMEOUT,
+    )
+    .map_err(|e| e.to_string())?;
+
+    replies
+        .into_iter()
+        .find_map(|reply| match reply {
+            Reply::Migrate(MigrateReply::Items(items)) => Some(items),
+            _ => None,
+        })
+        .ok_or_else(|| "storage did not export the items in time".to_string())
+}
+
+// sends the items to the destination with blocking io, so that it does not
+// hold up the event loop of the admin thread
+struct Client {
+    destination: String,
+    interval: Duration,
+    cancel: Arc<AtomicBool>,
+    waker: Arc<Waker>,
+}
+
+impl Client {
+    fn run(self, batches: Receiver<Vec<MigrationItem>>, reports: Sender<Sent>) {
+        let report = |sent| {
+            let _ = reports.send(sent);
+            let _ = self.waker.wake();
+        };
+
+        let addr = match config::resolve::resolve(&self.destination) {
+            Ok(addr) => addr,
+            Err(e) => {
+                report(Sent::Failed(e.to_string()));
+                return;
+            }
+        };
+
+        let (mut stream, mut reader) = match self.connect(addr) {
+            Ok(stream) => stream,
+            Err(e) => {
+                report(Sent::Failed(format!("failed to connect: {}", e)));
+                return;
+            }
+        };
+
+        let mut next = Instant::now();
+        let mut response = Vec::new();
+
+        while let Ok(batch) = batches.recv() {
+            let mut stored = Vec::with_capacity(batch.len());
+            let mut failed = 0;
+
+            for item in batch {
+                if self.cancel.load(Ordering::Relaxed) {
+                    return;
+                }
+
+                let now = Instant::now();
+                if next > now {
+                    std::thread::sleep(next - now);
+                }
+                next = next.max(now) + self.interval;
+
+                response.clear();
+                let result = stream
+                    .write_all(&set(&item))
+                    .and_then(|_| reader.read_until(b'\n', &mut response));
+                match result {
+                    Ok(0) => {
+                        report(Sent::Failed(
+                            "destination closed the connection".to_string(),
+                        ));
+                        return;
+                    }
+                    Ok(_) if response == b"STORED\r\n" => {
+                        stored.push((item.key, item.cas));
+                    }
+                    Ok(_) => {
+                        debug!(
+                            "destination did not store item: {}",
+                            String::from_utf8_lossy(&response).trim_end()
+                        );
+                        failed += 1;
+                    }
+                    Err(e) => {
+                        report(Sent::Failed(e.to_string()));
+                        return;
+                    }
+                }
+            }
+
+            report(Sent::Batch(stored, failed));
+        }
+    }
+
+    fn connect(&self, addr: SocketAddr) -> Result<(TcpStream, BufReader<TcpStream>)> {
+        let stream = TcpStream::connect_timeout(&addr, IO_TIMEOUT)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok((stream, reader))
+    }
+}
+
+// composes a memcache set which stores the item with its remaining ttl
+fn set(item: &MigrationItem) -> Vec<u8> {
+    let expiry = match item.ttl.as_secs() {
+        ttl if ttl > MAX_RELATIVE_EXPIRY => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs())
+                .unwrap_or(0);
+            now + ttl
+        }
+        ttl => ttl,
+    };
+
+    let mut request = Vec::with_capacity(item.key.len() + item.value.len() + 32);
+    request.extend_from_slice(b"set ");
+    request.extend_from_slice(&item.key);
+    request.extend_from_slice(
+        format!(" {} {} {}\r\n", item.flags, expiry, item.value.len()).as_bytes(),
+    );
+    request.extend_from_slice(&item.value);
+    request.extend_from_slice(b"\r\n");
+    request
+}
@@ -0,0 +1,191 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! CPU profiling and heap statistics for the admin port. CPU profiles are
+//! sampled in-process and written in the pprof protobuf format, so that they
+//! can be inspected with `pprof` without attaching an external profiler. CPU
+//! profiling is only available when built with the `profiling` feature.
+
+use crate::*;
+
+use std::path::PathBuf;
+
+counter!(
+    ADMIN_PROFILE_START,
+    "number of cpu profiles which have been started"
+);
+counter!(
+    ADMIN_PROFILE_EX,
+    "number of times starting or writing a cpu profile failed"
+);
+
+// the default sampling frequency, in samples per second
+const DEFAULT_FREQUENCY: i32 = 99;
+
+pub(crate) struct Profiler {
+    dir: PathBuf,
+    #[cfg(feature = "profiling")]
+    guard: Option<pprof::ProfilerGuard<'static>>,
+}
+
+impl Profiler {
+    pub fn new(config: &config::Admin) -> Self {
+        Self {
+            dir: PathBuf::from(config.profile_dir()),
+            #[cfg(feature = "profiling")]
+            guard: None,
+        }
+    }
+
+    /// Starts sampling the CPU profile at the given frequency, or the default
+    /// frequency if none is given.
+    pub fn start(&mut self, frequency: Option<u32>) -> Result<()> {
+        let frequency = frequency
+            .map(|f| f.min(i32::MAX as u32) as i32)
+            .unwrap_or(DEFAULT_FREQUENCY);
+
+        self.start_sampling(frequency).map_err(|e| {
+            ADMIN_PROFILE_EX.increment();
+            e
+        })?;
+
+        ADMIN_PROFILE_START.increment();
+        info!("started cpu profile at {} Hz", frequency);
+        Ok(())
+    }
+
+    /// Stops sampling and writes the CPU profile to a new file in the profile
+    /// directory. Returns the path of the file.
+    pub fn stop(&mut self) -> Result<PathBuf> {
+        let path = self.write_profile().map_err(|e| {
+            ADMIN_PROFILE_EX.increment();
+            e
+        })?;
+
+        info!("wrote cpu profile to: {}", path.display());
+        Ok(path)
+    }
+
+    #[cfg(feature = "profiling")]
+    fn start_sampling(&mut self, frequency: i32) -> Result<()> {
+        if self.guard.is_some() {
+            return Err(Error::new(ErrorKind::Other, "profile already running"));
+        }
+
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(frequency)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+
+        self.guard = Some(guard);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    fn start_sampling(&mut self, _frequency: i32) -> Result<()> {
+        Err(Error::new(
+            ErrorKind::Other,
+            "profiling is not enabled in this build",
+        ))
+    }
+
+    #[cfg(feature = "profiling")]
+    fn write_profile(&mut self) -> Result<PathBuf> {
+        use pprof::protos::Message;
+
+        let guard = self
+            .guard
+            .take()
+            .ok_or_else(|| Error::new(ErrorKind::Other, "no profile running"))?;
+
+        let profile = guard
+            .report()
+            .build()
+            .and_then(|report| report.pprof())
+            .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+
+        let mut content = Vec::new();
+        profile
+            .encode(&mut content)
+            .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))?;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = self
+            .dir
+            .join(format!("pelikan-{}-{}.pb", std::process::id(), now));
+        std::fs::write(&path, content)?;
+
+        Ok(path)
+    }
+
+    #[cfg(not(feature = "profiling"))]
+    fn write_profile(&mut self) -> Result<PathBuf> {
+        Err(Error::new(
+            ErrorKind::Other,
+            "profiling is not enabled in this build",
+        ))
+    }
+}
+
+/// Returns the statistics of the heap as reported by the allocator. Only the
+/// glibc allocator is supported, for other allocators this is empty.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+pub(crate) fn heap_stats() -> Vec<(String, u64)> {
+    type Mallinfo2 = unsafe extern "C" fn() -> libc::mallinfo2;
+
+    // `mallinfo2` was added in glibc 2.33, so it is looked up when called
+    // rather than linked against. Older versions only have `mallinfo`, whose
+    // fields wrap once the heap exceeds 4GB
+    let symbol = unsafe { libc::dlsym(libc::RTLD_DEFAULT, b"mallinfo2\0".as_ptr() as _) };
+
+    let stats: [u64; 7] = if symbol.is_null() {
+        #[allow(deprecated)]
+        let info = unsafe { libc::mallinfo() };
+        [
+            info.arena as u32 as u64,
+            info.hblkhd as u32 as u64,
+            info.hblks as u32 as u64,
+            info.uordblks as u32 as u64,
+            info.fordblks as u32 as u64,
+            info.ordblks as u32 as u64,
+            info.keepcost as u32 as u64,
+        ]
+    } else {
+        let mallinfo2: Mallinfo2 =
+            unsafe { std::mem::transmute::<*mut libc::c_void, Mallinfo2>(symbol) };
+        let info = unsafe { mallinfo2() };
+        [
+            info.arena as u64,
+            info.hblkhd as u64,
+            info.hblks as u64,
+            info.uordblks as u64,
+            info.fordblks as u64,
+            info.ordblks as u64,
+            info.keepcost as u64,
+        ]
+    };
+
+    [
+        "heap_arena",
+        "heap_mmap",
+        "heap_mmap_chunks",
+        "heap_allocated",
+        "heap_free",
+        "heap_free_chunks",
+        "heap_releasable",
+    ]
+    .iter()
+    .zip(stats)
+    .map(|(name, value)| (name.to_string(), value))
+    .collect()
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+pub(crate) fn heap_stats() -> Vec<(String, u64)> {
+    Vec::new()
+}
@@ -0,0 +1,168 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Keeps a short history of selected counters, so that `stats rates` can
+//! report their rate of change per second. This serves dashboards which can
+//! only show the latest value of a metric and cannot compute a rate from
+//! successive readings themselves.
+
+use crate::*;
+
+// the windows over which rates are reported, in seconds
+const WINDOWS: [u64; 3] = [1, 10, 60];
+
+// the counters are sampled once per second
+const INTERVAL: Duration = Duration::from_secs(1);
+
+struct Sample {
+    time: Instant,
+    values: Vec<Option<u64>>,
+}
+
+pub(crate) struct Rates {
+    metrics: Vec<String>,
+    /// One sample per second, oldest first, covering the longest window
+    history: VecDeque<Sample>,
+    next: Instant,
+}
+
+impl Rates {
+    pub fn new(config: &config::Admin) -> Self {
+        Self {
+            metrics: config.rate_metrics().to_vec(),
+            history: VecDeque::new(),
+            next: Instant::now(),
+        }
+    }
+
+    /// Reads the counters if a second has passed since they were last read.
+    pub fn sample(&mut self, now: Instant) {
+        if now < self.next || self.metrics.is_empty() {
+            return;
+        }
+        self.next = now + INTERVAL;
+
+        let mut values = vec![None; self.metrics.len()];
+        for metric in &rustcommon_metrics::metrics() {
+            let index = match self.metrics.iter().position(|name| name == metric.name()) {
+                Some(index) => index,
+                None => {
+                    continue;
+                }
+            };
+            if let Some(counter) = metric
+                .as_any()
+                .and_then(|any| any.downcast_ref::<Counter>())
+            {
+                values[index] = Some(counter.value());
+            }
+        }
+
+        self.push(Sample { time: now, values });
+    }
+
+    fn push(&mut self, sample: Sample) {
+        let longest = WINDOWS[WINDOWS.len() - 1] as usize;
+        if self.history.len() > longest {
+            self.history.pop_front();
+        }
+        self.history.push_back(sample);
+    }
+
+    /// The rate per second of each counter over each window, named as
+    /// `<counter>_<window>s`. A window which is longer than the history so far
+    /// covers all of it. Counters which do not exist in this process are
+    /// omitted.
+    pub fn rates(&self) -> Vec<(String, f64)> {
+        let latest = match self.history.back() {
+            Some(latest) => latest,
+            None => {
+                return Vec::new();
+            }
+        };
+
+        let mut rates = Vec::new();
+        for (index, name) in self.metrics.iter().enumerate() {
+            let value = match latest.values[index] {
+                Some(value) => value,
+                None => {
+                    continue;
+                }
+            };
+
+            for window in WINDOWS {
+                let earlier = self
+                    .history
+                    .iter()
+                    .find(|sample| {
+                        latest.time.saturating_duration_since(sample.time).as_secs() <= window
+                    })
+                    .unwrap_or(latest);
+
+                let elapsed = latest.time.saturating_duration_since(earlier.time);
+                // a counter which was reset within the window is reported as
+                // unchanged rather than as a negative rate
+                let delta = earlier.values[index]
+                    .map(|earlier| value.saturating_sub(earlier))
+                    .unwrap_or(0);
+                let rate = if elapsed.is_zero() {
+                    0.0
+                } else {
+                    delta as f64 / elapsed.as_secs_f64()
+                };
+
+                rates.push((format!("{}_{}s", name, window), rate));
+            }
+        }
+        rates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates() {
+        let mut rates = Rates {
+            metrics: vec!["get".to_string(), "missing".to_string()],
+            history: VecDeque::new(),
+            next: Instant::now(),
+        };
+        assert!(rates.rates().is_empty());
+
+        // the counter increases by 10 each second, and is reset after 70s
+        let start = Instant::now();
+        for second in 0..=90 {
+            let value = if second <= 70 {
+                second * 10
+            } else {
+                (second - 70) * 10
+            };
+            rates.push(Sample {
+                time: start + Duration::from_secs(second),
+                values: vec![Some(value), None],
+            });
+
+            if second == 5 {
+                // the longer windows cover the history so far
+                assert_eq!(
+                    rates.rates(),
+                    vec![
+                        ("get_1s".to_string(), 10.0),
+                        ("get_10s".to_string(), 10.0),
+                        ("get_60s".to_string(), 10.0),
+                    ]
+                );
+            }
+        }
+        assert_eq!(rates.history.len(), 61);
+
+        let rates = rates.rates();
+        assert_eq!(rates[0], ("get_1s".to_string(), 10.0));
+        assert_eq!(rates[1], ("get_10s".to_string(), 10.0));
+        assert_eq!(rates[2], ("get_60s".to_string(), 0.0));
+        assert_eq!(rates.len(), 3);
+    }
+}
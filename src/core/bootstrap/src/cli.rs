@@ -0,0 +1,95 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Helpers for the `main` function of a server binary.
+
+use backtrace::Backtrace;
+use clap::{App, Arg};
+use common::daemon::{Daemon, PidFile};
+use config::DaemonConfig;
+use rustcommon_metrics::*;
+use server::PERCENTILES;
+
+/// Installs a panic hook which terminates the whole process after logging
+/// the panic and printing a backtrace, rather than only the panicking thread.
+pub fn set_panic_hook() {
+    std::panic::set_hook(Box::new(|s| {
+        error!("{}", s);
+        println!("{:?}", Backtrace::new());
+        std::process::exit(101);
+    }));
+}
+
+/// Returns a command line parser with the options which every server accepts,
+/// `--stats` and the path of the configuration file, to which a binary adds
+/// its own options.
+pub fn app(name: &'static str, about: &'static str) -> App<'static, 'static> {
+    App::new(name)
+        .long_about(about)
+        .arg(
+            Arg::with_name("stats")
+                .short("s")
+                .long("stats")
+                .help("List all metrics in stats")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("CONFIG")
+                .help("Server configuration file")
+                .index(1),
+        )
+}
+
+/// Prints the name and type of every metric, as listed by `--stats`.
+pub fn print_metrics() {
+    println!("{:<31} {:<15} DESCRIPTION", "NAME", "TYPE");
+
+    let mut metrics = Vec::new();
+
+    for metric in &rustcommon_metrics::metrics() {
+        let any = match metric.as_any() {
+            Some(any) => any,
+            None => {
+                continue;
+            }
+        };
+
+        if any.downcast_ref::<Counter>().is_some() {
+            metrics.push(format!("{:<31} counter", metric.name()));
+        } else if any.downcast_ref::<Gauge>().is_some() {
+            metrics.push(format!("{:<31} gauge", metric.name()));
+        } else if any.downcast_ref::<Heatmap>().is_some() {
+            for (label, _) in PERCENTILES {
+                let name = format!("{}_{}", metric.name(), label);
+                metrics.push(format!("{:<31} percentile", name));
+            }
+        } else {
+            continue;
+        }
+    }
+
+    metrics.sort();
+    for metric in metrics {
+        println!("{}", metric);
+    }
+}
+
+/// Detaches and redirects output as configured, which must happen before any
+/// threads are launched. The returned pidfile should be held until exit, so
+/// that it is removed on shutdown. Exits the process if this fails.
+pub fn daemonize<T: DaemonConfig>(config: &T, name: &str) -> Option<PidFile> {
+    match Daemon::new()
+        .daemonize(config.daemonize())
+        .pid_filename(config.pid_filename())
+        .stdout(config.stdout())
+        .stderr(config.stderr())
+        .start()
+    {
+        Ok(pidfile) => pidfile,
+        Err(e) => {
+            println!("error daemonizing {}: {}", name, e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! The startup sequence which every Pelikan server binary shares, so that a
+//! new protocol server only provides its configuration, parser, and storage.
+//!
+//! The library half of a server uses [`ServerBuilder`], which configures
+//! logging and metrics before the storage is created, and then spawns the
+//! admin, listener, worker, and storage threads with their queues and signals
+//! wired together by the `server` crate:
+//!
+//! ```ignore
+//! let builder = ServerBuilder::new(&config).version(env!("CARGO_PKG_VERSION"));
+//! let storage = Storage::new(&config)?;
+//! let process = builder.spawn(Parser::new(), storage)?;
+//! ```
+//!
+//! The binary half uses the helpers in [`cli`] for the command line options
+//! which are common to the servers, to list the metrics, and to daemonize.
+
+#[macro_use]
+extern crate logger;
+
+use config::*;
+use entrystore::EntryStore;
+use logger::{configure_logging, Drain, Klog};
+use protocol_common::{Compose, CountSession, Execute, Lookup, Parse, RecordLatency};
+use std::io::Result;
+
+pub mod cli;
+
+pub use server::{ConfigLoader, Process, StorageClient};
+
+/// Builds a server process. Creating the builder configures logging and
+/// metrics, so that messages which are logged while the storage is created
+/// are not lost.
+pub struct ServerBuilder<'a, T> {
+    config: &'a T,
+    loader: Option<ConfigLoader>,
+    log_drain: Box<dyn Drain>,
+    version: String,
+}
+
+impl<'a, T> ServerBuilder<'a, T>
+where
+    T: AccessLogConfig
+        + AdminConfig
+        + DebugConfig
+        + KlogConfig
+        + ServerConfig
+        + TlsConfig
+        + WorkerConfig,
+{
+    pub fn new(config: &'a T) -> Self {
+        // initialize logging
+        let log_drain = configure_logging(config);
+
+        // initialize metrics
+        common::metrics::init();
+
+        Self {
+            config,
+            loader: None,
+            log_drain,
+            version: "unknown".to_string(),
+        }
+    }
+
+    /// The version which is reported by the `version` admin command.
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = version.to_string();
+        self
+    }
+
+    /// Enables the `reload` admin command, which uses the loader to read the
+    /// configuration again.
+    pub fn config_loader(mut self, loader: ConfigLoader) -> Self {
+        self.loader = Some(loader);
+        self
+    }
+
+    /// Binds the admin and data listeners and spawns the threads of the
+    /// process, which serves requests parsed by the parser from the storage.
+    pub fn spawn<Parser, Request, Response, Storage>(
+        self,
+        parser: Parser,
+        storage: Storage,
+    ) -> Result<Process>
+    where
+        Parser: 'static + Parse<Request> + Clone + Send,
+        Request: 'static
+            + Klog
+            + Klog<Response = Response>
+            + CountSession<Response>
+            + Lookup<Response>
+            + RecordLatency
+            + Send,
+        Response: 'static + Compose + Send,
+        Storage: 'static + Execute<Request, Response> + EntryStore + Send,
+    {
+        self.spawn_with_client(parser, storage)
+            .map(|(process, _)| process)
+    }
+
+    /// Spawns the process as `spawn()` does, and also returns a client which
+    /// submits requests to its storage from another front end, such as one
+    /// which runs on its own runtime.
+    pub fn spawn_with_client<Parser, Request, Response, Storage>(
+        self,
+        parser: Parser,
+        storage: Storage,
+    ) -> Result<(Process, StorageClient<Request, Response>)>
+    where
+        Parser: 'static + Parse<Request> + Clone + Send,
+        Request:
+            'static + Klog + Klog<Response = Response> + Lookup<Response> + RecordLatency + Send,
+        Response: 'static + Compose + Send,
+        Storage: 'static + Execute<Request, Response> + EntryStore + Send,
+    {
+        let builder = server::ProcessBuilder::<Parser, Request, Response, Storage>::new(
+            self.config,
+            self.log_drain,
+            parser,
+            storage,
+        )?
+        .version(&self.version);
+
+        let client = builder.storage_client();
+
+        let process = match self.loader {
+            Some(loader) => builder.config_loader(loader).spawn(),
+            None => builder.spawn(),
+        };
+
+        Ok((process, client))
+    }
+}
@@ -0,0 +1,694 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A gRPC front end for cache storage. This exposes a simple Get/BatchGet/Set/
+//! Delete API which is mapped onto memcache requests, which are executed by a
+//! [`Backend`]. A server process serves the RPCs from the same storage as its
+//! data port by using a [`server::StorageClient`] as the backend, while a
+//! [`Local`] backend owns storage which is only reachable through gRPC.
+//!
+//! Requests may include a `pelikan-namespace` metadata entry. When present,
+//! keys are scoped to the namespace by prefixing them with the namespace and a
+//! `:` separator. A namespace must not be empty or contain a `:`, so that the
+//! keys of one namespace can never alias those of another. Requests without a
+//! namespace may not use keys which contain a `:`, so they cannot reach into
+//! any namespace, and may be rejected altogether with `require_namespace()`.
+//!
+//! Client deadlines are honored by the server, bounded by the configured server
+//! timeout. The RPCs are reported under the listener name `grpc` by the
+//! `stats listeners` admin command, unless another is set with `name()`.
+
+#[macro_use]
+extern crate logger;
+
+use common::listener::ListenerStats;
+use core::time::Duration;
+use entrystore::EntryStore;
+use protocol_common::Execute;
+use protocol_memcache::{Delete, Get, Set, Ttl};
+use rustcommon_metrics::*;
+use server::StorageClient;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("pelikan.cache.v1");
+}
+
+use proto::cache_server::{Cache, CacheServer};
+use proto::*;
+
+/// The metadata key used to specify the namespace for a request.
+pub const NAMESPACE_KEY: &str = "pelikan-namespace";
+
+/// Separates the namespace from the key in storage.
+const SEPARATOR: u8 = b':';
+
+const MAX_KEY_LEN: usize = 250;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+counter!(GRPC_REQUEST, "total number of gRPC requests");
+counter!(
+    GRPC_REQUEST_EX,
+    "number of gRPC requests which resulted in an error status"
+);
+counter!(GRPC_GET, "number of gRPC get requests");
+counter!(GRPC_BATCH_GET, "number of gRPC batch get requests");
+counter!(GRPC_SET, "number of gRPC set requests");
+counter!(GRPC_DELETE, "number of gRPC delete requests");
+
+/// Executes the memcache requests which the RPCs are mapped onto.
+#[tonic::async_trait]
+pub trait Backend: 'static + Send + Sync {
+    async fn execute(
+        &self,
+        request: protocol_memcache::Request,
+    ) -> Result<protocol_memcache::Response, Status>;
+
+    /// Called periodically by the front end. Backends which own their storage
+    /// use this to expire items, as there is no event loop to do so.
+    fn tick(&self) {}
+}
+
+/// Executes the requests on the thread which owns the storage of a server
+/// process, so that the RPCs share the storage with its data port.
+#[tonic::async_trait]
+impl Backend for StorageClient<protocol_memcache::Request, protocol_memcache::Response> {
+    async fn execute(
+        &self,
+        request: protocol_memcache::Request,
+    ) -> Result<protocol_memcache::Response, Status> {
+        let (tx, rx) = oneshot::channel();
+        self.send(request, move |response| {
+            let _ = tx.send(response);
+        })
+        .map_err(|_| Status::unavailable("storage is not accepting requests"))?;
+        rx.await
+            .map_err(|_| Status::unavailable("storage stopped before responding"))
+    }
+}
+
+/// Storage which is owned by the gRPC front end and shared between the tasks
+/// handling each RPC behind a mutex which is never held across an await point.
+pub struct Local<S> {
+    storage: Mutex<S>,
+}
+
+impl<S> Local<S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage: Mutex::new(storage),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl<S> Backend for Local<S>
+where
+    S: 'static
+        + Execute<protocol_memcache::Request, protocol_memcache::Response>
+        + EntryStore
+        + Send,
+{
+    async fn execute(
+        &self,
+        request: protocol_memcache::Request,
+    ) -> Result<protocol_memcache::Response, Status> {
+        Ok(self.storage.lock().unwrap().execute(&request))
+    }
+
+    fn tick(&self) {
+        self.storage.lock().unwrap().expire();
+    }
+}
+
+pub struct GrpcServerBuilder<B> {
+    addr: SocketAddr,
+    backend: B,
+    name: String,
+    require_namespace: bool,
+    threads: usize,
+    timeout: Duration,
+}
+
+impl<B: Backend> GrpcServerBuilder<B> {
+    pub fn new(addr: SocketAddr, backend: B) -> Self {
+        Self {
+            addr,
+            backend,
+            name: "grpc".to_string(),
+            require_namespace: false,
+            threads: 1,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// The name under which the RPCs served are reported by the `stats
+    /// listeners` admin command.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Reject requests which do not specify a namespace, rather than using the
+    /// keys which are not in any namespace.
+    pub fn require_namespace(mut self, require: bool) -> Self {
+        self.require_namespace = require;
+        self
+    }
+
+    /// The number of runtime threads used when calling `spawn()`.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// The maximum time allowed for handling an RPC. Requests which specify a
+    /// shorter deadline will use that deadline instead.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Serve requests from an already bound listener on the current Tokio
+    /// runtime until the `shutdown` future completes.
+    pub async fn serve_on<F: core::future::Future<Output = ()>>(
+        self,
+        listener: TcpListener,
+        shutdown: F,
+    ) -> Result<(), tonic::transport::Error> {
+        let backend = Arc::new(self.backend);
+
+        let ticker = backend.clone();
+        let ticker = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK_INTERVAL);
+            loop {
+                interval.tick().await;
+                ticker.tick();
+            }
+        });
+
+        let service = CacheService {
+            backend,
+            listener: common::listener::register(&self.name),
+            require_namespace: self.require_namespace,
+        };
+
+        let result = Server::builder()
+            .timeout(self.timeout)
+            .add_service(CacheServer::new(service))
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown)
+            .await;
+
+        ticker.abort();
+        result
+    }
+
+    /// Launch a dedicated Tokio runtime which serves requests in the
+    /// background. The returned handle may be used to shutdown the server.
+    pub fn spawn(self) -> Result<GrpcServer, std::io::Error> {
+        let listener = std::net::TcpListener::bind(self.addr)?;
+        self.spawn_on(listener)
+    }
+
+    /// Like `spawn()`, but serves requests from an already bound listener.
+    /// This allows the address to be bound before any other part of the
+    /// process is started, so that a bind failure can be reported without
+    /// having to tear anything down.
+    pub fn spawn_on(self, listener: std::net::TcpListener) -> Result<GrpcServer, std::io::Error> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(self.threads)
+            .thread_name("pelikan_grpc")
+            .enable_all()
+            .build()?;
+
+        listener.set_nonblocking(true)?;
+        let listener = {
+            let _guard = runtime.enter();
+            TcpListener::from_std(listener)?
+        };
+        let local_addr = listener.local_addr()?;
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+
+        runtime.spawn(async move {
+            let shutdown = async {
+                let _ = shutdown_rx.await;
+            };
+            if let Err(e) = self.serve_on(listener, shutdown).await {
+                error!("grpc server terminated: {}", e);
+            }
+        });
+
+        Ok(GrpcServer {
+            local_addr,
+            runtime,
+            shutdown_tx,
+        })
+    }
+}
+
+/// A handle to a running gRPC front end.
+pub struct GrpcServer {
+    local_addr: SocketAddr,
+    runtime: Runtime,
+    shutdown_tx: oneshot::Sender<()>,
+}
+
+impl GrpcServer {
+    /// Returns the address the server is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops the server and shuts down the runtime.
+    pub fn shutdown(self) {
+        let _ = self.shutdown_tx.send(());
+        self.runtime.shutdown_timeout(Duration::from_secs(1));
+    }
+}
+
+struct CacheService<B> {
+    backend: Arc<B>,
+    listener: Arc<ListenerStats>,
+    require_namespace: bool,
+}
+
+/// Returns true if the bytes may be used in a key or namespace.
+fn valid(bytes: &[u8]) -> bool {
+    !bytes.is_empty()
+        && bytes.len() <= MAX_KEY_LEN
+        && !bytes
+            .iter()
+            .any(|b| b.is_ascii_whitespace() || b.is_ascii_control())
+}
+
+impl<B: Backend> CacheService<B> {
+    /// Returns the namespace from the request metadata, if any.
+    fn namespace<'a, T>(&self, request: &'a Request<T>) -> Result<Option<&'a [u8]>, Status> {
+        match request.metadata().get(NAMESPACE_KEY) {
+            Some(namespace) => {
+                let namespace = namespace.as_bytes();
+                if !valid(namespace) || namespace.contains(&SEPARATOR) {
+                    Err(Status::invalid_argument("invalid namespace"))
+                } else {
+                    Ok(Some(namespace))
+                }
+            }
+            None if self.require_namespace => {
+                Err(Status::invalid_argument("a namespace is required"))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Applies the namespace from the request metadata, if any, to the key.
+    fn key<T>(&self, request: &Request<T>, key: &[u8]) -> Result<Vec<u8>, Status> {
+        if !valid(key) {
+            return Err(Status::invalid_argument("invalid key"));
+        }
+
+        match self.namespace(request)? {
+            Some(namespace) => {
+                let mut namespaced = namespace.to_vec();
+                namespaced.push(SEPARATOR);
+                namespaced.extend_from_slice(key);
+                if namespaced.len() > MAX_KEY_LEN {
+                    Err(Status::invalid_argument("invalid key"))
+                } else {
+                    Ok(namespaced)
+                }
+            }
+            // such a key could name an item in a namespace
+            None if key.contains(&SEPARATOR) => Err(Status::invalid_argument(
+                "keys without a namespace may not contain ':'",
+            )),
+            None => Ok(key.to_vec()),
+        }
+    }
+
+    /// Removes the namespace prefix from a key which was returned by storage.
+    fn unkey<T>(&self, request: &Request<T>, key: &[u8]) -> Vec<u8> {
+        match self.namespace(request) {
+            Ok(Some(namespace)) => key[(namespace.len() + 1)..].to_vec(),
+            _ => key.to_vec(),
+        }
+    }
+
+    fn items<T>(&self, request: &Request<T>, response: protocol_memcache::Response) -> Vec<Item> {
+        let mut items = Vec::new();
+        if let protocol_memcache::Response::Values(values) = response {
+            for value in values.values() {
+                if let Some(data) = value.value() {
+                    items.push(Item {
+                        key: self.unkey(request, value.key()),
+                        value: data.to_vec(),
+                        flags: value.flags(),
+                    });
+                }
+            }
+        }
+        items
+    }
+}
+
+impl<B> CacheService<B> {
+    fn result<T>(&self, start: Instant, result: Result<T, Status>) -> Result<Response<T>, Status> {
+        GRPC_REQUEST.increment();
+        if result.is_err() {
+            GRPC_REQUEST_EX.increment();
+        }
+        self.listener.record_request();
+        self.listener.record_response(start.elapsed());
+        result.map(Response::new)
+    }
+}
+
+#[tonic::async_trait]
+impl<B: Backend> Cache for CacheService<B> {
+    async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        let start = Instant::now();
+        GRPC_GET.increment();
+        let response = match self.key(&request, &request.get_ref().key) {
+            Ok(key) => {
+                let get = Get::new(&[key.as_slice()]);
+                self.backend
+                    .execute(protocol_memcache::Request::Get(get))
+                    .await
+                    .map(|response| GetResponse {
+                        item: self.items(&request, response).pop(),
+                    })
+            }
+            Err(e) => Err(e),
+        };
+        self.result(start, response)
+    }
+
+    async fn batch_get(
+        &self,
+        request: Request<BatchGetRequest>,
+    ) -> Result<Response<BatchGetResponse>, Status> {
+        let start = Instant::now();
+        GRPC_BATCH_GET.increment();
+        let keys: Result<Vec<Vec<u8>>, Status> = request
+            .get_ref()
+            .keys
+            .iter()
+            .map(|key| self.key(&request, key))
+            .collect();
+        let response = match keys {
+            Ok(keys) => {
+                let keys: Vec<&[u8]> = keys.iter().map(|k| k.as_slice()).collect();
+                let get = Get::new(&keys);
+                self.backend
+                    .execute(protocol_memcache::Request::Get(get))
+                    .await
+                    .map(|response| BatchGetResponse {
+                        items: self.items(&request, response),
+                    })
+            }
+            Err(e) => Err(e),
+        };
+        self.result(start, response)
+    }
+
+    async fn set(&self, request: Request<SetRequest>) -> Result<Response<SetResponse>, Status> {
+        let start = Instant::now();
+        GRPC_SET.increment();
+        let r = request.get_ref();
+        let response = match self.key(&request, &r.key) {
+            Ok(key) => {
+                let ttl = Ttl::new(r.ttl.into(), common::expiry::TimeType::Delta);
+                let set = Set::new(&key, &r.value, r.flags, ttl, false);
+                match self
+                    .backend
+                    .execute(protocol_memcache::Request::Set(set))
+                    .await
+                {
+                    Ok(protocol_memcache::Response::Stored(_)) => Ok(SetResponse {}),
+                    Ok(_) => Err(Status::internal("failed to store item")),
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+        };
+        self.result(start, response)
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        let start = Instant::now();
+        GRPC_DELETE.increment();
+        let response = match self.key(&request, &request.get_ref().key) {
+            Ok(key) => {
+                let delete = Delete::new(&key, false);
+                self.backend
+                    .execute(protocol_memcache::Request::Delete(delete))
+                    .await
+                    .map(|response| DeleteResponse {
+                        deleted: matches!(response, protocol_memcache::Response::Deleted(_)),
+                    })
+            }
+            Err(e) => Err(e),
+        };
+        self.result(start, response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::SegcacheConfig;
+    use entrystore::Seg;
+    use proto::cache_client::CacheClient;
+    use tonic::transport::Channel;
+    use tonic::Code;
+
+    /// A backend which never responds.
+    struct Stalled;
+
+    #[tonic::async_trait]
+    impl Backend for Stalled {
+        async fn execute(
+            &self,
+            _request: protocol_memcache::Request,
+        ) -> Result<protocol_memcache::Response, Status> {
+            std::future::pending().await
+        }
+    }
+
+    fn local() -> GrpcServerBuilder<Local<Seg>> {
+        let storage = Seg::new(&SegcacheConfig::default()).expect("failed to create storage");
+        GrpcServerBuilder::new("127.0.0.1:0".parse().unwrap(), Local::new(storage))
+    }
+
+    async fn serve<B: Backend>(
+        builder: GrpcServerBuilder<B>,
+    ) -> (CacheClient<Channel>, oneshot::Sender<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = oneshot::channel::<()>();
+        tokio::spawn(builder.serve_on(listener, async {
+            let _ = rx.await;
+        }));
+        let client = CacheClient::connect(format!("http://{}", addr))
+            .await
+            .expect("failed to connect");
+        (client, tx)
+    }
+
+    fn namespaced<T>(message: T, namespace: &str) -> Request<T> {
+        let mut request = Request::new(message);
+        request
+            .metadata_mut()
+            .insert(NAMESPACE_KEY, namespace.parse().unwrap());
+        request
+    }
+
+    fn set(key: &[u8], value: &[u8]) -> SetRequest {
+        SetRequest {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            flags: 0,
+            ttl: 0,
+        }
+    }
+
+    fn get(key: &[u8]) -> GetRequest {
+        GetRequest { key: key.to_vec() }
+    }
+
+    #[tokio::test]
+    async fn get_set_delete() {
+        let (mut client, _shutdown) = serve(local()).await;
+
+        let response = client.get(get(b"0")).await.unwrap();
+        assert_eq!(response.into_inner().item, None);
+
+        let mut request = set(b"0", b"value");
+        request.flags = 7;
+        client.set(request).await.unwrap();
+
+        let item = client.get(get(b"0")).await.unwrap().into_inner().item;
+        assert_eq!(
+            item,
+            Some(Item {
+                key: b"0".to_vec(),
+                value: b"value".to_vec(),
+                flags: 7,
+            })
+        );
+
+        let delete = DeleteRequest { key: b"0".to_vec() };
+        let response = client.delete(delete.clone()).await.unwrap();
+        assert!(response.into_inner().deleted);
+        let response = client.delete(delete).await.unwrap();
+        assert!(!response.into_inner().deleted);
+
+        let response = client.get(get(b"0")).await.unwrap();
+        assert_eq!(response.into_inner().item, None);
+
+        // keys are checked before they reach storage
+        let status = client.get(get(b"")).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let status = client.set(set(b"a key", b"value")).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn batch_get() {
+        let (mut client, _shutdown) = serve(local()).await;
+
+        client.set(set(b"0", b"zero")).await.unwrap();
+        client.set(set(b"1", b"one")).await.unwrap();
+
+        let request = BatchGetRequest {
+            keys: vec![b"0".to_vec(), b"2".to_vec(), b"1".to_vec()],
+        };
+        let items = client.batch_get(request).await.unwrap().into_inner().items;
+        let items: Vec<(&[u8], &[u8])> = items
+            .iter()
+            .map(|item| (item.key.as_slice(), item.value.as_slice()))
+            .collect();
+        assert_eq!(items, vec![(&b"0"[..], &b"zero"[..]), (b"1", b"one")]);
+
+        // a single invalid key rejects the whole batch
+        let request = BatchGetRequest {
+            keys: vec![b"0".to_vec(), Vec::new()],
+        };
+        let status = client.batch_get(request).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn namespaces() {
+        let (mut client, _shutdown) = serve(local()).await;
+
+        // keys may contain the separator within a namespace
+        client
+            .set(namespaced(set(b"b:c", b"value"), "a"))
+            .await
+            .unwrap();
+        let item = client
+            .get(namespaced(get(b"b:c"), "a"))
+            .await
+            .unwrap()
+            .into_inner()
+            .item
+            .unwrap();
+        assert_eq!(item.key, b"b:c");
+        assert_eq!(item.value, b"value");
+
+        // but a namespace may not, so it cannot alias another namespace
+        let status = client.get(namespaced(get(b"c"), "a:b")).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let status = client.get(namespaced(get(b"c"), "")).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        // and requests without a namespace cannot reach into one
+        let status = client.get(get(b"a:b:c")).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        // the same key in another namespace, or in none, is another item
+        client.set(set(b"c", b"other")).await.unwrap();
+        let response = client.get(namespaced(get(b"c"), "b")).await.unwrap();
+        assert_eq!(response.into_inner().item, None);
+        let response = client.get(namespaced(get(b"c"), "a")).await.unwrap();
+        assert_eq!(response.into_inner().item, None);
+        let item = client.get(get(b"c")).await.unwrap().into_inner().item;
+        assert_eq!(item.unwrap().value, b"other");
+
+        let request = BatchGetRequest {
+            keys: vec![b"b:c".to_vec(), b"c".to_vec()],
+        };
+        let items = client
+            .batch_get(namespaced(request, "a"))
+            .await
+            .unwrap()
+            .into_inner()
+            .items;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].key, b"b:c");
+
+        let response = client
+            .delete(namespaced(DeleteRequest { key: b"c".to_vec() }, "a"))
+            .await
+            .unwrap();
+        assert!(!response.into_inner().deleted);
+    }
+
+    #[tokio::test]
+    async fn require_namespace() {
+        let (mut client, _shutdown) = serve(local().require_namespace(true)).await;
+
+        let status = client.set(set(b"0", b"value")).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        client
+            .set(namespaced(set(b"0", b"value"), "a"))
+            .await
+            .unwrap();
+        let response = client.get(namespaced(get(b"0"), "a")).await.unwrap();
+        assert!(response.into_inner().item.is_some());
+    }
+
+    #[tokio::test]
+    async fn deadlines() {
+        let addr = "127.0.0.1:0".parse().unwrap();
+
+        // the server timeout bounds each RPC
+        let builder = GrpcServerBuilder::new(addr, Stalled).timeout(Duration::from_millis(50));
+        let (mut client, _shutdown) = serve(builder).await;
+        let status = client.get(get(b"0")).await.unwrap_err();
+        assert!(matches!(
+            status.code(),
+            Code::Cancelled | Code::DeadlineExceeded
+        ));
+
+        // and a shorter deadline from the client is honored
+        let builder = GrpcServerBuilder::new(addr, Stalled).timeout(Duration::from_secs(60));
+        let (mut client, _shutdown) = serve(builder).await;
+        let mut request = Request::new(get(b"0"));
+        request.set_timeout(Duration::from_millis(50));
+        let start = std::time::Instant::now();
+        let status = client.get(request).await.unwrap_err();
+        assert!(matches!(
+            status.code(),
+            Code::Cancelled | Code::DeadlineExceeded
+        ));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
+
+common::metrics::test_no_duplicates!();
@@ -3,10 +3,13 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use super::map_result;
+use crate::endpoint::{Backoff, Endpoint};
+use crate::pool::{self, Pool};
 use crate::*;
+use config::resolve::resolve_all;
 use session::ClientSession;
-use std::collections::HashMap;
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
 
 heatmap!(
     BACKEND_EVENT_DEPTH,
@@ -25,15 +28,61 @@ counter!(
 counter!(BACKEND_EVENT_READ, "the number of read events received");
 counter!(BACKEND_EVENT_TOTAL, "the total number of events received");
 counter!(BACKEND_EVENT_WRITE, "the number of write events received");
+counter!(
+    BACKEND_CONNECT,
+    "the number of connection attempts to backend endpoints"
+);
+counter!(
+    BACKEND_CONNECT_EX,
+    "the number of connection attempts to backend endpoints which failed"
+);
+counter!(
+    BACKEND_CONNECT_TIMEOUT,
+    "the number of connection attempts to backend endpoints which timed out"
+);
+counter!(
+    BACKEND_CONNECT_FALLBACK,
+    "the number of connection attempts started while an earlier attempt to the same endpoint was still in progress"
+);
+
+/// Tracks which endpoint and address a backend session is connected to.
+struct Connection {
+    endpoint: usize,
+    addr: SocketAddr,
+}
+
+/// A set of connection attempts for a single endpoint. Following the happy
+/// eyeballs algorithm, attempts are made to each resolved address in turn,
+/// starting a new attempt whenever the previous one fails or has not
+/// completed within the fallback delay. The first attempt to complete wins
+/// and any others are abandoned.
+struct Race {
+    endpoint: usize,
+    attempts: Vec<Token>,
+    started: usize,
+    last: std::time::Instant,
+}
+
+/// A single in-progress connection attempt.
+struct Attempt {
+    race: usize,
+    addr: SocketAddr,
+    deadline: std::time::Instant,
+}
 
 pub struct BackendWorkerBuilder<Parser, Request, Response> {
-    free_queue: VecDeque<Token>,
+    connect_timeout: Duration,
+    endpoints: Vec<Endpoint>,
+    failover_delay: Duration,
+    fallback_delay: Duration,
     nevent: usize,
     parser: Parser,
     poll: Poll,
-    sessions: Slab<ClientSession<Parser, Request, Response>>,
+    resolve_interval: Option<Duration>,
     timeout: Duration,
     waker: Arc<Waker>,
+    _request: PhantomData<Request>,
+    _response: PhantomData<Response>,
 }
 
 impl<Parser, Request, Response> BackendWorkerBuilder<Parser, Request, Response>
@@ -52,30 +101,66 @@ where
 
         let nevent = config.nevent();
         let timeout = Duration::from_millis(config.timeout() as u64);
-
-        let mut sessions = Slab::new();
-        let mut free_queue = VecDeque::new();
-
-        for endpoint in config.socket_addrs()? {
-            let stream = TcpStream::connect(endpoint)?;
-            let mut session = ClientSession::new(Session::from(stream), parser.clone());
-            let s = sessions.vacant_entry();
-            let interest = session.interest();
-            session
-                .register(poll.registry(), Token(s.key()), interest)
-                .expect("failed to register");
-            free_queue.push_back(Token(s.key()));
-            s.insert(session);
+        let connect_timeout = Duration::from_millis(config.connect_timeout() as u64);
+        let fallback_delay = Duration::from_millis(config.fallback_delay() as u64);
+        let failover_delay = Duration::from_millis(config.failover_delay() as u64);
+        let resolve_interval = match config.resolve_interval() {
+            0 => None,
+            ms => Some(Duration::from_millis(ms as u64)),
+        };
+
+        let backoff = || {
+            Backoff::new(
+                Duration::from_millis(config.backoff_min() as u64),
+                Duration::from_millis(config.backoff_max() as u64),
+            )
+        };
+
+        // statically configured endpoints may be hostnames which we track so
+        // that they can be re-resolved, endpoints discovered through other
+        // means are used as-is
+        let mut endpoints: Vec<Endpoint> = if !config.endpoints().is_empty() {
+            let mut endpoints = Vec::new();
+            for name in config.endpoints() {
+                endpoints.push(Endpoint::new(
+                    Some(name.clone()),
+                    resolve_all(name)?,
+                    Pool::Primary,
+                    backoff(),
+                ));
+            }
+            endpoints
+        } else {
+            config
+                .socket_addrs()?
+                .drain(..)
+                .map(|addr| Endpoint::new(None, vec![addr], Pool::Primary, backoff()))
+                .collect()
+        };
+
+        // the standby endpoints follow the primary endpoints
+        for name in config.standby() {
+            endpoints.push(Endpoint::new(
+                Some(name.clone()),
+                resolve_all(name)?,
+                Pool::Standby,
+                backoff(),
+            ));
         }
 
         Ok(Self {
-            free_queue,
+            connect_timeout,
+            endpoints,
+            failover_delay,
+            fallback_delay,
             nevent,
             parser,
             poll,
-            sessions,
+            resolve_interval,
             timeout,
             waker,
+            _request: PhantomData,
+            _response: PhantomData,
         })
     }
 
@@ -86,18 +171,37 @@ where
     pub fn build(
         self,
         data_queue: Queues<(Request, Response, Token), (Request, Token)>,
-        signal_queue: Queues<(), Signal>,
+        signal_queue: Queues<Reply, Signal>,
     ) -> BackendWorker<Parser, Request, Response> {
+        // connections to each endpoint are opened once the worker runs
+        let reconnect = (0..self.endpoints.len()).collect();
+
         BackendWorker {
+            attempts: HashMap::new(),
             backlog: VecDeque::new(),
+            connect_timeout: self.connect_timeout,
+            connections: HashMap::new(),
             data_queue,
-            free_queue: self.free_queue,
+            endpoints: self.endpoints,
+            failover_delay: self.failover_delay,
+            fallback_delay: self.fallback_delay,
+            free_queue: VecDeque::new(),
+            idle: VecDeque::new(),
             nevent: self.nevent,
+            next_resolve: self
+                .resolve_interval
+                .map(|interval| std::time::Instant::now() + interval),
             parser: self.parser,
             pending: HashMap::new(),
             poll: self.poll,
-            sessions: self.sessions,
+            pool: Pool::Primary,
+            primary_down: None,
+            races: Slab::new(),
+            reconnect,
+            resolve_interval: self.resolve_interval,
+            sessions: Slab::new(),
             signal_queue,
+            stale: HashSet::new(),
             timeout: self.timeout,
             waker: self.waker,
         }
@@ -105,15 +209,35 @@ where
 }
 
 pub struct BackendWorker<Parser, Request, Response> {
+    attempts: HashMap<Token, Attempt>,
     backlog: VecDeque<(Request, Token)>,
+    connect_timeout: Duration,
+    connections: HashMap<Token, Connection>,
     data_queue: Queues<(Request, Response, Token), (Request, Token)>,
+    endpoints: Vec<Endpoint>,
+    failover_delay: Duration,
+    fallback_delay: Duration,
+    /// Established sessions to the active pool which have no outstanding
+    /// request
     free_queue: VecDeque<Token>,
+    /// Established sessions to the inactive pool, which are kept open so that
+    /// the pool is ready to take over
+    idle: VecDeque<Token>,
     nevent: usize,
+    next_resolve: Option<std::time::Instant>,
     parser: Parser,
     pending: HashMap<Token, Token>,
     poll: Poll,
+    /// The pool which this thread currently sends requests to
+    pool: Pool,
+    /// When the last connection to the primary pool was lost
+    primary_down: Option<std::time::Instant>,
+    races: Slab<Race>,
+    reconnect: Vec<usize>,
+    resolve_interval: Option<Duration>,
     sessions: Slab<ClientSession<Parser, Request, Response>>,
-    signal_queue: Queues<(), Signal>,
+    signal_queue: Queues<Reply, Signal>,
+    stale: HashSet<Token>,
     timeout: Duration,
     waker: Arc<Waker>,
 }
@@ -123,11 +247,299 @@ where
     Parser: Parse<Response> + Clone,
     Request: Compose,
 {
-    /// Return the `Session` to the `Listener` to handle flush/close
-    fn close(&mut self, token: Token) {
+    /// Remove a session without scheduling a reconnect
+    fn discard(&mut self, token: Token) {
         if self.sessions.contains(token.0) {
             let mut session = self.sessions.remove(token.0);
             let _ = session.flush();
+            let _ = session.deregister(self.poll.registry());
+        }
+    }
+
+    /// Close the session and schedule a new connection to its endpoint
+    fn close(&mut self, token: Token) {
+        self.discard(token);
+        self.free_queue.retain(|t| *t != token);
+        self.idle.retain(|t| *t != token);
+        self.stale.remove(&token);
+        if let Some(connection) = self.connections.remove(&token) {
+            self.endpoints[connection.endpoint]
+                .pool()
+                .connections()
+                .decrement();
+            self.reconnect.push(connection.endpoint);
+        }
+    }
+
+    /// Send a request on an established session
+    fn send(&mut self, token: Token, request: Request, fe_token: Token) -> Result<()> {
+        self.sessions[token.0].send(request)?;
+        self.pending.insert(token, fe_token);
+        Ok(())
+    }
+
+    /// Make an established session available for requests, sending the
+    /// oldest backlogged request if there is one
+    fn release(&mut self, token: Token) {
+        // sessions to a stale address are replaced once idle
+        if self.stale.contains(&token) {
+            self.close(token);
+            return;
+        }
+
+        let pool = self
+            .connections
+            .get(&token)
+            .map(|connection| self.endpoints[connection.endpoint].pool());
+        if pool != Some(self.pool) {
+            self.idle.push_back(token);
+            return;
+        }
+
+        if let Some((request, fe_token)) = self.backlog.pop_front() {
+            if self.send(token, request, fe_token).is_err() {
+                self.close(token);
+            }
+        } else {
+            self.free_queue.push_back(token);
+        }
+    }
+
+    /// Promote the standby pool once the primary pool has had no established
+    /// connection for the failover delay, and follow any change of the active
+    /// pool, whether by this thread, another one, or the admin command
+    fn failover(&mut self, now: std::time::Instant) {
+        let (mut primary, mut standby) = (false, false);
+        for connection in self.connections.values() {
+            match self.endpoints[connection.endpoint].pool() {
+                Pool::Primary => primary = true,
+                Pool::Standby => standby = true,
+            }
+        }
+
+        if primary {
+            self.primary_down = None;
+        } else {
+            let since = *self.primary_down.get_or_insert(now);
+            if self.pool == Pool::Primary
+                && standby
+                && now >= since + self.failover_delay
+                && pool::promote()
+            {
+                warn!(
+                    "no connection to the primary backends for {:?}, promoting the standby pool",
+                    now - since
+                );
+            }
+        }
+
+        let active = pool::active();
+        if active != self.pool {
+            self.switch(active);
+        }
+    }
+
+    /// Send requests to the other pool. Requests which are already in flight
+    /// to the previously active pool are completed there.
+    fn switch(&mut self, pool: Pool) {
+        info!(
+            "switching backend pool from {} to {}",
+            self.pool.as_str(),
+            pool.as_str()
+        );
+        self.pool = pool;
+
+        let inactive = std::mem::take(&mut self.free_queue);
+        for token in std::mem::replace(&mut self.idle, inactive) {
+            self.release(token);
+        }
+    }
+
+    /// Start a connection attempt to the next address for a race
+    fn attempt(&mut self, race_id: usize) {
+        let now = std::time::Instant::now();
+
+        let race = &mut self.races[race_id];
+        let addr = self.endpoints[race.endpoint].nth(race.started);
+        race.started += 1;
+        race.last = now;
+
+        BACKEND_CONNECT.increment();
+        let result = TcpStream::connect(addr).and_then(|stream| {
+            let mut session = ClientSession::new(Session::from(stream), self.parser.clone());
+            let s = self.sessions.vacant_entry();
+            let token = Token(s.key());
+            // writable interest is needed to learn when the connect completes
+            session.register(
+                self.poll.registry(),
+                token,
+                Interest::READABLE.add(Interest::WRITABLE),
+            )?;
+            s.insert(session);
+            Ok(token)
+        });
+
+        match result {
+            Ok(token) => {
+                self.races[race_id].attempts.push(token);
+                self.attempts.insert(
+                    token,
+                    Attempt {
+                        race: race_id,
+                        addr,
+                        deadline: now + self.connect_timeout,
+                    },
+                );
+            }
+            Err(e) => {
+                BACKEND_CONNECT_EX.increment();
+                debug!("failed to connect to backend {}: {}", addr, e);
+                self.check(race_id);
+            }
+        }
+    }
+
+    /// Handle a failed connection attempt
+    fn attempt_failed(&mut self, token: Token) {
+        BACKEND_CONNECT_EX.increment();
+        self.discard(token);
+        if let Some(attempt) = self.attempts.remove(&token) {
+            debug!("failed to connect to backend {}", attempt.addr);
+            self.races[attempt.race].attempts.retain(|t| *t != token);
+            self.check(attempt.race);
+        }
+    }
+
+    /// Once all attempts in a race have failed, try the next address. If
+    /// every address has been tried, back off before starting over.
+    fn check(&mut self, race_id: usize) {
+        let race = &self.races[race_id];
+        if !race.attempts.is_empty() {
+            return;
+        }
+
+        if race.started < self.endpoints[race.endpoint].len() {
+            self.attempt(race_id);
+        } else {
+            let race = self.races.remove(race_id);
+            let endpoint = &mut self.endpoints[race.endpoint];
+            let delay = endpoint.backoff.failure(std::time::Instant::now());
+            warn!(
+                "failed to connect to backend {}, retrying in {:?}",
+                endpoint.addr(),
+                delay
+            );
+            self.reconnect.push(race.endpoint);
+        }
+    }
+
+    /// Handle a completed connection attempt, abandoning any other attempts
+    /// in the same race
+    fn connected(&mut self, token: Token) {
+        let attempt = match self.attempts.remove(&token) {
+            Some(attempt) => attempt,
+            None => return,
+        };
+
+        let race = self.races.remove(attempt.race);
+        for other in race.attempts.iter().filter(|t| **t != token) {
+            self.attempts.remove(other);
+            self.discard(*other);
+        }
+
+        self.endpoints[race.endpoint].connected(attempt.addr);
+        self.endpoints[race.endpoint]
+            .pool()
+            .connections()
+            .increment();
+        self.connections.insert(
+            token,
+            Connection {
+                endpoint: race.endpoint,
+                addr: attempt.addr,
+            },
+        );
+        self.release(token);
+    }
+
+    /// Re-resolve endpoints if the interval has elapsed and manage connection
+    /// attempts. Idle sessions which are connected to an address which is no
+    /// longer current are replaced immediately, while busy sessions are
+    /// replaced once their outstanding request completes.
+    fn maintain(&mut self) {
+        let now = std::time::Instant::now();
+
+        if let (Some(interval), Some(next)) = (self.resolve_interval, self.next_resolve) {
+            if now >= next {
+                self.next_resolve = Some(now + interval);
+
+                let mut changed = false;
+                for endpoint in self.endpoints.iter_mut() {
+                    changed |= endpoint.resolve();
+                }
+
+                if changed {
+                    let stale: Vec<Token> = self
+                        .connections
+                        .iter()
+                        .filter(|(_, c)| c.addr != self.endpoints[c.endpoint].addr())
+                        .map(|(token, _)| *token)
+                        .collect();
+                    for token in stale {
+                        if self.free_queue.contains(&token) || self.idle.contains(&token) {
+                            self.close(token);
+                        } else {
+                            self.stale.insert(token);
+                        }
+                    }
+                }
+            }
+        }
+
+        // abandon attempts which have not completed within the timeout
+        let expired: Vec<Token> = self
+            .attempts
+            .iter()
+            .filter(|(_, attempt)| now >= attempt.deadline)
+            .map(|(token, _)| *token)
+            .collect();
+        for token in expired {
+            BACKEND_CONNECT_TIMEOUT.increment();
+            self.attempt_failed(token);
+        }
+
+        // start a parallel attempt to the next address for any race which
+        // has not completed within the fallback delay
+        let slow: Vec<usize> = self
+            .races
+            .iter()
+            .filter(|(_, race)| {
+                !race.attempts.is_empty()
+                    && race.started < self.endpoints[race.endpoint].len()
+                    && now >= race.last + self.fallback_delay
+            })
+            .map(|(id, _)| id)
+            .collect();
+        for id in slow {
+            BACKEND_CONNECT_FALLBACK.increment();
+            self.attempt(id);
+        }
+
+        self.failover(now);
+
+        // endpoints which are backing off are retried on a later call
+        for id in std::mem::take(&mut self.reconnect) {
+            if self.endpoints[id].backoff.ready(now) {
+                let race = self.races.insert(Race {
+                    endpoint: id,
+                    attempts: Vec::new(),
+                    started: 0,
+                    last: now,
+                });
+                self.attempt(race);
+            } else {
+                self.reconnect.push(id);
+            }
         }
     }
 
@@ -145,7 +557,7 @@ where
         match session.receive() {
             Ok((request, response)) => {
                 if let Some(fe_token) = self.pending.remove(&token) {
-                    self.free_queue.push_back(token);
+                    self.release(token);
                     self.data_queue
                         .try_send_to(0, (request, response, fe_token))
                         .map_err(|_| Error::new(ErrorKind::Other, "data queue is full"))
@@ -178,8 +590,12 @@ where
         let mut messages = Vec::with_capacity(QUEUE_CAPACITY);
         // let mut sessions = Vec::with_capacity(QUEUE_CAPACITY);
 
+        // open the initial connections
+        self.maintain();
+
         loop {
             BACKEND_EVENT_LOOP.increment();
+            common::rusage::sample();
 
             // get events with timeout
             if self.poll.poll(&mut events, Some(self.timeout)).is_err() {
@@ -206,32 +622,54 @@ where
                         self.data_queue.try_recv_all(&mut messages);
                         for (request, fe_token) in messages.drain(..).map(|v| v.into_inner()) {
                             if let Some(be_token) = self.free_queue.pop_front() {
-                                let session = &mut self.sessions[be_token.0];
-                                if session.send(request).is_err() {
+                                if self.send(be_token, request, fe_token).is_err() {
                                     panic!("we don't handle this right now");
-                                } else {
-                                    self.pending.insert(be_token, fe_token);
                                 }
                             } else {
-                                self.backlog.push_back((request, token));
+                                self.backlog.push_back((request, fe_token));
                             }
                         }
 
                         // check if we received any signals from the admin thread
-                        while let Some(signal) =
-                            self.signal_queue.try_recv().map(|v| v.into_inner())
-                        {
-                            match signal {
-                                Signal::FlushAll => {}
+                        while let Some(signal) = self.signal_queue.try_recv() {
+                            let sender = signal.sender();
+                            match signal.into_inner() {
+                                Signal::Health => {
+                                    // ready once there is a connection to at least one backend
+                                    let health = ThreadHealth {
+                                        listening: false,
+                                        storage: !self.connections.is_empty(),
+                                        warming_up: false,
+                                    };
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Health(health));
+                                    let _ = self.signal_queue.wake();
+                                }
                                 Signal::Shutdown => {
                                     // if we received a shutdown, we can return
                                     // and stop processing events
                                     return;
                                 }
+                                signal => signal.reply_default(|reply| {
+                                    let _ = self.signal_queue.try_send_to(sender, reply);
+                                    let _ = self.signal_queue.wake();
+                                }),
                             }
                         }
                     }
                     _ => {
+                        if self.attempts.contains_key(&token) {
+                            if event.is_error() {
+                                self.attempt_failed(token);
+                                continue;
+                            } else if event.is_writable() {
+                                self.connected(token);
+                            } else {
+                                continue;
+                            }
+                        }
+
                         if event.is_error() {
                             BACKEND_EVENT_ERROR.increment();
 
@@ -260,6 +698,8 @@ where
                 }
             }
 
+            self.maintain();
+
             // wakes the storage thread if necessary
             let _ = self.data_queue.wake();
         }
@@ -281,6 +721,8 @@ where
         parser: BackendParser,
         threads: usize,
     ) -> Result<Self> {
+        pool::init(!config.backend().standby().is_empty());
+
         let mut builders = Vec::new();
         for _ in 0..threads {
             builders.push(BackendWorkerBuilder::new(config, parser.clone())?);
@@ -298,7 +740,7 @@ where
         mut data_queues: Vec<
             Queues<(BackendRequest, BackendResponse, Token), (BackendRequest, Token)>,
         >,
-        mut signal_queues: Vec<Queues<(), Signal>>,
+        mut signal_queues: Vec<Queues<Reply, Signal>>,
     ) -> Vec<BackendWorker<BackendParser, BackendRequest, BackendResponse>> {
         self.builders
             .drain(..)
@@ -306,3 +748,90 @@ where
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol_ping::{Request, Response, ResponseParser};
+    use queues::Waiter;
+
+    // an address which refuses connections
+    fn closed() -> SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    #[test]
+    fn failover() {
+        let _lock = pool::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        pool::init(true);
+
+        // the standby is accepted by the kernel without needing to be polled
+        let standby = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let backoff = || Backoff::new(Duration::from_millis(10), Duration::from_millis(100));
+        let endpoints = vec![
+            Endpoint::new(None, vec![closed()], Pool::Primary, backoff()),
+            Endpoint::new(
+                None,
+                vec![standby.local_addr().unwrap()],
+                Pool::Standby,
+                backoff(),
+            ),
+        ];
+
+        let poll = Poll::new().unwrap();
+        let waker = Arc::new(Waker::from(
+            ::net::Waker::new(poll.registry(), WAKER_TOKEN).unwrap(),
+        ));
+        let failover_delay = Duration::from_millis(50);
+        let builder = BackendWorkerBuilder::<_, Request, Response> {
+            connect_timeout: Duration::from_millis(1000),
+            endpoints,
+            failover_delay,
+            fallback_delay: Duration::from_millis(250),
+            nevent: 16,
+            parser: ResponseParser::new(),
+            poll,
+            resolve_interval: None,
+            timeout: Duration::from_millis(10),
+            waker,
+            _request: PhantomData,
+            _response: PhantomData,
+        };
+
+        let waiter = Waiter::new().unwrap();
+        let (mut data_queues, _frontend) =
+            Queues::new(vec![builder.waker()], vec![waiter.waker()], QUEUE_CAPACITY);
+        let (mut signal_queues, _admin) =
+            Queues::new(vec![builder.waker()], vec![waiter.waker()], QUEUE_CAPACITY);
+        let mut worker = builder.build(data_queues.remove(0), signal_queues.remove(0));
+
+        // drive the connection attempts until the standby is promoted
+        let start = std::time::Instant::now();
+        let mut events = Events::with_capacity(16);
+        while pool::active() == Pool::Primary && start.elapsed() < Duration::from_secs(5) {
+            worker.maintain();
+            let _ = worker
+                .poll
+                .poll(&mut events, Some(Duration::from_millis(10)));
+            for event in events.iter() {
+                let token = event.token();
+                if worker.attempts.contains_key(&token) {
+                    if event.is_error() {
+                        worker.attempt_failed(token);
+                    } else if event.is_writable() {
+                        worker.connected(token);
+                    }
+                }
+            }
+        }
+
+        assert_eq!(pool::active(), Pool::Standby);
+        assert!(start.elapsed() >= failover_delay);
+
+        // requests now go to the standby connection, which was kept open
+        assert_eq!(worker.pool, Pool::Standby);
+        assert_eq!(worker.free_queue.len(), 1);
+        assert!(worker.idle.is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Tracks the addresses and reconnect state of backend endpoints.
+
+use crate::pool::Pool;
+use crate::*;
+use config::resolve::resolve_all;
+use rand::Rng;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+counter!(
+    BACKEND_RESOLVE,
+    "the number of times backend endpoints were re-resolved"
+);
+counter!(
+    BACKEND_RESOLVE_EX,
+    "the number of times re-resolving a backend endpoint failed"
+);
+counter!(
+    BACKEND_RESOLVE_CHANGE,
+    "the number of times re-resolving changed the address of an endpoint"
+);
+counter!(
+    BACKEND_BACKOFF,
+    "the number of times reconnecting to an endpoint was delayed by backoff"
+);
+
+/// Exponential backoff with jitter. Each consecutive failure doubles the
+/// delay, up to the maximum, and the actual delay is chosen uniformly between
+/// half and all of it so that clients do not reconnect in lockstep.
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    failures: u32,
+    until: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+            failures: 0,
+            until: None,
+        }
+    }
+
+    /// Returns true if a new attempt may be made.
+    pub fn ready(&self, now: Instant) -> bool {
+        self.until.map(|until| now >= until).unwrap_or(true)
+    }
+
+    /// Record a failure, delaying the next attempt.
+    pub fn failure(&mut self, now: Instant) -> Duration {
+        BACKEND_BACKOFF.increment();
+        let delay = self
+            .min
+            .saturating_mul(1 << self.failures.min(16))
+            .min(self.max);
+        let delay = rand::thread_rng().gen_range((delay / 2)..=delay);
+        self.failures = self.failures.saturating_add(1);
+        self.until = Some(now + delay);
+        delay
+    }
+
+    /// Record a success, resetting the delay.
+    pub fn success(&mut self) {
+        self.failures = 0;
+        self.until = None;
+    }
+}
+
+/// A backend endpoint. Endpoints which are configured by hostname may resolve
+/// to multiple addresses, possibly of both address families. The addresses
+/// are ordered so that the families are interleaved, as recommended by the
+/// happy eyeballs algorithm (RFC 8305), and connections are attempted in
+/// that order starting from the address which last succeeded.
+pub struct Endpoint {
+    name: Option<String>,
+    addrs: Vec<SocketAddr>,
+    current: usize,
+    pool: Pool,
+    pub backoff: Backoff,
+}
+
+impl Endpoint {
+    pub fn new(name: Option<String>, addrs: Vec<SocketAddr>, pool: Pool, backoff: Backoff) -> Self {
+        Self {
+            name,
+            addrs: interleave(addrs),
+            current: 0,
+            pool,
+            backoff,
+        }
+    }
+
+    /// The pool which the endpoint belongs to.
+    pub fn pool(&self) -> Pool {
+        self.pool
+    }
+
+    /// The address which most recently succeeded, or the first address if
+    /// none has.
+    pub fn addr(&self) -> SocketAddr {
+        self.addrs[self.current]
+    }
+
+    /// Returns the address which is `offset` places after the current
+    /// address, in connection attempt order.
+    pub fn nth(&self, offset: usize) -> SocketAddr {
+        self.addrs[(self.current + offset) % self.addrs.len()]
+    }
+
+    /// The number of resolved addresses.
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    /// Record that a connection to the address succeeded, making it the
+    /// current address.
+    pub fn connected(&mut self, addr: SocketAddr) {
+        if let Some(position) = self.addrs.iter().position(|a| *a == addr) {
+            self.current = position;
+        }
+        self.backoff.success();
+    }
+
+    /// Re-resolve the endpoint, returning true if the current address is no
+    /// longer valid. The current address is kept if it is still in the
+    /// resolved set. If resolution fails, the previously resolved addresses
+    /// are kept.
+    pub fn resolve(&mut self) -> bool {
+        let name = match &self.name {
+            Some(name) => name,
+            None => return false,
+        };
+
+        BACKEND_RESOLVE.increment();
+
+        let addrs = match resolve_all(name) {
+            Ok(addrs) => interleave(addrs),
+            Err(e) => {
+                BACKEND_RESOLVE_EX.increment();
+                warn!("failed to resolve backend endpoint {}: {}", name, e);
+                return false;
+            }
+        };
+
+        let previous = self.addr();
+        self.current = addrs.iter().position(|a| *a == previous).unwrap_or(0);
+        self.addrs = addrs;
+
+        if self.addr() != previous {
+            BACKEND_RESOLVE_CHANGE.increment();
+            info!(
+                "backend endpoint {} changed from {} to {}",
+                name,
+                previous,
+                self.addr()
+            );
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Reorder addresses so that address families alternate, keeping the
+/// relative order within each family and starting with the family of the
+/// first address.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6 = addrs.first().map(|a| a.is_ipv6()).unwrap_or(false);
+    let (mut preferred, mut other): (VecDeque<SocketAddr>, VecDeque<SocketAddr>) =
+        addrs.into_iter().partition(|a| a.is_ipv6() == first_v6);
+
+    let mut result = Vec::with_capacity(preferred.len() + other.len());
+    loop {
+        match (preferred.pop_front(), other.pop_front()) {
+            (None, None) => break,
+            (a, b) => {
+                result.extend(a);
+                result.extend(b);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleaving() {
+        let addrs: Vec<SocketAddr> = vec![
+            "[::1]:1".parse().unwrap(),
+            "[::2]:1".parse().unwrap(),
+            "[::3]:1".parse().unwrap(),
+            "127.0.0.1:1".parse().unwrap(),
+        ];
+        let result = interleave(addrs);
+        assert_eq!(
+            result,
+            vec![
+                "[::1]:1".parse::<SocketAddr>().unwrap(),
+                "127.0.0.1:1".parse().unwrap(),
+                "[::2]:1".parse().unwrap(),
+                "[::3]:1".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn backoff() {
+        let min = Duration::from_millis(10);
+        let max = Duration::from_millis(100);
+        let mut backoff = Backoff::new(min, max);
+        let now = Instant::now();
+
+        assert!(backoff.ready(now));
+
+        let delay = backoff.failure(now);
+        assert!(delay >= min / 2 && delay <= min);
+        assert!(!backoff.ready(now));
+        assert!(backoff.ready(now + delay));
+
+        for _ in 0..10 {
+            assert!(backoff.failure(now) <= max);
+        }
+
+        backoff.success();
+        assert!(backoff.ready(now));
+    }
+}
@@ -22,6 +22,10 @@ counter!(
 counter!(FRONTEND_EVENT_READ, "the number of read events received");
 counter!(FRONTEND_EVENT_TOTAL, "the total number of events received");
 counter!(FRONTEND_EVENT_WRITE, "the number of write events received");
+counter!(
+    FRONTEND_SESSION_KILL,
+    "the number of sessions closed by an admin command"
+);
 
 pub struct FrontendWorkerBuilder<
     FrontendParser,
@@ -81,7 +85,7 @@ impl<FrontendParser, FrontendRequest, FrontendResponse, BackendRequest, BackendR
         self,
         data_queue: Queues<(BackendRequest, Token), (BackendRequest, BackendResponse, Token)>,
         session_queue: Queues<Session, Session>,
-        signal_queue: Queues<(), Signal>,
+        signal_queue: Queues<Reply, Signal>,
     ) -> FrontendWorker<
         FrontendParser,
         FrontendRequest,
@@ -116,7 +120,7 @@ pub struct FrontendWorker<
     poll: Poll,
     session_queue: Queues<Session, Session>,
     sessions: Slab<ServerSession<FrontendParser, FrontendResponse, FrontendRequest>>,
-    signal_queue: Queues<(), Signal>,
+    signal_queue: Queues<Reply, Signal>,
     timeout: Duration,
     waker: Arc<Waker>,
 }
@@ -147,6 +151,41 @@ where
         }
     }
 
+    /// Describes each of the sessions handled by this frontend
+    fn session_infos(&self) -> Vec<SessionInfo> {
+        self.sessions
+            .iter()
+            .map(|(_, session)| SessionInfo {
+                id: session.id(),
+                peer_addr: session.peer_addr(),
+                age: session.age(),
+                bytes_read: session.bytes_read(),
+                bytes_written: session.bytes_written(),
+                read_pending: session.remaining(),
+                write_pending: session.write_pending(),
+            })
+            .collect()
+    }
+
+    /// Closes the session with the given id if it is handled by this frontend.
+    /// Returns true if the session was found.
+    fn kill(&mut self, id: u64) -> bool {
+        let key = self
+            .sessions
+            .iter()
+            .find(|(_, session)| session.id() == id)
+            .map(|(key, _)| key);
+
+        match key {
+            Some(key) => {
+                FRONTEND_SESSION_KILL.increment();
+                self.close(Token(key));
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Handle up to one request for a session
     fn read(&mut self, token: Token) -> Result<()> {
         let session = self
@@ -189,6 +228,7 @@ where
 
         loop {
             FRONTEND_EVENT_LOOP.increment();
+            common::rusage::sample();
 
             // get events with timeout
             if self.poll.poll(&mut events, Some(self.timeout)).is_err() {
@@ -261,16 +301,32 @@ where
                         }
 
                         // check if we received any signals from the admin thread
-                        while let Some(signal) =
-                            self.signal_queue.try_recv().map(|v| v.into_inner())
-                        {
-                            match signal {
-                                Signal::FlushAll => {}
+                        while let Some(signal) = self.signal_queue.try_recv() {
+                            let sender = signal.sender();
+                            match signal.into_inner() {
+                                Signal::ListSessions => {
+                                    let sessions = self.session_infos();
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Sessions(sessions));
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::KillSession(id) => {
+                                    let killed = self.kill(id);
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Killed(killed));
+                                    let _ = self.signal_queue.wake();
+                                }
                                 Signal::Shutdown => {
                                     // if we received a shutdown, we can return
                                     // and stop processing events
                                     return;
                                 }
+                                signal => signal.reply_default(|reply| {
+                                    let _ = self.signal_queue.try_send_to(sender, reply);
+                                    let _ = self.signal_queue.wake();
+                                }),
                             }
                         }
                     }
@@ -365,7 +421,7 @@ where
             Queues<(BackendRequest, Token), (BackendRequest, BackendResponse, Token)>,
         >,
         mut session_queues: Vec<Queues<Session, Session>>,
-        mut signal_queues: Vec<Queues<(), Signal>>,
+        mut signal_queues: Vec<Queues<Reply, Signal>>,
     ) -> Vec<
         FrontendWorker<
             FrontendParser,
@@ -14,7 +14,7 @@ extern crate rustcommon_metrics;
 use ::net::event::{Event, Source};
 use ::net::*;
 use admin::AdminBuilder;
-use common::signal::Signal;
+use common::signal::{Reply, SessionInfo, Signal, ThreadHealth};
 use common::ssl::tls_acceptor;
 use config::proxy::*;
 use config::*;
@@ -35,8 +35,10 @@ use waker::Waker;
 type Instant = rustcommon_metrics::Instant<rustcommon_metrics::Nanoseconds<u64>>;
 
 mod backend;
+mod endpoint;
 mod frontend;
 mod listener;
+mod pool;
 mod process;
 
 use backend::BackendBuilder;
@@ -75,7 +75,7 @@ impl ListenerBuilder {
 
     pub fn build(
         self,
-        signal_queue: Queues<(), Signal>,
+        signal_queue: Queues<Reply, Signal>,
         session_queue: Queues<Session, Session>,
     ) -> Listener {
         Listener {
@@ -85,6 +85,7 @@ impl ListenerBuilder {
             sessions: self.sessions,
             session_queue,
             signal_queue,
+            registered: true,
             timeout: self.timeout,
             waker: self.waker,
         }
@@ -104,7 +105,10 @@ pub struct Listener {
     /// receive sessions which should be closed
     session_queue: Queues<Session, Session>,
     /// Queue for receieving signals from the admin thread
-    signal_queue: Queues<(), Signal>,
+    signal_queue: Queues<Reply, Signal>,
+    /// Cleared if the listener could not be registered for events again, in
+    /// which case no further sessions are accepted
+    registered: bool,
     /// The timeout for each call to poll
     timeout: Duration,
     /// The waker handle for this thread
@@ -149,12 +153,12 @@ impl Listener {
         }
 
         // reregister is needed here so we will call accept if there is a backlog
-        if self
+        self.registered = self
             .listener
             .reregister(self.poll.registry(), LISTENER_TOKEN, Interest::READABLE)
-            .is_err()
-        {
-            // failed to reregister listener? how do we handle this?
+            .is_ok();
+        if !self.registered {
+            error!("failed to reregister the data listener");
         }
     }
 
@@ -267,6 +271,7 @@ impl Listener {
         // repeatedly run accepting new connections and moving them to the worker
         loop {
             LISTENER_EVENT_LOOP.increment();
+            common::rusage::sample();
             if self.poll.poll(&mut events, Some(self.timeout)).is_err() {
                 error!("Error polling server");
             }
@@ -291,16 +296,34 @@ impl Listener {
                         }
 
                         // check if we received any signals from the admin thread
-                        while let Some(signal) =
-                            self.signal_queue.try_recv().map(|v| v.into_inner())
-                        {
-                            match signal {
-                                Signal::FlushAll => {}
+                        while let Some(signal) = self.signal_queue.try_recv() {
+                            let sender = signal.sender();
+                            match signal.into_inner() {
+                                Signal::ReloadTls(acceptor) => {
+                                    if let Err(e) = self.listener.set_tls_acceptor(acceptor) {
+                                        error!("failed to reload tls certificate: {}", e);
+                                    }
+                                }
+                                Signal::Health => {
+                                    let health = ThreadHealth {
+                                        listening: self.registered,
+                                        storage: false,
+                                        warming_up: false,
+                                    };
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Health(health));
+                                    let _ = self.signal_queue.wake();
+                                }
                                 Signal::Shutdown => {
                                     // if we received a shutdown, we can return
                                     // and stop processing events
                                     return;
                                 }
+                                signal => signal.reply_default(|reply| {
+                                    let _ = self.signal_queue.try_send_to(sender, reply);
+                                    let _ = self.signal_queue.wake();
+                                }),
                             }
                         }
                     }
@@ -0,0 +1,180 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Primary and standby pools of backend endpoints. Requests are sent to the
+//! active pool, which is the primary pool until it has had no established
+//! connection for longer than the failover delay. The standby pool is then
+//! promoted. Connections to the standby pool are kept open, so that it is
+//! warm when it is promoted.
+//!
+//! The `pool` admin command shows which pool is active and switches it by
+//! hand. Returning to the primary pool after a failover is left to the
+//! operator, so that a primary which is flapping does not cause the pools to
+//! switch back and forth.
+
+use crate::*;
+use protocol_admin::{register_command, AdminResponse};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+counter!(
+    BACKEND_FAILOVER,
+    "the number of times the active backend pool was switched"
+);
+gauge!(
+    BACKEND_PRIMARY_CONN,
+    "the current number of connections to primary backend endpoints"
+);
+gauge!(
+    BACKEND_STANDBY_CONN,
+    "the current number of connections to standby backend endpoints"
+);
+
+// the active pool is shared by every backend thread in the process, so that
+// they fail over together and the admin command switches all of them
+static ACTIVE: AtomicU8 = AtomicU8::new(Pool::Primary as u8);
+static STANDBY: AtomicBool = AtomicBool::new(false);
+
+// held by tests which change the active pool, as it is shared between them
+#[cfg(test)]
+pub(crate) static TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// A pool of backend endpoints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Pool {
+    Primary = 0,
+    Standby = 1,
+}
+
+impl Pool {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Primary => "primary",
+            Self::Standby => "standby",
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        if value == Self::Standby as u8 {
+            Self::Standby
+        } else {
+            Self::Primary
+        }
+    }
+
+    /// The gauge of the number of connections to the endpoints in the pool.
+    pub fn connections(&self) -> &'static Gauge {
+        match self {
+            Self::Primary => &BACKEND_PRIMARY_CONN,
+            Self::Standby => &BACKEND_STANDBY_CONN,
+        }
+    }
+}
+
+/// Resets the active pool to the primary pool and registers the `pool` admin
+/// command. Switching to the standby pool is only possible if one is
+/// configured.
+pub fn init(standby: bool) {
+    ACTIVE.store(Pool::Primary as u8, Ordering::Relaxed);
+    STANDBY.store(standby, Ordering::Relaxed);
+
+    // the command may already be registered by an earlier proxy in the same
+    // process, in which case its handler is the same
+    let _ = register_command("pool", command);
+}
+
+/// The pool which requests are sent to.
+pub fn active() -> Pool {
+    Pool::from_u8(ACTIVE.load(Ordering::Relaxed))
+}
+
+/// Makes the standby pool active if the primary pool still is. Returns true
+/// if this call promoted it.
+pub fn promote() -> bool {
+    let promoted = ACTIVE
+        .compare_exchange(
+            Pool::Primary as u8,
+            Pool::Standby as u8,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        )
+        .is_ok();
+    if promoted {
+        BACKEND_FAILOVER.increment();
+    }
+    promoted
+}
+
+// makes the pool active, returning true if it was not already
+fn activate(pool: Pool) -> bool {
+    let changed = ACTIVE.swap(pool as u8, Ordering::Relaxed) != pool as u8;
+    if changed {
+        BACKEND_FAILOVER.increment();
+    }
+    changed
+}
+
+// handles `pool`, which shows the state of the pools, and `pool <name>`,
+// which makes the named pool active
+fn command(args: &[Vec<u8>]) -> AdminResponse {
+    let pool = match args {
+        [] => {
+            return AdminResponse::lines(vec![
+                format!("active {}", active().as_str()),
+                format!("primary_connections {}", BACKEND_PRIMARY_CONN.value()),
+                format!("standby_connections {}", BACKEND_STANDBY_CONN.value()),
+                format!("failovers {}", BACKEND_FAILOVER.value()),
+            ]);
+        }
+        [pool] if pool == b"primary" => Pool::Primary,
+        [pool] if pool == b"standby" => Pool::Standby,
+        _ => {
+            return AdminResponse::client_error("usage: pool [primary|standby]");
+        }
+    };
+
+    if pool == Pool::Standby && !STANDBY.load(Ordering::Relaxed) {
+        return AdminResponse::client_error("no standby pool is configured");
+    }
+
+    if activate(pool) {
+        warn!(
+            "admin switched the active backend pool to {}",
+            pool.as_str()
+        );
+    }
+    AdminResponse::ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switching() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        init(false);
+        assert!(matches!(
+            command(&[b"standby".to_vec()]),
+            AdminResponse::ClientError(_)
+        ));
+        assert!(matches!(
+            command(&[b"backup".to_vec()]),
+            AdminResponse::ClientError(_)
+        ));
+        assert_eq!(active(), Pool::Primary);
+
+        init(true);
+        assert!(matches!(command(&[]), AdminResponse::Lines(_)));
+        assert!(promote());
+        assert_eq!(active(), Pool::Standby);
+        assert!(!promote());
+
+        assert!(matches!(command(&[b"primary".to_vec()]), AdminResponse::Ok));
+        assert_eq!(active(), Pool::Primary);
+        assert!(matches!(command(&[b"standby".to_vec()]), AdminResponse::Ok));
+        assert_eq!(active(), Pool::Standby);
+    }
+}
@@ -60,7 +60,8 @@ where
         backend_parser: BackendParser,
         frontend_parser: FrontendParser,
     ) -> Result<Self> {
-        let admin = AdminBuilder::new(config)?;
+        let mut admin = AdminBuilder::new(config)?;
+        admin.data_tls(config.tls());
         let backend = BackendBuilder::new(config, backend_parser, 1)?;
         let frontend = FrontendBuilder::new(config, frontend_parser, 1)?;
         let listener = ListenerBuilder::new(config)?;
@@ -88,8 +89,11 @@ where
         let (signal_tx, signal_rx) = bounded(QUEUE_CAPACITY);
 
         // queues for the `Admin` to send `Signal`s to all sibling threads
-        let (mut signal_queue_tx, mut signal_queue_rx) =
-            Queues::new(vec![self.admin.waker()], thread_wakers, QUEUE_CAPACITY);
+        let (mut signal_queue_tx, mut signal_queue_rx) = Queues::new(
+            vec![self.admin.signal_waker()],
+            thread_wakers,
+            QUEUE_CAPACITY,
+        );
 
         // queues for the `Listener` to send `Session`s to the worker threads
         let (mut listener_session_queues, worker_session_queues) = Queues::new(
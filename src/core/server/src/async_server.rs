@@ -0,0 +1,280 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! An alternative front end which runs on a Tokio runtime instead of the mio
+//! based event loops. This is intended for embedding Pelikan protocol and
+//! storage components within an existing async service. Each client session
+//! is handled by its own task, and the storage is shared between tasks behind
+//! a mutex which is never held across an await point.
+//!
+//! This front end does not spawn the admin thread and does not support TLS.
+//! The event loop based `Process` remains the default for Pelikan servers.
+//!
+//! The requests served are reported under the listener name `async` unless
+//! another is set with `name()`, so that they can be told apart from those of
+//! the data port when both run in one process.
+
+use crate::*;
+use ::net::{TCP_ACCEPT, TCP_CLOSE, TCP_CONN_CURR, TCP_RECV_BYTE, TCP_SEND_BYTE};
+use session::{BufMut, Buffer};
+use std::borrow::{Borrow, BorrowMut};
+use std::sync::Mutex;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+
+const BUFFER_SIZE: usize = 16 * 1024;
+
+counter!(
+    ASYNC_SESSION_ACCEPT,
+    "number of sessions accepted by the async front end"
+);
+counter!(
+    ASYNC_SESSION_CLOSE,
+    "number of sessions closed by the async front end"
+);
+
+pub struct AsyncServerBuilder<Parser, Request, Response, Storage> {
+    addr: SocketAddr,
+    name: String,
+    parser: Parser,
+    storage: Storage,
+    threads: usize,
+    timeout: Duration,
+    _request: PhantomData<Request>,
+    _response: PhantomData<Response>,
+}
+
+impl<Parser, Request, Response, Storage> AsyncServerBuilder<Parser, Request, Response, Storage>
+where
+    Parser: 'static + Parse<Request> + Clone + Send,
+    Request: 'static + Klog + Klog<Response = Response> + Send,
+    Response: 'static + Compose + Send,
+    Storage: 'static + Execute<Request, Response> + EntryStore + Send,
+{
+    pub fn new<T: ServerConfig + WorkerConfig>(
+        config: &T,
+        parser: Parser,
+        storage: Storage,
+    ) -> Result<Self> {
+        let addr = config.server().socket_addr().map_err(|e| {
+            error!("{}", e);
+            std::io::Error::new(std::io::ErrorKind::Other, "Bad listen address")
+        })?;
+
+        let threads = config.worker().threads();
+        let timeout = Duration::from_millis(config.worker().timeout() as u64);
+
+        Ok(Self {
+            addr,
+            name: "async".to_string(),
+            parser,
+            storage,
+            threads,
+            timeout,
+            _request: PhantomData,
+            _response: PhantomData,
+        })
+    }
+
+    /// The name under which the requests served are reported by the `stats
+    /// listeners` admin command.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Bind the listener and serve clients on the current Tokio runtime. The
+    /// returned future completes only if the listener fails.
+    pub async fn serve(self) -> Result<()> {
+        let listener = TcpListener::bind(self.addr).await?;
+        self.serve_on(listener).await
+    }
+
+    /// Serve clients from an already bound listener on the current Tokio
+    /// runtime.
+    pub async fn serve_on(self, listener: TcpListener) -> Result<()> {
+        let storage = Arc::new(Mutex::new(self.storage));
+        let listener_stats = common::listener::register(&self.name);
+
+        spawn_expiry(storage.clone(), self.timeout);
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            TCP_ACCEPT.increment();
+            ASYNC_SESSION_ACCEPT.increment();
+
+            let _ = stream.set_nodelay(true);
+
+            let parser = self.parser.clone();
+            let storage = storage.clone();
+            let listener_stats = listener_stats.clone();
+
+            tokio::spawn(async move {
+                TCP_CONN_CURR.increment();
+                let (reader, writer) = stream.into_split();
+                if let Err(e) =
+                    handle_session(reader, writer, parser, storage, &listener_stats).await
+                {
+                    debug!("closing session: {}", e);
+                }
+                TCP_CONN_CURR.decrement();
+                TCP_CLOSE.increment();
+                ASYNC_SESSION_CLOSE.increment();
+            });
+        }
+    }
+
+    /// Launch a dedicated Tokio runtime which serves clients in the
+    /// background. The returned handle may be used to shutdown the runtime.
+    pub fn spawn(self) -> Result<AsyncServer> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(self.threads)
+            .thread_name(format!("{}_async", THREAD_PREFIX))
+            .enable_all()
+            .build()?;
+
+        let listener = runtime.block_on(TcpListener::bind(self.addr))?;
+        let local_addr = listener.local_addr()?;
+
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+
+        runtime.spawn(async move {
+            tokio::select! {
+                result = self.serve_on(listener) => {
+                    if let Err(e) = result {
+                        error!("async listener terminated: {}", e);
+                    }
+                }
+                _ = shutdown_rx => {}
+            }
+        });
+
+        Ok(AsyncServer {
+            local_addr,
+            runtime,
+            shutdown_tx,
+        })
+    }
+}
+
+/// A handle to a running async front end with its own runtime.
+pub struct AsyncServer {
+    local_addr: SocketAddr,
+    runtime: Runtime,
+    shutdown_tx: oneshot::Sender<()>,
+}
+
+impl AsyncServer {
+    /// Returns the address the listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops accepting new sessions and shuts down the runtime, terminating
+    /// any sessions which remain open.
+    pub fn shutdown(self) {
+        let _ = self.shutdown_tx.send(());
+        self.runtime.shutdown_background();
+    }
+}
+
+/// Periodically expire items, this takes the place of the expiration done on
+/// each iteration of the event loop.
+pub(crate) fn spawn_expiry<Storage: 'static + EntryStore + Send>(
+    storage: Arc<Mutex<Storage>>,
+    interval: Duration,
+) {
+    let interval = interval.max(Duration::from_millis(1));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            storage.lock().unwrap().expire();
+        }
+    });
+}
+
+/// Handles a single session, reading requests from `reader` and writing the
+/// responses to `writer` until the client hangs up or an error occurs.
+pub(crate) async fn handle_session<Reader, Writer, Parser, Request, Response, Storage>(
+    mut reader: Reader,
+    mut writer: Writer,
+    parser: Parser,
+    storage: Arc<Mutex<Storage>>,
+    listener: &ListenerStats,
+) -> Result<()>
+where
+    Reader: AsyncRead + Unpin,
+    Writer: AsyncWrite + Unpin,
+    Parser: Parse<Request>,
+    Request: Klog + Klog<Response = Response>,
+    Response: Compose,
+    Storage: Execute<Request, Response>,
+{
+    let mut read_buffer = Buffer::new(BUFFER_SIZE);
+    let mut write_buffer = Buffer::new(BUFFER_SIZE);
+
+    loop {
+        // make sure there is room to read into
+        if read_buffer.remaining_mut() == 0 {
+            read_buffer.reserve(BUFFER_SIZE);
+        }
+
+        let amt = reader.read(read_buffer.borrow_mut()).await?;
+        if amt == 0 {
+            return Err(Error::new(ErrorKind::Other, "client hangup"));
+        }
+        TCP_RECV_BYTE.add(amt as _);
+        unsafe {
+            read_buffer.advance_mut(amt);
+        }
+        let received = std::time::Instant::now();
+
+        // handle all complete requests in the buffer
+        let mut hangup = false;
+        loop {
+            let request = match parser.parse(read_buffer.borrow()) {
+                Ok(parsed) => {
+                    let consumed = parsed.consumed();
+                    let request = parsed.into_inner();
+                    read_buffer.advance(consumed);
+                    request
+                }
+                Err(e) => {
+                    if e.kind() == ErrorKind::WouldBlock {
+                        break;
+                    }
+                    return Err(e);
+                }
+            };
+
+            listener.record_request();
+            let response = storage.lock().unwrap().execute(&request);
+            PROCESS_REQ.increment();
+
+            if response.should_hangup() {
+                response.compose(&mut write_buffer);
+                hangup = true;
+                break;
+            }
+
+            request.klog(&response);
+            response.compose(&mut write_buffer);
+            listener.record_response(received.elapsed());
+        }
+
+        if write_buffer.remaining() > 0 {
+            let data: &[u8] = write_buffer.borrow();
+            writer.write_all(data).await?;
+            TCP_SEND_BYTE.add(data.len() as _);
+            write_buffer.clear();
+        }
+
+        if hangup {
+            return Err(Error::new(ErrorKind::Other, "should hangup"));
+        }
+    }
+}
@@ -73,6 +73,13 @@
 //! configuration. Fully negotiated sessions are then handed off to one or more
 //! worker threads.
 //!
+//! Other front ends, such as the `AsyncServer` and `QuicServer`, may serve
+//! requests in the same process, and they share the protocol metrics with the
+//! data listener. Each listener therefore has a name, which is configured for
+//! the data listener and set on the builder of the others, and the requests it
+//! serves and their latency are reported per name by the `stats listeners`
+//! admin command.
+//!
 //! ### Worker
 //! Worker threads handle ongoing communications for an established session.
 //! This includes request parsing and response composition. If the configuration
@@ -94,7 +101,8 @@ extern crate logger;
 use ::net::event::{Event, Source};
 use ::net::*;
 use admin::AdminBuilder;
-use common::signal::Signal;
+use common::listener::ListenerStats;
+use common::signal::{EventLoop, Reply, SessionInfo, Signal, ThreadHealth, Tunable};
 use common::ssl::tls_acceptor;
 use config::*;
 use core::marker::PhantomData;
@@ -102,12 +110,15 @@ use core::time::Duration;
 use crossbeam_channel::{bounded, Sender};
 use entrystore::EntryStore;
 use logger::{Drain, Klog};
-use protocol_common::{Compose, Execute, Parse};
+use protocol_common::{Compose, CountSession, Execute, Lookup, Parse, RecordLatency};
 use queues::Queues;
 use rustcommon_metrics::*;
+#[cfg(feature = "shaping")]
+use session::Shaping;
 use session::{Buf, ServerSession, Session};
 use slab::Slab;
 use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use waker::Waker;
 
@@ -115,10 +126,24 @@ mod listener;
 mod process;
 mod workers;
 
+#[cfg(feature = "tokio")]
+mod async_server;
+
+#[cfg(feature = "quic")]
+mod quic_server;
+
 use listener::ListenerBuilder;
 use workers::WorkersBuilder;
 
+pub use admin::ConfigLoader;
 pub use process::{Process, ProcessBuilder};
+pub use workers::StorageClient;
+
+#[cfg(feature = "tokio")]
+pub use async_server::{AsyncServer, AsyncServerBuilder};
+
+#[cfg(feature = "quic")]
+pub use quic_server::{QuicServer, QuicServerBuilder, ALPN};
 
 type Instant = rustcommon_metrics::Instant<rustcommon_metrics::Nanoseconds<u64>>;
 
@@ -132,6 +157,10 @@ const QUEUE_CAPACITY: usize = 64 * 1024;
 // determines the max number of calls to accept when the listener is ready
 const ACCEPT_BATCH: usize = 8;
 
+// determines the max number of requests handled for one session before the
+// worker moves on to other sessions, so a deep pipeline can't starve them
+const READ_BATCH: usize = 64;
+
 const LISTENER_TOKEN: Token = Token(usize::MAX - 1);
 const WAKER_TOKEN: Token = Token(usize::MAX);
 
@@ -3,7 +3,7 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use crate::*;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 counter!(LISTENER_EVENT_ERROR, "the number of error events received");
 counter!(
@@ -19,6 +19,25 @@ counter!(
     "the number of sessions discarded by the listener"
 );
 
+gauge!(
+    LISTENER_HANDSHAKE_CURR,
+    "the number of tls handshakes in progress"
+);
+counter!(
+    LISTENER_ACCEPT_DEFERRED,
+    "the number of times accepting new sessions was deferred because too many tls handshakes were in progress"
+);
+counter!(
+    LISTENER_HANDSHAKE_TIMEOUT,
+    "the number of sessions closed because the tls handshake did not complete in time"
+);
+
+/// A session which is completing its TLS handshake.
+struct Handshake {
+    session: Session,
+    started: Instant,
+}
+
 pub struct Listener {
     /// The actual network listener server
     listener: ::net::Listener,
@@ -27,12 +46,26 @@ pub struct Listener {
     /// The actual poll instantance
     poll: Poll,
     /// Sessions which have been opened, but are not fully established
-    sessions: Slab<Session>,
+    sessions: Slab<Handshake>,
+    /// Accepting new sessions is deferred while this many handshakes are in
+    /// progress, if set
+    max_handshakes: Option<usize>,
+    /// Handshakes which take longer than this are abandoned, if set
+    handshake_timeout: Option<Duration>,
+    /// The shaping applied to the responses on each new session
+    #[cfg(feature = "shaping")]
+    shaping: Shaping,
+    /// Set when accepting was deferred, so that it is resumed once the
+    /// handshakes in progress have drained
+    accept_deferred: bool,
     /// Queues for sending established sessions to the worker thread(s) and to
     /// receive sessions which should be closed
     session_queue: Queues<Session, Session>,
     /// Queue for receieving signals from the admin thread
-    signal_queue: Queues<(), Signal>,
+    signal_queue: Queues<Reply, Signal>,
+    /// Cleared if the listener could not be registered for events again, in
+    /// which case no further sessions are accepted
+    registered: bool,
     /// The timeout for each call to poll
     timeout: Duration,
     /// The waker handle for this thread
@@ -43,7 +76,11 @@ pub struct ListenerBuilder {
     listener: ::net::Listener,
     nevent: usize,
     poll: Poll,
-    sessions: Slab<Session>,
+    sessions: Slab<Handshake>,
+    max_handshakes: Option<usize>,
+    handshake_timeout: Option<Duration>,
+    #[cfg(feature = "shaping")]
+    shaping: Shaping,
     timeout: Duration,
     waker: Arc<Waker>,
 }
@@ -78,11 +115,42 @@ impl ListenerBuilder {
 
         let sessions = Slab::new();
 
+        #[cfg(feature = "handshake-backoff")]
+        let max_handshakes = Some(config.max_handshakes()).filter(|max| *max > 0);
+        #[cfg(feature = "handshake-backoff")]
+        let handshake_timeout = Some(config.handshake_timeout())
+            .filter(|timeout| *timeout > 0)
+            .map(|timeout| Duration::from_millis(timeout as u64));
+        #[cfg(not(feature = "handshake-backoff"))]
+        if config.max_handshakes() > 0 || config.handshake_timeout() > 0 {
+            warn!("handshake backoff is configured, but requires the handshake-backoff feature");
+        }
+        #[cfg(not(feature = "handshake-backoff"))]
+        let (max_handshakes, handshake_timeout) = (None, None);
+
+        #[cfg(feature = "shaping")]
+        let shaping = Shaping {
+            latency: Duration::from_millis(config.shaping_latency() as u64),
+            jitter: Duration::from_millis(config.shaping_jitter() as u64),
+            bandwidth: config.shaping_bandwidth(),
+        };
+        #[cfg(not(feature = "shaping"))]
+        if config.shaping_latency() > 0
+            || config.shaping_jitter() > 0
+            || config.shaping_bandwidth() > 0
+        {
+            warn!("response shaping is configured, but requires the shaping feature");
+        }
+
         Ok(Self {
             listener,
             nevent,
             poll,
             sessions,
+            max_handshakes,
+            handshake_timeout,
+            #[cfg(feature = "shaping")]
+            shaping,
             timeout,
             waker,
         })
@@ -92,9 +160,13 @@ impl ListenerBuilder {
         self.waker.clone()
     }
 
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
     pub fn build(
         self,
-        signal_queue: Queues<(), Signal>,
+        signal_queue: Queues<Reply, Signal>,
         session_queue: Queues<Session, Session>,
     ) -> Listener {
         Listener {
@@ -102,8 +174,14 @@ impl ListenerBuilder {
             nevent: self.nevent,
             poll: self.poll,
             sessions: self.sessions,
+            max_handshakes: self.max_handshakes,
+            handshake_timeout: self.handshake_timeout,
+            #[cfg(feature = "shaping")]
+            shaping: self.shaping,
+            accept_deferred: false,
             session_queue,
             signal_queue,
+            registered: true,
             timeout: self.timeout,
             waker: self.waker,
         }
@@ -114,7 +192,19 @@ impl Listener {
     /// Accept new sessions
     fn accept(&mut self) {
         for _ in 0..ACCEPT_BATCH {
+            // leave new connections in the backlog while the handshakes which
+            // are already in progress complete
+            if self.handshakes_saturated() {
+                LISTENER_ACCEPT_DEFERRED.increment();
+                self.accept_deferred = true;
+                return;
+            }
+            self.accept_deferred = false;
+
             if let Ok(mut session) = self.listener.accept().map(Session::from) {
+                #[cfg(feature = "shaping")]
+                session.shape(self.shaping);
+
                 if session.is_handshaking() {
                     let s = self.sessions.vacant_entry();
                     let interest = session.interest();
@@ -122,7 +212,10 @@ impl Listener {
                         .register(self.poll.registry(), Token(s.key()), interest)
                         .is_ok()
                     {
-                        s.insert(session);
+                        s.insert(Handshake {
+                            session,
+                            started: Instant::now(),
+                        });
                     } else {
                         // failed to register
                     }
@@ -148,12 +241,12 @@ impl Listener {
         }
 
         // reregister is needed here so we will call accept if there is a backlog
-        if self
+        self.registered = self
             .listener
             .reregister(self.poll.registry(), LISTENER_TOKEN, Interest::READABLE)
-            .is_err()
-        {
-            // failed to reregister listener? how do we handle this?
+            .is_ok();
+        if !self.registered {
+            error!("failed to reregister the data listener");
         }
     }
 
@@ -164,6 +257,7 @@ impl Listener {
         let session = self
             .sessions
             .get_mut(token.0)
+            .map(|handshake| &mut handshake.session)
             .ok_or_else(|| Error::new(ErrorKind::Other, "non-existant session"))?;
 
         // read from session to buffer
@@ -192,15 +286,47 @@ impl Listener {
     /// Closes the session with the given token
     fn close(&mut self, token: Token) {
         if self.sessions.contains(token.0) {
-            let mut session = self.sessions.remove(token.0);
+            let mut session = self.sessions.remove(token.0).session;
             let _ = session.flush();
         }
     }
 
+    /// Returns true if the number of handshakes in progress has reached the
+    /// limit, if there is one.
+    fn handshakes_saturated(&self) -> bool {
+        self.max_handshakes
+            .map(|max| self.sessions.len() >= max)
+            .unwrap_or(false)
+    }
+
+    /// Closes the sessions whose handshakes have exceeded the timeout.
+    fn expire_handshakes(&mut self) {
+        let timeout = match self.handshake_timeout {
+            Some(timeout) => timeout,
+            None => {
+                return;
+            }
+        };
+
+        let now = Instant::now();
+        let expired: Vec<usize> = self
+            .sessions
+            .iter()
+            .filter(|(_, handshake)| now.duration_since(handshake.started) > timeout)
+            .map(|(key, _)| key)
+            .collect();
+
+        for key in expired {
+            LISTENER_HANDSHAKE_TIMEOUT.increment();
+            self.close(Token(key));
+        }
+    }
+
     fn handshake(&mut self, token: Token) -> Result<()> {
         let session = self
             .sessions
             .get_mut(token.0)
+            .map(|handshake| &mut handshake.session)
             .ok_or_else(|| Error::new(ErrorKind::Other, "non-existant session"))?;
 
         session.do_handshake()
@@ -227,7 +353,7 @@ impl Listener {
         match self.handshake(token) {
             Ok(_) => {
                 // handshake is complete, send the session to a worker thread
-                let mut session = self.sessions.remove(token.0);
+                let mut session = self.sessions.remove(token.0).session;
                 for attempt in 1..=QUEUE_RETRIES {
                     if let Err(s) = self.session_queue.try_send_any(session) {
                         if attempt == QUEUE_RETRIES {
@@ -261,11 +387,19 @@ impl Listener {
                 .unwrap_or_else(|_| "unknown address".to_string())
         );
 
-        let mut events = Events::with_capacity(self.nevent);
+        let mut nevent = self.nevent;
+        let mut events = Events::with_capacity(nevent);
 
         // repeatedly run accepting new connections and moving them to the worker
         loop {
+            // a reload may have changed the number of events per poll
+            if self.nevent != nevent {
+                nevent = self.nevent;
+                events = Events::with_capacity(nevent);
+            }
+
             LISTENER_EVENT_LOOP.increment();
+            common::rusage::sample();
             if self.poll.poll(&mut events, Some(self.timeout)).is_err() {
                 error!("Error polling server");
             }
@@ -290,16 +424,42 @@ impl Listener {
                         }
 
                         // check if we received any signals from the admin thread
-                        while let Some(signal) =
-                            self.signal_queue.try_recv().map(|v| v.into_inner())
-                        {
-                            match signal {
-                                Signal::FlushAll => {}
+                        while let Some(signal) = self.signal_queue.try_recv() {
+                            let sender = signal.sender();
+                            match signal.into_inner() {
+                                Signal::Reload(reload) => {
+                                    self.nevent = reload.server_nevent;
+                                    self.timeout = reload.server_timeout;
+                                }
+                                Signal::Tune(EventLoop::Server, tunable) => match tunable {
+                                    Tunable::Nevent(nevent) => self.nevent = nevent,
+                                    Tunable::Timeout(timeout) => self.timeout = timeout,
+                                },
+                                Signal::ReloadTls(acceptor) => {
+                                    if let Err(e) = self.listener.set_tls_acceptor(acceptor) {
+                                        error!("failed to reload tls certificate: {}", e);
+                                    }
+                                }
+                                Signal::Health => {
+                                    let health = ThreadHealth {
+                                        listening: self.registered,
+                                        storage: false,
+                                        warming_up: false,
+                                    };
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Health(health));
+                                    let _ = self.signal_queue.wake();
+                                }
                                 Signal::Shutdown => {
                                     // if we received a shutdown, we can return
                                     // and stop processing events
                                     return;
                                 }
+                                signal => signal.reply_default(|reply| {
+                                    let _ = self.signal_queue.try_send_to(sender, reply);
+                                    let _ = self.signal_queue.wake();
+                                }),
                             }
                         }
                     }
@@ -309,6 +469,14 @@ impl Listener {
                 }
             }
 
+            // abandon handshakes which have taken too long, then resume
+            // accepting if it was deferred and the handshakes have drained
+            self.expire_handshakes();
+            if self.accept_deferred && !self.handshakes_saturated() {
+                self.accept();
+            }
+            LISTENER_HANDSHAKE_CURR.set(self.sessions.len() as _);
+
             let _ = self.session_queue.wake();
         }
     }
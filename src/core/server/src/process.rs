@@ -15,7 +15,13 @@ pub struct ProcessBuilder<Parser, Request, Response, Storage> {
 impl<Parser, Request, Response, Storage> ProcessBuilder<Parser, Request, Response, Storage>
 where
     Parser: 'static + Parse<Request> + Clone + Send,
-    Request: 'static + Klog + Klog<Response = Response> + Send,
+    Request: 'static
+        + Klog
+        + Klog<Response = Response>
+        + CountSession<Response>
+        + Lookup<Response>
+        + RecordLatency
+        + Send,
     Response: 'static + Compose + Send,
     Storage: 'static + Execute<Request, Response> + EntryStore + Send,
 {
@@ -25,7 +31,8 @@ where
         parser: Parser,
         storage: Storage,
     ) -> Result<Self> {
-        let admin = AdminBuilder::new(config)?;
+        let mut admin = AdminBuilder::new(config)?;
+        admin.data_tls(config.tls());
         let listener = ListenerBuilder::new(config)?;
         let workers = WorkersBuilder::new(config, parser, storage)?;
 
@@ -42,7 +49,24 @@ where
         self
     }
 
+    /// Returns a client which submits requests to the thread which owns the
+    /// storage, for front ends which run outside of the event loops.
+    pub fn storage_client(&self) -> StorageClient<Request, Response> {
+        self.workers.storage_client()
+    }
+
+    /// Enables the `reload` admin command, which uses the loader to read the
+    /// configuration again and applies the settings which can be changed at
+    /// runtime.
+    pub fn config_loader(mut self, loader: ConfigLoader) -> Self {
+        self.admin.config_loader(loader);
+        self
+    }
+
     pub fn spawn(self) -> Process {
+        let admin_addr = self.admin.local_addr().ok();
+        let listen_addr = self.listener.local_addr().ok();
+
         let mut thread_wakers = vec![self.listener.waker()];
         thread_wakers.extend_from_slice(&self.workers.wakers());
 
@@ -50,8 +74,11 @@ where
         let (signal_tx, signal_rx) = bounded(QUEUE_CAPACITY);
 
         // queues for the `Admin` to send `Signal`s to all sibling threads
-        let (mut signal_queue_tx, mut signal_queue_rx) =
-            Queues::new(vec![self.admin.waker()], thread_wakers, QUEUE_CAPACITY);
+        let (mut signal_queue_tx, mut signal_queue_rx) = Queues::new(
+            vec![self.admin.signal_waker()],
+            thread_wakers,
+            QUEUE_CAPACITY,
+        );
 
         // queues for the `Listener` to send `Session`s to the worker threads
         let (mut listener_session_queues, worker_session_queues) = Queues::new(
@@ -84,7 +111,9 @@ where
 
         Process {
             admin,
+            admin_addr,
             listener,
+            listen_addr,
             signal_tx,
             workers,
         }
@@ -93,12 +122,58 @@ where
 
 pub struct Process {
     admin: JoinHandle<()>,
+    admin_addr: Option<SocketAddr>,
     listener: JoinHandle<()>,
+    listen_addr: Option<SocketAddr>,
     signal_tx: Sender<Signal>,
     workers: Vec<JoinHandle<()>>,
 }
 
 impl Process {
+    /// Returns the address the data port is bound to. This is useful when the
+    /// configuration requests an ephemeral port.
+    pub fn listen_addr(&self) -> Option<SocketAddr> {
+        self.listen_addr
+    }
+
+    /// Returns the address the admin port is bound to.
+    pub fn admin_addr(&self) -> Option<SocketAddr> {
+        self.admin_addr
+    }
+
+    /// Returns the current value of the named counter, if it exists. Note that
+    /// metrics are process-wide and are shared by all instances running within
+    /// the same process.
+    pub fn counter(&self, name: &str) -> Option<u64> {
+        for metric in &rustcommon_metrics::metrics() {
+            if metric.name() != name {
+                continue;
+            }
+            if let Some(counter) = metric
+                .as_any()
+                .and_then(|any| any.downcast_ref::<Counter>())
+            {
+                return Some(counter.value());
+            }
+        }
+        None
+    }
+
+    /// Returns the current value of the named gauge, if it exists. Note that
+    /// metrics are process-wide and are shared by all instances running within
+    /// the same process.
+    pub fn gauge(&self, name: &str) -> Option<i64> {
+        for metric in &rustcommon_metrics::metrics() {
+            if metric.name() != name {
+                continue;
+            }
+            if let Some(gauge) = metric.as_any().and_then(|any| any.downcast_ref::<Gauge>()) {
+                return Some(gauge.value());
+            }
+        }
+        None
+    }
+
     /// Attempts to gracefully shutdown the `Process` by sending a shutdown to
     /// each thread and then waiting to join those threads.
     ///
@@ -0,0 +1,262 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! An experimental QUIC front end. Each bidirectional stream on a connection
+//! is treated as an independent session which carries the same protocol as
+//! the TCP listener. Since streams are independent, a lost packet only stalls
+//! the stream it belongs to, allowing clients to multiplex many concurrent
+//! requests over a single connection without head-of-line blocking. This is
+//! primarily intended to evaluate cache access over higher latency and lossy
+//! WAN links.
+//!
+//! QUIC requires TLS, so the certificate chain and private key must be
+//! provided in the `tls` section of the config. Peers must negotiate the
+//! [`ALPN`] protocol identifier. As UDP and TCP ports are separate, the
+//! listener defaults to the same port number as the TCP listener.
+//!
+//! Like the Tokio based front end, this shares the storage between tasks
+//! behind a mutex and does not spawn the admin thread.
+
+use crate::async_server::{handle_session, spawn_expiry};
+use crate::*;
+use common::ssl::TlsConfig as _;
+use futures_util::StreamExt;
+use quinn::{Endpoint, Incoming, NewConnection, VarInt};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Mutex;
+use tokio::runtime::Runtime;
+
+/// The ALPN protocol identifier which must be negotiated by clients.
+pub const ALPN: &[u8] = b"pelikan";
+
+const DEFAULT_MAX_STREAMS: u32 = 128;
+
+counter!(QUIC_CONN_ACCEPT, "number of QUIC connections accepted");
+counter!(
+    QUIC_CONN_HANDSHAKE_EX,
+    "number of QUIC connections which failed the handshake"
+);
+counter!(QUIC_CONN_CLOSE, "number of QUIC connections closed");
+gauge!(QUIC_CONN_CURR, "current number of QUIC connections");
+counter!(QUIC_STREAM_ACCEPT, "number of QUIC streams accepted");
+counter!(QUIC_STREAM_CLOSE, "number of QUIC streams closed");
+
+pub struct QuicServerBuilder<Parser, Request, Response, Storage> {
+    addr: SocketAddr,
+    crypto: rustls::ServerConfig,
+    max_streams: u32,
+    name: String,
+    parser: Parser,
+    storage: Storage,
+    threads: usize,
+    timeout: Duration,
+    _request: PhantomData<Request>,
+    _response: PhantomData<Response>,
+}
+
+impl<Parser, Request, Response, Storage> QuicServerBuilder<Parser, Request, Response, Storage>
+where
+    Parser: 'static + Parse<Request> + Clone + Send,
+    Request: 'static + Klog + Klog<Response = Response> + Send,
+    Response: 'static + Compose + Send,
+    Storage: 'static + Execute<Request, Response> + EntryStore + Send,
+{
+    pub fn new<T: ServerConfig + TlsConfig + WorkerConfig>(
+        config: &T,
+        parser: Parser,
+        storage: Storage,
+    ) -> Result<Self> {
+        let addr = config.server().socket_addr().map_err(|e| {
+            error!("{}", e);
+            std::io::Error::new(std::io::ErrorKind::Other, "Bad listen address")
+        })?;
+
+        let crypto = crypto(config)?;
+
+        let threads = config.worker().threads();
+        let timeout = Duration::from_millis(config.worker().timeout() as u64);
+
+        Ok(Self {
+            addr,
+            crypto,
+            max_streams: DEFAULT_MAX_STREAMS,
+            name: "quic".to_string(),
+            parser,
+            storage,
+            threads,
+            timeout,
+            _request: PhantomData,
+            _response: PhantomData,
+        })
+    }
+
+    /// Override the UDP address to listen on.
+    pub fn addr(mut self, addr: SocketAddr) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    /// The maximum number of concurrent bidirectional streams a client may
+    /// open on a single connection.
+    pub fn max_streams(mut self, streams: u32) -> Self {
+        self.max_streams = streams;
+        self
+    }
+
+    /// The name under which the requests served are reported by the `stats
+    /// listeners` admin command. Defaults to `quic`.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    fn endpoint(&self) -> Result<(Endpoint, Incoming)> {
+        let mut config = quinn::ServerConfig::with_crypto(Arc::new(self.crypto.clone()));
+        Arc::get_mut(&mut config.transport)
+            .unwrap()
+            .max_concurrent_bidi_streams(VarInt::from_u32(self.max_streams))
+            .max_concurrent_uni_streams(VarInt::from_u32(0));
+        Endpoint::server(config, self.addr)
+    }
+
+    /// Bind the endpoint and serve clients on the current Tokio runtime. The
+    /// returned future completes when the endpoint is closed.
+    pub async fn serve(self) -> Result<()> {
+        let (_endpoint, incoming) = self.endpoint()?;
+        self.serve_on(incoming).await;
+        Ok(())
+    }
+
+    async fn serve_on(self, mut incoming: Incoming) {
+        let storage = Arc::new(Mutex::new(self.storage));
+        let listener_stats = common::listener::register(&self.name);
+
+        spawn_expiry(storage.clone(), self.timeout);
+
+        while let Some(connecting) = incoming.next().await {
+            let parser = self.parser.clone();
+            let storage = storage.clone();
+            let listener_stats = listener_stats.clone();
+
+            tokio::spawn(async move {
+                let NewConnection { mut bi_streams, .. } = match connecting.await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        QUIC_CONN_HANDSHAKE_EX.increment();
+                        debug!("quic handshake failed: {}", e);
+                        return;
+                    }
+                };
+
+                QUIC_CONN_ACCEPT.increment();
+                QUIC_CONN_CURR.increment();
+
+                // each stream is handled as its own session, the loop ends
+                // when the connection is closed
+                while let Some(Ok((send, recv))) = bi_streams.next().await {
+                    QUIC_STREAM_ACCEPT.increment();
+
+                    let parser = parser.clone();
+                    let storage = storage.clone();
+                    let listener_stats = listener_stats.clone();
+
+                    tokio::spawn(async move {
+                        if let Err(e) =
+                            handle_session(recv, send, parser, storage, &listener_stats).await
+                        {
+                            debug!("closing stream: {}", e);
+                        }
+                        QUIC_STREAM_CLOSE.increment();
+                    });
+                }
+
+                QUIC_CONN_CURR.decrement();
+                QUIC_CONN_CLOSE.increment();
+            });
+        }
+    }
+
+    /// Launch a dedicated Tokio runtime which serves clients in the
+    /// background. The returned handle may be used to shutdown the runtime.
+    pub fn spawn(self) -> Result<QuicServer> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(self.threads)
+            .thread_name(format!("{}_quic", THREAD_PREFIX))
+            .enable_all()
+            .build()?;
+
+        // the endpoint must be created within the context of the runtime
+        let (endpoint, incoming) = {
+            let _guard = runtime.enter();
+            self.endpoint()?
+        };
+        let local_addr = endpoint.local_addr()?;
+
+        runtime.spawn(self.serve_on(incoming));
+
+        Ok(QuicServer {
+            endpoint,
+            local_addr,
+            runtime,
+        })
+    }
+}
+
+/// A handle to a running QUIC front end with its own runtime.
+pub struct QuicServer {
+    endpoint: Endpoint,
+    local_addr: SocketAddr,
+    runtime: Runtime,
+}
+
+impl QuicServer {
+    /// Returns the UDP address the endpoint is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Closes all connections and shuts down the runtime.
+    pub fn shutdown(self) {
+        self.endpoint.close(VarInt::from_u32(0), b"shutdown");
+        self.runtime.shutdown_background();
+    }
+}
+
+/// Loads the certificate chain and private key from the TLS config.
+fn crypto<T: TlsConfig>(config: &T) -> Result<rustls::ServerConfig> {
+    let tls = config.tls();
+
+    let chain = tls
+        .certificate_chain()
+        .or_else(|| tls.certificate())
+        .ok_or_else(|| Error::new(ErrorKind::Other, "quic requires a certificate"))?;
+    let key = tls
+        .private_key()
+        .ok_or_else(|| Error::new(ErrorKind::Other, "quic requires a private key"))?;
+
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(chain)?))?
+        .drain(..)
+        .map(rustls::Certificate)
+        .collect();
+
+    let key = rustls_pemfile::read_all(&mut BufReader::new(File::open(key)?))?
+        .drain(..)
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| Error::new(ErrorKind::Other, "no private key found"))?;
+
+    let mut crypto = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| Error::new(ErrorKind::Other, e))?;
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+
+    Ok(crypto)
+}
@@ -0,0 +1,256 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Adjusts the number of events per poll and the poll timeout of a worker
+//! thread to its load. A buffer sized for the peak wastes memory and cache at
+//! the trough, and a short timeout spends CPU on idle wakeups, while a small
+//! buffer at the peak takes several polls to drain the ready sessions.
+//!
+//! Each worker observes how many events each wakeup returns. Once per window,
+//! the number of events per poll doubles if polls often fill the buffer, and
+//! halves if they use little of it. The timeout doubles if most wakeups find
+//! no events, and halves if few do. Both stay within the configured bounds.
+//! The window lasts long enough for an idle thread to time out several times,
+//! so that a long timeout is still tuned.
+//!
+//! Setting either value by hand, through the admin port or a reload, stops
+//! autotuning until the next restart, so that the chosen value is kept.
+//!
+//! The storage thread is only woken through its queue, so it is not tuned.
+
+use super::*;
+use std::time::Instant;
+
+counter!(
+    WORKER_AUTOTUNE,
+    "the number of times a worker thread changed its number of events per poll or its timeout"
+);
+
+// how often the settings are reconsidered, at the least
+const WINDOW: Duration = Duration::from_secs(1);
+
+// a window with fewer wakeups than this says too little about the load, so
+// the window is lengthened to fit this many timeouts
+const MIN_WAKES: u32 = 10;
+
+// the share of wakeups which fill the buffer above which it is grown
+const FULL_RATIO: f64 = 0.1;
+
+// the buffer is shrunk when wakeups use less than this share of it
+const USED_RATIO: f64 = 0.25;
+
+// the share of wakeups without events above which the timeout is lengthened,
+// and below which it is shortened
+const IDLE_HIGH: f64 = 0.9;
+const IDLE_LOW: f64 = 0.5;
+
+pub struct Autotune {
+    nevent_min: usize,
+    nevent_max: usize,
+    timeout_min: Duration,
+    timeout_max: Duration,
+    /// The wakeups in the current window
+    wakes: u64,
+    /// The events returned in the current window
+    events: u64,
+    /// The wakeups in the current window which filled the buffer
+    full: u64,
+    /// The wakeups in the current window which returned no events
+    idle: u64,
+    window_start: Instant,
+}
+
+impl Autotune {
+    /// Returns a controller with the configured bounds, if autotuning is
+    /// enabled.
+    pub fn new(config: &config::Worker) -> Option<Self> {
+        if !config.autotune() {
+            return None;
+        }
+
+        Some(Self {
+            nevent_min: config.autotune_nevent_min().max(1),
+            nevent_max: config.autotune_nevent_max().max(1),
+            timeout_min: Duration::from_millis(config.autotune_timeout_min().max(1) as u64),
+            timeout_max: Duration::from_millis(config.autotune_timeout_max().max(1) as u64),
+            wakes: 0,
+            events: 0,
+            full: 0,
+            idle: 0,
+            window_start: Instant::now(),
+        })
+    }
+
+    /// Records a wakeup which returned `count` events from a buffer of
+    /// `nevent`, and adjusts the settings at the end of each window.
+    pub fn record(
+        &mut self,
+        count: usize,
+        nevent: &mut usize,
+        timeout: &mut Duration,
+        now: Instant,
+    ) {
+        self.wakes += 1;
+        self.events += count as u64;
+        if count >= *nevent {
+            self.full += 1;
+        } else if count == 0 {
+            self.idle += 1;
+        }
+
+        let window = WINDOW.max(timeout.saturating_mul(MIN_WAKES));
+        if now.saturating_duration_since(self.window_start) < window {
+            return;
+        }
+
+        if self.wakes >= MIN_WAKES as u64 {
+            let (next_nevent, next_timeout) = self.next(*nevent, *timeout);
+
+            if next_nevent != *nevent || next_timeout != *timeout {
+                WORKER_AUTOTUNE.increment();
+                info!(
+                    "autotune: {} wakeups with {} events, {} full and {} idle: nevent {} -> {}, timeout {}ms -> {}ms",
+                    self.wakes,
+                    self.events,
+                    self.full,
+                    self.idle,
+                    *nevent,
+                    next_nevent,
+                    timeout.as_millis(),
+                    next_timeout.as_millis()
+                );
+                *nevent = next_nevent;
+                *timeout = next_timeout;
+            }
+        }
+
+        self.wakes = 0;
+        self.events = 0;
+        self.full = 0;
+        self.idle = 0;
+        self.window_start = now;
+    }
+
+    /// Stops autotuning, as the settings were set by hand.
+    pub fn disable(autotune: &mut Option<Self>) {
+        if autotune.take().is_some() {
+            info!("autotune: disabled, as the worker settings were set by hand");
+        }
+    }
+
+    /// The settings for the next window, given those of this one.
+    fn next(&self, nevent: usize, timeout: Duration) -> (usize, Duration) {
+        let wakes = self.wakes as f64;
+
+        let nevent = if self.full as f64 / wakes > FULL_RATIO {
+            nevent.saturating_mul(2)
+        } else if self.full == 0 && (self.events as f64 / wakes) < nevent as f64 * USED_RATIO {
+            nevent / 2
+        } else {
+            nevent
+        };
+
+        let idle = self.idle as f64 / wakes;
+        let timeout = if idle > IDLE_HIGH {
+            timeout.saturating_mul(2)
+        } else if idle < IDLE_LOW {
+            timeout / 2
+        } else {
+            timeout
+        };
+
+        (
+            nevent.clamp(self.nevent_min, self.nevent_max.max(self.nevent_min)),
+            timeout.clamp(self.timeout_min, self.timeout_max.max(self.timeout_min)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn autotune() -> Autotune {
+        Autotune {
+            nevent_min: 16,
+            nevent_max: 1024,
+            timeout_min: Duration::from_millis(1),
+            timeout_max: Duration::from_millis(1000),
+            wakes: 0,
+            events: 0,
+            full: 0,
+            idle: 0,
+            window_start: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn autotune_peak_and_trough() {
+        let mut autotune = autotune();
+        let mut nevent = 256;
+        let mut timeout = Duration::from_millis(100);
+        let start = autotune.window_start;
+
+        // at the peak every poll fills the buffer, so it grows and the
+        // timeout shortens
+        for _ in 0..100 {
+            autotune.record(nevent, &mut nevent, &mut timeout, start);
+        }
+        assert_eq!((nevent, timeout), (256, Duration::from_millis(100)));
+        autotune.record(nevent, &mut nevent, &mut timeout, start + WINDOW);
+        assert_eq!((nevent, timeout), (512, Duration::from_millis(50)));
+
+        // at the trough most wakeups are timeouts, so the buffer shrinks and
+        // the timeout lengthens
+        let mut now = start + WINDOW;
+        for _ in 0..20 {
+            now += WINDOW;
+            for _ in 0..100 {
+                autotune.record(0, &mut nevent, &mut timeout, now - WINDOW / 2);
+            }
+            autotune.record(1, &mut nevent, &mut timeout, now);
+        }
+        assert_eq!((nevent, timeout), (16, Duration::from_millis(1000)));
+    }
+
+    #[test]
+    fn autotune_needs_enough_wakes() {
+        let mut autotune = autotune();
+        let mut nevent = 256;
+        let mut timeout = Duration::from_millis(100);
+        let start = autotune.window_start;
+
+        for _ in 0..5 {
+            autotune.record(0, &mut nevent, &mut timeout, start);
+        }
+        autotune.record(0, &mut nevent, &mut timeout, start + WINDOW);
+        assert_eq!((nevent, timeout), (256, Duration::from_millis(100)));
+        assert_eq!(autotune.wakes, 0);
+    }
+
+    #[test]
+    fn autotune_long_timeout() {
+        let mut autotune = autotune();
+        let mut nevent = 256;
+        let mut timeout = Duration::from_millis(500);
+        let start = autotune.window_start;
+
+        // an idle thread wakes only on its timeout, so the window lasts for
+        // enough timeouts to tune it
+        let interval = timeout;
+        for i in 1..10 {
+            autotune.record(0, &mut nevent, &mut timeout, start + interval * i);
+        }
+        assert_eq!((nevent, timeout), (256, Duration::from_millis(500)));
+        autotune.record(0, &mut nevent, &mut timeout, start + interval * 10);
+        assert_eq!((nevent, timeout), (128, Duration::from_millis(1000)));
+    }
+
+    #[test]
+    fn autotune_disable() {
+        let mut autotune = Some(autotune());
+        Autotune::disable(&mut autotune);
+        assert!(autotune.is_none());
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+use crossbeam_channel::{Receiver, TrySendError};
+
+counter!(
+    STORAGE_CLIENT_REQUEST,
+    "the number of requests executed for front ends outside of the event loops"
+);
+counter!(
+    STORAGE_CLIENT_FULL,
+    "the number of requests from outside of the event loops rejected because the queue was full"
+);
+
+/// A request from outside of the event loops, with the callback which
+/// receives its response.
+type ClientRequest<Request, Response> = (Request, Box<dyn FnOnce(Response) + Send>);
+
+/// Submits requests to the thread which owns the storage from outside of the
+/// event loops, such as from a front end which runs on its own runtime. These
+/// requests share the storage with the requests from the data port, so they
+/// see the same items and are subject to the same flushes and expiry.
+pub struct StorageClient<Request, Response> {
+    sender: Sender<ClientRequest<Request, Response>>,
+    waker: Arc<Waker>,
+}
+
+impl<Request, Response> Clone for StorageClient<Request, Response> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+}
+
+impl<Request, Response> StorageClient<Request, Response> {
+    /// Queues the request for the storage thread, which calls `reply` with
+    /// the response once it has been executed. The request is returned if the
+    /// queue is full or the storage thread has stopped.
+    pub fn send<F>(&self, request: Request, reply: F) -> std::result::Result<(), Request>
+    where
+        F: 'static + FnOnce(Response) + Send,
+    {
+        match self.sender.try_send((request, Box::new(reply))) {
+            Ok(()) => {
+                let _ = self.waker.wake();
+                Ok(())
+            }
+            Err(TrySendError::Full((request, _))) => {
+                STORAGE_CLIENT_FULL.increment();
+                Err(request)
+            }
+            Err(TrySendError::Disconnected((request, _))) => Err(request),
+        }
+    }
+}
+
+/// The receiving half of the `StorageClient`s, which is held by the thread
+/// which owns the storage.
+pub struct ClientQueue<Request, Response> {
+    receiver: Receiver<ClientRequest<Request, Response>>,
+    sender: Sender<ClientRequest<Request, Response>>,
+}
+
+impl<Request, Response> Default for ClientQueue<Request, Response> {
+    fn default() -> Self {
+        let (sender, receiver) = bounded(QUEUE_CAPACITY);
+        Self { receiver, sender }
+    }
+}
+
+impl<Request, Response> ClientQueue<Request, Response> {
+    /// Returns a client which wakes the thread with the given waker.
+    pub fn client(&self, waker: Arc<Waker>) -> StorageClient<Request, Response> {
+        StorageClient {
+            sender: self.sender.clone(),
+            waker,
+        }
+    }
+
+    /// Executes the queued requests, passing each response to its callback.
+    /// The keys which are stored are first added to the miss filter, if any,
+    /// so that the workers never answer a read of one as a miss.
+    pub fn execute<Storage>(&self, storage: &mut Storage, miss_filter: Option<&MissFilter>)
+    where
+        Storage: Execute<Request, Response>,
+        Request: Klog<Response = Response> + Lookup<Response>,
+    {
+        while let Ok((request, reply)) = self.receiver.try_recv() {
+            STORAGE_CLIENT_REQUEST.increment();
+            PROCESS_REQ.increment();
+            if let Some(miss_filter) = miss_filter {
+                request.stores(&mut |key| miss_filter.insert(key));
+            }
+            let response = storage.execute(&request);
+            request.klog(&response);
+            reply(response);
+        }
+    }
+}
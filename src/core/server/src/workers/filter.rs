@@ -0,0 +1,329 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A bloom filter of the keys which may be stored, which lets worker threads
+//! answer reads of keys that are definitely not stored without queueing them
+//! for the storage thread. This helps workloads where many reads miss on a
+//! stable set of keys.
+//!
+//! The storage thread adds the keys of each write to the filter before it is
+//! executed, so a key which is stored is never missing from it. Keys are only
+//! dropped when the storage thread rebuilds the filter from the keys in
+//! storage, which it does periodically. A rebuild fills a second filter a
+//! step at a time, so that the storage thread is not stalled by listing every
+//! key at once, and the second filter replaces the first once it is complete.
+//! Workers consult the filter once the first rebuild is complete.
+//!
+//! Reads which are answered by the filter do not reach storage, so they are
+//! not sampled for namespace stats and are not shed while storage warms up.
+
+use super::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::sync::atomic::AtomicU64;
+use std::time::Instant;
+
+counter!(
+    MISS_FILTER_SHORT_CIRCUIT,
+    "the number of reads answered as misses by the miss filter without reaching storage"
+);
+counter!(
+    MISS_FILTER_NEGATIVE,
+    "the number of keys read which were not found and which the miss filter showed were not stored"
+);
+counter!(
+    MISS_FILTER_FALSE_POSITIVE,
+    "the number of keys read which were not found but which the miss filter showed may be stored"
+);
+gauge!(
+    MISS_FILTER_FALSE_POSITIVE_PPM,
+    "the false positive rate of the miss filter between its last two rebuilds, in parts per million"
+);
+gauge!(
+    MISS_FILTER_FILL,
+    "the percentage of bits set in the miss filter when it was last rebuilt"
+);
+counter!(
+    MISS_FILTER_REBUILD,
+    "the number of times the miss filter was rebuilt from the keys in storage"
+);
+
+// the number of bits set for each key
+const HASHES: u64 = 4;
+
+pub struct MissFilter {
+    bits: Box<[AtomicU64]>,
+    // the filter which is being rebuilt, and which replaces `bits` once it
+    // holds every stored key
+    next: Box<[AtomicU64]>,
+    mask: u64,
+    ready: AtomicBool,
+}
+
+impl MissFilter {
+    /// Creates a filter which is at least the given number of bytes, or
+    /// returns `None` if the size is zero.
+    pub fn new(bytes: usize) -> Option<Self> {
+        if bytes == 0 {
+            return None;
+        }
+
+        let words = (bytes / 8).max(1).next_power_of_two();
+        let bits = (0..words).map(|_| AtomicU64::new(0)).collect();
+        let next = (0..words).map(|_| AtomicU64::new(0)).collect();
+
+        Some(Self {
+            bits,
+            next,
+            mask: (words * 64 - 1) as u64,
+            ready: AtomicBool::new(false),
+        })
+    }
+
+    // the bit positions for the key, using double hashing of a single hash
+    fn positions(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(key);
+        let hash = hasher.finish();
+
+        let h1 = hash & 0xFFFF_FFFF;
+        let h2 = (hash >> 32) | 1;
+        let mask = self.mask;
+
+        (0..HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) & mask) as usize)
+    }
+
+    /// Adds the key to the filter. This must happen before the key is stored.
+    /// The key is also added to the filter which is being rebuilt, as it may
+    /// be stored in a part of storage which the rebuild has already visited.
+    pub fn insert(&self, key: &[u8]) {
+        for position in self.positions(key) {
+            self.bits[position / 64].fetch_or(1 << (position % 64), Ordering::Relaxed);
+            self.next[position / 64].fetch_or(1 << (position % 64), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns false if the key is definitely not stored.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.positions(key).all(|position| {
+            self.bits[position / 64].load(Ordering::Relaxed) & (1 << (position % 64)) != 0
+        })
+    }
+
+    // clears the filter which is rebuilt, to start a rebuild
+    fn clear_next(&self) {
+        for word in self.next.iter() {
+            word.store(0, Ordering::Relaxed);
+        }
+    }
+
+    // adds keys which are stored to the filter which is rebuilt
+    fn extend_next(&self, keys: &[Box<[u8]>]) {
+        for key in keys {
+            for position in self.positions(key) {
+                self.next[position / 64].fetch_or(1 << (position % 64), Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Replaces the contents of the filter with the filter which was rebuilt,
+    /// and returns the number of bits which are set. Only the storage thread
+    /// changes the filter, so no key is stored while the words are replaced,
+    /// and a key which is in both the old and the new filter is never missing
+    /// while they are.
+    fn swap(&self) -> u64 {
+        let mut set = 0;
+        for (word, next) in self.bits.iter().zip(self.next.iter()) {
+            let bits = next.load(Ordering::Relaxed);
+            word.store(bits, Ordering::Relaxed);
+            set += bits.count_ones() as u64;
+        }
+
+        self.ready.store(true, Ordering::Release);
+        set
+    }
+
+    fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// Returns the response to a read of keys which are all definitely not
+    /// stored.
+    pub fn answer<Request, Response>(&self, request: &Request) -> Option<Response>
+    where
+        Request: Lookup<Response>,
+    {
+        if !self.is_ready() {
+            return None;
+        }
+
+        let keys = request.reads();
+        if keys.is_empty() || keys.iter().any(|key| self.contains(key)) {
+            return None;
+        }
+
+        let response = request.miss()?;
+        MISS_FILTER_SHORT_CIRCUIT.increment();
+        MISS_FILTER_NEGATIVE.add(keys.len() as _);
+        Some(response)
+    }
+
+    /// Counts the keys which storage did not find, according to whether the
+    /// filter could have answered for them.
+    pub fn record<Request, Response>(&self, request: &Request, response: &Response)
+    where
+        Request: Lookup<Response>,
+    {
+        if !self.is_ready() {
+            return;
+        }
+
+        let mut negative = 0;
+        let mut false_positive = 0;
+        request.misses(response, &mut |key| {
+            if self.contains(key) {
+                false_positive += 1;
+            } else {
+                negative += 1;
+            }
+        });
+
+        if negative > 0 {
+            MISS_FILTER_NEGATIVE.add(negative);
+        }
+        if false_positive > 0 {
+            MISS_FILTER_FALSE_POSITIVE.add(false_positive);
+        }
+    }
+}
+
+/// Rebuilds the filter on the storage thread at an interval. The first
+/// rebuild starts when the storage thread starts. Each rebuild visits the keys
+/// in storage over many iterations of the event loop, a bounded number at a
+/// time.
+pub struct Rebuild {
+    filter: Arc<MissFilter>,
+    interval: Duration,
+    next: Instant,
+    // the position of the rebuild in progress in the keys in storage
+    cursor: Option<usize>,
+    negative: u64,
+    false_positive: u64,
+}
+
+impl Rebuild {
+    pub fn new(filter: Arc<MissFilter>, interval: Duration) -> Self {
+        Self {
+            filter,
+            interval,
+            next: Instant::now(),
+            cursor: None,
+            negative: 0,
+            false_positive: 0,
+        }
+    }
+
+    pub fn filter(&self) -> &MissFilter {
+        &self.filter
+    }
+
+    /// Takes the next step of the rebuild in progress, or starts a rebuild if
+    /// one is due. Storage which cannot list its keys leaves the filter
+    /// unused.
+    pub fn maybe_rebuild<Storage: EntryStore>(&mut self, storage: &mut Storage, now: Instant) {
+        let cursor = match self.cursor {
+            Some(cursor) => cursor,
+            None if now >= self.next => {
+                self.next = now + self.interval;
+                self.filter.clear_next();
+                0
+            }
+            None => {
+                return;
+            }
+        };
+
+        let (keys, cursor) = match storage.scan_keys(cursor) {
+            Some(step) => step,
+            None => {
+                self.cursor = None;
+                return;
+            }
+        };
+        self.filter.extend_next(&keys);
+
+        if cursor != 0 {
+            self.cursor = Some(cursor);
+            return;
+        }
+        self.cursor = None;
+
+        let set = self.filter.swap();
+        MISS_FILTER_REBUILD.increment();
+        MISS_FILTER_FILL.set((set * 100 / (self.filter.mask + 1)) as _);
+
+        // the rate is the share of keys which were not stored which the
+        // filter failed to identify
+        let negative = MISS_FILTER_NEGATIVE.value();
+        let false_positive = MISS_FILTER_FALSE_POSITIVE.value();
+        let n = negative.wrapping_sub(self.negative);
+        let fp = false_positive.wrapping_sub(self.false_positive);
+        if n + fp > 0 {
+            MISS_FILTER_FALSE_POSITIVE_PPM.set((fp * 1_000_000 / (n + fp)) as _);
+        }
+        self.negative = negative;
+        self.false_positive = false_positive;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // rebuilds the filter with the given keys at once
+    fn rebuild(filter: &MissFilter, keys: &[Box<[u8]>]) {
+        filter.clear_next();
+        filter.extend_next(keys);
+        filter.swap();
+    }
+
+    #[test]
+    fn filter() {
+        assert!(MissFilter::new(0).is_none());
+
+        let filter = MissFilter::new(1024).unwrap();
+        assert!(!filter.is_ready());
+
+        let keys: Vec<Box<[u8]>> = (0..100)
+            .map(|i| format!("key{}", i).into_bytes().into_boxed_slice())
+            .collect();
+        rebuild(&filter, &keys);
+        assert!(filter.is_ready());
+        assert!(keys.iter().all(|key| filter.contains(key)));
+
+        filter.insert(b"added");
+        assert!(filter.contains(b"added"));
+
+        // with 80 bits per key, false positives are rare
+        let false_positives = (0..1000)
+            .filter(|i| filter.contains(format!("missing{}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 10, "false positives: {}", false_positives);
+
+        // keys which are no longer stored are dropped by a rebuild
+        rebuild(&filter, &keys[..1]);
+        assert!(filter.contains(&keys[0]));
+        assert!(!filter.contains(b"added"));
+
+        // keys which are stored while a rebuild is in progress are kept
+        filter.clear_next();
+        filter.extend_next(&keys[..1]);
+        filter.insert(b"during");
+        filter.extend_next(&keys[1..2]);
+        filter.swap();
+        assert!(filter.contains(&keys[0]));
+        assert!(filter.contains(&keys[1]));
+        assert!(filter.contains(b"during"));
+    }
+}
@@ -3,12 +3,21 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use crate::*;
+use common::events::Event;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::JoinHandle;
 
+mod autotune;
+mod client;
+mod filter;
 mod multi;
 mod single;
 mod storage;
 
+use autotune::*;
+pub use client::StorageClient;
+use client::*;
+use filter::*;
 use multi::*;
 use single::*;
 use storage::*;
@@ -30,6 +39,58 @@ counter!(
 counter!(WORKER_EVENT_READ, "the number of read events received");
 counter!(WORKER_EVENT_TOTAL, "the total number of events received");
 counter!(WORKER_EVENT_WRITE, "the number of write events received");
+counter!(
+    PROTOCOL_ERROR_RESPONSE,
+    "the number of times invalid input received an error response"
+);
+counter!(
+    PROTOCOL_ERROR_CLOSE,
+    "the number of sessions closed due to invalid input"
+);
+counter!(
+    PROTOCOL_MISMATCH,
+    "the number of sessions closed because the client spoke another protocol"
+);
+counter!(
+    WORKER_SESSION_KILL,
+    "the number of sessions closed by an admin command"
+);
+
+/// Accompanies a request which is queued for the storage thread. Reads from
+/// sessions which have since closed are skipped, and requests which have
+/// waited past their deadline are rejected, so that the storage thread does not
+/// spend time on responses which no client will use.
+pub struct Ticket {
+    token: Token,
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<std::time::Instant>,
+}
+
+impl Ticket {
+    fn new(token: Token, cancelled: Arc<AtomicBool>, deadline: Option<Duration>) -> Self {
+        Self {
+            token,
+            cancelled,
+            deadline: deadline.map(|d| std::time::Instant::now() + d),
+        }
+    }
+
+    /// The token of the session which queued the request.
+    fn token(&self) -> Token {
+        self.token
+    }
+
+    /// Returns true if the session has closed since the request was queued.
+    /// Its token may now belong to a different session.
+    fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the deadline for the request has passed.
+    fn expired(&self, now: std::time::Instant) -> bool {
+        self.deadline.map(|d| now >= d).unwrap_or(false)
+    }
+}
 
 fn map_result(result: Result<usize>) -> Result<()> {
     match result {
@@ -39,20 +100,172 @@ fn map_result(result: Result<usize>) -> Result<()> {
     }
 }
 
+/// Describe a session for the admin `sessions list` command.
+fn session_info<Parser, Request, Response>(
+    session: &ServerSession<Parser, Response, Request>,
+) -> SessionInfo
+where
+    Parser: Parse<Request>,
+    Response: Compose,
+{
+    SessionInfo {
+        id: session.id(),
+        peer_addr: session.peer_addr(),
+        age: session.age(),
+        bytes_read: session.bytes_read(),
+        bytes_written: session.bytes_written(),
+        read_pending: session.remaining(),
+        write_pending: session.write_pending(),
+    }
+}
+
+/// Returns the key of the session with the given id.
+fn find_session<Parser, Request, Response>(
+    sessions: &Slab<ServerSession<Parser, Response, Request>>,
+    id: u64,
+) -> Option<usize>
+where
+    Parser: Parse<Request>,
+    Response: Compose,
+{
+    sessions
+        .iter()
+        .find(|(_, session)| session.id() == id)
+        .map(|(key, _)| key)
+}
+
+/// Returns the sessions whose responses were held back by the shaping and are
+/// now due to be flushed. No event is received for these, as the socket was
+/// writable all along.
+#[cfg(feature = "shaping")]
+fn shaped_due<Parser, Request, Response>(
+    sessions: &Slab<ServerSession<Parser, Response, Request>>,
+) -> Vec<Token>
+where
+    Parser: Parse<Request>,
+    Response: Compose,
+{
+    let now = std::time::Instant::now();
+    sessions
+        .iter()
+        .filter(|(_, session)| session.shaped_until().map_or(false, |until| until <= now))
+        .map(|(key, _)| Token(key))
+        .collect()
+}
+
+/// Returns the poll timeout, shortened so that the event loop wakes when the
+/// next response which is held back by the shaping is due.
+#[cfg(feature = "shaping")]
+fn shaped_timeout<Parser, Request, Response>(
+    sessions: &Slab<ServerSession<Parser, Response, Request>>,
+    timeout: Duration,
+) -> Duration
+where
+    Parser: Parse<Request>,
+    Response: Compose,
+{
+    let now = std::time::Instant::now();
+    sessions
+        .iter()
+        .filter_map(|(_, session)| session.shaped_until())
+        .map(|until| until.saturating_duration_since(now))
+        .fold(timeout, Duration::min)
+}
+
+/// Read more data into a session whose buffer holds no complete request.
+/// Returns `false` if no more data is available without blocking, and an error
+/// if the client has hung up.
+fn refill<Parser, Request, Response>(
+    session: &mut ServerSession<Parser, Response, Request>,
+) -> Result<bool>
+where
+    Parser: Parse<Request>,
+    Response: Compose,
+{
+    match session.fill() {
+        Ok(0) => Err(Error::new(ErrorKind::Other, "client hangup")),
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Write the access log line for a request which is about to be responded to.
+/// Must be called before the response is sent, while the request is still the
+/// oldest pending request of the session.
+fn access_log<Parser, Request, Response>(
+    session: &ServerSession<Parser, Response, Request>,
+    request: &Request,
+    response: &Response,
+) where
+    Request: Klog<Response = Response>,
+{
+    if let Some(access) = request.access(response) {
+        let peer = session
+            .peer_addr()
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let latency = session.request_elapsed().unwrap_or(0) / 1000;
+        alog!(
+            "{} {} {} {} {}",
+            peer,
+            access.verb,
+            access.key_len,
+            access.status,
+            latency
+        );
+    }
+}
+
+/// Handle input which could not be parsed according to the configured policy.
+/// Returns an error if the session should be closed.
+fn protocol_error<Parser, Request, Response>(
+    session: &mut ServerSession<Parser, Response, Request>,
+    policy: ProtocolErrorPolicy,
+    e: Error,
+) -> Result<()>
+where
+    Parser: Parse<Request>,
+    Response: Compose,
+{
+    if let Some(mismatch) = session.mismatch() {
+        PROTOCOL_MISMATCH.increment();
+        match session.peer_addr() {
+            Some(peer) => warn!("protocol mismatch from {}: {}", peer, mismatch),
+            None => warn!("protocol mismatch: {}", mismatch),
+        }
+        return Err(Error::new(ErrorKind::InvalidInput, "protocol mismatch"));
+    }
+
+    if policy == ProtocolErrorPolicy::Error && session.resync().is_ok() {
+        PROTOCOL_ERROR_RESPONSE.increment();
+        Ok(())
+    } else {
+        PROTOCOL_ERROR_CLOSE.increment();
+        Err(e)
+    }
+}
+
 pub enum Workers<Parser, Request, Response, Storage> {
     Single {
         worker: SingleWorker<Parser, Request, Response, Storage>,
     },
     Multi {
         workers: Vec<MultiWorker<Parser, Request, Response>>,
-        storage: StorageWorker<Request, Response, Storage, Token>,
+        storage: StorageWorker<Request, Response, Storage>,
     },
 }
 
 impl<Parser, Request, Response, Storage> Workers<Parser, Request, Response, Storage>
 where
     Parser: 'static + Parse<Request> + Clone + Send,
-    Request: 'static + Klog + Klog<Response = Response> + Send,
+    Request: 'static
+        + Klog
+        + Klog<Response = Response>
+        + CountSession<Response>
+        + Lookup<Response>
+        + RecordLatency
+        + Send,
     Response: 'static + Compose + Send,
     Storage: 'static + EntryStore + Execute<Request, Response> + Send,
 {
@@ -104,18 +317,29 @@ where
     Response: Compose,
     Storage: Execute<Request, Response> + EntryStore,
 {
-    pub fn new<T: WorkerConfig>(config: &T, parser: Parser, storage: Storage) -> Result<Self> {
+    pub fn new<T: ServerConfig + WorkerConfig>(
+        config: &T,
+        parser: Parser,
+        storage: Storage,
+    ) -> Result<Self> {
         let threads = config.worker().threads();
 
         if threads > 1 {
+            // the filter is shared by the storage thread, which keeps it up to
+            // date, and the workers, which answer reads with it
+            let miss_filter = MissFilter::new(config.worker().miss_filter_size()).map(Arc::new);
+
             let mut workers = vec![];
             for _ in 0..threads {
-                workers.push(MultiWorkerBuilder::new(config, parser.clone())?)
+                workers.push(
+                    MultiWorkerBuilder::new(config, parser.clone())?
+                        .miss_filter(miss_filter.clone()),
+                )
             }
 
             Ok(Self::Multi {
                 workers,
-                storage: StorageWorkerBuilder::new(config, storage)?,
+                storage: StorageWorkerBuilder::new(config, storage)?.miss_filter(miss_filter),
             })
         } else {
             Ok(Self::Single {
@@ -136,6 +360,18 @@ where
         }
     }
 
+    /// Returns a client which submits requests to the thread which owns the
+    /// storage from outside of the event loops.
+    pub fn storage_client(&self) -> StorageClient<Request, Response> {
+        match self {
+            Self::Single { worker } => worker.client(),
+            Self::Multi {
+                workers: _,
+                storage,
+            } => storage.client(),
+        }
+    }
+
     pub fn wakers(&self) -> Vec<Arc<Waker>> {
         match self {
             Self::Single { worker } => {
@@ -154,7 +390,7 @@ where
     pub fn build(
         self,
         session_queues: Vec<Queues<Session, Session>>,
-        signal_queues: Vec<Queues<(), Signal>>,
+        signal_queues: Vec<Queues<Reply, Signal>>,
     ) -> Workers<Parser, Request, Response, Storage> {
         let mut signal_queues = signal_queues;
         let mut session_queues = session_queues;
@@ -3,34 +3,67 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use super::*;
+use std::collections::{HashMap, VecDeque};
+
+counter!(
+    STORAGE_QUEUE_SHED,
+    "the number of requests rejected because the storage queue was full"
+);
 
 pub struct MultiWorkerBuilder<Parser, Request, Response> {
+    autotune: Option<Autotune>,
+    compose_limit: usize,
+    listener: Arc<ListenerStats>,
+    miss_filter: Option<Arc<MissFilter>>,
     nevent: usize,
     parser: Parser,
     poll: Poll,
+    protocol_error: ProtocolErrorPolicy,
     sessions: Slab<ServerSession<Parser, Response, Request>>,
+    storage_queue_depth: usize,
+    storage_deadline: Option<Duration>,
     timeout: Duration,
     waker: Arc<Waker>,
 }
 
 impl<Parser, Request, Response> MultiWorkerBuilder<Parser, Request, Response> {
-    pub fn new<T: WorkerConfig>(config: &T, parser: Parser) -> Result<Self> {
+    pub fn new<T: ServerConfig + WorkerConfig>(config: &T, parser: Parser) -> Result<Self> {
+        let listener = common::listener::register(&config.server().name());
         let config = config.worker();
 
         let poll = Poll::new()?;
 
-        let waker = Arc::new(Waker::from(
-            ::net::Waker::new(poll.registry(), WAKER_TOKEN).unwrap(),
-        ));
+        let waker = Arc::new(
+            Waker::from(::net::Waker::new(poll.registry(), WAKER_TOKEN).unwrap())
+                .coalesce(config.wake_strategy() == WakeStrategy::Coalesce),
+        );
 
         let nevent = config.nevent();
         let timeout = Duration::from_millis(config.timeout() as u64);
+        let protocol_error = config.protocol_error();
+        let compose_limit = config.compose_limit();
+        let autotune = Autotune::new(config);
+        let storage_queue_depth = match config.storage_queue_depth() {
+            0 => usize::MAX,
+            depth => depth,
+        };
+        let storage_deadline = match config.storage_deadline() {
+            0 => None,
+            deadline => Some(Duration::from_millis(deadline as u64)),
+        };
 
         Ok(Self {
+            autotune,
+            compose_limit,
+            listener,
+            miss_filter: None,
             nevent,
             parser,
             poll,
+            protocol_error,
             sessions: Slab::new(),
+            storage_queue_depth,
+            storage_deadline,
             timeout,
             waker,
         })
@@ -40,20 +73,37 @@ impl<Parser, Request, Response> MultiWorkerBuilder<Parser, Request, Response> {
         self.waker.clone()
     }
 
+    /// Answer reads of keys which are not in the filter without queueing them
+    /// for the storage thread.
+    pub fn miss_filter(mut self, filter: Option<Arc<MissFilter>>) -> Self {
+        self.miss_filter = filter;
+        self
+    }
+
     pub fn build(
         self,
-        data_queue: Queues<(Request, Token), (Request, Response, Token)>,
+        data_queue: Queues<(Request, Ticket), (Request, Option<Response>, Ticket)>,
         session_queue: Queues<Session, Session>,
-        signal_queue: Queues<(), Signal>,
+        signal_queue: Queues<Reply, Signal>,
     ) -> MultiWorker<Parser, Request, Response> {
         MultiWorker {
+            autotune: self.autotune,
+            cancellations: HashMap::new(),
             data_queue,
+            compose_limit: self.compose_limit,
+            listener: self.listener,
+            miss_filter: self.miss_filter,
             nevent: self.nevent,
             parser: self.parser,
+            pending: VecDeque::new(),
             poll: self.poll,
+            protocol_error: self.protocol_error,
             session_queue,
             sessions: self.sessions,
             signal_queue,
+            stalled: HashMap::new(),
+            storage_queue_depth: self.storage_queue_depth,
+            storage_deadline: self.storage_deadline,
             timeout: self.timeout,
             waker: self.waker,
         }
@@ -61,13 +111,29 @@ impl<Parser, Request, Response> MultiWorkerBuilder<Parser, Request, Response> {
 }
 
 pub struct MultiWorker<Parser, Request, Response> {
-    data_queue: Queues<(Request, Token), (Request, Response, Token)>,
+    autotune: Option<Autotune>,
+    // set when a session closes, so that its queued requests are skipped
+    cancellations: HashMap<Token, Arc<AtomicBool>>,
+    data_queue: Queues<(Request, Ticket), (Request, Option<Response>, Ticket)>,
+    compose_limit: usize,
+    listener: Arc<ListenerStats>,
+    miss_filter: Option<Arc<MissFilter>>,
     nevent: usize,
     parser: Parser,
+    // sessions which stopped reading because the queue to the storage thread
+    // was full, and which resume once it has room
+    pending: VecDeque<Token>,
     poll: Poll,
+    protocol_error: ProtocolErrorPolicy,
     session_queue: Queues<Session, Session>,
     sessions: Slab<ServerSession<Parser, Response, Request>>,
-    signal_queue: Queues<(), Signal>,
+    signal_queue: Queues<Reply, Signal>,
+    // a request which could not be queued for the storage thread because
+    // another worker filled the queue first. it is sent ahead of any later
+    // request from the same session
+    stalled: HashMap<Token, (Request, Ticket)>,
+    storage_queue_depth: usize,
+    storage_deadline: Option<Duration>,
     timeout: Duration,
     waker: Arc<Waker>,
 }
@@ -75,37 +141,166 @@ pub struct MultiWorker<Parser, Request, Response> {
 impl<Parser, Request, Response> MultiWorker<Parser, Request, Response>
 where
     Parser: Parse<Request> + Clone,
-    Request: Klog + Klog<Response = Response>,
+    Request: Klog
+        + Klog<Response = Response>
+        + CountSession<Response>
+        + Lookup<Response>
+        + RecordLatency,
     Response: Compose,
 {
     /// Return the `Session` to the `Listener` to handle flush/close
     fn close(&mut self, token: Token) {
         if self.sessions.contains(token.0) {
-            let mut session = self.sessions.remove(token.0).into_inner();
+            if let Some(cancelled) = self.cancellations.remove(&token) {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+            self.stalled.remove(&token);
+
+            let session = self.sessions.remove(token.0);
+            common::events::publish(Event::SessionClosed { id: session.id() });
+
+            let mut session = session.into_inner();
             let _ = session.deregister(self.poll.registry());
             let _ = self.session_queue.try_send_any(session);
             let _ = self.session_queue.wake();
         }
     }
 
-    /// Handle up to one request for a session
+    /// Flushes the responses which are still buffered for each session, so
+    /// that they are not lost when the worker stops. Sessions which are not
+    /// writable are not waited on.
+    fn drain(&mut self) {
+        for (_, session) in self.sessions.iter_mut() {
+            let _ = session.flush();
+        }
+    }
+
+    /// Describes each of the sessions handled by this worker
+    fn session_infos(&self) -> Vec<SessionInfo> {
+        self.sessions
+            .iter()
+            .map(|(_, session)| session_info(session))
+            .collect()
+    }
+
+    /// Closes the session with the given id if it is handled by this worker.
+    /// Returns true if the session was found.
+    fn kill(&mut self, id: u64) -> bool {
+        match find_session(&self.sessions, id) {
+            Some(key) => {
+                WORKER_SESSION_KILL.increment();
+                self.close(Token(key));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Handle all requests which are available for a session
     fn read(&mut self, token: Token) -> Result<()> {
         let session = self
             .sessions
             .get_mut(token.0)
             .ok_or_else(|| Error::new(ErrorKind::Other, "non-existant session"))?;
 
+        let cancelled = self
+            .cancellations
+            .get(&token)
+            .ok_or_else(|| Error::new(ErrorKind::Other, "non-existant session"))?;
+
         // fill the session
         map_result(session.fill())?;
 
-        // process up to one request
-        match session.receive() {
-            Ok(request) => self
-                .data_queue
-                .try_send_to(0, (request, token))
-                .map_err(|_| Error::new(ErrorKind::Other, "data queue is full")),
-            Err(e) => map_err(e),
+        // a request which could not be queued earlier goes first
+        if let Some(stalled) = self.stalled.remove(&token) {
+            if let Err(stalled) = self.data_queue.try_send_to(0, stalled) {
+                self.stalled.insert(token, stalled);
+                self.pending.push_back(token);
+                return Ok(());
+            }
         }
+
+        // send each complete request in the buffer to the storage thread,
+        // skipping over any invalid input if the policy allows the session to
+        // continue. responses are returned in order, so the entire pipeline
+        // which is available on the socket is drained by a single event
+        loop {
+            // stop reading once the queue to the storage thread is full. the
+            // rest of the input stays buffered, and the session resumes once
+            // the storage thread has caught up
+            if self.data_queue.is_full_to(0) {
+                self.pending.push_back(token);
+                break;
+            }
+
+            // when the storage queue is too deep, requests are rejected with a
+            // busy response. responses must remain in order, so a session with
+            // requests queued for storage stops reading until they complete
+            let shed = self.data_queue.pending_to(0) >= self.storage_queue_depth;
+            if shed && session.pending_requests() > 0 {
+                break;
+            }
+
+            match session.receive() {
+                Ok(_) if shed => {
+                    STORAGE_QUEUE_SHED.increment();
+                    session.reject()?;
+                }
+                Ok(request) => {
+                    // a read of keys which are not stored is answered here,
+                    // unless a response to an earlier request is pending
+                    if session.pending_requests() == 1 {
+                        if let Some(response) =
+                            self.miss_filter.as_ref().and_then(|f| f.answer(&request))
+                        {
+                            request.count(&response, session.counters_mut());
+                            request.klog(&response);
+                            if logger::access_log_enabled() {
+                                access_log(session, &request, &response);
+                            }
+                            if let Some(latency) = session.request_elapsed() {
+                                request.record_latency(latency);
+                            }
+                            session.send(response)?;
+                            continue;
+                        }
+                    }
+
+                    let ticket = Ticket::new(token, cancelled.clone(), self.storage_deadline);
+                    if let Err(stalled) = self.data_queue.try_send_to(0, (request, ticket)) {
+                        self.stalled.insert(token, stalled);
+                        self.pending.push_back(token);
+                        break;
+                    }
+                }
+                Err(e) => {
+                    if e.kind() != ErrorKind::WouldBlock {
+                        protocol_error(session, self.protocol_error, e)?;
+                        continue;
+                    }
+
+                    // requests which follow a partially composed response
+                    // are handled once it completes
+                    if session.is_composing() || !refill(session)? {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // flush any error or busy responses, reregistering if we can't write them all
+        if session.write_pending() > 0 {
+            if let Err(e) = session.flush() {
+                map_err(e)?;
+            }
+
+            if session.write_pending() > 0 {
+                let interest = session.interest();
+                session.reregister(self.poll.registry(), token, interest)?;
+            }
+        }
+
+        Ok(())
     }
 
     /// Handle write by flushing the session
@@ -118,21 +313,64 @@ where
         match session.flush() {
             Ok(_) => Ok(()),
             Err(e) => map_err(e),
+        }?;
+
+        // compose the next part of a large response now that the write buffer
+        // has drained, bounding the time spent on it in this iteration
+        if session.is_composing() {
+            session.compose_next();
+
+            if let Err(e) = session.flush() {
+                map_err(e)?;
+            }
+
+            let interest = session.interest();
+            session.reregister(self.poll.registry(), token, interest)?;
+
+            // once the response is complete, resume handling any requests
+            // which are already buffered
+            if !session.is_composing() && session.remaining() > 0 {
+                return self.read(token);
+            }
         }
+
+        Ok(())
     }
 
     /// Run the worker in a loop, handling new events.
     pub fn run(&mut self) {
         // these are buffers which are re-used in each loop iteration to receive
         // events and queue messages
-        let mut events = Events::with_capacity(self.nevent);
+        let mut nevent = self.nevent;
+        let mut events = Events::with_capacity(nevent);
         let mut messages = Vec::with_capacity(QUEUE_CAPACITY);
 
         loop {
             WORKER_EVENT_LOOP.increment();
+            common::rusage::sample();
+
+            // a reload may have changed the number of events per poll
+            if self.nevent != nevent {
+                nevent = self.nevent;
+                events = Events::with_capacity(nevent);
+            }
+
+            // responses held back by the shaping are flushed once they are
+            // due, and the poll returns in time for the next
+            #[cfg(feature = "shaping")]
+            let timeout = {
+                for token in shaped_due(&self.sessions) {
+                    if self.write(token).is_err() {
+                        self.close(token);
+                    }
+                }
+                shaped_timeout(&self.sessions, self.timeout)
+            };
+            #[cfg(not(feature = "shaping"))]
+            let timeout = self.timeout;
 
             // get events with timeout
-            if self.poll.poll(&mut events, Some(self.timeout)).is_err() {
+            if self.poll.poll(&mut events, Some(timeout)).is_err() {
                 error!("Error polling");
             }
 
@@ -146,6 +384,16 @@ where
                 WORKER_EVENT_DEPTH.increment(timestamp, count as _, 1);
             }
 
+            // the new settings apply from the next iteration
+            if let Some(autotune) = self.autotune.as_mut() {
+                autotune.record(
+                    count,
+                    &mut self.nevent,
+                    &mut self.timeout,
+                    std::time::Instant::now(),
+                );
+            }
+
             // process all events
             for event in events.iter() {
                 let token = event.token();
@@ -162,7 +410,14 @@ where
                                 .register(self.poll.registry(), Token(s.key()), interest)
                                 .is_ok()
                             {
-                                s.insert(ServerSession::new(session, self.parser.clone()));
+                                self.cancellations
+                                    .insert(Token(s.key()), Arc::new(AtomicBool::new(false)));
+                                let session = s.insert(
+                                    ServerSession::new(session, self.parser.clone())
+                                        .compose_limit(self.compose_limit)
+                                        .listener(self.listener.clone()),
+                                );
+                                common::events::publish(Event::SessionOpened { id: session.id() });
                             } else {
                                 let _ = self.session_queue.try_send_any(session);
                             }
@@ -173,18 +428,58 @@ where
 
                         // handle all pending messages on the data queue
                         self.data_queue.try_recv_all(&mut messages);
-                        for (request, response, token) in messages.drain(..).map(|v| v.into_inner())
+                        for (request, response, ticket) in
+                            messages.drain(..).map(|v| v.into_inner())
                         {
-                            request.klog(&response);
+                            if let Some(response) = &response {
+                                request.klog(response);
+                                if let Some(miss_filter) = &self.miss_filter {
+                                    miss_filter.record(&request, response);
+                                }
+                            }
+
+                            // the session closed while the request was queued
+                            // and its token may now belong to another session
+                            if ticket.cancelled() {
+                                continue;
+                            }
+
+                            let token = ticket.token();
                             if let Some(session) = self.sessions.get_mut(token.0) {
-                                if response.should_hangup() {
-                                    let _ = session.send(response);
-                                    self.close(token);
-                                    continue;
-                                } else if session.send(response).is_err() {
-                                    self.close(token);
-                                    continue;
-                                } else if session.write_pending() > 0 {
+                                match response {
+                                    Some(mut response) => {
+                                        // the counters of the session are
+                                        // only known to this thread
+                                        request.count(&response, session.counters_mut());
+                                        request.report(&mut response, &session.counters());
+                                        if logger::access_log_enabled() {
+                                            access_log(session, &request, &response);
+                                        }
+                                        // responses are returned in order, so
+                                        // the oldest pending request is this one
+                                        if let Some(latency) = session.request_elapsed() {
+                                            request.record_latency(latency);
+                                        }
+                                        if response.should_hangup() {
+                                            let _ = session.send(response);
+                                            self.close(token);
+                                            continue;
+                                        } else if session.send(response).is_err() {
+                                            self.close(token);
+                                            continue;
+                                        }
+                                    }
+                                    // the request waited past its deadline, so
+                                    // it is rejected as though storage is busy
+                                    None => {
+                                        if session.reject().is_err() {
+                                            self.close(token);
+                                            continue;
+                                        }
+                                    }
+                                }
+
+                                if session.write_pending() > 0 {
                                     // try to immediately flush, if we still
                                     // have pending bytes, reregister. This
                                     // saves us one syscall when flushing would
@@ -196,7 +491,7 @@ where
                                         }
                                     }
 
-                                    if session.write_pending() > 0 {
+                                    if session.write_pending() > 0 || session.is_composing() {
                                         let interest = session.interest();
                                         if session
                                             .reregister(self.poll.registry(), token, interest)
@@ -216,16 +511,45 @@ where
                         }
 
                         // check if we received any signals from the admin thread
-                        while let Some(signal) =
-                            self.signal_queue.try_recv().map(|v| v.into_inner())
-                        {
-                            match signal {
-                                Signal::FlushAll => {}
+                        while let Some(signal) = self.signal_queue.try_recv() {
+                            let sender = signal.sender();
+                            match signal.into_inner() {
+                                Signal::Reload(reload) => {
+                                    Autotune::disable(&mut self.autotune);
+                                    self.nevent = reload.worker_nevent;
+                                    self.timeout = reload.worker_timeout;
+                                }
+                                Signal::Tune(EventLoop::Worker, tunable) => {
+                                    Autotune::disable(&mut self.autotune);
+                                    match tunable {
+                                        Tunable::Nevent(nevent) => self.nevent = nevent,
+                                        Tunable::Timeout(timeout) => self.timeout = timeout,
+                                    }
+                                }
+                                Signal::ListSessions => {
+                                    let sessions = self.session_infos();
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Sessions(sessions));
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::KillSession(id) => {
+                                    let killed = self.kill(id);
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Killed(killed));
+                                    let _ = self.signal_queue.wake();
+                                }
                                 Signal::Shutdown => {
-                                    // if we received a shutdown, we can return
-                                    // and stop processing events
+                                    // if we received a shutdown, we flush what
+                                    // we can and stop processing events
+                                    self.drain();
                                     return;
                                 }
+                                signal => signal.reply_default(|reply| {
+                                    let _ = self.signal_queue.try_send_to(sender, reply);
+                                    let _ = self.signal_queue.wake();
+                                }),
                             }
                         }
                     }
@@ -258,6 +582,18 @@ where
                 }
             }
 
+            // sessions which stopped reading because the queue to the storage
+            // thread was full resume once it has room. the queue is shared
+            // with the other workers, so this is checked on every iteration
+            // rather than only when this worker receives responses
+            for _ in 0..self.pending.len() {
+                if let Some(token) = self.pending.pop_front() {
+                    if self.sessions.contains(token.0) && self.read(token).is_err() {
+                        self.close(token);
+                    }
+                }
+            }
+
             // wakes the storage thread if necessary
             let _ = self.data_queue.wake();
         }
@@ -6,10 +6,15 @@ use super::*;
 use std::collections::VecDeque;
 
 pub struct SingleWorkerBuilder<Parser, Request, Response, Storage> {
+    autotune: Option<Autotune>,
+    clients: ClientQueue<Request, Response>,
+    compose_limit: usize,
+    listener: Arc<ListenerStats>,
     nevent: usize,
     parser: Parser,
     pending: VecDeque<Token>,
     poll: Poll,
+    protocol_error: ProtocolErrorPolicy,
     sessions: Slab<ServerSession<Parser, Response, Request>>,
     storage: Storage,
     timeout: Duration,
@@ -17,23 +22,37 @@ pub struct SingleWorkerBuilder<Parser, Request, Response, Storage> {
 }
 
 impl<Parser, Request, Response, Storage> SingleWorkerBuilder<Parser, Request, Response, Storage> {
-    pub fn new<T: WorkerConfig>(config: &T, parser: Parser, storage: Storage) -> Result<Self> {
+    pub fn new<T: ServerConfig + WorkerConfig>(
+        config: &T,
+        parser: Parser,
+        storage: Storage,
+    ) -> Result<Self> {
+        let listener = common::listener::register(&config.server().name());
         let config = config.worker();
 
         let poll = Poll::new()?;
 
-        let waker = Arc::new(Waker::from(
-            ::net::Waker::new(poll.registry(), WAKER_TOKEN).unwrap(),
-        ));
+        let waker = Arc::new(
+            Waker::from(::net::Waker::new(poll.registry(), WAKER_TOKEN).unwrap())
+                .coalesce(config.wake_strategy() == WakeStrategy::Coalesce),
+        );
 
         let nevent = config.nevent();
         let timeout = Duration::from_millis(config.timeout() as u64);
+        let protocol_error = config.protocol_error();
+        let compose_limit = config.compose_limit();
+        let autotune = Autotune::new(config);
 
         Ok(Self {
+            autotune,
+            clients: ClientQueue::default(),
+            compose_limit,
+            listener,
             nevent,
             parser,
             pending: VecDeque::new(),
             poll,
+            protocol_error,
             sessions: Slab::new(),
             storage,
             timeout,
@@ -45,16 +64,27 @@ impl<Parser, Request, Response, Storage> SingleWorkerBuilder<Parser, Request, Re
         self.waker.clone()
     }
 
+    /// Returns a client which submits requests to this thread from outside
+    /// of the event loops.
+    pub fn client(&self) -> StorageClient<Request, Response> {
+        self.clients.client(self.waker.clone())
+    }
+
     pub fn build(
         self,
         session_queue: Queues<Session, Session>,
-        signal_queue: Queues<(), Signal>,
+        signal_queue: Queues<Reply, Signal>,
     ) -> SingleWorker<Parser, Request, Response, Storage> {
         SingleWorker {
+            autotune: self.autotune,
+            clients: self.clients,
+            compose_limit: self.compose_limit,
+            listener: self.listener,
             nevent: self.nevent,
             parser: self.parser,
             pending: self.pending,
             poll: self.poll,
+            protocol_error: self.protocol_error,
             session_queue,
             sessions: self.sessions,
             signal_queue,
@@ -66,13 +96,18 @@ impl<Parser, Request, Response, Storage> SingleWorkerBuilder<Parser, Request, Re
 }
 
 pub struct SingleWorker<Parser, Request, Response, Storage> {
+    autotune: Option<Autotune>,
+    clients: ClientQueue<Request, Response>,
+    compose_limit: usize,
+    listener: Arc<ListenerStats>,
     nevent: usize,
     parser: Parser,
     pending: VecDeque<Token>,
     poll: Poll,
+    protocol_error: ProtocolErrorPolicy,
     session_queue: Queues<Session, Session>,
     sessions: Slab<ServerSession<Parser, Response, Request>>,
-    signal_queue: Queues<(), Signal>,
+    signal_queue: Queues<Reply, Signal>,
     storage: Storage,
     timeout: Duration,
     waker: Arc<Waker>,
@@ -81,21 +116,54 @@ pub struct SingleWorker<Parser, Request, Response, Storage> {
 impl<Parser, Request, Response, Storage> SingleWorker<Parser, Request, Response, Storage>
 where
     Parser: Parse<Request> + Clone,
-    Request: Klog + Klog<Response = Response>,
+    Request: Klog + Klog<Response = Response> + CountSession<Response> + RecordLatency,
     Response: Compose,
     Storage: EntryStore + Execute<Request, Response>,
 {
     /// Return the `Session` to the `Listener` to handle flush/close
     fn close(&mut self, token: Token) {
         if self.sessions.contains(token.0) {
-            let mut session = self.sessions.remove(token.0).into_inner();
+            let session = self.sessions.remove(token.0);
+            common::events::publish(Event::SessionClosed { id: session.id() });
+
+            let mut session = session.into_inner();
             let _ = self.poll.registry().deregister(&mut session);
             let _ = self.session_queue.try_send_any(session);
             let _ = self.session_queue.wake();
         }
     }
 
-    /// Handle up to one request for a session
+    /// Flushes the responses which are still buffered for each session, so
+    /// that they are not lost when the worker stops. Sessions which are not
+    /// writable are not waited on.
+    fn drain(&mut self) {
+        for (_, session) in self.sessions.iter_mut() {
+            let _ = session.flush();
+        }
+    }
+
+    /// Describes each of the sessions handled by this worker
+    fn session_infos(&self) -> Vec<SessionInfo> {
+        self.sessions
+            .iter()
+            .map(|(_, session)| session_info(session))
+            .collect()
+    }
+
+    /// Closes the session with the given id if it is handled by this worker.
+    /// Returns true if the session was found.
+    fn kill(&mut self, id: u64) -> bool {
+        match find_session(&self.sessions, id) {
+            Some(key) => {
+                WORKER_SESSION_KILL.increment();
+                self.close(Token(key));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Handle up to a batch of the requests which are available for a session
     fn read(&mut self, token: Token) -> Result<()> {
         let session = self
             .sessions
@@ -105,65 +173,81 @@ where
         // fill the session
         map_result(session.fill())?;
 
-        // process up to one pending request
-        match session.receive() {
-            Ok(request) => {
-                let response = self.storage.execute(&request);
-                PROCESS_REQ.increment();
-                if response.should_hangup() {
-                    let _ = session.send(response);
-                    return Err(Error::new(ErrorKind::Other, "should hangup"));
+        // process each complete request in the buffer. once the buffer holds
+        // no complete request, read more data so that a single readable event
+        // drains the pipeline which is available on the socket, up to a batch
+        // of requests
+        let mut drained = false;
+        for _ in 0..READ_BATCH {
+            match session.receive() {
+                Ok(request) => {
+                    let mut response = self.storage.execute(&request);
+                    PROCESS_REQ.increment();
+                    request.count(&response, session.counters_mut());
+                    request.report(&mut response, &session.counters());
+                    if response.should_hangup() {
+                        let _ = session.send(response);
+                        return Err(Error::new(ErrorKind::Other, "should hangup"));
+                    }
+                    request.klog(&response);
+                    if logger::access_log_enabled() {
+                        access_log(session, &request, &response);
+                    }
+                    if let Some(latency) = session.request_elapsed() {
+                        request.record_latency(latency);
+                    }
+                    if let Err(e) = session.send(response) {
+                        return map_err(e);
+                    }
                 }
-                request.klog(&response);
-                match session.send(response) {
-                    Ok(_) => {
-                        // attempt to flush immediately if there's now data in
-                        // the write buffer
-                        if session.write_pending() > 0 {
-                            match session.flush() {
-                                Ok(_) => Ok(()),
-                                Err(e) => map_err(e),
-                            }?;
-                        }
-
-                        // reregister to get writable event
-                        if session.write_pending() > 0 {
-                            let interest = session.interest();
-                            if self
-                                .poll
-                                .registry()
-                                .reregister(session, token, interest)
-                                .is_err()
-                            {
-                                return Err(Error::new(ErrorKind::Other, "failed to reregister"));
-                            }
-                        }
-
-                        // if there's still data to read, put the token on the
-                        // pending queue
-                        if session.remaining() > 0 {
-                            self.pending.push_back(token);
-                        }
-
-                        Ok(())
+                Err(e) => {
+                    if e.kind() != ErrorKind::WouldBlock {
+                        // the input could not be parsed, this either writes an
+                        // error response or closes the session depending on
+                        // the policy
+                        protocol_error(session, self.protocol_error, e)?;
+                        continue;
                     }
-                    Err(e) => {
-                        if e.kind() == ErrorKind::WouldBlock {
-                            Ok(())
-                        } else {
-                            Err(e)
-                        }
+
+                    // requests which follow a partially composed response
+                    // are handled once it completes
+                    if session.is_composing() || !refill(session)? {
+                        drained = true;
+                        break;
                     }
                 }
             }
-            Err(e) => {
-                if e.kind() == ErrorKind::WouldBlock {
-                    Ok(())
-                } else {
-                    Err(e)
-                }
+        }
+
+        // attempt to flush immediately if there's now data in the write buffer
+        if session.write_pending() > 0 {
+            match session.flush() {
+                Ok(_) => Ok(()),
+                Err(e) => map_err(e),
+            }?;
+        }
+
+        // reregister to get writable event, which is also needed to continue
+        // composing a large response
+        if session.write_pending() > 0 || session.is_composing() {
+            let interest = session.interest();
+            if self
+                .poll
+                .registry()
+                .reregister(session, token, interest)
+                .is_err()
+            {
+                return Err(Error::new(ErrorKind::Other, "failed to reregister"));
             }
         }
+
+        // if the batch ran out before the input did, put the token on the
+        // pending queue so the other sessions are handled first
+        if !drained {
+            self.pending.push_back(token);
+        }
+
+        Ok(())
     }
 
     fn write(&mut self, token: Token) -> Result<()> {
@@ -175,25 +259,75 @@ where
         match session.flush() {
             Ok(_) => Ok(()),
             Err(e) => map_err(e),
+        }?;
+
+        // compose the next part of a large response now that the write buffer
+        // has drained, bounding the time spent on it in this iteration
+        if session.is_composing() {
+            session.compose_next();
+
+            if let Err(e) = session.flush() {
+                map_err(e)?;
+            }
+
+            let interest = session.interest();
+            if self
+                .poll
+                .registry()
+                .reregister(session, token, interest)
+                .is_err()
+            {
+                return Err(Error::new(ErrorKind::Other, "failed to reregister"));
+            }
+
+            // once the response is complete, resume handling any requests
+            // which are already buffered
+            if !session.is_composing() && session.remaining() > 0 {
+                self.pending.push_back(token);
+            }
         }
+
+        Ok(())
     }
 
     /// Run the worker in a loop, handling new events.
     pub fn run(&mut self) {
-        let mut events = Events::with_capacity(self.nevent);
+        let mut nevent = self.nevent;
+        let mut events = Events::with_capacity(nevent);
 
         loop {
             WORKER_EVENT_LOOP.increment();
+            common::rusage::sample();
+
+            // a reload may have changed the number of events per poll
+            if self.nevent != nevent {
+                nevent = self.nevent;
+                events = Events::with_capacity(nevent);
+            }
 
             self.storage.expire();
 
+            // responses held back by the shaping are flushed once they are
+            // due, and the poll returns in time for the next
+            #[cfg(feature = "shaping")]
+            let timeout = {
+                for token in shaped_due(&self.sessions) {
+                    if self.write(token).is_err() {
+                        self.close(token);
+                    }
+                }
+                shaped_timeout(&self.sessions, self.timeout)
+            };
+            #[cfg(not(feature = "shaping"))]
+            let timeout = self.timeout;
+
             // we need another wakeup if there are still pending reads
             if !self.pending.is_empty() {
                 let _ = self.waker.wake();
             }
 
             // get events with timeout
-            if self.poll.poll(&mut events, Some(self.timeout)).is_err() {
+            if self.poll.poll(&mut events, Some(timeout)).is_err() {
                 error!("Error polling");
             }
 
@@ -207,6 +341,16 @@ where
                 WORKER_EVENT_DEPTH.increment(timestamp, count as _, 1);
             }
 
+            // the new settings apply from the next iteration
+            if let Some(autotune) = self.autotune.as_mut() {
+                autotune.record(
+                    count,
+                    &mut self.nevent,
+                    &mut self.timeout,
+                    std::time::Instant::now(),
+                );
+            }
+
             // process all events
             for event in events.iter() {
                 let token = event.token();
@@ -233,7 +377,12 @@ where
                                 .register(self.poll.registry(), Token(s.key()), interest)
                                 .is_ok()
                             {
-                                s.insert(ServerSession::new(session, self.parser.clone()));
+                                let session = s.insert(
+                                    ServerSession::new(session, self.parser.clone())
+                                        .compose_limit(self.compose_limit)
+                                        .listener(self.listener.clone()),
+                                );
+                                common::events::publish(Event::SessionOpened { id: session.id() });
                             } else {
                                 let _ = self.session_queue.try_send_any(session);
                             }
@@ -242,17 +391,132 @@ where
                             let _ = self.waker.wake();
                         }
 
+                        self.clients.execute(&mut self.storage);
+
                         // check if we received any signals from the admin thread
                         while let Some(signal) = self.signal_queue.try_recv() {
+                            let sender = signal.sender();
                             match signal.into_inner() {
                                 Signal::FlushAll => {
                                     self.storage.clear();
+                                    // acknowledge the flush, as the admin thread waits until
+                                    // every thread has applied it before replying
+                                    let _ = self.signal_queue.try_send_to(sender, Reply::Ack);
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::FlushAllAt(time) => {
+                                    self.storage.clear_at(time);
+                                    let _ = self.signal_queue.try_send_to(sender, Reply::Ack);
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::FlushNamespace(namespace) => {
+                                    let removed = self.storage.clear_namespace(&namespace);
+                                    info!("flushed {} items from namespace", removed);
+                                }
+                                Signal::FlushTtlBucket(bucket) => {
+                                    if !self.storage.clear_ttl_bucket(bucket) {
+                                        warn!("cannot flush invalid ttl bucket: {}", bucket);
+                                    }
+                                }
+                                Signal::Reload(reload) => {
+                                    Autotune::disable(&mut self.autotune);
+                                    self.nevent = reload.worker_nevent;
+                                    self.timeout = reload.worker_timeout;
+                                }
+                                Signal::Tune(EventLoop::Worker, tunable) => {
+                                    Autotune::disable(&mut self.autotune);
+                                    match tunable {
+                                        Tunable::Nevent(nevent) => self.nevent = nevent,
+                                        Tunable::Timeout(timeout) => self.timeout = timeout,
+                                    }
+                                }
+                                Signal::ListSessions => {
+                                    let sessions = self.session_infos();
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Sessions(sessions));
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::KillSession(id) => {
+                                    let killed = self.kill(id);
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Killed(killed));
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::SegmentStats => {
+                                    let buckets = self.storage.ttl_bucket_info();
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Segments(buckets));
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::HashTableStats => {
+                                    let hashtable = self.storage.hashtable_info();
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::HashTable(hashtable));
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::Compact => {
+                                    let compacted = self.storage.compact();
+                                    info!("compaction freed {} segments", compacted);
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Compacted(compacted));
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::ServeStale(enabled) => {
+                                    let grace = self.storage.serve_stale(enabled);
+                                    if !grace.is_zero() {
+                                        warn!("serving items up to {:?} past their expiry", grace);
+                                    } else if enabled {
+                                        warn!("not serving items past their expiry, as no grace period is configured");
+                                    } else {
+                                        warn!("stopped serving items past their expiry");
+                                    }
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::ServeStale(grace));
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::Migrate(step) => {
+                                    let reply = self.storage.migrate(step);
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Migrate(reply));
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::Fingerprints { rate, cursor } => {
+                                    let (fingerprints, cursor) =
+                                        self.storage.fingerprints(rate, cursor);
+                                    let _ = self.signal_queue.try_send_to(
+                                        sender,
+                                        Reply::Fingerprints(fingerprints, cursor),
+                                    );
+                                    let _ = self.signal_queue.wake();
+                                }
+                                Signal::Health => {
+                                    let health = ThreadHealth {
+                                        listening: false,
+                                        storage: true,
+                                        warming_up: self.storage.warming_up(),
+                                    };
+                                    let _ = self
+                                        .signal_queue
+                                        .try_send_to(sender, Reply::Health(health));
+                                    let _ = self.signal_queue.wake();
                                 }
                                 Signal::Shutdown => {
-                                    // if we received a shutdown, we can return
-                                    // and stop processing events
+                                    // if we received a shutdown, we flush what
+                                    // we can and stop processing events
+                                    self.drain();
                                     return;
                                 }
+                                signal => signal.reply_default(|reply| {
+                                    let _ = self.signal_queue.try_send_to(sender, reply);
+                                    let _ = self.signal_queue.wake();
+                                }),
                             }
                         }
                     }
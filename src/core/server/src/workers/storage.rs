@@ -2,7 +2,7 @@
 // Licensed under the Apache License, Version 2.0
 // http://www.apache.org/licenses/LICENSE-2.0
 
-use crate::*;
+use super::*;
 
 counter!(
     STORAGE_EVENT_LOOP,
@@ -13,8 +13,19 @@ heatmap!(
     1_000_000,
     "the distribution of the depth of the storage queue on each loop"
 );
+counter!(
+    STORAGE_CANCELLED,
+    "the number of queued reads skipped because their session had closed"
+);
+counter!(
+    STORAGE_DEADLINE_EXCEEDED,
+    "the number of queued requests rejected because they waited past their deadline"
+);
 
 pub struct StorageWorkerBuilder<Request, Response, Storage> {
+    clients: ClientQueue<Request, Response>,
+    miss_filter: Option<Arc<MissFilter>>,
+    miss_filter_interval: Duration,
     nevent: usize,
     poll: Poll,
     storage: Storage,
@@ -30,14 +41,19 @@ impl<Request, Response, Storage> StorageWorkerBuilder<Request, Response, Storage
 
         let poll = Poll::new()?;
 
-        let waker = Arc::new(Waker::from(
-            ::net::Waker::new(poll.registry(), WAKER_TOKEN).unwrap(),
-        ));
+        let waker = Arc::new(
+            Waker::from(::net::Waker::new(poll.registry(), WAKER_TOKEN).unwrap())
+                .coalesce(config.wake_strategy() == WakeStrategy::Coalesce),
+        );
 
         let nevent = config.nevent();
         let timeout = Duration::from_millis(config.timeout() as u64);
+        let miss_filter_interval = Duration::from_secs(config.miss_filter_interval().max(1) as u64);
 
         Ok(Self {
+            clients: ClientQueue::default(),
+            miss_filter: None,
+            miss_filter_interval,
             nevent,
             poll,
             storage,
@@ -52,13 +68,30 @@ impl<Request, Response, Storage> StorageWorkerBuilder<Request, Response, Storage
         self.waker.clone()
     }
 
+    /// Returns a client which submits requests to this thread from outside
+    /// of the event loops.
+    pub fn client(&self) -> StorageClient<Request, Response> {
+        self.clients.client(self.waker.clone())
+    }
+
+    /// Keep the filter of stored keys which the workers consult up to date.
+    pub fn miss_filter(mut self, filter: Option<Arc<MissFilter>>) -> Self {
+        self.miss_filter = filter;
+        self
+    }
+
     pub fn build(
         self,
-        data_queue: Queues<(Request, Response, Token), (Request, Token)>,
-        signal_queue: Queues<(), Signal>,
-    ) -> StorageWorker<Request, Response, Storage, Token> {
+        data_queue: Queues<(Request, Option<Response>, Ticket), (Request, Ticket)>,
+        signal_queue: Queues<Reply, Signal>,
+    ) -> StorageWorker<Request, Response, Storage> {
+        let interval = self.miss_filter_interval;
         StorageWorker {
+            clients: self.clients,
             data_queue,
+            miss_filter: self
+                .miss_filter
+                .map(|filter| Rebuild::new(filter, interval)),
             nevent: self.nevent,
             poll: self.poll,
             signal_queue,
@@ -71,11 +104,13 @@ impl<Request, Response, Storage> StorageWorkerBuilder<Request, Response, Storage
     }
 }
 
-pub struct StorageWorker<Request, Response, Storage, Token> {
-    data_queue: Queues<(Request, Response, Token), (Request, Token)>,
+pub struct StorageWorker<Request, Response, Storage> {
+    clients: ClientQueue<Request, Response>,
+    data_queue: Queues<(Request, Option<Response>, Ticket), (Request, Ticket)>,
+    miss_filter: Option<Rebuild>,
     nevent: usize,
     poll: Poll,
-    signal_queue: Queues<(), Signal>,
+    signal_queue: Queues<Reply, Signal>,
     storage: Storage,
     timeout: Duration,
     #[allow(dead_code)]
@@ -84,22 +119,34 @@ pub struct StorageWorker<Request, Response, Storage, Token> {
     _response: PhantomData<Response>,
 }
 
-impl<Request, Response, Storage, Token> StorageWorker<Request, Response, Storage, Token>
+impl<Request, Response, Storage> StorageWorker<Request, Response, Storage>
 where
     Storage: Execute<Request, Response> + EntryStore,
-    Request: Klog + Klog<Response = Response>,
+    Request: Klog + Klog<Response = Response> + Lookup<Response>,
     Response: Compose,
 {
     /// Run the `StorageWorker` in a loop, handling new session events.
     pub fn run(&mut self) {
-        let mut events = Events::with_capacity(self.nevent);
+        let mut nevent = self.nevent;
+        let mut events = Events::with_capacity(nevent);
         let mut messages = Vec::with_capacity(1024);
 
         loop {
             STORAGE_EVENT_LOOP.increment();
+            common::rusage::sample();
+
+            // a reload may have changed the number of events per poll
+            if self.nevent != nevent {
+                nevent = self.nevent;
+                events = Events::with_capacity(nevent);
+            }
 
             self.storage.expire();
 
+            if let Some(miss_filter) = self.miss_filter.as_mut() {
+                miss_filter.maybe_rebuild(&mut self.storage, std::time::Instant::now());
+            }
+
             // get events with timeout
             if self.poll.poll(&mut events, Some(self.timeout)).is_err() {
                 error!("Error polling");
@@ -116,13 +163,37 @@ where
 
                 STORAGE_QUEUE_DEPTH.increment(timestamp, messages.len() as _, 1);
 
+                let now = std::time::Instant::now();
+
                 for message in messages.drain(..) {
                     let sender = message.sender();
-                    let (request, token) = message.into_inner();
+                    let (request, ticket) = message.into_inner();
                     trace!("handling request from worker: {}", sender);
-                    let response = self.storage.execute(&request);
-                    PROCESS_REQ.increment();
-                    let mut message = (request, response, token);
+
+                    // no response is needed once the session has closed, but
+                    // writes are still applied, as the client may have sent
+                    // them without waiting for a reply
+                    if ticket.cancelled() && request.read_only() {
+                        STORAGE_CANCELLED.increment();
+                        continue;
+                    }
+
+                    // the worker rejects a request which has waited too long
+                    // when it is returned without a response
+                    let response = if ticket.expired(now) {
+                        STORAGE_DEADLINE_EXCEEDED.increment();
+                        None
+                    } else {
+                        PROCESS_REQ.increment();
+                        // the keys are added before they are stored, so that
+                        // a worker never answers a read of one as a miss
+                        if let Some(miss_filter) = &self.miss_filter {
+                            request.stores(&mut |key| miss_filter.filter().insert(key));
+                        }
+                        Some(self.storage.execute(&request))
+                    };
+
+                    let mut message = (request, response, ticket);
                     for retry in 0..QUEUE_RETRIES {
                         if let Err(m) = self.data_queue.try_send_to(sender, message) {
                             if (retry + 1) == QUEUE_RETRIES {
@@ -139,12 +210,103 @@ where
 
                 let _ = self.data_queue.wake();
 
+                self.clients.execute(
+                    &mut self.storage,
+                    self.miss_filter.as_ref().map(|f| f.filter()),
+                );
+
                 // check if we received any signals from the admin thread
-                while let Some(s) = self.signal_queue.try_recv().map(|v| v.into_inner()) {
-                    match s {
+                while let Some(s) = self.signal_queue.try_recv() {
+                    let sender = s.sender();
+                    match s.into_inner() {
                         Signal::FlushAll => {
                             warn!("received flush_all");
                             self.storage.clear();
+                            // acknowledge the flush, as the admin thread waits until
+                            // every thread has applied it before replying
+                            let _ = self.signal_queue.try_send_to(sender, Reply::Ack);
+                            let _ = self.signal_queue.wake();
+                        }
+                        Signal::FlushAllAt(time) => {
+                            self.storage.clear_at(time);
+                            let _ = self.signal_queue.try_send_to(sender, Reply::Ack);
+                            let _ = self.signal_queue.wake();
+                        }
+                        Signal::FlushNamespace(namespace) => {
+                            warn!("received flush namespace");
+                            let removed = self.storage.clear_namespace(&namespace);
+                            info!("flushed {} items from namespace", removed);
+                        }
+                        Signal::FlushTtlBucket(bucket) => {
+                            warn!("received flush ttl_bucket {}", bucket);
+                            if !self.storage.clear_ttl_bucket(bucket) {
+                                warn!("cannot flush invalid ttl bucket: {}", bucket);
+                            }
+                        }
+                        Signal::Reload(reload) => {
+                            self.nevent = reload.worker_nevent;
+                            self.timeout = reload.worker_timeout;
+                        }
+                        Signal::Tune(EventLoop::Worker, tunable) => match tunable {
+                            Tunable::Nevent(nevent) => self.nevent = nevent,
+                            Tunable::Timeout(timeout) => self.timeout = timeout,
+                        },
+                        Signal::SegmentStats => {
+                            let buckets = self.storage.ttl_bucket_info();
+                            let _ = self
+                                .signal_queue
+                                .try_send_to(sender, Reply::Segments(buckets));
+                            let _ = self.signal_queue.wake();
+                        }
+                        Signal::HashTableStats => {
+                            let hashtable = self.storage.hashtable_info();
+                            let _ = self
+                                .signal_queue
+                                .try_send_to(sender, Reply::HashTable(hashtable));
+                            let _ = self.signal_queue.wake();
+                        }
+                        Signal::Compact => {
+                            let compacted = self.storage.compact();
+                            info!("compaction freed {} segments", compacted);
+                            let _ = self
+                                .signal_queue
+                                .try_send_to(sender, Reply::Compacted(compacted));
+                            let _ = self.signal_queue.wake();
+                        }
+                        Signal::ServeStale(enabled) => {
+                            let grace = self.storage.serve_stale(enabled);
+                            if !grace.is_zero() {
+                                warn!("serving items up to {:?} past their expiry", grace);
+                            } else if enabled {
+                                warn!("not serving items past their expiry, as no grace period is configured");
+                            } else {
+                                warn!("stopped serving items past their expiry");
+                            }
+                            let _ = self
+                                .signal_queue
+                                .try_send_to(sender, Reply::ServeStale(grace));
+                            let _ = self.signal_queue.wake();
+                        }
+                        Signal::Migrate(step) => {
+                            let reply = self.storage.migrate(step);
+                            let _ = self.signal_queue.try_send_to(sender, Reply::Migrate(reply));
+                            let _ = self.signal_queue.wake();
+                        }
+                        Signal::Fingerprints { rate, cursor } => {
+                            let (fingerprints, cursor) = self.storage.fingerprints(rate, cursor);
+                            let _ = self
+                                .signal_queue
+                                .try_send_to(sender, Reply::Fingerprints(fingerprints, cursor));
+                            let _ = self.signal_queue.wake();
+                        }
+                        Signal::Health => {
+                            let health = ThreadHealth {
+                                listening: false,
+                                storage: true,
+                                warming_up: self.storage.warming_up(),
+                            };
+                            let _ = self.signal_queue.try_send_to(sender, Reply::Health(health));
+                            let _ = self.signal_queue.wake();
                         }
                         Signal::Shutdown => {
                             // if we received a shutdown, we can return and stop
@@ -155,6 +317,10 @@ where
 
                             return;
                         }
+                        signal => signal.reply_default(|reply| {
+                            let _ = self.signal_queue.try_send_to(sender, reply);
+                            let _ = self.signal_queue.wake();
+                        }),
                     }
                 }
             }
@@ -0,0 +1,101 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Tests the async front end by serving memcache from segcache storage on an
+//! ephemeral port.
+
+use config::SegcacheConfig;
+use entrystore::Seg;
+use protocol_memcache::{Request, RequestParser, Response};
+use server::{AsyncServer, AsyncServerBuilder};
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+fn start() -> AsyncServer {
+    let config: SegcacheConfig = toml::from_str("[server]\nhost = \"127.0.0.1\"\nport = \"0\"\n")
+        .expect("failed to parse config");
+    let storage = Seg::new(&config).expect("failed to create storage");
+
+    let parser = RequestParser::new();
+    AsyncServerBuilder::<RequestParser, Request, Response, Seg>::new(&config, parser, storage)
+        .expect("failed to create server")
+        .spawn()
+        .expect("failed to start server")
+}
+
+fn connect(server: &AsyncServer) -> TcpStream {
+    let stream = TcpStream::connect(server.local_addr()).expect("failed to connect");
+    stream
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .expect("failed to set read timeout");
+    stream
+}
+
+// sends the request and reads until the expected response has been received,
+// or the deadline has passed
+fn request(stream: &mut TcpStream, request: &[u8], expected: &[u8]) {
+    stream.write_all(request).expect("failed to send request");
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut response = Vec::new();
+    let mut buf = [0; 4096];
+    while response.len() < expected.len() && Instant::now() < deadline {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(bytes) => response.extend_from_slice(&buf[0..bytes]),
+            Err(_) => continue,
+        }
+    }
+
+    assert_eq!(
+        String::from_utf8_lossy(&response),
+        String::from_utf8_lossy(expected)
+    );
+}
+
+#[test]
+fn set_get_delete() {
+    let server = start();
+    assert_ne!(server.local_addr().port(), 0);
+    let mut stream = connect(&server);
+
+    request(&mut stream, b"get 0\r\n", b"END\r\n");
+    request(&mut stream, b"set 0 1 0 5\r\nvalue\r\n", b"STORED\r\n");
+    request(
+        &mut stream,
+        b"get 0\r\n",
+        b"VALUE 0 1 5\r\nvalue\r\nEND\r\n",
+    );
+    request(&mut stream, b"delete 0\r\n", b"DELETED\r\n");
+    request(&mut stream, b"get 0\r\n", b"END\r\n");
+    request(&mut stream, b"delete 0\r\n", b"NOT_FOUND\r\n");
+
+    server.shutdown();
+}
+
+#[test]
+fn pipelined_multiget() {
+    let server = start();
+    let mut stream = connect(&server);
+
+    // the requests are sent in a single write, and the responses are returned
+    // in order, with misses left out of the multiget
+    request(
+        &mut stream,
+        b"set 1 0 0 1\r\na\r\nset 2 0 0 1\r\nb\r\nget 1 3 2\r\nget 3\r\n",
+        b"STORED\r\nSTORED\r\nVALUE 1 0 1\r\na\r\nVALUE 2 0 1\r\nb\r\nEND\r\nEND\r\n",
+    );
+
+    // sessions share storage
+    let mut other = connect(&server);
+    request(
+        &mut other,
+        b"get 2 1\r\n",
+        b"VALUE 2 0 1\r\nb\r\nVALUE 1 0 1\r\na\r\nEND\r\n",
+    );
+
+    server.shutdown();
+}
@@ -0,0 +1,151 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Tests the QUIC front end by serving memcache from segcache storage on an
+//! ephemeral port, with a self-signed certificate generated for the run.
+
+use config::SegcacheConfig;
+use entrystore::Seg;
+use protocol_memcache::{Request, RequestParser, Response};
+use quinn::{Endpoint, NewConnection, RecvStream, SendStream};
+use server::{QuicServer, QuicServerBuilder, ALPN};
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+fn start(dir: &tempfile::TempDir) -> QuicServer {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])
+        .expect("failed to generate certificate");
+
+    let cert_file = dir.path().join("cert.pem");
+    let key_file = dir.path().join("key.pem");
+    std::fs::write(&cert_file, cert.serialize_pem().unwrap()).unwrap();
+    std::fs::write(&key_file, cert.serialize_private_key_pem()).unwrap();
+
+    let config: SegcacheConfig = toml::from_str(&format!(
+        "[server]\nhost = \"127.0.0.1\"\nport = \"0\"\n\n\
+        [tls]\ncertificate_chain = \"{}\"\nprivate_key = \"{}\"\n",
+        cert_file.display(),
+        key_file.display()
+    ))
+    .expect("failed to parse config");
+    let storage = Seg::new(&config).expect("failed to create storage");
+
+    let parser = RequestParser::new();
+    QuicServerBuilder::<RequestParser, Request, Response, Seg>::new(&config, parser, storage)
+        .expect("failed to create server")
+        .spawn()
+        .expect("failed to start server")
+}
+
+fn connect(runtime: &Runtime, addr: SocketAddr, cert: &[u8]) -> quinn::Connection {
+    runtime.block_on(async {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut &*cert).unwrap() {
+            roots.add(&rustls::Certificate(cert)).unwrap();
+        }
+
+        let mut crypto = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        crypto.alpn_protocols = vec![ALPN.to_vec()];
+
+        let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+
+        let NewConnection { connection, .. } = endpoint
+            .connect(addr, "localhost")
+            .unwrap()
+            .await
+            .expect("failed to connect");
+
+        connection
+    })
+}
+
+// a bidirectional stream on the connection
+struct Stream {
+    send: SendStream,
+    recv: RecvStream,
+}
+
+impl Stream {
+    async fn open(connection: &quinn::Connection) -> Self {
+        let (send, recv) = connection.open_bi().await.expect("failed to open stream");
+        Self { send, recv }
+    }
+
+    // sends the request and reads until the expected response has been
+    // received, failing if it does not arrive in time
+    async fn request(&mut self, request: &[u8], expected: &[u8]) {
+        self.send
+            .write_all(request)
+            .await
+            .expect("failed to send request");
+
+        let mut response = vec![0; expected.len()];
+        let mut read = 0;
+        while read < expected.len() {
+            let timeout = Duration::from_secs(5);
+            match tokio::time::timeout(timeout, self.recv.read(&mut response[read..])).await {
+                Ok(Ok(Some(bytes))) => read += bytes,
+                Ok(_) => panic!("stream closed"),
+                Err(_) => panic!(
+                    "timed out with response: {}",
+                    String::from_utf8_lossy(&response[0..read])
+                ),
+            }
+        }
+
+        assert_eq!(
+            String::from_utf8_lossy(&response),
+            String::from_utf8_lossy(expected)
+        );
+    }
+}
+
+#[test]
+fn independent_streams() {
+    let dir = tempfile::tempdir().expect("failed to create temporary directory");
+    let server = start(&dir);
+    assert_ne!(server.local_addr().port(), 0);
+    let cert = std::fs::read(dir.path().join("cert.pem")).unwrap();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let connection = connect(&runtime, server.local_addr(), &cert);
+
+    runtime.block_on(async {
+        let mut a = Stream::open(&connection).await;
+        let mut b = Stream::open(&connection).await;
+
+        // a partial request on the first stream does not hold up the second
+        a.send.write_all(b"set a 0 0 5\r\nva").await.unwrap();
+        b.request(b"set b 0 0 5\r\nvalue\r\n", b"STORED\r\n").await;
+        b.request(b"get b\r\n", b"VALUE b 0 5\r\nvalue\r\nEND\r\n")
+            .await;
+        b.request(b"get a\r\n", b"END\r\n").await;
+
+        // once completed, the request is served on its own stream, and the
+        // streams share storage
+        a.request(b"lue\r\n", b"STORED\r\n").await;
+        b.request(b"get a\r\n", b"VALUE a 0 5\r\nvalue\r\nEND\r\n")
+            .await;
+        a.request(b"get b\r\n", b"VALUE b 0 5\r\nvalue\r\nEND\r\n")
+            .await;
+
+        // closing one stream leaves the other open
+        a.send.finish().await.unwrap();
+        b.request(b"delete a\r\n", b"DELETED\r\n").await;
+        b.request(b"get a\r\n", b"END\r\n").await;
+    });
+
+    connection.close(0u32.into(), b"done");
+    server.shutdown();
+}
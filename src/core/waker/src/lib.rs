@@ -11,10 +11,25 @@
 //! fact that it must be registered to an event loop (such as epoll).
 
 use core::sync::atomic::{AtomicU64, Ordering};
-
+use rustcommon_metrics::*;
+
+counter!(WAKER_WAKE, "number of wakeups requested");
+counter!(
+    WAKER_SIGNAL,
+    "number of wakeups which signalled the thread being woken"
+);
+counter!(
+    WAKER_COALESCED,
+    "number of wakeups skipped because the thread already had one pending"
+);
+
+/// Wakes the thread which owns it. By default, wakeups are coalesced so that
+/// only the first wakeup after each `reset()` signals the thread, as the
+/// thread will see everything queued for it once it handles that wakeup.
 pub struct Waker {
     inner: Box<dyn GenericWaker>,
     pending: AtomicU64,
+    coalesce: bool,
 }
 
 impl From<MioWaker> for Waker {
@@ -22,15 +37,26 @@ impl From<MioWaker> for Waker {
         Self {
             inner: Box::new(other),
             pending: AtomicU64::new(0),
+            coalesce: true,
         }
     }
 }
 
 impl Waker {
+    /// Sets whether wakeups are coalesced. When disabled, every call to
+    /// `wake()` signals the thread.
+    pub fn coalesce(mut self, coalesce: bool) -> Self {
+        self.coalesce = coalesce;
+        self
+    }
+
     pub fn wake(&self) -> std::io::Result<()> {
-        if self.pending.fetch_add(1, Ordering::Relaxed) == 0 {
+        WAKER_WAKE.increment();
+        if !self.coalesce || self.pending.fetch_add(1, Ordering::Relaxed) == 0 {
+            WAKER_SIGNAL.increment();
             self.inner.wake()
         } else {
+            WAKER_COALESCED.increment();
             Ok(())
         }
     }
@@ -141,6 +167,7 @@ mod eventfd {
             Self {
                 inner: Box::new(other),
                 pending: AtomicU64::new(0),
+                coalesce: true,
             }
         }
     }
@@ -0,0 +1,100 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Errors returned by storage operations. These do not depend on the storage
+//! type, which allows each protocol to map every failure mode to its own
+//! response, and allows operators to tell the failure modes apart in metrics.
+
+use rustcommon_metrics::*;
+
+counter!(
+    STORAGE_ERROR_OUT_OF_MEMORY,
+    "number of operations which failed because no memory could be freed for the item"
+);
+counter!(
+    STORAGE_ERROR_ITEM_TOO_LARGE,
+    "number of operations which failed because the item is too large to be stored"
+);
+counter!(
+    STORAGE_ERROR_CAS_MISMATCH,
+    "number of operations which failed because the item was modified since its cas value was read"
+);
+counter!(
+    STORAGE_ERROR_NOT_FOUND,
+    "number of operations which failed because the item does not exist"
+);
+counter!(
+    STORAGE_ERROR_NOT_NUMERIC,
+    "number of arithmetic operations which failed because the value is not numeric"
+);
+counter!(
+    STORAGE_ERROR_OVERFLOW,
+    "number of arithmetic operations which failed because the result is out of range"
+);
+counter!(
+    STORAGE_ERROR_NOT_ADMITTED,
+    "number of writes which were not admitted by the admission policy"
+);
+counter!(
+    STORAGE_ERROR_INTERNAL,
+    "number of operations which failed because of an internal storage error"
+);
+
+/// The ways in which a storage operation may fail.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum StorageError {
+    /// No memory could be allocated or freed to store the item.
+    OutOfMemory,
+    /// The item is larger than the largest item which can be stored.
+    ItemTooLarge,
+    /// The item was modified since its cas value was read.
+    CasMismatch,
+    /// The item does not exist.
+    NotFound,
+    /// The value cannot be incremented or decremented as it is not numeric.
+    NotNumeric,
+    /// The result of incrementing or decrementing the value is out of range,
+    /// and overflow is configured as an error.
+    Overflow,
+    /// The write was rejected by the admission policy.
+    NotAdmitted,
+    /// The storage failed in a way that the client cannot act on, such as
+    /// detecting corrupted data.
+    Internal,
+}
+
+impl StorageError {
+    /// Increments the counter for this failure mode. This should be called
+    /// once by the protocol layer as it maps the error to a response.
+    pub fn increment(&self) {
+        match self {
+            Self::OutOfMemory => STORAGE_ERROR_OUT_OF_MEMORY.increment(),
+            Self::ItemTooLarge => STORAGE_ERROR_ITEM_TOO_LARGE.increment(),
+            Self::CasMismatch => STORAGE_ERROR_CAS_MISMATCH.increment(),
+            Self::NotFound => STORAGE_ERROR_NOT_FOUND.increment(),
+            Self::NotNumeric => STORAGE_ERROR_NOT_NUMERIC.increment(),
+            Self::Overflow => STORAGE_ERROR_OVERFLOW.increment(),
+            Self::NotAdmitted => STORAGE_ERROR_NOT_ADMITTED.increment(),
+            Self::Internal => STORAGE_ERROR_INTERNAL.increment(),
+        };
+    }
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            Self::OutOfMemory => "out of memory storing object",
+            Self::ItemTooLarge => "object too large for cache",
+            Self::CasMismatch => "cas mismatch",
+            Self::NotFound => "not found",
+            Self::NotNumeric => "cannot increment or decrement non-numeric value",
+            Self::Overflow => "increment or decrement overflow",
+            Self::NotAdmitted => "not admitted",
+            Self::Internal => "internal storage error",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for StorageError {}
@@ -7,12 +7,20 @@
 //! addition to the base `EntryStore` trait. For example [`Seg`] implements both
 //! [`EntryStore`] and [`protocol::memcache::MemcacheStorage`].
 
+mod error;
 mod noop;
 mod seg;
 
+pub use self::error::*;
 pub use self::noop::*;
 pub use self::seg::*;
 
+pub use common::namespace::NAMESPACE_SEPARATOR;
+
+use common::fingerprint::{hash, sampled, Fingerprint};
+use common::signal::{HashTableInfo, MigrateReply, MigrateStep, MigrationItem, TtlBucketInfo};
+use std::time::{Duration, SystemTime};
+
 /// A trait defining the basic requirements of a type which may be used for
 /// storage.
 pub trait EntryStore {
@@ -24,4 +32,147 @@ pub trait EntryStore {
 
     /// Remove all existing values from the entry store.
     fn clear(&mut self);
+
+    /// Remove all values which exist once the wall clock reaches the given
+    /// time, replacing any removal which was scheduled earlier. The default
+    /// implementation cannot defer the removal and clears the store now.
+    fn clear_at(&mut self, _time: SystemTime) {
+        self.clear();
+    }
+
+    /// Remove all values with keys in the given namespace, that is keys which
+    /// begin with the namespace followed by the configured separator, which is
+    /// the [`NAMESPACE_SEPARATOR`] by default.
+    /// Returns the number of values removed. The default implementation does
+    /// not support scoped removal and removes nothing.
+    fn clear_namespace(&mut self, _namespace: &[u8]) -> usize {
+        0
+    }
+
+    /// Remove all values stored in the TTL bucket with the given index, for
+    /// storage types which group values by TTL. Returns `false` if the bucket
+    /// does not exist. The default implementation has no TTL buckets.
+    fn clear_ttl_bucket(&mut self, _bucket: usize) -> bool {
+        false
+    }
+
+    /// Start or stop serving values past their expiry, in place of misses,
+    /// while the origin is degraded. Returns the time for which values are
+    /// served past their expiry, which is zero if the storage type does not
+    /// support this or it is not configured, which is the default
+    /// implementation.
+    fn serve_stale(&mut self, _enabled: bool) -> Duration {
+        Duration::ZERO
+    }
+
+    /// Returns true while storage is warming up after startup, during which
+    /// it should not be reported as ready. The default implementation has no
+    /// warm-up period.
+    fn warming_up(&mut self) -> bool {
+        false
+    }
+
+    /// Describe the occupancy of the hashtable, for storage types which use
+    /// one. The default implementation has no hashtable.
+    fn hashtable_info(&self) -> Option<HashTableInfo> {
+        None
+    }
+
+    /// Reclaim space held by removed or replaced values ahead of time, for
+    /// storage types which would otherwise only reclaim it under memory
+    /// pressure. Returns the number of segments freed. The default
+    /// implementation has nothing to reclaim.
+    fn compact(&mut self) -> usize {
+        0
+    }
+
+    /// Describe the segments in each TTL bucket which holds any, for storage
+    /// types which group values by TTL. The default implementation has no TTL
+    /// buckets.
+    fn ttl_bucket_info(&self) -> Vec<TtlBucketInfo> {
+        Vec::new()
+    }
+
+    /// Collect the keys of some of the values, so that a filter of the stored
+    /// keys can be built a step at a time. A scan starts with a cursor of zero
+    /// and each step returns some keys with the cursor for the next step. The
+    /// scan is complete once the returned cursor is zero again, and includes
+    /// every key which was stored for its whole duration. Returns `None` if
+    /// the storage type cannot enumerate its keys, which is the default
+    /// implementation.
+    fn scan_keys(&mut self, _cursor: usize) -> Option<(Vec<Box<[u8]>>, usize)> {
+        None
+    }
+
+    /// Copy the value with the given key, as it would be returned to a client,
+    /// so that it can be migrated to another instance. The default
+    /// implementation does not support migration and returns nothing.
+    fn export(&mut self, _key: &[u8]) -> Option<MigrationItem> {
+        None
+    }
+
+    /// Remove the value with the given key if it has not changed since it was
+    /// exported with the given cas value. Returns `true` if it was removed.
+    /// The default implementation does not support migration and removes
+    /// nothing.
+    fn remove_exported(&mut self, _key: &[u8], _cas: u64) -> bool {
+        false
+    }
+
+    /// Summarize the value with the given key without its contents, so that
+    /// the values held by two instances can be compared. The default
+    /// implementation cannot fingerprint its values and returns nothing.
+    fn fingerprint(&mut self, _key: &[u8]) -> Option<Fingerprint> {
+        None
+    }
+
+    /// Fingerprint a sample of one in every `rate` values in a part of
+    /// storage, starting at the cursor, and return them with the cursor to
+    /// continue from, as for `scan_keys`. Values are chosen by the hash of the
+    /// key, so that instances with the same keys sample the same ones.
+    fn fingerprints(&mut self, rate: u64, cursor: usize) -> (Vec<Fingerprint>, usize) {
+        match self.scan_keys(cursor) {
+            Some((keys, cursor)) => (
+                keys.iter()
+                    .filter(|key| sampled(hash(key), rate))
+                    .filter_map(|key| self.fingerprint(key))
+                    .collect(),
+                cursor,
+            ),
+            None => (Vec::new(), 0),
+        }
+    }
+
+    /// Apply a step of a live migration, using the functions above.
+    fn migrate(&mut self, step: MigrateStep) -> MigrateReply {
+        match step {
+            MigrateStep::Keys { prefix, cursor } => match self.scan_keys(cursor) {
+                Some((keys, cursor)) => MigrateReply::Keys(
+                    keys.into_iter()
+                        .filter(|key| key.starts_with(&prefix))
+                        .collect(),
+                    cursor,
+                ),
+                None => MigrateReply::Keys(Vec::new(), 0),
+            },
+            MigrateStep::Count { prefix, cursor } => match self.scan_keys(cursor) {
+                Some((keys, cursor)) => MigrateReply::Count(
+                    keys.iter().filter(|key| key.starts_with(&prefix)).count(),
+                    cursor,
+                ),
+                None => MigrateReply::Count(0, 0),
+            },
+            MigrateStep::Export(keys) => {
+                MigrateReply::Items(keys.iter().filter_map(|key| self.export(key)).collect())
+            }
+            MigrateStep::Remove(items) => MigrateReply::Removed(
+                items
+                    .iter()
+                    .filter(|(key, cas)| self.remove_exported(key, *cas))
+                    .count(),
+            ),
+        }
+    }
 }
+
+common::metrics::test_no_duplicates!();
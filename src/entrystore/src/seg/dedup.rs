@@ -0,0 +1,139 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Suppression of duplicate writes. Clients with aggressive retry policies
+//! may send the same write several times in quick succession. Each recent
+//! write is remembered by a fingerprint of the key, value, flags, and TTL,
+//! along with the version of the item it stored. A write with the same
+//! fingerprint within the window is acknowledged without being applied again,
+//! as long as the item it stored has not been replaced in the meantime.
+
+use super::*;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+counter!(
+    DEDUP_SUPPRESSED,
+    "number of writes acknowledged without being applied because they duplicate a recent write"
+);
+
+#[derive(Copy, Clone)]
+struct Entry {
+    fingerprint: u64,
+    version: u64,
+    written: Duration,
+}
+
+/// A direct-mapped table of recent writes. A write replaces any entry in its
+/// slot, so only the most recent writes are remembered.
+pub(super) struct Dedup {
+    window: Duration,
+    entries: Box<[Option<Entry>]>,
+}
+
+impl Dedup {
+    pub fn new(window: Duration, entries: usize) -> Self {
+        Self {
+            window,
+            entries: vec![None; entries.max(1)].into_boxed_slice(),
+        }
+    }
+
+    /// Calculates the fingerprint of a write.
+    pub fn fingerprint(key: &[u8], value: &[u8], flags: u32, ttl: Duration) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write_usize(key.len());
+        hasher.write(key);
+        hasher.write(value);
+        hasher.write_u32(flags);
+        hasher.write_u64(ttl.as_secs());
+        hasher.finish()
+    }
+
+    /// Returns the version of the item stored by a write with the same
+    /// fingerprint, if there was one within the window.
+    pub fn recent(&self, fingerprint: u64, now: Duration) -> Option<u64> {
+        match self.entries[self.index(fingerprint)] {
+            Some(entry)
+                if entry.fingerprint == fingerprint
+                    && now.saturating_sub(entry.written) <= self.window =>
+            {
+                Some(entry.version)
+            }
+            _ => None,
+        }
+    }
+
+    /// Remembers a write which stored the given item version.
+    pub fn record(&mut self, fingerprint: u64, version: u64, now: Duration) {
+        let index = self.index(fingerprint);
+        self.entries[index] = Some(Entry {
+            fingerprint,
+            version,
+            written: now,
+        });
+    }
+
+    fn index(&self, fingerprint: u64) -> usize {
+        (fingerprint % self.entries.len() as u64) as usize
+    }
+}
+
+impl Seg {
+    /// Returns the fingerprint of a write if duplicate suppression is enabled.
+    pub(super) fn write_fingerprint(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        ttl: Duration,
+    ) -> Option<u64> {
+        self.dedup
+            .as_ref()
+            .map(|_| Dedup::fingerprint(key, value, flags, ttl))
+    }
+
+    /// Returns true if the write duplicates a recent write whose item is
+    /// still stored, in which case the write does not need to be applied.
+    pub(super) fn duplicate_write(&mut self, key: &[u8], fingerprint: Option<u64>) -> bool {
+        let now = self.clock.uptime();
+        let version = match (self.dedup.as_ref(), fingerprint) {
+            (Some(dedup), Some(fingerprint)) => dedup.recent(fingerprint, now),
+            _ => None,
+        };
+
+        let duplicate = match version {
+            Some(version) => self
+                .data
+                .get_no_freq_incr(key)
+                .map(|item| item.version() == version)
+                .unwrap_or(false),
+            None => false,
+        };
+
+        if duplicate {
+            DEDUP_SUPPRESSED.increment();
+        }
+
+        duplicate
+    }
+
+    /// Remembers a write which was applied.
+    pub(super) fn record_write(&mut self, key: &[u8], fingerprint: Option<u64>) {
+        let fingerprint = match fingerprint {
+            Some(fingerprint) => fingerprint,
+            None => {
+                return;
+            }
+        };
+
+        let now = self.clock.uptime();
+        if let Some(item) = self.data.get_no_freq_incr(key) {
+            if let Some(dedup) = self.dedup.as_mut() {
+                dedup.record(fingerprint, item.version(), now);
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! This module defines how `Seg` storage will be used to execute `Http`
+//! requests. Values are stored using the same representation as the memcache
+//! protocol, so items may be shared between the two protocols.
+
+use super::rules::decompress;
+use super::*;
+use protocol_common::*;
+
+use protocol_http::*;
+
+use std::time::Duration;
+
+impl Execute<Request, Response> for Seg {
+    fn execute(&mut self, request: &Request) -> Response {
+        let response = match request {
+            Request::Get(_) if self.shed_read() => Response::new(Status::ServiceUnavailable),
+            Request::Get(get) if get.accept_gzip() => self.http_get(get).gzip(GZIP_MIN_SIZE),
+            Request::Get(get) => self.http_get(get),
+            Request::Put(put) => self.http_put(put),
+            Request::Delete(delete) => self.http_delete(delete),
+            Request::Invalid(invalid) => Response::new(invalid.status()),
+        };
+        response.close(request.close())
+    }
+}
+
+impl Seg {
+    fn http_get(&mut self, get: &Get) -> Response {
+        if let Some(item) = self.data.get(get.key()) {
+            self.stale_read(get.key());
+            let o = item.optional().unwrap_or(&[0, 0, 0, 0]);
+            let flags = u32::from_be_bytes([o[0], o[1], o[2], o[3]]);
+            match item.value() {
+                seg::Value::Bytes(b) => match decompress(item.optional(), b) {
+                    Ok(b) => Response::ok(&b, flags, item.cas().into()),
+                    Err(_) => Response::new(Status::InternalServerError),
+                },
+                seg::Value::U64(v) => {
+                    Response::ok(format!("{}", v).as_bytes(), flags, item.cas().into())
+                }
+            }
+        } else {
+            Response::new(Status::NotFound)
+        }
+    }
+
+    fn http_put(&mut self, put: &Put) -> Response {
+        if put.ttl() < 0 {
+            // immediate expire maps to a delete
+            self.data.delete(put.key());
+            return Response::new(Status::NoContent);
+        }
+
+        if put.if_none_match() && self.data.get_no_freq_incr(put.key()).is_some() {
+            return Response::new(Status::PreconditionFailed);
+        }
+
+        // no item has a CAS value which does not fit in 32 bits, so such a
+        // value must not be truncated into one which might match
+        let if_match = match put.if_match().map(u32::try_from) {
+            Some(Ok(cas)) => Some(cas),
+            Some(Err(_)) => return Response::new(Status::PreconditionFailed),
+            None => None,
+        };
+
+        let ttl = Duration::from_secs(put.ttl() as u64);
+        let flags = put.flags().to_be_bytes();
+
+        // numeric values are stored as integers, as with the memcache protocol
+        let numeric = std::str::from_utf8(put.value())
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok());
+
+        // only unconditional writes are suppressed as duplicates
+        let fingerprint = match if_match {
+            Some(_) => None,
+            None => self.write_fingerprint(put.key(), put.value(), put.flags(), ttl),
+        };
+        if self.duplicate_write(put.key(), fingerprint) {
+            return Response::new(Status::NoContent);
+        }
+
+        let result = match (if_match, numeric) {
+            (Some(cas), Some(v)) => self.data.cas(put.key(), v, Some(&flags), ttl, cas),
+            (Some(cas), None) => self
+                .data
+                .cas(put.key(), put.value(), Some(&flags), ttl, cas),
+            (None, Some(v)) => self.data.insert(put.key(), v, Some(&flags), ttl),
+            (None, None) => self.data.insert(put.key(), put.value(), Some(&flags), ttl),
+        };
+
+        if result.is_ok() {
+            self.record_write(put.key(), fingerprint);
+        }
+
+        match result.map_err(storage_error) {
+            Ok(_) => Response::new(Status::NoContent),
+            Err(StorageError::CasMismatch) | Err(StorageError::NotFound) => {
+                Response::new(Status::PreconditionFailed)
+            }
+            // the write was declined by the admission policy and was not
+            // stored, which is reported as retryable rather than as success
+            Err(StorageError::NotAdmitted) => Response::new(Status::ServiceUnavailable),
+            Err(StorageError::ItemTooLarge) => Response::new(Status::PayloadTooLarge),
+            Err(StorageError::OutOfMemory) => Response::new(Status::InsufficientStorage),
+            Err(StorageError::NotNumeric)
+            | Err(StorageError::Overflow)
+            | Err(StorageError::Internal) => Response::new(Status::InternalServerError),
+        }
+    }
+
+    fn http_delete(&mut self, delete: &Delete) -> Response {
+        if let Some(cas) = delete.if_match() {
+            match self.data.get_no_freq_incr(delete.key()) {
+                Some(item) => {
+                    if u64::from(item.cas()) != cas {
+                        return Response::new(Status::PreconditionFailed);
+                    }
+                }
+                None => {
+                    return Response::new(Status::PreconditionFailed);
+                }
+            }
+        }
+
+        if self.data.delete(delete.key()) {
+            Response::new(Status::NoContent)
+        } else {
+            Response::new(Status::NotFound)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::SegcacheConfig;
+
+    fn execute(seg: &mut Seg, request: &[u8]) -> Response {
+        let request = RequestParser::new()
+            .parse(request)
+            .expect("failed to parse request")
+            .into_inner();
+        seg.execute(&request)
+    }
+
+    // returns the CAS value from the ETag of a response
+    fn etag(response: &Response) -> u64 {
+        let mut buffer = Vec::new();
+        response.compose(&mut buffer);
+        let response = String::from_utf8_lossy(&buffer);
+        let etag = response
+            .lines()
+            .find_map(|line| line.strip_prefix("ETag: "))
+            .expect("no etag");
+        etag.trim_matches('"').parse().unwrap()
+    }
+
+    fn put_if_match(seg: &mut Seg, cas: u64) -> Response {
+        let request = format!(
+            "PUT /keys/drink HTTP/1.1\r\nContent-Length: 3\r\nIf-Match: \"{}\"\r\n\r\ntea",
+            cas
+        );
+        execute(seg, request.as_bytes())
+    }
+
+    #[test]
+    fn conditional_put() {
+        let mut seg = Seg::new(&SegcacheConfig::default()).expect("failed to create storage");
+
+        let put = b"PUT /keys/drink HTTP/1.1\r\nContent-Length: 6\r\n\r\ncoffee";
+        assert_eq!(execute(&mut seg, put).status(), Status::NoContent);
+
+        let get = b"GET /keys/drink HTTP/1.1\r\n\r\n";
+        let cas = etag(&execute(&mut seg, get));
+
+        // values which only match once truncated to 32 bits are rejected
+        let response = put_if_match(&mut seg, cas + (1 << 32));
+        assert_eq!(response.status(), Status::PreconditionFailed);
+        let response = put_if_match(&mut seg, cas + 1);
+        assert_eq!(response.status(), Status::PreconditionFailed);
+        assert_eq!(etag(&execute(&mut seg, get)), cas);
+
+        let response = put_if_match(&mut seg, cas);
+        assert_eq!(response.status(), Status::NoContent);
+        let response = execute(&mut seg, get);
+        assert_eq!(response.len(), 3);
+        assert_ne!(etag(&response), cas);
+    }
+}
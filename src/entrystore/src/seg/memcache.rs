@@ -5,6 +5,7 @@
 //! This module defines how `Seg` storage will be used to execute `Memcache`
 //! storage commands.
 
+use super::rules::{compress, decompress, COMPRESSED};
 use super::*;
 use protocol_common::*;
 
@@ -14,21 +15,566 @@ use std::time::Duration;
 
 impl Execute<Request, Response> for Seg {
     fn execute(&mut self, request: &Request) -> Response {
-        match request {
+        let response = match request {
+            request if self.disabled(request) => {
+                COMMAND_DISABLED.increment();
+                return Response::client_error("command disabled");
+            }
+            Request::Get(_)
+            | Request::Gets(_)
+            | Request::Gat(_)
+            | Request::Gats(_)
+            | Request::MetaGet(_)
+                if self.shed_read() =>
+            {
+                return Response::server_error("warming up");
+            }
             Request::Get(get) => self.get(get),
             Request::Gets(gets) => self.gets(gets),
+            Request::Gat(gat) => self.gat(gat),
+            Request::Gats(gats) => self.gats(gats),
             Request::Set(set) => self.set(set),
             Request::Add(add) => self.add(add),
             Request::Replace(replace) => self.replace(replace),
             Request::Cas(cas) => self.cas(cas),
             Request::Incr(incr) => self.incr(incr),
             Request::Decr(decr) => self.decr(decr),
+            Request::MetaArithmetic(ma) => self.meta_arithmetic(ma),
+            Request::MetaDelete(md) => self.meta_delete(md),
+            Request::MetaGet(mg) => self.meta_get(mg),
+            Request::MetaNoop(mn) => self.meta_noop(mn),
+            Request::MetaSet(ms) => self.meta_set(ms),
             Request::Append(append) => self.append(append),
             Request::Prepend(prepend) => self.prepend(prepend),
             Request::Delete(delete) => self.delete(delete),
             Request::FlushAll(flush_all) => self.flush_all(flush_all),
             Request::Quit(quit) => self.quit(quit),
+            Request::Stats(stats) => self.stats(stats),
+            Request::Time(time) => self.time(time),
+            Request::Touch(touch) => self.touch(touch),
+        };
+
+        if common::namespace::sample() {
+            record_namespaces(&self.rules, request, &response);
         }
+
+        response
+    }
+}
+
+impl Seg {
+    // Returns true if the request belongs to a command family which is
+    // disabled. The meta arithmetic command belongs to both the arithmetic and
+    // the meta families, and `stats sizes` needs both of its settings.
+    fn disabled(&self, request: &Request) -> bool {
+        match request {
+            Request::Incr(_) | Request::Decr(_) => !self.commands.arithmetic,
+            Request::MetaArithmetic(_) => !(self.commands.arithmetic && self.commands.meta),
+            Request::MetaDelete(_)
+            | Request::MetaGet(_)
+            | Request::MetaNoop(_)
+            | Request::MetaSet(_) => !self.commands.meta,
+            Request::FlushAll(_) => !self.commands.flush,
+            Request::Stats(stats) => match stats.group() {
+                StatsGroup::Sizes => !(self.commands.stats && self.commands.stats_sizes),
+                _ => !self.commands.stats,
+            },
+            _ => false,
+        }
+    }
+
+    // Reads an item and sets its TTL as for a touch.
+    fn get_and_touch(&mut self, key: &[u8], ttl: Ttl, cas: bool) -> Result<Value, StorageError> {
+        let item = match self.data.get(key) {
+            Some(item) => item,
+            None => {
+                return Ok(Value::none(key));
+            }
+        };
+        self.stale_read(key);
+
+        let o = item.optional().unwrap_or(&[0, 0, 0, 0]);
+        let flags = u32::from_be_bytes([o[0], o[1], o[2], o[3]]);
+        let data = match item.value() {
+            seg::Value::Bytes(b) => decompress(item.optional(), b)?.into_owned(),
+            seg::Value::U64(v) => format!("{}", v).into_bytes(),
+        };
+        let cas_value = self.touch_read(key, ttl).unwrap_or_else(|| item.cas());
+
+        let cas = if cas { Some(cas_value.into()) } else { None };
+        Ok(Value::new(key, flags, cas, &data))
+    }
+
+    // Sets the TTL of an item which was just read, as for a touch, and returns
+    // its new cas value if it was changed. The value has already been read, so
+    // it is returned even if a caching rule denies writes to the key, but the
+    // TTL is only changed if writes are allowed. An item which is only served
+    // while stale has already expired and is not brought back by the touch.
+    fn touch_read(&mut self, key: &[u8], ttl: Ttl) -> Option<u32> {
+        let action = self.rules.evaluate(key);
+        if action.deny || self.data.ttl(key).is_none() {
+            return None;
+        }
+
+        let ttl = ttl.get().unwrap_or(0);
+        if ttl < 0 {
+            // immediate expire maps to a delete, after the value is read
+            self.data.delete(key);
+            return None;
+        }
+
+        let ttl = action
+            .ttl
+            .unwrap_or_else(|| Duration::from_secs(ttl as u64));
+
+        // the touch is a write, so the item has a new cas value
+        match self.data.touch(key, ttl) {
+            Ok(item) => Some(item.cas()),
+            Err(e) => {
+                storage_error(e);
+                None
+            }
+        }
+    }
+
+    // Stores the item unless the write duplicates a recent one. A negative
+    // TTL removes the item instead.
+    fn set_item(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        ttl: Ttl,
+        noreply: bool,
+    ) -> Response {
+        let action = self.rules.evaluate(key);
+        if action.deny {
+            return denied();
+        }
+
+        let ttl = ttl.get().unwrap_or(0);
+
+        if ttl < 0 {
+            // immediate expire maps to a delete
+            self.data.delete(key);
+            return Response::stored(noreply);
+        }
+
+        let ttl = action
+            .ttl
+            .unwrap_or_else(|| Duration::from_secs(ttl as u64));
+
+        let fingerprint = self.write_fingerprint(key, value, flags, ttl);
+        if self.duplicate_write(key, fingerprint) {
+            return Response::stored(noreply);
+        }
+
+        let result = self.store(key, value, flags, ttl, action.compress, None);
+
+        if result.is_ok() {
+            self.record_write(key, fingerprint);
+        }
+
+        store_response(result, noreply)
+    }
+
+    // Stores the item only if the key is not already stored.
+    fn add_item(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        ttl: Ttl,
+        noreply: bool,
+    ) -> Response {
+        let action = self.rules.evaluate(key);
+        if action.deny {
+            return denied();
+        }
+
+        if self.data.get_no_freq_incr(key).is_some() {
+            return Response::not_stored(noreply);
+        }
+
+        let ttl = ttl.get().unwrap_or(0);
+
+        if ttl < 0 {
+            // immediate expire maps to a delete
+            self.data.delete(key);
+            return Response::stored(noreply);
+        }
+
+        let ttl = action
+            .ttl
+            .unwrap_or_else(|| Duration::from_secs(ttl as u64));
+
+        let result = self.store(key, value, flags, ttl, action.compress, None);
+
+        store_response(result, noreply)
+    }
+
+    // Stores the item only if the key is already stored.
+    fn replace_item(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        ttl: Ttl,
+        noreply: bool,
+    ) -> Response {
+        let action = self.rules.evaluate(key);
+        if action.deny {
+            return denied();
+        }
+
+        if self.data.get_no_freq_incr(key).is_none() {
+            return Response::not_stored(noreply);
+        }
+
+        let ttl = ttl.get().unwrap_or(0);
+
+        if ttl < 0 {
+            // immediate expire maps to a delete
+            self.data.delete(key);
+            return Response::stored(noreply);
+        }
+
+        let ttl = action
+            .ttl
+            .unwrap_or_else(|| Duration::from_secs(ttl as u64));
+
+        let result = self.store(key, value, flags, ttl, action.compress, None);
+
+        store_response(result, noreply)
+    }
+
+    // Stores the item only if the cas value of the stored item matches.
+    fn cas_item(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        ttl: Ttl,
+        cas: u64,
+        noreply: bool,
+    ) -> Response {
+        let action = self.rules.evaluate(key);
+        if action.deny {
+            return denied();
+        }
+
+        // duration of zero is treated as no expiry. as we have
+        // no way of checking the cas value without performing a cas
+        // and checking the result, setting the shortest possible ttl
+        // results in nearly immediate expiry
+        let ttl = ttl.get().unwrap_or(1);
+
+        let ttl = if ttl < 0 {
+            Duration::from_secs(0)
+        } else {
+            action
+                .ttl
+                .unwrap_or_else(|| Duration::from_secs(ttl as u64))
+        };
+
+        let result = self.store(key, value, flags, ttl, action.compress, Some(cas as u32));
+
+        store_response(result, noreply)
+    }
+
+    // The statistics memcached reports for `stats` which seg has an
+    // equivalent for, so that tools which read them from the data port work.
+    // The counters of commands which memcached counts together are summed.
+    fn general_stats(&mut self, stats: &mut Statistics) {
+        let metrics = metric_values();
+        let sum = |names: &[&str]| -> i64 {
+            names
+                .iter()
+                .map(|name| metrics.get(*name).copied().unwrap_or(0))
+                .sum()
+        };
+
+        stats.push("pid", std::process::id());
+        stats.push("uptime", self.clock.uptime().as_secs());
+        stats.push("time", self.clock.unix_time().as_secs());
+        stats.push("curr_connections", sum(&["tcp_conn_curr"]));
+        stats.push("total_connections", sum(&["tcp_accept"]));
+        stats.push(
+            "cmd_get",
+            sum(&["get_key", "gets_key", "gat_key", "gats_key", "mg"]),
+        );
+        stats.push(
+            "cmd_set",
+            sum(&["set", "add", "replace", "append", "prepend", "cas", "ms"]),
+        );
+        stats.push("cmd_touch", sum(&["touch", "gat", "gats"]));
+        stats.push(
+            "get_hits",
+            sum(&[
+                "get_key_hit",
+                "gets_key_hit",
+                "gat_key_hit",
+                "gats_key_hit",
+                "mg_hit",
+            ]),
+        );
+        stats.push(
+            "get_misses",
+            sum(&[
+                "get_key_miss",
+                "gets_key_miss",
+                "gat_key_miss",
+                "gats_key_miss",
+                "mg_miss",
+            ]),
+        );
+        stats.push("delete_hits", sum(&["delete_deleted", "md_deleted"]));
+        stats.push("delete_misses", sum(&["delete_not_found", "md_not_found"]));
+        stats.push("cas_hits", sum(&["cas_stored"]));
+        stats.push("cas_badval", sum(&["cas_exists"]));
+        stats.push("cas_misses", sum(&["cas_not_found"]));
+        stats.push("touch_hits", sum(&["touch_touched"]));
+        stats.push("touch_misses", sum(&["touch_not_found"]));
+        stats.push("curr_items", self.data.live_items());
+        stats.push("bytes", sum(&["item_current_bytes"]));
+        stats.push("limit_maxbytes", self.heap_size);
+        stats.push("evictions", sum(&["item_evict"]));
+        stats.push("expired_items", sum(&["item_expire"]));
+    }
+
+    // Items are grouped by TTL bucket rather than by slab class, so the index
+    // of the bucket takes the place of the class in each name.
+    fn item_stats(&mut self, stats: &mut Statistics) {
+        for bucket in self.ttl_bucket_info() {
+            let prefix = format!("items:{}", bucket.index);
+            stats.push(&format!("{}:number", prefix), bucket.live_items);
+            stats.push(&format!("{}:age", prefix), bucket.oldest.as_secs());
+            stats.push(&format!("{}:ttl", prefix), bucket.ttl.as_secs());
+        }
+    }
+
+    // The segments of each TTL bucket take the place of the pages of a slab
+    // class, and each live item takes the place of a used chunk.
+    fn segment_stats(&mut self, stats: &mut Statistics) {
+        let buckets = self.ttl_bucket_info();
+        let segments: usize = buckets.iter().map(|bucket| bucket.segments).sum();
+
+        for bucket in &buckets {
+            let prefix = bucket.index;
+            stats.push(&format!("{}:ttl", prefix), bucket.ttl.as_secs());
+            stats.push(&format!("{}:segment_size", prefix), self.segment_size);
+            stats.push(&format!("{}:total_pages", prefix), bucket.segments);
+            stats.push(&format!("{}:used_chunks", prefix), bucket.live_items);
+            stats.push(&format!("{}:live_bytes", prefix), bucket.live_bytes);
+            stats.push(&format!("{}:dead_bytes", prefix), bucket.dead_bytes);
+            stats.push(&format!("{}:merged", prefix), bucket.merged);
+        }
+        stats.push("active_slabs", buckets.len());
+        stats.push("total_malloced", segments * self.segment_size);
+    }
+
+    // The number of items of each size, rounded up to a multiple of 32 bytes
+    // as memcached does. This reads every item, so as with memcached it is
+    // expensive for a large cache, and it is disabled unless the config
+    // enables it. The sampled sizes of writes are a cheaper alternative.
+    fn size_stats(&mut self, stats: &mut Statistics) {
+        let mut sizes = std::collections::BTreeMap::<usize, u64>::new();
+        for key in self.data.keys_with_prefix(b"") {
+            if let Some(item) = self.data.get_no_freq_incr(&key) {
+                let value = match item.value() {
+                    seg::Value::Bytes(b) => b.len(),
+                    seg::Value::U64(_) => std::mem::size_of::<u64>(),
+                };
+                let size = key.len() + value + item.optional().map(|o| o.len()).unwrap_or(0);
+                *sizes.entry((size + 31) / 32 * 32).or_default() += 1;
+            }
+        }
+
+        for (size, count) in sizes {
+            stats.push(&size.to_string(), count);
+        }
+    }
+
+    // Stores the item, or swaps it if the cas value is given. Numeric values
+    // are stored as integers so they can be incremented, while other values
+    // are stored compressed if a caching rule requires it.
+    fn store(
+        &mut self,
+        key: &[u8],
+        value: &[u8],
+        flags: u32,
+        ttl: Duration,
+        compressed: bool,
+        cas: Option<u32>,
+    ) -> Result<(), SegError> {
+        let flags = flags.to_be_bytes();
+
+        if let Some(v) = std::str::from_utf8(value)
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+        {
+            return match cas {
+                Some(cas) => self.data.cas(key, v, Some(&flags), ttl, cas),
+                None => self.data.insert(key, v, Some(&flags), ttl),
+            };
+        }
+
+        let compressed = if compressed { compress(value) } else { None };
+        let optional = [flags[0], flags[1], flags[2], flags[3], COMPRESSED];
+        let (value, optional) = match compressed.as_ref() {
+            Some(compressed) => (&compressed[..], &optional[..]),
+            None => (value, &flags[..]),
+        };
+
+        match cas {
+            Some(cas) => self.data.cas(key, value, Some(optional), ttl, cas),
+            None => self.data.insert(key, value, Some(optional), ttl),
+        }
+    }
+
+    // Stores a new item which holds the value of the existing item with the
+    // data added to its end, or to its start for a prepend. As in memcached,
+    // the flags and expiry of the existing item are kept, and nothing is
+    // stored if there is no existing item.
+    fn concatenate(&mut self, key: &[u8], data: &[u8], prepend: bool, noreply: bool) -> Response {
+        let action = self.rules.evaluate(key);
+        if action.deny {
+            return denied();
+        }
+
+        let item = match self.data.get_no_freq_incr(key) {
+            Some(item) => item,
+            None => {
+                return Response::not_stored(noreply);
+            }
+        };
+
+        let ttl = match self.data.ttl(key) {
+            Some(ttl) => ttl,
+            None => {
+                return Response::not_stored(noreply);
+            }
+        };
+
+        let o = item.optional().unwrap_or(&[0, 0, 0, 0]);
+        let flags = u32::from_be_bytes([o[0], o[1], o[2], o[3]]);
+
+        let current = match item.value() {
+            seg::Value::Bytes(b) => match decompress(item.optional(), b) {
+                Ok(b) => b.into_owned(),
+                Err(e) => {
+                    return error_response(e, noreply);
+                }
+            },
+            seg::Value::U64(v) => format!("{}", v).into_bytes(),
+        };
+
+        let value = if prepend {
+            [data, &current].concat()
+        } else {
+            [&current, data].concat()
+        };
+
+        let result = self.store(key, &value, flags, ttl, action.compress, None);
+
+        store_response(result, noreply)
+    }
+}
+
+/// Attribute a sampled request to the namespaces of the keys it operates on.
+/// Writes which match a caching rule with a namespace are attributed to that
+/// namespace.
+fn record_namespaces(rules: &Rules, request: &Request, response: &Response) {
+    use common::namespace::{record_delete, record_read, record_write};
+
+    let stored = match response {
+        Response::Stored(_) => true,
+        Response::Meta(meta) => meta.code() == MetaCode::Header,
+        _ => false,
+    };
+    let stored_bytes = |value: &[u8]| if stored { value.len() } else { 0 };
+    let write = |key: &[u8], bytes: usize| record_write(&rules.attribute(key), bytes);
+
+    match request {
+        Request::Get(_) | Request::Gets(_) | Request::Gat(_) | Request::Gats(_) => {
+            if let Response::Values(values) = response {
+                for value in values.values() {
+                    record_read(value.key(), value.value().is_some());
+                }
+            }
+        }
+        Request::Set(r) => write(r.key(), stored_bytes(r.value())),
+        Request::Add(r) => write(r.key(), stored_bytes(r.value())),
+        Request::Replace(r) => write(r.key(), stored_bytes(r.value())),
+        Request::Cas(r) => write(r.key(), stored_bytes(r.value())),
+        Request::Append(r) => write(r.key(), stored_bytes(r.value())),
+        Request::Prepend(r) => write(r.key(), stored_bytes(r.value())),
+        Request::Incr(r) => write(r.key(), 0),
+        Request::Decr(r) => write(r.key(), 0),
+        Request::MetaArithmetic(r) => write(r.key(), 0),
+        Request::MetaGet(r) => {
+            if let Response::Meta(meta) = response {
+                let hit = matches!(meta.code(), MetaCode::Header | MetaCode::Value);
+                record_read(r.key(), hit);
+            }
+        }
+        Request::MetaSet(r) => write(r.key(), stored_bytes(r.value())),
+        Request::Touch(r) => write(r.key(), 0),
+        Request::Delete(r) => record_delete(r.key()),
+        Request::MetaDelete(r) => record_delete(r.key()),
+        Request::FlushAll(_)
+        | Request::MetaNoop(_)
+        | Request::Quit(_)
+        | Request::Stats(_)
+        | Request::Time(_) => {}
+    }
+}
+
+// The current value of each counter and gauge, by name.
+fn metric_values() -> std::collections::HashMap<String, i64> {
+    let mut values = std::collections::HashMap::new();
+    for metric in &rustcommon_metrics::metrics() {
+        let any = match metric.as_any() {
+            Some(any) => any,
+            None => {
+                continue;
+            }
+        };
+
+        if let Some(counter) = any.downcast_ref::<rustcommon_metrics::Counter>() {
+            values.insert(metric.name().to_string(), counter.value() as i64);
+        } else if let Some(gauge) = any.downcast_ref::<rustcommon_metrics::Gauge>() {
+            values.insert(metric.name().to_string(), gauge.value());
+        }
+    }
+    values
+}
+
+/// The response to a write which is denied by a caching rule.
+fn denied() -> Response {
+    Response::client_error("denied by rule")
+}
+
+/// Map a storage error to a response. A write which was rejected by the
+/// admission policy is reported as not stored, while failures which the client
+/// cannot resolve by retrying the same request are reported as errors.
+fn error_response(error: StorageError, noreply: bool) -> Response {
+    match error {
+        StorageError::NotFound => Response::not_found(noreply),
+        StorageError::CasMismatch => Response::exists(noreply),
+        StorageError::NotAdmitted => Response::not_stored(noreply),
+        StorageError::NotNumeric | StorageError::Overflow => Response::client_error(error),
+        StorageError::OutOfMemory | StorageError::ItemTooLarge | StorageError::Internal => {
+            Response::server_error(error)
+        }
+    }
+}
+
+/// Map the result of storing an item to a response.
+fn store_response<T>(result: Result<T, SegError>, noreply: bool) -> Response {
+    match result {
+        Ok(_) => Response::stored(noreply),
+        Err(e) => error_response(storage_error(e), noreply),
     }
 }
 
@@ -37,12 +583,16 @@ impl Storage for Seg {
         let mut values = Vec::with_capacity(get.keys().len());
         for key in get.keys().iter() {
             if let Some(item) = self.data.get(key) {
+                self.stale_read(key);
                 let o = item.optional().unwrap_or(&[0, 0, 0, 0]);
                 let flags = u32::from_be_bytes([o[0], o[1], o[2], o[3]]);
                 match item.value() {
-                    seg::Value::Bytes(b) => {
-                        values.push(Value::new(item.key(), flags, None, b));
-                    }
+                    seg::Value::Bytes(b) => match decompress(item.optional(), b) {
+                        Ok(b) => values.push(Value::new(item.key(), flags, None, &b)),
+                        Err(e) => {
+                            return Response::server_error(e);
+                        }
+                    },
                     seg::Value::U64(v) => {
                         values.push(Value::new(
                             item.key(),
@@ -63,12 +613,18 @@ impl Storage for Seg {
         let mut values = Vec::with_capacity(get.keys().len());
         for key in get.keys().iter() {
             if let Some(item) = self.data.get(key) {
+                self.stale_read(key);
                 let o = item.optional().unwrap_or(&[0, 0, 0, 0]);
                 let flags = u32::from_be_bytes([o[0], o[1], o[2], o[3]]);
                 match item.value() {
-                    seg::Value::Bytes(b) => {
-                        values.push(Value::new(item.key(), flags, Some(item.cas().into()), b));
-                    }
+                    seg::Value::Bytes(b) => match decompress(item.optional(), b) {
+                        Ok(b) => {
+                            values.push(Value::new(item.key(), flags, Some(item.cas().into()), &b))
+                        }
+                        Err(e) => {
+                            return Response::server_error(e);
+                        }
+                    },
                     seg::Value::U64(v) => {
                         values.push(Value::new(
                             item.key(),
@@ -85,262 +641,233 @@ impl Storage for Seg {
         Values::new(values.into_boxed_slice()).into()
     }
 
-    fn set(&mut self, set: &Set) -> Response {
-        let ttl = set.ttl().get().unwrap_or(0);
-
-        if ttl < 0 {
-            // immediate expire maps to a delete
-            self.data.delete(set.key());
-            Response::stored(set.noreply())
-        } else if let Ok(s) = std::str::from_utf8(set.value()) {
-            if let Ok(v) = s.parse::<u64>() {
-                if self
-                    .data
-                    .insert(
-                        set.key(),
-                        v,
-                        Some(&set.flags().to_be_bytes()),
-                        Duration::from_secs(ttl as u64),
-                    )
-                    .is_ok()
-                {
-                    Response::stored(set.noreply())
-                } else {
-                    Response::server_error("")
-                }
-            } else if self
-                .data
-                .insert(
-                    set.key(),
-                    set.value(),
-                    Some(&set.flags().to_be_bytes()),
-                    Duration::from_secs(ttl as u64),
-                )
-                .is_ok()
-            {
-                Response::stored(set.noreply())
-            } else {
-                Response::server_error("")
-            }
-        } else if self
-            .data
-            .insert(
-                set.key(),
-                set.value(),
-                Some(&set.flags().to_be_bytes()),
-                Duration::from_secs(ttl as u64),
-            )
-            .is_ok()
-        {
-            Response::stored(set.noreply())
-        } else {
-            Response::server_error("")
+    fn gat(&mut self, gat: &Gat) -> Response {
+        let values: Result<Vec<Value>, StorageError> = gat
+            .keys()
+            .iter()
+            .map(|key| self.get_and_touch(key, gat.ttl(), false))
+            .collect();
+        match values {
+            Ok(values) => Values::new(values.into_boxed_slice()).into(),
+            Err(e) => Response::server_error(e),
         }
     }
 
-    fn add(&mut self, add: &Add) -> Response {
-        if self.data.get_no_freq_incr(add.key()).is_some() {
-            return Response::not_stored(add.noreply());
+    fn gats(&mut self, gats: &Gats) -> Response {
+        let values: Result<Vec<Value>, StorageError> = gats
+            .keys()
+            .iter()
+            .map(|key| self.get_and_touch(key, gats.ttl(), true))
+            .collect();
+        match values {
+            Ok(values) => Values::new(values.into_boxed_slice()).into(),
+            Err(e) => Response::server_error(e),
         }
+    }
 
-        let ttl = add.ttl().get().unwrap_or(0);
+    fn set(&mut self, set: &Set) -> Response {
+        self.set_item(
+            set.key(),
+            set.value(),
+            set.flags(),
+            set.ttl(),
+            set.noreply(),
+        )
+    }
 
-        if ttl < 0 {
-            // immediate expire maps to a delete
-            self.data.delete(add.key());
-            Response::stored(add.noreply())
-        } else if let Ok(s) = std::str::from_utf8(add.value()) {
-            if let Ok(v) = s.parse::<u64>() {
-                if self
-                    .data
-                    .insert(
-                        add.key(),
-                        v,
-                        Some(&add.flags().to_be_bytes()),
-                        Duration::from_secs(ttl as u64),
-                    )
-                    .is_ok()
-                {
-                    Response::stored(add.noreply())
-                } else {
-                    Response::server_error("")
-                }
-            } else if self
-                .data
-                .insert(
-                    add.key(),
-                    add.value(),
-                    Some(&add.flags().to_be_bytes()),
-                    Duration::from_secs(ttl as u64),
-                )
-                .is_ok()
-            {
-                Response::stored(add.noreply())
-            } else {
-                Response::server_error("")
-            }
-        } else if self
-            .data
-            .insert(
-                add.key(),
-                add.value(),
-                Some(&add.flags().to_be_bytes()),
-                Duration::from_secs(ttl as u64),
-            )
-            .is_ok()
-        {
-            Response::stored(add.noreply())
-        } else {
-            Response::server_error("")
-        }
+    fn add(&mut self, add: &Add) -> Response {
+        self.add_item(
+            add.key(),
+            add.value(),
+            add.flags(),
+            add.ttl(),
+            add.noreply(),
+        )
     }
 
     fn replace(&mut self, replace: &Replace) -> Response {
-        if self.data.get_no_freq_incr(replace.key()).is_none() {
-            return Response::not_stored(replace.noreply());
-        }
-
-        let ttl = replace.ttl().get().unwrap_or(0);
-
-        if ttl < 0 {
-            // immediate expire maps to a delete
-            self.data.delete(replace.key());
-            Response::stored(replace.noreply())
-        } else if let Ok(s) = std::str::from_utf8(replace.value()) {
-            if let Ok(v) = s.parse::<u64>() {
-                if self
-                    .data
-                    .insert(
-                        replace.key(),
-                        v,
-                        Some(&replace.flags().to_be_bytes()),
-                        Duration::from_secs(ttl as u64),
-                    )
-                    .is_ok()
-                {
-                    Response::stored(replace.noreply())
-                } else {
-                    Response::server_error("")
-                }
-            } else if self
-                .data
-                .insert(
-                    replace.key(),
-                    replace.value(),
-                    Some(&replace.flags().to_be_bytes()),
-                    Duration::from_secs(ttl as u64),
-                )
-                .is_ok()
-            {
-                Response::stored(replace.noreply())
-            } else {
-                Response::server_error("")
-            }
-        } else if self
-            .data
-            .insert(
-                replace.key(),
-                replace.value(),
-                Some(&replace.flags().to_be_bytes()),
-                Duration::from_secs(ttl as u64),
-            )
-            .is_ok()
-        {
-            Response::stored(replace.noreply())
-        } else {
-            Response::server_error("")
-        }
+        self.replace_item(
+            replace.key(),
+            replace.value(),
+            replace.flags(),
+            replace.ttl(),
+            replace.noreply(),
+        )
     }
 
-    fn append(&mut self, _: &Append) -> Response {
-        Response::error()
+    fn append(&mut self, append: &Append) -> Response {
+        self.concatenate(append.key(), append.value(), false, append.noreply())
     }
 
-    fn prepend(&mut self, _: &Prepend) -> Response {
-        Response::error()
+    fn prepend(&mut self, prepend: &Prepend) -> Response {
+        self.concatenate(prepend.key(), prepend.value(), true, prepend.noreply())
     }
 
     fn incr(&mut self, incr: &Incr) -> Response {
-        match self.data.wrapping_add(incr.key(), incr.value()) {
+        match self.data.add(incr.key(), incr.value(), self.overflow) {
             Ok(item) => match item.value() {
                 seg::Value::U64(v) => Response::numeric(v, incr.noreply()),
                 _ => Response::server_error(""),
             },
-            Err(SegError::NotFound) => Response::not_found(incr.noreply()),
-            Err(SegError::NotNumeric) => Response::error(),
-            Err(_) => Response::server_error(""),
+            Err(e) => error_response(storage_error(e), incr.noreply()),
         }
     }
 
     fn decr(&mut self, decr: &Decr) -> Response {
-        match self.data.saturating_sub(decr.key(), decr.value()) {
+        match self.data.sub(decr.key(), decr.value(), self.underflow) {
             Ok(item) => match item.value() {
                 seg::Value::U64(v) => Response::numeric(v, decr.noreply()),
                 _ => Response::server_error(""),
             },
-            Err(SegError::NotFound) => Response::not_found(decr.noreply()),
-            Err(SegError::NotNumeric) => Response::error(),
-            Err(_) => Response::server_error(""),
+            Err(e) => error_response(storage_error(e), decr.noreply()),
         }
     }
 
-    fn cas(&mut self, cas: &Cas) -> Response {
-        // duration of zero is treated as no expiry. as we have
-        // no way of checking the cas value without performing a cas
-        // and checking the result, setting the shortest possible ttl
-        // results in nearly immediate expiry
-        let ttl = cas.ttl().get().unwrap_or(1);
+    fn meta_arithmetic(&mut self, ma: &MetaArithmetic) -> Response {
+        let key = ma.key();
+        let flags = 0u32.to_be_bytes();
 
-        let ttl = if ttl < 0 {
-            Duration::from_secs(0)
-        } else {
-            Duration::from_secs(ttl as u64)
+        let result = match (ma.mode(), ma.autovivify()) {
+            (ArithmeticMode::Incr, None) => self.data.add(key, ma.delta(), self.overflow),
+            (ArithmeticMode::Decr, None) => self.data.sub(key, ma.delta(), self.underflow),
+            (ArithmeticMode::Incr, Some(ttl)) => self.data.add_or_insert(
+                key,
+                ma.delta(),
+                self.overflow,
+                ma.initial(),
+                Some(&flags),
+                Duration::from_secs(ttl.into()),
+            ),
+            (ArithmeticMode::Decr, Some(ttl)) => self.data.sub_or_insert(
+                key,
+                ma.delta(),
+                self.underflow,
+                ma.initial(),
+                Some(&flags),
+                Duration::from_secs(ttl.into()),
+            ),
         };
 
-        if let Ok(s) = std::str::from_utf8(cas.value()) {
-            if let Ok(v) = s.parse::<u64>() {
-                match self.data.cas(
-                    cas.key(),
-                    v,
-                    Some(&cas.flags().to_be_bytes()),
-                    ttl,
-                    cas.cas() as u32,
-                ) {
-                    Ok(_) => Response::stored(cas.noreply()),
-                    Err(SegError::NotFound) => Response::not_found(cas.noreply()),
-                    Err(SegError::Exists) => Response::exists(cas.noreply()),
-                    Err(_) => Response::error(),
+        match result {
+            Ok(item) => match item.value() {
+                seg::Value::U64(v) => ma.hit(v, item.cas().into()),
+                _ => Response::server_error(""),
+            },
+            Err(e) => match storage_error(e) {
+                StorageError::NotFound => ma.miss(),
+                StorageError::NotAdmitted | StorageError::CasMismatch => ma.not_stored(),
+                error => error_response(error, false),
+            },
+        }
+    }
+
+    fn meta_delete(&mut self, md: &MetaDelete) -> Response {
+        if let Some(cas) = md.cas() {
+            match self.data.get_no_freq_incr(md.key()) {
+                Some(item) if u64::from(item.cas()) != cas => {
+                    return md.exists();
                 }
-            } else {
-                match self.data.cas(
-                    cas.key(),
-                    cas.value(),
-                    Some(&cas.flags().to_be_bytes()),
-                    ttl,
-                    cas.cas() as u32,
-                ) {
-                    Ok(_) => Response::stored(cas.noreply()),
-                    Err(SegError::NotFound) => Response::not_found(cas.noreply()),
-                    Err(SegError::Exists) => Response::exists(cas.noreply()),
-                    Err(_) => Response::error(),
+                Some(_) => {}
+                None => {
+                    return md.not_found();
                 }
             }
+        }
+
+        if self.data.delete(md.key()) {
+            md.deleted()
         } else {
-            match self.data.cas(
-                cas.key(),
-                cas.value(),
-                Some(&cas.flags().to_be_bytes()),
-                ttl,
-                cas.cas() as u32,
-            ) {
-                Ok(_) => Response::stored(cas.noreply()),
-                Err(SegError::NotFound) => Response::not_found(cas.noreply()),
-                Err(SegError::Exists) => Response::exists(cas.noreply()),
-                Err(_) => Response::error(),
+            md.not_found()
+        }
+    }
+
+    fn meta_get(&mut self, mg: &MetaGet) -> Response {
+        let key = mg.key();
+
+        // this must be checked before the read below counts as an access
+        let accessed = mg.return_hit() && self.data.accessed(key).unwrap_or(false);
+
+        let item = match self.data.get(key) {
+            Some(item) => item,
+            None => {
+                return mg.miss();
+            }
+        };
+        let stale = self.stale_read(key);
+
+        let o = item.optional().unwrap_or(&[0, 0, 0, 0]);
+        let flags = u32::from_be_bytes([o[0], o[1], o[2], o[3]]);
+        let data = match item.value() {
+            seg::Value::Bytes(b) => match decompress(item.optional(), b) {
+                Ok(b) => b.into_owned(),
+                Err(e) => {
+                    return Response::server_error(e);
+                }
+            },
+            seg::Value::U64(v) => format!("{}", v).into_bytes(),
+        };
+
+        let cas = match mg.ttl() {
+            Some(ttl) => self.touch_read(key, ttl).unwrap_or_else(|| item.cas()),
+            None => item.cas(),
+        };
+
+        // items which are stored without an expiry report no TTL, and stale
+        // items report zero
+        let ttl = match self.data.ttl(key) {
+            Some(ttl) if ttl.is_zero() => None,
+            ttl => Some(ttl.map(|ttl| ttl.as_secs()).unwrap_or(0)),
+        };
+
+        mg.hit(&data, flags, cas.into(), ttl, stale, accessed)
+    }
+
+    fn meta_noop(&mut self, mn: &MetaNoop) -> Response {
+        mn.response()
+    }
+
+    fn meta_set(&mut self, ms: &MetaSet) -> Response {
+        let (key, value, flags, ttl) = (ms.key(), ms.value(), ms.flags(), ms.ttl());
+
+        // appends and prepends keep the flags and TTL of the existing item
+        let response = match (ms.mode(), ms.cas()) {
+            (SetMode::Set, Some(cas)) => self.cas_item(key, value, flags, ttl, cas, false),
+            (SetMode::Set, None) => self.set_item(key, value, flags, ttl, false),
+            (SetMode::Add, _) => self.add_item(key, value, flags, ttl, false),
+            (SetMode::Replace, _) => self.replace_item(key, value, flags, ttl, false),
+            (SetMode::Append, _) => self.concatenate(key, value, false, false),
+            (SetMode::Prepend, _) => self.concatenate(key, value, true, false),
+        };
+
+        match response {
+            Response::Stored(_) => {
+                let cas = if ms.return_cas() {
+                    self.data
+                        .get_no_freq_incr(key)
+                        .map(|item| item.cas().into())
+                } else {
+                    None
+                };
+                ms.stored(cas)
             }
+            Response::NotStored(_) => ms.not_stored(),
+            Response::Exists(_) => ms.exists(),
+            Response::NotFound(_) => ms.not_found(),
+            response => response,
         }
     }
 
+    fn cas(&mut self, cas: &Cas) -> Response {
+        self.cas_item(
+            cas.key(),
+            cas.value(),
+            cas.flags(),
+            cas.ttl(),
+            cas.cas(),
+            cas.noreply(),
+        )
+    }
+
     fn delete(&mut self, delete: &Delete) -> Response {
         if self.data.delete(delete.key()) {
             Response::deleted(delete.noreply())
@@ -356,4 +883,246 @@ impl Storage for Seg {
     fn quit(&mut self, _quit: &Quit) -> Response {
         Response::hangup()
     }
+
+    fn stats(&mut self, stats: &Stats) -> Response {
+        let mut statistics = Statistics::default();
+        match stats.group() {
+            StatsGroup::General => self.general_stats(&mut statistics),
+            StatsGroup::Items => self.item_stats(&mut statistics),
+            StatsGroup::Segments => self.segment_stats(&mut statistics),
+            StatsGroup::Sizes => self.size_stats(&mut statistics),
+            // the counters of the session are added by the worker
+            StatsGroup::Me => {}
+        }
+        Response::Statistics(statistics)
+    }
+
+    fn time(&mut self, _time: &Time) -> Response {
+        Response::server_time(self.clock.unix_time(), self.clock.uptime())
+    }
+
+    fn touch(&mut self, touch: &Touch) -> Response {
+        let action = self.rules.evaluate(touch.key());
+        if action.deny {
+            return denied();
+        }
+
+        // an item which is only served while stale has already expired
+        if self.data.ttl(touch.key()).is_none() {
+            return Response::not_found(touch.noreply());
+        }
+
+        let ttl = touch.ttl().get().unwrap_or(0);
+
+        if ttl < 0 {
+            // immediate expire maps to a delete
+            self.data.delete(touch.key());
+            return Response::touched(touch.noreply());
+        }
+
+        let ttl = action
+            .ttl
+            .unwrap_or_else(|| Duration::from_secs(ttl as u64));
+
+        match self.data.touch(touch.key(), ttl) {
+            Ok(_) => Response::touched(touch.noreply()),
+            Err(e) => error_response(storage_error(e), touch.noreply()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::SegcacheConfig;
+
+    fn execute(seg: &mut Seg, request: &[u8]) -> String {
+        let request = RequestParser::new()
+            .parse(request)
+            .expect("failed to parse request")
+            .into_inner();
+        let mut buffer = Vec::new();
+        seg.execute(&request).compose(&mut buffer);
+        String::from_utf8(buffer).unwrap()
+    }
+
+    // appending to or prepending to an item which was stored without a ttl
+    // must not give it one
+    #[test]
+    fn concatenate_without_ttl() {
+        let mut seg = Seg::new(&SegcacheConfig::default()).expect("failed to create storage");
+
+        assert_eq!(
+            execute(&mut seg, b"set drink 0 0 3\r\ntea\r\n"),
+            "STORED\r\n"
+        );
+        assert_eq!(seg.data.ttl(b"drink"), Some(Duration::ZERO));
+
+        assert_eq!(
+            execute(&mut seg, b"append drink 0 0 4\r\n+dew\r\n"),
+            "STORED\r\n"
+        );
+        assert_eq!(seg.data.ttl(b"drink"), Some(Duration::ZERO));
+
+        assert_eq!(
+            execute(&mut seg, b"prepend drink 0 0 6\r\ngreen \r\n"),
+            "STORED\r\n"
+        );
+        assert_eq!(seg.data.ttl(b"drink"), Some(Duration::ZERO));
+
+        assert_eq!(
+            execute(&mut seg, b"get drink\r\n"),
+            "VALUE drink 0 13\r\ngreen tea+dew\r\nEND\r\n"
+        );
+    }
+
+    #[test]
+    fn concatenate_keeps_ttl() {
+        let mut seg = Seg::new(&SegcacheConfig::default()).expect("failed to create storage");
+
+        assert_eq!(
+            execute(&mut seg, b"set drink 0 60 3\r\ntea\r\n"),
+            "STORED\r\n"
+        );
+        assert_eq!(
+            execute(&mut seg, b"append drink 0 0 4\r\n+dew\r\n"),
+            "STORED\r\n"
+        );
+        let ttl = seg.data.ttl(b"drink").expect("not found");
+        assert!(ttl > Duration::ZERO && ttl <= Duration::from_secs(60));
+    }
+
+    const DISABLED: &str = "CLIENT_ERROR command disabled\r\n";
+
+    // storage with the command families enabled as given by the config
+    fn with_commands(arithmetic: bool, flush: bool, meta: bool) -> Seg {
+        let config: SegcacheConfig = toml::from_str(&format!(
+            "[commands]\narithmetic = {}\nflush = {}\nmeta = {}\n",
+            arithmetic, flush, meta
+        ))
+        .expect("invalid config");
+        Seg::new(&config).expect("failed to create storage")
+    }
+
+    #[test]
+    fn disabled_by_config() {
+        let mut seg = with_commands(false, false, false);
+
+        assert_eq!(execute(&mut seg, b"incr count 1\r\n"), DISABLED);
+        assert_eq!(execute(&mut seg, b"decr count 1\r\n"), DISABLED);
+        assert_eq!(execute(&mut seg, b"flush_all\r\n"), DISABLED);
+        assert_eq!(execute(&mut seg, b"mn\r\n"), DISABLED);
+        assert_eq!(execute(&mut seg, b"mg count v\r\n"), DISABLED);
+        assert_eq!(execute(&mut seg, b"ma count\r\n"), DISABLED);
+
+        // other commands are still served
+        assert_eq!(execute(&mut seg, b"set count 0 0 1\r\n1\r\n"), "STORED\r\n");
+        assert_eq!(
+            execute(&mut seg, b"get count\r\n"),
+            "VALUE count 0 1\r\n1\r\nEND\r\n"
+        );
+
+        // meta arithmetic requires both of its families
+        let mut seg = with_commands(true, true, false);
+        assert_eq!(execute(&mut seg, b"ma count\r\n"), DISABLED);
+        let mut seg = with_commands(false, true, true);
+        assert_eq!(execute(&mut seg, b"ma count\r\n"), DISABLED);
+        assert_eq!(execute(&mut seg, b"mn\r\n"), "MN\r\n");
+    }
+
+    #[test]
+    fn disabled_stats() {
+        // stats sizes reads every item, so it is disabled by default
+        let mut seg = Seg::new(&SegcacheConfig::default()).expect("failed to create storage");
+        assert!(execute(&mut seg, b"stats\r\n").starts_with("STAT "));
+        assert_eq!(execute(&mut seg, b"stats sizes\r\n"), DISABLED);
+
+        let config: SegcacheConfig =
+            toml::from_str("[commands]\nstats_sizes = true\n").expect("invalid config");
+        let mut seg = Seg::new(&config).expect("failed to create storage");
+        assert_eq!(
+            execute(&mut seg, b"set drink 0 0 3\r\ntea\r\n"),
+            "STORED\r\n"
+        );
+        assert_eq!(
+            execute(&mut seg, b"stats sizes\r\n"),
+            "STAT 32 1\r\nEND\r\n"
+        );
+
+        // and every group is disabled with stats
+        let config: SegcacheConfig =
+            toml::from_str("[commands]\nstats = false\nstats_sizes = true\n")
+                .expect("invalid config");
+        let mut seg = Seg::new(&config).expect("failed to create storage");
+        for request in [
+            "stats",
+            "stats items",
+            "stats slabs",
+            "stats sizes",
+            "stats me",
+        ] {
+            let request = format!("{}\r\n", request);
+            assert_eq!(execute(&mut seg, request.as_bytes()), DISABLED);
+        }
+    }
+
+    #[test]
+    fn disabled_by_build() {
+        // a family which is left out of the build is disabled even though the
+        // config enables it
+        let mut seg = with_commands(true, true, true);
+
+        let expected = |enabled: bool, response: &'static str| {
+            if enabled {
+                response
+            } else {
+                DISABLED
+            }
+        };
+
+        assert_eq!(
+            execute(&mut seg, b"incr count 1\r\n"),
+            expected(cfg!(feature = "arithmetic"), "NOT_FOUND\r\n")
+        );
+        assert_eq!(
+            execute(&mut seg, b"mn\r\n"),
+            expected(cfg!(feature = "meta"), "MN\r\n")
+        );
+        assert_eq!(
+            execute(&mut seg, b"flush_all\r\n") == DISABLED,
+            !cfg!(feature = "flush")
+        );
+    }
+
+    #[test]
+    fn serve_stale() {
+        let config: SegcacheConfig =
+            toml::from_str("[seg]\nstale_grace = 10\n").expect("invalid config");
+        let mut seg = Seg::new(&config).expect("failed to create storage");
+
+        assert_eq!(
+            execute(&mut seg, b"set drink 0 1 3\r\ntea\r\n"),
+            "STORED\r\n"
+        );
+        assert_eq!(seg.serve_stale(true), Duration::from_secs(10));
+
+        // the expired item is kept and served while stale items are served,
+        // and each such read is counted
+        std::thread::sleep(Duration::from_secs(2));
+        seg.expire();
+        let reads = STALE_READ.value();
+        assert_eq!(
+            execute(&mut seg, b"get drink\r\n"),
+            "VALUE drink 0 3\r\ntea\r\nEND\r\n"
+        );
+        assert_eq!(STALE_READ.value(), reads + 1);
+
+        // once stale items are no longer served, the item is removed by the
+        // next expiration
+        assert_eq!(seg.serve_stale(false), Duration::ZERO);
+        std::thread::sleep(Duration::from_secs(1));
+        seg.expire();
+        assert_eq!(execute(&mut seg, b"get drink\r\n"), "END\r\n");
+        assert_eq!(STALE_READ.value(), reads + 1);
+    }
 }
@@ -6,24 +6,113 @@
 //! This storage type is suitable for use in simple key-value cache backends.
 //! See: [`::seg`] crate for more details behind the underlying storage design.
 
-use crate::EntryStore;
+use crate::{EntryStore, StorageError};
 
-use config::seg::Eviction;
-use config::SegConfig;
+use common::fingerprint::Fingerprint;
+use common::signal::{HashTableInfo, MigrationItem, TtlBucketInfo};
+use common::time::Clock;
+use config::seg::{Admission, Eviction, Overflow};
+use config::{CommandsConfig, RulesConfig, SegConfig};
+use rustcommon_metrics::*;
 use seg::{Policy, SegError};
 
+use std::time::{Duration, SystemTime};
+
+mod dedup;
+mod http;
 mod memcache;
+mod rules;
+
+use dedup::Dedup;
+use rules::{decompress, Rules};
+
+/// The version of the underlying [`::seg`] storage engine
+pub const SEG_VERSION: &str = ::seg::ENGINE_VERSION;
+
+// the number of hashtable buckets visited by each step of a scan of the keys.
+// each bucket holds up to seven items, plus those in its overflow chain
+const SCAN_BUCKETS: usize = 1024;
+
+gauge!(
+    WARMUP,
+    "set to 1 while storage is in the warm-up period after startup"
+);
+counter!(
+    WARMUP_SHED,
+    "number of reads rejected during the warm-up period after startup"
+);
+counter!(
+    COMMAND_DISABLED,
+    "number of requests rejected because their command is disabled"
+);
+gauge!(
+    STALE_SERVE,
+    "set to 1 while expired items are served in place of misses"
+);
+counter!(
+    STALE_READ,
+    "number of reads answered with an item past its expiry"
+);
 
 /// A wrapper around [`seg::Seg`] which implements `EntryStore` and storage
 /// protocol traits.
 pub struct Seg {
     data: ::seg::Seg,
+    clock: Clock,
+    warmup: Option<Warmup>,
+    dedup: Option<Dedup>,
+    commands: Commands,
+    rules: Rules,
+    // the time for which items are kept past their expiry while serving
+    // stale items, and whether that is enabled
+    stale_grace: Duration,
+    serving_stale: bool,
+    // how incr and decr handle results which are out of range
+    overflow: ::seg::Overflow,
+    underflow: ::seg::Overflow,
+    // the time at which a delayed flush_all takes effect
+    flush_at: Option<SystemTime>,
+    // the sizes the datastructure was built with, which are reported by the
+    // memcache stats
+    heap_size: usize,
+    segment_size: usize,
+}
+
+// The command families which are enabled. Each may be disabled in the config,
+// or removed from the build entirely with the corresponding `no-*` feature.
+struct Commands {
+    arithmetic: bool,
+    flush: bool,
+    meta: bool,
+    stats: bool,
+    stats_sizes: bool,
+}
+
+// Tracks the warm-up period after startup, during which a fraction of reads
+// are rejected. The fraction is applied deterministically by accumulating
+// credit for each read and shedding whenever a whole read has accrued.
+struct Warmup {
+    duration: Duration,
+    shed_ratio: f64,
+    credit: f64,
 }
 
 impl Seg {
     /// Create `Seg` storage based on the config and the `TimeType` which is
     /// used to interpret various expiry time formats.
-    pub fn new<T: SegConfig>(config: &T) -> Result<Self, std::io::Error> {
+    pub fn new<T: SegConfig + CommandsConfig + RulesConfig>(
+        config: &T,
+    ) -> Result<Self, std::io::Error> {
+        let commands = Commands {
+            arithmetic: config.commands().arithmetic() && cfg!(feature = "arithmetic"),
+            flush: config.commands().flush() && cfg!(feature = "flush"),
+            meta: config.commands().meta() && cfg!(feature = "meta"),
+            stats: config.commands().stats(),
+            stats_sizes: config.commands().stats_sizes(),
+        };
+
+        let rules = Rules::new(config.rules())?;
+
         let config = config.seg();
 
         // build up the eviction policy from the config
@@ -41,6 +130,23 @@ impl Seg {
             },
         };
 
+        let admission = match config.admission() {
+            Admission::Always => ::seg::Admission::Always,
+            Admission::Probabilistic => ::seg::Admission::Probabilistic {
+                min_size: config.admission_min_size(),
+                probability: config.admission_probability(),
+            },
+            Admission::Frequency => ::seg::Admission::Frequency {
+                threshold: config.admission_threshold(),
+            },
+        };
+
+        let heap_size = config.heap_size();
+        let segment_size = config.segment_size() as usize;
+
+        let overflow = arithmetic_overflow(config.incr_overflow());
+        let underflow = arithmetic_overflow(config.decr_underflow());
+
         // build the datastructure from the config
         let data = ::seg::Seg::builder()
             .hash_power(config.hash_power())
@@ -48,19 +154,374 @@ impl Seg {
             .heap_size(config.heap_size())
             .segment_size(config.segment_size())
             .eviction(eviction)
+            .admission(admission)
             .datapool_path(config.datapool_path())
+            .cardinality_interval(Duration::from_secs(config.cardinality_interval()))
+            .size_sample_rate(config.size_sample_rate())
             .build()?;
 
-        Ok(Self { data })
+        let separator = config.namespace_separator();
+        if !separator.is_ascii() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "namespace separator must be an ascii character",
+            ));
+        }
+        common::namespace::set_separator(separator as u8);
+        common::namespace::set_sample_rate(config.namespace_sample_rate());
+        common::namespace::set_enabled(config.namespace_stats());
+
+        let warmup = if config.warmup() > 0 {
+            WARMUP.set(1);
+            Some(Warmup {
+                duration: Duration::from_secs(config.warmup()),
+                shed_ratio: config.warmup_shed_ratio().clamp(0.0, 1.0),
+                credit: 0.0,
+            })
+        } else {
+            None
+        };
+
+        let dedup = if config.dedup_window() > 0 {
+            Some(Dedup::new(
+                Duration::from_millis(config.dedup_window()),
+                config.dedup_entries(),
+            ))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            data,
+            clock: Clock::new(),
+            warmup,
+            dedup,
+            commands,
+            rules,
+            stale_grace: Duration::from_secs(config.stale_grace()),
+            serving_stale: false,
+            overflow,
+            underflow,
+            flush_at: None,
+            heap_size,
+            segment_size,
+        })
+    }
+
+    // Returns true if the item with the key has expired and is only returned
+    // because stale items are being served, counting each such read.
+    fn stale_read(&mut self, key: &[u8]) -> bool {
+        if !self.serving_stale {
+            return false;
+        }
+        let stale = self.data.ttl(key).is_none();
+        if stale {
+            STALE_READ.increment();
+        }
+        stale
+    }
+
+    // Ends the warm-up period if it has elapsed.
+    fn update_warmup(&mut self) {
+        if let Some(warmup) = self.warmup.as_ref() {
+            if self.clock.uptime() >= warmup.duration {
+                self.warmup = None;
+                WARMUP.set(0);
+            }
+        }
+    }
+
+    // Returns true if a read should be rejected with a retryable error because
+    // storage is still warming up.
+    fn shed_read(&mut self) -> bool {
+        self.update_warmup();
+
+        let warmup = match self.warmup.as_mut() {
+            Some(warmup) => warmup,
+            None => {
+                return false;
+            }
+        };
+
+        warmup.credit += warmup.shed_ratio;
+        if warmup.credit >= 1.0 {
+            warmup.credit -= 1.0;
+            WARMUP_SHED.increment();
+            true
+        } else {
+            false
+        }
     }
 }
 
+fn arithmetic_overflow(overflow: Overflow) -> ::seg::Overflow {
+    match overflow {
+        Overflow::Wrap => ::seg::Overflow::Wrap,
+        Overflow::Saturate => ::seg::Overflow::Saturate,
+        Overflow::Error => ::seg::Overflow::Error,
+    }
+}
+
+impl From<SegError> for StorageError {
+    fn from(other: SegError) -> Self {
+        match other {
+            SegError::HashTableInsertEx | SegError::EvictionEx | SegError::NoFreeSegments => {
+                Self::OutOfMemory
+            }
+            SegError::ItemOversized { .. } => Self::ItemTooLarge,
+            SegError::Exists => Self::CasMismatch,
+            SegError::NotFound => Self::NotFound,
+            SegError::NotNumeric => Self::NotNumeric,
+            SegError::Overflow => Self::Overflow,
+            SegError::NotAdmitted => Self::NotAdmitted,
+            SegError::DataCorrupted => Self::Internal,
+        }
+    }
+}
+
+/// Converts an error from the storage engine, counting it by failure mode.
+fn storage_error(error: SegError) -> StorageError {
+    let error = StorageError::from(error);
+    error.increment();
+    error
+}
+
 impl EntryStore for Seg {
+    fn warming_up(&mut self) -> bool {
+        self.update_warmup();
+        self.warmup.is_some()
+    }
+
     fn expire(&mut self) {
+        self.update_warmup();
+
+        if let Some(time) = self.flush_at {
+            if SystemTime::now() >= time {
+                self.clear();
+            }
+        }
+
         self.data.expire();
     }
 
     fn clear(&mut self) {
-        self.data.clear();
+        self.flush_at = None;
+        self.data.flush();
+    }
+
+    fn clear_at(&mut self, time: SystemTime) {
+        if SystemTime::now() >= time {
+            self.clear();
+        } else {
+            self.flush_at = Some(time);
+        }
+    }
+
+    fn clear_namespace(&mut self, namespace: &[u8]) -> usize {
+        let mut prefix = namespace.to_vec();
+        prefix.push(common::namespace::separator());
+        self.data.clear_prefix(&prefix)
+    }
+
+    fn clear_ttl_bucket(&mut self, bucket: usize) -> bool {
+        self.data.clear_ttl_bucket(bucket).is_some()
+    }
+
+    fn compact(&mut self) -> usize {
+        self.data.compact()
+    }
+
+    fn serve_stale(&mut self, enabled: bool) -> Duration {
+        self.serving_stale = enabled && !self.stale_grace.is_zero();
+        if self.serving_stale {
+            self.data.set_grace(self.stale_grace);
+            STALE_SERVE.set(1);
+            self.stale_grace
+        } else {
+            // items past their expiry are removed by the next expiration
+            self.data.set_grace(Duration::ZERO);
+            STALE_SERVE.set(0);
+            Duration::ZERO
+        }
+    }
+
+    fn hashtable_info(&self) -> Option<HashTableInfo> {
+        Some(self.data.hashtable_info())
+    }
+
+    fn ttl_bucket_info(&self) -> Vec<TtlBucketInfo> {
+        self.data
+            .ttl_bucket_stats()
+            .into_iter()
+            .map(|stats| TtlBucketInfo {
+                index: stats.index,
+                ttl: Duration::from_secs(stats.ttl.into()),
+                segments: stats.segments,
+                live_items: stats.live_items,
+                live_bytes: stats.live_bytes,
+                dead_bytes: stats.dead_bytes,
+                oldest: Duration::from_secs(stats.oldest.into()),
+                merged: stats.merged,
+            })
+            .collect()
+    }
+
+    fn scan_keys(&mut self, cursor: usize) -> Option<(Vec<Box<[u8]>>, usize)> {
+        Some(self.data.scan_keys(cursor, SCAN_BUCKETS))
+    }
+
+    fn export(&mut self, key: &[u8]) -> Option<MigrationItem> {
+        let ttl = self.data.ttl(key)?;
+        let item = self.data.get_no_freq_incr(key)?;
+        let o = item.optional().unwrap_or(&[0, 0, 0, 0]);
+        let flags = u32::from_be_bytes([o[0], o[1], o[2], o[3]]);
+        // values compressed by a rule are sent as the client wrote them, so
+        // that the rules of the destination decide how they are stored
+        let value = match item.value() {
+            seg::Value::Bytes(b) => decompress(item.optional(), b).ok()?.into_owned(),
+            seg::Value::U64(v) => format!("{}", v).into_bytes(),
+        };
+
+        Some(MigrationItem {
+            key: key.into(),
+            value,
+            flags,
+            ttl,
+            cas: item.cas().into(),
+        })
+    }
+
+    fn fingerprint(&mut self, key: &[u8]) -> Option<Fingerprint> {
+        // the value is hashed as it would be returned to a client, so that it
+        // does not depend on whether a rule compressed it
+        let item = self.export(key)?;
+        let ttl_bucket = self.data.ttl_bucket(key)?;
+        Some(Fingerprint::new(key, &item.value, item.flags, ttl_bucket))
+    }
+
+    fn remove_exported(&mut self, key: &[u8], cas: u64) -> bool {
+        let current = self
+            .data
+            .get_no_freq_incr(key)
+            .map(|item| u64::from(item.cas()));
+        current == Some(cas) && self.data.delete(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::signal::{MigrateReply, MigrateStep};
+    use config::SegcacheConfig;
+
+    fn seg() -> Seg {
+        Seg::new(&SegcacheConfig::default()).expect("failed to create storage")
+    }
+
+    #[test]
+    fn export() {
+        let mut seg = seg();
+        let flags = 7_u32.to_be_bytes();
+        let ttl = Duration::from_secs(60);
+
+        assert!(seg.export(b"tea:green").is_none());
+
+        assert!(seg
+            .data
+            .insert(b"tea:green", b"sencha", Some(&flags), ttl)
+            .is_ok());
+        let cas = seg.data.get(b"tea:green").expect("not found").cas();
+        let item = seg.export(b"tea:green").expect("not exported");
+        assert_eq!(&*item.key, b"tea:green");
+        assert_eq!(item.value, b"sencha");
+        assert_eq!(item.flags, 7);
+        assert!(item.ttl > Duration::ZERO && item.ttl <= ttl);
+        assert_eq!(item.cas, u64::from(cas));
+
+        // numeric values are exported as they would be returned to a client
+        assert!(seg.data.insert(b"tea:count", 42_u64, None, ttl).is_ok());
+        let item = seg.export(b"tea:count").expect("not exported");
+        assert_eq!(item.value, b"42");
+        assert_eq!(item.flags, 0);
+    }
+
+    #[test]
+    fn migrate_keys() {
+        let mut seg = seg();
+        for key in [&b"tea:green"[..], b"tea:black", b"coffee:espresso"] {
+            assert!(seg.data.insert(key, b"value", None, Duration::ZERO).is_ok());
+        }
+
+        // the keys under the prefix are found across every step of the scan
+        let mut keys = Vec::new();
+        let mut cursor = 0;
+        loop {
+            match seg.migrate(MigrateStep::Keys {
+                prefix: b"tea:".to_vec(),
+                cursor,
+            }) {
+                MigrateReply::Keys(found, next) => {
+                    keys.extend(found);
+                    cursor = next;
+                }
+                reply => panic!("unexpected reply: {:?}", reply),
+            }
+            if cursor == 0 {
+                break;
+            }
+        }
+        keys.sort();
+        let expected: Vec<Box<[u8]>> =
+            vec![b"tea:black".to_vec().into(), b"tea:green".to_vec().into()];
+        assert_eq!(keys, expected);
+
+        // counting the keys takes the same steps
+        let mut count = 0;
+        let mut cursor = 0;
+        loop {
+            match seg.migrate(MigrateStep::Count {
+                prefix: b"tea:".to_vec(),
+                cursor,
+            }) {
+                MigrateReply::Count(found, next) => {
+                    count += found;
+                    cursor = next;
+                }
+                reply => panic!("unexpected reply: {:?}", reply),
+            }
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn remove_exported() {
+        let mut seg = seg();
+        assert!(seg
+            .data
+            .insert(b"tea", b"green", None, Duration::ZERO)
+            .is_ok());
+        let exported = seg.export(b"tea").expect("not exported");
+
+        // an item which changed since it was exported is kept
+        assert!(seg
+            .data
+            .insert(b"tea", b"black", None, Duration::ZERO)
+            .is_ok());
+        assert!(!seg.remove_exported(b"tea", exported.cas));
+        assert!(seg.data.get(b"tea").is_some());
+
+        let exported = seg.export(b"tea").expect("not exported");
+        assert_eq!(
+            seg.migrate(MigrateStep::Remove(vec![(
+                b"tea".to_vec().into(),
+                exported.cas
+            )])),
+            MigrateReply::Removed(1)
+        );
+        assert!(seg.data.get(b"tea").is_none());
+        assert!(!seg.remove_exported(b"tea", exported.cas));
     }
 }
@@ -0,0 +1,390 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Caching rules, which let operators apply a policy to writes without
+//! changing the clients. Each write over the memcache protocol is checked
+//! against the rules from the config, and the first rule whose pattern matches
+//! the key may override the TTL, deny the write, compress the value, or
+//! attribute the write to a namespace in the per-namespace stats.
+//!
+//! Compressed values are marked by a byte which follows the flags of the item,
+//! and are decompressed when they are read, so compression is transparent to
+//! clients.
+
+use super::*;
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use regex::bytes::Regex;
+
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+counter!(RULE_HIT, "number of writes which matched a caching rule");
+counter!(RULE_DENY, "number of writes denied by a caching rule");
+counter!(
+    RULE_COMPRESS,
+    "number of values stored compressed by a caching rule"
+);
+counter!(
+    RULE_COMPRESS_SAVED_BYTE,
+    "number of bytes saved by compressing values"
+);
+counter!(
+    RULE_DECOMPRESS_EX,
+    "number of compressed values which could not be decompressed"
+);
+
+/// Follows the flags in the optional bytes of an item whose value is stored
+/// compressed.
+pub(super) const COMPRESSED: u8 = 1;
+
+/// The policy which applies to a write.
+#[derive(Clone, Copy, Default)]
+pub(super) struct Action {
+    pub deny: bool,
+    pub ttl: Option<Duration>,
+    pub compress: bool,
+}
+
+/// The rules from the config, in the order they are evaluated.
+pub(super) struct Rules {
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    prefix: Option<Box<[u8]>>,
+    regex: Option<Regex>,
+    action: Action,
+    namespace: Option<Box<[u8]>>,
+    hits: Arc<DynBoxedMetric<Counter>>,
+}
+
+impl Rule {
+    fn matches(&self, key: &[u8]) -> bool {
+        self.prefix
+            .as_ref()
+            .map(|prefix| key.starts_with(prefix))
+            .unwrap_or(true)
+            && self
+                .regex
+                .as_ref()
+                .map(|regex| regex.is_match(key))
+                .unwrap_or(true)
+    }
+}
+
+impl Rules {
+    /// Compiles the rules. Each rule must have a unique name, which is used
+    /// in the name of its stat, and at least one pattern.
+    pub fn new(config: &[config::Rule]) -> Result<Self, std::io::Error> {
+        let invalid = |rule: &config::Rule, reason: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("caching rule \"{}\" {}", rule.name(), reason),
+            )
+        };
+
+        let mut rules = Vec::with_capacity(config.len());
+        for (index, rule) in config.iter().enumerate() {
+            if rule.name().is_empty()
+                || !rule
+                    .name()
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                return Err(invalid(
+                    rule,
+                    "must be named with letters, digits, and underscores",
+                ));
+            }
+            if config[..index].iter().any(|r| r.name() == rule.name()) {
+                return Err(invalid(rule, "is defined more than once"));
+            }
+            if rule.prefix().is_none() && rule.regex().is_none() {
+                return Err(invalid(rule, "must have a prefix or a regex"));
+            }
+
+            let regex = match rule.regex() {
+                Some(regex) => Some(Regex::new(regex).map_err(|e| invalid(rule, &e.to_string()))?),
+                None => None,
+            };
+
+            rules.push(Rule {
+                prefix: rule.prefix().map(|p| p.as_bytes().into()),
+                regex,
+                action: Action {
+                    deny: rule.deny(),
+                    ttl: rule.ttl().map(|ttl| Duration::from_secs(ttl.into())),
+                    compress: rule.compress(),
+                },
+                namespace: rule.namespace().map(|n| n.as_bytes().into()),
+                hits: common::rules::register(rule.name()),
+            });
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Returns the policy for a write to the key, counting a hit for the rule
+    /// which matched. Writes which match no rule are stored as requested.
+    pub fn evaluate(&self, key: &[u8]) -> Action {
+        match self.find(key) {
+            Some(rule) => {
+                RULE_HIT.increment();
+                rule.hits.increment();
+                if rule.action.deny {
+                    RULE_DENY.increment();
+                }
+                rule.action
+            }
+            None => Action::default(),
+        }
+    }
+
+    /// Returns the key which a write is attributed to in the per-namespace
+    /// stats, which is prefixed with the namespace of the matching rule if it
+    /// has one.
+    pub fn attribute<'a>(&self, key: &'a [u8]) -> Cow<'a, [u8]> {
+        match self.find(key).and_then(|rule| rule.namespace.as_ref()) {
+            Some(namespace) => {
+                let mut routed = namespace.to_vec();
+                routed.push(common::namespace::separator());
+                routed.extend_from_slice(key);
+                Cow::Owned(routed)
+            }
+            None => Cow::Borrowed(key),
+        }
+    }
+
+    fn find(&self, key: &[u8]) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(key))
+    }
+}
+
+/// Compresses a value, returning `None` if that would not make it smaller.
+pub(super) fn compress(value: &[u8]) -> Option<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+    match encoder.write_all(value).and_then(|_| encoder.finish()) {
+        Ok(compressed) if compressed.len() < value.len() => {
+            RULE_COMPRESS.increment();
+            RULE_COMPRESS_SAVED_BYTE.add((value.len() - compressed.len()) as _);
+            Some(compressed)
+        }
+        _ => None,
+    }
+}
+
+/// Returns the value of an item as the client stored it, decompressing it if
+/// it was stored compressed. A compressed value which cannot be decompressed
+/// is an error, rather than being returned as the compressed bytes.
+pub(super) fn decompress<'a>(
+    optional: Option<&[u8]>,
+    value: &'a [u8],
+) -> Result<Cow<'a, [u8]>, StorageError> {
+    if optional.and_then(|o| o.get(4)) != Some(&COMPRESSED) {
+        return Ok(Cow::Borrowed(value));
+    }
+
+    let mut decompressed = Vec::new();
+    match DeflateDecoder::new(value).read_to_end(&mut decompressed) {
+        Ok(_) => Ok(Cow::Owned(decompressed)),
+        Err(_) => {
+            RULE_DECOMPRESS_EX.increment();
+            Err(StorageError::Internal)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::SegcacheConfig;
+    use protocol_common::*;
+    use protocol_memcache::*;
+
+    const CONFIG: &str = r#"
+        [[rules]]
+        name = "rules_test_sessions"
+        prefix = "session:"
+        ttl = 60
+
+        [[rules]]
+        name = "rules_test_users"
+        prefix = "user_"
+        regex = "^user_[0-9]+$"
+        namespace = "users"
+        compress = true
+
+        [[rules]]
+        name = "rules_test_blocked"
+        prefix = "tmp:"
+        deny = true
+    "#;
+
+    fn seg() -> Seg {
+        let config: SegcacheConfig = toml::from_str(CONFIG).expect("invalid config");
+        Seg::new(&config).expect("failed to create storage")
+    }
+
+    fn execute(seg: &mut Seg, request: &[u8]) -> String {
+        let request = RequestParser::new()
+            .parse(request)
+            .expect("failed to parse request")
+            .into_inner();
+        let mut buffer = Vec::new();
+        seg.execute(&request).compose(&mut buffer);
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn deny() {
+        let mut seg = seg();
+
+        let hits = seg.rules.find(b"tmp:1").expect("no rule").hits.value();
+        assert!(seg.rules.evaluate(b"tmp:1").deny);
+        assert_eq!(seg.rules.find(b"tmp:1").unwrap().hits.value(), hits + 1);
+
+        assert_eq!(
+            execute(&mut seg, b"set tmp:1 0 0 3\r\ntea\r\n"),
+            "CLIENT_ERROR denied by rule\r\n"
+        );
+        assert_eq!(execute(&mut seg, b"get tmp:1\r\n"), "END\r\n");
+
+        // keys which match no rule are stored as requested
+        assert!(!seg.rules.evaluate(b"tmp").deny);
+        assert_eq!(execute(&mut seg, b"set tmp 0 0 3\r\ntea\r\n"), "STORED\r\n");
+    }
+
+    #[test]
+    fn ttl() {
+        let mut seg = seg();
+
+        assert_eq!(
+            seg.rules.evaluate(b"session:1").ttl,
+            Some(Duration::from_secs(60))
+        );
+
+        // the ttl of the rule replaces the ttl the client asked for
+        assert_eq!(
+            execute(&mut seg, b"set session:1 0 0 3\r\ntea\r\n"),
+            "STORED\r\n"
+        );
+        let ttl = seg.data.ttl(b"session:1").expect("not found");
+        assert!(ttl > Duration::ZERO && ttl <= Duration::from_secs(60));
+
+        assert_eq!(
+            execute(&mut seg, b"set other 0 0 3\r\ntea\r\n"),
+            "STORED\r\n"
+        );
+        assert_eq!(seg.data.ttl(b"other"), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn deflate() {
+        let mut seg = seg();
+        let value = "tea".repeat(64);
+
+        // the value is stored compressed and marked after the flags
+        let request = format!("set user_1 7 0 {}\r\n{}\r\n", value.len(), value);
+        assert_eq!(execute(&mut seg, request.as_bytes()), "STORED\r\n");
+        let item = seg.data.get(b"user_1").expect("not found");
+        assert_eq!(item.optional(), Some(&[0, 0, 0, 7, COMPRESSED][..]));
+        match item.value() {
+            seg::Value::Bytes(b) => assert!(b.len() < value.len()),
+            seg::Value::U64(_) => panic!("value is not bytes"),
+        }
+
+        // and is returned as the client stored it
+        assert_eq!(
+            execute(&mut seg, b"get user_1\r\n"),
+            format!("VALUE user_1 7 {}\r\n{}\r\nEND\r\n", value.len(), value)
+        );
+
+        // the key must match both the prefix and the regex
+        let request = format!("set user_x 7 0 {}\r\n{}\r\n", value.len(), value);
+        assert_eq!(execute(&mut seg, request.as_bytes()), "STORED\r\n");
+        let item = seg.data.get(b"user_x").expect("not found");
+        assert_eq!(item.optional(), Some(&[0, 0, 0, 7][..]));
+    }
+
+    #[test]
+    fn fingerprint() {
+        let mut seg = seg();
+        let value = "tea".repeat(64);
+
+        // a compressed value has the same fingerprint as an uncompressed one,
+        // as values are hashed as they are returned to clients
+        for key in ["user_1", "user_x"] {
+            let request = format!("set {} 7 0 {}\r\n{}\r\n", key, value.len(), value);
+            assert_eq!(execute(&mut seg, request.as_bytes()), "STORED\r\n");
+        }
+        let compressed = seg.fingerprint(b"user_1").expect("not found");
+        let uncompressed = seg.fingerprint(b"user_x").expect("not found");
+        assert_eq!(compressed.value, uncompressed.value);
+        assert_eq!(compressed.ttl_bucket, uncompressed.ttl_bucket);
+        assert_eq!(
+            compressed,
+            Fingerprint::new(b"user_1", value.as_bytes(), 7, compressed.ttl_bucket)
+        );
+        assert!(seg.fingerprint(b"user_2").is_none());
+
+        // walking storage a part at a time finds every value
+        let mut found = Vec::new();
+        let mut cursor = 0;
+        loop {
+            let (fingerprints, next) = seg.fingerprints(1, cursor);
+            found.extend(fingerprints);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        found.sort();
+        let mut expected = vec![compressed, uncompressed];
+        expected.sort();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn deflate_error() {
+        let marked: &[u8] = &[0, 0, 0, 0, COMPRESSED];
+        let invalid = [0xff; 8];
+
+        assert!(decompress(Some(marked), &invalid).is_err());
+        assert_eq!(
+            decompress(Some(&[0, 0, 0, 0]), &invalid).unwrap(),
+            Cow::Borrowed(&invalid[..])
+        );
+
+        // a value which cannot be decompressed is a server error, rather
+        // than the compressed bytes being returned
+        let mut seg = seg();
+        assert!(seg
+            .data
+            .insert(b"user_2", &invalid[..], Some(marked), Duration::ZERO)
+            .is_ok());
+        assert!(execute(&mut seg, b"get user_2\r\n").starts_with("SERVER_ERROR"));
+        assert!(execute(&mut seg, b"gets user_2\r\n").starts_with("SERVER_ERROR"));
+        assert!(execute(&mut seg, b"gat 0 user_2\r\n").starts_with("SERVER_ERROR"));
+    }
+
+    #[test]
+    fn namespace() {
+        let seg = seg();
+        let separator = common::namespace::separator();
+
+        let mut attributed = b"users".to_vec();
+        attributed.push(separator);
+        attributed.extend_from_slice(b"user_1");
+        assert_eq!(&*seg.rules.attribute(b"user_1"), &attributed[..]);
+
+        // keys which match no rule, or a rule without a namespace, keep their
+        // own namespace
+        assert_eq!(&*seg.rules.attribute(b"user_x"), b"user_x");
+        assert_eq!(&*seg.rules.attribute(b"session:1"), b"session:1");
+    }
+}
@@ -34,9 +34,12 @@
 //! a file, while letting all other log messages pass to standard out. This
 //! could allow splitting command/access/audit logs from the normal logging.
 
+pub use log::Level;
 pub use rustcommon_logger::*;
 
-use config::{DebugConfig, KlogConfig};
+use config::{AccessLogConfig, DebugConfig, KlogConfig};
+use log::LevelFilter;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 ////////////////////////////////////////////////////////////////////////////////
 // TODO(bmartin): everything below is Pelikan specific, and should be factored
@@ -52,13 +55,61 @@ macro_rules! klog {
     )
 }
 
+#[macro_export]
+macro_rules! alog {
+    ($($arg:tt)*) => (
+        // as with the command log, error level keeps these messages from being
+        // filtered by the level of the debug log
+        error!(target: "access", $($arg)*);
+    )
+}
+
+// set when the access log has an output, so that callers can skip gathering
+// the fields of each access log line when they would be discarded
+static ACCESS_LOG: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if the access log is configured to write to a file.
+pub fn access_log_enabled() -> bool {
+    ACCESS_LOG.load(Ordering::Relaxed)
+}
+
+/// Changes the most verbose level of the debug log messages which are emitted.
+/// This takes effect immediately, without a restart. The command log and the
+/// access log are not affected.
+pub fn set_log_level(level: Level) {
+    log::set_max_level(level.to_level_filter());
+}
+
+/// Returns the most verbose level of the debug log messages which are emitted.
+pub fn log_level() -> Option<Level> {
+    log::max_level().to_level()
+}
+
+/// The protocol specific fields of an access log line.
+pub struct Access {
+    /// the command or method of the request
+    pub verb: &'static str,
+    /// the total length of the keys in the request
+    pub key_len: usize,
+    /// a short description of the outcome of the request
+    pub status: &'static str,
+}
+
 pub trait Klog {
     type Response;
 
     fn klog(&self, response: &Self::Response);
+
+    /// Returns the fields for the access log line of this request, or `None`
+    /// if the request should not appear in the access log.
+    fn access(&self, _response: &Self::Response) -> Option<Access> {
+        None
+    }
 }
 
-pub fn configure_logging<T: DebugConfig + KlogConfig>(config: &T) -> Box<dyn Drain> {
+pub fn configure_logging<T: DebugConfig + KlogConfig + AccessLogConfig>(
+    config: &T,
+) -> Box<dyn Drain> {
     let debug_config = config.debug();
 
     let debug_output: Box<dyn Output> = if let Some(file) = debug_config.log_file() {
@@ -97,10 +148,41 @@ pub fn configure_logging<T: DebugConfig + KlogConfig>(config: &T) -> Box<dyn Dra
         NopLogBuilder::new().build()
     };
 
-    MultiLogBuilder::new()
-        .level_filter(debug_config.log_level().to_level_filter())
+    let access_log_config = config.access_log();
+
+    let access_log = if let Some(file) = access_log_config.file() {
+        let backup = access_log_config
+            .backup()
+            .unwrap_or(format!("{}.old", file));
+        let output = Box::new(
+            File::new(&file, &backup, access_log_config.max_size())
+                .expect("failed to open access log file"),
+        );
+        ACCESS_LOG.store(access_log_config.sample() > 0, Ordering::Relaxed);
+        SamplingLogBuilder::new()
+            .output(output)
+            .format(klog_format)
+            .sample(access_log_config.sample())
+            .log_queue_depth(access_log_config.queue_depth())
+            .single_message_size(access_log_config.single_message_size())
+            .build()
+            .expect("failed to initialize access log")
+    } else {
+        NopLogBuilder::new().build()
+    };
+
+    // the drain accepts every level, so that the level can be changed at
+    // runtime through `set_log_level()`, which filters the messages before
+    // they reach the drain
+    let drain = MultiLogBuilder::new()
+        .level_filter(LevelFilter::Trace)
         .default(debug_log)
         .add_target("klog", klog)
+        .add_target("access", access_log)
         .build()
-        .start()
+        .start();
+
+    set_log_level(debug_config.log_level());
+
+    drain
 }
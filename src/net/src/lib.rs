@@ -7,12 +7,14 @@ mod listener;
 mod stream;
 mod tcp;
 mod tls_tcp;
+mod unix;
 
 pub use connector::*;
 pub use listener::*;
 pub use stream::*;
 pub use tcp::*;
 pub use tls_tcp::*;
+pub use unix::*;
 
 pub mod event {
     pub use mio::event::*;
@@ -46,6 +48,16 @@ gauge!(TCP_CONN_CURR, "current number of open TCP streams");
 counter!(TCP_RECV_BYTE, "number of bytes received on TCP streams");
 counter!(TCP_SEND_BYTE, "number of bytes sent on TCP streams");
 
+counter!(
+    UNIX_ACCEPT,
+    "number of unix domain socket streams passively opened with accept"
+);
+counter!(UNIX_CLOSE, "number of unix domain socket streams closed");
+gauge!(
+    UNIX_CONN_CURR,
+    "current number of open unix domain socket streams"
+);
+
 counter!(STREAM_ACCEPT, "number of calls to accept");
 counter!(
     STREAM_ACCEPT_EX,
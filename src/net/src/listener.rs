@@ -11,6 +11,7 @@ pub struct Listener {
 enum ListenerType {
     Plain(TcpListener),
     Tls((TcpListener, TlsTcpAcceptor)),
+    Unix(UnixListener),
 }
 
 impl From<TcpListener> for Listener {
@@ -29,6 +30,14 @@ impl From<(TcpListener, TlsTcpAcceptor)> for Listener {
     }
 }
 
+impl From<UnixListener> for Listener {
+    fn from(other: UnixListener) -> Self {
+        Self {
+            inner: ListenerType::Unix(other),
+        }
+    }
+}
+
 impl Listener {
     /// Accepts a new `Stream`.
     ///
@@ -56,13 +65,46 @@ impl Listener {
                 let stream = acceptor.accept(stream)?;
                 Ok(Stream::from(stream))
             }
+            ListenerType::Unix(listener) => {
+                let stream = listener.accept()?;
+                Ok(Stream::from(stream))
+            }
         }
     }
 
+    /// Replaces the acceptor used for new TLS sessions, for example after the
+    /// certificate has been renewed. Sessions which were already accepted are
+    /// not affected. Returns an error if the listener does not use TLS.
+    pub fn set_tls_acceptor(&mut self, acceptor: TlsTcpAcceptor) -> Result<()> {
+        match &mut self.inner {
+            ListenerType::Plain(_) | ListenerType::Unix(_) => {
+                Err(Error::new(ErrorKind::Other, "listener does not use tls"))
+            }
+            ListenerType::Tls((_listener, current)) => {
+                *current = acceptor;
+                Ok(())
+            }
+        }
+    }
+
+    /// The address the listener is bound to. Returns an error for a unix
+    /// domain socket, which is identified by its `local_path()` instead.
     pub fn local_addr(&self) -> Result<SocketAddr> {
         match &self.inner {
             ListenerType::Plain(listener) => listener.local_addr(),
             ListenerType::Tls((listener, _acceptor)) => listener.local_addr(),
+            ListenerType::Unix(_) => Err(Error::new(
+                ErrorKind::Other,
+                "unix domain socket has no socket address",
+            )),
+        }
+    }
+
+    /// The path of the socket file, if the listener is on a unix domain socket.
+    pub fn local_path(&self) -> Option<&Path> {
+        match &self.inner {
+            ListenerType::Unix(listener) => Some(listener.path()),
+            _ => None,
         }
     }
 }
@@ -79,6 +121,7 @@ impl event::Source for Listener {
             ListenerType::Tls((listener, _acceptor)) => {
                 listener.register(registry, token, interests)
             }
+            ListenerType::Unix(listener) => listener.register(registry, token, interests),
         }
     }
 
@@ -93,6 +136,7 @@ impl event::Source for Listener {
             ListenerType::Tls((listener, _acceptor)) => {
                 listener.reregister(registry, token, interests)
             }
+            ListenerType::Unix(listener) => listener.reregister(registry, token, interests),
         }
     }
 
@@ -100,6 +144,7 @@ impl event::Source for Listener {
         match &mut self.inner {
             ListenerType::Plain(listener) => listener.deregister(registry),
             ListenerType::Tls((listener, _acceptor)) => listener.deregister(registry),
+            ListenerType::Unix(listener) => listener.deregister(registry),
         }
     }
 }
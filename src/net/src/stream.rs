@@ -21,6 +21,7 @@ impl AsRawFd for Stream {
         match &self.inner {
             StreamType::Tcp(s) => s.as_raw_fd(),
             StreamType::TlsTcp(s) => s.as_raw_fd(),
+            StreamType::Unix(s) => s.as_raw_fd(),
         }
     }
 }
@@ -36,6 +37,7 @@ impl Stream {
                 }
             }
             StreamType::TlsTcp(s) => s.interest(),
+            StreamType::Unix(_) => Interest::READABLE,
         }
     }
 
@@ -43,19 +45,20 @@ impl Stream {
         match &mut self.inner {
             StreamType::Tcp(s) => s.is_established(),
             StreamType::TlsTcp(s) => !s.is_handshaking(),
+            StreamType::Unix(_) => true,
         }
     }
 
     pub fn is_handshaking(&self) -> bool {
         match &self.inner {
-            StreamType::Tcp(_) => false,
+            StreamType::Tcp(_) | StreamType::Unix(_) => false,
             StreamType::TlsTcp(s) => s.is_handshaking(),
         }
     }
 
     pub fn do_handshake(&mut self) -> Result<()> {
         match &mut self.inner {
-            StreamType::Tcp(_) => Ok(()),
+            StreamType::Tcp(_) | StreamType::Unix(_) => Ok(()),
             StreamType::TlsTcp(s) => s.do_handshake(),
         }
     }
@@ -64,6 +67,21 @@ impl Stream {
         match &mut self.inner {
             StreamType::Tcp(s) => s.set_nodelay(nodelay),
             StreamType::TlsTcp(s) => s.set_nodelay(nodelay),
+            // there is no nagle delay on unix domain sockets
+            StreamType::Unix(_) => Ok(()),
+        }
+    }
+
+    /// Returns the address of the remote side of the stream. Unix domain
+    /// socket streams have no socket address and always return an error.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        match &self.inner {
+            StreamType::Tcp(s) => s.peer_addr(),
+            StreamType::TlsTcp(s) => s.peer_addr(),
+            StreamType::Unix(_) => Err(Error::new(
+                ErrorKind::Other,
+                "unix domain socket has no socket address",
+            )),
         }
     }
 
@@ -71,6 +89,7 @@ impl Stream {
         let result = match &mut self.inner {
             StreamType::Tcp(s) => s.shutdown(Shutdown::Both).map(|_| true),
             StreamType::TlsTcp(s) => s.shutdown().map(|v| v == ShutdownResult::Received),
+            StreamType::Unix(s) => s.shutdown(Shutdown::Both).map(|_| true),
         };
 
         STREAM_SHUTDOWN.increment();
@@ -93,6 +112,7 @@ impl Debug for Stream {
         match &self.inner {
             StreamType::Tcp(s) => write!(f, "{:?}", s),
             StreamType::TlsTcp(s) => write!(f, "{:?}", s),
+            StreamType::Unix(s) => write!(f, "{:?}", s),
         }
     }
 }
@@ -113,11 +133,20 @@ impl From<TlsTcpStream> for Stream {
     }
 }
 
+impl From<UnixStream> for Stream {
+    fn from(other: UnixStream) -> Self {
+        Self {
+            inner: StreamType::Unix(other),
+        }
+    }
+}
+
 impl Read for Stream {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         match &mut self.inner {
             StreamType::Tcp(s) => s.read(buf),
             StreamType::TlsTcp(s) => s.read(buf),
+            StreamType::Unix(s) => s.read(buf),
         }
     }
 }
@@ -127,6 +156,7 @@ impl Write for Stream {
         match &mut self.inner {
             StreamType::Tcp(s) => s.write(buf),
             StreamType::TlsTcp(s) => s.write(buf),
+            StreamType::Unix(s) => s.write(buf),
         }
     }
 
@@ -134,6 +164,7 @@ impl Write for Stream {
         match &mut self.inner {
             StreamType::Tcp(s) => s.flush(),
             StreamType::TlsTcp(s) => s.flush(),
+            StreamType::Unix(s) => s.flush(),
         }
     }
 }
@@ -143,6 +174,7 @@ impl event::Source for Stream {
         match &mut self.inner {
             StreamType::Tcp(s) => s.register(registry, token, interest),
             StreamType::TlsTcp(s) => s.register(registry, token, interest),
+            StreamType::Unix(s) => s.register(registry, token, interest),
         }
     }
 
@@ -155,6 +187,7 @@ impl event::Source for Stream {
         match &mut self.inner {
             StreamType::Tcp(s) => s.reregister(registry, token, interest),
             StreamType::TlsTcp(s) => s.reregister(registry, token, interest),
+            StreamType::Unix(s) => s.reregister(registry, token, interest),
         }
     }
 
@@ -162,6 +195,7 @@ impl event::Source for Stream {
         match &mut self.inner {
             StreamType::Tcp(s) => s.deregister(registry),
             StreamType::TlsTcp(s) => s.deregister(registry),
+            StreamType::Unix(s) => s.deregister(registry),
         }
     }
 }
@@ -172,4 +206,5 @@ impl event::Source for Stream {
 enum StreamType {
     Tcp(TcpStream),
     TlsTcp(TlsTcpStream),
+    Unix(UnixStream),
 }
@@ -56,6 +56,10 @@ impl TcpStream {
     pub fn set_nodelay(&mut self, nodelay: bool) -> Result<()> {
         self.inner.set_nodelay(nodelay)
     }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
 }
 
 impl Drop for TcpStream {
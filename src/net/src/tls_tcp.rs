@@ -34,6 +34,10 @@ impl TlsTcpStream {
         self.inner.get_mut().set_nodelay(nodelay)
     }
 
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.inner.get_ref().peer_addr()
+    }
+
     pub fn is_handshaking(&self) -> bool {
         self.state == TlsState::Handshaking
     }
@@ -149,6 +153,7 @@ impl event::Source for TlsTcpStream {
 /// Provides a wrapped acceptor for server-side TLS. This returns our wrapped
 /// `TlsStream` type so that clients can store negotiated and handshaking
 /// streams in a structure with a uniform type.
+#[derive(Clone)]
 pub struct TlsTcpAcceptor {
     inner: boring::ssl::SslContext,
 }
@@ -0,0 +1,217 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::*;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+pub struct UnixStream {
+    inner: mio::net::UnixStream,
+}
+
+impl Drop for UnixStream {
+    fn drop(&mut self) {
+        UNIX_CONN_CURR.decrement();
+        UNIX_CLOSE.increment();
+    }
+}
+
+impl Debug for UnixStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "{:?}", self.inner)
+    }
+}
+
+impl Deref for UnixStream {
+    type Target = mio::net::UnixStream;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl Read for UnixStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Write for UnixStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl event::Source for UnixStream {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interest: mio::Interest,
+    ) -> Result<()> {
+        self.inner.register(registry, token, interest)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interest: mio::Interest,
+    ) -> Result<()> {
+        self.inner.reregister(registry, token, interest)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> Result<()> {
+        self.inner.deregister(registry)
+    }
+}
+
+/// A listener on a unix domain socket, which is only reachable from the same
+/// host. The socket file is removed when the listener is dropped.
+pub struct UnixListener {
+    inner: mio::net::UnixListener,
+    path: PathBuf,
+}
+
+impl Deref for UnixListener {
+    type Target = mio::net::UnixListener;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl UnixListener {
+    /// Binds to the path and sets the permissions of the socket file to the
+    /// mode, such as `0o600`, rather than leaving them to the umask. A socket
+    /// file which was left behind by a process that has exited is replaced,
+    /// but an error is returned if another listener is still accepting on the
+    /// path.
+    pub fn bind<P: AsRef<Path>>(path: P, mode: u32) -> Result<UnixListener> {
+        let path = path.as_ref();
+
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    "path exists and is not a socket",
+                ));
+            }
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(Error::new(
+                    ErrorKind::AddrInUse,
+                    "socket is in use by another listener",
+                ));
+            }
+            std::fs::remove_file(path)?;
+        }
+
+        let inner = mio::net::UnixListener::bind(path)?;
+
+        // the socket file is removed if the permissions cannot be set
+        let listener = Self {
+            inner,
+            path: path.to_path_buf(),
+        };
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+
+        Ok(listener)
+    }
+
+    pub fn accept(&self) -> Result<UnixStream> {
+        let result = self
+            .inner
+            .accept()
+            .map(|(stream, _addr)| UnixStream { inner: stream });
+
+        if result.is_ok() {
+            UNIX_ACCEPT.increment();
+            UNIX_CONN_CURR.increment();
+        }
+
+        result
+    }
+
+    /// The path of the socket file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for UnixListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+impl event::Source for UnixListener {
+    fn register(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> Result<()> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &mio::Registry,
+        token: mio::Token,
+        interests: mio::Interest,
+    ) -> Result<()> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &mio::Registry) -> Result<()> {
+        self.inner.deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bind() {
+        let path = std::env::temp_dir().join(format!("net-unix-{}.sock", std::process::id()));
+
+        let listener = UnixListener::bind(&path, 0o600).expect("failed to bind");
+        assert_eq!(listener.path(), path);
+
+        // the mode is applied regardless of the umask
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut client = std::os::unix::net::UnixStream::connect(&path).expect("failed to connect");
+        let mut stream = loop {
+            match listener.accept() {
+                Ok(stream) => break stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::yield_now(),
+                Err(e) => panic!("failed to accept: {}", e),
+            }
+        };
+
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0; 4];
+        loop {
+            match stream.read(&mut buf) {
+                Ok(4) => break,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::yield_now(),
+                other => panic!("unexpected read: {:?}", other),
+            }
+        }
+        assert_eq!(&buf, b"ping");
+
+        // a live listener is not replaced
+        assert!(UnixListener::bind(&path, 0o600).is_err());
+
+        // the socket file is removed with the listener
+        drop(listener);
+        assert!(!path.exists());
+    }
+}
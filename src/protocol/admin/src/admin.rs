@@ -9,21 +9,199 @@
 
 use crate::*;
 use common::bytes::SliceExtension;
+use common::fingerprint::Fingerprint;
+use common::listener::ListenerStats;
+use common::namespace::NamespaceStats;
+use common::signal::{EventLoop, HashTableInfo, SessionInfo, TtlBucketInfo, Tunable};
+use logger::Level;
 use rustcommon_metrics::*;
 
+use std::collections::BTreeMap;
 use std::io::{Error, ErrorKind, Result};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// the percentiles of the latency which are reported for each listener
+pub(crate) const LISTENER_PERCENTILES: &[(&str, f64)] =
+    &[("p50", 50.0), ("p90", 90.0), ("p99", 99.0), ("p999", 99.9)];
+
+// the longest interval in seconds for `stats diff`, as the admin connection is
+// held open until the response is sent
+const STATS_DIFF_MAX: u64 = 3600;
+
+// the largest number of events per event loop iteration for `tune`, as the
+// event buffer is allocated to hold this many events
+const TUNE_NEVENT_MAX: usize = 65536;
+
+// the longest event loop timeout in milliseconds for `tune`, as signals such
+// as shutdown may wait this long to be handled
+const TUNE_TIMEOUT_MAX: u64 = 10_000;
+
+// the value of each counter when the counters were last reset, which is
+// subtracted from the counter wherever it is reported. The counters themselves
+// are never zeroed, as other readers rely on them only increasing.
+static RESET: RwLock<Option<Arc<BTreeMap<String, u64>>>> = RwLock::new(None);
 
 // TODO(bmartin): see TODO for protocol::data::Request, this is cleaner here
 // since the variants are simple, but better to take the same approach in both
 // modules.
 #[derive(PartialEq, Eq, Debug)]
 pub enum AdminRequest {
+    Auth(Vec<u8>),
+    /// A command which was registered by the server, see
+    /// [`register_command`]
+    Custom {
+        verb: &'static str,
+        args: Vec<Vec<u8>>,
+    },
+    /// A destructive command which was sent with `--confirm <token>`, where
+    /// the token was returned by a dry run of the same command
+    Confirmed {
+        request: Box<AdminRequest>,
+        token: String,
+    },
+    /// A destructive command which was sent with `--dry-run`, which describes
+    /// its impact and returns a token to confirm it rather than running it
+    DryRun(Box<AdminRequest>),
     FlushAll,
-    Stats,
+    /// A flush which takes effect after a delay, given in seconds or, like
+    /// memcache expiry times, as a UNIX timestamp if it is more than 30 days
+    FlushAllDelayed(u32),
+    FlushNamespace(Vec<u8>),
+    FlushTtlBucket(usize),
+    /// Export the fingerprints of a sample of one in every given number of
+    /// items, chosen by the hash of the key, so that the items held by two
+    /// instances can be compared without their values
+    Fingerprints(u64),
+    /// Report the version along with the commit, toolchain, and target which
+    /// the binary was built from
+    BuildInfo,
+    /// A liveness probe, which checks that every thread is responsive
+    Health,
+    LogLevel,
+    SetLogLevel(Level),
+    /// Run a compaction pass over storage now, rather than waiting for memory
+    /// pressure to trigger one
+    MaintenanceCompact,
+    MetricsDescribe,
+    /// Copy the items with keys which begin with the prefix to the instance at
+    /// the destination, sending up to `rate` items per second. With `delete`,
+    /// each item is removed once the destination has stored it
+    Migrate {
+        prefix: Vec<u8>,
+        destination: String,
+        rate: u32,
+        delete: bool,
+    },
+    /// Stop the migration which is in progress
+    MigrateCancel,
+    /// Report the progress of the current or most recent migration
+    MigrateStatus,
+    /// Start a CPU profile, sampling at the given frequency in Hz if provided
+    ProfileStart(Option<u32>),
+    ProfileStop,
+    ProfileHeap,
+    /// A readiness probe, which also checks that the data listener and
+    /// storage are available
+    Ready,
+    Reload,
+    ReloadTls,
+    /// Start or stop serving items past their expiry, in place of misses,
+    /// while the origin is degraded
+    ServeStale(bool),
+    SessionsList,
+    SessionsKill(u64),
+    /// Gracefully stop the process, as if the shutdown signal was received
+    Shutdown,
+    Stats(StatsFormat),
+    /// Enable or disable tracking of per-namespace statistics
+    StatsDetail(bool),
+    StatsDetailDump,
+    StatsDiff(Duration),
+    /// Report the rate per second of selected counters
+    StatsRates,
+    /// Report the counters from zero, other than those of resource usage
+    StatsReset,
+    /// Report the occupancy of the hashtable
+    StatsHashTable,
+    StatsListeners,
+    StatsNamespaces,
+    StatsSegments,
+    /// Change a setting of one kind of event loop without a restart
+    Tune(EventLoop, Tunable),
     Version,
     Quit,
 }
 
+impl AdminRequest {
+    /// The name of the command, which is the first word of the request. This
+    /// is shared by the variants of a command, such as `flush namespace` and
+    /// `flush ttl_bucket`. The exception is `stats reset`, which is named
+    /// `stats_reset` so that it can require authentication while the other
+    /// stats commands do not.
+    pub fn command(&self) -> &'static str {
+        match self {
+            Self::Auth(_) => "auth",
+            Self::BuildInfo => "buildinfo",
+            Self::Custom { verb, .. } => *verb,
+            Self::Confirmed { request, .. } | Self::DryRun(request) => request.command(),
+            Self::FlushAll | Self::FlushAllDelayed(_) => "flush_all",
+            Self::FlushNamespace(_) | Self::FlushTtlBucket(_) => "flush",
+            Self::Fingerprints(_) => "fingerprints",
+            Self::Health => "health",
+            Self::LogLevel | Self::SetLogLevel(_) => "loglevel",
+            Self::MaintenanceCompact => "maintenance",
+            Self::MetricsDescribe => "metrics",
+            Self::Migrate { .. } | Self::MigrateCancel | Self::MigrateStatus => "migrate",
+            Self::ProfileStart(_) | Self::ProfileStop | Self::ProfileHeap => "profile",
+            Self::Ready => "ready",
+            Self::Reload | Self::ReloadTls => "reload",
+            Self::ServeStale(_) => "stale",
+            Self::SessionsList | Self::SessionsKill(_) => "sessions",
+            Self::Shutdown => "shutdown",
+            Self::Stats(_)
+            | Self::StatsDetail(_)
+            | Self::StatsDetailDump
+            | Self::StatsDiff(_)
+            | Self::StatsRates
+            | Self::StatsHashTable
+            | Self::StatsListeners
+            | Self::StatsNamespaces
+            | Self::StatsSegments => "stats",
+            Self::StatsReset => "stats_reset",
+            Self::Tune(..) => "tune",
+            Self::Version => "version",
+            Self::Quit => "quit",
+        }
+    }
+
+    /// Returns true for the commands which remove items or stop the process,
+    /// which may require confirmation.
+    pub fn is_destructive(&self) -> bool {
+        matches!(
+            self,
+            Self::FlushAll
+                | Self::FlushAllDelayed(_)
+                | Self::FlushNamespace(_)
+                | Self::FlushTtlBucket(_)
+                | Self::Shutdown
+        )
+    }
+}
+
+/// The format of the response to `stats`, which is given as an argument to
+/// the command. The classic format is kept for humans and for existing
+/// memcache tooling, while the others are easier for tooling to parse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// A `STAT name value` line for each metric, followed by `END`
+    Classic,
+    /// A single JSON object, with each metric as a key
+    Json,
+    /// A `name=value` line for each metric, followed by `END`
+    Kv,
+}
+
 #[derive(Default, Copy, Clone)]
 pub struct AdminRequestParser {}
 
@@ -46,25 +224,175 @@ impl Parse<AdminRequest> for AdminRequestParser {
             let mut single_byte_windows = trimmed_buffer.windows(1);
             if let Some(command_verb_end) = single_byte_windows.position(|w| w == b" ") {
                 let command_verb = &trimmed_buffer[0..command_verb_end];
-                // TODO(bmartin): 'stats slab' will go here eventually which will
-                // remove the need for ignoring this lint.
-                #[allow(clippy::match_single_binding)]
-                match command_verb {
-                    _ => Err(Error::from(ErrorKind::InvalidInput)),
-                }
+                let args: Vec<&[u8]> = trimmed_buffer[(command_verb_end + 1)..]
+                    .split(|b| *b == b' ')
+                    .filter(|arg| !arg.is_empty())
+                    .collect();
+
+                // a destructive command may end with `--dry-run` or with
+                // `--confirm <token>`
+                let (args, confirmation) = match (command_verb, args.as_slice()) {
+                    (b"flush_all" | b"flush" | b"shutdown", [args @ .., b"--dry-run"]) => {
+                        (args, Some(None))
+                    }
+                    (b"flush_all" | b"flush" | b"shutdown", [args @ .., b"--confirm", token]) => {
+                        (args, Some(Some(*token)))
+                    }
+                    (_, args) => (args, None),
+                };
+
+                let request = match (command_verb, args) {
+                    (b"flush_all", []) => AdminRequest::FlushAll,
+                    (b"shutdown", []) => AdminRequest::Shutdown,
+                    (b"auth", [token]) => AdminRequest::Auth(token.to_vec()),
+                    (b"flush_all", [delay]) => match std::str::from_utf8(delay)
+                        .ok()
+                        .and_then(|delay| delay.parse().ok())
+                    {
+                        Some(0) => AdminRequest::FlushAll,
+                        Some(delay) => AdminRequest::FlushAllDelayed(delay),
+                        None => {
+                            return Err(Error::from(ErrorKind::InvalidInput));
+                        }
+                    },
+                    // scoped variants of flush_all which only remove a subset
+                    // of the items in storage
+                    (b"flush", [b"namespace", namespace]) => {
+                        AdminRequest::FlushNamespace(namespace.to_vec())
+                    }
+                    (b"flush", [b"ttl_bucket", bucket]) => std::str::from_utf8(bucket)
+                        .ok()
+                        .and_then(|bucket| bucket.parse().ok())
+                        .map(AdminRequest::FlushTtlBucket)
+                        .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?,
+                    (b"fingerprints", [b"sample", rate]) => std::str::from_utf8(rate)
+                        .ok()
+                        .and_then(|rate| rate.parse().ok())
+                        .filter(|rate| *rate > 0)
+                        .map(AdminRequest::Fingerprints)
+                        .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?,
+                    (b"loglevel", [level]) => parse_level(level)
+                        .map(AdminRequest::SetLogLevel)
+                        .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?,
+                    (b"maintenance", [b"compact"]) => AdminRequest::MaintenanceCompact,
+                    (b"metrics", [b"describe"]) => AdminRequest::MetricsDescribe,
+                    (b"migrate", [b"cancel"]) => AdminRequest::MigrateCancel,
+                    (b"migrate", [b"status"]) => AdminRequest::MigrateStatus,
+                    (b"migrate", [prefix, destination, rate]) => {
+                        parse_migrate(prefix, destination, rate, false)
+                            .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?
+                    }
+                    (b"migrate", [prefix, destination, rate, b"delete"]) => {
+                        parse_migrate(prefix, destination, rate, true)
+                            .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?
+                    }
+                    (b"profile", [b"start"]) => AdminRequest::ProfileStart(None),
+                    (b"profile", [b"start", frequency]) => std::str::from_utf8(frequency)
+                        .ok()
+                        .and_then(|frequency| frequency.parse().ok())
+                        .filter(|frequency| *frequency > 0)
+                        .map(|frequency| AdminRequest::ProfileStart(Some(frequency)))
+                        .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?,
+                    (b"profile", [b"stop"]) => AdminRequest::ProfileStop,
+                    (b"profile", [b"heap"]) => AdminRequest::ProfileHeap,
+                    (b"reload", [b"tls"]) => AdminRequest::ReloadTls,
+                    (b"sessions", [b"list"]) => AdminRequest::SessionsList,
+                    (b"sessions", [b"kill", id]) => std::str::from_utf8(id)
+                        .ok()
+                        .and_then(|id| id.parse().ok())
+                        .map(AdminRequest::SessionsKill)
+                        .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?,
+                    (b"stale", [b"on"]) => AdminRequest::ServeStale(true),
+                    (b"stale", [b"off"]) => AdminRequest::ServeStale(false),
+                    // the interval must be at least one second, as a shorter
+                    // interval would only report noise
+                    (b"stats", [b"diff", seconds]) => std::str::from_utf8(seconds)
+                        .ok()
+                        .and_then(|seconds| seconds.parse().ok())
+                        .filter(|seconds| (1..=STATS_DIFF_MAX).contains(seconds))
+                        .map(|seconds| AdminRequest::StatsDiff(Duration::from_secs(seconds)))
+                        .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?,
+                    (b"stats", [b"detail", b"on"]) => AdminRequest::StatsDetail(true),
+                    (b"stats", [b"detail", b"off"]) => AdminRequest::StatsDetail(false),
+                    (b"stats", [b"detail", b"dump"]) => AdminRequest::StatsDetailDump,
+                    (b"stats", [b"hashtable"]) => AdminRequest::StatsHashTable,
+                    (b"stats", [b"json"]) => AdminRequest::Stats(StatsFormat::Json),
+                    (b"stats", [b"kv"]) => AdminRequest::Stats(StatsFormat::Kv),
+                    (b"stats", [b"listeners"]) => AdminRequest::StatsListeners,
+                    (b"stats", [b"namespaces"]) => AdminRequest::StatsNamespaces,
+                    (b"stats", [b"rates"]) => AdminRequest::StatsRates,
+                    (b"stats", [b"reset"]) => AdminRequest::StatsReset,
+                    (b"stats", [b"segments"]) => AdminRequest::StatsSegments,
+                    (b"tune", [target, setting, value]) => parse_tunable(target, setting, value)
+                        .map(|(target, tunable)| AdminRequest::Tune(target, tunable))
+                        .ok_or_else(|| Error::from(ErrorKind::InvalidInput))?,
+                    (verb, args) => match custom::registered(verb) {
+                        Some(verb) => AdminRequest::Custom {
+                            verb,
+                            args: args.iter().map(|arg| arg.to_vec()).collect(),
+                        },
+                        None => {
+                            return Err(Error::from(ErrorKind::InvalidInput));
+                        }
+                    },
+                };
+
+                let request = match confirmation {
+                    None => request,
+                    Some(None) => AdminRequest::DryRun(Box::new(request)),
+                    Some(Some(token)) => AdminRequest::Confirmed {
+                        request: Box::new(request),
+                        token: std::str::from_utf8(token)
+                            .map_err(|_| Error::from(ErrorKind::InvalidInput))?
+                            .to_string(),
+                    },
+                };
+
+                Ok(ParseOk::new(request, command_end + CRLF.len()))
             } else {
                 match &trimmed_buffer[0..] {
                     b"flush_all" => Ok(ParseOk::new(
                         AdminRequest::FlushAll,
                         command_end + CRLF.len(),
                     )),
-                    b"stats" => Ok(ParseOk::new(AdminRequest::Stats, command_end + CRLF.len())),
+                    b"stats" => Ok(ParseOk::new(
+                        AdminRequest::Stats(StatsFormat::Classic),
+                        command_end + CRLF.len(),
+                    )),
+                    b"buildinfo" => Ok(ParseOk::new(
+                        AdminRequest::BuildInfo,
+                        command_end + CRLF.len(),
+                    )),
+                    b"fingerprints" => Ok(ParseOk::new(
+                        AdminRequest::Fingerprints(1),
+                        command_end + CRLF.len(),
+                    )),
+                    b"health" => Ok(ParseOk::new(AdminRequest::Health, command_end + CRLF.len())),
+                    b"ready" => Ok(ParseOk::new(AdminRequest::Ready, command_end + CRLF.len())),
+                    b"loglevel" => Ok(ParseOk::new(
+                        AdminRequest::LogLevel,
+                        command_end + CRLF.len(),
+                    )),
                     b"quit" => Ok(ParseOk::new(AdminRequest::Quit, command_end + CRLF.len())),
+                    b"reload" => Ok(ParseOk::new(AdminRequest::Reload, command_end + CRLF.len())),
+                    b"shutdown" => Ok(ParseOk::new(
+                        AdminRequest::Shutdown,
+                        command_end + CRLF.len(),
+                    )),
                     b"version" => Ok(ParseOk::new(
                         AdminRequest::Version,
                         command_end + CRLF.len(),
                     )),
-                    _ => Err(Error::from(ErrorKind::InvalidInput)),
+                    verb => match custom::registered(verb) {
+                        Some(verb) => Ok(ParseOk::new(
+                            AdminRequest::Custom {
+                                verb,
+                                args: Vec::new(),
+                            },
+                            command_end + CRLF.len(),
+                        )),
+                        None => Err(Error::from(ErrorKind::InvalidInput)),
+                    },
                 }
             }
         } else {
@@ -73,6 +401,72 @@ impl Parse<AdminRequest> for AdminRequestParser {
     }
 }
 
+// the levels are only accepted in lowercase, as they appear in the config
+pub(crate) fn parse_level(level: &[u8]) -> Option<Level> {
+    match level {
+        b"trace" => Some(Level::Trace),
+        b"debug" => Some(Level::Debug),
+        b"info" => Some(Level::Info),
+        b"warn" => Some(Level::Warn),
+        b"error" => Some(Level::Error),
+        _ => None,
+    }
+}
+
+// parses the arguments of `tune <admin|server|worker> <nevent|timeout> <value>`,
+// where the timeout is in milliseconds
+fn parse_tunable(target: &[u8], setting: &[u8], value: &[u8]) -> Option<(EventLoop, Tunable)> {
+    let target = match target {
+        b"admin" => EventLoop::Admin,
+        b"server" => EventLoop::Server,
+        b"worker" => EventLoop::Worker,
+        _ => return None,
+    };
+
+    let value = std::str::from_utf8(value).ok()?;
+
+    let tunable = match setting {
+        b"nevent" => Tunable::Nevent(
+            value
+                .parse()
+                .ok()
+                .filter(|nevent| (1..=TUNE_NEVENT_MAX).contains(nevent))?,
+        ),
+        b"timeout" => Tunable::Timeout(Duration::from_millis(
+            value
+                .parse()
+                .ok()
+                .filter(|timeout| *timeout <= TUNE_TIMEOUT_MAX)?,
+        )),
+        _ => return None,
+    };
+
+    Some((target, tunable))
+}
+
+// parses the arguments of `migrate <prefix> <host:port> <rate> [delete]`, where
+// the rate is in items per second
+fn parse_migrate(
+    prefix: &[u8],
+    destination: &[u8],
+    rate: &[u8],
+    delete: bool,
+) -> Option<AdminRequest> {
+    let destination = std::str::from_utf8(destination).ok()?.to_string();
+    let rate = std::str::from_utf8(rate)
+        .ok()?
+        .parse()
+        .ok()
+        .filter(|rate| *rate > 0)?;
+
+    Some(AdminRequest::Migrate {
+        prefix: prefix.to_vec(),
+        destination,
+        rate,
+        delete,
+    })
+}
+
 pub struct Version {
     version: String,
 }
@@ -87,40 +481,354 @@ impl Compose for Version {
     }
 }
 
+/// The result of a health or readiness probe. Each failure is reported as a
+/// distinct state, so that an orchestrator can tell why the probe failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Probe {
+    Ok,
+    /// Not every thread replied to the probe in time
+    Unresponsive,
+    /// No thread has a data listener which is registered for events
+    NotListening,
+    /// No thread holds storage which has been initialized
+    NoStorage,
+    /// Storage is still in the warm-up period after startup
+    WarmingUp,
+}
+
+impl Probe {
+    /// The name of the state, as reported in a probe response.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Unresponsive => "unresponsive",
+            Self::NotListening => "not_listening",
+            Self::NoStorage => "no_storage",
+            Self::WarmingUp => "warming_up",
+        }
+    }
+}
+
+/// What a destructive command would affect, as described by a dry run, and
+/// the token which confirms the command. The counts are estimates, as items
+/// may be stored or expire before the command runs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DryRun {
+    pub token: String,
+    /// The time for which the token may be used
+    pub expires: Duration,
+    /// Each count, such as the number of items which would be removed
+    pub impact: Vec<(&'static str, u64)>,
+}
+
+/// Reports every counter from zero, other than the resource usage counters,
+/// which are copied from the operating system. Gauges describe the current
+/// state rather than a total and are unaffected.
+pub fn reset_counters() {
+    let mut reset = BTreeMap::new();
+    for metric in &rustcommon_metrics::metrics() {
+        if metric.name().starts_with("ru_") {
+            continue;
+        }
+        if let Some(counter) = metric
+            .as_any()
+            .and_then(|any| any.downcast_ref::<Counter>())
+        {
+            reset.insert(metric.name().to_string(), counter.value());
+        }
+    }
+    *RESET.write().unwrap() = Some(Arc::new(reset));
+}
+
+/// The value of the named counter as it is reported, which is the count since
+/// the counters were last reset.
+pub fn reported_counter(name: &str, value: u64) -> u64 {
+    let reset = RESET
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|reset| reset.get(name).copied())
+        .unwrap_or(0);
+    value.wrapping_sub(reset)
+}
+
+/// The value of each metric at a point in time, named as in the `stats`
+/// response. Two snapshots are compared to find the metrics which changed
+/// over an interval.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    values: BTreeMap<String, i64>,
+    // the values of the counters when they were last reset, which are only
+    // subtracted when the snapshot is reported so that a diff across a reset
+    // is still the change in each counter
+    reset: Option<Arc<BTreeMap<String, u64>>>,
+}
+
+impl StatsSnapshot {
+    /// Records the current value of every metric.
+    pub fn capture() -> Self {
+        let mut values = BTreeMap::new();
+        for metric in &rustcommon_metrics::metrics() {
+            let any = match metric.as_any() {
+                Some(any) => any,
+                None => {
+                    continue;
+                }
+            };
+
+            if let Some(counter) = any.downcast_ref::<Counter>() {
+                values.insert(metric.name().to_string(), counter.value() as i64);
+            } else if let Some(gauge) = any.downcast_ref::<Gauge>() {
+                values.insert(metric.name().to_string(), gauge.value());
+            } else if let Some(heatmap) = any.downcast_ref::<Heatmap>() {
+                for (label, value) in PERCENTILES {
+                    let percentile = heatmap.percentile(*value).unwrap_or(0);
+                    values.insert(format!("{}_{}", metric.name(), label), percentile as i64);
+                }
+            }
+        }
+        for (thread, usage) in common::rusage::snapshot() {
+            for ((field, _), value) in common::rusage::FIELDS.iter().zip(usage.values().iter()) {
+                values.insert(format!("thread_{}_{}", thread, field), *value as i64);
+            }
+        }
+        Self {
+            values,
+            reset: RESET.read().unwrap().clone(),
+        }
+    }
+
+    // the value of each metric as it is reported, with each counter counted
+    // from when the counters were last reset
+    fn reported(&self) -> impl Iterator<Item = (&String, i64)> + '_ {
+        self.values.iter().map(move |(name, value)| {
+            let reset = self
+                .reset
+                .as_ref()
+                .and_then(|reset| reset.get(name).copied())
+                .unwrap_or(0);
+            (name, value.wrapping_sub(reset as i64))
+        })
+    }
+
+    /// Returns the change in value of each metric which differs from the
+    /// earlier snapshot, sorted by name. A metric which is missing from the
+    /// earlier snapshot is treated as having been zero.
+    pub fn diff(&self, earlier: &Self) -> Vec<(String, i64)> {
+        self.values
+            .iter()
+            .filter_map(|(name, value)| {
+                let previous = earlier.values.get(name).copied().unwrap_or(0);
+                let delta = value.wrapping_sub(previous);
+                if delta != 0 {
+                    Some((name.clone(), delta))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Renders the snapshot as a single JSON object. The map keeps its keys
+    /// sorted.
+    pub fn to_json(&self) -> String {
+        let data: serde_json::Map<String, serde_json::Value> = self
+            .reported()
+            .map(|(name, value)| (name.clone(), value.into()))
+            .collect();
+
+        serde_json::Value::Object(data).to_string()
+    }
+}
+
 pub enum AdminResponse {
+    ClientError(String),
+    /// The number of segments freed by compaction
+    Compacted(usize),
+    /// A part of a fingerprint export, which is followed by `END` if it is
+    /// the last part
+    Fingerprints {
+        fingerprints: Vec<Fingerprint>,
+        last: bool,
+    },
     Hangup,
+    HeapStats(Vec<(String, u64)>),
+    /// Lines of text followed by `END`, for the responses of custom commands
+    Lines(Vec<String>),
+    LogLevel(Option<Level>),
+    MetricsDescribe,
+    NotFound,
     Ok,
-    Stats,
+    Probe(Probe),
+    Profile(String),
+    ServerError(String),
+    Sessions(Vec<SessionInfo>),
+    Stats(StatsSnapshot, StatsFormat),
+    StatsDetailDump(Vec<(Box<[u8]>, Arc<NamespaceStats>)>),
+    StatsDiff(Vec<(String, i64)>),
+    StatsRates(Vec<(String, f64)>),
+    StatsHashTable(HashTableInfo),
+    StatsListeners(Vec<(String, Arc<ListenerStats>)>),
+    StatsNamespaces(Vec<(Box<[u8]>, Arc<NamespaceStats>)>),
+    StatsSegments(Vec<TtlBucketInfo>),
     Version(Version),
 }
 
 impl AdminResponse {
+    pub fn client_error<T: ToString>(message: T) -> Self {
+        Self::ClientError(message.to_string())
+    }
+
+    pub fn compacted(segments: usize) -> Self {
+        Self::Compacted(segments)
+    }
+
+    pub fn fingerprints(fingerprints: Vec<Fingerprint>, last: bool) -> Self {
+        Self::Fingerprints { fingerprints, last }
+    }
+
     pub fn hangup() -> Self {
         Self::Hangup
     }
 
+    pub fn heap_stats(stats: Vec<(String, u64)>) -> Self {
+        Self::HeapStats(stats)
+    }
+
+    /// A response with each of the lines, which must not contain a line
+    /// break, followed by `END`.
+    pub fn lines(lines: Vec<String>) -> Self {
+        Self::Lines(lines)
+    }
+
+    pub fn log_level(level: Option<Level>) -> Self {
+        Self::LogLevel(level)
+    }
+
+    pub fn metrics_describe() -> Self {
+        Self::MetricsDescribe
+    }
+
+    pub fn not_found() -> Self {
+        Self::NotFound
+    }
+
     pub fn ok() -> Self {
         Self::Ok
     }
 
-    pub fn stats() -> Self {
-        Self::Stats
+    pub fn probe(probe: Probe) -> Self {
+        Self::Probe(probe)
+    }
+
+    pub fn profile<T: ToString>(path: T) -> Self {
+        Self::Profile(path.to_string())
+    }
+
+    pub fn server_error<T: ToString>(message: T) -> Self {
+        Self::ServerError(message.to_string())
+    }
+
+    pub fn sessions(sessions: Vec<SessionInfo>) -> Self {
+        Self::Sessions(sessions)
+    }
+
+    pub fn stats(format: StatsFormat) -> Self {
+        Self::Stats(StatsSnapshot::capture(), format)
+    }
+
+    pub fn stats_detail_dump() -> Self {
+        Self::StatsDetailDump(common::namespace::snapshot())
+    }
+
+    pub fn stats_diff(earlier: &StatsSnapshot, later: &StatsSnapshot) -> Self {
+        Self::StatsDiff(later.diff(earlier))
+    }
+
+    pub fn stats_rates(rates: Vec<(String, f64)>) -> Self {
+        Self::StatsRates(rates)
+    }
+
+    pub fn stats_hashtable(hashtable: HashTableInfo) -> Self {
+        Self::StatsHashTable(hashtable)
+    }
+
+    pub fn stats_listeners() -> Self {
+        Self::StatsListeners(common::listener::snapshot())
+    }
+
+    pub fn stats_namespaces() -> Self {
+        Self::StatsNamespaces(common::namespace::snapshot())
+    }
+
+    pub fn stats_segments(buckets: Vec<TtlBucketInfo>) -> Self {
+        Self::StatsSegments(buckets)
     }
 
     pub fn version(version: String) -> Self {
         Self::Version(Version { version })
     }
+
+    /// A line with the version, followed by a line for each field of the
+    /// build information, each as the name and then the value.
+    pub fn build_info(version: &str) -> Self {
+        let mut lines = vec![format!("version {}", version)];
+        for (name, value) in common::buildinfo::fields() {
+            lines.push(format!("{} {}", name, value));
+        }
+        Self::Lines(lines)
+    }
+
+    /// The token which confirms a destructive command and the number of
+    /// seconds it may be used for, followed by a line for each count of what
+    /// the command would affect.
+    pub fn dry_run(dry_run: &DryRun) -> Self {
+        let mut lines = vec![
+            format!("token {}", dry_run.token),
+            format!("expires {}", dry_run.expires.as_secs()),
+        ];
+        for (name, count) in &dry_run.impact {
+            lines.push(format!("{} {}", name, count));
+        }
+        Self::Lines(lines)
+    }
 }
 
 impl Compose for AdminResponse {
     fn compose(&self, buf: &mut dyn BufMut) -> usize {
         match self {
+            Self::ClientError(message) => {
+                let line = format!("CLIENT_ERROR {}\r\n", message);
+                buf.put_slice(line.as_bytes());
+                line.len()
+            }
+            Self::Compacted(segments) => {
+                let line = format!("COMPACTED {}\r\n", segments);
+                buf.put_slice(line.as_bytes());
+                line.len()
+            }
             Self::Hangup => 0,
-            Self::Ok => {
-                buf.put_slice(b"OK\r\n");
-                4
+            Self::HeapStats(stats) => {
+                let mut size = 0;
+                for (name, value) in stats {
+                    let line = format!("STAT {} {}\r\n", name, value);
+                    size += line.as_bytes().len();
+                    buf.put_slice(line.as_bytes());
+                }
+                buf.put_slice(b"END\r\n");
+                size + 5
+            }
+            Self::LogLevel(level) => {
+                let level = level
+                    .map(|l| l.as_str().to_lowercase())
+                    .unwrap_or_else(|| "off".to_string());
+                let line = format!("LOGLEVEL {}\r\n", level);
+                buf.put_slice(line.as_bytes());
+                line.len()
             }
-            Self::Stats => {
+            Self::MetricsDescribe => {
+                // each line contains the metric name and type, followed by
+                // the description which may contain spaces
                 let mut size = 0;
                 let mut data = Vec::new();
                 for metric in &rustcommon_metrics::metrics() {
@@ -131,18 +839,27 @@ impl Compose for AdminResponse {
                         }
                     };
 
-                    if let Some(counter) = any.downcast_ref::<Counter>() {
-                        data.push(format!("STAT {} {}\r\n", metric.name(), counter.value()));
-                    } else if let Some(gauge) = any.downcast_ref::<Gauge>() {
-                        data.push(format!("STAT {} {}\r\n", metric.name(), gauge.value()));
-                    } else if let Some(heatmap) = any.downcast_ref::<Heatmap>() {
-                        for (label, value) in PERCENTILES {
-                            let percentile = heatmap.percentile(*value).unwrap_or(0);
+                    let description = metric.description().unwrap_or("");
+
+                    if any.downcast_ref::<Counter>().is_some() {
+                        data.push(format!(
+                            "METRIC {} counter {}\r\n",
+                            metric.name(),
+                            description
+                        ));
+                    } else if any.downcast_ref::<Gauge>().is_some() {
+                        data.push(format!(
+                            "METRIC {} gauge {}\r\n",
+                            metric.name(),
+                            description
+                        ));
+                    } else if any.downcast_ref::<Heatmap>().is_some() {
+                        for (label, _) in PERCENTILES {
                             data.push(format!(
-                                "STAT {}_{} {}\r\n",
+                                "METRIC {}_{} percentile {}\r\n",
                                 metric.name(),
                                 label,
-                                percentile
+                                description
                             ));
                         }
                     }
@@ -156,15 +873,317 @@ impl Compose for AdminResponse {
                 buf.put_slice(b"END\r\n");
                 size + 5
             }
+            Self::NotFound => {
+                buf.put_slice(b"NOT_FOUND\r\n");
+                11
+            }
+            Self::Ok => {
+                buf.put_slice(b"OK\r\n");
+                4
+            }
+            Self::Profile(path) => {
+                let line = format!("PROFILE {}\r\n", path);
+                buf.put_slice(line.as_bytes());
+                line.len()
+            }
+            Self::Probe(Probe::Ok) => {
+                buf.put_slice(b"OK\r\n");
+                4
+            }
+            Self::Probe(probe) => {
+                let line = format!("SERVER_ERROR {}\r\n", probe.as_str());
+                buf.put_slice(line.as_bytes());
+                line.len()
+            }
+            Self::ServerError(message) => {
+                let line = format!("SERVER_ERROR {}\r\n", message);
+                buf.put_slice(line.as_bytes());
+                line.len()
+            }
+            Self::Fingerprints { .. }
+            | Self::Lines(_)
+            | Self::Sessions(_)
+            | Self::Stats(..)
+            | Self::StatsDetailDump(_)
+            | Self::StatsDiff(_)
+            | Self::StatsRates(_)
+            | Self::StatsListeners(_)
+            | Self::StatsNamespaces(_)
+            | Self::StatsSegments(_) => self.compose_partial(buf, &mut 0, usize::MAX).0,
+            Self::StatsHashTable(hashtable) => {
+                // the summary is followed by the distributions, with a line for
+                // each chain length and each number of items which occur
+                let mut lines = vec![
+                    format!("STAT power {}\r\n", hashtable.power),
+                    format!("STAT buckets {}\r\n", hashtable.buckets),
+                    format!("STAT overflow_buckets {}\r\n", hashtable.overflow_buckets),
+                    format!("STAT overflow_used {}\r\n", hashtable.overflow_used),
+                    format!("STAT items {}\r\n", hashtable.items),
+                    format!("STAT slots {}\r\n", hashtable.slots),
+                    format!("STAT load_factor {:.4}\r\n", hashtable.load_factor()),
+                    format!("STAT lookups {}\r\n", hashtable.lookups),
+                    format!("STAT tag_collisions {}\r\n", hashtable.tag_collisions),
+                    format!("STAT collision_rate {:.6}\r\n", hashtable.collision_rate()),
+                ];
+                for (len, buckets) in hashtable.chain_lengths.iter().enumerate() {
+                    if *buckets > 0 {
+                        lines.push(format!("CHAIN_LEN {} buckets={}\r\n", len, buckets));
+                    }
+                }
+                for (items, buckets) in hashtable.occupancy.iter().enumerate() {
+                    if *buckets > 0 {
+                        lines.push(format!("OCCUPANCY {} buckets={}\r\n", items, buckets));
+                    }
+                }
+
+                let mut size = 0;
+                for line in lines {
+                    size += line.len();
+                    buf.put_slice(line.as_bytes());
+                }
+                buf.put_slice(b"END\r\n");
+                size + 5
+            }
             Self::Version(v) => v.compose(buf),
         }
     }
+
+    // the stats responses may be large, so they are composed from a snapshot
+    // taken when the response was created and the cursor is the number of
+    // lines composed so far
+    fn compose_partial(
+        &self,
+        buf: &mut dyn BufMut,
+        cursor: &mut usize,
+        limit: usize,
+    ) -> (usize, bool) {
+        match self {
+            Self::Fingerprints { fingerprints, last } => {
+                // each line contains the key hash, the value hash, and the
+                // index of the ttl bucket, with the hashes in hexadecimal
+                let lines = fingerprints.iter().skip(*cursor).map(|fingerprint| {
+                    format!(
+                        "FINGERPRINT {:016x} {:016x} {}\r\n",
+                        fingerprint.key, fingerprint.value, fingerprint.ttl_bucket
+                    )
+                });
+                if *last {
+                    compose_lines(buf, lines, cursor, limit)
+                } else {
+                    compose_part(buf, lines, cursor, limit)
+                }
+            }
+            Self::Lines(lines) => {
+                let lines = lines
+                    .iter()
+                    .skip(*cursor)
+                    .map(|line| format!("{}\r\n", line));
+                compose_lines(buf, lines, cursor, limit)
+            }
+            Self::Sessions(sessions) => {
+                // each line contains the session id followed by its fields,
+                // with the age in seconds
+                let lines = sessions.iter().skip(*cursor).map(|session| {
+                    let peer_addr = session
+                        .peer_addr
+                        .map(|addr| addr.to_string())
+                        .unwrap_or_else(|| "unknown".to_string());
+                    format!(
+                        "SESSION {} peer={} age={} bytes_read={} bytes_written={} read_pending={} write_pending={}\r\n",
+                        session.id,
+                        peer_addr,
+                        session.age.as_secs(),
+                        session.bytes_read,
+                        session.bytes_written,
+                        session.read_pending,
+                        session.write_pending
+                    )
+                });
+                compose_lines(buf, lines, cursor, limit)
+            }
+            Self::Stats(snapshot, StatsFormat::Json) => {
+                // the object is opened before the first entry and closed after
+                // the last, so the entries can be composed across calls
+                let start = *cursor;
+                let mut size = 0;
+                if start == 0 {
+                    buf.put_slice(b"{");
+                    size += 1;
+                }
+                for (index, (name, value)) in snapshot.reported().enumerate().skip(*cursor) {
+                    if index > start && size >= limit {
+                        return (size, false);
+                    }
+                    let separator = if index == 0 { "" } else { "," };
+                    let entry = format!(
+                        "{}{}:{}",
+                        separator,
+                        serde_json::Value::from(name.as_str()),
+                        value
+                    );
+                    buf.put_slice(entry.as_bytes());
+                    size += entry.len();
+                    *cursor += 1;
+                }
+                buf.put_slice(b"}\r\n");
+                (size + 3, true)
+            }
+            Self::Stats(snapshot, StatsFormat::Classic) => {
+                let lines = snapshot
+                    .reported()
+                    .skip(*cursor)
+                    .map(|(name, value)| format!("STAT {} {}\r\n", name, value));
+                compose_lines(buf, lines, cursor, limit)
+            }
+            Self::Stats(snapshot, StatsFormat::Kv) => {
+                let lines = snapshot
+                    .reported()
+                    .skip(*cursor)
+                    .map(|(name, value)| format!("{}={}\r\n", name, value));
+                compose_lines(buf, lines, cursor, limit)
+            }
+            Self::StatsDetailDump(namespaces) => {
+                // the format of the memcached detail dump, with one line for
+                // each namespace
+                let lines = namespaces.iter().skip(*cursor).map(|(namespace, stats)| {
+                    format!(
+                        "PREFIX {} get {} hit {} set {} del {}\r\n",
+                        String::from_utf8_lossy(namespace),
+                        stats.reads(),
+                        stats.hits(),
+                        stats.writes(),
+                        stats.deletes()
+                    )
+                });
+                compose_lines(buf, lines, cursor, limit)
+            }
+            Self::StatsDiff(deltas) => {
+                let lines = deltas
+                    .iter()
+                    .skip(*cursor)
+                    .map(|(name, delta)| format!("STAT {} {}\r\n", name, delta));
+                compose_lines(buf, lines, cursor, limit)
+            }
+            Self::StatsRates(rates) => {
+                let lines = rates
+                    .iter()
+                    .skip(*cursor)
+                    .map(|(name, rate)| format!("STAT {} {:.2}\r\n", name, rate));
+                compose_lines(buf, lines, cursor, limit)
+            }
+            Self::StatsListeners(listeners) => {
+                // the snapshot is sorted by name, so the fields for each
+                // listener are grouped together. latencies are upper bounds
+                // in microseconds
+                let lines = listeners
+                    .iter()
+                    .flat_map(|(name, stats)| {
+                        let mut fields = vec![
+                            ("request".to_string(), stats.requests()),
+                            ("response".to_string(), stats.responses()),
+                        ];
+                        for (label, percentile) in LISTENER_PERCENTILES {
+                            let latency = stats.latency(*percentile).as_micros() as u64;
+                            fields.push((format!("latency_{}_us", label), latency));
+                        }
+                        fields
+                            .into_iter()
+                            .map(|(field, value)| format!("STAT {}:{} {}\r\n", name, field, value))
+                            .collect::<Vec<String>>()
+                    })
+                    .skip(*cursor);
+                compose_lines(buf, lines, cursor, limit)
+            }
+            Self::StatsNamespaces(namespaces) => {
+                // the snapshot is sorted by namespace, so the fields for each
+                // namespace are grouped together
+                let lines = namespaces
+                    .iter()
+                    .flat_map(|(namespace, stats)| {
+                        let namespace = String::from_utf8_lossy(namespace).into_owned();
+                        [
+                            ("requests", stats.requests()),
+                            ("hits", stats.hits()),
+                            ("misses", stats.misses()),
+                            ("bytes_stored", stats.bytes_stored()),
+                            ("evictions", stats.evictions()),
+                        ]
+                        .iter()
+                        .map(|(field, value)| format!("STAT {}:{} {}\r\n", namespace, field, value))
+                        .collect::<Vec<String>>()
+                    })
+                    .skip(*cursor);
+                compose_lines(buf, lines, cursor, limit)
+            }
+            Self::StatsSegments(buckets) => {
+                // each line contains the ttl bucket index followed by its
+                // fields, with durations in seconds
+                let lines = buckets.iter().skip(*cursor).map(|bucket| {
+                    format!(
+                        "TTL_BUCKET {} ttl={} segments={} live_items={} live_bytes={} dead_bytes={} oldest={} merged={}\r\n",
+                        bucket.index,
+                        bucket.ttl.as_secs(),
+                        bucket.segments,
+                        bucket.live_items,
+                        bucket.live_bytes,
+                        bucket.dead_bytes,
+                        bucket.oldest.as_secs(),
+                        bucket.merged
+                    )
+                });
+                compose_lines(buf, lines, cursor, limit)
+            }
+            _ => (self.compose(buf), true),
+        }
+    }
+}
+
+// Composes lines until at least `limit` bytes are written, followed by the
+// `END` marker once there are no lines remaining. The lines must begin after
+// the `cursor` lines which were composed by earlier calls.
+fn compose_lines<I: Iterator<Item = String>>(
+    buf: &mut dyn BufMut,
+    lines: I,
+    cursor: &mut usize,
+    limit: usize,
+) -> (usize, bool) {
+    let (size, complete) = compose_part(buf, lines, cursor, limit);
+    if !complete {
+        return (size, false);
+    }
+
+    buf.put_slice(b"END\r\n");
+    (size + 5, true)
+}
+
+// composes the lines without the `END` which closes a response, for the parts
+// of a response which is sent in several parts
+fn compose_part<I: Iterator<Item = String>>(
+    buf: &mut dyn BufMut,
+    lines: I,
+    cursor: &mut usize,
+    limit: usize,
+) -> (usize, bool) {
+    let mut size = 0;
+    for line in lines {
+        if size >= limit {
+            return (size, false);
+        }
+        buf.put_slice(line.as_bytes());
+        size += line.len();
+        *cursor += 1;
+    }
+    (size, true)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    counter!(TEST_RESET_COUNTER);
+    counter!(RU_TEST_RESET);
+    gauge!(TEST_RESET_GAUGE);
+
     #[test]
     fn parse_incomplete() {
         let parser = AdminRequestParser::new();
@@ -186,33 +1205,1048 @@ mod tests {
         let parsed = parser.parse(b"flush_all\r\n");
         assert!(parsed.is_ok());
         assert_eq!(parsed.unwrap().into_inner(), AdminRequest::FlushAll);
+
+        let parsed = parser.parse(b"flush_all 0\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::FlushAll);
+
+        let parsed = parser.parse(b"flush_all 30\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::FlushAllDelayed(30));
+        assert_eq!(request.command(), "flush_all");
+
+        let buffers: Vec<&[u8]> = vec![b"flush_all soon\r\n", b"flush_all 30 60\r\n"];
+        for buffer in buffers.iter() {
+            assert!(parser.parse(buffer).is_err());
+        }
     }
 
     #[test]
-    fn parse_quit() {
+    fn parse_flush_scoped() {
         let parser = AdminRequestParser::new();
 
-        let parsed = parser.parse(b"quit\r\n");
+        let parsed = parser.parse(b"flush namespace tenant\r\n");
         assert!(parsed.is_ok());
-        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::Quit);
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::FlushNamespace(b"tenant".to_vec())
+        );
+
+        let parsed = parser.parse(b"flush ttl_bucket 42\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::FlushTtlBucket(42)
+        );
+
+        let buffers: Vec<&[u8]> = vec![
+            b"flush namespace\r\n",
+            b"flush ttl_bucket abc\r\n",
+            b"flush everything now\r\n",
+        ];
+        for buffer in buffers.iter() {
+            if let Err(e) = parser.parse(buffer) {
+                assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            } else {
+                panic!("parser should not have returned a request");
+            }
+        }
     }
 
     #[test]
-    fn parse_stats() {
+    fn parse_loglevel() {
         let parser = AdminRequestParser::new();
 
-        let parsed = parser.parse(b"stats\r\n");
+        let parsed = parser.parse(b"loglevel\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::LogLevel);
+
+        let parsed = parser.parse(b"loglevel debug\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::SetLogLevel(Level::Debug)
+        );
+
+        let buffers: Vec<&[u8]> = vec![b"loglevel verbose\r\n", b"loglevel info warn\r\n"];
+        for buffer in buffers.iter() {
+            if let Err(e) = parser.parse(buffer) {
+                assert_eq!(e.kind(), ErrorKind::InvalidInput);
+            } else {
+                panic!("parser should not have returned a request");
+            }
+        }
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::log_level(Some(Level::Warn)).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], b"LOGLEVEL warn\r\n");
+    }
+
+    #[test]
+    fn parse_metrics_describe() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"metrics describe\r\n");
         assert!(parsed.is_ok());
-        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::Stats);
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::MetricsDescribe);
+
+        if let Err(e) = parser.parse(b"metrics\r\n") {
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        } else {
+            panic!("parser should not have returned a request");
+        }
     }
 
     #[test]
-    fn parse_version() {
+    fn parse_profile() {
         let parser = AdminRequestParser::new();
 
-        let parsed = parser.parse(b"version\r\n");
+        let parsed = parser.parse(b"profile start\r\n");
         assert!(parsed.is_ok());
-        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::Version);
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::ProfileStart(None)
+        );
+
+        let parsed = parser.parse(b"profile start 250\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::ProfileStart(Some(250)));
+        assert_eq!(request.command(), "profile");
+
+        let parsed = parser.parse(b"profile stop\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::ProfileStop);
+
+        let parsed = parser.parse(b"profile heap\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::ProfileHeap);
+
+        let buffers: Vec<&[u8]> = vec![
+            b"profile\r\n",
+            b"profile start 0\r\n",
+            b"profile start fast\r\n",
+            b"profile pause\r\n",
+        ];
+        for buffer in buffers.iter() {
+            assert!(parser.parse(buffer).is_err());
+        }
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::profile("/tmp/profile.pb").compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], b"PROFILE /tmp/profile.pb\r\n");
+    }
+
+    #[test]
+    fn parse_stale() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"stale on\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::ServeStale(true));
+        assert_eq!(request.command(), "stale");
+
+        let parsed = parser.parse(b"stale off\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::ServeStale(false)
+        );
+
+        let buffers: Vec<&[u8]> = vec![b"stale\r\n", b"stale 60\r\n"];
+        for buffer in buffers.iter() {
+            assert!(parser.parse(buffer).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_fingerprints() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"fingerprints\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::Fingerprints(1));
+        assert_eq!(request.command(), "fingerprints");
+
+        let parsed = parser.parse(b"fingerprints sample 100\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::Fingerprints(100)
+        );
+
+        let buffers: Vec<&[u8]> = vec![
+            b"fingerprints sample\r\n",
+            b"fingerprints sample 0\r\n",
+            b"fingerprints 100\r\n",
+        ];
+        for buffer in buffers.iter() {
+            assert!(parser.parse(buffer).is_err());
+        }
+    }
+
+    #[test]
+    fn compose_fingerprints() {
+        let fingerprint = Fingerprint {
+            key: 0x0123456789abcdef,
+            value: 0xff,
+            ttl_bucket: 3,
+        };
+
+        // only the last part of the export is followed by END
+        let mut buf = Vec::new();
+        let size = AdminResponse::fingerprints(vec![fingerprint], false).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(buf, b"FINGERPRINT 0123456789abcdef 00000000000000ff 3\r\n");
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::fingerprints(vec![fingerprint], true).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            buf,
+            b"FINGERPRINT 0123456789abcdef 00000000000000ff 3\r\nEND\r\n"
+        );
+    }
+
+    #[test]
+    fn parse_maintenance() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"maintenance compact\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::MaintenanceCompact);
+        assert_eq!(request.command(), "maintenance");
+
+        let buffers: Vec<&[u8]> = vec![b"maintenance\r\n", b"maintenance defrag\r\n"];
+        for buffer in buffers.iter() {
+            assert!(parser.parse(buffer).is_err());
+        }
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::compacted(3).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], b"COMPACTED 3\r\n");
+    }
+
+    #[test]
+    fn parse_quit() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"quit\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::Quit);
+    }
+
+    #[test]
+    fn parse_auth() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"auth s3cret\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::Auth(b"s3cret".to_vec()));
+        assert_eq!(request.command(), "auth");
+
+        let parsed = parser.parse(b"auth\r\n");
+        assert!(parsed.is_err());
+    }
+
+    #[test]
+    fn parse_reload() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"reload\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::Reload);
+    }
+
+    #[test]
+    fn parse_tune() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"tune worker nevent 512\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::Tune(EventLoop::Worker, Tunable::Nevent(512))
+        );
+
+        let parsed = parser.parse(b"tune admin timeout 10\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::Tune(
+                EventLoop::Admin,
+                Tunable::Timeout(Duration::from_millis(10))
+            )
+        );
+
+        // a zero timeout polls without blocking, but zero events is invalid
+        let parsed = parser.parse(b"tune server timeout 0\r\n");
+        assert!(parsed.is_ok());
+        assert!(parser.parse(b"tune server nevent 0\r\n").is_err());
+
+        // the number of events and the timeout are bounded
+        assert!(parser.parse(b"tune worker nevent 65536\r\n").is_ok());
+        assert!(parser.parse(b"tune worker nevent 65537\r\n").is_err());
+        assert!(parser
+            .parse(b"tune worker nevent 18446744073709551615\r\n")
+            .is_err());
+        assert!(parser.parse(b"tune admin timeout 10000\r\n").is_ok());
+        assert!(parser.parse(b"tune admin timeout 10001\r\n").is_err());
+        assert!(parser.parse(b"tune admin timeout -1\r\n").is_err());
+
+        assert!(parser.parse(b"tune storage nevent 512\r\n").is_err());
+        assert!(parser.parse(b"tune worker threads 2\r\n").is_err());
+        assert!(parser.parse(b"tune worker nevent many\r\n").is_err());
+        assert!(parser.parse(b"tune worker nevent\r\n").is_err());
+    }
+
+    #[test]
+    fn custom_command() {
+        let parser = AdminRequestParser::new();
+
+        // unregistered verbs are rejected, as are registrations of verbs
+        // which are not a single word or which are already registered
+        assert!(parser.parse(b"echo a b\r\n").is_err());
+        assert!(register_command("two words", |_| AdminResponse::ok()).is_err());
+
+        register_command("echo", |args| {
+            AdminResponse::lines(
+                args.iter()
+                    .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                    .collect(),
+            )
+        })
+        .expect("failed to register");
+        assert!(register_command("echo", |_| AdminResponse::ok()).is_err());
+
+        let parsed = parser.parse(b"echo a b\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(
+            request,
+            AdminRequest::Custom {
+                verb: "echo",
+                args: vec![b"a".to_vec(), b"b".to_vec()],
+            }
+        );
+        assert_eq!(request.command(), "echo");
+
+        let parsed = parser.parse(b"echo\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::Custom {
+                verb: "echo",
+                args: Vec::new(),
+            }
+        );
+
+        let mut buf = Vec::new();
+        let size = handle_command("echo", &[b"a".to_vec(), b"b".to_vec()]).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], &b"a\r\nb\r\nEND\r\n"[..]);
+
+        // a built-in verb only reaches the custom command for the requests
+        // which the built-in command does not accept
+        register_command("buildinfo", |_| AdminResponse::ok()).expect("failed to register");
+        assert_eq!(
+            parser.parse(b"buildinfo\r\n").unwrap().into_inner(),
+            AdminRequest::BuildInfo
+        );
+        assert_eq!(
+            parser.parse(b"buildinfo full\r\n").unwrap().into_inner(),
+            AdminRequest::Custom {
+                verb: "buildinfo",
+                args: vec![b"full".to_vec()],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_migrate() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"migrate tenant: 10.0.0.2:12321 1000 delete\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(
+            request,
+            AdminRequest::Migrate {
+                prefix: b"tenant:".to_vec(),
+                destination: "10.0.0.2:12321".to_string(),
+                rate: 1000,
+                delete: true,
+            }
+        );
+        assert_eq!(request.command(), "migrate");
+
+        let parsed = parser.parse(b"migrate tenant: cache-2:12321 500\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::Migrate {
+                prefix: b"tenant:".to_vec(),
+                destination: "cache-2:12321".to_string(),
+                rate: 500,
+                delete: false,
+            }
+        );
+
+        let parsed = parser.parse(b"migrate status\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::MigrateStatus);
+
+        let parsed = parser.parse(b"migrate cancel\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::MigrateCancel);
+
+        // the rate must be a positive number of items per second
+        assert!(parser
+            .parse(b"migrate tenant: cache-2:12321 0\r\n")
+            .is_err());
+        assert!(parser
+            .parse(b"migrate tenant: cache-2:12321 fast\r\n")
+            .is_err());
+        assert!(parser
+            .parse(b"migrate tenant: cache-2:12321 100 move\r\n")
+            .is_err());
+    }
+
+    #[test]
+    fn parse_shutdown() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"shutdown\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::Shutdown);
+        assert_eq!(request.command(), "shutdown");
+
+        let parsed = parser.parse(b"shutdown now\r\n");
+        assert!(parsed.is_err());
+    }
+
+    #[test]
+    fn parse_confirmation() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"flush_all --dry-run\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(
+            request,
+            AdminRequest::DryRun(Box::new(AdminRequest::FlushAll))
+        );
+        assert_eq!(request.command(), "flush_all");
+
+        let parsed = parser.parse(b"flush namespace tenant --confirm 5f3a\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::Confirmed {
+                request: Box::new(AdminRequest::FlushNamespace(b"tenant".to_vec())),
+                token: "5f3a".to_string(),
+            }
+        );
+
+        let parsed = parser.parse(b"shutdown --confirm 5f3a\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::Confirmed {
+                request: Box::new(AdminRequest::Shutdown),
+                token: "5f3a".to_string(),
+            }
+        );
+
+        // only destructive commands may be confirmed, and a token is required
+        let buffers: Vec<&[u8]> = vec![
+            b"stats --dry-run\r\n",
+            b"shutdown --confirm\r\n",
+            b"flush_all --dry-run --confirm 5f3a\r\n",
+        ];
+        for buffer in buffers.iter() {
+            assert!(parser.parse(buffer).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_reload_tls() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"reload tls\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::ReloadTls);
+
+        let parsed = parser.parse(b"reload certs\r\n");
+        assert!(parsed.is_err());
+    }
+
+    #[test]
+    fn parse_sessions() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"sessions list\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::SessionsList);
+
+        let parsed = parser.parse(b"sessions kill 42\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::SessionsKill(42));
+        assert_eq!(request.command(), "sessions");
+
+        let buffers: Vec<&[u8]> = vec![
+            b"sessions\r\n",
+            b"sessions kill\r\n",
+            b"sessions kill abc\r\n",
+        ];
+        for buffer in buffers.iter() {
+            assert!(parser.parse(buffer).is_err());
+        }
+    }
+
+    #[test]
+    fn compose_sessions() {
+        let sessions = vec![
+            SessionInfo {
+                id: 3,
+                peer_addr: Some("127.0.0.1:41000".parse().unwrap()),
+                age: std::time::Duration::from_secs(12),
+                bytes_read: 100,
+                bytes_written: 2048,
+                read_pending: 0,
+                write_pending: 16,
+            },
+            SessionInfo {
+                id: 7,
+                peer_addr: None,
+                age: std::time::Duration::from_millis(500),
+                bytes_read: 0,
+                bytes_written: 0,
+                read_pending: 0,
+                write_pending: 0,
+            },
+        ];
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::sessions(sessions).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            "SESSION 3 peer=127.0.0.1:41000 age=12 bytes_read=100 bytes_written=2048 read_pending=0 write_pending=16\r\n\
+             SESSION 7 peer=unknown age=0 bytes_read=0 bytes_written=0 read_pending=0 write_pending=0\r\n\
+             END\r\n"
+        );
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::not_found().compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], b"NOT_FOUND\r\n");
+    }
+
+    #[test]
+    fn parse_stats() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"stats\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::Stats(StatsFormat::Classic)
+        );
+    }
+
+    #[test]
+    fn parse_stats_detail() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"stats detail on\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::StatsDetail(true));
+        assert_eq!(request.command(), "stats");
+
+        let parsed = parser.parse(b"stats detail off\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::StatsDetail(false)
+        );
+
+        let parsed = parser.parse(b"stats detail dump\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::StatsDetailDump);
+
+        let buffers: Vec<&[u8]> = vec![b"stats detail\r\n", b"stats detail maybe\r\n"];
+        for buffer in buffers.iter() {
+            assert!(parser.parse(buffer).is_err());
+        }
+    }
+
+    #[test]
+    fn stats_rates() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"stats rates\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::StatsRates);
+        assert_eq!(request.command(), "stats");
+
+        let response = AdminResponse::stats_rates(vec![
+            ("get_1s".to_string(), 1.5),
+            ("get_10s".to_string(), 2.0),
+        ]);
+        let mut buf = Vec::new();
+        let size = response.compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            &buf[..],
+            &b"STAT get_1s 1.50\r\nSTAT get_10s 2.00\r\nEND\r\n"[..]
+        );
+    }
+
+    #[test]
+    fn parse_stats_reset() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"stats reset\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::StatsReset);
+        assert_eq!(request.command(), "stats_reset");
+
+        assert!(parser.parse(b"stats reset now\r\n").is_err());
+    }
+
+    #[test]
+    fn stats_reset() {
+        TEST_RESET_COUNTER.add(5);
+        RU_TEST_RESET.add(5);
+        TEST_RESET_GAUGE.set(5);
+        let earlier = StatsSnapshot::capture();
+
+        reset_counters();
+        TEST_RESET_COUNTER.increment();
+        let later = StatsSnapshot::capture();
+
+        // counters are reported from the reset, while gauges and resource
+        // usage counters are reported as they are
+        let reported: BTreeMap<String, i64> = later
+            .reported()
+            .map(|(name, value)| (name.clone(), value))
+            .collect();
+        assert_eq!(reported["test_reset_counter"], 1);
+        assert_eq!(reported["ru_test_reset"], 5);
+        assert_eq!(reported["test_reset_gauge"], 5);
+        assert_eq!(
+            reported_counter("test_reset_counter", TEST_RESET_COUNTER.value()),
+            1
+        );
+
+        // the counters themselves keep counting, so a diff across the reset
+        // is the change in each counter
+        assert_eq!(TEST_RESET_COUNTER.value(), 6);
+        assert!(later
+            .diff(&earlier)
+            .contains(&("test_reset_counter".to_string(), 1)));
+    }
+
+    #[test]
+    fn compose_stats_detail_dump() {
+        let mut buf = Vec::new();
+        let size = AdminResponse::stats_detail_dump().compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert!(buf.ends_with(b"END\r\n"));
+        let text = std::str::from_utf8(&buf).unwrap();
+        assert!(text.lines().all(|l| l == "END" || l.starts_with("PREFIX ")));
+    }
+
+    #[test]
+    fn parse_stats_listeners() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"stats listeners\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::StatsListeners);
+    }
+
+    #[test]
+    fn compose_stats_listeners() {
+        let stats = Arc::new(ListenerStats::default());
+        stats.record_request();
+        stats.record_response(Duration::from_micros(3));
+        let response = AdminResponse::StatsListeners(vec![("data".to_string(), stats)]);
+
+        let mut buf = Vec::new();
+        let size = response.compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            "STAT data:request 1\r\n\
+             STAT data:response 1\r\n\
+             STAT data:latency_p50_us 4\r\n\
+             STAT data:latency_p90_us 4\r\n\
+             STAT data:latency_p99_us 4\r\n\
+             STAT data:latency_p999_us 4\r\n\
+             END\r\n"
+        );
+    }
+
+    #[test]
+    fn parse_stats_segments() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"stats segments\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::StatsSegments);
+        assert_eq!(request.command(), "stats");
+    }
+
+    #[test]
+    fn compose_stats_segments() {
+        let buckets = vec![
+            TtlBucketInfo {
+                index: 1,
+                ttl: std::time::Duration::from_secs(8),
+                segments: 2,
+                live_items: 10,
+                live_bytes: 640,
+                dead_bytes: 128,
+                oldest: std::time::Duration::from_secs(5),
+                merged: 1,
+            },
+            TtlBucketInfo {
+                index: 1024,
+                ttl: std::time::Duration::from_secs(131072),
+                ..Default::default()
+            },
+        ];
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::stats_segments(buckets).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            "TTL_BUCKET 1 ttl=8 segments=2 live_items=10 live_bytes=640 dead_bytes=128 oldest=5 merged=1\r\n\
+             TTL_BUCKET 1024 ttl=131072 segments=0 live_items=0 live_bytes=0 dead_bytes=0 oldest=0 merged=0\r\n\
+             END\r\n"
+        );
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::stats_segments(Vec::new()).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], b"END\r\n");
+    }
+
+    #[test]
+    fn parse_stats_hashtable() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"stats hashtable\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::StatsHashTable);
+        assert_eq!(request.command(), "stats");
+    }
+
+    #[test]
+    fn compose_stats_hashtable() {
+        let hashtable = HashTableInfo {
+            power: 8,
+            buckets: 32,
+            overflow_buckets: 0,
+            overflow_used: 0,
+            items: 56,
+            slots: 224,
+            chain_lengths: vec![32, 0],
+            occupancy: vec![0, 8, 24],
+            tag_collisions: 1,
+            lookups: 1000,
+        };
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::stats_hashtable(hashtable).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            std::str::from_utf8(&buf).unwrap(),
+            "STAT power 8\r\n\
+             STAT buckets 32\r\n\
+             STAT overflow_buckets 0\r\n\
+             STAT overflow_used 0\r\n\
+             STAT items 56\r\n\
+             STAT slots 224\r\n\
+             STAT load_factor 0.2500\r\n\
+             STAT lookups 1000\r\n\
+             STAT tag_collisions 1\r\n\
+             STAT collision_rate 0.001000\r\n\
+             CHAIN_LEN 0 buckets=32\r\n\
+             OCCUPANCY 1 buckets=8\r\n\
+             OCCUPANCY 2 buckets=24\r\n\
+             END\r\n"
+        );
+    }
+
+    #[test]
+    fn parse_stats_namespaces() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"stats namespaces\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::StatsNamespaces);
+
+        if let Err(e) = parser.parse(b"stats everything\r\n") {
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        } else {
+            panic!("parser should not have returned a request");
+        }
+    }
+
+    #[test]
+    fn compose_stats() {
+        let mut buf = Vec::new();
+        let size = AdminResponse::stats(StatsFormat::Classic).compose(&mut buf);
+        assert_eq!(size, buf.len());
+
+        let response = std::str::from_utf8(&buf).unwrap();
+        let mut lines: Vec<&str> = response.split_terminator("\r\n").collect();
+        assert_eq!(lines.pop(), Some("END"));
+
+        // each metric is a name and value, sorted by name
+        for line in &lines {
+            let tokens: Vec<&str> = line.split(' ').collect();
+            assert_eq!(tokens.len(), 3, "malformed line: {}", line);
+            assert_eq!(tokens[0], "STAT");
+        }
+        assert!(lines.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn parse_stats_diff() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"stats diff 10\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::StatsDiff(Duration::from_secs(10))
+        );
+
+        let parsed = parser.parse(b"stats diff 3600\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::StatsDiff(Duration::from_secs(3600))
+        );
+
+        let buffers: Vec<&[u8]> = vec![
+            b"stats diff\r\n",
+            b"stats diff 0\r\n",
+            b"stats diff -1\r\n",
+            b"stats diff soon\r\n",
+            b"stats diff 3601\r\n",
+            b"stats diff 18446744073709551615\r\n",
+        ];
+        for buffer in buffers.iter() {
+            assert!(parser.parse(buffer).is_err());
+        }
+    }
+
+    #[test]
+    fn compose_stats_diff() {
+        let mut earlier = StatsSnapshot::default();
+        earlier.values.insert("get".to_string(), 10);
+        earlier.values.insert("set".to_string(), 5);
+        earlier.values.insert("curr_items".to_string(), 100);
+
+        let mut later = earlier.clone();
+        later.values.insert("get".to_string(), 25);
+        later.values.insert("curr_items".to_string(), 90);
+        later.values.insert("evict".to_string(), 3);
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::stats_diff(&earlier, &later).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            &buf[..],
+            &b"STAT curr_items -10\r\nSTAT evict 3\r\nSTAT get 15\r\nEND\r\n"[..]
+        );
+    }
+
+    #[test]
+    fn compose_partial_stats() {
+        let response = AdminResponse::StatsDiff(vec![
+            ("curr_items".to_string(), -10),
+            ("evict".to_string(), 3),
+            ("get".to_string(), 15),
+        ]);
+
+        let mut complete = Vec::new();
+        let size = response.compose(&mut complete);
+        assert_eq!(size, complete.len());
+
+        // with the smallest limit, each call composes a single line and the
+        // last call also composes the end marker
+        let mut buf = Vec::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            let (size, done) = response.compose_partial(&mut buf, &mut cursor, 1);
+            assert!(size > 0);
+            calls += 1;
+            if done {
+                break;
+            }
+        }
+        assert_eq!(calls, 3);
+        assert_eq!(buf, complete);
+    }
+
+    #[test]
+    fn parse_stats_format() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"stats json\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::Stats(StatsFormat::Json));
+        assert_eq!(request.command(), "stats");
+
+        let parsed = parser.parse(b"stats kv\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(
+            parsed.unwrap().into_inner(),
+            AdminRequest::Stats(StatsFormat::Kv)
+        );
+
+        assert!(parser.parse(b"stats xml\r\n").is_err());
+    }
+
+    #[test]
+    fn compose_stats_formats() {
+        let snapshot = StatsSnapshot {
+            values: [("evict".to_string(), 3), ("get".to_string(), 15)]
+                .iter()
+                .cloned()
+                .collect(),
+        };
+
+        let mut buf = Vec::new();
+        let response = AdminResponse::Stats(snapshot.clone(), StatsFormat::Classic);
+        let size = response.compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], &b"STAT evict 3\r\nSTAT get 15\r\nEND\r\n"[..]);
+
+        let mut buf = Vec::new();
+        let response = AdminResponse::Stats(snapshot.clone(), StatsFormat::Kv);
+        let size = response.compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], &b"evict=3\r\nget=15\r\nEND\r\n"[..]);
+
+        let mut buf = Vec::new();
+        let response = AdminResponse::Stats(snapshot.clone(), StatsFormat::Json);
+        let size = response.compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], &b"{\"evict\":3,\"get\":15}\r\n"[..]);
+        assert_eq!(&buf[..(buf.len() - 2)], snapshot.to_json().as_bytes());
+
+        // composed one entry at a time, the object is still opened once and
+        // closed once
+        let mut partial = Vec::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            let (size, done) = response.compose_partial(&mut partial, &mut cursor, 1);
+            assert!(size > 0);
+            calls += 1;
+            if done {
+                break;
+            }
+        }
+        assert_eq!(calls, 2);
+        assert_eq!(partial, buf);
+    }
+
+    #[test]
+    fn compose_stats_json() {
+        let mut buf = Vec::new();
+        let size = AdminResponse::stats(StatsFormat::Json).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert!(buf.ends_with(b"\r\n"));
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&buf[..(buf.len() - 2)]).expect("invalid json");
+        let object = json.as_object().expect("not an object");
+        assert!(object.values().all(|v| v.is_number()));
+    }
+
+    #[test]
+    fn parse_probes() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"health\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::Health);
+        assert_eq!(request.command(), "health");
+
+        let parsed = parser.parse(b"ready\r\n");
+        assert!(parsed.is_ok());
+        let request = parsed.unwrap().into_inner();
+        assert_eq!(request, AdminRequest::Ready);
+        assert_eq!(request.command(), "ready");
+    }
+
+    #[test]
+    fn compose_probes() {
+        let mut buf = Vec::new();
+        let size = AdminResponse::probe(Probe::Ok).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], b"OK\r\n");
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::probe(Probe::NotListening).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], b"SERVER_ERROR not_listening\r\n");
+
+        let mut buf = Vec::new();
+        let size = AdminResponse::probe(Probe::WarmingUp).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(&buf[..], b"SERVER_ERROR warming_up\r\n");
+    }
+
+    #[test]
+    fn parse_version() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"version\r\n");
+        assert!(parsed.is_ok());
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::Version);
+    }
+
+    #[test]
+    fn parse_buildinfo() {
+        let parser = AdminRequestParser::new();
+
+        let parsed = parser.parse(b"buildinfo\r\n");
+        assert_eq!(parsed.unwrap().into_inner(), AdminRequest::BuildInfo);
+
+        let mut buf = Vec::new();
+        AdminResponse::build_info("1.0.0").compose(&mut buf);
+        let response = std::str::from_utf8(&buf).unwrap();
+        assert!(response.starts_with("version 1.0.0\r\ncommit "));
+        assert!(response.contains(&format!("\r\ntarget {}\r\n", common::buildinfo::TARGET)));
+        assert!(response.ends_with("END\r\n"));
     }
 
     #[test]
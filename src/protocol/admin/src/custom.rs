@@ -0,0 +1,73 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Custom admin commands. The built-in commands are fixed, so a server which
+//! needs its own admin verbs registers each of them here with a handler before
+//! it is launched. A request for a registered verb is parsed as
+//! [`AdminRequest::Custom`], which the admin thread dispatches to the handler.
+//! Handlers run on the admin thread, so they must not block.
+//!
+//! Custom commands are only considered once a request does not parse as any
+//! of the built-in commands, so they can never shadow one. A verb which is
+//! also a built-in command only receives the requests which the built-in
+//! command does not accept, such as `stats <name>`.
+//!
+//! Like the metrics, the registry is shared by everything in the process.
+
+use crate::*;
+
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::{Arc, RwLock};
+
+/// Handles a request for a custom command, given the arguments which follow
+/// the verb.
+pub type AdminHandler = Arc<dyn Fn(&[Vec<u8>]) -> AdminResponse + Send + Sync>;
+
+static COMMANDS: RwLock<BTreeMap<&'static str, AdminHandler>> = RwLock::new(BTreeMap::new());
+
+/// Registers a custom command. The verb must be a single word which is not
+/// the verb of another custom command.
+pub fn register_command<F>(verb: &'static str, handler: F) -> Result<()>
+where
+    F: Fn(&[Vec<u8>]) -> AdminResponse + Send + Sync + 'static,
+{
+    if verb.is_empty() || !verb.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "admin command verb must be a single word",
+        ));
+    }
+    let mut commands = COMMANDS.write().unwrap();
+    if commands.contains_key(verb) {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            "admin command verb is already registered",
+        ));
+    }
+    commands.insert(verb, Arc::new(handler));
+
+    Ok(())
+}
+
+/// Calls the handler of a custom command. Commands are never unregistered, so
+/// the handler exists for any request which was parsed.
+pub fn handle_command(verb: &str, args: &[Vec<u8>]) -> AdminResponse {
+    // the handler is called without holding the lock
+    let handler = COMMANDS.read().unwrap().get(verb).cloned();
+    match handler {
+        Some(handler) => handler(args),
+        None => AdminResponse::client_error("unknown command"),
+    }
+}
+
+// Returns the verb as it was registered, if it is a custom command.
+pub(crate) fn registered(verb: &[u8]) -> Option<&'static str> {
+    let verb = std::str::from_utf8(verb).ok()?;
+    COMMANDS
+        .read()
+        .unwrap()
+        .get_key_value(verb)
+        .map(|(verb, _)| *verb)
+}
@@ -0,0 +1,882 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Implements the HTTP admin protocol. This is served on a separate listener
+//! from the ASCII admin protocol so that tools which can only speak HTTP, such
+//! as a Prometheus scraper, can be pointed at it directly.
+//!
+//! Routes:
+//! * `GET /metrics` returns all metrics in the Prometheus text exposition
+//!   format.
+//! * `GET /health` is a liveness probe, and `GET /ready` is a readiness probe.
+//!   Both return `200 OK` if the probe passes, or `503 Service Unavailable`
+//!   with the state which failed in the body.
+//! * `GET /stats` returns the same metrics as `stats json` on the ASCII port.
+//! * `GET /version` returns the version of the service.
+//! * `GET /buildinfo` returns the version and the information about the build
+//!   as a JSON object, like `buildinfo` on the ASCII port.
+//! * `POST /flush` removes all items from storage, like `flush_all`. With
+//!   `?dry_run` it describes what would be removed and returns a token as a
+//!   JSON object instead, and `?confirm=<token>` confirms it.
+//! * `GET /loglevel` returns the current log level, and
+//!   `PUT /loglevel?level=<level>` changes it.
+//!
+//! When the admin port requires authentication for a command, the HTTP route
+//! for it requires the same token in an `Authorization: Bearer <token>`
+//! header.
+
+use crate::*;
+use logger::Level;
+use rustcommon_metrics::*;
+
+use std::io::{Error, ErrorKind, Result};
+
+const MAX_HEADERS: usize = 32;
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum HttpAdminRequest {
+    /// A request for the metrics in the Prometheus exposition format.
+    Metrics { close: bool },
+    /// A liveness probe.
+    Health { close: bool },
+    /// A readiness probe.
+    Ready { close: bool },
+    /// A request for all metrics as a JSON object.
+    Stats { close: bool },
+    /// A request for the version of the service.
+    Version { close: bool },
+    /// A request for the version and the information about the build.
+    BuildInfo { close: bool },
+    /// A request to remove all items from storage.
+    FlushAll {
+        close: bool,
+        token: Option<Box<[u8]>>,
+        /// Describe the items which would be removed instead
+        dry_run: bool,
+        /// The token returned by a dry run
+        confirm: Option<String>,
+    },
+    /// A request for the current log level.
+    LogLevel { close: bool },
+    /// A request to change the log level.
+    SetLogLevel {
+        level: Level,
+        close: bool,
+        token: Option<Box<[u8]>>,
+    },
+    /// A well-formed request which does not match any route.
+    Invalid { status: HttpStatus, close: bool },
+}
+
+impl HttpAdminRequest {
+    /// Indicates that the client asked for the connection to be closed.
+    pub fn close(&self) -> bool {
+        match self {
+            Self::Metrics { close } => *close,
+            Self::Health { close } => *close,
+            Self::Ready { close } => *close,
+            Self::Stats { close } => *close,
+            Self::Version { close } => *close,
+            Self::BuildInfo { close } => *close,
+            Self::FlushAll { close, .. } => *close,
+            Self::LogLevel { close } => *close,
+            Self::SetLogLevel { close, .. } => *close,
+            Self::Invalid { close, .. } => *close,
+        }
+    }
+}
+
+#[derive(Default, Copy, Clone)]
+pub struct HttpAdminRequestParser {}
+
+impl HttpAdminRequestParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Parse<HttpAdminRequest> for HttpAdminRequestParser {
+    fn parse(&self, buffer: &[u8]) -> Result<ParseOk<HttpAdminRequest>> {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut headers);
+
+        let consumed = match request.parse(buffer) {
+            Ok(httparse::Status::Complete(len)) => len,
+            Ok(httparse::Status::Partial) => {
+                return Err(Error::from(ErrorKind::WouldBlock));
+            }
+            Err(_) => {
+                return Err(Error::from(ErrorKind::InvalidInput));
+            }
+        };
+
+        // HTTP/1.0 defaults to closing the connection
+        let mut close = request.version == Some(0);
+        let mut token = None;
+        for header in request.headers.iter() {
+            if header.name.eq_ignore_ascii_case("authorization") {
+                token = header
+                    .value
+                    .strip_prefix(b"Bearer ")
+                    .map(|t| t.to_vec().into_boxed_slice());
+            } else if header.name.eq_ignore_ascii_case("connection") {
+                if header.value.eq_ignore_ascii_case(b"close") {
+                    close = true;
+                } else if header.value.eq_ignore_ascii_case(b"keep-alive") {
+                    close = false;
+                }
+            } else if header.name.eq_ignore_ascii_case("content-length") && header.value != b"0" {
+                // none of the routes accept a body
+                return Err(Error::from(ErrorKind::InvalidInput));
+            }
+        }
+
+        let (path, query) = match request.path.unwrap_or("").split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (request.path.unwrap_or(""), ""),
+        };
+
+        let request = match (request.method, path) {
+            (Some("GET"), "/metrics") => HttpAdminRequest::Metrics { close },
+            (Some("GET"), "/health") => HttpAdminRequest::Health { close },
+            (Some("GET"), "/ready") => HttpAdminRequest::Ready { close },
+            (Some("GET"), "/stats") => HttpAdminRequest::Stats { close },
+            (Some("GET"), "/version") => HttpAdminRequest::Version { close },
+            (Some("GET"), "/buildinfo") => HttpAdminRequest::BuildInfo { close },
+            (Some("POST"), "/flush") => {
+                let dry_run = query
+                    .split('&')
+                    .any(|param| param == "dry_run" || param.starts_with("dry_run="));
+                let confirm = query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("confirm="))
+                    .map(|token| token.to_string());
+                if dry_run && confirm.is_some() {
+                    HttpAdminRequest::Invalid {
+                        status: HttpStatus::BadRequest,
+                        close,
+                    }
+                } else {
+                    HttpAdminRequest::FlushAll {
+                        close,
+                        token,
+                        dry_run,
+                        confirm,
+                    }
+                }
+            }
+            (Some("GET"), "/loglevel") => HttpAdminRequest::LogLevel { close },
+            (Some("PUT"), "/loglevel") => {
+                let level = query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("level="))
+                    .and_then(|level| parse_level(level.as_bytes()));
+                match level {
+                    Some(level) => HttpAdminRequest::SetLogLevel {
+                        level,
+                        close,
+                        token,
+                    },
+                    None => HttpAdminRequest::Invalid {
+                        status: HttpStatus::BadRequest,
+                        close,
+                    },
+                }
+            }
+            (_, "/metrics")
+            | (_, "/health")
+            | (_, "/ready")
+            | (_, "/stats")
+            | (_, "/version")
+            | (_, "/buildinfo")
+            | (_, "/flush")
+            | (_, "/loglevel") => HttpAdminRequest::Invalid {
+                status: HttpStatus::MethodNotAllowed,
+                close,
+            },
+            _ => HttpAdminRequest::Invalid {
+                status: HttpStatus::NotFound,
+                close,
+            },
+        };
+
+        Ok(ParseOk::new(request, consumed))
+    }
+}
+
+/// The subset of HTTP status codes used by the admin routes.
+#[derive(PartialEq, Eq, Debug, Copy, Clone)]
+pub enum HttpStatus {
+    Ok,
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    TooManyRequests,
+    InternalServerError,
+    ServiceUnavailable,
+}
+
+impl HttpStatus {
+    fn status_line(&self) -> &'static str {
+        match self {
+            Self::Ok => "HTTP/1.1 200 OK",
+            Self::BadRequest => "HTTP/1.1 400 Bad Request",
+            Self::Unauthorized => "HTTP/1.1 401 Unauthorized",
+            Self::Forbidden => "HTTP/1.1 403 Forbidden",
+            Self::NotFound => "HTTP/1.1 404 Not Found",
+            Self::MethodNotAllowed => "HTTP/1.1 405 Method Not Allowed",
+            Self::TooManyRequests => "HTTP/1.1 429 Too Many Requests",
+            Self::InternalServerError => "HTTP/1.1 500 Internal Server Error",
+            Self::ServiceUnavailable => "HTTP/1.1 503 Service Unavailable",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct HttpAdminResponse {
+    status: HttpStatus,
+    content_type: Option<&'static str>,
+    body: Vec<u8>,
+    close: bool,
+}
+
+impl HttpAdminResponse {
+    /// A response with all metrics in the Prometheus text exposition format.
+    pub fn metrics() -> Self {
+        Self {
+            status: HttpStatus::Ok,
+            content_type: Some("text/plain; version=0.0.4"),
+            body: prometheus().into_bytes(),
+            close: false,
+        }
+    }
+
+    /// A response to a health or readiness probe, with the state of the probe
+    /// in the body.
+    pub fn probe(probe: Probe) -> Self {
+        let status = if probe == Probe::Ok {
+            HttpStatus::Ok
+        } else {
+            HttpStatus::ServiceUnavailable
+        };
+
+        Self {
+            status,
+            content_type: Some("text/plain"),
+            body: format!("{}\n", probe.as_str()).into_bytes(),
+            close: false,
+        }
+    }
+
+    /// A response with all metrics as a single JSON object.
+    pub fn stats() -> Self {
+        let mut body = StatsSnapshot::capture().to_json().into_bytes();
+        body.push(b'\n');
+
+        Self {
+            status: HttpStatus::Ok,
+            content_type: Some("application/json"),
+            body,
+            close: false,
+        }
+    }
+
+    /// A response with the version of the service.
+    pub fn version(version: &str) -> Self {
+        Self::text(HttpStatus::Ok, version)
+    }
+
+    /// A response with the version and each field of the build information
+    /// as a single JSON object.
+    pub fn build_info(version: &str) -> Self {
+        let mut data = serde_json::Map::new();
+        data.insert("version".to_string(), version.into());
+        for (name, value) in common::buildinfo::fields() {
+            data.insert(name.to_string(), value.into());
+        }
+        let mut body = serde_json::Value::Object(data).to_string().into_bytes();
+        body.push(b'\n');
+
+        Self {
+            status: HttpStatus::Ok,
+            content_type: Some("application/json"),
+            body,
+            close: false,
+        }
+    }
+
+    /// A response to a dry run with the token and each count of what the
+    /// command would affect as a single JSON object.
+    pub fn dry_run(dry_run: &DryRun) -> Self {
+        let mut data = serde_json::Map::new();
+        data.insert("token".to_string(), dry_run.token.clone().into());
+        data.insert("expires".to_string(), dry_run.expires.as_secs().into());
+        for (name, count) in &dry_run.impact {
+            data.insert(name.to_string(), (*count).into());
+        }
+        let mut body = serde_json::Value::Object(data).to_string().into_bytes();
+        body.push(b'\n');
+
+        Self {
+            status: HttpStatus::Ok,
+            content_type: Some("application/json"),
+            body,
+            close: false,
+        }
+    }
+
+    /// A response with the current log level, which is `off` if logging is
+    /// disabled.
+    pub fn log_level(level: Option<Level>) -> Self {
+        let level = level
+            .map(|l| l.as_str().to_lowercase())
+            .unwrap_or_else(|| "off".to_string());
+        Self::text(HttpStatus::Ok, &level)
+    }
+
+    /// A response with a single line of plain text as the body.
+    pub fn text(status: HttpStatus, message: &str) -> Self {
+        Self {
+            status,
+            content_type: Some("text/plain"),
+            body: format!("{}\n", message).into_bytes(),
+            close: false,
+        }
+    }
+
+    /// A response without a body.
+    pub fn status(status: HttpStatus) -> Self {
+        Self {
+            status,
+            content_type: None,
+            body: Vec::new(),
+            close: false,
+        }
+    }
+
+    /// Marks the connection to be closed after this response is sent.
+    pub fn close(mut self, close: bool) -> Self {
+        self.close = close;
+        self
+    }
+}
+
+impl HttpAdminResponse {
+    fn header(&self) -> String {
+        let mut header = format!(
+            "{}\r\nContent-Length: {}\r\n",
+            self.status.status_line(),
+            self.body.len()
+        );
+        if let Some(content_type) = self.content_type {
+            header.push_str(&format!("Content-Type: {}\r\n", content_type));
+        }
+        if self.close {
+            header.push_str("Connection: close\r\n");
+        }
+        header.push_str("\r\n");
+        header
+    }
+}
+
+impl Compose for HttpAdminResponse {
+    fn compose(&self, buf: &mut dyn BufMut) -> usize {
+        let header = self.header();
+        buf.put_slice(header.as_bytes());
+        buf.put_slice(&self.body);
+        header.len() + self.body.len()
+    }
+
+    // the cursor is zero until the header has been composed, and is then one
+    // more than the number of bytes of the body composed so far
+    fn compose_partial(
+        &self,
+        buf: &mut dyn BufMut,
+        cursor: &mut usize,
+        limit: usize,
+    ) -> (usize, bool) {
+        let mut size = 0;
+        if *cursor == 0 {
+            let header = self.header();
+            buf.put_slice(header.as_bytes());
+            size += header.len();
+            *cursor = 1;
+
+            if size >= limit {
+                return (size, self.body.is_empty());
+            }
+        }
+
+        let offset = *cursor - 1;
+        let end = offset
+            .saturating_add(limit.saturating_sub(size).max(1))
+            .min(self.body.len());
+        buf.put_slice(&self.body[offset..end]);
+        size += end - offset;
+        *cursor = end + 1;
+
+        (size, end == self.body.len())
+    }
+
+    fn should_hangup(&self) -> bool {
+        self.close
+    }
+}
+
+/// Renders all metrics in the Prometheus text exposition format. Counters and
+/// gauges map directly onto the Prometheus types, and heatmaps are exposed as
+/// summaries with a quantile for each of the standard percentiles.
+fn prometheus() -> String {
+    let mut metrics = Vec::new();
+    for metric in &rustcommon_metrics::metrics() {
+        let any = match metric.as_any() {
+            Some(any) => any,
+            None => {
+                continue;
+            }
+        };
+
+        let name = sanitize(metric.name());
+        let mut lines = String::new();
+
+        if let Some(description) = metric.description() {
+            lines.push_str(&format!("# HELP {} {}\n", name, description));
+        }
+
+        if let Some(counter) = any.downcast_ref::<Counter>() {
+            lines.push_str(&format!("# TYPE {} counter\n", name));
+            lines.push_str(&format!(
+                "{} {}\n",
+                name,
+                reported_counter(metric.name(), counter.value())
+            ));
+        } else if let Some(gauge) = any.downcast_ref::<Gauge>() {
+            lines.push_str(&format!("# TYPE {} gauge\n", name));
+            lines.push_str(&format!("{} {}\n", name, gauge.value()));
+        } else if let Some(heatmap) = any.downcast_ref::<Heatmap>() {
+            lines.push_str(&format!("# TYPE {} summary\n", name));
+            for (_, percentile) in PERCENTILES {
+                let value = heatmap.percentile(*percentile).unwrap_or(0);
+                // rounding keeps the quantiles free of floating point noise
+                let quantile = (percentile * 100.0).round() / 10000.0;
+                lines.push_str(&format!(
+                    "{}{{quantile=\"{}\"}} {}\n",
+                    name, quantile, value
+                ));
+            }
+        } else {
+            continue;
+        }
+
+        metrics.push((name, lines));
+    }
+
+    // the traffic of each listener, with the listener as a label
+    let listeners = common::listener::snapshot();
+    if !listeners.is_empty() {
+        let mut request = String::from(
+            "# HELP listener_request number of requests received by each listener\n\
+            # TYPE listener_request counter\n",
+        );
+        let mut response = String::from(
+            "# HELP listener_response number of responses sent by each listener\n\
+            # TYPE listener_response counter\n",
+        );
+        let mut latency = String::from(
+            "# HELP listener_request_latency_us upper bound on the request latency of each listener\n\
+            # TYPE listener_request_latency_us summary\n",
+        );
+        for (listener, stats) in &listeners {
+            request.push_str(&format!(
+                "listener_request{{listener=\"{}\"}} {}\n",
+                listener,
+                stats.requests()
+            ));
+            response.push_str(&format!(
+                "listener_response{{listener=\"{}\"}} {}\n",
+                listener,
+                stats.responses()
+            ));
+            for (_, percentile) in LISTENER_PERCENTILES {
+                let quantile = (percentile * 100.0).round() / 10000.0;
+                latency.push_str(&format!(
+                    "listener_request_latency_us{{listener=\"{}\",quantile=\"{}\"}} {}\n",
+                    listener,
+                    quantile,
+                    stats.latency(*percentile).as_micros()
+                ));
+            }
+        }
+        metrics.push(("listener_request".to_string(), request));
+        metrics.push(("listener_request_latency_us".to_string(), latency));
+        metrics.push(("listener_response".to_string(), response));
+    }
+
+    // the usage of each thread, with the thread as a label
+    let threads = common::rusage::snapshot();
+    if !threads.is_empty() {
+        for (index, (field, description)) in common::rusage::FIELDS.iter().enumerate() {
+            let name = format!("thread_{}", field);
+            let mut lines = format!(
+                "# HELP {} {} for each thread\n# TYPE {} counter\n",
+                name, description, name
+            );
+            for (thread, usage) in &threads {
+                lines.push_str(&format!(
+                    "{}{{thread=\"{}\"}} {}\n",
+                    name,
+                    thread,
+                    usage.values()[index]
+                ));
+            }
+            metrics.push((name, lines));
+        }
+    }
+
+    metrics.sort();
+    metrics.into_iter().map(|(_, lines)| lines).collect()
+}
+
+/// Metric names in the exposition format may only contain ASCII letters,
+/// digits, underscores, and colons, and may not begin with a digit. Any other
+/// characters are replaced with an underscore.
+fn sanitize(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_metrics() {
+        let parser = HttpAdminRequestParser::new();
+
+        let buffer = b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let parsed = parser.parse(buffer).expect("failed to parse");
+        assert_eq!(parsed.consumed(), buffer.len());
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::Metrics { close: false }
+        );
+
+        let parsed = parser
+            .parse(b"GET /metrics?name=x HTTP/1.0\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::Metrics { close: true }
+        );
+
+        assert_eq!(
+            parser
+                .parse(b"GET /metrics HTTP/1.1\r\n")
+                .map_err(|e| e.kind()),
+            Err(ErrorKind::WouldBlock)
+        );
+    }
+
+    #[test]
+    fn parse_probes() {
+        let parser = HttpAdminRequestParser::new();
+
+        let parsed = parser
+            .parse(b"GET /health HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::Health { close: false }
+        );
+
+        let parsed = parser
+            .parse(b"GET /ready HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(parsed.into_inner(), HttpAdminRequest::Ready { close: true });
+
+        let parsed = parser
+            .parse(b"PUT /ready HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::Invalid {
+                status: HttpStatus::MethodNotAllowed,
+                close: false
+            }
+        );
+    }
+
+    #[test]
+    fn parse_commands() {
+        let parser = HttpAdminRequestParser::new();
+
+        let parsed = parser
+            .parse(b"GET /stats HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::Stats { close: false }
+        );
+
+        let parsed = parser
+            .parse(b"GET /version HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::Version { close: false }
+        );
+
+        let parsed = parser
+            .parse(b"GET /buildinfo HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::BuildInfo { close: false }
+        );
+
+        let parsed = parser
+            .parse(b"POST /flush HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::FlushAll {
+                close: false,
+                token: Some(b"secret".to_vec().into_boxed_slice()),
+                dry_run: false,
+                confirm: None,
+            }
+        );
+
+        let parsed = parser
+            .parse(b"POST /flush?dry_run HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::FlushAll {
+                close: false,
+                token: None,
+                dry_run: true,
+                confirm: None,
+            }
+        );
+
+        let parsed = parser
+            .parse(b"POST /flush?confirm=5f3a HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::FlushAll {
+                close: false,
+                token: None,
+                dry_run: false,
+                confirm: Some("5f3a".to_string()),
+            }
+        );
+
+        let parsed = parser
+            .parse(b"GET /flush HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::Invalid {
+                status: HttpStatus::MethodNotAllowed,
+                close: false
+            }
+        );
+
+        let parsed = parser
+            .parse(b"GET /loglevel HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::LogLevel { close: false }
+        );
+
+        let parsed = parser
+            .parse(b"PUT /loglevel?level=debug HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::SetLogLevel {
+                level: Level::Debug,
+                close: false,
+                token: None,
+            }
+        );
+
+        let parsed = parser
+            .parse(b"PUT /loglevel?level=loud HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::Invalid {
+                status: HttpStatus::BadRequest,
+                close: false
+            }
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let parser = HttpAdminRequestParser::new();
+
+        let parsed = parser
+            .parse(b"POST /metrics HTTP/1.1\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::Invalid {
+                status: HttpStatus::MethodNotAllowed,
+                close: false
+            }
+        );
+
+        let parsed = parser
+            .parse(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            HttpAdminRequest::Invalid {
+                status: HttpStatus::NotFound,
+                close: true
+            }
+        );
+
+        assert_eq!(
+            parser.parse(b"stats\r\n\r\n").map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn compose() {
+        let mut buf = Vec::new();
+        let size = HttpAdminResponse::status(HttpStatus::NotFound)
+            .close(true)
+            .compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            &buf[..],
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+
+        let mut buf = Vec::new();
+        let size = HttpAdminResponse::text(HttpStatus::TooManyRequests, "rate limit exceeded")
+            .compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            &buf[..],
+            &b"HTTP/1.1 429 Too Many Requests\r\nContent-Length: 20\r\nContent-Type: text/plain\r\n\r\nrate limit exceeded\n"[..]
+        );
+    }
+
+    #[test]
+    fn compose_partial() {
+        let response = HttpAdminResponse::text(HttpStatus::Ok, "0123456789");
+
+        let mut complete = Vec::new();
+        let size = response.compose(&mut complete);
+        assert_eq!(size, complete.len());
+
+        // the header is composed whole, and the body is split at the limit
+        let mut buf = Vec::new();
+        let mut cursor = 0;
+        let (size, done) = response.compose_partial(&mut buf, &mut cursor, 4);
+        assert!(!done);
+        assert_eq!(size, complete.len() - 11);
+
+        let mut calls = 0;
+        loop {
+            let (size, done) = response.compose_partial(&mut buf, &mut cursor, 4);
+            assert!(size <= 4);
+            calls += 1;
+            if done {
+                break;
+            }
+        }
+        assert_eq!(calls, 3);
+        assert_eq!(buf, complete);
+    }
+
+    #[test]
+    fn compose_probes() {
+        let mut buf = Vec::new();
+        let size = HttpAdminResponse::probe(Probe::Ok).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            &buf[..],
+            &b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\nContent-Type: text/plain\r\n\r\nok\n"[..]
+        );
+
+        let mut buf = Vec::new();
+        let size = HttpAdminResponse::probe(Probe::NoStorage).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            &buf[..],
+            &b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 11\r\nContent-Type: text/plain\r\n\r\nno_storage\n"[..]
+        );
+    }
+
+    #[test]
+    fn compose_commands() {
+        let mut buf = Vec::new();
+        let size = HttpAdminResponse::version("1.0.0").compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert_eq!(
+            &buf[..],
+            &b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\nContent-Type: text/plain\r\n\r\n1.0.0\n"[..]
+        );
+
+        let mut buf = Vec::new();
+        let size = HttpAdminResponse::log_level(Some(Level::Warn)).compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert!(buf.ends_with(b"\r\n\r\nwarn\n"));
+
+        let mut buf = Vec::new();
+        let size = HttpAdminResponse::stats().compose(&mut buf);
+        assert_eq!(size, buf.len());
+        assert!(buf.starts_with(b"HTTP/1.1 200 OK\r\n"));
+        let body = buf
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|p| &buf[(p + 4)..])
+            .expect("no body");
+        let json: serde_json::Value = serde_json::from_slice(body).expect("invalid json");
+        assert!(json.is_object());
+    }
+
+    #[test]
+    fn listeners() {
+        let stats = common::listener::register("http_test");
+        stats.record_request();
+        stats.record_response(core::time::Duration::from_micros(3));
+
+        let metrics = prometheus();
+        assert!(metrics.contains("listener_request{listener=\"http_test\"} 1\n"));
+        assert!(metrics.contains("listener_response{listener=\"http_test\"} 1\n"));
+        assert!(metrics
+            .contains("listener_request_latency_us{listener=\"http_test\",quantile=\"0.5\"} 4\n"));
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(sanitize("admin_request_parse"), "admin_request_parse");
+        assert_eq!(sanitize("get/key-hit"), "get_key_hit");
+        assert_eq!(sanitize("9lives"), "_9lives");
+    }
+}
@@ -5,8 +5,12 @@
 pub use protocol_common::*;
 
 mod admin;
+mod custom;
+mod http;
 
 pub use admin::*;
+pub use custom::*;
+pub use http::*;
 
 pub static PERCENTILES: &[(&str, f64)] = &[
     ("p25", 25.0),
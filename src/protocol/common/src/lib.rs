@@ -6,13 +6,31 @@
 //! traits so that the a server implementation can easily switch between
 //! protocol implementations.
 
+mod mismatch;
+
 pub use bytes::BufMut;
+pub use mismatch::Mismatch;
 
 pub const CRLF: &str = "\r\n";
 
 pub trait Compose {
     fn compose(&self, dst: &mut dyn BufMut) -> usize;
 
+    /// Compose part of the message, resuming from the `cursor` which starts at
+    /// zero and is otherwise opaque to the caller. Implementations should stop
+    /// once at least `limit` bytes have been written and advance the cursor so
+    /// that the next call continues where this one stopped. Returns the number
+    /// of bytes written and whether the message is now complete. The default
+    /// implementation composes the entire message in one call.
+    fn compose_partial(
+        &self,
+        dst: &mut dyn BufMut,
+        _cursor: &mut usize,
+        _limit: usize,
+    ) -> (usize, bool) {
+        (self.compose(dst), true)
+    }
+
     /// Indicates that the connection should be closed.
     /// Override this function as appropriate for the
     /// protocol.
@@ -25,6 +43,77 @@ pub trait Execute<Request, Response: Compose> {
     fn execute(&mut self, request: &Request) -> Response;
 }
 
+/// Records the server-side latency of a request, in nanoseconds, so that the
+/// latency distribution can be tracked separately for each command. The
+/// default implementation records nothing, which is appropriate for protocols
+/// where the overall request latency already describes every command.
+pub trait RecordLatency {
+    fn record_latency(&self, _latency: u64) {}
+}
+
+/// Exposes the keys which a request reads and stores, so that a server can keep
+/// a filter of the keys which may be stored and answer reads of any other keys
+/// as misses without reaching storage. The default implementation exposes no
+/// keys, which leaves every request to storage. Implementations which expose
+/// the keys a request reads must also expose every key which a request may
+/// store.
+pub trait Lookup<Response> {
+    /// Calls the function with each key which the request may store.
+    fn stores(&self, _key: &mut dyn FnMut(&[u8])) {}
+
+    /// The keys of a request which only reads them.
+    fn reads(&self) -> &[Box<[u8]>] {
+        &[]
+    }
+
+    /// The response to a request which reads keys when none of them are
+    /// stored.
+    fn miss(&self) -> Option<Response> {
+        None
+    }
+
+    /// Calls the function with each key which the response shows was not
+    /// found.
+    fn misses(&self, _response: &Response, _key: &mut dyn FnMut(&[u8])) {}
+
+    /// Returns true if the request does not change storage, so that it may be
+    /// skipped once no client is waiting for the response. The default treats
+    /// every request as one which may change storage.
+    fn read_only(&self) -> bool {
+        false
+    }
+}
+
+/// The counters of a single session. A client may ask for them, so that it can
+/// check the view the server has of its connection against its own.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SessionCounters {
+    /// The requests which were received on the session
+    pub requests: u64,
+    /// The keys which were found by reads
+    pub hits: u64,
+    /// The keys which were not found by reads
+    pub misses: u64,
+    /// The requests which could not be parsed, were rejected, or failed
+    pub errors: u64,
+    /// The bytes read from the client
+    pub bytes_read: u64,
+    /// The bytes written to the client
+    pub bytes_written: u64,
+}
+
+/// Counts the outcome of each request in the counters of its session, and
+/// reports the counters in the response to a request which asks for them. The
+/// default implementation counts and reports nothing, which is appropriate for
+/// protocols without such a request.
+pub trait CountSession<Response> {
+    /// Counts the outcome of the request, given its response.
+    fn count(&self, _response: &Response, _counters: &mut SessionCounters) {}
+
+    /// Adds the counters to the response, if the request asks for them.
+    fn report(&self, _response: &mut Response, _counters: &SessionCounters) {}
+}
+
 #[derive(Debug, PartialEq)]
 pub struct ParseOk<T> {
     message: T,
@@ -47,4 +136,28 @@ impl<T> ParseOk<T> {
 
 pub trait Parse<T> {
     fn parse(&self, buffer: &[u8]) -> Result<ParseOk<T>, std::io::Error>;
+
+    /// The response to send when the input cannot be parsed. Protocols which
+    /// are line-delimited may return a response here, allowing a session to
+    /// skip the invalid line and continue. The default implementation returns
+    /// `None`, which indicates that the protocol cannot recover from invalid
+    /// input and that the session must be closed.
+    fn error_response(&self) -> Option<&'static [u8]> {
+        None
+    }
+
+    /// The response which is sent in place of handling a request when the
+    /// server is overloaded. Clients may retry the request. The default
+    /// implementation returns `None`, which indicates that the protocol has no
+    /// such response and that the session must be closed instead.
+    fn busy_response(&self) -> Option<&'static [u8]> {
+        None
+    }
+
+    /// Checks whether input which could not be parsed appears to be some other
+    /// protocol. This is only used for the first input on a session. The
+    /// default implementation returns `None`, which disables detection.
+    fn mismatch(&self, _buffer: &[u8]) -> Option<Mismatch> {
+        None
+    }
 }
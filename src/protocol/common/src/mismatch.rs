@@ -0,0 +1,148 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Detection of clients which speak a different protocol than the one served
+//! by the port they connected to. This is almost always a misconfiguration,
+//! such as a TLS client pointed at a plaintext port, and a generic parse
+//! failure gives the operator little to go on.
+
+use core::fmt::{Display, Formatter};
+
+// request lines which begin an HTTP request
+const HTTP_PREFIXES: &[&[u8]] = &[
+    b"GET /",
+    b"HEAD /",
+    b"PUT /",
+    b"POST /",
+    b"DELETE /",
+    b"PATCH /",
+    b"OPTIONS ",
+    b"PRI * HTTP/2",
+];
+
+/// A protocol which a client appears to be speaking instead of the protocol
+/// of the port it is connected to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// A TLS handshake was received on a plaintext port.
+    Tls,
+    /// An HTTP request was received.
+    Http,
+    /// A RESP (redis) command was received.
+    Resp,
+}
+
+impl Mismatch {
+    /// Inspect the first bytes received on a connection and return the
+    /// protocol they belong to, if it is recognized.
+    pub fn detect(input: &[u8]) -> Option<Self> {
+        if input.len() >= 2 && input[0] == 0x16 && input[1] == 0x03 {
+            // a tls record header for a handshake message
+            Some(Self::Tls)
+        } else if HTTP_PREFIXES.iter().any(|p| input.starts_with(p)) {
+            Some(Self::Http)
+        } else if input.len() >= 2 && input[0] == b'*' && input[1].is_ascii_digit() {
+            // commands are sent as an array of bulk strings
+            Some(Self::Resp)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if the input begins with a complete HTTP request line, that
+    /// is a method, a request target, and an HTTP version. Protocols with
+    /// verbs that are also HTTP methods, such as a memcache `GET`, check this
+    /// before parsing, as the request line may otherwise parse as a request.
+    pub fn http_request_line(input: &[u8]) -> bool {
+        let line = match input.windows(2).position(|w| w == b"\r\n") {
+            Some(end) => &input[..end],
+            None => {
+                return false;
+            }
+        };
+
+        if !HTTP_PREFIXES.iter().any(|p| line.starts_with(p)) {
+            return false;
+        }
+
+        let mut tokens = line.split(|b| *b == b' ');
+        match (tokens.next(), tokens.next(), tokens.next(), tokens.next()) {
+            (Some(_), Some(target), Some(version), None) => {
+                !target.is_empty() && version.starts_with(b"HTTP/")
+            }
+            _ => false,
+        }
+    }
+
+    /// A response which the client will be able to understand, explaining why
+    /// the connection is being closed. There is no response for TLS, as a TLS
+    /// client expects a handshake and cannot display an error from the server.
+    pub fn response(&self) -> Option<&'static [u8]> {
+        match self {
+            Self::Tls => None,
+            Self::Http => Some(
+                b"HTTP/1.1 400 Bad Request\r\nContent-Length: 36\r\nConnection: close\r\n\r\nprotocol mismatch: port is not HTTP\n",
+            ),
+            Self::Resp => Some(b"-ERR protocol mismatch: port is not RESP\r\n"),
+        }
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Tls => write!(f, "client sent a TLS handshake to a plaintext port"),
+            Self::Http => write!(f, "client sent an HTTP request"),
+            Self::Resp => write!(f, "client sent a RESP command"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert_eq!(
+            Mismatch::detect(&[0x16, 0x03, 0x01, 0x02]),
+            Some(Mismatch::Tls)
+        );
+        assert_eq!(
+            Mismatch::detect(b"GET /keys/coffee HTTP/1.1\r\n"),
+            Some(Mismatch::Http)
+        );
+        assert_eq!(
+            Mismatch::detect(b"*2\r\n$3\r\nGET\r\n$6\r\ncoffee\r\n"),
+            Some(Mismatch::Resp)
+        );
+        assert_eq!(Mismatch::detect(b"get coffee\r\n"), None);
+        assert_eq!(Mismatch::detect(b"GET coffee\r\n"), None);
+        assert_eq!(Mismatch::detect(b""), None);
+    }
+
+    #[test]
+    fn http_request_line() {
+        assert!(Mismatch::http_request_line(
+            b"GET /keys/coffee HTTP/1.1\r\n"
+        ));
+        assert!(Mismatch::http_request_line(
+            b"DELETE /x HTTP/1.0\r\nHost: cache\r\n\r\n"
+        ));
+        assert!(!Mismatch::http_request_line(b"GET /keys/coffee HTTP/1.1"));
+        assert!(!Mismatch::http_request_line(b"GET /coffee /tea\r\n"));
+        assert!(!Mismatch::http_request_line(b"GET coffee HTTP/1.1\r\n"));
+        assert!(!Mismatch::http_request_line(b"get /x HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn http_response() {
+        let response = Mismatch::Http.response().unwrap();
+        let body = b"protocol mismatch: port is not HTTP\n";
+        assert!(response.ends_with(body));
+        assert!(std::str::from_utf8(response)
+            .unwrap()
+            .contains(&format!("Content-Length: {}\r\n", body.len())));
+    }
+}
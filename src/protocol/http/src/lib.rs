@@ -0,0 +1,87 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A minimal HTTP/1.1 protocol for key-value access. Keys are addressed as
+//! resources under `/keys/{key}`:
+//!
+//! * `GET /keys/{key}` returns the value, with the flags in the `X-Flags`
+//!   header and the CAS value as the `ETag`.
+//! * `PUT /keys/{key}` stores the request body. The `X-TTL` and `X-Flags`
+//!   headers may be used to set the TTL in seconds and the flags. Conditional
+//!   requests are supported with `If-Match` (compare-and-swap against the
+//!   `ETag`) and `If-None-Match: *` (store only if the key does not exist).
+//! * `DELETE /keys/{key}` removes the key, optionally conditioned by
+//!   `If-Match`.
+//!
+//! Clients which send `Accept-Encoding: gzip` with a `GET` may receive large
+//! values compressed with `Content-Encoding: gzip`. This only affects the
+//! transfer of the value, which is stored as it was written.
+//!
+//! This protocol is intended for debugging with tools such as `curl` and for
+//! simple clients, and is not a general purpose HTTP server.
+
+#[macro_use]
+extern crate logger;
+
+mod request;
+mod response;
+
+pub use request::*;
+pub use response::*;
+
+pub use protocol_common::*;
+
+/// The version of the protocol implementation
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+use rustcommon_metrics::*;
+
+counter!(HTTP_GET);
+counter!(HTTP_GET_HIT);
+counter!(HTTP_GET_MISS);
+counter!(HTTP_PUT);
+counter!(HTTP_PUT_STORED);
+counter!(HTTP_PUT_NOT_STORED);
+counter!(HTTP_DELETE);
+counter!(HTTP_DELETE_DELETED);
+counter!(HTTP_DELETE_NOT_FOUND);
+counter!(
+    HTTP_GZIP,
+    "number of responses sent with a gzip content encoding"
+);
+counter!(
+    HTTP_GZIP_SAVED_BYTES,
+    "number of response body bytes saved by gzip content encoding"
+);
+counter!(
+    HTTP_PARSE_EX,
+    "number of requests which could not be parsed"
+);
+counter!(
+    HTTP_UNSUPPORTED,
+    "number of requests for an unsupported method or resource"
+);
+
+type Instant = common::time::Instant<common::time::Nanoseconds<u64>>;
+
+// the largest latency which is tracked precisely, in nanoseconds
+const LATENCY_MAX: u64 = 1_000_000_000;
+
+heatmap!(
+    HTTP_GET_LATENCY,
+    LATENCY_MAX,
+    "distribution of GET request latencies in nanoseconds"
+);
+heatmap!(
+    HTTP_PUT_LATENCY,
+    LATENCY_MAX,
+    "distribution of PUT request latencies in nanoseconds"
+);
+heatmap!(
+    HTTP_DELETE_LATENCY,
+    LATENCY_MAX,
+    "distribution of DELETE request latencies in nanoseconds"
+);
+
+common::metrics::test_no_duplicates!();
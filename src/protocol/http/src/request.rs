@@ -0,0 +1,606 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::*;
+use logger::{Access, Klog};
+use protocol_common::{CountSession, Lookup, Mismatch, Parse, ParseOk, RecordLatency};
+use std::io::{Error, ErrorKind};
+
+pub const DEFAULT_MAX_KEY_LEN: usize = 250;
+pub const DEFAULT_MAX_VALUE_SIZE: usize = 512 * 1024 * 1024; // 512MB max value size
+
+const MAX_HEADERS: usize = 32;
+const KEYS_PATH: &str = "/keys/";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Request {
+    Get(Get),
+    Put(Put),
+    Delete(Delete),
+    Invalid(Invalid),
+}
+
+impl Request {
+    /// Indicates that the client asked for the connection to be closed.
+    pub fn close(&self) -> bool {
+        match self {
+            Self::Get(r) => r.close,
+            Self::Put(r) => r.close,
+            Self::Delete(r) => r.close,
+            Self::Invalid(r) => r.close,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Get {
+    key: Box<[u8]>,
+    accept_gzip: bool,
+    close: bool,
+}
+
+impl Get {
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// True if the `Accept-Encoding` header allows the value to be sent with
+    /// gzip compression.
+    pub fn accept_gzip(&self) -> bool {
+        self.accept_gzip
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Put {
+    key: Box<[u8]>,
+    value: Box<[u8]>,
+    ttl: i64,
+    flags: u32,
+    if_match: Option<u64>,
+    if_none_match: bool,
+    close: bool,
+}
+
+impl Put {
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    /// The TTL in seconds. Zero means no expiration and negative values mean
+    /// immediate expiration.
+    pub fn ttl(&self) -> i64 {
+        self.ttl
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// The CAS value from an `If-Match` header, if present.
+    pub fn if_match(&self) -> Option<u64> {
+        self.if_match
+    }
+
+    /// True if the request had an `If-None-Match: *` header.
+    pub fn if_none_match(&self) -> bool {
+        self.if_none_match
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Delete {
+    key: Box<[u8]>,
+    if_match: Option<u64>,
+    close: bool,
+}
+
+impl Delete {
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// The CAS value from an `If-Match` header, if present.
+    pub fn if_match(&self) -> Option<u64> {
+        self.if_match
+    }
+}
+
+/// A well-formed HTTP request which cannot be served. The status indicates
+/// the reason.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Invalid {
+    status: Status,
+    close: bool,
+}
+
+impl Invalid {
+    pub fn status(&self) -> Status {
+        self.status
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct RequestParser {
+    max_key_len: usize,
+    max_value_size: usize,
+}
+
+impl RequestParser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn max_key_len(mut self, bytes: usize) -> Self {
+        self.max_key_len = bytes;
+        self
+    }
+
+    pub fn max_value_size(mut self, bytes: usize) -> Self {
+        self.max_value_size = bytes;
+        self
+    }
+}
+
+impl Default for RequestParser {
+    fn default() -> Self {
+        Self {
+            max_key_len: DEFAULT_MAX_KEY_LEN,
+            max_value_size: DEFAULT_MAX_VALUE_SIZE,
+        }
+    }
+}
+
+impl Parse<Request> for RequestParser {
+    fn parse(&self, buffer: &[u8]) -> Result<ParseOk<Request>, Error> {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut headers);
+
+        let header_len = match request.parse(buffer) {
+            Ok(httparse::Status::Complete(len)) => len,
+            Ok(httparse::Status::Partial) => {
+                return Err(Error::from(ErrorKind::WouldBlock));
+            }
+            Err(_) => {
+                HTTP_PARSE_EX.increment();
+                return Err(Error::from(ErrorKind::InvalidInput));
+            }
+        };
+
+        // HTTP/1.0 defaults to closing the connection
+        let mut close = request.version == Some(0);
+        let mut content_length = 0;
+        let mut ttl = 0;
+        let mut flags = 0;
+        let mut if_match = None;
+        let mut if_none_match = false;
+        let mut accept_gzip = false;
+        let mut chunked = false;
+        let mut valid = true;
+
+        for header in request.headers.iter() {
+            let value = match std::str::from_utf8(header.value) {
+                Ok(v) => v.trim(),
+                Err(_) => {
+                    valid = false;
+                    continue;
+                }
+            };
+
+            if header.name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<usize>().map_err(|_| {
+                    HTTP_PARSE_EX.increment();
+                    Error::from(ErrorKind::InvalidInput)
+                })?;
+            } else if header.name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = !value.eq_ignore_ascii_case("identity");
+            } else if header.name.eq_ignore_ascii_case("connection") {
+                if value.eq_ignore_ascii_case("close") {
+                    close = true;
+                } else if value.eq_ignore_ascii_case("keep-alive") {
+                    close = false;
+                }
+            } else if header.name.eq_ignore_ascii_case("x-ttl") {
+                match value.parse::<i64>() {
+                    Ok(v) => ttl = v,
+                    Err(_) => valid = false,
+                }
+            } else if header.name.eq_ignore_ascii_case("x-flags") {
+                match value.parse::<u32>() {
+                    Ok(v) => flags = v,
+                    Err(_) => valid = false,
+                }
+            } else if header.name.eq_ignore_ascii_case("if-match") {
+                // the ETag of a compressed body names the same value
+                let etag = value.trim_matches('"');
+                let etag = etag.strip_suffix(GZIP_ETAG_SUFFIX).unwrap_or(etag);
+                match etag.parse::<u64>() {
+                    Ok(v) => if_match = Some(v),
+                    Err(_) => valid = false,
+                }
+            } else if header.name.eq_ignore_ascii_case("if-none-match") {
+                if value == "*" {
+                    if_none_match = true;
+                } else {
+                    valid = false;
+                }
+            } else if header.name.eq_ignore_ascii_case("accept-encoding") {
+                accept_gzip = accepts(value, "gzip");
+            }
+        }
+
+        // bodies must be sent with a content length. As the end of a chunked
+        // body is unknown, the rest of the stream can't be parsed either and
+        // the connection is closed after the response.
+        if chunked {
+            HTTP_UNSUPPORTED.increment();
+            return Ok(ParseOk::new(
+                Request::Invalid(Invalid {
+                    status: Status::LengthRequired,
+                    close: true,
+                }),
+                header_len,
+            ));
+        }
+
+        if content_length > self.max_value_size {
+            HTTP_PARSE_EX.increment();
+            return Err(Error::from(ErrorKind::InvalidInput));
+        }
+
+        let consumed = header_len + content_length;
+        if buffer.len() < consumed {
+            return Err(Error::from(ErrorKind::WouldBlock));
+        }
+
+        let body = &buffer[header_len..consumed];
+
+        let invalid = |status| {
+            HTTP_UNSUPPORTED.increment();
+            Ok(ParseOk::new(
+                Request::Invalid(Invalid { status, close }),
+                consumed,
+            ))
+        };
+
+        let key = match request.path.and_then(|p| p.strip_prefix(KEYS_PATH)) {
+            Some(key) => match decode(key) {
+                Some(key) if !key.is_empty() && key.len() <= self.max_key_len => key,
+                _ => {
+                    return invalid(Status::BadRequest);
+                }
+            },
+            None => {
+                return invalid(Status::NotFound);
+            }
+        };
+
+        if !valid {
+            return invalid(Status::BadRequest);
+        }
+
+        let key = key.into_boxed_slice();
+
+        let request = match request.method {
+            Some("GET") => {
+                HTTP_GET.increment();
+                Request::Get(Get {
+                    key,
+                    accept_gzip,
+                    close,
+                })
+            }
+            Some("PUT") => {
+                HTTP_PUT.increment();
+                Request::Put(Put {
+                    key,
+                    value: body.to_vec().into_boxed_slice(),
+                    ttl,
+                    flags,
+                    if_match,
+                    if_none_match,
+                    close,
+                })
+            }
+            Some("DELETE") => {
+                HTTP_DELETE.increment();
+                Request::Delete(Delete {
+                    key,
+                    if_match,
+                    close,
+                })
+            }
+            _ => {
+                return invalid(Status::MethodNotAllowed);
+            }
+        };
+
+        Ok(ParseOk::new(request, consumed))
+    }
+
+    fn busy_response(&self) -> Option<&'static [u8]> {
+        Some(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n")
+    }
+
+    fn mismatch(&self, buffer: &[u8]) -> Option<Mismatch> {
+        // malformed HTTP is reported as a parse error, not a mismatch
+        Mismatch::detect(buffer).filter(|m| *m != Mismatch::Http)
+    }
+}
+
+/// Returns true if the coding is acceptable according to the value of an
+/// `Accept-Encoding` header. Codings with a quality of zero are refused.
+fn accepts(value: &str, coding: &str) -> bool {
+    value.split(',').any(|item| {
+        let mut params = item.split(';');
+        let name = params.next().unwrap_or("").trim();
+        if !name.eq_ignore_ascii_case(coding) {
+            return false;
+        }
+        params
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .all(|q| q.parse::<f32>().map(|q| q > 0.0).unwrap_or(false))
+    })
+}
+
+/// Decodes a percent-encoded path segment. Returns `None` if the encoding is
+/// invalid.
+fn decode(input: &str) -> Option<Vec<u8>> {
+    let input = input.as_bytes();
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] == b'%' {
+            let hex = input.get((i + 1)..(i + 3))?;
+            let hex = std::str::from_utf8(hex).ok()?;
+            output.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            output.push(input[i]);
+            i += 1;
+        }
+    }
+    Some(output)
+}
+
+impl RecordLatency for Request {
+    fn record_latency(&self, latency: u64) {
+        let now = Instant::now();
+        match self {
+            Self::Get(_) => HTTP_GET_LATENCY.increment(now, latency, 1),
+            Self::Put(_) => HTTP_PUT_LATENCY.increment(now, latency, 1),
+            Self::Delete(_) => HTTP_DELETE_LATENCY.increment(now, latency, 1),
+            Self::Invalid(_) => {}
+        }
+    }
+}
+
+// reads are always left to storage
+impl Lookup<Response> for Request {
+    fn read_only(&self) -> bool {
+        matches!(self, Self::Get(_) | Self::Invalid(_))
+    }
+}
+
+// there is no request for the counters of a session
+impl CountSession<Response> for Request {}
+
+impl Klog for Request {
+    type Response = Response;
+
+    fn klog(&self, response: &Self::Response) {
+        let (method, key) = match self {
+            Self::Get(r) => {
+                if response.status() == Status::Ok {
+                    HTTP_GET_HIT.increment();
+                } else {
+                    HTTP_GET_MISS.increment();
+                }
+                ("GET", r.key())
+            }
+            Self::Put(r) => {
+                if response.status() == Status::NoContent {
+                    HTTP_PUT_STORED.increment();
+                } else {
+                    HTTP_PUT_NOT_STORED.increment();
+                }
+                ("PUT", r.key())
+            }
+            Self::Delete(r) => {
+                if response.status() == Status::NoContent {
+                    HTTP_DELETE_DELETED.increment();
+                } else {
+                    HTTP_DELETE_NOT_FOUND.increment();
+                }
+                ("DELETE", r.key())
+            }
+            Self::Invalid(_) => {
+                return;
+            }
+        };
+        klog!(
+            "\"{} {}\" {} {}",
+            method,
+            String::from_utf8_lossy(key),
+            response.status().code(),
+            response.len()
+        );
+    }
+
+    fn access(&self, response: &Self::Response) -> Option<Access> {
+        let (verb, key) = match self {
+            Self::Get(r) => ("GET", r.key()),
+            Self::Put(r) => ("PUT", r.key()),
+            Self::Delete(r) => ("DELETE", r.key()),
+            Self::Invalid(_) => ("-", &[][..]),
+        };
+        Some(Access {
+            verb,
+            key_len: key.len(),
+            status: response.status().code_str(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(buffer: &[u8]) -> Result<ParseOk<Request>, Error> {
+        RequestParser::new().parse(buffer)
+    }
+
+    #[test]
+    fn get() {
+        let buffer = b"GET /keys/coffee HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let parsed = parse(buffer).expect("failed to parse");
+        assert_eq!(parsed.consumed(), buffer.len());
+        assert_eq!(
+            parsed.into_inner(),
+            Request::Get(Get {
+                key: b"coffee".to_vec().into_boxed_slice(),
+                accept_gzip: false,
+                close: false,
+            })
+        );
+
+        // percent-encoded keys are decoded
+        let parsed = parse(b"GET /keys/a%20b HTTP/1.0\r\n\r\n").expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            Request::Get(Get {
+                key: b"a b".to_vec().into_boxed_slice(),
+                accept_gzip: false,
+                close: true,
+            })
+        );
+
+        // compression is negotiated with the accept-encoding header
+        for (header, accept_gzip) in [
+            ("gzip", true),
+            ("deflate, GZIP;q=0.5", true),
+            ("gzip;q=0", false),
+            ("br", false),
+        ] {
+            let buffer = format!(
+                "GET /keys/coffee HTTP/1.1\r\nAccept-Encoding: {}\r\n\r\n",
+                header
+            );
+            match parse(buffer.as_bytes()).map(|p| p.into_inner()) {
+                Ok(Request::Get(get)) => assert_eq!(get.accept_gzip(), accept_gzip, "{}", header),
+                other => panic!("unexpected parse result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn put() {
+        let buffer =
+            b"PUT /keys/drink HTTP/1.1\r\nContent-Length: 6\r\nX-TTL: 60\r\nX-Flags: 3\r\nIf-Match: \"7\"\r\n\r\ncoffee";
+
+        // incomplete body
+        assert_eq!(
+            parse(&buffer[0..(buffer.len() - 1)]).map_err(|e| e.kind()),
+            Err(ErrorKind::WouldBlock)
+        );
+
+        let parsed = parse(buffer).expect("failed to parse");
+        assert_eq!(parsed.consumed(), buffer.len());
+        assert_eq!(
+            parsed.into_inner(),
+            Request::Put(Put {
+                key: b"drink".to_vec().into_boxed_slice(),
+                value: b"coffee".to_vec().into_boxed_slice(),
+                ttl: 60,
+                flags: 3,
+                if_match: Some(7),
+                if_none_match: false,
+                close: false,
+            })
+        );
+
+        // the ETag of a compressed body names the same value
+        let buffer =
+            b"PUT /keys/drink HTTP/1.1\r\nContent-Length: 3\r\nIf-Match: \"7-gzip\"\r\n\r\ntea";
+        let parsed = parse(buffer).expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            Request::Put(Put {
+                key: b"drink".to_vec().into_boxed_slice(),
+                value: b"tea".to_vec().into_boxed_slice(),
+                ttl: 0,
+                flags: 0,
+                if_match: Some(7),
+                if_none_match: false,
+                close: false,
+            })
+        );
+    }
+
+    #[test]
+    fn delete() {
+        let parsed = parse(b"DELETE /keys/drink HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            Request::Delete(Delete {
+                key: b"drink".to_vec().into_boxed_slice(),
+                if_match: None,
+                close: true,
+            })
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let parsed = parse(b"GET /other HTTP/1.1\r\n\r\n").expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            Request::Invalid(Invalid {
+                status: Status::NotFound,
+                close: false,
+            })
+        );
+
+        let parsed = parse(b"POST /keys/drink HTTP/1.1\r\n\r\n").expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            Request::Invalid(Invalid {
+                status: Status::MethodNotAllowed,
+                close: false,
+            })
+        );
+
+        let parsed =
+            parse(b"PUT /keys/drink HTTP/1.1\r\nX-TTL: soon\r\n\r\n").expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            Request::Invalid(Invalid {
+                status: Status::BadRequest,
+                close: false,
+            })
+        );
+
+        let parsed = parse(
+            b"PUT /keys/drink HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n6\r\ncoffee\r\n0\r\n\r\n",
+        )
+        .expect("failed to parse");
+        assert_eq!(
+            parsed.into_inner(),
+            Request::Invalid(Invalid {
+                status: Status::LengthRequired,
+                close: true,
+            })
+        );
+
+        assert_eq!(
+            parse(b"NOT HTTP\r\n\r\n").map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidInput)
+        );
+    }
+}
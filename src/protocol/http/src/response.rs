@@ -0,0 +1,261 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use protocol_common::BufMut;
+use std::io::Write;
+
+/// Values smaller than this are not worth compressing, as the savings would be
+/// outweighed by the gzip framing and the cost of compression.
+pub const GZIP_MIN_SIZE: usize = 1024;
+
+/// Appended to the ETag of a compressed body, as a strong ETag must differ
+/// between encodings of the same value.
+pub const GZIP_ETAG_SUFFIX: &str = "-gzip";
+
+/// The subset of HTTP status codes used by this protocol.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Status {
+    Ok,
+    NoContent,
+    BadRequest,
+    NotFound,
+    MethodNotAllowed,
+    LengthRequired,
+    PreconditionFailed,
+    PayloadTooLarge,
+    InternalServerError,
+    ServiceUnavailable,
+    InsufficientStorage,
+}
+
+impl Status {
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Ok => 200,
+            Self::NoContent => 204,
+            Self::BadRequest => 400,
+            Self::NotFound => 404,
+            Self::MethodNotAllowed => 405,
+            Self::LengthRequired => 411,
+            Self::PreconditionFailed => 412,
+            Self::PayloadTooLarge => 413,
+            Self::InternalServerError => 500,
+            Self::ServiceUnavailable => 503,
+            Self::InsufficientStorage => 507,
+        }
+    }
+
+    /// The status code as text, for use in logs.
+    pub fn code_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "200",
+            Self::NoContent => "204",
+            Self::BadRequest => "400",
+            Self::NotFound => "404",
+            Self::MethodNotAllowed => "405",
+            Self::LengthRequired => "411",
+            Self::PreconditionFailed => "412",
+            Self::PayloadTooLarge => "413",
+            Self::InternalServerError => "500",
+            Self::ServiceUnavailable => "503",
+            Self::InsufficientStorage => "507",
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::NoContent => "No Content",
+            Self::BadRequest => "Bad Request",
+            Self::NotFound => "Not Found",
+            Self::MethodNotAllowed => "Method Not Allowed",
+            Self::LengthRequired => "Length Required",
+            Self::PreconditionFailed => "Precondition Failed",
+            Self::PayloadTooLarge => "Payload Too Large",
+            Self::InternalServerError => "Internal Server Error",
+            Self::ServiceUnavailable => "Service Unavailable",
+            Self::InsufficientStorage => "Insufficient Storage",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Response {
+    status: Status,
+    flags: Option<u32>,
+    etag: Option<u64>,
+    body: Option<Box<[u8]>>,
+    gzip: bool,
+    close: bool,
+}
+
+impl Response {
+    /// A successful response carrying a value.
+    pub fn ok(value: &[u8], flags: u32, cas: u64) -> Self {
+        Self {
+            status: Status::Ok,
+            flags: Some(flags),
+            etag: Some(cas),
+            body: Some(value.to_vec().into_boxed_slice()),
+            gzip: false,
+            close: false,
+        }
+    }
+
+    /// A response without a body.
+    pub fn new(status: Status) -> Self {
+        Self {
+            status,
+            flags: None,
+            etag: None,
+            body: None,
+            gzip: false,
+            close: false,
+        }
+    }
+
+    /// Compresses the body with gzip if it is at least `min_size` bytes. The
+    /// body is left as-is if compression would not make it smaller.
+    pub fn gzip(mut self, min_size: usize) -> Self {
+        let body = match &self.body {
+            Some(body) if !self.gzip && body.len() >= min_size => body,
+            _ => {
+                return self;
+            }
+        };
+
+        let mut encoder = GzEncoder::new(Vec::with_capacity(body.len()), Compression::fast());
+        let compressed = match encoder.write_all(body).and_then(|_| encoder.finish()) {
+            Ok(compressed) if compressed.len() < body.len() => compressed,
+            _ => {
+                return self;
+            }
+        };
+
+        HTTP_GZIP.increment();
+        HTTP_GZIP_SAVED_BYTES.add((body.len() - compressed.len()) as _);
+        self.body = Some(compressed.into_boxed_slice());
+        self.gzip = true;
+        self
+    }
+
+    /// Marks the connection to be closed after this response is sent.
+    pub fn close(mut self, close: bool) -> Self {
+        self.close = close;
+        self
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Returns the length of the response body.
+    pub fn len(&self) -> usize {
+        self.body.as_ref().map(|b| b.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Compose for Response {
+    fn compose(&self, dst: &mut dyn BufMut) -> usize {
+        let mut header = format!(
+            "HTTP/1.1 {} {}\r\nContent-Length: {}\r\n",
+            self.status.code(),
+            self.status.reason(),
+            self.len()
+        );
+        if let Some(etag) = self.etag {
+            let suffix = if self.gzip { GZIP_ETAG_SUFFIX } else { "" };
+            header.push_str(&format!("ETag: \"{}{}\"\r\n", etag, suffix));
+        }
+        if let Some(flags) = self.flags {
+            header.push_str(&format!("X-Flags: {}\r\n", flags));
+        }
+        if self.gzip {
+            header.push_str("Content-Encoding: gzip\r\n");
+        }
+        // a value may be compressed depending on the request, so caches must
+        // key every response carrying one on the accepted encodings
+        if self.status == Status::Ok && self.body.is_some() {
+            header.push_str("Vary: Accept-Encoding\r\n");
+        }
+        if self.close {
+            header.push_str("Connection: close\r\n");
+        }
+        header.push_str("\r\n");
+
+        dst.put_slice(header.as_bytes());
+
+        let mut size = header.len();
+        if let Some(body) = &self.body {
+            dst.put_slice(body);
+            size += body.len();
+        }
+        size
+    }
+
+    fn should_hangup(&self) -> bool {
+        self.close
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compose() {
+        let mut buf = Vec::new();
+        Response::ok(b"coffee", 1, 42).compose(&mut buf);
+        assert_eq!(
+            &buf[..],
+            b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\nETag: \"42\"\r\nX-Flags: 1\r\nVary: Accept-Encoding\r\n\r\ncoffee"
+        );
+
+        let mut buf = Vec::new();
+        Response::new(Status::NotFound)
+            .close(true)
+            .compose(&mut buf);
+        assert_eq!(
+            &buf[..],
+            b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn gzip() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        // small values are not compressed
+        let response = Response::ok(b"coffee", 0, 1).gzip(1024);
+        assert!(!response.gzip);
+        assert_eq!(response.len(), 6);
+
+        let value = b"coffee".repeat(1024);
+        let response = Response::ok(&value, 0, 1).gzip(1024);
+        assert!(response.gzip);
+        assert!(response.len() < value.len());
+
+        let mut buf = Vec::new();
+        response.compose(&mut buf);
+        let header = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"1-gzip\"\r\nX-Flags: 0\r\nContent-Encoding: gzip\r\nVary: Accept-Encoding\r\n\r\n",
+            response.len()
+        );
+        assert_eq!(&buf[..header.len()], header.as_bytes());
+
+        let mut decoded = Vec::new();
+        GzDecoder::new(&buf[header.len()..])
+            .read_to_end(&mut decoded)
+            .expect("failed to decode");
+        assert_eq!(decoded, value);
+    }
+}
@@ -0,0 +1,18 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A fuzz target which makes sure that the internal protocol implementation
+//! will handle arbitrary data without panicking.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use protocol_common::Parse;
+use protocol_internal::*;
+
+const PARSER: FrameParser = FrameParser::new(1024 * 1024);
+
+fuzz_target!(|data: &[u8]| {
+    let _ = PARSER.parse(data);
+});
@@ -0,0 +1,28 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A fuzz target which makes sure that any frame which is parsed from
+//! arbitrary data is composed into a frame which parses to the same message.
+
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use protocol_common::{Compose, Parse};
+use protocol_internal::*;
+
+const PARSER: FrameParser = FrameParser::new(1024 * 1024);
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(parsed) = PARSER.parse(data) {
+        let frame = parsed.into_inner();
+
+        let mut buf = Vec::new();
+        let len = frame.compose(&mut buf);
+        assert_eq!(len, buf.len());
+
+        let reparsed = PARSER.parse(&buf).expect("composed frame did not parse");
+        assert_eq!(reparsed.consumed(), buf.len());
+        assert_eq!(reparsed.into_inner(), frame);
+    }
+});
@@ -0,0 +1,278 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::*;
+
+use protocol_common::{BufMut, Compose, Parse, ParseOk};
+use std::io::{Error, ErrorKind};
+
+/// The first bytes of every frame.
+pub const MAGIC: [u8; 2] = *b"PK";
+
+/// The newest version of the protocol which this build can parse and compose.
+pub const VERSION: u8 = 1;
+
+/// The oldest version of the protocol which this build can parse and compose.
+pub const MIN_VERSION: u8 = 1;
+
+/// The length of the header which precedes the payload of each frame.
+pub const HEADER_LEN: usize = 12;
+
+// the checksum covers the bytes of the header which precede it
+const CHECKED_LEN: usize = 8;
+
+/// A message, along with the version of the protocol it is encoded with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    version: u8,
+    message: Message,
+}
+
+impl Frame {
+    /// A frame using the newest version of the protocol. Once the peers have
+    /// exchanged a [`Hello`], frames should instead use the version they agreed
+    /// on, see [`Frame::with_version`].
+    pub fn new(message: Message) -> Self {
+        Self::with_version(VERSION, message)
+    }
+
+    pub fn with_version(version: u8, message: Message) -> Self {
+        Self { version, message }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    pub fn into_message(self) -> Message {
+        self.message
+    }
+}
+
+impl Compose for Frame {
+    fn compose(&self, dst: &mut dyn BufMut) -> usize {
+        INTERNAL_FRAME_COMPOSE.increment();
+
+        let mut payload = Vec::new();
+        self.message.encode(&mut payload);
+
+        let mut header = [0; HEADER_LEN];
+        header[0..2].copy_from_slice(&MAGIC);
+        header[2] = self.version;
+        header[3] = self.message.kind() as u8;
+        header[4..8].copy_from_slice(&(payload.len() as u32).to_be_bytes());
+        let checksum = checksum(&header[0..CHECKED_LEN], &payload);
+        header[CHECKED_LEN..HEADER_LEN].copy_from_slice(&checksum.to_be_bytes());
+
+        dst.put_slice(&header);
+        dst.put_slice(&payload);
+
+        HEADER_LEN + payload.len()
+    }
+}
+
+fn checksum(header: &[u8], payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(header);
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// A parser for frames, which rejects any frame with a payload larger than the
+/// maximum size. A frame which fails its checksum is rejected with
+/// `ErrorKind::InvalidData`, and one with an unsupported version with
+/// `ErrorKind::Unsupported`.
+#[derive(Clone, Copy)]
+pub struct FrameParser {
+    max_size: usize,
+}
+
+impl FrameParser {
+    pub const fn new(max_size: usize) -> Self {
+        Self { max_size }
+    }
+}
+
+impl Parse<Frame> for FrameParser {
+    fn parse(&self, buffer: &[u8]) -> Result<ParseOk<Frame>, Error> {
+        if buffer.len() < HEADER_LEN {
+            return Err(Error::from(ErrorKind::WouldBlock));
+        }
+
+        // a stream which is not at the start of a frame is rejected without
+        // waiting for the length it claims
+        let length = u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]) as usize;
+        if buffer[0..2] != MAGIC || length > self.max_size {
+            INTERNAL_FRAME_PARSE_EX.increment();
+            return Err(Error::from(ErrorKind::InvalidInput));
+        }
+
+        let framed_len = HEADER_LEN + length;
+        if buffer.len() < framed_len {
+            return Err(Error::from(ErrorKind::WouldBlock));
+        }
+
+        let payload = &buffer[HEADER_LEN..framed_len];
+        let expected = u32::from_be_bytes([buffer[8], buffer[9], buffer[10], buffer[11]]);
+        if checksum(&buffer[0..CHECKED_LEN], payload) != expected {
+            INTERNAL_FRAME_CHECKSUM_EX.increment();
+            return Err(Error::from(ErrorKind::InvalidData));
+        }
+
+        // a hello is understood in every version, so that peers of different
+        // versions can agree on one to use
+        let version = buffer[2];
+        let kind = buffer[3];
+        if kind != Kind::Hello as u8 && !(MIN_VERSION..=VERSION).contains(&version) {
+            INTERNAL_FRAME_VERSION_EX.increment();
+            return Err(Error::from(ErrorKind::Unsupported));
+        }
+
+        let message = Message::decode(kind, payload).ok_or_else(|| {
+            INTERNAL_FRAME_PARSE_EX.increment();
+            Error::from(ErrorKind::InvalidInput)
+        })?;
+
+        INTERNAL_FRAME_PARSE.increment();
+        Ok(ParseOk::new(Frame { version, message }, framed_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARSER: FrameParser = FrameParser::new(1024);
+
+    fn compose(frame: &Frame) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let len = frame.compose(&mut buf);
+        assert_eq!(len, buf.len());
+        buf
+    }
+
+    #[test]
+    fn roundtrip() {
+        let messages = vec![
+            Message::Hello(Hello::new(Capabilities::MIGRATION)),
+            Message::Item(Item {
+                key: b"tenant:coffee".to_vec().into_boxed_slice(),
+                value: b"strong".to_vec().into_boxed_slice(),
+                flags: 42,
+                ttl: 3600,
+                cas: 7,
+            }),
+            Message::Item(Item {
+                key: b"empty".to_vec().into_boxed_slice(),
+                value: Vec::new().into_boxed_slice(),
+                flags: 0,
+                ttl: 0,
+                cas: 0,
+            }),
+            Message::Delete(b"tenant:tea".to_vec().into_boxed_slice()),
+            Message::Ack(1024),
+            Message::Error("destination is full".to_string()),
+        ];
+
+        for message in messages {
+            let frame = Frame::new(message);
+            let buf = compose(&frame);
+
+            let parsed = PARSER.parse(&buf).expect("failed to parse");
+            assert_eq!(parsed.consumed(), buf.len());
+            assert_eq!(parsed.into_inner(), frame);
+        }
+    }
+
+    #[test]
+    fn incomplete() {
+        let buf = compose(&Frame::new(Message::Ack(1)));
+        for len in 0..buf.len() {
+            let e = PARSER.parse(&buf[0..len]).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::WouldBlock);
+        }
+
+        // a frame is parsed on its own, leaving the rest of the buffer
+        let mut pipelined = buf.clone();
+        pipelined.extend_from_slice(&buf[0..4]);
+        let parsed = PARSER.parse(&pipelined).expect("failed to parse");
+        assert_eq!(parsed.consumed(), buf.len());
+    }
+
+    #[test]
+    fn corrupted() {
+        let mut buf = compose(&Frame::new(Message::Delete(
+            b"coffee".to_vec().into_boxed_slice(),
+        )));
+
+        // flipping any bit in the payload or the covered header is detected
+        let last = buf.len() - 1;
+        buf[last] ^= 1;
+        let e = PARSER.parse(&buf).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        buf[last] ^= 1;
+
+        buf[2] ^= 1;
+        let e = PARSER.parse(&buf).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        buf[2] ^= 1;
+
+        assert!(PARSER.parse(&buf).is_ok());
+
+        // bytes which are not the start of a frame are rejected at once
+        let e = PARSER.parse(b"get coffee\r\n\r\n").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn oversized() {
+        let frame = Frame::new(Message::Delete(vec![b'a'; 2048].into_boxed_slice()));
+        let buf = compose(&frame);
+
+        // the frame is rejected from its header, before it is buffered
+        let e = PARSER.parse(&buf[0..HEADER_LEN]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn versions() {
+        // frames from a version which is not supported are rejected
+        let buf = compose(&Frame::with_version(VERSION + 1, Message::Ack(1)));
+        let e = PARSER.parse(&buf).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Unsupported);
+
+        // except for a hello, so that the peers can agree on a version
+        let hello = Hello {
+            min_version: MIN_VERSION,
+            max_version: VERSION + 1,
+            capabilities: Capabilities::MIGRATION,
+        };
+        let buf = compose(&Frame::with_version(VERSION + 1, Message::Hello(hello)));
+        let parsed = PARSER.parse(&buf).expect("failed to parse").into_inner();
+        assert_eq!(parsed.version(), VERSION + 1);
+        assert_eq!(parsed.into_message(), Message::Hello(hello));
+    }
+
+    #[test]
+    fn malformed() {
+        // an item which claims a key longer than its payload
+        let mut item = compose(&Frame::new(Message::Item(Item {
+            key: b"coffee".to_vec().into_boxed_slice(),
+            value: Vec::new().into_boxed_slice(),
+            flags: 0,
+            ttl: 0,
+            cas: 0,
+        })));
+        item[HEADER_LEN + 19] = 7;
+        let checksum = checksum(&item[0..CHECKED_LEN], &item[HEADER_LEN..]);
+        item[CHECKED_LEN..HEADER_LEN].copy_from_slice(&checksum.to_be_bytes());
+
+        let e = PARSER.parse(&item).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+}
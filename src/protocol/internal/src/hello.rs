@@ -0,0 +1,162 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::*;
+
+use std::ops::BitOr;
+
+/// The features which one end of an exchange supports, as a set of bits.
+/// Bits which are not known to this build are kept, so that a newer peer can
+/// still find the capabilities which both ends share.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    /// Accepts the items streamed by a migration, acknowledging them once they
+    /// are stored
+    pub const MIGRATION: Self = Self(1);
+    /// Accepts a stream of the writes and deletes made on another instance
+    pub const REPLICATION: Self = Self(1 << 1);
+    /// Accepts items to fill its storage after a restart
+    pub const WARMUP: Self = Self(1 << 2);
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Whether every capability in `other` is also in this set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The capabilities which are in both sets.
+    pub const fn intersection(&self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+// the fields of a hello, each of which must be present in every version
+const HELLO_LEN: usize = 10;
+
+/// The first message sent by each end of an exchange, describing the range of
+/// versions and the capabilities it supports. Its encoding is the same in
+/// every version of the protocol, and later versions may only append fields,
+/// so that any two builds can agree on what to use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hello {
+    pub min_version: u8,
+    pub max_version: u8,
+    pub capabilities: Capabilities,
+}
+
+/// The version and the capabilities which both ends of an exchange support.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Negotiated {
+    pub version: u8,
+    pub capabilities: Capabilities,
+}
+
+impl Hello {
+    /// A hello for this build, which supports every version it can parse.
+    pub fn new(capabilities: Capabilities) -> Self {
+        Self {
+            min_version: MIN_VERSION,
+            max_version: VERSION,
+            capabilities,
+        }
+    }
+
+    /// Agrees on the newest version which both ends support, and on the
+    /// capabilities which they share. Returns `None` if the ranges of versions
+    /// do not overlap, in which case the exchange should be ended.
+    pub fn negotiate(&self, peer: &Hello) -> Option<Negotiated> {
+        let version = self.max_version.min(peer.max_version);
+        if version < self.min_version.max(peer.min_version) {
+            return None;
+        }
+
+        Some(Negotiated {
+            version,
+            capabilities: self.capabilities.intersection(peer.capabilities),
+        })
+    }
+
+    pub(crate) fn encode(&self, payload: &mut Vec<u8>) {
+        payload.push(self.min_version);
+        payload.push(self.max_version);
+        payload.extend_from_slice(&self.capabilities.bits().to_be_bytes());
+    }
+
+    // any fields appended by a later version are ignored
+    pub(crate) fn decode(payload: &[u8]) -> Option<Self> {
+        if payload.len() < HELLO_LEN {
+            return None;
+        }
+
+        let mut capabilities = [0; 8];
+        capabilities.copy_from_slice(&payload[2..HELLO_LEN]);
+
+        Some(Self {
+            min_version: payload[0],
+            max_version: payload[1],
+            capabilities: Capabilities::from_bits(u64::from_be_bytes(capabilities)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        let local = Hello::new(Capabilities::MIGRATION | Capabilities::WARMUP);
+
+        // a newer peer which shares only some of the capabilities
+        let peer = Hello {
+            min_version: MIN_VERSION,
+            max_version: VERSION + 1,
+            capabilities: Capabilities::MIGRATION | Capabilities::from_bits(1 << 63),
+        };
+        let negotiated = local.negotiate(&peer).expect("failed to negotiate");
+        assert_eq!(negotiated.version, VERSION);
+        assert_eq!(negotiated.capabilities, Capabilities::MIGRATION);
+        assert_eq!(peer.negotiate(&local), Some(negotiated));
+
+        // a peer which no longer supports any version of this build
+        let peer = Hello {
+            min_version: VERSION + 1,
+            max_version: VERSION + 2,
+            capabilities: Capabilities::MIGRATION,
+        };
+        assert!(local.negotiate(&peer).is_none());
+    }
+
+    #[test]
+    fn decode() {
+        let hello = Hello::new(Capabilities::REPLICATION);
+        let mut payload = Vec::new();
+        hello.encode(&mut payload);
+        assert_eq!(payload.len(), HELLO_LEN);
+
+        // fields appended by a later version are ignored
+        payload.extend_from_slice(b"later");
+        assert_eq!(Hello::decode(&payload), Some(hello));
+
+        assert_eq!(Hello::decode(&payload[0..(HELLO_LEN - 1)]), None);
+    }
+}
@@ -0,0 +1,58 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A protocol crate for the traffic between pelikan instances, such as the
+//! items streamed by a migration, by replication, or to warm up a new
+//! instance. Both ends are pelikan, so unlike the client protocols the format
+//! is designed to stay robust across a rolling upgrade:
+//!
+//! * each frame is length-prefixed and carries a CRC32 checksum of its header
+//!   and payload, so that a corrupted frame is rejected rather than applied
+//! * each frame carries the version of the protocol it was composed with, and
+//!   the two ends agree on a version and a set of capabilities by exchanging a
+//!   [`Hello`] before sending anything else
+//!
+//! A frame is laid out as follows, with all integers in big-endian order. The
+//! checksum covers the eight bytes of the header which precede it, followed by
+//! the payload.
+//!
+//! ```text
+//! +-------+---------+------+--------+----------+---------+
+//! | magic | version | kind | length | checksum | payload |
+//! |  2B   |   1B    |  1B  |   4B   |    4B    | length  |
+//! +-------+---------+------+--------+----------+---------+
+//! ```
+
+mod frame;
+mod hello;
+mod message;
+
+pub use frame::*;
+pub use hello::*;
+pub use message::*;
+
+use rustcommon_metrics::*;
+
+counter!(
+    INTERNAL_FRAME_PARSE,
+    "number of internal protocol frames which were parsed"
+);
+counter!(
+    INTERNAL_FRAME_PARSE_EX,
+    "number of internal protocol frames which were rejected as malformed"
+);
+counter!(
+    INTERNAL_FRAME_CHECKSUM_EX,
+    "number of internal protocol frames which were rejected because their checksum did not match"
+);
+counter!(
+    INTERNAL_FRAME_VERSION_EX,
+    "number of internal protocol frames which were rejected because their version is not supported"
+);
+counter!(
+    INTERNAL_FRAME_COMPOSE,
+    "number of internal protocol frames which were composed"
+);
+
+common::metrics::test_no_duplicates!();
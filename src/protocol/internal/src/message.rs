@@ -0,0 +1,132 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use crate::*;
+
+/// The kind of a message, which is encoded in the frame header. Values are
+/// never reused, so that a peer can tell a kind it does not know from one
+/// which has been removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Kind {
+    Hello = 1,
+    Item = 2,
+    Delete = 3,
+    Ack = 4,
+    Error = 5,
+}
+
+impl TryFrom<u8> for Kind {
+    type Error = ();
+
+    fn try_from(kind: u8) -> Result<Self, ()> {
+        match kind {
+            1 => Ok(Self::Hello),
+            2 => Ok(Self::Item),
+            3 => Ok(Self::Delete),
+            4 => Ok(Self::Ack),
+            5 => Ok(Self::Error),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// Opens the exchange, see [`Hello`]
+    Hello(Hello),
+    /// An item to store
+    Item(Item),
+    /// The key of an item to remove
+    Delete(Box<[u8]>),
+    /// Acknowledges that the given number of items and deletes, counted from
+    /// the start of the exchange, have been applied
+    Ack(u64),
+    /// Describes a failure which ends the exchange
+    Error(String),
+}
+
+// the fixed-size fields of an item, which precede the key and the value
+const ITEM_HEADER_LEN: usize = 20;
+
+/// An item sent between instances.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Item {
+    pub key: Box<[u8]>,
+    pub value: Box<[u8]>,
+    pub flags: u32,
+    /// The number of seconds until the item expires, or zero if it does not
+    pub ttl: u32,
+    /// The cas value of the item on the sender, so that the sender can tell
+    /// whether the item changed after it was sent
+    pub cas: u64,
+}
+
+impl Message {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Self::Hello(_) => Kind::Hello,
+            Self::Item(_) => Kind::Item,
+            Self::Delete(_) => Kind::Delete,
+            Self::Ack(_) => Kind::Ack,
+            Self::Error(_) => Kind::Error,
+        }
+    }
+
+    pub(crate) fn encode(&self, payload: &mut Vec<u8>) {
+        match self {
+            Self::Hello(hello) => hello.encode(payload),
+            Self::Item(item) => {
+                payload.reserve(ITEM_HEADER_LEN + item.key.len() + item.value.len());
+                payload.extend_from_slice(&item.flags.to_be_bytes());
+                payload.extend_from_slice(&item.ttl.to_be_bytes());
+                payload.extend_from_slice(&item.cas.to_be_bytes());
+                payload.extend_from_slice(&(item.key.len() as u32).to_be_bytes());
+                payload.extend_from_slice(&item.key);
+                payload.extend_from_slice(&item.value);
+            }
+            Self::Delete(key) => payload.extend_from_slice(key),
+            Self::Ack(count) => payload.extend_from_slice(&count.to_be_bytes()),
+            Self::Error(message) => payload.extend_from_slice(message.as_bytes()),
+        }
+    }
+
+    /// Decodes the payload of a frame of the given kind, returning `None` if
+    /// the kind is unknown or the payload is malformed.
+    pub(crate) fn decode(kind: u8, payload: &[u8]) -> Option<Self> {
+        match Kind::try_from(kind).ok()? {
+            Kind::Hello => Hello::decode(payload).map(Self::Hello),
+            Kind::Item => {
+                if payload.len() < ITEM_HEADER_LEN {
+                    return None;
+                }
+
+                let key_len = u32::from_be_bytes(payload[16..20].try_into().ok()?) as usize;
+                let key_end = ITEM_HEADER_LEN.checked_add(key_len)?;
+                if key_len == 0 || key_end > payload.len() {
+                    return None;
+                }
+
+                Some(Self::Item(Item {
+                    key: payload[ITEM_HEADER_LEN..key_end].into(),
+                    value: payload[key_end..].into(),
+                    flags: u32::from_be_bytes(payload[0..4].try_into().ok()?),
+                    ttl: u32::from_be_bytes(payload[4..8].try_into().ok()?),
+                    cas: u64::from_be_bytes(payload[8..16].try_into().ok()?),
+                }))
+            }
+            Kind::Delete => {
+                if payload.is_empty() {
+                    None
+                } else {
+                    Some(Self::Delete(payload.into()))
+                }
+            }
+            Kind::Ack => Some(Self::Ack(u64::from_be_bytes(payload.try_into().ok()?))),
+            Kind::Error => std::str::from_utf8(payload)
+                .ok()
+                .map(|message| Self::Error(message.to_string())),
+        }
+    }
+}
@@ -9,12 +9,12 @@
 #![no_main]
 use libfuzzer_sys::fuzz_target;
 
-use protocol_memcache::*;
 use protocol_common::Parse;
+use protocol_memcache::*;
 
 const MAX_KEY_LEN: usize = 128;
 const MAX_BATCH_SIZE: usize = 128;
-const MAX_VALUE_SIZE: usize = 4*4096;
+const MAX_VALUE_SIZE: usize = 4 * 4096;
 
 fuzz_target!(|data: &[u8]| {
     let parser = RequestParser::new()
@@ -46,6 +46,28 @@ fuzz_target!(|data: &[u8]| {
                     validate_key(key);
                 }
             }
+            Request::Gat(gat) => {
+                if gat.keys().is_empty() {
+                    panic!("no keys");
+                }
+                if gat.keys().len() > MAX_BATCH_SIZE {
+                    panic!("batch size exceeds max");
+                }
+                for key in gat.keys().iter() {
+                    validate_key(key);
+                }
+            }
+            Request::Gats(gats) => {
+                if gats.keys().is_empty() {
+                    panic!("no keys");
+                }
+                if gats.keys().len() > MAX_BATCH_SIZE {
+                    panic!("batch size exceeds max");
+                }
+                for key in gats.keys().iter() {
+                    validate_key(key);
+                }
+            }
             Request::Set(set) => {
                 validate_key(set.key());
                 validate_value(set.value());
@@ -79,8 +101,27 @@ fuzz_target!(|data: &[u8]| {
             Request::Decr(decr) => {
                 validate_key(decr.key());
             }
+            Request::MetaArithmetic(ma) => {
+                validate_key(ma.key());
+            }
+            Request::MetaDelete(md) => {
+                validate_key(md.key());
+            }
+            Request::MetaGet(mg) => {
+                validate_key(mg.key());
+            }
+            Request::MetaSet(ms) => {
+                validate_key(ms.key());
+                validate_value(ms.value());
+            }
+            Request::Touch(touch) => {
+                validate_key(touch.key());
+            }
             Request::FlushAll(_) => {}
+            Request::MetaNoop(_) => {}
             Request::Quit(_) => {}
+            Request::Stats(_) => {}
+            Request::Time(_) => {}
         }
     }
 });
@@ -0,0 +1,120 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Base64 encoding for keys. The meta protocol allows keys to be sent with the
+//! `b` flag, which indicates that the key is base64 encoded on the wire. The
+//! key is decoded before it is stored, so clients with keys that contain
+//! arbitrary bytes (such as whitespace or control characters) do not need to
+//! escape them. Keys in responses to such requests are encoded again.
+//!
+//! The standard alphabet with padding is used, matching memcached.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const PAD: u8 = b'=';
+
+/// Encode a raw key for transmission.
+pub fn encode_key(key: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity((key.len() + 2) / 3 * 4);
+
+    for chunk in key.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        encoded.push(ALPHABET[(n >> 18) as usize & 0x3F]);
+        encoded.push(ALPHABET[(n >> 12) as usize & 0x3F]);
+        if chunk.len() > 1 {
+            encoded.push(ALPHABET[(n >> 6) as usize & 0x3F]);
+        } else {
+            encoded.push(PAD);
+        }
+        if chunk.len() > 2 {
+            encoded.push(ALPHABET[n as usize & 0x3F]);
+        } else {
+            encoded.push(PAD);
+        }
+    }
+
+    encoded
+}
+
+/// Decode a key which was received in its encoded form. Returns `None` if the
+/// input is not valid base64 or if it decodes to an empty key.
+pub fn decode_key(encoded: &[u8]) -> Option<Vec<u8>> {
+    if encoded.is_empty() || encoded.len() % 4 != 0 {
+        return None;
+    }
+
+    let mut key = Vec::with_capacity(encoded.len() / 4 * 3);
+    let chunks = encoded.len() / 4;
+
+    for (i, chunk) in encoded.chunks(4).enumerate() {
+        // padding may only appear at the end of the final chunk
+        let padding = chunk.iter().rev().take_while(|b| **b == PAD).count();
+        if padding > 2 || (padding > 0 && i + 1 != chunks) {
+            return None;
+        }
+
+        let mut n = 0_u32;
+        for b in &chunk[..(4 - padding)] {
+            n = n << 6 | value(*b)? as u32;
+        }
+        n <<= 6 * padding as u32;
+
+        key.push((n >> 16) as u8);
+        if padding < 2 {
+            key.push((n >> 8) as u8);
+        }
+        if padding < 1 {
+            key.push(n as u8);
+        }
+    }
+
+    Some(key)
+}
+
+// Returns the value of a single character of the base64 alphabet.
+fn value(b: u8) -> Option<u8> {
+    match b {
+        b'A'..=b'Z' => Some(b - b'A'),
+        b'a'..=b'z' => Some(b - b'a' + 26),
+        b'0'..=b'9' => Some(b - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let keys: &[(&[u8], &[u8])] = &[
+            (b"f", b"Zg=="),
+            (b"fo", b"Zm8="),
+            (b"foo", b"Zm9v"),
+            (b"foob", b"Zm9vYg=="),
+            (b"key with spaces\r\n", b"a2V5IHdpdGggc3BhY2VzDQo="),
+            (&[0, 255, 128], b"AP+A"),
+        ];
+
+        for (raw, encoded) in keys {
+            assert_eq!(&encode_key(raw)[..], *encoded);
+            assert_eq!(decode_key(encoded).as_deref(), Some(*raw));
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let inputs: &[&[u8]] = &[b"", b"Zg=", b"Z===", b"Zg==Zg==", b"Zm9*", b"Zm 9"];
+        for input in inputs {
+            assert_eq!(decode_key(input), None);
+        }
+    }
+}
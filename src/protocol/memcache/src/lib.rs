@@ -5,6 +5,7 @@
 #[macro_use]
 extern crate logger;
 
+mod base64;
 mod request;
 mod response;
 mod storage;
@@ -12,6 +13,7 @@ mod util;
 
 pub(crate) use util::*;
 
+pub use base64::{decode_key, encode_key};
 pub use request::*;
 pub use response::*;
 pub use storage::*;
@@ -19,11 +21,14 @@ pub use storage::*;
 pub use protocol_common::*;
 
 use common::expiry::TimeType;
-use logger::Klog;
+use logger::{Access, Klog};
 use rustcommon_metrics::*;
 
 const CRLF: &[u8] = b"\r\n";
 
+/// The version of the protocol implementation
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 pub enum MemcacheError {
     Error(Error),
     ClientError(ClientError),
@@ -49,6 +54,18 @@ counter!(GETS_KEY);
 counter!(GETS_KEY_HIT);
 counter!(GETS_KEY_MISS);
 
+counter!(GAT);
+counter!(GAT_EX);
+counter!(GAT_KEY);
+counter!(GAT_KEY_HIT);
+counter!(GAT_KEY_MISS);
+
+counter!(GATS);
+counter!(GATS_EX);
+counter!(GATS_KEY);
+counter!(GATS_KEY_HIT);
+counter!(GATS_KEY_MISS);
+
 counter!(SET);
 counter!(SET_EX);
 counter!(SET_STORED);
@@ -89,6 +106,32 @@ counter!(DECR_EX);
 counter!(DECR_STORED);
 counter!(DECR_NOT_FOUND);
 
+counter!(MA);
+counter!(MA_EX);
+counter!(MA_STORED);
+counter!(MA_NOT_FOUND);
+counter!(MA_NOT_STORED);
+
+counter!(MG);
+counter!(MG_EX);
+counter!(MG_HIT);
+counter!(MG_MISS);
+
+counter!(MS);
+counter!(MS_EX);
+counter!(MS_STORED);
+counter!(MS_NOT_STORED);
+counter!(MS_EXISTS);
+counter!(MS_NOT_FOUND);
+
+counter!(MD);
+counter!(MD_EX);
+counter!(MD_DELETED);
+counter!(MD_NOT_FOUND);
+counter!(MD_EXISTS);
+
+counter!(MN);
+
 counter!(CAS);
 counter!(CAS_EX);
 counter!(CAS_EXISTS);
@@ -100,4 +143,108 @@ counter!(FLUSH_ALL_EX);
 
 counter!(QUIT);
 
+counter!(STATS);
+counter!(STATS_EX);
+
+counter!(TIME);
+
+counter!(TOUCH);
+counter!(TOUCH_EX);
+counter!(TOUCH_TOUCHED);
+counter!(TOUCH_NOT_FOUND);
+
+// the largest latency which is tracked precisely, in nanoseconds
+const LATENCY_MAX: u64 = 1_000_000_000;
+
+heatmap!(
+    GET_LATENCY,
+    LATENCY_MAX,
+    "distribution of get request latencies in nanoseconds"
+);
+heatmap!(
+    GETS_LATENCY,
+    LATENCY_MAX,
+    "distribution of gets request latencies in nanoseconds"
+);
+heatmap!(
+    GAT_LATENCY,
+    LATENCY_MAX,
+    "distribution of gat request latencies in nanoseconds"
+);
+heatmap!(
+    GATS_LATENCY,
+    LATENCY_MAX,
+    "distribution of gats request latencies in nanoseconds"
+);
+heatmap!(
+    SET_LATENCY,
+    LATENCY_MAX,
+    "distribution of set request latencies in nanoseconds"
+);
+heatmap!(
+    ADD_LATENCY,
+    LATENCY_MAX,
+    "distribution of add request latencies in nanoseconds"
+);
+heatmap!(
+    REPLACE_LATENCY,
+    LATENCY_MAX,
+    "distribution of replace request latencies in nanoseconds"
+);
+heatmap!(
+    APPEND_LATENCY,
+    LATENCY_MAX,
+    "distribution of append request latencies in nanoseconds"
+);
+heatmap!(
+    PREPEND_LATENCY,
+    LATENCY_MAX,
+    "distribution of prepend request latencies in nanoseconds"
+);
+heatmap!(
+    DELETE_LATENCY,
+    LATENCY_MAX,
+    "distribution of delete request latencies in nanoseconds"
+);
+heatmap!(
+    INCR_LATENCY,
+    LATENCY_MAX,
+    "distribution of incr request latencies in nanoseconds"
+);
+heatmap!(
+    DECR_LATENCY,
+    LATENCY_MAX,
+    "distribution of decr request latencies in nanoseconds"
+);
+heatmap!(
+    MA_LATENCY,
+    LATENCY_MAX,
+    "distribution of meta arithmetic request latencies in nanoseconds"
+);
+heatmap!(
+    MG_LATENCY,
+    LATENCY_MAX,
+    "distribution of meta get request latencies in nanoseconds"
+);
+heatmap!(
+    MS_LATENCY,
+    LATENCY_MAX,
+    "distribution of meta set request latencies in nanoseconds"
+);
+heatmap!(
+    MD_LATENCY,
+    LATENCY_MAX,
+    "distribution of meta delete request latencies in nanoseconds"
+);
+heatmap!(
+    CAS_LATENCY,
+    LATENCY_MAX,
+    "distribution of cas request latencies in nanoseconds"
+);
+heatmap!(
+    TOUCH_LATENCY,
+    LATENCY_MAX,
+    "distribution of touch request latencies in nanoseconds"
+);
+
 common::metrics::test_no_duplicates!();
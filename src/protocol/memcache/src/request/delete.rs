@@ -11,6 +11,14 @@ pub struct Delete {
 }
 
 impl Delete {
+    /// Create a new `Delete` request.
+    pub fn new(key: &[u8], noreply: bool) -> Self {
+        Self {
+            key: key.to_vec().into_boxed_slice(),
+            noreply,
+        }
+    }
+
     pub fn key(&self) -> &[u8] {
         self.key.as_ref()
     }
@@ -0,0 +1,130 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Gats {
+    pub(crate) ttl: Ttl,
+    pub(crate) keys: Box<[Box<[u8]>]>,
+}
+
+impl Gats {
+    pub fn ttl(&self) -> Ttl {
+        self.ttl
+    }
+
+    pub fn keys(&self) -> &[Box<[u8]>] {
+        self.keys.as_ref()
+    }
+}
+
+impl RequestParser {
+    pub(crate) fn parse_gats<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], Gats> {
+        // we can use the gat parser here and convert the request
+        match self.parse_gat_no_stats(input) {
+            Ok((input, request)) => {
+                GATS.increment();
+                GATS_KEY.add(request.keys.len() as _);
+                Ok((
+                    input,
+                    Gats {
+                        ttl: request.ttl,
+                        keys: request.keys,
+                    },
+                ))
+            }
+            Err(e) => {
+                if !e.is_incomplete() {
+                    GATS.increment();
+                    GATS_EX.increment();
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Compose for Gats {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let verb = b"gats";
+        let ttl = format!(" {}", self.ttl.get().unwrap_or(0)).into_bytes();
+
+        let mut size = verb.len() + ttl.len() + CRLF.len();
+
+        session.put_slice(verb);
+        session.put_slice(&ttl);
+        for key in self.keys.iter() {
+            session.put_slice(b" ");
+            session.put_slice(key);
+            size += 1 + key.len();
+        }
+        session.put_slice(CRLF);
+
+        size
+    }
+}
+
+impl Klog for Gats {
+    type Response = Response;
+
+    fn klog(&self, response: &Self::Response) {
+        if let Response::Values(ref res) = response {
+            let mut hit_keys = 0;
+            let mut miss_keys = 0;
+
+            for value in res.values() {
+                if value.len().is_none() {
+                    miss_keys += 1;
+
+                    klog!(
+                        "\"gats {}\" {} 0",
+                        String::from_utf8_lossy(value.key()),
+                        MISS
+                    );
+                } else {
+                    hit_keys += 1;
+
+                    klog!(
+                        "\"gats {}\" {} {}",
+                        String::from_utf8_lossy(value.key()),
+                        HIT,
+                        value.len().unwrap(),
+                    );
+                }
+            }
+
+            GATS_KEY_HIT.add(hit_keys as _);
+            GATS_KEY_MISS.add(miss_keys as _);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let parser = RequestParser::new();
+
+        // test parsing a simple request
+        assert_eq!(
+            parser.parse_request(b"gats 60 key\r\n"),
+            Ok((
+                &b""[..],
+                Request::Gats(Gats {
+                    ttl: Ttl::new(60, TimeType::Memcache),
+                    keys: vec![b"key".to_vec().into_boxed_slice()].into_boxed_slice(),
+                })
+            ))
+        );
+
+        // command name is not case sensitive
+        assert_eq!(
+            parser.parse_request(b"gats 60 key\r\n"),
+            parser.parse_request(b"GATS 60 key\r\n"),
+        );
+    }
+}
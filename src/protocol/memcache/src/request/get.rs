@@ -10,6 +10,13 @@ pub struct Get {
 }
 
 impl Get {
+    /// Create a new `Get` request for the provided keys.
+    pub fn new(keys: &[&[u8]]) -> Self {
+        Self {
+            keys: keys.iter().map(|k| k.to_vec().into_boxed_slice()).collect(),
+        }
+    }
+
     pub fn keys(&self) -> &[Box<[u8]>] {
         self.keys.as_ref()
     }
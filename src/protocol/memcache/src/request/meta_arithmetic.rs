@@ -0,0 +1,384 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// The operation performed by a meta arithmetic request.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ArithmeticMode {
+    Incr,
+    Decr,
+}
+
+/// A meta arithmetic (`ma`) request. Unlike `incr` and `decr`, a missing key
+/// may be created with an initial value by passing the `N` flag.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MetaArithmetic {
+    pub(crate) key: Box<[u8]>,
+    pub(crate) base64: bool,
+    pub(crate) mode: ArithmeticMode,
+    pub(crate) delta: u64,
+    pub(crate) initial: u64,
+    pub(crate) autovivify: Option<u32>,
+    pub(crate) opaque: Option<Box<[u8]>>,
+    pub(crate) quiet: bool,
+    pub(crate) return_cas: bool,
+    pub(crate) return_key: bool,
+    pub(crate) return_value: bool,
+}
+
+impl MetaArithmetic {
+    pub fn key(&self) -> &[u8] {
+        self.key.as_ref()
+    }
+
+    pub fn mode(&self) -> ArithmeticMode {
+        self.mode
+    }
+
+    pub fn delta(&self) -> u64 {
+        self.delta
+    }
+
+    /// The value stored when the key is created by this request.
+    pub fn initial(&self) -> u64 {
+        self.initial
+    }
+
+    /// If set, a missing key is created with this TTL in seconds. A TTL of
+    /// zero means that the item does not expire.
+    pub fn autovivify(&self) -> Option<u32> {
+        self.autovivify
+    }
+
+    pub fn quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// The response for a request which updated or created the item.
+    pub fn hit(&self, value: u64, cas: u64) -> Response {
+        let mut flags = self.return_flags();
+        if self.return_cas {
+            push_flag(&mut flags, b'c', cas);
+        }
+
+        if self.return_value {
+            let mut buffer = itoa::Buffer::new();
+            let value = buffer.format(value);
+            Response::meta(MetaCode::Value, flags, Some(value.as_bytes()), self.quiet)
+        } else {
+            Response::meta(MetaCode::Header, flags, None, self.quiet)
+        }
+    }
+
+    /// The response for a request on a missing key without auto-create.
+    pub fn miss(&self) -> Response {
+        Response::meta(MetaCode::NotFound, self.return_flags(), None, self.quiet)
+    }
+
+    /// The response for a request which could not be applied.
+    pub fn not_stored(&self) -> Response {
+        Response::meta(MetaCode::NotStored, self.return_flags(), None, self.quiet)
+    }
+
+    fn return_flags(&self) -> Vec<u8> {
+        meta_return_flags(
+            &self.key,
+            self.base64,
+            self.opaque.as_deref(),
+            self.return_key,
+        )
+    }
+}
+
+impl RequestParser {
+    // this is to be called after parsing the command, so we do not match the verb
+    pub(crate) fn parse_meta_arithmetic_no_stats<'a>(
+        &self,
+        input: &'a [u8],
+    ) -> IResult<&'a [u8], MetaArithmetic> {
+        let (input, _) = space1(input)?;
+        let (input, key) = key(input, self.max_key_len)?;
+
+        let key = match key {
+            Some(k) => k,
+            None => {
+                return Err(nom::Err::Failure((input, nom::error::ErrorKind::Tag)));
+            }
+        };
+
+        let (input, flags) = take_till(|b| b == b'\r')(input)?;
+        let (input, _) = crlf(input)?;
+
+        let mut request = MetaArithmetic {
+            key: key.to_owned().into_boxed_slice(),
+            base64: false,
+            mode: ArithmeticMode::Incr,
+            delta: 1,
+            initial: 0,
+            autovivify: None,
+            opaque: None,
+            quiet: false,
+            return_cas: false,
+            return_key: false,
+            return_value: false,
+        };
+
+        let failure = || nom::Err::Failure((input, nom::error::ErrorKind::Tag));
+
+        for token in flags.split(|b| *b == b' ').filter(|t| !t.is_empty()) {
+            let (flag, arg) = (token[0], &token[1..]);
+            match flag {
+                b'b' if arg.is_empty() => request.base64 = true,
+                b'q' if arg.is_empty() => request.quiet = true,
+                b'c' if arg.is_empty() => request.return_cas = true,
+                b'k' if arg.is_empty() => request.return_key = true,
+                b'v' if arg.is_empty() => request.return_value = true,
+                b'D' => request.delta = flag_u64(arg).ok_or_else(failure)?,
+                b'J' => request.initial = flag_u64(arg).ok_or_else(failure)?,
+                b'N' => {
+                    let ttl = flag_u64(arg).ok_or_else(failure)?;
+                    request.autovivify = Some(u32::try_from(ttl).map_err(|_| failure())?);
+                }
+                b'M' => {
+                    request.mode = match arg {
+                        b"I" | b"i" | b"+" => ArithmeticMode::Incr,
+                        b"D" | b"d" | b"-" => ArithmeticMode::Decr,
+                        _ => {
+                            return Err(failure());
+                        }
+                    }
+                }
+                b'O' if !arg.is_empty() && arg.len() <= MAX_OPAQUE_LEN => {
+                    request.opaque = Some(arg.to_owned().into_boxed_slice());
+                }
+                _ => {
+                    return Err(failure());
+                }
+            }
+        }
+
+        if request.base64 {
+            request.key = decode_key(&request.key)
+                .ok_or_else(failure)?
+                .into_boxed_slice();
+        }
+
+        Ok((input, request))
+    }
+
+    pub fn parse_meta_arithmetic<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], MetaArithmetic> {
+        match self.parse_meta_arithmetic_no_stats(input) {
+            Ok((input, request)) => {
+                MA.increment();
+                Ok((input, request))
+            }
+            Err(e) => {
+                if !e.is_incomplete() {
+                    MA.increment();
+                    MA_EX.increment();
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Compose for MetaArithmetic {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let verb = b"ma ";
+        let key = if self.base64 {
+            encode_key(&self.key)
+        } else {
+            self.key.to_vec()
+        };
+
+        let mut flags = String::new();
+        if self.base64 {
+            flags.push_str(" b");
+        }
+        if self.mode == ArithmeticMode::Decr {
+            flags.push_str(" MD");
+        }
+        if self.delta != 1 {
+            flags.push_str(&format!(" D{}", self.delta));
+        }
+        if self.initial != 0 {
+            flags.push_str(&format!(" J{}", self.initial));
+        }
+        if let Some(ttl) = self.autovivify {
+            flags.push_str(&format!(" N{}", ttl));
+        }
+        if let Some(opaque) = &self.opaque {
+            flags.push_str(&format!(" O{}", String::from_utf8_lossy(opaque)));
+        }
+        if self.quiet {
+            flags.push_str(" q");
+        }
+        if self.return_cas {
+            flags.push_str(" c");
+        }
+        if self.return_key {
+            flags.push_str(" k");
+        }
+        if self.return_value {
+            flags.push_str(" v");
+        }
+        flags.push_str("\r\n");
+
+        let size = verb.len() + key.len() + flags.len();
+
+        session.put_slice(verb);
+        session.put_slice(&key);
+        session.put_slice(flags.as_bytes());
+
+        size
+    }
+}
+
+impl Klog for MetaArithmetic {
+    type Response = Response;
+
+    fn klog(&self, response: &Self::Response) {
+        let (code, len) = match response {
+            Response::Meta(ref res) => match res.code() {
+                MetaCode::Header | MetaCode::Value => {
+                    MA_STORED.increment();
+                    (STORED, res.len())
+                }
+                MetaCode::NotFound => {
+                    MA_NOT_FOUND.increment();
+                    (NOT_FOUND, res.len())
+                }
+                MetaCode::NotStored | MetaCode::Exists => {
+                    MA_NOT_STORED.increment();
+                    (NOT_STORED, res.len())
+                }
+                MetaCode::Miss | MetaCode::NoOp => {
+                    return;
+                }
+            },
+            _ => {
+                return;
+            }
+        };
+        klog!("\"ma {}\" {} {}", string_key(self.key()), code, len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(key: &[u8]) -> MetaArithmetic {
+        MetaArithmetic {
+            key: key.to_vec().into_boxed_slice(),
+            base64: false,
+            mode: ArithmeticMode::Incr,
+            delta: 1,
+            initial: 0,
+            autovivify: None,
+            opaque: None,
+            quiet: false,
+            return_cas: false,
+            return_key: false,
+            return_value: false,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let parser = RequestParser::new();
+
+        // basic command
+        assert_eq!(
+            parser.parse_request(b"ma 0\r\n"),
+            Ok((&b""[..], Request::MetaArithmetic(request(b"0"))))
+        );
+
+        // decrement with a delta, auto-create, and an initial value
+        let mut expected = request(b"0");
+        expected.mode = ArithmeticMode::Decr;
+        expected.delta = 5;
+        expected.initial = 100;
+        expected.autovivify = Some(60);
+        assert_eq!(
+            parser.parse_request(b"ma 0 MD D5 N60 J100\r\n"),
+            Ok((&b""[..], Request::MetaArithmetic(expected)))
+        );
+
+        // return flags
+        let mut expected = request(b"0");
+        expected.opaque = Some(b"123".to_vec().into_boxed_slice());
+        expected.quiet = true;
+        expected.return_cas = true;
+        expected.return_key = true;
+        expected.return_value = true;
+        assert_eq!(
+            parser.parse_request(b"ma 0 O123 q c k v\r\n"),
+            Ok((&b""[..], Request::MetaArithmetic(expected)))
+        );
+
+        // base64 encoded key
+        let mut expected = request(b"a b");
+        expected.base64 = true;
+        assert_eq!(
+            parser.parse_request(b"ma YSBi b\r\n"),
+            Ok((&b""[..], Request::MetaArithmetic(expected)))
+        );
+
+        // trailing space doesn't matter
+        assert_eq!(
+            parser.parse_request(b"ma 0 MI\r\n"),
+            parser.parse_request(b"ma 0 MI \r\n"),
+        );
+
+        // unknown flags, bad arguments, and bad keys are rejected
+        assert!(parser.parse_request(b"ma 0 Z\r\n").is_err());
+        assert!(parser.parse_request(b"ma 0 Mx\r\n").is_err());
+        assert!(parser.parse_request(b"ma 0 D-1\r\n").is_err());
+        assert!(parser.parse_request(b"ma 0 N4294967296\r\n").is_err());
+        assert!(parser.parse_request(b"ma YSB b\r\n").is_err());
+        assert!(parser.parse_request(b"ma\r\n").is_err());
+    }
+
+    #[test]
+    fn respond() {
+        let mut request = request(b"a b");
+        let mut buf = Vec::new();
+        request.hit(42, 7).compose(&mut buf);
+        assert_eq!(&buf[..], b"HD\r\n");
+
+        request.base64 = true;
+        request.opaque = Some(b"xyz".to_vec().into_boxed_slice());
+        request.return_cas = true;
+        request.return_key = true;
+        request.return_value = true;
+
+        let mut buf = Vec::new();
+        request.hit(42, 7).compose(&mut buf);
+        assert_eq!(&buf[..], b"VA 2 Oxyz kYSBi b c7\r\n42\r\n");
+
+        let mut buf = Vec::new();
+        request.miss().compose(&mut buf);
+        assert_eq!(&buf[..], b"NF Oxyz kYSBi b\r\n");
+    }
+
+    #[test]
+    fn compose() {
+        let parser = RequestParser::new();
+        let mut buf = Vec::new();
+        let mut request = request(b"a b");
+        request.base64 = true;
+        request.mode = ArithmeticMode::Decr;
+        request.autovivify = Some(0);
+        request.initial = 3;
+        request.compose(&mut buf);
+        assert_eq!(&buf[..], b"ma YSBi b MD J3 N0\r\n");
+        assert_eq!(
+            parser.parse_request(&buf),
+            Ok((&b""[..], Request::MetaArithmetic(request)))
+        );
+    }
+}
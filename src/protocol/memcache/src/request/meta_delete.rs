@@ -0,0 +1,266 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// A meta delete (`md`) request. With the `C` flag the item is only removed
+/// if its cas value matches.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MetaDelete {
+    pub(crate) key: Box<[u8]>,
+    pub(crate) base64: bool,
+    pub(crate) cas: Option<u64>,
+    pub(crate) opaque: Option<Box<[u8]>>,
+    pub(crate) quiet: bool,
+    pub(crate) return_key: bool,
+}
+
+impl MetaDelete {
+    pub fn key(&self) -> &[u8] {
+        self.key.as_ref()
+    }
+
+    /// If set, the item is only removed if its cas value matches this.
+    pub fn cas(&self) -> Option<u64> {
+        self.cas
+    }
+
+    /// The response for a request which removed the item.
+    pub fn deleted(&self) -> Response {
+        Response::meta(MetaCode::Header, self.return_flags(), None, self.quiet)
+    }
+
+    /// The response for a request on a missing key.
+    pub fn not_found(&self) -> Response {
+        Response::meta(MetaCode::NotFound, self.return_flags(), None, self.quiet)
+    }
+
+    /// The response for a request whose cas value did not match.
+    pub fn exists(&self) -> Response {
+        Response::meta(MetaCode::Exists, self.return_flags(), None, self.quiet)
+    }
+
+    fn return_flags(&self) -> Vec<u8> {
+        meta_return_flags(
+            &self.key,
+            self.base64,
+            self.opaque.as_deref(),
+            self.return_key,
+        )
+    }
+}
+
+impl RequestParser {
+    // this is to be called after parsing the command, so we do not match the verb
+    pub(crate) fn parse_meta_delete_no_stats<'a>(
+        &self,
+        input: &'a [u8],
+    ) -> IResult<&'a [u8], MetaDelete> {
+        let (input, _) = space1(input)?;
+        let (input, key) = key(input, self.max_key_len)?;
+
+        let key = match key {
+            Some(k) => k,
+            None => {
+                return Err(nom::Err::Failure((input, nom::error::ErrorKind::Tag)));
+            }
+        };
+
+        let (input, flags) = take_till(|b| b == b'\r')(input)?;
+        let (input, _) = crlf(input)?;
+
+        let mut request = MetaDelete {
+            key: key.to_owned().into_boxed_slice(),
+            base64: false,
+            cas: None,
+            opaque: None,
+            quiet: false,
+            return_key: false,
+        };
+
+        let failure = || nom::Err::Failure((input, nom::error::ErrorKind::Tag));
+
+        for token in flags.split(|b| *b == b' ').filter(|t| !t.is_empty()) {
+            let (flag, arg) = (token[0], &token[1..]);
+            match flag {
+                b'b' if arg.is_empty() => request.base64 = true,
+                b'q' if arg.is_empty() => request.quiet = true,
+                b'k' if arg.is_empty() => request.return_key = true,
+                b'C' => request.cas = Some(flag_u64(arg).ok_or_else(failure)?),
+                b'O' if !arg.is_empty() && arg.len() <= MAX_OPAQUE_LEN => {
+                    request.opaque = Some(arg.to_owned().into_boxed_slice());
+                }
+                _ => {
+                    return Err(failure());
+                }
+            }
+        }
+
+        if request.base64 {
+            request.key = decode_key(&request.key)
+                .ok_or_else(failure)?
+                .into_boxed_slice();
+        }
+
+        Ok((input, request))
+    }
+
+    pub fn parse_meta_delete<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], MetaDelete> {
+        match self.parse_meta_delete_no_stats(input) {
+            Ok((input, request)) => {
+                MD.increment();
+                Ok((input, request))
+            }
+            Err(e) => {
+                if !e.is_incomplete() {
+                    MD.increment();
+                    MD_EX.increment();
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Compose for MetaDelete {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let verb = b"md ";
+        let key = if self.base64 {
+            encode_key(&self.key)
+        } else {
+            self.key.to_vec()
+        };
+
+        let mut flags = String::new();
+        if self.base64 {
+            flags.push_str(" b");
+        }
+        if let Some(cas) = self.cas {
+            flags.push_str(&format!(" C{}", cas));
+        }
+        if let Some(opaque) = &self.opaque {
+            flags.push_str(&format!(" O{}", String::from_utf8_lossy(opaque)));
+        }
+        if self.quiet {
+            flags.push_str(" q");
+        }
+        if self.return_key {
+            flags.push_str(" k");
+        }
+        flags.push_str("\r\n");
+
+        let size = verb.len() + key.len() + flags.len();
+
+        session.put_slice(verb);
+        session.put_slice(&key);
+        session.put_slice(flags.as_bytes());
+
+        size
+    }
+}
+
+impl Klog for MetaDelete {
+    type Response = Response;
+
+    fn klog(&self, response: &Self::Response) {
+        let (code, len) = match response {
+            Response::Meta(ref res) => match res.code() {
+                MetaCode::Header => {
+                    MD_DELETED.increment();
+                    (DELETED, res.len())
+                }
+                MetaCode::NotFound => {
+                    MD_NOT_FOUND.increment();
+                    (NOT_FOUND, res.len())
+                }
+                MetaCode::Exists => {
+                    MD_EXISTS.increment();
+                    (EXISTS, res.len())
+                }
+                _ => {
+                    return;
+                }
+            },
+            _ => {
+                return;
+            }
+        };
+        klog!("\"md {}\" {} {}", string_key(self.key()), code, len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(key: &[u8]) -> MetaDelete {
+        MetaDelete {
+            key: key.to_vec().into_boxed_slice(),
+            base64: false,
+            cas: None,
+            opaque: None,
+            quiet: false,
+            return_key: false,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let parser = RequestParser::new();
+
+        // basic command
+        assert_eq!(
+            parser.parse_request(b"md 0\r\n"),
+            Ok((&b""[..], Request::MetaDelete(request(b"0"))))
+        );
+
+        let mut expected = request(b"0");
+        expected.cas = Some(7);
+        expected.opaque = Some(b"1".to_vec().into_boxed_slice());
+        expected.quiet = true;
+        expected.return_key = true;
+        assert_eq!(
+            parser.parse_request(b"md 0 C7 O1 q k\r\n"),
+            Ok((&b""[..], Request::MetaDelete(expected)))
+        );
+
+        // unknown flags and bad arguments are rejected
+        assert!(parser.parse_request(b"md 0 I\r\n").is_err());
+        assert!(parser.parse_request(b"md 0 C\r\n").is_err());
+        assert!(parser.parse_request(b"md\r\n").is_err());
+    }
+
+    #[test]
+    fn respond() {
+        let mut request = request(b"0");
+        request.return_key = true;
+        let mut buf = Vec::new();
+        request.deleted().compose(&mut buf);
+        assert_eq!(&buf[..], b"HD k0\r\n");
+
+        // quiet mode hides success and misses
+        request.quiet = true;
+        let mut buf = Vec::new();
+        request.deleted().compose(&mut buf);
+        request.not_found().compose(&mut buf);
+        assert!(buf.is_empty());
+        request.exists().compose(&mut buf);
+        assert_eq!(&buf[..], b"EX k0\r\n");
+    }
+
+    #[test]
+    fn compose() {
+        let parser = RequestParser::new();
+        let mut buf = Vec::new();
+        let mut request = request(b"a b");
+        request.base64 = true;
+        request.cas = Some(3);
+        request.compose(&mut buf);
+        assert_eq!(&buf[..], b"md YSBi b C3\r\n");
+        assert_eq!(
+            parser.parse_request(&buf),
+            Ok((&b""[..], Request::MetaDelete(request)))
+        );
+    }
+}
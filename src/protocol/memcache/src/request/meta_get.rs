@@ -0,0 +1,407 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// A meta get (`mg`) request. Only the parts of the item which are asked for
+/// by the flags are returned, and the `T` flag updates the TTL of the item as
+/// for `touch`.
+///
+/// The flags which depend on state that is not kept for each item are out of
+/// scope, and requests which use them are rejected rather than answered as if
+/// the flag was not set. These are `l` (the time since the item was last
+/// read), and the recache flags `N` and `R`. Those need each item to record
+/// whether a client has already been told to recache it, so that only one is
+/// given the `W` flag and the others the `Z` flag, and segments do not keep
+/// that state. Clients which rely on them should use `add` to elect the one
+/// which recaches an item.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MetaGet {
+    pub(crate) key: Box<[u8]>,
+    pub(crate) base64: bool,
+    pub(crate) opaque: Option<Box<[u8]>>,
+    pub(crate) quiet: bool,
+    pub(crate) return_cas: bool,
+    pub(crate) return_hit: bool,
+    pub(crate) return_flags: bool,
+    pub(crate) return_key: bool,
+    pub(crate) return_size: bool,
+    pub(crate) return_ttl: bool,
+    pub(crate) return_value: bool,
+    pub(crate) ttl: Option<Ttl>,
+}
+
+impl MetaGet {
+    pub fn key(&self) -> &[u8] {
+        self.key.as_ref()
+    }
+
+    /// If set, the TTL of the item is changed to this.
+    pub fn ttl(&self) -> Option<Ttl> {
+        self.ttl
+    }
+
+    /// Whether the response should say if the item had been read before this
+    /// request, which is asked for by the `h` flag.
+    pub fn return_hit(&self) -> bool {
+        self.return_hit
+    }
+
+    /// The response for a request which found the item. The remaining TTL is
+    /// in seconds, and is `None` for an item which does not expire, which is
+    /// returned as `-1`. A stale item is one which has expired but is still
+    /// served, which is marked by the `X` flag. Whether the item had been read
+    /// before this request is only returned if asked for.
+    pub fn hit(
+        &self,
+        value: &[u8],
+        flags: u32,
+        cas: u64,
+        ttl: Option<u64>,
+        stale: bool,
+        accessed: bool,
+    ) -> Response {
+        let mut return_flags = self.return_flags();
+        if self.return_cas {
+            push_flag(&mut return_flags, b'c', cas);
+        }
+        if self.return_flags {
+            push_flag(&mut return_flags, b'f', flags);
+        }
+        if self.return_size {
+            push_flag(&mut return_flags, b's', value.len());
+        }
+        if self.return_ttl {
+            match ttl {
+                Some(ttl) => push_flag(&mut return_flags, b't', ttl),
+                None => return_flags.extend_from_slice(b" t-1"),
+            }
+        }
+        if self.return_hit {
+            return_flags.extend_from_slice(if accessed { b" h1" } else { b" h0" });
+        }
+        if stale {
+            return_flags.extend_from_slice(b" X");
+        }
+
+        // quiet mode only hides misses
+        if self.return_value {
+            Response::meta(MetaCode::Value, return_flags, Some(value), false)
+        } else {
+            Response::meta(MetaCode::Header, return_flags, None, false)
+        }
+    }
+
+    /// The response for a request on a missing key.
+    pub fn miss(&self) -> Response {
+        Response::meta(MetaCode::Miss, self.return_flags(), None, self.quiet)
+    }
+
+    fn return_flags(&self) -> Vec<u8> {
+        meta_return_flags(
+            &self.key,
+            self.base64,
+            self.opaque.as_deref(),
+            self.return_key,
+        )
+    }
+}
+
+impl RequestParser {
+    // this is to be called after parsing the command, so we do not match the verb
+    pub(crate) fn parse_meta_get_no_stats<'a>(
+        &self,
+        input: &'a [u8],
+    ) -> IResult<&'a [u8], MetaGet> {
+        let (input, _) = space1(input)?;
+        let (input, key) = key(input, self.max_key_len)?;
+
+        let key = match key {
+            Some(k) => k,
+            None => {
+                return Err(nom::Err::Failure((input, nom::error::ErrorKind::Tag)));
+            }
+        };
+
+        let (input, flags) = take_till(|b| b == b'\r')(input)?;
+        let (input, _) = crlf(input)?;
+
+        let mut request = MetaGet {
+            key: key.to_owned().into_boxed_slice(),
+            base64: false,
+            opaque: None,
+            quiet: false,
+            return_cas: false,
+            return_hit: false,
+            return_flags: false,
+            return_key: false,
+            return_size: false,
+            return_ttl: false,
+            return_value: false,
+            ttl: None,
+        };
+
+        let failure = || nom::Err::Failure((input, nom::error::ErrorKind::Tag));
+
+        for token in flags.split(|b| *b == b' ').filter(|t| !t.is_empty()) {
+            let (flag, arg) = (token[0], &token[1..]);
+            match flag {
+                b'b' if arg.is_empty() => request.base64 = true,
+                b'q' if arg.is_empty() => request.quiet = true,
+                b'c' if arg.is_empty() => request.return_cas = true,
+                b'f' if arg.is_empty() => request.return_flags = true,
+                b'h' if arg.is_empty() => request.return_hit = true,
+                b'k' if arg.is_empty() => request.return_key = true,
+                b's' if arg.is_empty() => request.return_size = true,
+                b't' if arg.is_empty() => request.return_ttl = true,
+                b'v' if arg.is_empty() => request.return_value = true,
+                b'T' => request.ttl = Some(flag_ttl(arg, self.time_type).ok_or_else(failure)?),
+                b'O' if !arg.is_empty() && arg.len() <= MAX_OPAQUE_LEN => {
+                    request.opaque = Some(arg.to_owned().into_boxed_slice());
+                }
+                // not supported, see the documentation of `MetaGet`
+                b'l' | b'N' | b'R' => {
+                    return Err(failure());
+                }
+                _ => {
+                    return Err(failure());
+                }
+            }
+        }
+
+        if request.base64 {
+            request.key = decode_key(&request.key)
+                .ok_or_else(failure)?
+                .into_boxed_slice();
+        }
+
+        Ok((input, request))
+    }
+
+    pub fn parse_meta_get<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], MetaGet> {
+        match self.parse_meta_get_no_stats(input) {
+            Ok((input, request)) => {
+                MG.increment();
+                Ok((input, request))
+            }
+            Err(e) => {
+                if !e.is_incomplete() {
+                    MG.increment();
+                    MG_EX.increment();
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Compose for MetaGet {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let verb = b"mg ";
+        let key = if self.base64 {
+            encode_key(&self.key)
+        } else {
+            self.key.to_vec()
+        };
+
+        let mut flags = String::new();
+        if self.base64 {
+            flags.push_str(" b");
+        }
+        if let Some(ttl) = self.ttl {
+            flags.push_str(&format!(" T{}", ttl.get().unwrap_or(0)));
+        }
+        if let Some(opaque) = &self.opaque {
+            flags.push_str(&format!(" O{}", String::from_utf8_lossy(opaque)));
+        }
+        if self.quiet {
+            flags.push_str(" q");
+        }
+        if self.return_cas {
+            flags.push_str(" c");
+        }
+        if self.return_flags {
+            flags.push_str(" f");
+        }
+        if self.return_hit {
+            flags.push_str(" h");
+        }
+        if self.return_key {
+            flags.push_str(" k");
+        }
+        if self.return_size {
+            flags.push_str(" s");
+        }
+        if self.return_ttl {
+            flags.push_str(" t");
+        }
+        if self.return_value {
+            flags.push_str(" v");
+        }
+        flags.push_str("\r\n");
+
+        let size = verb.len() + key.len() + flags.len();
+
+        session.put_slice(verb);
+        session.put_slice(&key);
+        session.put_slice(flags.as_bytes());
+
+        size
+    }
+}
+
+impl Klog for MetaGet {
+    type Response = Response;
+
+    fn klog(&self, response: &Self::Response) {
+        let (code, len) = match response {
+            Response::Meta(ref res) => match res.code() {
+                MetaCode::Header | MetaCode::Value => {
+                    MG_HIT.increment();
+                    (HIT, res.value().map(|v| v.len()).unwrap_or(0))
+                }
+                MetaCode::Miss => {
+                    MG_MISS.increment();
+                    (MISS, 0)
+                }
+                _ => {
+                    return;
+                }
+            },
+            _ => {
+                return;
+            }
+        };
+        klog!("\"mg {}\" {} {}", string_key(self.key()), code, len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(key: &[u8]) -> MetaGet {
+        MetaGet {
+            key: key.to_vec().into_boxed_slice(),
+            base64: false,
+            opaque: None,
+            quiet: false,
+            return_cas: false,
+            return_hit: false,
+            return_flags: false,
+            return_key: false,
+            return_size: false,
+            return_ttl: false,
+            return_value: false,
+            ttl: None,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let parser = RequestParser::new();
+
+        // basic command
+        assert_eq!(
+            parser.parse_request(b"mg 0\r\n"),
+            Ok((&b""[..], Request::MetaGet(request(b"0"))))
+        );
+
+        // return flags and a touch
+        let mut expected = request(b"0");
+        expected.opaque = Some(b"123".to_vec().into_boxed_slice());
+        expected.quiet = true;
+        expected.return_cas = true;
+        expected.return_flags = true;
+        expected.return_hit = true;
+        expected.return_key = true;
+        expected.return_size = true;
+        expected.return_ttl = true;
+        expected.return_value = true;
+        expected.ttl = Some(Ttl::new(60, TimeType::Memcache));
+        assert_eq!(
+            parser.parse_request(b"mg 0 v t s k h f c q O123 T60\r\n"),
+            Ok((&b""[..], Request::MetaGet(expected)))
+        );
+
+        // base64 encoded key
+        let mut expected = request(b"a b");
+        expected.base64 = true;
+        assert_eq!(
+            parser.parse_request(b"mg YSBi b\r\n"),
+            Ok((&b""[..], Request::MetaGet(expected)))
+        );
+
+        // unknown flags, bad arguments, and missing keys are rejected
+        assert!(parser.parse_request(b"mg 0 Z\r\n").is_err());
+        assert!(parser.parse_request(b"mg 0 h1\r\n").is_err());
+        assert!(parser.parse_request(b"mg 0 Tx\r\n").is_err());
+        assert!(parser.parse_request(b"mg 0 v1\r\n").is_err());
+        assert!(parser.parse_request(b"mg\r\n").is_err());
+
+        // as are the flags which are not supported
+        assert!(parser.parse_request(b"mg 0 l\r\n").is_err());
+        assert!(parser.parse_request(b"mg 0 N30\r\n").is_err());
+        assert!(parser.parse_request(b"mg 0 R30\r\n").is_err());
+    }
+
+    #[test]
+    fn respond() {
+        let mut request = request(b"0");
+        let mut buf = Vec::new();
+        request
+            .hit(b"abc", 1, 7, Some(60), false, false)
+            .compose(&mut buf);
+        assert_eq!(&buf[..], b"HD\r\n");
+
+        request.opaque = Some(b"xyz".to_vec().into_boxed_slice());
+        request.quiet = true;
+        request.return_cas = true;
+        request.return_flags = true;
+        request.return_size = true;
+        request.return_ttl = true;
+        request.return_value = true;
+
+        // stale items are marked, and quiet mode does not hide hits
+        let mut buf = Vec::new();
+        request
+            .hit(b"abc", 1, 7, Some(0), true, false)
+            .compose(&mut buf);
+        assert_eq!(&buf[..], b"VA 3 Oxyz c7 f1 s3 t0 X\r\nabc\r\n");
+
+        // items which do not expire have a TTL of -1
+        request.return_value = false;
+        request.return_hit = true;
+        let mut buf = Vec::new();
+        request
+            .hit(b"abc", 1, 7, None, false, true)
+            .compose(&mut buf);
+        assert_eq!(&buf[..], b"HD Oxyz c7 f1 s3 t-1 h1\r\n");
+
+        // but it does hide misses
+        let mut buf = Vec::new();
+        request.miss().compose(&mut buf);
+        assert!(buf.is_empty());
+
+        request.quiet = false;
+        request.miss().compose(&mut buf);
+        assert_eq!(&buf[..], b"EN Oxyz\r\n");
+    }
+
+    #[test]
+    fn compose() {
+        let parser = RequestParser::new();
+        let mut buf = Vec::new();
+        let mut request = request(b"a b");
+        request.base64 = true;
+        request.ttl = Some(Ttl::new(30, TimeType::Memcache));
+        request.return_value = true;
+        request.compose(&mut buf);
+        assert_eq!(&buf[..], b"mg YSBi b T30 v\r\n");
+        assert_eq!(
+            parser.parse_request(&buf),
+            Ok((&b""[..], Request::MetaGet(request)))
+        );
+    }
+}
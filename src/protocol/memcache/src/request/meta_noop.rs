@@ -0,0 +1,61 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// A meta no-op (`mn`) request. Clients send it after a pipeline of quiet
+/// requests, whose successes are not answered, so that the reply to it shows
+/// that all of them were processed.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MetaNoop {}
+
+impl MetaNoop {
+    pub fn response(&self) -> Response {
+        Response::meta(MetaCode::NoOp, Vec::new(), None, false)
+    }
+}
+
+impl RequestParser {
+    // this is to be called after parsing the command, so we do not match the verb
+    pub fn parse_meta_noop<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], MetaNoop> {
+        let (input, _) = space0(input)?;
+        let (input, _) = crlf(input)?;
+
+        MN.increment();
+
+        Ok((input, MetaNoop {}))
+    }
+}
+
+impl Compose for MetaNoop {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        session.put_slice(b"mn\r\n");
+        4
+    }
+}
+
+impl Klog for MetaNoop {
+    type Response = Response;
+
+    fn klog(&self, _response: &Self::Response) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let parser = RequestParser::new();
+
+        assert_eq!(
+            parser.parse_request(b"mn\r\n"),
+            Ok((&b""[..], Request::MetaNoop(MetaNoop {})))
+        );
+
+        let mut buf = Vec::new();
+        MetaNoop {}.response().compose(&mut buf);
+        assert_eq!(&buf[..], b"MN\r\n");
+    }
+}
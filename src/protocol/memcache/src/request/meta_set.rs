@@ -0,0 +1,419 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// The operation performed by a meta set request, which matches one of the
+/// classic storage commands.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SetMode {
+    Set,
+    Add,
+    Replace,
+    Append,
+    Prepend,
+}
+
+/// A meta set (`ms`) request. The flags, TTL, compare-and-swap value, and the
+/// kind of write are all given by flags.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MetaSet {
+    pub(crate) key: Box<[u8]>,
+    pub(crate) value: Box<[u8]>,
+    pub(crate) base64: bool,
+    pub(crate) cas: Option<u64>,
+    pub(crate) flags: u32,
+    pub(crate) ttl: Ttl,
+    pub(crate) mode: SetMode,
+    pub(crate) opaque: Option<Box<[u8]>>,
+    pub(crate) quiet: bool,
+    pub(crate) return_cas: bool,
+    pub(crate) return_key: bool,
+}
+
+impl MetaSet {
+    pub fn key(&self) -> &[u8] {
+        self.key.as_ref()
+    }
+
+    pub fn value(&self) -> &[u8] {
+        self.value.as_ref()
+    }
+
+    /// If set, the item is only stored if its cas value matches this.
+    pub fn cas(&self) -> Option<u64> {
+        self.cas
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub fn ttl(&self) -> Ttl {
+        self.ttl
+    }
+
+    pub fn mode(&self) -> SetMode {
+        self.mode
+    }
+
+    /// Returns true if the response should include the cas value of the
+    /// stored item.
+    pub fn return_cas(&self) -> bool {
+        self.return_cas
+    }
+
+    /// The response for a request which stored the item.
+    pub fn stored(&self, cas: Option<u64>) -> Response {
+        let mut flags = self.return_flags();
+        if let Some(cas) = cas.filter(|_| self.return_cas) {
+            push_flag(&mut flags, b'c', cas);
+        }
+        Response::meta(MetaCode::Header, flags, None, self.quiet)
+    }
+
+    /// The response for a request which was not applied, such as an add of an
+    /// existing key.
+    pub fn not_stored(&self) -> Response {
+        Response::meta(MetaCode::NotStored, self.return_flags(), None, false)
+    }
+
+    /// The response for a request whose cas value did not match.
+    pub fn exists(&self) -> Response {
+        Response::meta(MetaCode::Exists, self.return_flags(), None, false)
+    }
+
+    /// The response for a compare-and-swap of a missing key.
+    pub fn not_found(&self) -> Response {
+        Response::meta(MetaCode::NotFound, self.return_flags(), None, false)
+    }
+
+    fn return_flags(&self) -> Vec<u8> {
+        meta_return_flags(
+            &self.key,
+            self.base64,
+            self.opaque.as_deref(),
+            self.return_key,
+        )
+    }
+}
+
+impl RequestParser {
+    // this is to be called after parsing the command, so we do not match the verb
+    pub(crate) fn parse_meta_set_no_stats<'a>(
+        &self,
+        input: &'a [u8],
+    ) -> IResult<&'a [u8], MetaSet> {
+        let (input, _) = space1(input)?;
+        let (input, key) = key(input, self.max_key_len)?;
+
+        let key = match key {
+            Some(k) => k,
+            None => {
+                return Err(nom::Err::Failure((input, nom::error::ErrorKind::Tag)));
+            }
+        };
+
+        let (input, _) = space1(input)?;
+        let (input, bytes) = parse_usize(input)?;
+
+        if bytes > self.max_value_size {
+            return Err(nom::Err::Failure((input, nom::error::ErrorKind::Tag)));
+        }
+
+        let (input, flags) = take_till(|b| b == b'\r')(input)?;
+        let (input, _) = crlf(input)?;
+        let (input, value) = take(bytes)(input)?;
+        let (input, _) = crlf(input)?;
+
+        let mut request = MetaSet {
+            key: key.to_owned().into_boxed_slice(),
+            value: value.to_owned().into_boxed_slice(),
+            base64: false,
+            cas: None,
+            flags: 0,
+            ttl: Ttl::none(),
+            mode: SetMode::Set,
+            opaque: None,
+            quiet: false,
+            return_cas: false,
+            return_key: false,
+        };
+
+        let failure = || nom::Err::Failure((input, nom::error::ErrorKind::Tag));
+
+        for token in flags.split(|b| *b == b' ').filter(|t| !t.is_empty()) {
+            let (flag, arg) = (token[0], &token[1..]);
+            match flag {
+                b'b' if arg.is_empty() => request.base64 = true,
+                b'q' if arg.is_empty() => request.quiet = true,
+                b'c' if arg.is_empty() => request.return_cas = true,
+                b'k' if arg.is_empty() => request.return_key = true,
+                b'C' => request.cas = Some(flag_u64(arg).ok_or_else(failure)?),
+                b'F' => {
+                    let flags = flag_u64(arg).ok_or_else(failure)?;
+                    request.flags = u32::try_from(flags).map_err(|_| failure())?;
+                }
+                b'T' => request.ttl = flag_ttl(arg, self.time_type).ok_or_else(failure)?,
+                b'M' => {
+                    request.mode = match arg {
+                        b"S" | b"s" => SetMode::Set,
+                        b"E" | b"e" => SetMode::Add,
+                        b"R" | b"r" => SetMode::Replace,
+                        b"A" | b"a" => SetMode::Append,
+                        b"P" | b"p" => SetMode::Prepend,
+                        _ => {
+                            return Err(failure());
+                        }
+                    }
+                }
+                b'O' if !arg.is_empty() && arg.len() <= MAX_OPAQUE_LEN => {
+                    request.opaque = Some(arg.to_owned().into_boxed_slice());
+                }
+                _ => {
+                    return Err(failure());
+                }
+            }
+        }
+
+        // a compare-and-swap only replaces the whole item
+        if request.cas.is_some() && request.mode != SetMode::Set {
+            return Err(failure());
+        }
+
+        if request.base64 {
+            request.key = decode_key(&request.key)
+                .ok_or_else(failure)?
+                .into_boxed_slice();
+        }
+
+        Ok((input, request))
+    }
+
+    pub fn parse_meta_set<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], MetaSet> {
+        match self.parse_meta_set_no_stats(input) {
+            Ok((input, request)) => {
+                MS.increment();
+                Ok((input, request))
+            }
+            Err(e) => {
+                if !e.is_incomplete() {
+                    MS.increment();
+                    MS_EX.increment();
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Compose for MetaSet {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let verb = b"ms ";
+        let key = if self.base64 {
+            encode_key(&self.key)
+        } else {
+            self.key.to_vec()
+        };
+
+        let mut flags = format!(" {}", self.value.len());
+        if self.base64 {
+            flags.push_str(" b");
+        }
+        if let Some(cas) = self.cas {
+            flags.push_str(&format!(" C{}", cas));
+        }
+        if self.flags != 0 {
+            flags.push_str(&format!(" F{}", self.flags));
+        }
+        if let Some(ttl) = self.ttl.get() {
+            flags.push_str(&format!(" T{}", ttl));
+        }
+        match self.mode {
+            SetMode::Set => {}
+            SetMode::Add => flags.push_str(" ME"),
+            SetMode::Replace => flags.push_str(" MR"),
+            SetMode::Append => flags.push_str(" MA"),
+            SetMode::Prepend => flags.push_str(" MP"),
+        }
+        if let Some(opaque) = &self.opaque {
+            flags.push_str(&format!(" O{}", String::from_utf8_lossy(opaque)));
+        }
+        if self.quiet {
+            flags.push_str(" q");
+        }
+        if self.return_cas {
+            flags.push_str(" c");
+        }
+        if self.return_key {
+            flags.push_str(" k");
+        }
+        flags.push_str("\r\n");
+
+        let size = verb.len() + key.len() + flags.len() + self.value.len() + CRLF.len();
+
+        session.put_slice(verb);
+        session.put_slice(&key);
+        session.put_slice(flags.as_bytes());
+        session.put_slice(&self.value);
+        session.put_slice(CRLF);
+
+        size
+    }
+}
+
+impl Klog for MetaSet {
+    type Response = Response;
+
+    fn klog(&self, response: &Self::Response) {
+        let (code, len) = match response {
+            Response::Meta(ref res) => match res.code() {
+                MetaCode::Header => {
+                    MS_STORED.increment();
+                    (STORED, res.len())
+                }
+                MetaCode::NotStored => {
+                    MS_NOT_STORED.increment();
+                    (NOT_STORED, res.len())
+                }
+                MetaCode::Exists => {
+                    MS_EXISTS.increment();
+                    (EXISTS, res.len())
+                }
+                MetaCode::NotFound => {
+                    MS_NOT_FOUND.increment();
+                    (NOT_FOUND, res.len())
+                }
+                _ => {
+                    return;
+                }
+            },
+            _ => {
+                return;
+            }
+        };
+        klog!(
+            "\"ms {} {}\" {} {}",
+            string_key(self.key()),
+            self.value().len(),
+            code,
+            len
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(key: &[u8], value: &[u8]) -> MetaSet {
+        MetaSet {
+            key: key.to_vec().into_boxed_slice(),
+            value: value.to_vec().into_boxed_slice(),
+            base64: false,
+            cas: None,
+            flags: 0,
+            ttl: Ttl::none(),
+            mode: SetMode::Set,
+            opaque: None,
+            quiet: false,
+            return_cas: false,
+            return_key: false,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let parser = RequestParser::new();
+
+        // basic command
+        assert_eq!(
+            parser.parse_request(b"ms 0 1\r\n1\r\n"),
+            Ok((&b""[..], Request::MetaSet(request(b"0", b"1"))))
+        );
+
+        // flags, ttl, and mode
+        let mut expected = request(b"0", b"hello");
+        expected.flags = 42;
+        expected.ttl = Ttl::new(60, TimeType::Memcache);
+        expected.mode = SetMode::Add;
+        expected.opaque = Some(b"1".to_vec().into_boxed_slice());
+        expected.quiet = true;
+        expected.return_cas = true;
+        expected.return_key = true;
+        assert_eq!(
+            parser.parse_request(b"ms 0 5 F42 T60 ME O1 q c k\r\nhello\r\n"),
+            Ok((&b""[..], Request::MetaSet(expected)))
+        );
+
+        // compare-and-swap
+        let mut expected = request(b"0", b"1");
+        expected.cas = Some(7);
+        assert_eq!(
+            parser.parse_request(b"ms 0 1 C7\r\n1\r\n"),
+            Ok((&b""[..], Request::MetaSet(expected)))
+        );
+
+        // the value is binary safe
+        assert_eq!(
+            parser.parse_request(b"ms 0 4\r\n\r\n\r\n\r\n"),
+            Ok((&b""[..], Request::MetaSet(request(b"0", b"\r\n\r\n"))))
+        );
+
+        // incomplete values wait for more data
+        assert!(matches!(
+            parser.parse_request(b"ms 0 5\r\nhel"),
+            Err(nom::Err::Incomplete(_))
+        ));
+
+        // unknown flags, bad arguments, and compare-and-swap with another
+        // mode are rejected
+        assert!(parser.parse_request(b"ms 0 1 Z\r\n1\r\n").is_err());
+        assert!(parser.parse_request(b"ms 0 1 MX\r\n1\r\n").is_err());
+        assert!(parser
+            .parse_request(b"ms 0 1 F4294967296\r\n1\r\n")
+            .is_err());
+        assert!(parser.parse_request(b"ms 0 1 C7 MA\r\n1\r\n").is_err());
+        assert!(parser.parse_request(b"ms 0 x\r\n1\r\n").is_err());
+    }
+
+    #[test]
+    fn respond() {
+        let mut request = request(b"0", b"1");
+        let mut buf = Vec::new();
+        request.stored(Some(7)).compose(&mut buf);
+        assert_eq!(&buf[..], b"HD\r\n");
+
+        request.return_cas = true;
+        request.return_key = true;
+        let mut buf = Vec::new();
+        request.stored(Some(7)).compose(&mut buf);
+        assert_eq!(&buf[..], b"HD k0 c7\r\n");
+
+        // quiet mode only hides success
+        request.quiet = true;
+        let mut buf = Vec::new();
+        request.stored(Some(7)).compose(&mut buf);
+        assert!(buf.is_empty());
+        request.not_found().compose(&mut buf);
+        assert_eq!(&buf[..], b"NF k0\r\n");
+    }
+
+    #[test]
+    fn compose() {
+        let parser = RequestParser::new();
+        let mut buf = Vec::new();
+        let mut request = request(b"a b", b"hello");
+        request.base64 = true;
+        request.flags = 3;
+        request.ttl = Ttl::new(30, TimeType::Memcache);
+        request.mode = SetMode::Prepend;
+        request.compose(&mut buf);
+        assert_eq!(&buf[..], b"ms YSBi 5 b F3 T30 MP\r\nhello\r\n");
+        assert_eq!(
+            parser.parse_request(&buf),
+            Ok((&b""[..], Request::MetaSet(request)))
+        );
+    }
+}
@@ -3,7 +3,7 @@
 // http://www.apache.org/licenses/LICENSE-2.0
 
 use crate::*;
-use common::expiry::TimeType;
+use common::expiry::{TimeType, MEMCACHE_MAX_DELTA};
 use core::fmt::{Display, Formatter};
 use core::num::NonZeroI32;
 use protocol_common::{BufMut, Parse, ParseOk};
@@ -15,13 +15,23 @@ mod cas;
 mod decr;
 mod delete;
 mod flush_all;
+mod gat;
+mod gats;
 mod get;
 mod gets;
 mod incr;
+mod meta_arithmetic;
+mod meta_delete;
+mod meta_get;
+mod meta_noop;
+mod meta_set;
 mod prepend;
 mod quit;
 mod replace;
 mod set;
+mod stats;
+mod time;
+mod touch;
 
 pub use add::Add;
 pub use append::Append;
@@ -29,13 +39,23 @@ pub use cas::Cas;
 pub use decr::Decr;
 pub use delete::Delete;
 pub use flush_all::FlushAll;
+pub use gat::Gat;
+pub use gats::Gats;
 pub use get::Get;
 pub use gets::Gets;
 pub use incr::Incr;
+pub use meta_arithmetic::{ArithmeticMode, MetaArithmetic};
+pub use meta_delete::MetaDelete;
+pub use meta_get::MetaGet;
+pub use meta_noop::MetaNoop;
+pub use meta_set::{MetaSet, SetMode};
 pub use prepend::Prepend;
 pub use quit::Quit;
 pub use replace::Replace;
 pub use set::Set;
+pub use stats::{Stats, StatsGroup};
+pub use time::Time;
+pub use touch::Touch;
 
 pub const DEFAULT_MAX_BATCH_SIZE: usize = 1024;
 pub const DEFAULT_MAX_KEY_LEN: usize = 250;
@@ -49,16 +69,62 @@ const EXISTS: u8 = 6;
 const DELETED: u8 = 7;
 const NOT_FOUND: u8 = 8;
 const NOT_STORED: u8 = 9;
+const TOUCHED: u8 = 10;
 
 fn string_key(key: &[u8]) -> Cow<'_, str> {
     String::from_utf8_lossy(key)
 }
 
+// the longest opaque token of a meta command which is echoed back to the
+// client
+const MAX_OPAQUE_LEN: usize = 32;
+
+// parses the numeric argument of a meta flag
+fn flag_u64(token: &[u8]) -> Option<u64> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+// parses a meta flag which holds a TTL, where a negative TTL means that the
+// item expires immediately
+fn flag_ttl(token: &[u8], time_type: TimeType) -> Option<Ttl> {
+    let ttl = std::str::from_utf8(token).ok()?.parse().ok()?;
+    Some(Ttl::new(ttl, time_type))
+}
+
+// the return flags of a meta command which identify the request that a
+// response belongs to, and which are included regardless of the outcome
+fn meta_return_flags(key: &[u8], base64: bool, opaque: Option<&[u8]>, return_key: bool) -> Vec<u8> {
+    let mut flags = Vec::new();
+    if let Some(opaque) = opaque {
+        flags.extend_from_slice(b" O");
+        flags.extend_from_slice(opaque);
+    }
+    if return_key {
+        flags.extend_from_slice(b" k");
+        if base64 {
+            flags.extend_from_slice(&encode_key(key));
+            flags.extend_from_slice(b" b");
+        } else {
+            flags.extend_from_slice(key);
+        }
+    }
+    flags
+}
+
+// appends a return flag with a numeric argument, such as ` c42`, without
+// formatting it through a string
+fn push_flag<I: itoa::Integer>(flags: &mut Vec<u8>, flag: u8, value: I) {
+    flags.push(b' ');
+    flags.push(flag);
+    flags.extend_from_slice(itoa::Buffer::new().format(value).as_bytes());
+}
+
 #[derive(Copy, Clone)]
 pub struct RequestParser {
     max_value_size: usize,
     max_batch_size: usize,
     max_key_len: usize,
+    strict: bool,
     time_type: TimeType,
 }
 
@@ -87,6 +153,66 @@ impl RequestParser {
         self
     }
 
+    /// In strict mode, request lines which the lenient parser tolerates but
+    /// which are not valid according to the protocol are rejected. This
+    /// includes uppercase command names, leading, trailing, or repeated
+    /// spaces, numeric arguments with leading zeros, and tokens which are
+    /// longer than the max key length.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Checks the request line against the strict rules. Buffers which do not
+    /// yet contain a complete request line are left to the parser.
+    fn check_strict(&self, input: &[u8]) -> bool {
+        let line = match input.windows(2).position(|w| w == b"\r\n") {
+            Some(end) => &input[..end],
+            None => {
+                return true;
+            }
+        };
+
+        let mut tokens = line.split(|b| *b == b' ');
+
+        // the command name must be lowercase
+        let numeric: &[usize] = match tokens.next() {
+            Some(b"add") | Some(b"append") | Some(b"prepend") | Some(b"replace") | Some(b"set") => {
+                &[2, 3, 4]
+            }
+            Some(b"cas") => &[2, 3, 4, 5],
+            Some(b"decr") | Some(b"incr") | Some(b"touch") => &[2],
+            Some(b"flush_all") | Some(b"gat") | Some(b"gats") => &[1],
+            Some(b"delete") | Some(b"get") | Some(b"gets") | Some(b"quit") | Some(b"stats")
+            | Some(b"time") => &[],
+            // the arguments of meta commands are flags, after the length of
+            // the value for a meta set
+            Some(b"ma") | Some(b"md") | Some(b"mg") | Some(b"mn") => &[],
+            Some(b"ms") => &[2],
+            _ => {
+                return false;
+            }
+        };
+
+        for (index, token) in tokens.enumerate().map(|(i, t)| (i + 1, t)) {
+            // an empty token means that spaces were leading, trailing, or
+            // repeated
+            if token.is_empty() || token.len() > self.max_key_len {
+                return false;
+            }
+            if numeric.contains(&index) && token != b"noreply" {
+                let digits = token.strip_prefix(b"-").unwrap_or(token);
+                // u64::MAX is 20 digits long
+                if digits.is_empty() || digits.len() > 20 || (digits.len() > 1 && digits[0] == b'0')
+                {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
     fn parse_command<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], Command> {
         let (remaining, command_bytes) = take_till(|b| (b == b' ' || b == b'\r'))(input)?;
         let command = match command_bytes {
@@ -96,13 +222,23 @@ impl RequestParser {
             b"decr" | b"DECR" => Command::Decr,
             b"delete" | b"DELETE" => Command::Delete,
             b"flush_all" | b"FLUSH_ALL" => Command::FlushAll,
+            b"gat" | b"GAT" => Command::Gat,
+            b"gats" | b"GATS" => Command::Gats,
             b"incr" | b"INCR" => Command::Incr,
             b"get" | b"GET" => Command::Get,
+            b"ma" => Command::MetaArithmetic,
+            b"md" => Command::MetaDelete,
+            b"mg" => Command::MetaGet,
+            b"mn" => Command::MetaNoop,
+            b"ms" => Command::MetaSet,
             b"gets" | b"GETS" => Command::Gets,
             b"prepend" | b"PREPEND" => Command::Prepend,
             b"quit" | b"QUIT" => Command::Quit,
             b"replace" | b"REPLACE" => Command::Replace,
             b"set" | b"SET" => Command::Set,
+            b"stats" | b"STATS" => Command::Stats,
+            b"time" | b"TIME" => Command::Time,
+            b"touch" | b"TOUCH" => Command::Touch,
             _ => {
                 // TODO(bmartin): we can return an unknown command error here
                 return Err(nom::Err::Failure((input, nom::error::ErrorKind::Tag)));
@@ -137,6 +273,14 @@ impl RequestParser {
                 let (input, request) = self.parse_flush_all(input)?;
                 Ok((input, Request::FlushAll(request)))
             }
+            (input, Command::Gat) => {
+                let (input, request) = self.parse_gat(input)?;
+                Ok((input, Request::Gat(request)))
+            }
+            (input, Command::Gats) => {
+                let (input, request) = self.parse_gats(input)?;
+                Ok((input, Request::Gats(request)))
+            }
             (input, Command::Incr) => {
                 let (input, request) = self.parse_incr(input)?;
                 Ok((input, Request::Incr(request)))
@@ -149,6 +293,26 @@ impl RequestParser {
                 let (input, request) = self.parse_gets(input)?;
                 Ok((input, Request::Gets(request)))
             }
+            (input, Command::MetaArithmetic) => {
+                let (input, request) = self.parse_meta_arithmetic(input)?;
+                Ok((input, Request::MetaArithmetic(request)))
+            }
+            (input, Command::MetaDelete) => {
+                let (input, request) = self.parse_meta_delete(input)?;
+                Ok((input, Request::MetaDelete(request)))
+            }
+            (input, Command::MetaGet) => {
+                let (input, request) = self.parse_meta_get(input)?;
+                Ok((input, Request::MetaGet(request)))
+            }
+            (input, Command::MetaNoop) => {
+                let (input, request) = self.parse_meta_noop(input)?;
+                Ok((input, Request::MetaNoop(request)))
+            }
+            (input, Command::MetaSet) => {
+                let (input, request) = self.parse_meta_set(input)?;
+                Ok((input, Request::MetaSet(request)))
+            }
             (input, Command::Prepend) => {
                 let (input, request) = self.parse_prepend(input)?;
                 Ok((input, Request::Prepend(request)))
@@ -165,6 +329,18 @@ impl RequestParser {
                 let (input, request) = self.parse_set(input)?;
                 Ok((input, Request::Set(request)))
             }
+            (input, Command::Stats) => {
+                let (input, request) = self.parse_stats(input)?;
+                Ok((input, Request::Stats(request)))
+            }
+            (input, Command::Time) => {
+                let (input, request) = self.parse_time(input)?;
+                Ok((input, Request::Time(request)))
+            }
+            (input, Command::Touch) => {
+                let (input, request) = self.parse_touch(input)?;
+                Ok((input, Request::Touch(request)))
+            }
         }
     }
 }
@@ -175,6 +351,7 @@ impl Default for RequestParser {
             max_value_size: DEFAULT_MAX_VALUE_SIZE,
             max_batch_size: DEFAULT_MAX_BATCH_SIZE,
             max_key_len: DEFAULT_MAX_KEY_LEN,
+            strict: false,
             time_type: TimeType::Memcache,
         }
     }
@@ -182,12 +359,37 @@ impl Default for RequestParser {
 
 impl Parse<Request> for RequestParser {
     fn parse(&self, buffer: &[u8]) -> Result<ParseOk<Request>, std::io::Error> {
+        if self.strict && !self.check_strict(buffer) {
+            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+        }
+
+        // an HTTP request line such as `GET /x HTTP/1.1` would otherwise parse
+        // as a get of two keys, rather than being detected as a mismatch. the
+        // verbs are matched in either case, but HTTP methods are uppercase
+        if buffer.first().map(|b| b.is_ascii_uppercase()) == Some(true)
+            && Mismatch::http_request_line(buffer)
+        {
+            return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+        }
+
         match self.parse_request(buffer) {
             Ok((input, request)) => Ok(ParseOk::new(request, buffer.len() - input.len())),
             Err(Err::Incomplete(_)) => Err(std::io::Error::from(std::io::ErrorKind::WouldBlock)),
             Err(_) => Err(std::io::Error::from(std::io::ErrorKind::InvalidInput)),
         }
     }
+
+    fn error_response(&self) -> Option<&'static [u8]> {
+        Some(b"ERROR\r\n")
+    }
+
+    fn busy_response(&self) -> Option<&'static [u8]> {
+        Some(b"SERVER_ERROR busy\r\n")
+    }
+
+    fn mismatch(&self, buffer: &[u8]) -> Option<Mismatch> {
+        Mismatch::detect(buffer)
+    }
 }
 
 impl Compose for Request {
@@ -199,13 +401,23 @@ impl Compose for Request {
             Self::Decr(r) => r.compose(session),
             Self::Delete(r) => r.compose(session),
             Self::FlushAll(r) => r.compose(session),
+            Self::Gat(r) => r.compose(session),
+            Self::Gats(r) => r.compose(session),
             Self::Incr(r) => r.compose(session),
             Self::Get(r) => r.compose(session),
             Self::Gets(r) => r.compose(session),
+            Self::MetaArithmetic(r) => r.compose(session),
+            Self::MetaDelete(r) => r.compose(session),
+            Self::MetaGet(r) => r.compose(session),
+            Self::MetaNoop(r) => r.compose(session),
+            Self::MetaSet(r) => r.compose(session),
             Self::Prepend(r) => r.compose(session),
             Self::Quit(r) => r.compose(session),
             Self::Replace(r) => r.compose(session),
             Self::Set(r) => r.compose(session),
+            Self::Stats(r) => r.compose(session),
+            Self::Time(r) => r.compose(session),
+            Self::Touch(r) => r.compose(session),
         }
     }
 }
@@ -221,13 +433,217 @@ impl Klog for Request {
             Self::Decr(r) => r.klog(response),
             Self::Delete(r) => r.klog(response),
             Self::FlushAll(r) => r.klog(response),
+            Self::Gat(r) => r.klog(response),
+            Self::Gats(r) => r.klog(response),
             Self::Incr(r) => r.klog(response),
             Self::Get(r) => r.klog(response),
             Self::Gets(r) => r.klog(response),
+            Self::MetaArithmetic(r) => r.klog(response),
+            Self::MetaDelete(r) => r.klog(response),
+            Self::MetaGet(r) => r.klog(response),
+            Self::MetaNoop(r) => r.klog(response),
+            Self::MetaSet(r) => r.klog(response),
             Self::Prepend(r) => r.klog(response),
             Self::Quit(r) => r.klog(response),
             Self::Replace(r) => r.klog(response),
             Self::Set(r) => r.klog(response),
+            Self::Stats(r) => r.klog(response),
+            Self::Time(r) => r.klog(response),
+            Self::Touch(r) => r.klog(response),
+        }
+    }
+
+    fn access(&self, response: &Self::Response) -> Option<Access> {
+        let key_len = match self {
+            Self::Add(r) => r.key().len(),
+            Self::Append(r) => r.key().len(),
+            Self::Cas(r) => r.key().len(),
+            Self::Decr(r) => r.key().len(),
+            Self::Delete(r) => r.key().len(),
+            Self::Incr(r) => r.key().len(),
+            Self::Get(r) => r.keys().iter().map(|k| k.len()).sum(),
+            Self::Gets(r) => r.keys().iter().map(|k| k.len()).sum(),
+            Self::Gat(r) => r.keys().iter().map(|k| k.len()).sum(),
+            Self::Gats(r) => r.keys().iter().map(|k| k.len()).sum(),
+            Self::MetaArithmetic(r) => r.key().len(),
+            Self::MetaDelete(r) => r.key().len(),
+            Self::MetaGet(r) => r.key().len(),
+            Self::MetaSet(r) => r.key().len(),
+            Self::Prepend(r) => r.key().len(),
+            Self::Replace(r) => r.key().len(),
+            Self::Set(r) => r.key().len(),
+            Self::Touch(r) => r.key().len(),
+            Self::FlushAll(_)
+            | Self::MetaNoop(_)
+            | Self::Quit(_)
+            | Self::Stats(_)
+            | Self::Time(_) => 0,
+        };
+
+        Some(Access {
+            verb: self.command(),
+            key_len,
+            status: response.status(),
+        })
+    }
+}
+
+impl RecordLatency for Request {
+    fn record_latency(&self, latency: u64) {
+        let now = Instant::now();
+        match self {
+            Self::Add(_) => ADD_LATENCY.increment(now, latency, 1),
+            Self::Append(_) => APPEND_LATENCY.increment(now, latency, 1),
+            Self::Cas(_) => CAS_LATENCY.increment(now, latency, 1),
+            Self::Decr(_) => DECR_LATENCY.increment(now, latency, 1),
+            Self::Delete(_) => DELETE_LATENCY.increment(now, latency, 1),
+            Self::Incr(_) => INCR_LATENCY.increment(now, latency, 1),
+            Self::Get(_) => GET_LATENCY.increment(now, latency, 1),
+            Self::Gets(_) => GETS_LATENCY.increment(now, latency, 1),
+            Self::Gat(_) => GAT_LATENCY.increment(now, latency, 1),
+            Self::Gats(_) => GATS_LATENCY.increment(now, latency, 1),
+            Self::MetaArithmetic(_) => MA_LATENCY.increment(now, latency, 1),
+            Self::MetaDelete(_) => MD_LATENCY.increment(now, latency, 1),
+            Self::MetaGet(_) => MG_LATENCY.increment(now, latency, 1),
+            Self::MetaSet(_) => MS_LATENCY.increment(now, latency, 1),
+            Self::Prepend(_) => PREPEND_LATENCY.increment(now, latency, 1),
+            Self::Replace(_) => REPLACE_LATENCY.increment(now, latency, 1),
+            Self::Set(_) => SET_LATENCY.increment(now, latency, 1),
+            Self::Touch(_) => TOUCH_LATENCY.increment(now, latency, 1),
+            Self::FlushAll(_)
+            | Self::MetaNoop(_)
+            | Self::Quit(_)
+            | Self::Stats(_)
+            | Self::Time(_) => {}
+        }
+    }
+}
+
+impl CountSession<Response> for Request {
+    // each key of a read is a hit or a miss
+    fn count(&self, response: &Response, counters: &mut SessionCounters) {
+        match response {
+            Response::Error(_) | Response::ClientError(_) | Response::ServerError(_) => {
+                counters.errors += 1;
+            }
+            Response::Values(values) => {
+                for value in values.values() {
+                    if value.len().is_some() {
+                        counters.hits += 1;
+                    } else {
+                        counters.misses += 1;
+                    }
+                }
+            }
+            Response::Meta(meta) if matches!(self, Self::MetaGet(_)) => match meta.code() {
+                MetaCode::Header | MetaCode::Value => counters.hits += 1,
+                MetaCode::Miss => counters.misses += 1,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn report(&self, response: &mut Response, counters: &SessionCounters) {
+        if let (Self::Stats(stats), Response::Statistics(statistics)) = (self, response) {
+            if stats.group() == StatsGroup::Me {
+                statistics.push("requests", counters.requests);
+                statistics.push("hits", counters.hits);
+                statistics.push("misses", counters.misses);
+                statistics.push("errors", counters.errors);
+                statistics.push("bytes_read", counters.bytes_read);
+                statistics.push("bytes_written", counters.bytes_written);
+            }
+        }
+    }
+}
+
+impl Lookup<Response> for Request {
+    // commands which only change an existing item are included, since a
+    // filter only needs to never miss a key which is stored
+    fn stores(&self, key: &mut dyn FnMut(&[u8])) {
+        match self {
+            Self::Add(r) => key(r.key()),
+            Self::Append(r) => key(r.key()),
+            Self::Cas(r) => key(r.key()),
+            Self::Decr(r) => key(r.key()),
+            Self::Gat(r) => {
+                for k in r.keys() {
+                    key(k)
+                }
+            }
+            Self::Gats(r) => {
+                for k in r.keys() {
+                    key(k)
+                }
+            }
+            Self::Incr(r) => key(r.key()),
+            Self::MetaArithmetic(r) => key(r.key()),
+            Self::MetaGet(r) => {
+                // a meta get only changes the item when it touches it
+                if r.ttl().is_some() {
+                    key(r.key())
+                }
+            }
+            Self::MetaSet(r) => key(r.key()),
+            Self::Prepend(r) => key(r.key()),
+            Self::Replace(r) => key(r.key()),
+            Self::Set(r) => key(r.key()),
+            Self::Touch(r) => key(r.key()),
+            Self::Delete(_)
+            | Self::FlushAll(_)
+            | Self::Get(_)
+            | Self::Gets(_)
+            | Self::MetaDelete(_)
+            | Self::MetaNoop(_)
+            | Self::Quit(_)
+            | Self::Stats(_)
+            | Self::Time(_) => {}
+        }
+    }
+
+    fn reads(&self) -> &[Box<[u8]>] {
+        match self {
+            Self::Get(r) => r.keys(),
+            Self::Gets(r) => r.keys(),
+            _ => &[],
+        }
+    }
+
+    fn miss(&self) -> Option<Response> {
+        let values = self
+            .reads()
+            .iter()
+            .map(|key| Value::none(key))
+            .collect::<Vec<_>>();
+        if values.is_empty() {
+            None
+        } else {
+            Some(Response::values(values.into_boxed_slice()))
+        }
+    }
+
+    fn misses(&self, response: &Response, key: &mut dyn FnMut(&[u8])) {
+        if let Response::Values(values) = response {
+            for value in values.values() {
+                if value.len().is_none() {
+                    key(value.key());
+                }
+            }
+        }
+    }
+
+    fn read_only(&self) -> bool {
+        match self {
+            Self::Get(_)
+            | Self::Gets(_)
+            | Self::MetaNoop(_)
+            | Self::Quit(_)
+            | Self::Stats(_)
+            | Self::Time(_) => true,
+            // a meta get only changes the item when it touches it
+            Self::MetaGet(r) => r.ttl().is_none(),
+            _ => false,
         }
     }
 }
@@ -240,35 +656,62 @@ pub enum Request {
     Decr(Decr),
     Delete(Delete),
     FlushAll(FlushAll),
+    Gat(Gat),
+    Gats(Gats),
     Incr(Incr),
     Get(Get),
     Gets(Gets),
+    MetaArithmetic(MetaArithmetic),
+    MetaDelete(MetaDelete),
+    MetaGet(MetaGet),
+    MetaNoop(MetaNoop),
+    MetaSet(MetaSet),
     Prepend(Prepend),
     Quit(Quit),
     Replace(Replace),
     Set(Set),
+    Stats(Stats),
+    Time(Time),
+    Touch(Touch),
 }
 
-impl Display for Request {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+impl Request {
+    /// The name of the command, as it appears on the wire.
+    pub fn command(&self) -> &'static str {
         match self {
-            Request::Add(_) => write!(f, "add"),
-            Request::Append(_) => write!(f, "append"),
-            Request::Cas(_) => write!(f, "cas"),
-            Request::Decr(_) => write!(f, "decr"),
-            Request::Delete(_) => write!(f, "delete"),
-            Request::FlushAll(_) => write!(f, "flush_all"),
-            Request::Incr(_) => write!(f, "incr"),
-            Request::Get(_) => write!(f, "get"),
-            Request::Gets(_) => write!(f, "gets"),
-            Request::Prepend(_) => write!(f, "prepend"),
-            Request::Quit(_) => write!(f, "quit"),
-            Request::Replace(_) => write!(f, "replace"),
-            Request::Set(_) => write!(f, "set"),
+            Request::Add(_) => "add",
+            Request::Append(_) => "append",
+            Request::Cas(_) => "cas",
+            Request::Decr(_) => "decr",
+            Request::Delete(_) => "delete",
+            Request::FlushAll(_) => "flush_all",
+            Request::Gat(_) => "gat",
+            Request::Gats(_) => "gats",
+            Request::Incr(_) => "incr",
+            Request::Get(_) => "get",
+            Request::Gets(_) => "gets",
+            Request::MetaArithmetic(_) => "ma",
+            Request::MetaDelete(_) => "md",
+            Request::MetaGet(_) => "mg",
+            Request::MetaNoop(_) => "mn",
+            Request::MetaSet(_) => "ms",
+            Request::Prepend(_) => "prepend",
+            Request::Quit(_) => "quit",
+            Request::Replace(_) => "replace",
+            Request::Set(_) => "set",
+            Request::Stats(_) => "stats",
+            Request::Time(_) => "time",
+            Request::Touch(_) => "touch",
         }
     }
 }
 
+impl Display for Request {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.command())
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Command {
     Add,
@@ -277,13 +720,23 @@ pub enum Command {
     Decr,
     Delete,
     FlushAll,
+    Gat,
+    Gats,
     Incr,
     Get,
     Gets,
+    MetaArithmetic,
+    MetaDelete,
+    MetaGet,
+    MetaNoop,
+    MetaSet,
     Prepend,
     Quit,
     Replace,
     Set,
+    Stats,
+    Time,
+    Touch,
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -316,9 +769,10 @@ impl Ttl {
     /// approximately 68 years.
     ///
     /// For `TimeType::Memcache` the expiration time is treated as
-    /// `TimeType::Delta` if it is a duration of less than 30 days in seconds.
-    /// If the provided expiration time is larger than that, it is treated as
-    /// a UNIX epoch time following the `TimeType::Unix` rules.
+    /// `TimeType::Delta` if it is a duration of up to 30 days in seconds. If
+    /// the provided expiration time is larger than that, it is treated as a
+    /// UNIX epoch time following the `TimeType::Unix` rules. This matches the
+    /// behavior of memcached.
     pub fn new(exptime: i64, time_type: TimeType) -> Self {
         // all negative values mean to expire immediately, early return
         if exptime < 0 {
@@ -334,7 +788,7 @@ impl Ttl {
 
         // normalize all expiration times into delta
         let exptime = if time_type == TimeType::Unix
-            || (time_type == TimeType::Memcache && exptime > 60 * 60 * 24 * 30)
+            || (time_type == TimeType::Memcache && exptime > MEMCACHE_MAX_DELTA as i64)
         {
             // treat it as a unix timestamp
 
@@ -413,4 +867,169 @@ mod tests {
             Ok((&b" key \"value\"\r\n"[..], Command::Set))
         );
     }
+
+    #[test]
+    fn strict() {
+        let lenient = RequestParser::new();
+        let strict = RequestParser::new().strict(true);
+
+        let valid: &[&[u8]] = &[
+            b"get key\r\n",
+            b"get 007\r\n",
+            b"set key 0 -1 1 noreply\r\n0\r\n",
+            b"cas key 0 0 1 10\r\n0\r\n",
+            b"incr key 1\r\n",
+            b"touch key -1\r\n",
+            b"flush_all 0\r\n",
+            b"flush_all noreply\r\n",
+            b"gat 60 a b\r\n",
+            b"mg key v t\r\n",
+            b"ms key 1 T-1 F0\r\n0\r\n",
+            b"stats me\r\n",
+        ];
+        for request in valid {
+            assert!(lenient.parse(request).is_ok());
+            assert!(strict.parse(request).is_ok());
+        }
+
+        // tolerated by the lenient parser but rejected in strict mode
+        let tolerated: &[&[u8]] = &[
+            b"GET key\r\n",
+            b"get  key\r\n",
+            b"get key \r\n",
+            b"set key 00 0 1\r\n0\r\n",
+            b"set key 0 0 01\r\n0\r\n",
+            b"incr key 0000000000000000000001\r\n",
+            b"gats 060 key\r\n",
+            b"ms key 01\r\n0\r\n",
+            b"STATS me\r\n",
+        ];
+        for request in tolerated {
+            assert!(lenient.parse(request).is_ok());
+            assert_eq!(
+                strict.parse(request).map(|_| ()).map_err(|e| e.kind()),
+                Err(std::io::ErrorKind::InvalidInput)
+            );
+        }
+
+        // incomplete request lines are left to the parser
+        assert_eq!(
+            strict.parse(b"get  key").map(|_| ()).map_err(|e| e.kind()),
+            Err(std::io::ErrorKind::WouldBlock)
+        );
+    }
+
+    #[test]
+    fn http_request_line() {
+        let parser = RequestParser::new();
+
+        // an HTTP request is rejected, so that it is detected as a mismatch
+        let request = b"GET /keys/coffee HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            parser.parse(request).map(|_| ()).map_err(|e| e.kind()),
+            Err(std::io::ErrorKind::InvalidInput)
+        );
+        assert_eq!(parser.mismatch(request), Some(Mismatch::Http));
+
+        // uppercase gets of keys which begin with a slash are not affected
+        assert!(parser.parse(b"GET /keys/coffee\r\n").is_ok());
+        assert!(parser.parse(b"GET /a /b\r\n").is_ok());
+    }
+
+    #[test]
+    fn read_only() {
+        let parser = RequestParser::new();
+        let read_only = |request: &[u8]| {
+            parser
+                .parse(request)
+                .expect("failed to parse")
+                .into_inner()
+                .read_only()
+        };
+
+        assert!(read_only(b"get coffee\r\n"));
+        assert!(read_only(b"gets coffee tea\r\n"));
+        assert!(read_only(b"mg coffee v\r\n"));
+        assert!(read_only(b"stats\r\n"));
+
+        // writes, including those which only change the ttl, are not
+        assert!(!read_only(b"set coffee 0 0 1\r\n1\r\n"));
+        assert!(!read_only(b"delete coffee\r\n"));
+        assert!(!read_only(b"incr coffee 1\r\n"));
+        assert!(!read_only(b"touch coffee 60\r\n"));
+        assert!(!read_only(b"gat 60 coffee\r\n"));
+        assert!(!read_only(b"mg coffee v T60\r\n"));
+        assert!(!read_only(b"flush_all\r\n"));
+    }
+
+    #[test]
+    fn ttl() {
+        common::time::refresh_clock();
+        let now = UnixInstant::<Seconds<u32>>::recent()
+            .checked_duration_since(UnixInstant::from_secs(0))
+            .map(|v| v.as_secs())
+            .unwrap() as i64;
+
+        // negative is immediate expiration and zero is no expiration
+        assert_eq!(Ttl::new(-1, TimeType::Memcache).get(), Some(-1));
+        assert_eq!(Ttl::new(0, TimeType::Memcache).get(), None);
+
+        // up to and including 30 days, memcache times are durations
+        let max = MEMCACHE_MAX_DELTA as i64;
+        assert_eq!(Ttl::new(1, TimeType::Memcache).get(), Some(1));
+        assert_eq!(Ttl::new(max, TimeType::Memcache).get(), Some(max as i32));
+
+        // beyond 30 days they are absolute, so this is far in the past
+        assert_eq!(Ttl::new(max + 1, TimeType::Memcache).get(), Some(-1));
+
+        // absolute times in the future are converted to durations, allowing
+        // for the clock to advance while the test runs
+        let ttl = Ttl::new(now + 3600, TimeType::Memcache).get().unwrap();
+        assert!((3598..=3600).contains(&ttl), "ttl: {}", ttl);
+        let ttl = Ttl::new(now + 2 * max, TimeType::Memcache).get().unwrap();
+        assert!(ttl > max as i32, "ttl: {}", ttl);
+
+        // the current time or earlier is immediate expiration
+        assert_eq!(Ttl::new(now, TimeType::Memcache).get(), Some(-1));
+        assert_eq!(Ttl::new(now - 1, TimeType::Unix).get(), Some(-1));
+
+        // unix times are always absolute and delta times are never absolute
+        let ttl = Ttl::new(now + 60, TimeType::Unix).get().unwrap();
+        assert!((58..=60).contains(&ttl), "ttl: {}", ttl);
+        assert_eq!(
+            Ttl::new(max + 1, TimeType::Delta).get(),
+            Some(max as i32 + 1)
+        );
+
+        // far-future times are clamped
+        assert_eq!(Ttl::new(i64::MAX, TimeType::Memcache).get(), Some(i32::MAX));
+        assert_eq!(Ttl::new(i64::MAX, TimeType::Delta).get(), Some(i32::MAX));
+    }
+
+    #[test]
+    fn lookup() {
+        let parser = RequestParser::new();
+        let request = |input: &[u8]| parser.parse(input).unwrap().into_inner();
+
+        let mut stored = Vec::new();
+        request(b"set key 0 0 1\r\n0\r\n").stores(&mut |key| stored.push(key.to_vec()));
+        request(b"incr count 1\r\n").stores(&mut |key| stored.push(key.to_vec()));
+        request(b"delete key\r\n").stores(&mut |key| stored.push(key.to_vec()));
+        assert_eq!(stored, vec![b"key".to_vec(), b"count".to_vec()]);
+
+        // only plain reads may be answered without storage
+        let get = request(b"get a b\r\n");
+        assert_eq!(get.reads().len(), 2);
+        assert!(request(b"incr count 1\r\n").reads().is_empty());
+        assert!(request(b"incr count 1\r\n").miss().is_none());
+
+        let miss = get.miss().unwrap();
+        let mut missed = Vec::new();
+        get.misses(&miss, &mut |key| missed.push(key.to_vec()));
+        assert_eq!(missed, vec![b"a".to_vec(), b"b".to_vec()]);
+
+        let mut composed = Vec::new();
+        miss.compose(&mut composed);
+        assert_eq!(composed, b"END\r\n");
+    }
 }
@@ -14,6 +14,17 @@ pub struct Set {
 }
 
 impl Set {
+    /// Create a new `Set` request.
+    pub fn new(key: &[u8], value: &[u8], flags: u32, ttl: Ttl, noreply: bool) -> Self {
+        Self {
+            key: key.to_vec().into_boxed_slice(),
+            value: value.to_vec().into_boxed_slice(),
+            flags,
+            ttl,
+            noreply,
+        }
+    }
+
     pub fn key(&self) -> &[u8] {
         &self.key
     }
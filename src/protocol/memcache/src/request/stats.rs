@@ -0,0 +1,153 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// The group of statistics which a `stats` request asks for.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum StatsGroup {
+    /// The general statistics of the server, which is asked for by a `stats`
+    /// request without a group.
+    General,
+    /// The items in each group of storage.
+    Items,
+    /// The memory used by each group of storage. This is also asked for as
+    /// `slabs`, which is the name used by memcached.
+    Segments,
+    /// The number of items of each size.
+    Sizes,
+    /// The counters of the session which sent the request, so that a client
+    /// can check them against its own accounting. These are only known to
+    /// the server, which adds them to the response from storage.
+    Me,
+}
+
+impl StatsGroup {
+    /// The name of the group, as it appears on the wire.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::General => "",
+            Self::Items => "items",
+            Self::Segments => "segments",
+            Self::Sizes => "sizes",
+            Self::Me => "me",
+        }
+    }
+}
+
+/// Requests a group of statistics, which are returned as `STAT` lines.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Stats {
+    pub(crate) group: StatsGroup,
+}
+
+impl Stats {
+    pub fn group(&self) -> StatsGroup {
+        self.group
+    }
+}
+
+impl RequestParser {
+    // this is to be called after parsing the command, so we do not match the verb
+    pub fn parse_stats<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], Stats> {
+        let (input, _) = space0(input)?;
+        let (remaining, group) = take_till(|b| (b == b' ' || b == b'\r'))(input)?;
+        let (remaining, _) = space0(remaining)?;
+        let (remaining, _) = crlf(remaining)?;
+
+        STATS.increment();
+
+        let group = match group {
+            b"" => StatsGroup::General,
+            b"items" => StatsGroup::Items,
+            b"segments" | b"slabs" => StatsGroup::Segments,
+            b"sizes" => StatsGroup::Sizes,
+            b"me" => StatsGroup::Me,
+            _ => {
+                STATS_EX.increment();
+                return Err(nom::Err::Failure((input, nom::error::ErrorKind::Tag)));
+            }
+        };
+
+        Ok((remaining, Stats { group }))
+    }
+}
+
+impl Compose for Stats {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let request = match self.group {
+            StatsGroup::General => b"stats\r\n".to_vec(),
+            group => format!("stats {}\r\n", group.as_str()).into_bytes(),
+        };
+        session.put_slice(&request);
+        request.len()
+    }
+}
+
+impl Klog for Stats {
+    type Response = Response;
+
+    fn klog(&self, _response: &Self::Response) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let parser = RequestParser::new();
+
+        assert_eq!(
+            parser.parse_request(b"stats me\r\n"),
+            Ok((
+                &b""[..],
+                Request::Stats(Stats {
+                    group: StatsGroup::Me
+                })
+            ))
+        );
+
+        // without a group, and with the name memcached uses for segments
+        assert_eq!(
+            parser.parse_request(b"stats\r\n"),
+            Ok((
+                &b""[..],
+                Request::Stats(Stats {
+                    group: StatsGroup::General
+                })
+            ))
+        );
+        assert_eq!(
+            parser.parse_request(b"stats slabs\r\n"),
+            Ok((
+                &b""[..],
+                Request::Stats(Stats {
+                    group: StatsGroup::Segments
+                })
+            ))
+        );
+
+        // unknown groups are rejected
+        assert!(parser.parse_request(b"stats you\r\n").is_err());
+        assert!(parser.parse_request(b"statsme\r\n").is_err());
+    }
+
+    #[test]
+    fn compose() {
+        let mut buffer = Vec::new();
+        Stats {
+            group: StatsGroup::Me,
+        }
+        .compose(&mut buffer);
+        assert_eq!(&buffer[..], b"stats me\r\n");
+
+        let mut buffer = Vec::new();
+        Stats {
+            group: StatsGroup::General,
+        }
+        .compose(&mut buffer);
+        assert_eq!(&buffer[..], b"stats\r\n");
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// Requests the current wall clock time and the uptime of the server. This is
+/// not part of the memcache protocol and is intended for debugging expiration
+/// issues which may be caused by clock skew between clients and the server.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Time {}
+
+impl Time {}
+
+impl RequestParser {
+    // this is to be called after parsing the command, so we do not match the verb
+    pub fn parse_time<'a>(&self, input: &'a [u8]) -> IResult<&'a [u8], Time> {
+        let (input, _) = space0(input)?;
+        let (input, _) = crlf(input)?;
+
+        TIME.increment();
+
+        Ok((input, Time {}))
+    }
+}
+
+impl Compose for Time {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        session.put_slice(b"time\r\n");
+        6
+    }
+}
+
+impl Klog for Time {
+    type Response = Response;
+
+    fn klog(&self, _response: &Self::Response) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let parser = RequestParser::new();
+
+        // time command
+        assert_eq!(
+            parser.parse_request(b"time\r\n"),
+            Ok((&b""[..], Request::Time(Time {})))
+        );
+
+        // trailing whitespace is allowed
+        assert_eq!(
+            parser.parse_request(b"time \r\n"),
+            Ok((&b""[..], Request::Time(Time {})))
+        );
+    }
+}
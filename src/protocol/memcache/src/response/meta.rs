@@ -0,0 +1,158 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// The return codes of the meta commands.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum MetaCode {
+    /// Success, without a value.
+    Header,
+    /// Success, with a value.
+    Value,
+    /// The item was not found.
+    NotFound,
+    /// The item was not stored.
+    NotStored,
+    /// The compare-and-swap token did not match.
+    Exists,
+    /// The item was not found by a meta get.
+    Miss,
+    /// The reply to a meta no-op, which marks the end of a pipeline of quiet
+    /// requests.
+    NoOp,
+}
+
+impl MetaCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Header => "HD",
+            Self::Value => "VA",
+            Self::NotFound => "NF",
+            Self::NotStored => "NS",
+            Self::Exists => "EX",
+            Self::Miss => "EN",
+            Self::NoOp => "MN",
+        }
+    }
+}
+
+/// A response to one of the meta commands. Any return flags requested by the
+/// client are included in the header line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Meta {
+    code: MetaCode,
+    flags: Vec<u8>,
+    value: Option<Box<[u8]>>,
+    quiet: bool,
+}
+
+impl Meta {
+    /// Create a response with the code and the formatted return flags, each of
+    /// which is preceded by a space.
+    pub fn new(code: MetaCode, flags: Vec<u8>, value: Option<&[u8]>, quiet: bool) -> Self {
+        Self {
+            code,
+            flags,
+            value: value.map(|v| v.to_vec().into_boxed_slice()),
+            quiet,
+        }
+    }
+
+    pub fn code(&self) -> MetaCode {
+        self.code
+    }
+
+    pub fn value(&self) -> Option<&[u8]> {
+        self.value.as_deref()
+    }
+
+    // in quiet mode only the codes which indicate a failure are sent
+    fn suppressed(&self) -> bool {
+        self.quiet
+            && matches!(
+                self.code,
+                MetaCode::Header | MetaCode::NotFound | MetaCode::Miss
+            )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        if self.suppressed() {
+            0
+        } else {
+            let len = match &self.value {
+                Some(value) => 1 + itoa::Buffer::new().format(value.len()).len(),
+                None => 0,
+            };
+            let header = self.code.as_str().len() + len + self.flags.len() + CRLF.len();
+            header + self.value.as_ref().map(|v| v.len() + 2).unwrap_or(0)
+        }
+    }
+}
+
+impl Compose for Meta {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        if self.suppressed() {
+            return 0;
+        }
+
+        let code = self.code.as_str().as_bytes();
+        session.put_slice(code);
+        let mut size = code.len();
+
+        // the length is written directly rather than formatted, to avoid an
+        // allocation for every response
+        if let Some(value) = &self.value {
+            let mut buffer = itoa::Buffer::new();
+            let len = buffer.format(value.len());
+            session.put_slice(b" ");
+            session.put_slice(len.as_bytes());
+            size += 1 + len.len();
+        }
+
+        session.put_slice(&self.flags);
+        session.put_slice(CRLF);
+        size += self.flags.len() + CRLF.len();
+
+        if let Some(value) = &self.value {
+            session.put_slice(value);
+            session.put_slice(CRLF);
+            size += value.len() + CRLF.len();
+        }
+
+        size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compose() {
+        let mut buf = Vec::new();
+        let response = Meta::new(MetaCode::Header, b" c42".to_vec(), None, false);
+        assert_eq!(response.compose(&mut buf), response.len());
+        assert_eq!(&buf[..], b"HD c42\r\n");
+
+        let mut buf = Vec::new();
+        let response = Meta::new(MetaCode::Value, b" Oabc".to_vec(), Some(b"11"), false);
+        assert_eq!(response.compose(&mut buf), response.len());
+        assert_eq!(&buf[..], b"VA 2 Oabc\r\n11\r\n");
+
+        // quiet mode suppresses success and misses, but not failures
+        let mut buf = Vec::new();
+        let response = Meta::new(MetaCode::NotFound, Vec::new(), None, true);
+        assert_eq!(response.compose(&mut buf), 0);
+        assert!(buf.is_empty());
+
+        let response = Meta::new(MetaCode::NotStored, Vec::new(), None, true);
+        assert_eq!(response.compose(&mut buf), 4);
+        assert_eq!(&buf[..], b"NS\r\n");
+    }
+}
@@ -9,22 +9,30 @@ mod client_error;
 mod deleted;
 mod error;
 mod exists;
+mod meta;
 mod not_found;
 mod not_stored;
 mod numeric;
 mod server_error;
+mod server_time;
+mod statistics;
 mod stored;
+mod touched;
 mod values;
 
 pub use client_error::ClientError;
 pub use deleted::Deleted;
 pub use error::Error;
 pub use exists::Exists;
+pub use meta::{Meta, MetaCode};
 pub use not_found::NotFound;
 pub use not_stored::NotStored;
 pub use numeric::Numeric;
 pub use server_error::ServerError;
+pub use server_time::ServerTime;
+pub use statistics::Statistics;
 pub use stored::Stored;
+pub use touched::Touched;
 pub use values::{Value, Values};
 
 #[derive(Debug, PartialEq, Eq)]
@@ -39,6 +47,10 @@ pub enum Response {
     Values(Values),
     Numeric(Numeric),
     Deleted(Deleted),
+    ServerTime(ServerTime),
+    Touched(Touched),
+    Meta(Meta),
+    Statistics(Statistics),
     Hangup,
 }
 
@@ -79,6 +91,10 @@ impl Response {
         Self::Values(Values { values })
     }
 
+    pub fn meta(code: MetaCode, flags: Vec<u8>, value: Option<&[u8]>, quiet: bool) -> Self {
+        Self::Meta(Meta::new(code, flags, value, quiet))
+    }
+
     pub fn hangup() -> Self {
         Self::Hangup
     }
@@ -90,6 +106,47 @@ impl Response {
     pub fn deleted(noreply: bool) -> Self {
         Self::Deleted(Deleted::new(noreply))
     }
+
+    pub fn touched(noreply: bool) -> Self {
+        Self::Touched(Touched::new(noreply))
+    }
+
+    pub fn server_time(unix_time: std::time::Duration, uptime: std::time::Duration) -> Self {
+        Self::ServerTime(ServerTime::new(unix_time, uptime))
+    }
+
+    pub fn statistics(stats: Vec<(String, String)>) -> Self {
+        Self::Statistics(Statistics::new(stats))
+    }
+
+    /// The leading token of the response on the wire, which summarizes the
+    /// outcome of the request. A multiget which found at least one key is
+    /// reported as `VALUE` and one which found none as `END`.
+    pub fn status(&self) -> &'static str {
+        match self {
+            Self::Error(_) => "ERROR",
+            Self::ClientError(_) => "CLIENT_ERROR",
+            Self::ServerError(_) => "SERVER_ERROR",
+            Self::Stored(_) => "STORED",
+            Self::NotStored(_) => "NOT_STORED",
+            Self::Exists(_) => "EXISTS",
+            Self::NotFound(_) => "NOT_FOUND",
+            Self::Values(values) => {
+                if values.values().iter().any(|v| v.value().is_some()) {
+                    "VALUE"
+                } else {
+                    "END"
+                }
+            }
+            Self::Numeric(_) => "NUMERIC",
+            Self::Deleted(_) => "DELETED",
+            Self::ServerTime(_) => "TIME",
+            Self::Touched(_) => "TOUCHED",
+            Self::Meta(meta) => meta.code().as_str(),
+            Self::Statistics(_) => "STAT",
+            Self::Hangup => "HANGUP",
+        }
+    }
 }
 
 impl From<Values> for Response {
@@ -111,10 +168,26 @@ impl Compose for Response {
             Self::Values(e) => e.compose(session),
             Self::Numeric(e) => e.compose(session),
             Self::Deleted(e) => e.compose(session),
+            Self::ServerTime(e) => e.compose(session),
+            Self::Touched(e) => e.compose(session),
+            Self::Meta(e) => e.compose(session),
+            Self::Statistics(e) => e.compose(session),
             Self::Hangup => 0,
         }
     }
 
+    fn compose_partial(
+        &self,
+        session: &mut dyn BufMut,
+        cursor: &mut usize,
+        limit: usize,
+    ) -> (usize, bool) {
+        match self {
+            Self::Values(e) => e.compose_partial(session, cursor, limit),
+            _ => (self.compose(session), true),
+        }
+    }
+
     fn should_hangup(&self) -> bool {
         matches!(self, Self::Error(_) | Self::ClientError(_) | Self::Hangup)
     }
@@ -133,6 +206,9 @@ pub enum ResponseType {
     Empty,
     Numeric(u64),
     Deleted,
+    ServerTime,
+    Statistics,
+    Touched,
 }
 
 pub struct ResponseParser {}
@@ -150,6 +226,9 @@ pub(crate) fn response_type(input: &[u8]) -> IResult<&[u8], ResponseType> {
         b"VALUE" => ResponseType::Values,
         b"END" => ResponseType::Empty,
         b"DELETED" => ResponseType::Deleted,
+        b"TIME" => ResponseType::ServerTime,
+        b"STAT" => ResponseType::Statistics,
+        b"TOUCHED" => ResponseType::Touched,
         _ => {
             if let Ok(s) = std::str::from_utf8(response_type_token) {
                 if let Ok(value) = s.parse::<u64>() {
@@ -219,6 +298,18 @@ pub(crate) fn response(input: &[u8]) -> IResult<&[u8], Response> {
             let (input, response) = deleted::parse(input)?;
             Ok((input, Response::Deleted(response)))
         }
+        (input, ResponseType::ServerTime) => {
+            let (input, response) = server_time::parse(input)?;
+            Ok((input, Response::ServerTime(response)))
+        }
+        (input, ResponseType::Statistics) => {
+            let (input, response) = statistics::parse(input)?;
+            Ok((input, Response::Statistics(response)))
+        }
+        (input, ResponseType::Touched) => {
+            let (input, response) = touched::parse(input)?;
+            Ok((input, Response::Touched(response)))
+        }
     }
 }
 
@@ -23,7 +23,7 @@ impl Numeric {
         if self.noreply {
             0
         } else {
-            format!("{}\r\n", self.value).len()
+            itoa::Buffer::new().format(self.value).len() + CRLF.len()
         }
     }
 }
@@ -31,9 +31,11 @@ impl Numeric {
 impl Compose for Numeric {
     fn compose(&self, session: &mut dyn BufMut) -> usize {
         if !self.noreply {
-            let response = format!("{}\r\n", self.value).into_bytes();
-            session.put_slice(&response);
-            response.len()
+            let mut buffer = itoa::Buffer::new();
+            let value = buffer.format(self.value);
+            session.put_slice(value.as_bytes());
+            session.put_slice(CRLF);
+            value.len() + CRLF.len()
         } else {
             0
         }
@@ -56,4 +58,16 @@ mod tests {
             Ok((&b""[..], Response::numeric(42, false),))
         );
     }
+
+    #[test]
+    fn compose() {
+        let numeric = Numeric::new(12345, false);
+        let mut buf = Vec::new();
+        assert_eq!(numeric.compose(&mut buf), numeric.len());
+        assert_eq!(&buf[..], b"12345\r\n");
+
+        let numeric = Numeric::new(12345, true);
+        assert_eq!(numeric.compose(&mut buf), 0);
+        assert!(numeric.is_empty());
+    }
 }
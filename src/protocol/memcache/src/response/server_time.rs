@@ -0,0 +1,115 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+use std::time::Duration;
+
+/// The response to a `time` request. This carries the wall clock time of the
+/// server, as seconds and microseconds since the UNIX epoch, followed by the
+/// monotonic uptime of the server in the same format:
+///
+/// `TIME <unix seconds> <microseconds> <uptime seconds> <microseconds>\r\n`
+#[derive(Debug, PartialEq, Eq)]
+pub struct ServerTime {
+    unix_time: Duration,
+    uptime: Duration,
+}
+
+impl ServerTime {
+    pub fn new(unix_time: Duration, uptime: Duration) -> Self {
+        Self { unix_time, uptime }
+    }
+
+    /// The wall clock time of the server as a duration since the UNIX epoch.
+    pub fn unix_time(&self) -> Duration {
+        self.unix_time
+    }
+
+    /// The time elapsed on the monotonic clock of the server since startup.
+    pub fn uptime(&self) -> Duration {
+        self.uptime
+    }
+}
+
+impl Compose for ServerTime {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let response = format!(
+            "TIME {} {} {} {}\r\n",
+            self.unix_time.as_secs(),
+            self.unix_time.subsec_micros(),
+            self.uptime.as_secs(),
+            self.uptime.subsec_micros()
+        )
+        .into_bytes();
+        session.put_slice(&response);
+        response.len()
+    }
+}
+
+pub fn parse(input: &[u8]) -> IResult<&[u8], ServerTime> {
+    let (input, _) = space1(input)?;
+    let (input, unix_secs) = parse_u64(input)?;
+    let (input, _) = space1(input)?;
+    let (input, unix_micros) = parse_u32(input)?;
+    let (input, _) = space1(input)?;
+    let (input, uptime_secs) = parse_u64(input)?;
+    let (input, _) = space1(input)?;
+    let (input, uptime_micros) = parse_u32(input)?;
+    let (input, _) = space0(input)?;
+    let (input, _) = crlf(input)?;
+    Ok((
+        input,
+        ServerTime {
+            unix_time: duration(input, unix_secs, unix_micros)?,
+            uptime: duration(input, uptime_secs, uptime_micros)?,
+        },
+    ))
+}
+
+// the microseconds must be a fraction of a second, otherwise converting them
+// to nanoseconds could overflow
+fn duration(
+    input: &[u8],
+    secs: u64,
+    micros: u32,
+) -> Result<Duration, nom::Err<(&[u8], nom::error::ErrorKind)>> {
+    if micros >= 1_000_000 {
+        return Err(nom::Err::Failure((input, nom::error::ErrorKind::Verify)));
+    }
+    Ok(Duration::new(secs, micros * 1000))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            response(b"TIME 1656000000 123456 42 7\r\n"),
+            Ok((
+                &b""[..],
+                Response::server_time(
+                    Duration::new(1656000000, 123_456_000),
+                    Duration::new(42, 7_000)
+                ),
+            ))
+        );
+
+        assert!(response(b"TIME 1656000000 1000000 42 7\r\n").is_err());
+        assert!(response(b"TIME 1656000000 123456 42 4294967295\r\n").is_err());
+    }
+
+    #[test]
+    fn compose() {
+        let response = Response::server_time(
+            Duration::new(1656000000, 123_456_789),
+            Duration::from_millis(1500),
+        );
+        let mut buffer = Vec::new();
+        let len = response.compose(&mut buffer);
+        assert_eq!(&buffer[..], b"TIME 1656000000 123456 1 500000\r\n");
+        assert_eq!(len, buffer.len());
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+/// The response to a `stats` request. Each statistic is a name and a value on
+/// its own line, and the response ends with a line of `END`:
+///
+/// `STAT <name> <value>\r\n ... END\r\n`
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct Statistics {
+    stats: Vec<(String, String)>,
+}
+
+impl Statistics {
+    pub fn new(stats: Vec<(String, String)>) -> Self {
+        Self { stats }
+    }
+
+    pub fn stats(&self) -> &[(String, String)] {
+        &self.stats
+    }
+
+    /// Adds a statistic to the end of the response.
+    pub fn push<T: ToString>(&mut self, name: &str, value: T) {
+        self.stats.push((name.to_string(), value.to_string()));
+    }
+}
+
+impl Compose for Statistics {
+    fn compose(&self, session: &mut dyn BufMut) -> usize {
+        let mut len = 0;
+        for (name, value) in &self.stats {
+            let line = format!("STAT {} {}\r\n", name, value).into_bytes();
+            session.put_slice(&line);
+            len += line.len();
+        }
+        session.put_slice(b"END\r\n");
+        len + 5
+    }
+}
+
+// parses the remainder of a response once the first `STAT` token is matched
+pub fn parse(input: &[u8]) -> IResult<&[u8], Statistics> {
+    let mut stats = Vec::new();
+    let mut input = input;
+
+    loop {
+        let (i, _) = space1(input)?;
+        let (i, name) = take_till(|b| (b == b' ' || b == b'\r'))(i)?;
+        let (i, _) = space1(i)?;
+        let (i, value) = take_till(|b| b == b'\r')(i)?;
+        let (i, _) = crlf(i)?;
+
+        let name = std::str::from_utf8(name)
+            .map_err(|_| nom::Err::Failure((input, nom::error::ErrorKind::Tag)))?;
+        let value = std::str::from_utf8(value)
+            .map_err(|_| nom::Err::Failure((input, nom::error::ErrorKind::Tag)))?;
+        stats.push((name.to_string(), value.to_string()));
+
+        let (i, token) = take_till(|b| (b == b' ' || b == b'\r'))(i)?;
+        match token {
+            b"STAT" => {
+                input = i;
+            }
+            b"END" => {
+                let (i, _) = crlf(i)?;
+                return Ok((i, Statistics { stats }));
+            }
+            _ => {
+                return Err(nom::Err::Failure((i, nom::error::ErrorKind::Tag)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let mut expected = Statistics::default();
+        expected.push("requests", 3);
+        expected.push("version", "1.0 beta");
+        assert_eq!(
+            response(b"STAT requests 3\r\nSTAT version 1.0 beta\r\nEND\r\n"),
+            Ok((&b""[..], Response::Statistics(expected)))
+        );
+
+        assert!(response(b"STAT requests 3\r\nVALUE\r\n").is_err());
+    }
+
+    #[test]
+    fn compose() {
+        let mut statistics = Statistics::default();
+        statistics.push("hits", 1);
+        statistics.push("misses", 2);
+        let mut buffer = Vec::new();
+        let len = Response::Statistics(statistics).compose(&mut buffer);
+        assert_eq!(&buffer[..], b"STAT hits 1\r\nSTAT misses 2\r\nEND\r\n");
+        assert_eq!(len, buffer.len());
+    }
+}
@@ -50,6 +50,19 @@ impl Value {
         &self.key
     }
 
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub fn cas(&self) -> Option<u64> {
+        self.cas
+    }
+
+    /// Returns the data for this value, or `None` if the key was a miss.
+    pub fn value(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> Option<usize> {
         self.data.as_ref().map(|v| v.len())
@@ -69,6 +82,29 @@ impl Compose for Values {
 
         size
     }
+
+    // the cursor is the index of the next value to compose
+    fn compose_partial(
+        &self,
+        session: &mut dyn BufMut,
+        cursor: &mut usize,
+        limit: usize,
+    ) -> (usize, bool) {
+        let mut size = 0;
+
+        while let Some(value) = self.values.get(*cursor) {
+            if size >= limit {
+                return (size, false);
+            }
+            size += value.compose(session);
+            *cursor += 1;
+        }
+
+        let suffix = b"END\r\n";
+        session.put_slice(suffix);
+
+        (size + suffix.len(), true)
+    }
 }
 
 impl Compose for Value {
@@ -80,24 +116,36 @@ impl Compose for Value {
         let data = self.data.as_ref().unwrap();
 
         let prefix = b"VALUE ";
-        let header_fields = if let Some(cas) = self.cas {
-            format!(" {} {} {}\r\n", self.flags, data.len(), cas).into_bytes()
-        } else {
-            format!(" {} {}\r\n", self.flags, data.len()).into_bytes()
-        };
-
-        let size = prefix.len() + self.key.len() + header_fields.len() + data.len() + CRLF.len();
 
         session.put_slice(prefix);
         session.put_slice(&self.key);
-        session.put_slice(&header_fields);
+        let mut size = prefix.len() + self.key.len();
+
+        // the numbers are written directly rather than formatted, to avoid an
+        // allocation for every value
+        let mut buffer = itoa::Buffer::new();
+        size += put_number(session, &mut buffer, self.flags as u64);
+        size += put_number(session, &mut buffer, data.len() as u64);
+        if let Some(cas) = self.cas {
+            size += put_number(session, &mut buffer, cas);
+        }
+
+        session.put_slice(CRLF);
         session.put_slice(data);
         session.put_slice(CRLF);
 
-        size
+        size + CRLF.len() + data.len() + CRLF.len()
     }
 }
 
+// writes a space followed by the number, returning the number of bytes written
+fn put_number(session: &mut dyn BufMut, buffer: &mut itoa::Buffer, number: u64) -> usize {
+    let number = buffer.format(number);
+    session.put_slice(b" ");
+    session.put_slice(number.as_bytes());
+    1 + number.len()
+}
+
 pub fn parse(input: &[u8]) -> IResult<&[u8], Values> {
     let mut values = Vec::new();
     let mut input = input;
@@ -228,4 +276,35 @@ mod tests {
             Ok((&b""[..], Response::values(vec![].into_boxed_slice()),))
         );
     }
+
+    #[test]
+    fn compose_partial() {
+        let values = vec![
+            Value::new(b"0", 0, None, b"1"),
+            Value::none(b"1"),
+            Value::new(b"2", 0, None, b"3"),
+        ];
+        let response = Response::values(values.into_boxed_slice());
+
+        let mut complete = Vec::new();
+        let len = response.compose(&mut complete);
+        assert_eq!(len, complete.len());
+
+        // with a small limit, each part ends once a hit has been written
+        let mut buffer = Vec::new();
+        let mut cursor = 0;
+        let mut parts = 0;
+        let mut size = 0;
+        loop {
+            let (len, done) = response.compose_partial(&mut buffer, &mut cursor, 1);
+            size += len;
+            parts += 1;
+            if done {
+                break;
+            }
+        }
+        assert_eq!(parts, 2);
+        assert_eq!(size, buffer.len());
+        assert_eq!(buffer, complete);
+    }
 }
@@ -13,7 +13,8 @@ mod test;
 
 use crate::Response;
 pub use keyword::Keyword;
-use logger::Klog;
+use logger::{Access, Klog};
+use protocol_common::{CountSession, Lookup, RecordLatency};
 
 pub use parse::Parser as RequestParser;
 
@@ -23,6 +24,19 @@ pub enum Request {
     Ping,
 }
 
+// a single command, so the overall request latency is the ping latency
+impl RecordLatency for Request {}
+
+// a ping has no keys and does not change any state
+impl Lookup<Response> for Request {
+    fn read_only(&self) -> bool {
+        true
+    }
+}
+
+// there is no request for the counters of a session
+impl CountSession<Response> for Request {}
+
 impl Klog for Request {
     type Response = Response;
 
@@ -31,4 +45,14 @@ impl Klog for Request {
             Request::Ping => klog!("ping {}", 6),
         }
     }
+
+    fn access(&self, _response: &Self::Response) -> Option<Access> {
+        match self {
+            Request::Ping => Some(Access {
+                verb: "ping",
+                key_len: 0,
+                status: "PONG",
+            }),
+        }
+    }
 }
@@ -0,0 +1,76 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+use std::io::{Error, ErrorKind};
+
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct DbSizeRequest {}
+
+impl TryFrom<Message> for DbSizeRequest {
+    type Error = Error;
+
+    fn try_from(other: Message) -> Result<Self, Error> {
+        if let Message::Array(array) = other {
+            if array.inner.is_none() {
+                return Err(Error::new(ErrorKind::Other, "malformed command"));
+            }
+
+            let array = array.inner.unwrap();
+
+            if array.len() != 1 {
+                return Err(Error::new(ErrorKind::Other, "malformed command"));
+            }
+
+            Ok(Self {})
+        } else {
+            Err(Error::new(ErrorKind::Other, "malformed command"))
+        }
+    }
+}
+
+impl DbSizeRequest {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl From<&DbSizeRequest> for Message {
+    fn from(_other: &DbSizeRequest) -> Message {
+        Message::Array(Array {
+            inner: Some(vec![Message::BulkString(BulkString::new(b"DBSIZE"))]),
+        })
+    }
+}
+
+impl Compose for DbSizeRequest {
+    fn compose(&self, buf: &mut dyn BufMut) -> usize {
+        let message = Message::from(self);
+        message.compose(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parser() {
+        let parser = RequestParser::new();
+        assert_eq!(
+            parser.parse(b"dbsize\r\n").unwrap().into_inner(),
+            Request::DbSize(DbSizeRequest::new())
+        );
+
+        assert_eq!(
+            parser
+                .parse(b"*1\r\n$6\r\nDBSIZE\r\n")
+                .unwrap()
+                .into_inner(),
+            Request::DbSize(DbSizeRequest::new())
+        );
+
+        assert!(parser.parse(b"dbsize 0\r\n").is_err());
+    }
+}
@@ -10,10 +10,14 @@ use protocol_common::ParseOk;
 use std::io::{Error, ErrorKind};
 use std::sync::Arc;
 
+mod dbsize;
 mod get;
+mod randomkey;
 mod set;
 
+pub use dbsize::DbSizeRequest;
 pub use get::GetRequest;
+pub use randomkey::RandomKeyRequest;
 pub use set::SetRequest;
 
 #[derive(Default)]
@@ -98,6 +102,12 @@ impl Parse<Request> for RequestParser {
                         Some(b"set") | Some(b"SET") => {
                             SetRequest::try_from(message).map(Request::from)
                         }
+                        Some(b"dbsize") | Some(b"DBSIZE") => {
+                            DbSizeRequest::try_from(message).map(Request::from)
+                        }
+                        Some(b"randomkey") | Some(b"RANDOMKEY") => {
+                            RandomKeyRequest::try_from(message).map(Request::from)
+                        }
                         _ => Err(Error::new(ErrorKind::Other, "unknown command")),
                     },
                     _ => {
@@ -120,6 +130,8 @@ impl Compose for Request {
         match self {
             Self::Get(r) => r.compose(buf),
             Self::Set(r) => r.compose(buf),
+            Self::DbSize(r) => r.compose(buf),
+            Self::RandomKey(r) => r.compose(buf),
         }
     }
 }
@@ -128,6 +140,8 @@ impl Compose for Request {
 pub enum Request {
     Get(GetRequest),
     Set(SetRequest),
+    DbSize(DbSizeRequest),
+    RandomKey(RandomKeyRequest),
 }
 
 impl From<GetRequest> for Request {
@@ -142,10 +156,24 @@ impl From<SetRequest> for Request {
     }
 }
 
+impl From<DbSizeRequest> for Request {
+    fn from(other: DbSizeRequest) -> Self {
+        Self::DbSize(other)
+    }
+}
+
+impl From<RandomKeyRequest> for Request {
+    fn from(other: RandomKeyRequest) -> Self {
+        Self::RandomKey(other)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Command {
     Get,
     Set,
+    DbSize,
+    RandomKey,
 }
 
 impl TryFrom<&[u8]> for Command {
@@ -155,6 +183,8 @@ impl TryFrom<&[u8]> for Command {
         match other {
             b"get" | b"GET" => Ok(Command::Get),
             b"set" | b"SET" => Ok(Command::Set),
+            b"dbsize" | b"DBSIZE" => Ok(Command::DbSize),
+            b"randomkey" | b"RANDOMKEY" => Ok(Command::RandomKey),
             _ => Err(()),
         }
     }
@@ -0,0 +1,76 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+use std::io::{Error, ErrorKind};
+
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct RandomKeyRequest {}
+
+impl TryFrom<Message> for RandomKeyRequest {
+    type Error = Error;
+
+    fn try_from(other: Message) -> Result<Self, Error> {
+        if let Message::Array(array) = other {
+            if array.inner.is_none() {
+                return Err(Error::new(ErrorKind::Other, "malformed command"));
+            }
+
+            let array = array.inner.unwrap();
+
+            if array.len() != 1 {
+                return Err(Error::new(ErrorKind::Other, "malformed command"));
+            }
+
+            Ok(Self {})
+        } else {
+            Err(Error::new(ErrorKind::Other, "malformed command"))
+        }
+    }
+}
+
+impl RandomKeyRequest {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl From<&RandomKeyRequest> for Message {
+    fn from(_other: &RandomKeyRequest) -> Message {
+        Message::Array(Array {
+            inner: Some(vec![Message::BulkString(BulkString::new(b"RANDOMKEY"))]),
+        })
+    }
+}
+
+impl Compose for RandomKeyRequest {
+    fn compose(&self, buf: &mut dyn BufMut) -> usize {
+        let message = Message::from(self);
+        message.compose(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parser() {
+        let parser = RequestParser::new();
+        assert_eq!(
+            parser.parse(b"randomkey\r\n").unwrap().into_inner(),
+            Request::RandomKey(RandomKeyRequest::new())
+        );
+
+        assert_eq!(
+            parser
+                .parse(b"*1\r\n$9\r\nRANDOMKEY\r\n")
+                .unwrap()
+                .into_inner(),
+            Request::RandomKey(RandomKeyRequest::new())
+        );
+
+        assert!(parser.parse(b"randomkey 0\r\n").is_err());
+    }
+}
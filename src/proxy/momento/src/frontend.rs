@@ -102,6 +102,16 @@ pub(crate) async fn handle_resp_client(
                             break;
                         }
                     }
+                    resp::Request::DbSize(_) | resp::Request::RandomKey(_) => {
+                        // the cache does not expose its keyspace
+                        if socket
+                            .write_all(b"-ERR unsupported command\r\n")
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
                 }
                 buf.advance(consumed);
             }
@@ -8,6 +8,7 @@ extern crate logger;
 use backtrace::Backtrace;
 use clap::App;
 use clap::Arg;
+use common::daemon::Daemon;
 use config::PingproxyConfig;
 use pingproxy::Pingproxy;
 use rustcommon_metrics::*;
@@ -93,6 +94,22 @@ fn main() {
         Default::default()
     };
 
+    // detach and redirect output before any threads are launched, holding
+    // the pidfile until exit so that it is removed on shutdown
+    let _pidfile = match Daemon::new()
+        .daemonize(config.daemonize())
+        .pid_filename(config.pid_filename())
+        .stdout(config.stdout())
+        .stderr(config.stderr())
+        .start()
+    {
+        Ok(pidfile) => pidfile,
+        Err(e) => {
+            println!("error daemonizing pingproxy: {}", e);
+            std::process::exit(1);
+        }
+    };
+
     // launch proxy
     Pingproxy::new(config).wait()
 }
@@ -7,22 +7,62 @@
 pub use waker::Waker;
 
 use crossbeam_queue::*;
+use mio::{Events, Poll, Token};
 use rand::distributions::Uniform;
 use rand::Rng as RandRng;
 use rand::SeedableRng;
 use rand_chacha::ChaCha20Rng;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use waker::MioWaker;
+
+const WAITER_TOKEN: Token = Token(usize::MAX);
 
 /// A struct for sending and receiving items by using very simple routing. This
 /// allows for us to send messages to a specific receiver, to any receiver, or
 /// all receivers. Automatically wraps items with the identifier of the sender
 /// so that a response can be sent back to the corresponding receiver.
+///
+/// Items which are broadcast as requests are also tagged with a sequence
+/// number. Each item sent back to a receiver echoes the sequence number of the
+/// last item received from it, so that the replies to a request can be told
+/// apart from late replies to an earlier one.
 pub struct Queues<T, U> {
     senders: Vec<WakingSender<TrackedItem<T>>>,
     receiver: Arc<ArrayQueue<TrackedItem<U>>>,
     id: usize,
     rng: ChaCha20Rng,
     distr: Uniform<usize>,
+    seq: u64,
+    received: Vec<u64>,
+    waiter: Option<Waiter>,
+}
+
+/// An event loop which is only woken by the senders to one side of the queues.
+/// It allows a thread which has nothing else to do to wait for items to
+/// arrive, instead of repeatedly checking the queue.
+pub struct Waiter {
+    poll: Poll,
+    events: Events,
+    waker: Arc<Waker>,
+}
+
+impl Waiter {
+    pub fn new() -> Result<Self, std::io::Error> {
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::from(MioWaker::new(poll.registry(), WAITER_TOKEN)?));
+        Ok(Self {
+            poll,
+            events: Events::with_capacity(1),
+            waker,
+        })
+    }
+
+    /// The waker to use for this side when constructing the queues, in place
+    /// of the waker for the event loop of the thread.
+    pub fn waker(&self) -> Arc<Waker> {
+        self.waker.clone()
+    }
 }
 
 struct WakingSender<T> {
@@ -156,6 +196,9 @@ impl<T, U> Queues<T, U> {
                 rng: ChaCha20Rng::from_entropy(),
                 distr: Uniform::new(0, a_tx.len()),
                 id,
+                seq: 0,
+                received: vec![0; a_tx.len()],
+                waiter: None,
             })
         }
 
@@ -166,6 +209,9 @@ impl<T, U> Queues<T, U> {
                 rng: ChaCha20Rng::from_entropy(),
                 distr: Uniform::new(0, b_tx.len()),
                 id,
+                seq: 0,
+                received: vec![0; b_tx.len()],
+                waiter: None,
             })
         }
 
@@ -174,20 +220,68 @@ impl<T, U> Queues<T, U> {
 
     /// Try to receive a single item from the queue. Returns a `TrackedItem<T>`
     /// which allows the receiver to know which sender sent the item.
-    pub fn try_recv(&self) -> Option<TrackedItem<U>> {
-        self.receiver.pop()
+    pub fn try_recv(&mut self) -> Option<TrackedItem<U>> {
+        let item = self.receiver.pop()?;
+        self.received[item.sender] = item.seq;
+        Some(item)
     }
 
     /// Try to receive all pending items from the queue.
-    pub fn try_recv_all(&self, buf: &mut Vec<TrackedItem<U>>) {
+    pub fn try_recv_all(&mut self, buf: &mut Vec<TrackedItem<U>>) {
         let pending = self.receiver.len();
         for _ in 0..pending {
-            if let Some(item) = self.receiver.pop() {
+            if let Some(item) = self.try_recv() {
                 buf.push(item);
             }
         }
     }
 
+    /// Attaches a waiter, whose waker was used for this side when constructing
+    /// the queues, so that `recv_timeout()` can wait for items.
+    pub fn set_waiter(&mut self, waiter: Waiter) {
+        self.waiter = Some(waiter);
+    }
+
+    /// Receive a single item, waiting up to the timeout for one to arrive if
+    /// the queue is empty. Without a waiter, this does not wait.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Option<TrackedItem<U>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            // the waker is reset before checking the queue, so that an item
+            // which is sent after the check wakes the poll below
+            if let Some(waiter) = self.waiter.as_ref() {
+                waiter.waker.reset();
+            }
+            if let Some(item) = self.try_recv() {
+                return Some(item);
+            }
+
+            let now = Instant::now();
+            let waiter = self.waiter.as_mut()?;
+            if now >= deadline {
+                return None;
+            }
+            let _ = waiter.poll.poll(&mut waiter.events, Some(deadline - now));
+        }
+    }
+
+    /// Returns the number of receivers on the other side of the queues.
+    pub fn receivers(&self) -> usize {
+        self.senders.len()
+    }
+
+    /// Returns the number of items which have been sent to the receiver
+    /// specified by the `id` and which it has not yet received.
+    pub fn pending_to(&self, id: usize) -> usize {
+        self.senders[id].inner.len()
+    }
+
+    /// Returns true if the queue to the receiver specified by the `id` is full,
+    /// in which case sending to it would fail.
+    pub fn is_full_to(&self, id: usize) -> bool {
+        self.senders[id].inner.is_full()
+    }
+
     /// Try to send a single item to the receiver specified by the `id`. Allows
     /// targeted 1:1 communication.
     ///
@@ -198,6 +292,7 @@ impl<T, U> Queues<T, U> {
         self.senders[id]
             .try_send(TrackedItem {
                 sender: self.id,
+                seq: self.received[id],
                 inner: item,
             })
             .map_err(|e| e.into_inner())
@@ -215,6 +310,7 @@ impl<T, U> Queues<T, U> {
         self.senders[id]
             .try_send(TrackedItem {
                 sender: self.id,
+                seq: self.received[id],
                 inner: item,
             })
             .map_err(|e| e.into_inner())
@@ -238,10 +334,32 @@ impl<T: Clone, U> Queues<T, U> {
     /// side.
     pub fn try_send_all(&mut self, item: T) -> Result<(), T> {
         let mut result = Ok(());
+        for (id, sender) in self.senders.iter_mut().enumerate() {
+            if sender
+                .try_send(TrackedItem {
+                    sender: self.id,
+                    seq: self.received[id],
+                    inner: item.clone(),
+                })
+                .is_err()
+            {
+                result = Err(item.clone());
+            }
+        }
+        result
+    }
+
+    /// Broadcasts the item to all receivers on the other side as a request,
+    /// tagged with a new sequence number. Returns the sequence number which
+    /// the replies to this request will carry.
+    pub fn try_request_all(&mut self, item: T) -> Result<u64, T> {
+        self.seq += 1;
+        let mut result = Ok(self.seq);
         for sender in self.senders.iter_mut() {
             if sender
                 .try_send(TrackedItem {
                     sender: self.id,
+                    seq: self.seq,
                     inner: item.clone(),
                 })
                 .is_err()
@@ -255,6 +373,7 @@ impl<T: Clone, U> Queues<T, U> {
 
 pub struct TrackedItem<T> {
     sender: usize,
+    seq: u64,
     inner: T,
 }
 
@@ -262,6 +381,11 @@ impl<T> TrackedItem<T> {
     pub fn sender(&self) -> usize {
         self.sender
     }
+    /// The sequence number of the request, or of the request being replied
+    /// to. Items which are neither have a sequence number of zero.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
     pub fn into_inner(self) -> T {
         self.inner
     }
@@ -269,10 +393,11 @@ impl<T> TrackedItem<T> {
 
 #[cfg(test)]
 mod tests {
-    use crate::Queues;
+    use crate::{Queues, Waiter};
     use ::net::Waker as MioWaker;
     use ::net::{Poll, Token};
     use std::sync::Arc;
+    use std::time::Duration;
     use waker::Waker;
 
     const WAKER_TOKEN: Token = Token(usize::MAX);
@@ -366,4 +491,66 @@ mod tests {
             Some((0, "orange".to_string()))
         );
     }
+    #[test]
+    fn requests() {
+        let poll = Poll::new().expect("failed to create event loop");
+        let waker = Arc::new(Waker::from(
+            MioWaker::new(poll.registry(), WAKER_TOKEN).expect("failed to create waker"),
+        ));
+        let waiter = Waiter::new().expect("failed to create waiter");
+
+        let a_wakers = vec![waiter.waker()];
+        let b_wakers = vec![waker.clone(), waker];
+
+        let (mut a, mut b) = Queues::<usize, String>::new(&a_wakers, &b_wakers, 1024);
+        let mut a = a.remove(0);
+        a.set_waiter(waiter);
+
+        // nothing arrives before the timeout
+        assert!(a.recv_timeout(Duration::from_millis(10)).is_none());
+
+        // each request is tagged with a new sequence number
+        assert_eq!(a.try_request_all(1), Ok(1));
+        assert_eq!(a.try_request_all(2), Ok(2));
+
+        // replies echo the sequence number of the last request received
+        for b in b.iter_mut() {
+            assert_eq!(
+                b.try_recv().map(|v| (v.seq(), v.into_inner())),
+                Some((1, 1))
+            );
+            b.try_send_to(0, "first".to_string())
+                .expect("failed to send");
+            assert_eq!(
+                b.try_recv().map(|v| (v.seq(), v.into_inner())),
+                Some((2, 2))
+            );
+        }
+
+        // a reply sent from another thread wakes the waiting receiver
+        let mut late = b.remove(1);
+        let thread = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            late.try_send_to(0, "second".to_string())
+                .expect("failed to send");
+            late.wake().expect("failed to wake");
+        });
+
+        let mut replies = Vec::new();
+        while let Some(reply) = a.recv_timeout(Duration::from_secs(10)) {
+            replies.push((reply.sender(), reply.seq(), reply.into_inner()));
+            if replies.len() == 3 {
+                break;
+            }
+        }
+        thread.join().expect("failed to join");
+        assert_eq!(
+            replies,
+            vec![
+                (0, 1, "first".to_string()),
+                (1, 1, "first".to_string()),
+                (1, 2, "second".to_string()),
+            ]
+        );
+    }
 }
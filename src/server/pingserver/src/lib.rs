@@ -11,9 +11,9 @@
 
 use config::*;
 use entrystore::Noop;
-use logger::*;
-use protocol_ping::{Request, RequestParser, Response};
-use server::{Process, ProcessBuilder};
+use protocol_ping::{Request, RequestParser};
+use server_bootstrap::{Process, ServerBuilder};
+use std::net::SocketAddr;
 
 type Parser = RequestParser;
 type Storage = Noop;
@@ -26,11 +26,8 @@ pub struct Pingserver {
 impl Pingserver {
     /// Creates a new `Pingserver` process from the given `PingserverConfig`.
     pub fn new(config: PingserverConfig) -> Result<Self, std::io::Error> {
-        // initialize logging
-        let log_drain = configure_logging(&config);
-
-        // initialize metrics
-        common::metrics::init();
+        // initialize logging and metrics
+        let builder = ServerBuilder::new(&config).version(env!("CARGO_PKG_VERSION"));
 
         // initialize storage
         let storage = Storage::new();
@@ -38,18 +35,32 @@ impl Pingserver {
         // initialize parser
         let parser = Parser::new();
 
-        // initialize process
-        let process_builder = ProcessBuilder::<Parser, Request, Response, Storage>::new(
-            &config, log_drain, parser, storage,
-        )?
-        .version(env!("CARGO_PKG_VERSION"));
-
         // spawn threads
-        let process = process_builder.spawn();
+        let process = builder.spawn::<_, Request, _, _>(parser, storage)?;
 
         Ok(Self { process })
     }
 
+    /// Returns the address the data port is bound to.
+    pub fn listen_addr(&self) -> Option<SocketAddr> {
+        self.process.listen_addr()
+    }
+
+    /// Returns the address the admin port is bound to.
+    pub fn admin_addr(&self) -> Option<SocketAddr> {
+        self.process.admin_addr()
+    }
+
+    /// Returns the current value of the named counter, if it exists.
+    pub fn counter(&self, name: &str) -> Option<u64> {
+        self.process.counter(name)
+    }
+
+    /// Returns the current value of the named gauge, if it exists.
+    pub fn gauge(&self, name: &str) -> Option<i64> {
+        self.process.gauge(name)
+    }
+
     /// Wait for all threads to complete. Blocks until the process has fully
     /// terminated. Under normal conditions, this will block indefinitely.
     pub fn wait(self) {
@@ -0,0 +1,256 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Compares get requests over the experimental QUIC front end with the same
+//! requests over TCP with TLS. Both transports use a self-signed certificate
+//! generated at startup.
+//!
+//! The `serial` benchmarks issue one request at a time over a single stream,
+//! which measures the per-request overhead of each transport. The `parallel`
+//! benchmarks issue a batch of requests concurrently. Over TCP the batch is
+//! pipelined on the connection, while over QUIC each request uses its own
+//! stream so that a delay on one request does not stall the others.
+//!
+//! This runs over loopback, which has neither loss nor meaningful latency. To
+//! evaluate behavior on a WAN link, combine with a network emulator such as
+//! `tc qdisc add dev lo root netem delay 20ms loss 1%`.
+//!
+//! Requires the `quic` feature:
+//!
+//! ```text
+//! cargo bench --features quic --bench quic
+//! ```
+
+use config::{SegConfig, SegcacheConfig};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use entrystore::Seg;
+use pelikan_segcache_rs::Segcache;
+use protocol_memcache::{Request, RequestParser, Response};
+use quinn::{Endpoint, NewConnection};
+use server::{QuicServerBuilder, ALPN};
+
+use boring::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+const PARALLEL: usize = 16;
+
+fn config(dir: &tempfile::TempDir) -> SegcacheConfig {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])
+        .expect("failed to generate certificate");
+
+    let cert_file = dir.path().join("cert.pem");
+    let key_file = dir.path().join("key.pem");
+    let config_file = dir.path().join("segcache.toml");
+
+    std::fs::write(&cert_file, cert.serialize_pem().unwrap()).unwrap();
+    std::fs::write(&key_file, cert.serialize_private_key_pem()).unwrap();
+    std::fs::write(
+        &config_file,
+        format!(
+            "[admin]\nport = \"9997\"\n\n\
+            [server]\nhost = \"127.0.0.1\"\nport = \"12322\"\n\n\
+            [seg]\nheap_size = 67108864\n\n\
+            [tls]\ncertificate_chain = \"{}\"\nprivate_key = \"{}\"\n",
+            cert_file.display(),
+            key_file.display()
+        ),
+    )
+    .unwrap();
+
+    SegcacheConfig::load(config_file.to_str().unwrap()).expect("failed to load config")
+}
+
+fn quic_client(
+    runtime: &tokio::runtime::Runtime,
+    addr: SocketAddr,
+    cert: &[u8],
+) -> quinn::Connection {
+    runtime.block_on(async {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut &*cert).unwrap() {
+            roots.add(&rustls::Certificate(cert)).unwrap();
+        }
+
+        let mut crypto = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        crypto.alpn_protocols = vec![ALPN.to_vec()];
+
+        let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+
+        let NewConnection { connection, .. } = endpoint
+            .connect(addr, "localhost")
+            .unwrap()
+            .await
+            .expect("failed to connect");
+
+        connection
+    })
+}
+
+/// Send a request on a QUIC stream and read until the expected number of
+/// bytes has been received.
+async fn quic_request(
+    send: &mut quinn::SendStream,
+    recv: &mut quinn::RecvStream,
+    request: &[u8],
+    response: &[u8],
+    buffer: &mut [u8],
+) {
+    send.write_all(request).await.expect("write error");
+    let mut read = 0;
+    while read < response.len() {
+        match recv.read(&mut buffer[read..]).await {
+            Ok(Some(bytes)) => read += bytes,
+            _ => panic!("read error"),
+        }
+    }
+    assert_eq!(&buffer[0..read], response, "invalid response");
+}
+
+fn tls_request<S: Read + Write>(
+    stream: &mut S,
+    request: &[u8],
+    response: &[u8],
+    buffer: &mut [u8],
+) {
+    stream.write_all(request).expect("write error");
+    let mut read = 0;
+    while read < response.len() {
+        match stream.read(&mut buffer[read..]) {
+            Ok(bytes) if bytes > 0 => read += bytes,
+            _ => panic!("read error"),
+        }
+    }
+    assert_eq!(&buffer[0..read], response, "invalid response");
+}
+
+fn quic_benchmark(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let config = config(&dir);
+    let cert = std::fs::read(dir.path().join("cert.pem")).unwrap();
+
+    // launch the tcp+tls server
+    let server = Segcache::new(config).expect("failed to launch segcache");
+
+    // launch the quic server on the same port number, using its own storage
+    let config = SegcacheConfig::load(dir.path().join("segcache.toml").to_str().unwrap()).unwrap();
+    let storage = Seg::new(&config).expect("failed to initialize storage");
+    let parser = RequestParser::new().max_value_size(config.seg().segment_size() as usize);
+    let quic =
+        QuicServerBuilder::<RequestParser, Request, Response, Seg>::new(&config, parser, storage)
+            .expect("failed to configure quic")
+            .spawn()
+            .expect("failed to launch quic");
+
+    // wait for server to startup. duration is chosen to be longer than we'd
+    // expect startup to take in a slow ci environment.
+    std::thread::sleep(Duration::from_secs(10));
+
+    // connect using tcp+tls
+    let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+    connector.set_verify(SslVerifyMode::NONE);
+    let connector = connector.build();
+    let stream = TcpStream::connect("127.0.0.1:12322").expect("failed to connect");
+    stream.set_nodelay(true).unwrap();
+    let mut tls = connector
+        .connect("localhost", stream)
+        .expect("tls handshake failed");
+
+    // connect using quic
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let connection = quic_client(&runtime, quic.local_addr(), &cert);
+    let (mut send, mut recv) = runtime.block_on(connection.open_bi()).unwrap();
+
+    let mut buffer = vec![0; 1024 * 1024];
+
+    let mut group = c.benchmark_group("transport");
+
+    for vlen in [1, 64, 1024, 4096, 16384].iter() {
+        let key = format!("{:01$}", vlen, 16);
+        let value = format!("{:A>1$}", 0, vlen);
+
+        // populate both servers
+        let set = format!("set {} 0 0 {}\r\n{}\r\n", key, vlen, value);
+        tls_request(&mut tls, set.as_bytes(), b"STORED\r\n", &mut buffer);
+        runtime.block_on(quic_request(
+            &mut send,
+            &mut recv,
+            set.as_bytes(),
+            b"STORED\r\n",
+            &mut buffer,
+        ));
+
+        let get = format!("get {}\r\n", key);
+        let response = format!("VALUE {} 0 {}\r\n{}\r\nEND\r\n", key, vlen, value);
+
+        group.throughput(Throughput::Elements(1));
+
+        group.bench_function(format!("serial/tcp_tls/{}b", vlen), |b| {
+            b.iter(|| tls_request(&mut tls, get.as_bytes(), response.as_bytes(), &mut buffer))
+        });
+
+        group.bench_function(format!("serial/quic/{}b", vlen), |b| {
+            b.iter(|| {
+                runtime.block_on(quic_request(
+                    &mut send,
+                    &mut recv,
+                    get.as_bytes(),
+                    response.as_bytes(),
+                    &mut buffer,
+                ))
+            })
+        });
+
+        group.throughput(Throughput::Elements(PARALLEL as u64));
+
+        let pipelined = get.repeat(PARALLEL);
+        let responses = response.repeat(PARALLEL);
+        group.bench_function(format!("parallel/tcp_tls/{}b", vlen), |b| {
+            b.iter(|| {
+                tls_request(
+                    &mut tls,
+                    pipelined.as_bytes(),
+                    responses.as_bytes(),
+                    &mut buffer,
+                )
+            })
+        });
+
+        let mut streams: Vec<_> = (0..PARALLEL)
+            .map(|_| runtime.block_on(connection.open_bi()).unwrap())
+            .collect();
+        let mut buffers = vec![vec![0; response.len()]; PARALLEL];
+        group.bench_function(format!("parallel/quic/{}b", vlen), |b| {
+            b.iter(|| {
+                runtime.block_on(futures::future::join_all(
+                    streams
+                        .iter_mut()
+                        .zip(buffers.iter_mut())
+                        .map(|((send, recv), buffer)| {
+                            quic_request(send, recv, get.as_bytes(), response.as_bytes(), buffer)
+                        }),
+                ))
+            })
+        });
+    }
+
+    group.finish();
+
+    // shutdown the servers
+    connection.close(0u32.into(), b"done");
+    quic.shutdown();
+    server.shutdown();
+}
+
+criterion_group!(benches, quic_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,282 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! An interactive client for the memcache protocol on the data port and the
+//! admin protocol on the admin port. Commands are entered without the trailing
+//! CRLF, and storage commands prompt for the value on a second line.
+//!
+//! Responses are read until they are complete, so multi-line responses such as
+//! a multiget or the admin `stats` are printed in full before the next prompt.
+
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+use std::io::{BufRead, BufReader, Read, Result, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+
+// storage commands are followed by a data block, and each is paired with the
+// index of the token which holds the number of bytes in the block
+const STORAGE_COMMANDS: &[(&str, usize)] = &[
+    ("set", 4),
+    ("add", 4),
+    ("replace", 4),
+    ("append", 4),
+    ("prepend", 4),
+    ("cas", 4),
+    ("ms", 2),
+];
+
+// meta commands only reply to a quiet request, which has the `q` flag, if it
+// fails. a meta no-op follows each quiet request so that the end of its
+// replies is known
+const META_COMMANDS: &[&str] = &["mg", "ms", "md", "ma"];
+
+// the first tokens of the lines which are a complete response on their own,
+// any other line is part of a response which is completed by `END`
+const TERMINAL_TOKENS: &[&str] = &[
+    "END",
+    "ERROR",
+    "CLIENT_ERROR",
+    "SERVER_ERROR",
+    "STORED",
+    "NOT_STORED",
+    "EXISTS",
+    "NOT_FOUND",
+    "DELETED",
+    "TOUCHED",
+    "TIME",
+    "OK",
+    "VERSION",
+    "COMPACTED",
+    "LOGLEVEL",
+    "PROFILE",
+    "HD",
+    "NF",
+    "NS",
+    "EX",
+    "EN",
+    "MN",
+];
+
+/// Connect to the server at `addr` and run the interactive client until the
+/// input ends or the server hangs up.
+pub fn connect(addr: &str) -> Result<()> {
+    let stream = TcpStream::connect(addr)?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+
+    let mut editor = Editor::<()>::new();
+    let history = history_path();
+    if let Some(ref path) = history {
+        let _ = editor.load_history(path);
+    }
+
+    println!("connected to {}, use ctrl-d to exit", addr);
+
+    let prompt = format!("{}> ", addr);
+    loop {
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("{}", e);
+                break;
+            }
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line);
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let mut request = format!("{}\r\n", line).into_bytes();
+
+        let bytes_token = STORAGE_COMMANDS
+            .iter()
+            .find(|(command, _)| *command == tokens[0])
+            .map(|(_, index)| *index);
+
+        if let Some(index) = bytes_token.filter(|index| tokens.len() > *index) {
+            let data = match editor.readline("") {
+                Ok(data) => data,
+                Err(_) => break,
+            };
+            // the server would read a data block of a different length as
+            // the start of the next request, so it is not sent at all
+            if tokens[index].parse::<usize>().ok() != Some(data.len()) {
+                eprintln!(
+                    "data is {} bytes, but the command is for {}, not sent",
+                    data.len(),
+                    tokens[index]
+                );
+                continue;
+            }
+            request.extend_from_slice(data.as_bytes());
+            request.extend_from_slice(b"\r\n");
+        }
+
+        let quiet = META_COMMANDS.contains(&tokens[0]) && tokens[1..].contains(&"q");
+        if quiet {
+            request.extend_from_slice(b"mn\r\n");
+        }
+
+        writer.write_all(&request)?;
+
+        // there is no response to a request with noreply
+        if tokens.last() == Some(&"noreply") {
+            continue;
+        }
+
+        if !print_responses(&mut reader, quiet)? {
+            println!("connection closed by server");
+            break;
+        }
+    }
+
+    if let Some(ref path) = history {
+        let _ = editor.save_history(path);
+    }
+
+    Ok(())
+}
+
+// Reads and prints the responses to a request, which for a quiet meta request
+// are any failures followed by the `MN` of the no-op sent after it. Returns
+// false if the server has closed the connection.
+fn print_responses<R: Read>(reader: &mut BufReader<R>, quiet: bool) -> Result<bool> {
+    loop {
+        match print_response(reader)? {
+            None => return Ok(false),
+            Some(token) if !quiet || token == "MN" => return Ok(true),
+            Some(_) => {}
+        }
+    }
+}
+
+// Reads and prints a single response. Returns the first token of the line
+// which ended it, or `None` if the server has closed the connection.
+//
+// A response ends with a line which is a complete response on its own, such as
+// `END` or the result of an `incr`, and the data blocks which follow `VALUE`
+// and `VA` lines are read in full, so that the next response starts in step
+// with the next request.
+fn print_response<R: Read>(reader: &mut BufReader<R>) -> Result<Option<String>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let trimmed = line.trim_end_matches(&['\r', '\n'][..]);
+        println!("{}", trimmed);
+
+        let mut tokens = trimmed.split(' ');
+        let token = tokens.next().unwrap_or("");
+        match token {
+            "VALUE" => {
+                // VALUE <key> <flags> <bytes> [<cas>]
+                print_data(reader, tokens.nth(2))?;
+            }
+            "VA" => {
+                // VA <bytes> <flags>*, which is the whole response
+                print_data(reader, tokens.next())?;
+                return Ok(Some(token.to_string()));
+            }
+            _ => {
+                // the result of an incr or decr, or the stats in json format
+                if TERMINAL_TOKENS.contains(&token)
+                    || token.parse::<u64>().is_ok()
+                    || token.starts_with('{')
+                {
+                    return Ok(Some(token.to_string()));
+                }
+            }
+        }
+    }
+}
+
+// Reads and prints the data block which follows a line, where `bytes` is the
+// token which holds its length.
+fn print_data<R: Read>(reader: &mut BufReader<R>, bytes: Option<&str>) -> Result<()> {
+    let bytes = bytes.and_then(|v| v.parse::<usize>().ok()).unwrap_or(0);
+    let mut data = vec![0; bytes + 2];
+    reader.read_exact(&mut data)?;
+    data.truncate(bytes);
+    println!("{}", String::from_utf8_lossy(&data));
+    Ok(())
+}
+
+// The history is kept in the home directory of the user, if there is one.
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".segcache_history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // reads a response and returns the line which follows it
+    fn next_line(reader: &mut BufReader<&[u8]>) -> String {
+        assert!(print_response(reader).expect("failed to read").is_some());
+        let mut line = String::new();
+        reader.read_line(&mut line).expect("failed to read");
+        line
+    }
+
+    #[test]
+    fn response_boundaries() {
+        let responses: &[&[u8]] = &[
+            b"VALUE 0 0 3\r\nEND\r\nEND\r\n",
+            b"VA 5 t-1\r\nEND\r\n\r\n",
+            b"FINGERPRINT 0000000000000001 0000000000000002 3\r\nEND\r\n",
+            b"CHAIN_LEN 1 buckets=3\r\nOCCUPANCY 1 buckets=3\r\nEND\r\n",
+            b"version 0.1.0\r\ntarget x86_64\r\nEND\r\n",
+            b"token abc\r\nexpires 60\r\nitems 3\r\nEND\r\n",
+            b"items=3\r\nEND\r\n",
+            b"{\"items\":3}\r\n",
+            b"42\r\n",
+            b"HD c1\r\n",
+            b"STORED\r\n",
+        ];
+
+        for response in responses {
+            let mut input = response.to_vec();
+            input.extend_from_slice(b"NEXT\r\n");
+            let mut reader = BufReader::new(&input[..]);
+            assert_eq!(
+                next_line(&mut reader),
+                "NEXT\r\n",
+                "response: {}",
+                String::from_utf8_lossy(response)
+            );
+        }
+
+        // the server closed the connection
+        let mut reader = BufReader::new(&b"STAT items 3\r\n"[..]);
+        assert!(print_response(&mut reader)
+            .expect("failed to read")
+            .is_none());
+    }
+
+    #[test]
+    fn quiet_meta() {
+        // the replies to a quiet request end with the reply to the no-op
+        let input = b"NS\r\nMN\r\nNEXT\r\n";
+        let mut reader = BufReader::new(&input[..]);
+        assert!(print_responses(&mut reader, true).expect("failed to read"));
+        let mut line = String::new();
+        reader.read_line(&mut line).expect("failed to read");
+        assert_eq!(line, "NEXT\r\n");
+
+        let mut reader = BufReader::new(&input[..]);
+        assert!(print_responses(&mut reader, false).expect("failed to read"));
+        let mut line = String::new();
+        reader.read_line(&mut line).expect("failed to read");
+        assert_eq!(line, "MN\r\n");
+    }
+}
@@ -5,49 +5,212 @@
 //! Segcache is a cache implementation which used segment based storage and uses
 //! a subset of the Memcache protocol. Segment based storage allows us to
 //! perform efficient eager expiration of items.
+//!
+//! The data port may alternatively serve a simple HTTP/1.1 REST API by setting
+//! `protocol = "http"` in the configuration. See the `protocol-http` crate for
+//! details of the API.
+//!
+//! Builds with the `grpc` feature may also serve a gRPC API from the same
+//! storage as the memcache data port, by enabling it in the `grpc` section of
+//! the configuration. See the `grpc` crate for details of the API.
+//!
+//! In addition to the standalone binary, this library may be used to run a
+//! Segcache instance within another process. The returned handle can be used
+//! to inspect the bound addresses and metrics, and to shutdown the instance:
+//!
+//! ```no_run
+//! use config::SegcacheConfig;
+//! use pelikan_segcache_rs::Segcache;
+//!
+//! let server = Segcache::new(SegcacheConfig::default()).expect("failed to launch");
+//! println!("listening on: {:?}", server.listen_addr());
+//! server.shutdown();
+//! ```
 
+use config::segcache::Protocol;
 use config::*;
 use entrystore::Seg;
 use logger::*;
-use protocol_memcache::{Request, RequestParser, Response};
-use server::{Process, ProcessBuilder};
+use serde_json::json;
+use server_bootstrap::{ConfigLoader, Process, ServerBuilder};
+use std::net::SocketAddr;
 
-type Parser = RequestParser;
 type Storage = Seg;
 
+/// Features which were enabled when this binary was compiled. This must list
+/// every feature of this crate.
+const FEATURES: &[(&str, bool)] = &[
+    ("debug", cfg!(feature = "debug")),
+    ("quic", cfg!(feature = "quic")),
+    ("grpc", cfg!(feature = "grpc")),
+    ("profiling", cfg!(feature = "profiling")),
+    ("shaping", cfg!(feature = "shaping")),
+    ("handshake-backoff", cfg!(feature = "handshake-backoff")),
+    ("arithmetic", cfg!(feature = "arithmetic")),
+    ("flush", cfg!(feature = "flush")),
+    ("meta", cfg!(feature = "meta")),
+    ("admin-flush", cfg!(feature = "admin-flush")),
+];
+
+/// Returns a machine-readable description of this build, including the
+/// protocols it speaks, the storage engine, and the compiled-in features. This
+/// allows orchestration to gate rollouts on capabilities rather than parsing
+/// version strings. Protocols which are not supported have a `null` version.
+pub fn version_info() -> serde_json::Value {
+    let features: Vec<&str> = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect();
+
+    json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "protocols": {
+            "memcache_text": protocol_memcache::VERSION,
+            "resp": null,
+            "http": protocol_http::VERSION,
+        },
+        "storage": {
+            "engine": "seg",
+            "version": entrystore::SEG_VERSION,
+        },
+        "features": features,
+    })
+}
+
 /// This structure represents a running `Segcache` process.
-#[allow(dead_code)]
 pub struct Segcache {
     process: Process,
+    #[cfg(feature = "grpc")]
+    grpc: Option<grpc::GrpcServer>,
 }
 
 impl Segcache {
     /// Creates a new `Segcache` process from the given `SegcacheConfig`.
     pub fn new(config: SegcacheConfig) -> Result<Self, std::io::Error> {
-        // initialize logging
-        let log_drain = configure_logging(&config);
+        Self::launch(config, None)
+    }
+
+    /// Creates a new `Segcache` process from a `SegcacheConfig` which was
+    /// loaded from the given file. The `reload` admin command reads the file
+    /// again to apply any changes to the settings which can be reloaded.
+    pub fn with_config_file(config: SegcacheConfig, file: &str) -> Result<Self, std::io::Error> {
+        let file = file.to_string();
+        let loader: ConfigLoader = Box::new(move || {
+            SegcacheConfig::load(&file).map(|config| Box::new(config) as Box<dyn ReloadConfig>)
+        });
+
+        Self::launch(config, Some(loader))
+    }
+
+    fn launch(
+        config: SegcacheConfig,
+        loader: Option<ConfigLoader>,
+    ) -> Result<Self, std::io::Error> {
+        // initialize logging and metrics
+        let mut builder = ServerBuilder::new(&config).version(env!("CARGO_PKG_VERSION"));
+        if let Some(loader) = loader {
+            builder = builder.config_loader(loader);
+        }
 
-        // initialize metrics
-        common::metrics::init();
+        info!(
+            "{} {} (storage: seg {}, protocol: {:?})",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            entrystore::SEG_VERSION,
+            config.protocol(),
+        );
 
         // initialize storage
         let storage = Storage::new(&config)?;
 
-        // initialize parser
-        let parser = Parser::new()
-            .max_value_size(config.seg().segment_size() as usize)
-            .time_type(config.time().time_type());
+        let max_value_size = config.seg().segment_size() as usize;
+
+        if config.grpc().enabled() && !cfg!(feature = "grpc") {
+            warn!("grpc is enabled in the config, but this build does not include it");
+        }
+        if config.grpc().enabled() && config.protocol() != Protocol::Memcache {
+            warn!("grpc is only served alongside the memcache protocol");
+        }
+
+        // bind the grpc listener before any threads are spawned, so that a
+        // failure to bind leaves nothing running
+        #[cfg(feature = "grpc")]
+        let grpc_listener = if config.grpc().enabled() && config.protocol() == Protocol::Memcache {
+            Some(std::net::TcpListener::bind(config.grpc().socket_addr()?)?)
+        } else {
+            None
+        };
 
-        // initialize process
-        let process_builder = ProcessBuilder::<Parser, Request, Response, Storage>::new(
-            &config, log_drain, parser, storage,
-        )?
-        .version(env!("CARGO_PKG_VERSION"));
+        // initialize parser and spawn threads for the configured protocol
+        let (process, client) = match config.protocol() {
+            Protocol::Memcache => {
+                let parser = protocol_memcache::RequestParser::new()
+                    .max_value_size(max_value_size)
+                    .time_type(config.time().time_type())
+                    .strict(config.server().strict_protocol());
 
-        // spawn threads
-        let process = process_builder.spawn();
+                let (process, client) = builder
+                    .spawn_with_client::<_, protocol_memcache::Request, _, _>(parser, storage)?;
+                (process, Some(client))
+            }
+            Protocol::Http => {
+                let parser = protocol_http::RequestParser::new().max_value_size(max_value_size);
 
-        Ok(Self { process })
+                let process = builder.spawn::<_, protocol_http::Request, _, _>(parser, storage)?;
+                (process, None)
+            }
+        };
+
+        #[cfg(feature = "grpc")]
+        let grpc = match (client, grpc_listener) {
+            (Some(client), Some(listener)) => {
+                let grpc = config.grpc();
+                let server = grpc::GrpcServerBuilder::new(listener.local_addr()?, client)
+                    .threads(grpc.threads())
+                    .timeout(std::time::Duration::from_millis(grpc.timeout() as u64))
+                    .require_namespace(grpc.require_namespace())
+                    .spawn_on(listener)?;
+                info!("serving grpc on: {}", server.local_addr());
+                Some(server)
+            }
+            _ => None,
+        };
+        #[cfg(not(feature = "grpc"))]
+        drop(client);
+
+        Ok(Self {
+            process,
+            #[cfg(feature = "grpc")]
+            grpc,
+        })
+    }
+
+    /// Returns the address the data port is bound to.
+    pub fn listen_addr(&self) -> Option<SocketAddr> {
+        self.process.listen_addr()
+    }
+
+    /// Returns the address the admin port is bound to.
+    pub fn admin_addr(&self) -> Option<SocketAddr> {
+        self.process.admin_addr()
+    }
+
+    /// Returns the address the gRPC front end is bound to, if it is enabled.
+    #[cfg(feature = "grpc")]
+    pub fn grpc_addr(&self) -> Option<SocketAddr> {
+        self.grpc.as_ref().map(|grpc| grpc.local_addr())
+    }
+
+    /// Returns the current value of the named counter, if it exists.
+    pub fn counter(&self, name: &str) -> Option<u64> {
+        self.process.counter(name)
+    }
+
+    /// Returns the current value of the named gauge, if it exists.
+    pub fn gauge(&self, name: &str) -> Option<i64> {
+        self.process.gauge(name)
     }
 
     /// Wait for all threads to complete. Blocks until the process has fully
@@ -60,8 +223,41 @@ impl Segcache {
     /// fully terminated. This is more likely to be used for running integration
     /// tests or other automated testing.
     pub fn shutdown(self) {
+        #[cfg(feature = "grpc")]
+        if let Some(grpc) = self.grpc {
+            grpc.shutdown();
+        }
         self.process.shutdown()
     }
 }
 
 common::metrics::test_no_duplicates!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features() {
+        // every feature in the manifest must be reported
+        let manifest = include_str!("../Cargo.toml");
+        let section = manifest
+            .split("[features]")
+            .nth(1)
+            .and_then(|s| s.split("\n[").next())
+            .expect("no features section");
+        for line in section.lines() {
+            if let Some((name, _)) = line.split_once('=') {
+                let name = name.trim();
+                if name == "default" || name.starts_with('#') {
+                    continue;
+                }
+                assert!(
+                    FEATURES.iter().any(|(feature, _)| *feature == name),
+                    "feature {} is not reported",
+                    name
+                );
+            }
+        }
+    }
+}
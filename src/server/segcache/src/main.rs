@@ -10,96 +10,93 @@
 //! More details about the benefits of this design can be found in this
 //! [blog post](https://twitter.github.io/pelikan/2021/segcache.html).
 //!
-//! Running this binary is the primary way of using Segcache.
+//! Running this binary is the primary way of using Segcache. It also provides
+//! an interactive client, `connect <addr>`, which can be used with either the
+//! data port or the admin port.
 
-#[macro_use]
-extern crate logger;
+mod cli;
 
-use backtrace::Backtrace;
-use clap::{App, Arg};
+use clap::{AppSettings, Arg, SubCommand};
 use config::SegcacheConfig;
-use pelikan_segcache_rs::Segcache;
-use rustcommon_metrics::*;
-use server::PERCENTILES;
+use pelikan_segcache_rs::{version_info, Segcache};
+use server_bootstrap::cli as bootstrap;
 
 /// The entry point into the running Segcache instance. This function parses the
 /// command line options, loads the configuration, and launches the core
 /// threads.
 fn main() {
     // custom panic hook to terminate whole process after unwinding
-    std::panic::set_hook(Box::new(|s| {
-        error!("{}", s);
-        println!("{:?}", Backtrace::new());
-        std::process::exit(101);
-    }));
+    bootstrap::set_panic_hook();
 
     // parse command line options
-    let matches = App::new(env!("CARGO_BIN_NAME"))
-        .version(env!("CARGO_PKG_VERSION"))
-        .version_short("v")
-        .long_about(
-            "One of the unified cache backends implemented in Rust. It \
-            uses segment-based storage to cache key/val pairs. It speaks the \
-            memcached ASCII protocol and supports some ASCII memcached \
-            commands.",
-        )
-        .arg(
-            Arg::with_name("stats")
-                .short("s")
-                .long("stats")
-                .help("List all metrics in stats")
-                .takes_value(false),
-        )
-        .arg(
-            Arg::with_name("CONFIG")
-                .help("Server configuration file")
-                .index(1),
-        )
-        .arg(
-            Arg::with_name("print-config")
-                .help("List all options in config")
-                .long("config")
-                .short("c"),
-        )
-        .get_matches();
+    let matches = bootstrap::app(
+        env!("CARGO_BIN_NAME"),
+        "One of the unified cache backends implemented in Rust. It \
+        uses segment-based storage to cache key/val pairs. It speaks the \
+        memcached ASCII protocol and supports some ASCII memcached \
+        commands.",
+    )
+    .version(env!("CARGO_PKG_VERSION"))
+    .setting(AppSettings::DisableVersion)
+    .arg(
+        Arg::with_name("version")
+            .short("v")
+            .long("version")
+            .help("Prints version information"),
+    )
+    .arg(
+        Arg::with_name("json")
+            .long("json")
+            .requires("version")
+            .help("Prints version information as JSON, including capabilities"),
+    )
+    .arg(
+        Arg::with_name("print-config")
+            .help("List all options in config")
+            .long("config")
+            .short("c"),
+    )
+    .subcommand(
+        SubCommand::with_name("connect")
+            .about("Interactive client for the data or admin port")
+            .arg(
+                Arg::with_name("ADDR")
+                    .help("Address of the server, for example 127.0.0.1:12321")
+                    .required(true)
+                    .index(1),
+            ),
+    )
+    .get_matches();
 
-    // output stats descriptions and exit if the `stats` option was provided
-    if matches.is_present("stats") {
-        println!("{:<31} {:<15} DESCRIPTION", "NAME", "TYPE");
-
-        let mut metrics = Vec::new();
-
-        for metric in &rustcommon_metrics::metrics() {
-            let any = match metric.as_any() {
-                Some(any) => any,
-                None => {
-                    continue;
-                }
-            };
-
-            if any.downcast_ref::<Counter>().is_some() {
-                metrics.push(format!("{:<31} counter", metric.name()));
-            } else if any.downcast_ref::<Gauge>().is_some() {
-                metrics.push(format!("{:<31} gauge", metric.name()));
-            } else if any.downcast_ref::<Heatmap>().is_some() {
-                for (label, _) in PERCENTILES {
-                    let name = format!("{}_{}", metric.name(), label);
-                    metrics.push(format!("{:<31} percentile", name));
-                }
-            } else {
-                continue;
-            }
+    // run the interactive client and exit if the `connect` subcommand was used
+    if let Some(matches) = matches.subcommand_matches("connect") {
+        let addr = matches.value_of("ADDR").unwrap();
+        if let Err(e) = cli::connect(addr) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
         }
+        std::process::exit(0);
+    }
 
-        metrics.sort();
-        for metric in metrics {
-            println!("{}", metric);
+    // output version information and exit if the `version` option was provided
+    if matches.is_present("version") {
+        if matches.is_present("json") {
+            println!("{}", version_info());
+        } else {
+            println!("{} {}", env!("CARGO_BIN_NAME"), env!("CARGO_PKG_VERSION"));
         }
         std::process::exit(0);
     }
 
+    // output stats descriptions and exit if the `stats` option was provided
+    if matches.is_present("stats") {
+        bootstrap::print_metrics();
+        std::process::exit(0);
+    }
+
     // load config from file
-    let config = if let Some(file) = matches.value_of("CONFIG") {
+    let file = matches.value_of("CONFIG");
+    let config = if let Some(file) = file {
         match SegcacheConfig::load(file) {
             Ok(c) => c,
             Err(e) => {
@@ -116,8 +113,17 @@ fn main() {
         std::process::exit(0);
     }
 
+    // detach and redirect output before any threads are launched, holding
+    // the pidfile until exit so that it is removed on shutdown
+    let _pidfile = bootstrap::daemonize(&config, "segcache");
+
     // launch segcache
-    match Segcache::new(config) {
+    let segcache = match file {
+        Some(file) => Segcache::with_config_file(config, file),
+        None => Segcache::new(config),
+    };
+
+    match segcache {
         Ok(segcache) => segcache.wait(),
         Err(e) => {
             println!("error launching segcache: {}", e);
@@ -6,12 +6,49 @@
 //! tests against a Segcache instance. This allows us to run the same test suite
 //! for multiple server configurations.
 
+use boring::ssl::SslConnector;
 use logger::*;
 
+use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::net::TcpStream;
+use std::sync::Mutex;
 use std::time::Duration;
 
+// when set, sessions on the data port are established over tls
+static TLS_CONNECTOR: Mutex<Option<SslConnector>> = Mutex::new(None);
+
+/// Runs the data port tests over TLS, using the connector for the handshake of
+/// each session.
+#[allow(dead_code)]
+pub fn use_tls(connector: SslConnector) {
+    *TLS_CONNECTOR.lock().unwrap() = Some(connector);
+}
+
+pub trait Stream: Read + Write {}
+
+impl<T: Read + Write> Stream for T {}
+
+// opens a new session to the data port
+fn connect() -> Box<dyn Stream> {
+    let stream = TcpStream::connect("127.0.0.1:12321").expect("failed to connect");
+    stream
+        .set_read_timeout(Some(Duration::from_millis(250)))
+        .expect("failed to set read timeout");
+    stream
+        .set_write_timeout(Some(Duration::from_millis(250)))
+        .expect("failed to set write timeout");
+
+    match TLS_CONNECTOR.lock().unwrap().as_ref() {
+        Some(connector) => Box::new(
+            connector
+                .connect("localhost", stream)
+                .expect("tls handshake failed"),
+        ),
+        None => Box::new(stream),
+    }
+}
+
 pub fn tests() {
     debug!("beginning tests");
     println!();
@@ -127,7 +164,13 @@ pub fn tests() {
         "set value (key: 9)",
         &[("set 9 0 0 1\r\na\r\n", Some("STORED\r\n"))],
     );
-    test("incr (key: 9)", &[("incr 9 1\r\n", Some("ERROR\r\n"))]);
+    test(
+        "incr (key: 9)",
+        &[(
+            "incr 9 1\r\n",
+            Some("CLIENT_ERROR cannot increment or decrement non-numeric value\r\n"),
+        )],
+    );
 
     // test decrement
     test(
@@ -145,33 +188,234 @@ pub fn tests() {
         "set value (key: 10)",
         &[("set 10 0 0 1\r\na\r\n", Some("STORED\r\n"))],
     );
-    test("decr (key: 10)", &[("decr 10 1\r\n", Some("ERROR\r\n"))]);
+    test(
+        "decr (key: 10)",
+        &[(
+            "decr 10 1\r\n",
+            Some("CLIENT_ERROR cannot increment or decrement non-numeric value\r\n"),
+        )],
+    );
 
-    // test unsupported commands
+    // test append and prepend
     test(
         "append (key: 7)",
-        &[("append 7 0 0 1\r\n0\r\n", Some("ERROR\r\n"))],
+        &[("append 7 0 0 1\r\n0\r\n", Some("NOT_STORED\r\n"))],
+    );
+    test(
+        "set value (key: 7)",
+        &[("set 7 42 0 1\r\nb\r\n", Some("STORED\r\n"))],
+    );
+    test(
+        "append (key: 7)",
+        &[("append 7 0 0 1\r\nc\r\n", Some("STORED\r\n"))],
+    );
+    test(
+        "prepend (key: 7)",
+        &[("prepend 7 0 0 1\r\na\r\n", Some("STORED\r\n"))],
+    );
+    test(
+        "get value (key: 7)",
+        &[("get 7\r\n", Some("VALUE 7 42 3\r\nabc\r\nEND\r\n"))],
     );
     test(
         "prepend (key: 8)",
-        &[("prepend 8 0 0 1\r\n0\r\n", Some("ERROR\r\n"))],
+        &[("prepend 8 0 0 1\r\n0\r\n", Some("NOT_STORED\r\n"))],
+    );
+
+    // test touch
+    test(
+        "touch (key: 11)",
+        &[("touch 11 60\r\n", Some("NOT_FOUND\r\n"))],
+    );
+    test(
+        "set value (key: 11)",
+        &[("set 11 5 60 5\r\nvalue\r\n", Some("STORED\r\n"))],
+    );
+    test(
+        "touch (key: 11)",
+        &[("touch 11 3600\r\n", Some("TOUCHED\r\n"))],
+    );
+    test(
+        "get value (key: 11)",
+        &[("get 11\r\n", Some("VALUE 11 5 5\r\nvalue\r\nEND\r\n"))],
+    );
+    test(
+        "touch (key: 11)",
+        &[("touch 11 -1\r\n", Some("TOUCHED\r\n"))],
+    );
+    test("get value (key: 11)", &[("get 11\r\n", Some("END\r\n"))]);
+
+    // a touch writes the item again, so it has a new cas value
+    test(
+        "set value (key: 12)",
+        &[("set 12 0 60 5\r\nvalue\r\n", Some("STORED\r\n"))],
+    );
+    test(
+        "gets value (key: 12)",
+        &[("gets 12\r\n", Some("VALUE 12 0 5 1\r\nvalue\r\nEND\r\n"))],
+    );
+    test(
+        "touch (key: 12)",
+        &[("touch 12 3600\r\n", Some("TOUCHED\r\n"))],
+    );
+    test(
+        "gets value (key: 12)",
+        &[("gets 12\r\n", Some("VALUE 12 0 5 2\r\nvalue\r\nEND\r\n"))],
+    );
+    test(
+        "cas fail (key: 12)",
+        &[("cas 12 0 0 1 1\r\n1\r\n", Some("EXISTS\r\n"))],
+    );
+    test(
+        "cas success (key: 12)",
+        &[("cas 12 0 0 1 2\r\n1\r\n", Some("STORED\r\n"))],
+    );
+
+    // test gat and gats
+    test("gat (key: 12)", &[("gat 60 12\r\n", Some("END\r\n"))]);
+    test(
+        "set value (key: 12)",
+        &[("set 12 3 60 1\r\n0\r\n", Some("STORED\r\n"))],
+    );
+    test(
+        "gat (key: 12)",
+        &[("gat 3600 12 13\r\n", Some("VALUE 12 3 1\r\n0\r\nEND\r\n"))],
+    );
+    test(
+        "gat (key: 12)",
+        &[("gat -1 12\r\n", Some("VALUE 12 3 1\r\n0\r\nEND\r\n"))],
+    );
+    test("get value (key: 12)", &[("get 12\r\n", Some("END\r\n"))]);
+
+    // test the meta commands
+    test("mg (key: 14)", &[("mg 14 v\r\n", Some("EN\r\n"))]);
+    test(
+        "ms (key: 14)",
+        &[("ms 14 5 F3 T60\r\nvalue\r\n", Some("HD\r\n"))],
+    );
+    test(
+        "mg (key: 14)",
+        &[("mg 14 v f s k\r\n", Some("VA 5 k14 f3 s5\r\nvalue\r\n"))],
+    );
+    test(
+        "ms add (key: 14)",
+        &[("ms 14 1 ME\r\nx\r\n", Some("NS\r\n"))],
+    );
+    // quiet successes are not answered, so the no-op is the only reply
+    test(
+        "ms append (key: 14)",
+        &[("ms 14 1 MA q\r\n!\r\nmn\r\n", Some("MN\r\n"))],
+    );
+    test(
+        "mg (key: 14)",
+        &[("mg 14 v\r\n", Some("VA 6\r\nvalue!\r\n"))],
+    );
+    test("md (key: 14)", &[("md 14 O1\r\n", Some("HD O1\r\n"))]);
+    test("md (key: 14)", &[("md 14\r\n", Some("NF\r\n"))]);
+    test("mg (key: 14)", &[("mg 14 q\r\nmn\r\n", Some("MN\r\n"))]);
+
+    // items without an expiry have no TTL, and the first read is not a hit
+    // on an item which had been read before
+    test(
+        "mg ttl and hit (key: 14)",
+        &[
+            ("ms 14 5 T0\r\nvalue\r\n", Some("HD\r\n")),
+            ("mg 14 t h\r\n", Some("HD t-1 h0\r\n")),
+            ("mg 14 t h\r\n", Some("HD t-1 h1\r\n")),
+            ("md 14\r\n", Some("HD\r\n")),
+        ],
+    );
+
+    // the counters of a session only include its own requests
+    test(
+        "stats me (key: 15)",
+        &[
+            ("set 15 0 0 1\r\n1\r\n", Some("STORED\r\n")),
+            ("get 15 16\r\n", Some("VALUE 15 0 1\r\n1\r\nEND\r\n")),
+            (
+                "stats me\r\n",
+                Some("STAT requests 3\r\nSTAT hits 1\r\nSTAT misses 1\r\nSTAT errors 0\r\n"),
+            ),
+        ],
+    );
+
+    // the values of the other groups change as the tests run, so they are
+    // compared before and after an item is stored
+    test("stats", &[("stats\r\n", Some("STAT pid "))]);
+    let general = stats("stats");
+    let items = stats("stats items");
+    test(
+        "set value (key: 17)",
+        &[("set 17 0 60 1\r\n1\r\n", Some("STORED\r\n"))],
+    );
+
+    info!("testing: stats after set (key: 17)");
+    assert_eq!(
+        stat(&stats("stats"), "curr_items"),
+        stat(&general, "curr_items") + 1
+    );
+    // an item with a TTL of 60 seconds is held in the TTL bucket with index 7
+    let after = stats("stats items");
+    assert_eq!(
+        stat(&after, "items:7:number"),
+        stat(&items, "items:7:number") + 1
+    );
+    assert!(after.contains_key("items:7:age"));
+    assert!(stat(&stats("stats slabs"), "7:total_pages") > 0);
+    info!("status: passed\n");
+
+    // stats sizes reads every item, so it is only served if enabled
+    test(
+        "stats sizes",
+        &[("stats sizes\r\n", Some("CLIENT_ERROR command disabled\r\n"))],
+    );
+
+    test(
+        "delete (key: 17)",
+        &[("delete 17\r\n", Some("DELETED\r\n"))],
     );
 
     std::thread::sleep(Duration::from_millis(500));
 }
 
+// sends a stats request on a new session and returns the value of each stat
+fn stats(request: &str) -> HashMap<String, String> {
+    let mut stream = connect();
+    stream
+        .write_all(format!("{}\r\n", request).as_bytes())
+        .expect("failed to send request");
+
+    let mut response = Vec::new();
+    let mut buf = vec![0; 4096];
+    while !response.ends_with(b"END\r\n") {
+        let bytes = stream.read(&mut buf).expect("failed to read response");
+        assert!(bytes > 0, "connection closed");
+        response.extend_from_slice(&buf[0..bytes]);
+    }
+
+    String::from_utf8(response)
+        .expect("response is not utf8")
+        .lines()
+        .filter_map(|line| line.strip_prefix("STAT "))
+        .filter_map(|line| line.split_once(' '))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+// the value of a numeric stat, where a missing stat is zero
+fn stat(stats: &HashMap<String, String>, name: &str) -> u64 {
+    stats
+        .get(name)
+        .map(|value| value.parse().expect("stat is not numeric"))
+        .unwrap_or(0)
+}
+
 // opens a new connection, operating on request + response pairs from the
 // provided data.
 fn test(name: &str, data: &[(&str, Option<&str>)]) {
     info!("testing: {}", name);
     debug!("connecting to server");
-    let mut stream = TcpStream::connect("127.0.0.1:12321").expect("failed to connect");
-    stream
-        .set_read_timeout(Some(Duration::from_millis(250)))
-        .expect("failed to set read timeout");
-    stream
-        .set_write_timeout(Some(Duration::from_millis(250)))
-        .expect("failed to set write timeout");
+    let mut stream = connect();
 
     debug!("sending request");
     for (request, response) in data {
@@ -238,10 +482,52 @@ pub fn admin_tests() {
             Some(&format!("VERSION {}\r\n", env!("CARGO_PKG_VERSION"))),
         )],
     );
+
+    // the data port has served the data tests under its default name
+    admin_test(
+        "stats listeners",
+        &[("stats listeners\r\n", Some("STAT data:request "))],
+    );
+
+    admin_test(
+        "buildinfo",
+        &[(
+            "buildinfo\r\n",
+            Some(&format!(
+                "version {}\r\ncommit {}\r\n",
+                env!("CARGO_PKG_VERSION"),
+                common::buildinfo::COMMIT
+            )),
+        )],
+    );
+
+    // a dry run does not flush, and a token which was never issued is refused
+    admin_test(
+        "flush_all dry run",
+        &[
+            ("flush_all --dry-run\r\n", Some("token ")),
+            (
+                "flush_all --confirm 0123456789abcdef\r\n",
+                Some("CLIENT_ERROR invalid or expired confirmation token\r\n"),
+            ),
+        ],
+    );
+
+    // stale items are only served if a grace period is configured
+    admin_test(
+        "stale",
+        &[
+            (
+                "stale on\r\n",
+                Some("CLIENT_ERROR serving stale items is not configured\r\n"),
+            ),
+            ("stale off\r\n", Some("OK\r\n")),
+        ],
+    );
 }
 
 // opens a new connection to the admin port, sends a request, and checks the response.
-fn admin_test(name: &str, data: &[(&str, Option<&str>)]) {
+pub fn admin_test(name: &str, data: &[(&str, Option<&str>)]) {
     info!("testing: {}", name);
     debug!("connecting to server");
     let mut stream = TcpStream::connect("127.0.0.1:9999").expect("failed to connect");
@@ -29,6 +29,11 @@ fn main() {
 
     admin_tests();
 
+    // the handle exposes the bound addresses and the metrics for the instance
+    assert!(server.listen_addr().is_some());
+    assert!(server.admin_addr().is_some());
+    assert!(server.counter("tcp_accept").unwrap_or(0) > 0);
+
     // shutdown server and join
     info!("shutdown...");
     let _ = server.shutdown();
@@ -0,0 +1,127 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! This test module checks that the gRPC front end serves the same storage as
+//! the memcache data port, both when the single worker owns the storage and
+//! when it is owned by a storage thread.
+
+#[macro_use]
+extern crate logger;
+
+use config::SegcacheConfig;
+use grpc::proto::cache_client::CacheClient;
+use grpc::proto::{DeleteRequest, GetRequest, SetRequest};
+use pelikan_segcache_rs::Segcache;
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::Duration;
+
+fn main() {
+    let dir = tempfile::tempdir().expect("failed to create temporary directory");
+
+    for threads in [1, 2] {
+        debug!("launching server with {} worker threads", threads);
+        let server = launch(dir.path(), threads);
+
+        shared_storage(&server);
+
+        info!("shutdown...");
+        server.shutdown();
+    }
+
+    info!("passed!");
+}
+
+fn launch(dir: &Path, threads: usize) -> Segcache {
+    let config_file = dir.join(format!("grpc_{}.toml", threads));
+    std::fs::write(
+        &config_file,
+        format!(
+            "[grpc]\nenabled = true\nhost = \"127.0.0.1\"\nport = \"0\"\n\n\
+            [worker]\nthreads = {}\n",
+            threads
+        ),
+    )
+    .unwrap();
+
+    let config =
+        SegcacheConfig::load(config_file.to_str().unwrap()).expect("failed to load config");
+    let server = Segcache::new(config).expect("failed to launch segcache");
+
+    // wait for server to startup. duration is chosen to be longer than we'd
+    // expect startup to take in a slow ci environment.
+    std::thread::sleep(Duration::from_secs(10));
+
+    server
+}
+
+// sends a request to the memcache data port and returns the response
+fn memcache(request: &str) -> String {
+    let mut stream = TcpStream::connect("127.0.0.1:12321").expect("failed to connect");
+    stream
+        .set_read_timeout(Some(Duration::from_millis(250)))
+        .expect("failed to set read timeout");
+    stream.write_all(request.as_bytes()).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    let mut buf = vec![0; 4096];
+    let len = stream.read(&mut buf).expect("failed to read response");
+    String::from_utf8_lossy(&buf[0..len]).to_string()
+}
+
+fn shared_storage(server: &Segcache) {
+    let addr = server.grpc_addr().expect("grpc is not enabled");
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let mut client = CacheClient::connect(format!("http://{}", addr))
+            .await
+            .expect("failed to connect");
+
+        // written on the data port and read through grpc
+        assert_eq!(memcache("set 0 7 0 5\r\nvalue\r\n"), "STORED\r\n");
+        let item = client
+            .get(GetRequest { key: b"0".to_vec() })
+            .await
+            .expect("get failed")
+            .into_inner()
+            .item
+            .expect("item not found");
+        assert_eq!(item.value, b"value");
+        assert_eq!(item.flags, 7);
+
+        // written through grpc and read on the data port
+        client
+            .set(SetRequest {
+                key: b"1".to_vec(),
+                value: b"other".to_vec(),
+                flags: 0,
+                ttl: 0,
+            })
+            .await
+            .expect("set failed");
+        assert_eq!(memcache("get 1\r\n"), "VALUE 1 0 5\r\nother\r\nEND\r\n");
+
+        // deleted through grpc
+        let response = client
+            .delete(DeleteRequest { key: b"0".to_vec() })
+            .await
+            .expect("delete failed");
+        assert!(response.into_inner().deleted);
+        assert_eq!(memcache("get 0\r\n"), "END\r\n");
+
+        // and flushed on the data port
+        assert_eq!(memcache("flush_all\r\n"), "OK\r\n");
+        let response = client
+            .get(GetRequest { key: b"1".to_vec() })
+            .await
+            .expect("get failed");
+        assert_eq!(response.into_inner().item, None);
+    });
+}
@@ -0,0 +1,297 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! This test module runs the integration test suite against instances of
+//! Segcache which use TLS on the data port, first with server authentication
+//! only and then with mutual TLS. The CA and the certificates are generated
+//! for each run, so no keys are kept in the repository.
+//!
+//! Beyond the suite itself, this covers session resumption and the rotation of
+//! the server certificate through the `reload tls` admin command.
+
+#[macro_use]
+extern crate logger;
+
+mod common;
+
+use crate::common::*;
+
+use boring::ssl::{SslConnector, SslFiletype, SslMethod, SslSession, SslSessionCacheMode};
+use boring::x509::X509;
+use config::SegcacheConfig;
+use pelikan_segcache_rs::Segcache;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa};
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn main() {
+    let dir = tempfile::tempdir().expect("failed to create temporary directory");
+    let ca = ca();
+    std::fs::write(dir.path().join("ca.pem"), ca.serialize_pem().unwrap()).unwrap();
+
+    tls(dir.path(), &ca);
+
+    mtls(dir.path(), &ca);
+
+    info!("passed!");
+}
+
+// runs the suite with the server authenticated by a certificate from the ca,
+// then checks that sessions are resumed and that the certificate is rotated
+fn tls(dir: &Path, ca: &Certificate) {
+    debug!("launching tls server");
+    let server_cert = issue(ca, "server", dir, "server");
+    let server = launch(dir, "tls", false);
+
+    let connector = connector(dir, None);
+    use_tls(connector.build());
+
+    tests();
+
+    admin_tests();
+
+    resumption(dir);
+
+    rotation(dir, ca, &server_cert);
+
+    info!("shutdown...");
+    let _ = server.shutdown();
+}
+
+// runs the suite with clients required to present a certificate from the ca
+fn mtls(dir: &Path, ca: &Certificate) {
+    debug!("launching mtls server");
+    issue(ca, "server", dir, "server");
+    issue(ca, "client", dir, "client");
+    let server = launch(dir, "mtls", true);
+
+    info!("testing: session without a client certificate");
+    assert!(
+        rejected(connector(dir, None).build()),
+        "session without a client certificate was accepted"
+    );
+    info!("status: passed\n");
+
+    use_tls(connector(dir, Some("client")).build());
+
+    tests();
+
+    info!("shutdown...");
+    let _ = server.shutdown();
+}
+
+// a session resumed with a ticket from an earlier session skips the full
+// handshake
+fn resumption(dir: &Path) {
+    info!("testing: session resumption");
+
+    let ticket: Arc<Mutex<Option<SslSession>>> = Arc::new(Mutex::new(None));
+    let mut connector = connector(dir, None);
+    connector.set_session_cache_mode(SslSessionCacheMode::CLIENT);
+    let latest = ticket.clone();
+    connector.set_new_session_callback(move |_, session| {
+        *latest.lock().unwrap() = Some(session);
+    });
+    let connector = connector.build();
+
+    // tls 1.3 tickets are sent after the handshake, so complete a request to
+    // receive one
+    let mut stream = connector
+        .connect("localhost", data_stream())
+        .expect("tls handshake failed");
+    assert!(!stream.ssl().session_reused());
+    request(&mut stream, "set resume 0 0 1\r\n1\r\n", "STORED\r\n");
+    drop(stream);
+
+    let session = ticket
+        .lock()
+        .unwrap()
+        .take()
+        .expect("no session ticket was received");
+
+    let mut config = connector.configure().unwrap();
+    // SAFETY: the session was created by a connection from the same context
+    unsafe {
+        config.set_session(&session).expect("failed to set session");
+    }
+    let mut stream = config
+        .connect("localhost", data_stream())
+        .expect("tls handshake failed");
+    assert!(stream.ssl().session_reused(), "session was not resumed");
+    request(
+        &mut stream,
+        "get resume\r\n",
+        "VALUE resume 0 1\r\n1\r\nEND\r\n",
+    );
+
+    info!("status: passed\n");
+}
+
+// the server certificate is replaced by `reload tls` without affecting the
+// sessions which are already established, and a failed reload leaves the
+// current certificate in place
+fn rotation(dir: &Path, ca: &Certificate, current: &str) {
+    info!("testing: certificate rotation");
+
+    let connector = connector(dir, None).build();
+    let mut established = connector
+        .connect("localhost", data_stream())
+        .expect("tls handshake failed");
+    assert_eq!(peer_certificate(&established), der(current));
+
+    // a reload with an unusable key is rejected
+    std::fs::write(dir.join("server.key"), "not a key").unwrap();
+    admin_test(
+        "reload tls (invalid key)",
+        &[("reload tls\r\n", Some("SERVER_ERROR"))],
+    );
+    let stream = connector
+        .connect("localhost", data_stream())
+        .expect("tls handshake failed");
+    assert_eq!(peer_certificate(&stream), der(current));
+
+    let rotated = issue(ca, "rotated", dir, "server");
+    admin_test("reload tls", &[("reload tls\r\n", Some("OK\r\n"))]);
+
+    // new sessions are given the new certificate
+    let mut stream = connector
+        .connect("localhost", data_stream())
+        .expect("tls handshake failed");
+    assert_eq!(peer_certificate(&stream), der(&rotated));
+    request(&mut stream, "set rotate 0 0 1\r\n1\r\n", "STORED\r\n");
+
+    // while the established session continues with the old one
+    request(
+        &mut established,
+        "get rotate\r\n",
+        "VALUE rotate 0 1\r\n1\r\nEND\r\n",
+    );
+
+    info!("status: passed\n");
+}
+
+// generates the certificate authority which signs every other certificate
+fn ca() -> Certificate {
+    let mut params = CertificateParams::new(vec![]);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "pelikan test ca");
+    Certificate::from_params(params).expect("failed to generate ca")
+}
+
+// issues a certificate for localhost, writing it and its key to `{file}.pem`
+// and `{file}.key`. Returns the certificate in PEM format.
+fn issue(ca: &Certificate, name: &str, dir: &Path, file: &str) -> String {
+    let mut params = CertificateParams::new(vec!["localhost".into()]);
+    params.distinguished_name.push(DnType::CommonName, name);
+    let cert = Certificate::from_params(params).expect("failed to generate certificate");
+
+    // the signature differs each time the certificate is serialized, so it is
+    // serialized once for both the file and the caller
+    let pem = cert
+        .serialize_pem_with_signer(ca)
+        .expect("failed to sign certificate");
+    std::fs::write(dir.join(format!("{}.pem", file)), &pem).unwrap();
+    std::fs::write(
+        dir.join(format!("{}.key", file)),
+        cert.serialize_private_key_pem(),
+    )
+    .unwrap();
+
+    pem
+}
+
+// launches a server which uses the server certificate from the directory
+fn launch(dir: &Path, name: &str, verify_client: bool) -> Segcache {
+    let config_file = dir.join(format!("{}.toml", name));
+    std::fs::write(
+        &config_file,
+        format!(
+            "[tls]\ncertificate_chain = \"{}\"\nprivate_key = \"{}\"\n\
+            ca_file = \"{}\"\nverify_client = {}\n",
+            dir.join("server.pem").display(),
+            dir.join("server.key").display(),
+            dir.join("ca.pem").display(),
+            verify_client,
+        ),
+    )
+    .unwrap();
+
+    let config =
+        SegcacheConfig::load(config_file.to_str().unwrap()).expect("failed to load config");
+    let server = Segcache::new(config).expect("failed to launch segcache");
+
+    // wait for server to startup. duration is chosen to be longer than we'd
+    // expect startup to take in a slow ci environment.
+    std::thread::sleep(Duration::from_secs(10));
+
+    server
+}
+
+// a connector which trusts the ca, presenting the named client certificate if
+// one is given
+fn connector(dir: &Path, client: Option<&str>) -> boring::ssl::SslConnectorBuilder {
+    let mut builder = SslConnector::builder(SslMethod::tls()).unwrap();
+    builder
+        .set_ca_file(dir.join("ca.pem"))
+        .expect("failed to load ca");
+    if let Some(client) = client {
+        builder
+            .set_certificate_file(dir.join(format!("{}.pem", client)), SslFiletype::PEM)
+            .expect("failed to load client certificate");
+        builder
+            .set_private_key_file(dir.join(format!("{}.key", client)), SslFiletype::PEM)
+            .expect("failed to load client key");
+    }
+    builder
+}
+
+fn data_stream() -> TcpStream {
+    let stream = TcpStream::connect("127.0.0.1:12321").expect("failed to connect");
+    stream
+        .set_read_timeout(Some(Duration::from_millis(250)))
+        .expect("failed to set read timeout");
+    stream
+}
+
+// returns true if the server refuses to handle requests from the session,
+// either by failing the handshake or by closing the session afterwards
+fn rejected(connector: SslConnector) -> bool {
+    match connector.connect("localhost", data_stream()) {
+        Err(_) => true,
+        Ok(mut stream) => {
+            let _ = stream.write_all(b"get 0\r\n");
+            let mut buf = [0; 64];
+            !matches!(stream.read(&mut buf), Ok(bytes) if bytes > 0)
+        }
+    }
+}
+
+fn request<S: Read + Write>(stream: &mut S, request: &str, response: &str) {
+    stream
+        .write_all(request.as_bytes())
+        .expect("error sending request");
+
+    let mut buf = vec![0; response.len()];
+    stream.read_exact(&mut buf).expect("error reading response");
+    assert_eq!(std::str::from_utf8(&buf).unwrap(), response);
+}
+
+fn peer_certificate<S: Read + Write>(stream: &boring::ssl::SslStream<S>) -> Vec<u8> {
+    stream
+        .ssl()
+        .peer_certificate()
+        .expect("no peer certificate")
+        .to_der()
+        .unwrap()
+}
+
+fn der(pem: &str) -> Vec<u8> {
+    X509::from_pem(pem.as_bytes()).unwrap().to_der().unwrap()
+}
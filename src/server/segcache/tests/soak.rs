@@ -0,0 +1,518 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A long-running soak test, which drives a mixed workload against an
+//! in-process instance of Segcache while periodically checking invariants
+//! that a short test would not catch being violated:
+//!
+//! * every value which is read is the last value written to its key, and keys
+//!   which were deleted stay deleted until they are written again
+//! * no counter ever goes backwards
+//! * the item and segment gauges are consistent with the size of the heap
+//! * the number of items in the hashtable matches the live item gauge
+//! * the resident memory of the process does not keep growing once the heap
+//!   has been filled
+//!
+//! The workload is paused while the invariants are checked, so that the
+//! metrics are not changing underneath the check. The test does nothing unless
+//! `SOAK_DURATION` is set, so that it can be built with the other tests and run
+//! on a schedule, for example:
+//!
+//! ```text
+//! SOAK_DURATION=14400 cargo test --release -p segcache --test soak
+//! ```
+//!
+//! The workload can be adjusted with `SOAK_CONNECTIONS` (default: 8),
+//! `SOAK_CHECK_INTERVAL` in seconds (default: 60), and `SOAK_RSS_GROWTH`, the
+//! percentage that resident memory may grow by after the first check (default:
+//! 10).
+
+use config::SegcacheConfig;
+use pelikan_segcache_rs::Segcache;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use rustcommon_metrics::Counter;
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+const HEAP_SIZE: usize = 64 * 1024 * 1024;
+const SEGMENT_SIZE: usize = 1024 * 1024;
+
+// the number of distinct keys used by each connection
+const KEYS: usize = 16384;
+
+// resident memory may also grow by this much, so that small heaps are not
+// flagged for allocator noise
+const RSS_SLACK: u64 = 32 * 1024 * 1024;
+
+fn main() {
+    let duration = match std::env::var("SOAK_DURATION") {
+        Ok(seconds) => Duration::from_secs(seconds.parse().expect("invalid SOAK_DURATION")),
+        Err(_) => {
+            println!("SOAK_DURATION is not set, skipping the soak test");
+            return;
+        }
+    };
+    let connections = setting("SOAK_CONNECTIONS", 8);
+    let interval = Duration::from_secs(setting("SOAK_CHECK_INTERVAL", 60));
+    let rss_growth = setting("SOAK_RSS_GROWTH", 10);
+
+    let dir = tempfile::tempdir().expect("failed to create temporary directory");
+    let config_file = dir.path().join("segcache.toml");
+    std::fs::write(
+        &config_file,
+        format!(
+            "[worker]\nthreads = 2\n\n[seg]\nheap_size = {}\nsegment_size = {}\n",
+            HEAP_SIZE, SEGMENT_SIZE
+        ),
+    )
+    .unwrap();
+    let config =
+        SegcacheConfig::load(config_file.to_str().unwrap()).expect("failed to load config");
+    let server = Segcache::new(config).expect("failed to launch segcache");
+
+    // wait for server to startup. duration is chosen to be longer than we'd
+    // expect startup to take in a slow ci environment.
+    std::thread::sleep(Duration::from_secs(10));
+
+    // clients hold the lock for reading around each request, so that taking
+    // it for writing waits for in-flight requests and pauses the workload
+    let pause = Arc::new(RwLock::new(()));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let clients: Vec<_> = (0..connections)
+        .map(|id| {
+            let pause = pause.clone();
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name(format!("soak_client_{}", id))
+                .spawn(move || Client::new(id).run(&pause, &stop))
+                .unwrap()
+        })
+        .collect();
+
+    let mut checker = Checker::new(rss_growth);
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        std::thread::sleep(interval.min(duration.saturating_sub(start.elapsed())));
+
+        // a client which fails poisons the lock, after printing why it failed
+        let _paused = pause
+            .write()
+            .unwrap_or_else(|_| panic!("a client failed, see above"));
+        checker.check(&server);
+        println!(
+            "{:>6}s: {} checks passed, {} items, {} evictions, {} KiB resident",
+            start.elapsed().as_secs(),
+            checker.checks,
+            server.gauge("item_current").unwrap_or(0),
+            server.counter("item_evict").unwrap_or(0),
+            rss().unwrap_or(0) / 1024,
+        );
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    let mut operations = 0;
+    for client in clients {
+        operations += client.join().expect("client failed");
+    }
+
+    checker.check(&server);
+    println!(
+        "passed: {} operations and {} checks over {}s",
+        operations,
+        checker.checks,
+        start.elapsed().as_secs()
+    );
+
+    let _ = server.shutdown();
+}
+
+fn setting(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .map(|value| value.parse().unwrap_or_else(|_| panic!("invalid {}", name)))
+        .unwrap_or(default)
+}
+
+/// What a client expects to read for one of its keys.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expected {
+    /// The key was deleted, so it must not be found
+    Absent,
+    /// The key was last set to this value. It may have been evicted or have
+    /// expired, so it may also not be found
+    Value(Vec<u8>),
+    /// The outcome of the last write is not known, so any value is allowed
+    Unknown,
+}
+
+/// A connection which drives the workload for its own set of keys, and checks
+/// each response against what it has written.
+struct Client {
+    id: u64,
+    rng: SmallRng,
+    writes: u64,
+    keys: Vec<Expected>,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn new(id: u64) -> Self {
+        let stream = TcpStream::connect("127.0.0.1:12321").expect("failed to connect");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .expect("failed to set read timeout");
+
+        Self {
+            id,
+            rng: SmallRng::seed_from_u64(id),
+            writes: 0,
+            keys: vec![Expected::Unknown; KEYS],
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+
+    /// Runs the workload until stopped, returning the number of operations.
+    fn run(mut self, pause: &RwLock<()>, stop: &AtomicBool) -> u64 {
+        let mut operations = 0;
+        while !stop.load(Ordering::Relaxed) {
+            let _running = pause.read().unwrap();
+            let key = self.rng.gen_range(0..KEYS);
+            match self.rng.gen_range(0..100) {
+                0..=39 => self.get(&[key]),
+                40..=44 => {
+                    let keys: Vec<usize> = (0..8).map(|_| self.rng.gen_range(0..KEYS)).collect();
+                    self.get(&keys)
+                }
+                45..=84 => self.set(key),
+                85..=89 => self.add(key),
+                _ => self.delete(key),
+            }
+            operations += 1;
+        }
+        operations
+    }
+
+    fn key(&self, key: usize) -> String {
+        format!("soak:{}:{}", self.id, key)
+    }
+
+    // values are mostly small, with an occasional large value which takes up
+    // a meaningful part of a segment. Each value starts with a unique prefix
+    // so that a stale value is never mistaken for the current one.
+    fn value(&mut self) -> Vec<u8> {
+        self.writes += 1;
+        let len = if self.rng.gen_ratio(1, 100) {
+            self.rng.gen_range(4096..=65536)
+        } else {
+            self.rng.gen_range(1..=512)
+        };
+        let mut value = format!("{}:{}:", self.id, self.writes).into_bytes();
+        value.resize(value.len().max(len), b'a' + (self.writes % 26) as u8);
+        value
+    }
+
+    // most items do not expire, but some expire quickly so that expiration
+    // runs alongside eviction
+    fn ttl(&mut self) -> u32 {
+        if self.rng.gen_ratio(1, 5) {
+            self.rng.gen_range(1..=10)
+        } else {
+            0
+        }
+    }
+
+    fn get(&mut self, keys: &[usize]) {
+        let names: Vec<String> = keys.iter().map(|key| self.key(*key)).collect();
+        self.send(format!("get {}\r\n", names.join(" ")).as_bytes());
+
+        let mut found = HashMap::new();
+        loop {
+            let line = self.line();
+            if line == "END" {
+                break;
+            }
+            // VALUE <key> <flags> <bytes>
+            let fields: Vec<&str> = line.split(' ').collect();
+            assert!(
+                fields.len() == 4 && fields[0] == "VALUE",
+                "unexpected response to get: {}",
+                line
+            );
+            let len: usize = fields[3].parse().expect("invalid value length");
+            let mut value = vec![0; len + 2];
+            self.reader
+                .read_exact(&mut value)
+                .expect("failed to read value");
+            value.truncate(len);
+            found.insert(fields[1].to_string(), value);
+        }
+
+        for key in keys {
+            let value = found.get(&self.key(*key));
+            match (&self.keys[*key], value) {
+                (Expected::Absent, Some(_)) => {
+                    panic!("deleted key was found: {}", self.key(*key))
+                }
+                (Expected::Value(expected), Some(value)) if expected != value => {
+                    panic!(
+                        "stale or corrupt value for {}: expected {} bytes starting {:?}, found {} bytes starting {:?}",
+                        self.key(*key),
+                        expected.len(),
+                        String::from_utf8_lossy(&expected[..expected.len().min(16)]),
+                        value.len(),
+                        String::from_utf8_lossy(&value[..value.len().min(16)]),
+                    )
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn set(&mut self, key: usize) {
+        let value = self.value();
+        let ttl = self.ttl();
+        let mut request =
+            format!("set {} 0 {} {}\r\n", self.key(key), ttl, value.len()).into_bytes();
+        request.extend_from_slice(&value);
+        request.extend_from_slice(b"\r\n");
+        self.send(&request);
+
+        self.keys[key] = match self.line().as_str() {
+            "STORED" => Expected::Value(value),
+            // the server may fail to allocate under memory pressure, which
+            // leaves the key in an unknown state
+            line if line.starts_with("SERVER_ERROR") => Expected::Unknown,
+            line => panic!("unexpected response to set: {}", line),
+        };
+    }
+
+    fn add(&mut self, key: usize) {
+        let value = self.value();
+        let mut request = format!("add {} 0 0 {}\r\n", self.key(key), value.len()).into_bytes();
+        request.extend_from_slice(&value);
+        request.extend_from_slice(b"\r\n");
+        self.send(&request);
+
+        match self.line().as_str() {
+            "STORED" => self.keys[key] = Expected::Value(value),
+            "NOT_STORED" => {
+                if self.keys[key] == Expected::Absent {
+                    panic!("add found a deleted key: {}", self.key(key));
+                }
+            }
+            line if line.starts_with("SERVER_ERROR") => self.keys[key] = Expected::Unknown,
+            line => panic!("unexpected response to add: {}", line),
+        }
+    }
+
+    fn delete(&mut self, key: usize) {
+        self.send(format!("delete {}\r\n", self.key(key)).as_bytes());
+
+        match self.line().as_str() {
+            "DELETED" | "NOT_FOUND" => self.keys[key] = Expected::Absent,
+            line => panic!("unexpected response to delete: {}", line),
+        }
+    }
+
+    fn send(&mut self, request: &[u8]) {
+        self.writer
+            .write_all(request)
+            .expect("failed to send request");
+    }
+
+    // reads one line of the response, without the CRLF
+    fn line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader
+            .read_line(&mut line)
+            .expect("failed to read response");
+        assert!(line.ends_with("\r\n"), "incomplete response: {:?}", line);
+        line.truncate(line.len() - 2);
+        line
+    }
+}
+
+/// Checks the invariants of the server while the workload is paused.
+struct Checker {
+    checks: u64,
+    counters: HashMap<String, u64>,
+    rss_baseline: Option<u64>,
+    rss_growth: u64,
+}
+
+impl Checker {
+    fn new(rss_growth: u64) -> Self {
+        Self {
+            checks: 0,
+            counters: HashMap::new(),
+            rss_baseline: None,
+            rss_growth,
+        }
+    }
+
+    fn check(&mut self, server: &Segcache) {
+        let mut violations = Vec::new();
+
+        self.counters(&mut violations);
+        gauges(server, &mut violations);
+        items(server, &mut violations);
+        self.rss(&mut violations);
+
+        self.checks += 1;
+
+        if !violations.is_empty() {
+            panic!(
+                "invariants violated on check {}:\n  {}",
+                self.checks,
+                violations.join("\n  ")
+            );
+        }
+    }
+
+    // no counter may decrease between checks
+    fn counters(&mut self, violations: &mut Vec<String>) {
+        for metric in &rustcommon_metrics::metrics() {
+            if let Some(counter) = metric
+                .as_any()
+                .and_then(|any| any.downcast_ref::<Counter>())
+            {
+                let value = counter.value();
+                if let Some(previous) = self.counters.insert(metric.name().to_string(), value) {
+                    if value < previous {
+                        violations.push(format!(
+                            "counter {} went backwards: {} -> {}",
+                            metric.name(),
+                            previous,
+                            value
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    // once the heap has been filled, resident memory should stay flat. The
+    // first check is the baseline, as the heap is allocated up front and the
+    // server has reached a steady state by then
+    fn rss(&mut self, violations: &mut Vec<String>) {
+        let rss = match rss() {
+            Some(rss) => rss,
+            None => return,
+        };
+
+        match self.rss_baseline {
+            None => self.rss_baseline = Some(rss),
+            Some(baseline) => {
+                let limit = baseline + baseline * self.rss_growth / 100 + RSS_SLACK;
+                if rss > limit {
+                    violations.push(format!(
+                        "resident memory grew from {} to {} bytes, beyond the limit of {}",
+                        baseline, rss, limit
+                    ));
+                }
+            }
+        }
+    }
+}
+
+// the item and segment gauges must be consistent with each other and with the
+// size of the heap
+fn gauges(server: &Segcache, violations: &mut Vec<String>) {
+    let gauge = |name: &str| server.gauge(name).unwrap_or(0);
+
+    for name in [
+        "item_current",
+        "item_current_bytes",
+        "item_dead",
+        "item_dead_bytes",
+        "segment_free",
+    ] {
+        if gauge(name) < 0 {
+            violations.push(format!("gauge {} is negative: {}", name, gauge(name)));
+        }
+    }
+
+    let segments = gauge("segment_current");
+    if segments != (HEAP_SIZE / SEGMENT_SIZE) as i64 {
+        violations.push(format!(
+            "segment_current is {} for a heap of {} segments",
+            segments,
+            HEAP_SIZE / SEGMENT_SIZE
+        ));
+    }
+    if gauge("segment_free") > segments {
+        violations.push(format!(
+            "segment_free {} is more than the {} segments",
+            gauge("segment_free"),
+            segments
+        ));
+    }
+
+    let bytes = gauge("item_current_bytes") + gauge("item_dead_bytes");
+    let used = (segments - gauge("segment_free")) * SEGMENT_SIZE as i64;
+    if bytes > used {
+        violations.push(format!(
+            "items hold {} bytes but only {} bytes of segments are in use",
+            bytes, used
+        ));
+    }
+}
+
+// every live item is linked into the hashtable. Expiration may still remove
+// items while the workload is paused, so the comparison is only made if the
+// live item gauge did not change while the hashtable was being read
+fn items(server: &Segcache, violations: &mut Vec<String>) {
+    let before = server.gauge("item_current").unwrap_or(0);
+    let hashtable = hashtable_items();
+    let after = server.gauge("item_current").unwrap_or(0);
+
+    if before == after && hashtable != before as u64 {
+        violations.push(format!(
+            "hashtable holds {} items but item_current is {}",
+            hashtable, before
+        ));
+    }
+}
+
+// reads the number of items in the hashtable with `stats hashtable`
+fn hashtable_items() -> u64 {
+    let mut stream = TcpStream::connect("127.0.0.1:9999").expect("failed to connect to admin");
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .expect("failed to set read timeout");
+    stream
+        .write_all(b"stats hashtable\r\n")
+        .expect("failed to send request");
+
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            panic!("admin closed the session before reporting the hashtable items");
+        }
+        let line = line.trim_end();
+        if let Some(items) = line.strip_prefix("STAT items ") {
+            return items.parse().expect("invalid item count");
+        }
+        if line == "END" || line.starts_with("SERVER_ERROR") {
+            panic!("hashtable items were not reported: {}", line);
+        }
+    }
+}
+
+// the resident memory of the process in bytes, where it is available
+fn rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
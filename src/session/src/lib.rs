@@ -15,10 +15,17 @@ extern crate log;
 mod buffer;
 mod client;
 mod server;
+#[cfg(feature = "shaping")]
+mod shaping;
 
 pub use buffer::*;
 pub use client::ClientSession;
 pub use server::ServerSession;
+#[cfg(feature = "shaping")]
+pub use shaping::Shaping;
+
+#[cfg(feature = "shaping")]
+use shaping::Shaper;
 
 use std::os::unix::prelude::AsRawFd;
 
@@ -27,7 +34,9 @@ use core::borrow::{Borrow, BorrowMut};
 use core::fmt::Debug;
 use core::marker::PhantomData;
 use protocol_common::Compose;
+use protocol_common::Mismatch;
 use protocol_common::Parse;
+use protocol_common::SessionCounters;
 use rustcommon_metrics::*;
 use rustcommon_time::Nanoseconds;
 use std::collections::VecDeque;
@@ -36,6 +45,7 @@ use std::io::ErrorKind;
 use std::io::Read;
 use std::io::Result;
 use std::io::Write;
+use std::net::SocketAddr;
 
 const ONE_SECOND: u64 = 1_000_000_000; // in nanoseconds
 
@@ -56,6 +66,10 @@ counter!(
     "number of exceptions while writing to sessions"
 );
 counter!(SESSION_SEND_BYTE, "number of bytes written to sessions");
+counter!(
+    SESSION_SEND_PARTIAL,
+    "number of responses composed incrementally across multiple writes"
+);
 
 heatmap!(
     REQUEST_LATENCY,
@@ -89,6 +103,8 @@ pub struct Session {
     stream: Stream,
     read_buffer: Buffer,
     write_buffer: Buffer,
+    #[cfg(feature = "shaping")]
+    shaper: Option<Shaper>,
 }
 
 impl AsRawFd for Session {
@@ -111,9 +127,29 @@ impl Session {
             stream,
             read_buffer,
             write_buffer,
+            #[cfg(feature = "shaping")]
+            shaper: None,
         }
     }
 
+    /// Applies the shaping to the bytes which are written to the session.
+    #[cfg(feature = "shaping")]
+    pub fn shape(&mut self, shaping: Shaping) {
+        self.shaper = if shaping.is_enabled() {
+            Some(Shaper::new(shaping, self.stream.as_raw_fd() as u64))
+        } else {
+            None
+        };
+    }
+
+    /// The time at which bytes which are held back by the shaping may be
+    /// written, if there are any. The session must be flushed again then, as
+    /// no event signals it.
+    #[cfg(feature = "shaping")]
+    pub fn shaped_until(&self) -> Option<std::time::Instant> {
+        self.shaper.as_ref().and_then(|shaper| shaper.next())
+    }
+
     /// Return the event `Interest`s for the `Session`.
     pub fn interest(&mut self) -> Interest {
         if self.write_buffer.has_remaining() {
@@ -133,6 +169,11 @@ impl Session {
         self.stream.is_handshaking()
     }
 
+    /// Returns the address of the remote side of the session.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
     /// Fill the read buffer by calling read on the underlying stream until read
     /// would block. Returns the number of bytes read. `Ok(0)` indicates that
     /// the remote side has closed the stream.
@@ -190,9 +231,27 @@ impl Session {
     /// Attempts to flush the `Session` to the underlying `Stream`. This may
     /// result in multiple calls
     pub fn flush(&mut self) -> Result<usize> {
+        // only the bytes which the shaping releases may be written
+        #[cfg(feature = "shaping")]
+        let limit = match self.shaper.as_mut() {
+            Some(shaper) => {
+                let limit =
+                    shaper.allowance(self.write_buffer.remaining(), std::time::Instant::now());
+                if limit == 0 && self.write_buffer.has_remaining() {
+                    return Err(Error::from(ErrorKind::WouldBlock));
+                }
+                limit
+            }
+            None => usize::MAX,
+        };
+        #[cfg(not(feature = "shaping"))]
+        let limit = usize::MAX;
+
         let mut flushed = 0;
-        while self.write_buffer.has_remaining() {
-            match self.stream.write(self.write_buffer.borrow()) {
+        while self.write_buffer.has_remaining() && flushed < limit {
+            let buf: &[u8] = self.write_buffer.borrow();
+            let buf = &buf[..buf.len().min(limit - flushed)];
+            match self.stream.write(buf) {
                 Ok(amt) => {
                     // successfully wrote `amt` bytes to the stream, advance the
                     // write buffer and increment the flushed stat
@@ -222,6 +281,11 @@ impl Session {
 
         SESSION_SEND_BYTE.add(flushed as _);
 
+        #[cfg(feature = "shaping")]
+        if let Some(shaper) = self.shaper.as_mut() {
+            shaper.wrote(flushed);
+        }
+
         Ok(flushed)
     }
 
@@ -4,6 +4,14 @@
 
 use super::*;
 
+use common::listener::ListenerStats;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// the id of the next session, so that each session can be identified
+// uniquely for the lifetime of the process
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
 /// A basic session to represent the server side of a framed session, meaning
 /// that is is used by a server to talk to a client.
 ///
@@ -29,6 +37,29 @@ pub struct ServerSession<Parser, Tx, Rx> {
     outstanding: VecDeque<(Option<Instant>, usize)>,
     // tracks the time the session buffer was last filled
     timestamp: Instant,
+    // indicates that input is discarded until the end of the current line
+    discard: bool,
+    // a response which is being composed incrementally, along with the cursor
+    // to resume from and the timestamp of the corresponding request
+    composing: Option<(Tx, usize, Option<Instant>)>,
+    // responses are composed incrementally once this many bytes are written
+    compose_limit: usize,
+    // the statistics of the listener which accepted the session, if any
+    listener: Option<Arc<ListenerStats>>,
+    // the address of the client, if it could be determined
+    peer_addr: Option<SocketAddr>,
+    // set once the first request has been parsed
+    received: bool,
+    // identifies the session for the lifetime of the process
+    id: u64,
+    // the time the session was created
+    created: Instant,
+    // the total number of bytes read from and written to the stream
+    bytes_read: u64,
+    bytes_written: u64,
+    // the counters of the requests on the session, which may be reported to
+    // the client
+    counters: SessionCounters,
     // markers for the receive and transmit types
     _rx: PhantomData<Rx>,
     _tx: PhantomData<Tx>,
@@ -53,17 +84,45 @@ where
 {
     // Create a new `ServerSession` from a `Session` and a `Parser`
     pub fn new(session: Session, parser: Parser) -> Self {
+        let peer_addr = session.peer_addr().ok();
         Self {
             session,
             parser,
             pending: VecDeque::with_capacity(NUM_PENDING),
             outstanding: VecDeque::with_capacity(NUM_PENDING),
             timestamp: Instant::now(),
+            discard: false,
+            composing: None,
+            compose_limit: usize::MAX,
+            listener: None,
+            peer_addr,
+            received: false,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            created: Instant::now(),
+            bytes_read: 0,
+            bytes_written: 0,
+            counters: SessionCounters::default(),
             _rx: PhantomData,
             _tx: PhantomData,
         }
     }
 
+    /// Sets the number of bytes to compose for a single response before
+    /// deferring the remainder until the write buffer has drained. This
+    /// bounds the time spent composing very large responses, such as a
+    /// multiget for many keys, in any one event loop iteration.
+    pub fn compose_limit(mut self, bytes: usize) -> Self {
+        self.compose_limit = bytes;
+        self
+    }
+
+    /// Records the requests and responses of this session, along with their
+    /// latency, in the statistics of the listener which accepted it.
+    pub fn listener(mut self, stats: Arc<ListenerStats>) -> Self {
+        self.listener = Some(stats);
+        self
+    }
+
     /// Consume the `ServerSession` and return the inner `Session`
     pub fn into_inner(self) -> Session {
         self.session
@@ -71,10 +130,25 @@ where
 
     /// Attempt to receive a single message from the current session buffer.
     pub fn receive(&mut self) -> Result<Rx> {
+        // responses must be sent in order, so no further requests are handled
+        // until the current response is entirely composed
+        if self.composing.is_some() {
+            return Err(Error::from(ErrorKind::WouldBlock));
+        }
+
+        if self.discard && !self.skip_line() {
+            return Err(Error::from(ErrorKind::WouldBlock));
+        }
+
         let src: &[u8] = self.session.borrow();
         match self.parser.parse(src) {
             Ok(res) => {
+                if let Some(listener) = &self.listener {
+                    listener.record_request();
+                }
                 self.pending.push_back(self.timestamp);
+                self.received = true;
+                self.counters.requests += 1;
                 let consumed = res.consumed();
                 let msg = res.into_inner();
                 self.session.consume(consumed);
@@ -84,28 +158,223 @@ where
         }
     }
 
-    /// Send a message to the session buffer.
+    /// Recover from input which could not be parsed by writing the error
+    /// response for the protocol and discarding input through the end of the
+    /// current line. Returns an error if the protocol does not support
+    /// recovering from invalid input, in which case the session should be
+    /// closed.
+    pub fn resync(&mut self) -> Result<()> {
+        let response = self.parser.error_response().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "protocol cannot recover from invalid input",
+            )
+        })?;
+
+        SESSION_SEND.increment();
+        self.session.put_slice(response);
+        self.outstanding.push_back((None, response.len()));
+        self.counters.errors += 1;
+
+        self.discard = true;
+        self.skip_line();
+
+        Ok(())
+    }
+
+    /// Checks whether input which could not be parsed is the first input on
+    /// the session and appears to be another protocol. If so, a response the
+    /// client can understand is written where there is one, and the session
+    /// should be closed once it has been flushed.
+    pub fn mismatch(&mut self) -> Option<Mismatch> {
+        if self.received {
+            return None;
+        }
+
+        let src: &[u8] = self.session.borrow();
+        let mismatch = self.parser.mismatch(src)?;
+
+        if let Some(response) = mismatch.response() {
+            SESSION_SEND.increment();
+            self.session.put_slice(response);
+            self.outstanding.push_back((None, response.len()));
+        }
+
+        Some(mismatch)
+    }
+
+    /// Respond to the oldest received request with the busy response for the
+    /// protocol instead of handling it. Returns an error if the protocol has
+    /// no busy response, in which case the session should be closed.
+    pub fn reject(&mut self) -> Result<()> {
+        let response = self.parser.busy_response().ok_or_else(|| {
+            Error::new(
+                ErrorKind::Other,
+                "protocol cannot reject requests when busy",
+            )
+        })?;
+
+        SESSION_SEND.increment();
+        let timestamp = self.pending.pop_front();
+        self.session.put_slice(response);
+        self.outstanding.push_back((timestamp, response.len()));
+        self.counters.errors += 1;
+
+        Ok(())
+    }
+
+    /// Returns the address of the client, if it could be determined when the
+    /// session was established.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Returns the id of the session, which is unique within the process.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns the time elapsed since the session was created.
+    pub fn age(&self) -> std::time::Duration {
+        std::time::Duration::from_nanos((Instant::now() - self.created).as_nanos())
+    }
+
+    /// Returns the total number of bytes read from the underlying stream.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Returns the total number of bytes written to the underlying stream.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Returns the counters of the requests on the session, along with the
+    /// bytes read and written.
+    pub fn counters(&self) -> SessionCounters {
+        SessionCounters {
+            bytes_read: self.bytes_read,
+            bytes_written: self.bytes_written,
+            ..self.counters
+        }
+    }
+
+    /// Get mutable access to the counters, so that the outcome of each request
+    /// can be counted once it has a response.
+    pub fn counters_mut(&mut self) -> &mut SessionCounters {
+        &mut self.counters
+    }
+
+    /// Returns the nanoseconds elapsed since the oldest request without a
+    /// response was read into the session buffer. This is the server-side
+    /// latency of that request so far.
+    pub fn request_elapsed(&self) -> Option<u64> {
+        self.pending
+            .front()
+            .map(|timestamp| (Instant::now() - *timestamp).as_nanos())
+    }
+
+    /// Returns the number of requests which have been received and which do
+    /// not yet have a response.
+    pub fn pending_requests(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Discards buffered input through the next CRLF. Returns true if the end
+    /// of the line was found, otherwise all buffered input is discarded and
+    /// the remainder of the line will be discarded as it arrives.
+    fn skip_line(&mut self) -> bool {
+        let src: &[u8] = self.session.borrow();
+        if let Some(position) = src.windows(2).position(|w| w == b"\r\n") {
+            self.session.consume(position + 2);
+            self.discard = false;
+            true
+        } else {
+            // retain a trailing CR, since the LF may arrive with the next read
+            let amt = if src.last() == Some(&b'\r') {
+                src.len() - 1
+            } else {
+                src.len()
+            };
+            self.session.consume(amt);
+            false
+        }
+    }
+
+    /// Send a message to the session buffer. Messages which are larger than
+    /// the compose limit may only be partially composed, in which case
+    /// `compose_next()` must be called to compose the remainder.
     pub fn send(&mut self, tx: Tx) -> Result<usize> {
         SESSION_SEND.increment();
 
         let timestamp = self.pending.pop_front();
 
-        let size = tx.compose(&mut self.session);
+        let mut cursor = 0;
+        let (size, complete) =
+            tx.compose_partial(&mut self.session, &mut cursor, self.compose_limit);
 
+        if complete {
+            self.track(timestamp, size);
+        } else {
+            SESSION_SEND_PARTIAL.increment();
+            self.outstanding.push_back((None, size));
+            self.composing = Some((tx, cursor, timestamp));
+        }
+
+        Ok(size)
+    }
+
+    /// Continue composing a partially composed message once the write buffer
+    /// has drained below the compose limit. Returns the number of bytes added
+    /// to the write buffer.
+    pub fn compose_next(&mut self) -> usize {
+        if self.session.write_pending() >= self.compose_limit {
+            return 0;
+        }
+
+        if let Some((tx, mut cursor, timestamp)) = self.composing.take() {
+            let (size, complete) =
+                tx.compose_partial(&mut self.session, &mut cursor, self.compose_limit);
+
+            if complete {
+                self.track(timestamp, size);
+            } else {
+                self.outstanding.push_back((None, size));
+                self.composing = Some((tx, cursor, timestamp));
+            }
+
+            size
+        } else {
+            0
+        }
+    }
+
+    /// Returns true if a message is partially composed.
+    pub fn is_composing(&self) -> bool {
+        self.composing.is_some()
+    }
+
+    // Track the latency of a response once it has been fully composed
+    fn track(&mut self, timestamp: Option<Instant>, size: usize) {
         if size == 0 {
             // we have a zero sized response, increment heatmap now
             if let Some(timestamp) = timestamp {
-                let now = Instant::now();
-                let latency = now - timestamp;
-                REQUEST_LATENCY.increment(now, latency.as_nanos(), 1);
+                self.record_latency(Instant::now(), timestamp);
             }
         } else {
             // we have bytes in our response, we need to add it on the
             // outstanding response queue
             self.outstanding.push_back((timestamp, size));
         }
+    }
 
-        Ok(size)
+    // Record the latency of a response which was fully written at `now`
+    fn record_latency(&self, now: Instant, timestamp: Instant) {
+        let latency = now - timestamp;
+        REQUEST_LATENCY.increment(now, latency.as_nanos(), 1);
+        if let Some(listener) = &self.listener {
+            listener.record_response(core::time::Duration::from_nanos(latency.as_nanos()));
+        }
     }
 
     /// Advances the read pointer for the session write buffer by `amt` bytes.
@@ -128,8 +397,7 @@ where
                 } else {
                     amt -= front.1;
                     if let Some(ts) = front.0 {
-                        let latency = now - ts;
-                        REQUEST_LATENCY.increment(now, latency.as_nanos(), 1);
+                        self.record_latency(now, ts);
                     }
                 }
             } else {
@@ -147,6 +415,7 @@ where
         let final_pending = self.session.write_pending();
 
         let flushed = current_pending - final_pending;
+        self.bytes_written += flushed as u64;
 
         self.advance_write(flushed);
 
@@ -158,6 +427,13 @@ where
         self.session.write_pending()
     }
 
+    /// The time at which responses which are held back by the shaping may be
+    /// flushed, if there are any.
+    #[cfg(feature = "shaping")]
+    pub fn shaped_until(&self) -> Option<std::time::Instant> {
+        self.session.shaped_until()
+    }
+
     /// Reads from the underlying stream into the read buffer and returns the
     /// number of bytes read.
     pub fn fill(&mut self) -> Result<usize> {
@@ -167,6 +443,7 @@ where
         match self.session.fill() {
             Ok(amt) => {
                 SESSION_RECV_BYTE.add(amt as _);
+                self.bytes_read += amt as u64;
                 Ok(amt)
             }
             Err(e) => {
@@ -178,9 +455,15 @@ where
         }
     }
 
-    /// Returns the current event interest for this session.
+    /// Returns the current event interest for this session. Sessions with a
+    /// partially composed message are also interested in writable events so
+    /// that the remainder can be composed.
     pub fn interest(&mut self) -> Interest {
-        self.session.interest()
+        if self.composing.is_some() {
+            self.session.interest().add(Interest::WRITABLE)
+        } else {
+            self.session.interest()
+        }
     }
 
     /// Attempt to handshake the underlying session.
@@ -0,0 +1,226 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Shaping of the bytes written to a session, which adds latency and jitter
+//! and caps the bandwidth of each session. This lets clients test their
+//! timeout and retry behavior against a server which is otherwise healthy,
+//! without emulating the network outside the process.
+//!
+//! Bytes are held in the write buffer until they are due, so a flush which is
+//! held back returns `WouldBlock` as if the socket were full. The event loop
+//! must flush the session again once it is due, see `Session::shaped_until()`.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// the bandwidth cap allows bursts of up to this long at the capped rate
+const BURST: Duration = Duration::from_millis(10);
+
+/// The shaping applied to the bytes written to a session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Shaping {
+    /// The time each write is held before it is sent
+    pub latency: Duration,
+    /// The upper bound of a random delay which is added to the latency of
+    /// each write
+    pub jitter: Duration,
+    /// The maximum rate in bytes per second, or zero for no cap
+    pub bandwidth: u64,
+}
+
+impl Shaping {
+    /// Returns true if the shaping changes anything.
+    pub fn is_enabled(&self) -> bool {
+        !self.latency.is_zero() || !self.jitter.is_zero() || self.bandwidth > 0
+    }
+}
+
+pub(crate) struct Shaper {
+    shaping: Shaping,
+    /// The bytes at the end of the write buffer which are not yet due, with
+    /// the time they are due, oldest first
+    held: VecDeque<(Instant, usize)>,
+    /// The bytes at the start of the write buffer which are due
+    released: usize,
+    /// The number of bytes which may be written before the cap applies
+    tokens: f64,
+    refilled: Instant,
+    /// The state of the generator for the jitter
+    random: u64,
+}
+
+impl Shaper {
+    pub fn new(shaping: Shaping, seed: u64) -> Self {
+        Self {
+            shaping,
+            held: VecDeque::new(),
+            released: 0,
+            tokens: burst(shaping.bandwidth),
+            refilled: Instant::now(),
+            // the generator must not start from zero
+            random: seed | 1,
+        }
+    }
+
+    /// Holds any bytes which were added to the write buffer since the last
+    /// call, and returns the number of bytes which may be written now.
+    pub fn allowance(&mut self, pending: usize, now: Instant) -> usize {
+        let tracked = self.released + self.held.iter().map(|(_, size)| size).sum::<usize>();
+        if pending > tracked {
+            // jitter does not reorder writes, so each is due no earlier than
+            // the one before it
+            let mut due = now + self.shaping.latency + self.jitter();
+            if let Some((previous, _)) = self.held.back() {
+                due = due.max(*previous);
+            }
+            self.held.push_back((due, pending - tracked));
+        }
+
+        while let Some((due, size)) = self.held.front() {
+            if *due > now {
+                break;
+            }
+            self.released += size;
+            self.held.pop_front();
+        }
+
+        if self.shaping.bandwidth == 0 {
+            return self.released;
+        }
+
+        self.refill(now);
+        self.released.min(self.tokens as usize)
+    }
+
+    /// Records that bytes from the start of the write buffer were written.
+    pub fn wrote(&mut self, amt: usize) {
+        self.released = self.released.saturating_sub(amt);
+        if self.shaping.bandwidth > 0 {
+            self.tokens = (self.tokens - amt as f64).max(0.0);
+        }
+    }
+
+    /// The time at which more bytes may be written, if any are held back by
+    /// the shaping rather than by the socket.
+    pub fn next(&self) -> Option<Instant> {
+        if self.released > 0 && self.shaping.bandwidth > 0 {
+            // wait for enough tokens to write a full burst, or the rest of
+            // the released bytes if that is less
+            let wanted = (self.released as f64).min(burst(self.shaping.bandwidth));
+            let wait = (wanted - self.tokens).max(0.0) / self.shaping.bandwidth as f64;
+            return Some(self.refilled + Duration::from_secs_f64(wait));
+        }
+        // bytes which are due but not capped are only waiting for the socket
+        self.held.front().map(|(due, _)| *due)
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled);
+        self.refilled = now;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.shaping.bandwidth as f64)
+            .min(burst(self.shaping.bandwidth));
+    }
+
+    // a uniformly distributed delay of up to the configured jitter
+    fn jitter(&mut self) -> Duration {
+        let jitter = self.shaping.jitter.as_nanos() as u64;
+        if jitter == 0 {
+            return Duration::ZERO;
+        }
+
+        // xorshift64, which is enough for test traffic
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+
+        Duration::from_nanos(self.random % (jitter + 1))
+    }
+}
+
+// the most bytes which may be written at once under the cap
+fn burst(bandwidth: u64) -> f64 {
+    (bandwidth as f64 * BURST.as_secs_f64()).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency() {
+        let mut shaper = Shaper::new(
+            Shaping {
+                latency: Duration::from_millis(10),
+                ..Default::default()
+            },
+            0,
+        );
+
+        let start = Instant::now();
+        assert_eq!(shaper.allowance(100, start), 0);
+        assert_eq!(shaper.next(), Some(start + Duration::from_millis(10)));
+
+        // a later write is held for its own latency
+        let later = start + Duration::from_millis(5);
+        assert_eq!(shaper.allowance(150, later), 0);
+
+        assert_eq!(
+            shaper.allowance(150, start + Duration::from_millis(10)),
+            100
+        );
+        shaper.wrote(100);
+        assert_eq!(shaper.next(), Some(later + Duration::from_millis(10)));
+        assert_eq!(shaper.allowance(50, later + Duration::from_millis(10)), 50);
+        shaper.wrote(50);
+        assert_eq!(shaper.next(), None);
+    }
+
+    #[test]
+    fn jitter() {
+        let mut shaper = Shaper::new(
+            Shaping {
+                jitter: Duration::from_millis(10),
+                ..Default::default()
+            },
+            42,
+        );
+
+        // writes are never reordered by the jitter
+        let start = Instant::now();
+        let mut previous = start;
+        for i in 1..=100 {
+            shaper.allowance(i, start);
+            if let Some((due, _)) = shaper.held.back() {
+                assert!(*due >= previous);
+                assert!(*due <= start + Duration::from_millis(10));
+                previous = *due;
+            }
+        }
+        assert_eq!(
+            shaper.allowance(100, start + Duration::from_millis(10)),
+            100
+        );
+    }
+
+    #[test]
+    fn bandwidth() {
+        let mut shaper = Shaper::new(
+            Shaping {
+                bandwidth: 100_000,
+                ..Default::default()
+            },
+            0,
+        );
+
+        // the first burst is 10ms at the capped rate
+        let start = Instant::now();
+        assert_eq!(shaper.allowance(5000, start), 1000);
+        shaper.wrote(1000);
+        assert_eq!(shaper.allowance(4000, start), 0);
+
+        let next = shaper.next().unwrap();
+        assert_eq!(next, start + Duration::from_millis(10));
+        assert_eq!(shaper.allowance(4000, next), 1000);
+    }
+}
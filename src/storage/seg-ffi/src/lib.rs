@@ -0,0 +1,10 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Builds the C interface of the `seg` storage engine as a shared library.
+//! The interface itself lives in `seg::ffi` and is described by the header in
+//! `src/storage/seg/include/seg.h`. Keeping the `cdylib` in this crate means
+//! that the Rust users of `seg` do not build a shared library they never link.
+
+pub use seg::ffi::*;
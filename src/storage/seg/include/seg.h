@@ -0,0 +1,233 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+#ifndef SEG_H
+#define SEG_H
+
+/* Warning, this file is autogenerated by cbindgen. Don't modify this manually. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Status codes returned by the C interface.
+ */
+typedef enum SegStatus {
+  /**
+   * The operation was successful.
+   */
+  SEG_STATUS_OK = 0,
+  /**
+   * The key was not found.
+   */
+  SEG_STATUS_NOT_FOUND = 1,
+  /**
+   * The item exists and the operation was not performed.
+   */
+  SEG_STATUS_EXISTS = 2,
+  /**
+   * The item is too large to be stored.
+   */
+  SEG_STATUS_ITEM_OVERSIZED = 3,
+  /**
+   * No segment could be allocated for the item.
+   */
+  SEG_STATUS_NO_FREE_SEGMENTS = 4,
+  /**
+   * The stored value is not numeric.
+   */
+  SEG_STATUS_NOT_NUMERIC = 5,
+  /**
+   * The provided buffer is too small to hold the value. The required size
+   * is written to the length output parameter.
+   */
+  SEG_STATUS_BUFFER_TOO_SMALL = 6,
+  /**
+   * A required pointer argument was null.
+   */
+  SEG_STATUS_INVALID_ARGUMENT = 7,
+  /**
+   * Any other storage error.
+   */
+  SEG_STATUS_ERROR = 8,
+} SegStatus;
+
+/**
+ * Opaque handle to a cache instance.
+ */
+typedef struct SegHandle SegHandle;
+
+/**
+ * Options used to construct a new cache instance. Use
+ * `seg_options_default()` to get an initialized set of options.
+ */
+typedef struct SegOptions {
+  /**
+   * Hashtable will have `2^hash_power` slots.
+   */
+  uint8_t hash_power;
+  /**
+   * Additional hashtable capacity for chaining, as a fraction.
+   */
+  double overflow_factor;
+  /**
+   * Total bytes used for item storage.
+   */
+  size_t heap_size;
+  /**
+   * Size of each segment in bytes.
+   */
+  int32_t segment_size;
+} SegOptions;
+
+/**
+ * A point-in-time view of the storage metrics. Note that metrics are
+ * process-wide, so these are aggregated across all cache instances.
+ */
+typedef struct SegStats {
+  int64_t item_current;
+  int64_t item_current_bytes;
+  int64_t item_dead;
+  int64_t item_dead_bytes;
+  uint64_t item_delete;
+  uint64_t item_evict;
+  uint64_t item_expire;
+  int64_t segment_current;
+  int64_t segment_free;
+  uint64_t segment_evict;
+  uint64_t segment_expire;
+} SegStats;
+
+/**
+ * Returns the default set of options.
+ */
+struct SegOptions seg_options_default(void);
+
+/**
+ * Create a new cache instance. Returns null if the options are invalid or
+ * the cache could not be allocated. Passing null for `options` uses the
+ * defaults.
+ *
+ * # Safety
+ *
+ * `options` must be null or point to a valid `SegOptions`.
+ */
+struct SegHandle *seg_create(const struct SegOptions *options);
+
+/**
+ * Destroy a cache instance, releasing all memory. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * `seg` must be null or a handle returned by `seg_create()` which has not
+ * already been destroyed.
+ */
+void seg_destroy(struct SegHandle *seg);
+
+/**
+ * Lookup an item by key, copying the value into `buf`. On success, or when
+ * `buf` is too small, `len` is set to the length of the value. If `cas` is
+ * non-null it receives the CAS value of the item. Numeric values are
+ * returned in their decimal string representation.
+ *
+ * # Safety
+ *
+ * `seg` must be a valid handle, `key` must point to `klen` bytes, `buf` must
+ * point to `buf_len` writable bytes, and `len` must be a valid pointer.
+ */
+enum SegStatus seg_get(struct SegHandle *seg,
+                       const uint8_t *key,
+                       size_t klen,
+                       uint8_t *buf,
+                       size_t buf_len,
+                       size_t *len,
+                       uint32_t *cas);
+
+/**
+ * Store an item, replacing any existing item with the same key. Values which
+ * are the decimal representation of an unsigned 64bit integer are stored as
+ * numeric values so that they can be used with `seg_incr()`/`seg_decr()`. A
+ * `ttl` of zero means the item does not expire.
+ *
+ * # Safety
+ *
+ * `seg` must be a valid handle, `key` must point to `klen` bytes, and
+ * `value` must point to `vlen` bytes.
+ */
+enum SegStatus seg_set(struct SegHandle *seg,
+                       const uint8_t *key,
+                       size_t klen,
+                       const uint8_t *value,
+                       size_t vlen,
+                       uint32_t ttl);
+
+/**
+ * Remove an item by key.
+ *
+ * # Safety
+ *
+ * `seg` must be a valid handle and `key` must point to `klen` bytes.
+ */
+enum SegStatus seg_delete(struct SegHandle *seg, const uint8_t *key, size_t klen);
+
+/**
+ * Increment a numeric value with wrapping semantics. If `result` is non-null
+ * it receives the new value.
+ *
+ * # Safety
+ *
+ * `seg` must be a valid handle and `key` must point to `klen` bytes.
+ */
+enum SegStatus seg_incr(struct SegHandle *seg,
+                        const uint8_t *key,
+                        size_t klen,
+                        uint64_t delta,
+                        uint64_t *result);
+
+/**
+ * Decrement a numeric value, saturating at zero. If `result` is non-null it
+ * receives the new value.
+ *
+ * # Safety
+ *
+ * `seg` must be a valid handle and `key` must point to `klen` bytes.
+ */
+enum SegStatus seg_decr(struct SegHandle *seg,
+                        const uint8_t *key,
+                        size_t klen,
+                        uint64_t delta,
+                        uint64_t *result);
+
+/**
+ * Expire any segments which have reached their TTL. Returns the number of
+ * segments expired.
+ *
+ * # Safety
+ *
+ * `seg` must be a valid handle.
+ */
+size_t seg_expire(struct SegHandle *seg);
+
+/**
+ * Remove all items from the cache. Returns the number of segments cleared.
+ *
+ * # Safety
+ *
+ * `seg` must be a valid handle.
+ */
+size_t seg_flush(struct SegHandle *seg);
+
+/**
+ * Read the current storage metrics into `stats`.
+ *
+ * # Safety
+ *
+ * `stats` must be a valid pointer.
+ */
+enum SegStatus seg_stats(struct SegStats *stats);
+
+#endif /* SEG_H */
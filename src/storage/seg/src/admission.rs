@@ -0,0 +1,208 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Admission control for writes of new items. Scan-heavy workloads write many
+//! items which are never read again, and each of these displaces an item which
+//! may have been. An admission policy rejects some of these writes before they
+//! reach the segments. Writes which replace an existing item are always
+//! admitted.
+
+use crate::*;
+
+use ahash::RandomState;
+
+counter!(
+    ADMISSION_REJECT,
+    "number of writes of new items rejected by the admission policy"
+);
+
+// each row of the frequency sketch has 2^WIDTH_POWER counters
+const WIDTH_POWER: u32 = 16;
+const WIDTH: usize = 1 << WIDTH_POWER;
+const DEPTH: usize = 4;
+
+// the counters are halved after this many increments, so that the frequencies
+// reflect recent accesses
+const SAMPLES: usize = 10 * WIDTH;
+
+/// Admission policies define which writes of new items are stored.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Admission {
+    /// Every write is admitted.
+    Always,
+    /// Writes of items which are at least `min_size` bytes are admitted with
+    /// the given probability. Large items which are only written once are
+    /// then unlikely to be stored, while an item which is written repeatedly
+    /// is eventually admitted.
+    Probabilistic {
+        /// The smallest item size, in bytes, subject to admission.
+        min_size: usize,
+        /// The probability, from 0.0 to 1.0, that a write is admitted.
+        probability: f64,
+    },
+    /// Writes are admitted once the key has been accessed at least
+    /// `threshold` times recently. Reads and writes of each key are counted
+    /// in a count-min sketch, whose counts decay over time.
+    Frequency {
+        /// The number of recent accesses required for admission.
+        threshold: u8,
+    },
+}
+
+impl Default for Admission {
+    fn default() -> Self {
+        Self::Always
+    }
+}
+
+/// Applies the admission policy, tracking key frequencies if required.
+pub(crate) struct Admitter {
+    policy: Admission,
+    sketch: Option<FrequencySketch>,
+}
+
+impl Admitter {
+    pub fn new(policy: Admission) -> Self {
+        let sketch = match policy {
+            Admission::Frequency { .. } => Some(FrequencySketch::new()),
+            _ => None,
+        };
+
+        Self { policy, sketch }
+    }
+
+    /// Returns true if the policy is to admit every write, in which case the
+    /// caller may skip checking whether the item is new.
+    pub fn always(&self) -> bool {
+        self.policy == Admission::Always
+    }
+
+    /// Records an access of the key.
+    pub fn record(&mut self, key: &[u8]) {
+        if let Some(sketch) = self.sketch.as_mut() {
+            sketch.increment(key);
+        }
+    }
+
+    /// Returns true if a write of a new item with the given key and size
+    /// should be stored.
+    pub fn admit(&mut self, key: &[u8], size: usize) -> bool {
+        let admit = match self.policy {
+            Admission::Always => true,
+            Admission::Probabilistic {
+                min_size,
+                probability,
+            } => size < min_size || thread_rng().gen_bool(probability.clamp(0.0, 1.0)),
+            Admission::Frequency { threshold } => self
+                .sketch
+                .as_ref()
+                .map(|sketch| sketch.estimate(key) >= threshold)
+                .unwrap_or(true),
+        };
+
+        if !admit {
+            ADMISSION_REJECT.increment();
+        }
+
+        admit
+    }
+}
+
+/// A count-min sketch with saturating 8-bit counters which are periodically
+/// halved.
+pub(crate) struct FrequencySketch {
+    hash_builder: RandomState,
+    counters: Box<[u8]>,
+    increments: usize,
+}
+
+impl FrequencySketch {
+    pub fn new() -> Self {
+        Self {
+            hash_builder: RandomState::with_seeds(
+                0x3c6ef372fe94f82b,
+                0xa54ff53a5f1d36f1,
+                0x510e527fade682d1,
+                0x9b05688c2b3e6c1f,
+            ),
+            counters: vec![0; WIDTH * DEPTH].into_boxed_slice(),
+            increments: 0,
+        }
+    }
+
+    pub fn increment(&mut self, key: &[u8]) {
+        for index in self.indices(key) {
+            self.counters[index] = self.counters[index].saturating_add(1);
+        }
+
+        self.increments += 1;
+        if self.increments >= SAMPLES {
+            for counter in self.counters.iter_mut() {
+                *counter >>= 1;
+            }
+            self.increments = 0;
+        }
+    }
+
+    pub fn estimate(&self, key: &[u8]) -> u8 {
+        self.indices(key)
+            .map(|index| self.counters[index])
+            .min()
+            .unwrap_or(0)
+    }
+
+    // one counter in each row, each selected by a different part of the hash
+    fn indices(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let mut hasher = self.hash_builder.build_hasher();
+        hasher.write(key);
+        let hash = hasher.finish();
+
+        (0..DEPTH).map(move |row| {
+            let column = (hash >> (row as u32 * WIDTH_POWER)) as usize & (WIDTH - 1);
+            row * WIDTH + column
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequency() {
+        let mut sketch = FrequencySketch::new();
+        assert_eq!(sketch.estimate(b"coffee"), 0);
+
+        for _ in 0..3 {
+            sketch.increment(b"coffee");
+        }
+        assert_eq!(sketch.estimate(b"coffee"), 3);
+        assert_eq!(sketch.estimate(b"tea"), 0);
+
+        // counts are halved once enough increments have been seen
+        sketch.increments = SAMPLES - 1;
+        sketch.increment(b"tea");
+        assert_eq!(sketch.estimate(b"coffee"), 1);
+        assert_eq!(sketch.estimate(b"tea"), 0);
+    }
+
+    #[test]
+    fn policies() {
+        let mut admitter = Admitter::new(Admission::Always);
+        assert!(admitter.admit(b"coffee", 1_000_000));
+
+        let mut admitter = Admitter::new(Admission::Probabilistic {
+            min_size: 1024,
+            probability: 0.0,
+        });
+        assert!(admitter.admit(b"coffee", 100));
+        assert!(!admitter.admit(b"coffee", 1024));
+
+        let mut admitter = Admitter::new(Admission::Frequency { threshold: 2 });
+        admitter.record(b"coffee");
+        assert!(!admitter.admit(b"coffee", 100));
+        admitter.record(b"coffee");
+        assert!(admitter.admit(b"coffee", 100));
+    }
+}
@@ -11,6 +11,9 @@ use std::path::Path;
 pub struct Builder {
     hash_power: u8,
     overflow_factor: f64,
+    cardinality_interval: std::time::Duration,
+    size_sample_rate: u64,
+    admission: Admission,
     segments_builder: SegmentsBuilder,
 }
 
@@ -20,6 +23,9 @@ impl Default for Builder {
         Self {
             hash_power: 16,
             overflow_factor: 0.0,
+            cardinality_interval: std::time::Duration::ZERO,
+            size_sample_rate: 0,
+            admission: Admission::Always,
             segments_builder: SegmentsBuilder::default(),
         }
     }
@@ -145,6 +151,59 @@ impl Builder {
         self
     }
 
+    /// Enable estimation of the number of distinct keys read and written. The
+    /// estimates are reported through the keyspace cardinality gauges once per
+    /// interval, as part of `expire()`. An interval of zero, the default, disables the estimation.
+    ///
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// // report the number of distinct keys seen each minute
+    /// let cache = Seg::builder()
+    ///     .cardinality_interval(Duration::from_secs(60))
+    ///     .build();
+    /// ```
+    pub fn cardinality_interval(mut self, interval: std::time::Duration) -> Self {
+        self.cardinality_interval = interval;
+        self
+    }
+
+    /// Enable sampling of the key and value sizes of writes, which are
+    /// reported through the item key size and value size heatmaps. One in
+    /// every `rate` writes is sampled. A rate of zero, the default, disables
+    /// the sampling.
+    ///
+    /// ```
+    /// use seg::Seg;
+    ///
+    /// // sample the sizes of one in every hundred writes
+    /// let cache = Seg::builder()
+    ///     .size_sample_rate(100)
+    ///     .build();
+    /// ```
+    pub fn size_sample_rate(mut self, rate: u64) -> Self {
+        self.size_sample_rate = rate;
+        self
+    }
+
+    /// Specify the admission policy for writes of new items. See the
+    /// `Admission` documentation for more details about each policy. By
+    /// default, every write is admitted.
+    ///
+    /// ```
+    /// use seg::{Admission, Seg};
+    ///
+    /// // only store new items once their key has been seen twice
+    /// let cache = Seg::builder()
+    ///     .admission(Admission::Frequency { threshold: 2 })
+    ///     .build();
+    /// ```
+    pub fn admission(mut self, policy: Admission) -> Self {
+        self.admission = policy;
+        self
+    }
+
     /// Consumes the builder and returns a fully-allocated `Seg` instance.
     ///
     /// ```
@@ -162,12 +221,23 @@ impl Builder {
         let hashtable = HashTable::new(self.hash_power, self.overflow_factor);
         let segments = self.segments_builder.build()?;
         let ttl_buckets = TtlBuckets::default();
+        let cardinality = if self.cardinality_interval.is_zero() {
+            None
+        } else {
+            let secs = std::cmp::min(u32::MAX as u64, self.cardinality_interval.as_secs());
+            Some(Cardinality::new(Duration::from_secs(secs as u32)))
+        };
 
         Ok(Seg {
             hashtable,
             segments,
             ttl_buckets,
             time: Instant::recent(),
+            version: 0,
+            cardinality,
+            admitter: Admitter::new(self.admission),
+            sizes: (self.size_sample_rate > 0).then(|| SizeSampler::new(self.size_sample_rate)),
+            grace: Duration::from_secs(0),
         })
     }
 }
@@ -0,0 +1,182 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Approximate tracking of the number of distinct keys which are read and
+//! written. A large number of distinct keys read relative to the number of
+//! distinct keys written, or relative to the number of requests, is a sign of
+//! key churn which will destroy the hit rate of the cache.
+//!
+//! Each set of keys is tracked with a HyperLogLog sketch. The estimates are
+//! reported through gauges and the sketches are reset at a fixed interval, so
+//! the gauges reflect the distinct keys seen during the previous interval.
+
+use crate::*;
+
+use ahash::RandomState;
+
+gauge!(
+    KEYSPACE_READ_CARDINALITY,
+    "estimated number of distinct keys read in the last reporting interval"
+);
+gauge!(
+    KEYSPACE_WRITE_CARDINALITY,
+    "estimated number of distinct keys written in the last reporting interval"
+);
+
+// the number of bits of the hash used to select a register. the sketches use
+// 2^PRECISION bytes each and have a standard error of about 1.6%
+const PRECISION: u32 = 12;
+const REGISTERS: usize = 1 << PRECISION;
+
+/// A HyperLogLog sketch which estimates the number of distinct hashes which
+/// have been inserted.
+pub(crate) struct HyperLogLog {
+    registers: Box<[u8]>,
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        Self {
+            registers: vec![0; REGISTERS].into_boxed_slice(),
+        }
+    }
+
+    pub fn insert(&mut self, hash: u64) {
+        let index = (hash >> (64 - PRECISION)) as usize;
+        // a sentinel bit bounds the rank when the remaining bits are all zero
+        let remaining = (hash << PRECISION) | (1 << (PRECISION - 1));
+        let rank = remaining.leading_zeros() as u8 + 1;
+        if self.registers[index] < rank {
+            self.registers[index] = rank;
+        }
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+
+        let mut sum = 0.0;
+        let mut zeros = 0;
+        for register in self.registers.iter() {
+            sum += 1.0 / (1_u64 << register) as f64;
+            if *register == 0 {
+                zeros += 1;
+            }
+        }
+
+        let estimate = alpha * m * m / sum;
+
+        // use linear counting for small cardinalities, where the raw estimate
+        // is heavily biased
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for register in self.registers.iter_mut() {
+            *register = 0;
+        }
+    }
+}
+
+/// Tracks the distinct keys read and written, reporting the estimates once
+/// per interval.
+pub(crate) struct Cardinality {
+    hash_builder: RandomState,
+    reads: HyperLogLog,
+    writes: HyperLogLog,
+    interval: Duration,
+    next_report: Instant,
+}
+
+impl Cardinality {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            hash_builder: RandomState::with_seeds(
+                0xbb8c484891ec6c86,
+                0x0522a25ae9c769f9,
+                0xeed2797b9571bc75,
+                0x4feb29c1fbbd59d0,
+            ),
+            reads: HyperLogLog::new(),
+            writes: HyperLogLog::new(),
+            interval,
+            next_report: Instant::recent() + interval,
+        }
+    }
+
+    pub fn record_read(&mut self, key: &[u8]) {
+        let hash = self.hash(key);
+        self.reads.insert(hash);
+    }
+
+    pub fn record_write(&mut self, key: &[u8]) {
+        let hash = self.hash(key);
+        self.writes.insert(hash);
+    }
+
+    /// Reports the estimates and starts a new interval if the current interval
+    /// has ended.
+    pub fn report(&mut self, now: Instant) {
+        if now < self.next_report {
+            return;
+        }
+
+        KEYSPACE_READ_CARDINALITY.set(self.reads.estimate() as i64);
+        KEYSPACE_WRITE_CARDINALITY.set(self.writes.estimate() as i64);
+
+        self.reads.clear();
+        self.writes.clear();
+        self.next_report = now + self.interval;
+    }
+
+    fn hash(&self, key: &[u8]) -> u64 {
+        let mut hasher = self.hash_builder.build_hasher();
+        hasher.write(key);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate() {
+        let hash_builder = RandomState::with_seeds(1, 2, 3, 4);
+        let hash = |i: u64| {
+            let mut hasher = hash_builder.build_hasher();
+            hasher.write(&i.to_be_bytes());
+            hasher.finish()
+        };
+
+        let mut sketch = HyperLogLog::new();
+        assert_eq!(sketch.estimate(), 0);
+
+        // duplicates do not change the estimate
+        for _ in 0..10 {
+            sketch.insert(hash(0));
+        }
+        assert_eq!(sketch.estimate(), 1);
+
+        for cardinality in &[1_000_u64, 100_000] {
+            sketch.clear();
+            for i in 0..*cardinality {
+                sketch.insert(hash(i));
+                sketch.insert(hash(i));
+            }
+            let estimate = sketch.estimate() as f64;
+            let error = (estimate - *cardinality as f64).abs() / *cardinality as f64;
+            assert!(
+                error < 0.05,
+                "estimate: {} actual: {}",
+                estimate,
+                cardinality
+            );
+        }
+    }
+}
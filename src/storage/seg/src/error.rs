@@ -25,4 +25,8 @@ pub enum SegError {
     DataCorrupted,
     #[error("item is not numeric")]
     NotNumeric,
+    #[error("arithmetic overflow")]
+    Overflow,
+    #[error("item not admitted")]
+    NotAdmitted,
 }
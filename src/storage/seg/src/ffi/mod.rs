@@ -0,0 +1,482 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! A C-compatible interface to the storage engine. This allows the legacy C
+//! servers and other non-Rust embedders to link against the storage engine
+//! and use Segcache as their storage layer. The shared library is built by the
+//! `seg-ffi` crate, which enables this module.
+//!
+//! The header for this interface lives in `include/seg.h` and is generated
+//! with `cbindgen` using the configuration in `cbindgen.toml`:
+//!
+//! ```text
+//! cbindgen --config cbindgen.toml --crate seg --output include/seg.h
+//! ```
+//!
+//! All functions take an opaque `SegHandle` which is created by
+//! `seg_create()` and must be released with `seg_destroy()`. As with the Rust
+//! API, a handle is not safe for concurrent use and callers must provide
+//! their own synchronization if it is shared between threads.
+
+use crate::*;
+
+use core::slice;
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests;
+
+/// The largest hash power accepted by `seg_create()`. The hashtable buckets
+/// are indexed by the low bits of the hash and the item tags are taken from
+/// the top 12 bits, so these must not overlap.
+const MAX_HASH_POWER: u8 = 55;
+
+/// The largest overflow factor accepted by `seg_create()`, which is bounded
+/// by the maximum length of a hashtable chain.
+const MAX_OVERFLOW_FACTOR: f64 = 16.0;
+
+/// Opaque handle to a cache instance.
+pub struct SegHandle {
+    inner: Seg,
+}
+
+/// Status codes returned by the C interface.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SegStatus {
+    /// The operation was successful.
+    Ok = 0,
+    /// The key was not found.
+    NotFound = 1,
+    /// The item exists and the operation was not performed.
+    Exists = 2,
+    /// The item is too large to be stored.
+    ItemOversized = 3,
+    /// No segment could be allocated for the item.
+    NoFreeSegments = 4,
+    /// The stored value is not numeric.
+    NotNumeric = 5,
+    /// The provided buffer is too small to hold the value. The required size
+    /// is written to the length output parameter.
+    BufferTooSmall = 6,
+    /// A required pointer argument was null.
+    InvalidArgument = 7,
+    /// Any other storage error.
+    Error = 8,
+}
+
+impl From<SegError> for SegStatus {
+    fn from(other: SegError) -> Self {
+        match other {
+            SegError::NotFound => Self::NotFound,
+            SegError::Exists => Self::Exists,
+            SegError::ItemOversized { .. } => Self::ItemOversized,
+            SegError::NoFreeSegments => Self::NoFreeSegments,
+            SegError::NotNumeric => Self::NotNumeric,
+            // admission is not configurable through the C interface
+            SegError::HashTableInsertEx
+            | SegError::EvictionEx
+            | SegError::DataCorrupted
+            | SegError::Overflow
+            | SegError::NotAdmitted => Self::Error,
+        }
+    }
+}
+
+/// Options used to construct a new cache instance. Use
+/// `seg_options_default()` to get an initialized set of options.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SegOptions {
+    /// Hashtable will have `2^hash_power` slots.
+    pub hash_power: u8,
+    /// Additional hashtable capacity for chaining, as a fraction.
+    pub overflow_factor: f64,
+    /// Total bytes used for item storage.
+    pub heap_size: usize,
+    /// Size of each segment in bytes.
+    pub segment_size: i32,
+}
+
+/// A point-in-time view of the storage metrics. Note that metrics are
+/// process-wide, so these are aggregated across all cache instances.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SegStats {
+    pub item_current: i64,
+    pub item_current_bytes: i64,
+    pub item_dead: i64,
+    pub item_dead_bytes: i64,
+    pub item_delete: u64,
+    pub item_evict: u64,
+    pub item_expire: u64,
+    pub segment_current: i64,
+    pub segment_free: i64,
+    pub segment_evict: u64,
+    pub segment_expire: u64,
+}
+
+/// Returns the default set of options.
+#[no_mangle]
+pub extern "C" fn seg_options_default() -> SegOptions {
+    default_options()
+}
+
+fn default_options() -> SegOptions {
+    SegOptions {
+        hash_power: 16,
+        overflow_factor: 0.0,
+        heap_size: 64 * 1024 * 1024,
+        segment_size: 1024 * 1024,
+    }
+}
+
+/// Create a new cache instance. Returns null if the options are invalid or
+/// the cache could not be allocated. Passing null for `options` uses the
+/// defaults.
+///
+/// # Safety
+///
+/// `options` must be null or point to a valid `SegOptions`.
+#[no_mangle]
+pub unsafe extern "C" fn seg_create(options: *const SegOptions) -> *mut SegHandle {
+    let options = if options.is_null() {
+        default_options()
+    } else {
+        *options
+    };
+
+    if !valid_options(&options) {
+        return core::ptr::null_mut();
+    }
+
+    let result = catch_unwind(|| {
+        Seg::builder()
+            .hash_power(options.hash_power)
+            .overflow_factor(options.overflow_factor)
+            .heap_size(options.heap_size)
+            .segment_size(options.segment_size)
+            .build()
+    });
+
+    match result {
+        Ok(Ok(inner)) => Box::into_raw(Box::new(SegHandle { inner })),
+        Ok(Err(e)) => {
+            error!("failed to create cache: {}", e);
+            core::ptr::null_mut()
+        }
+        Err(_) => {
+            error!("failed to create cache: panicked while building");
+            core::ptr::null_mut()
+        }
+    }
+}
+
+/// Destroy a cache instance, releasing all memory. Passing null is a no-op.
+///
+/// # Safety
+///
+/// `seg` must be null or a handle returned by `seg_create()` which has not
+/// already been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn seg_destroy(seg: *mut SegHandle) {
+    if !seg.is_null() {
+        guard((), || drop(Box::from_raw(seg)))
+    }
+}
+
+/// Lookup an item by key, copying the value into `buf`. On success, or when
+/// `buf` is too small, `len` is set to the length of the value. If `cas` is
+/// non-null it receives the CAS value of the item. Numeric values are
+/// returned in their decimal string representation.
+///
+/// # Safety
+///
+/// `seg` must be a valid handle, `key` must point to `klen` bytes, `buf` must
+/// point to `buf_len` writable bytes, and `len` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn seg_get(
+    seg: *mut SegHandle,
+    key: *const u8,
+    klen: usize,
+    buf: *mut u8,
+    buf_len: usize,
+    len: *mut usize,
+    cas: *mut u32,
+) -> SegStatus {
+    guard(SegStatus::Error, || {
+        if seg.is_null() || key.is_null() || len.is_null() || (buf.is_null() && buf_len != 0) {
+            return SegStatus::InvalidArgument;
+        }
+
+        let seg = &mut (*seg).inner;
+        let key = slice::from_raw_parts(key, klen);
+
+        let item = match seg.get(key) {
+            Some(item) => item,
+            None => return SegStatus::NotFound,
+        };
+
+        let numeric;
+        let value = match item.value() {
+            Value::Bytes(b) => b,
+            Value::U64(v) => {
+                numeric = format!("{}", v);
+                numeric.as_bytes()
+            }
+        };
+
+        *len = value.len();
+
+        if !cas.is_null() {
+            *cas = item.cas();
+        }
+
+        if value.len() > buf_len {
+            return SegStatus::BufferTooSmall;
+        }
+
+        if !value.is_empty() {
+            core::ptr::copy_nonoverlapping(value.as_ptr(), buf, value.len());
+        }
+
+        SegStatus::Ok
+    })
+}
+
+/// Store an item, replacing any existing item with the same key. Values which
+/// are the decimal representation of an unsigned 64bit integer are stored as
+/// numeric values so that they can be used with `seg_incr()`/`seg_decr()`. A
+/// `ttl` of zero means the item does not expire.
+///
+/// # Safety
+///
+/// `seg` must be a valid handle, `key` must point to `klen` bytes, and
+/// `value` must point to `vlen` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn seg_set(
+    seg: *mut SegHandle,
+    key: *const u8,
+    klen: usize,
+    value: *const u8,
+    vlen: usize,
+    ttl: u32,
+) -> SegStatus {
+    guard(SegStatus::Error, || {
+        if seg.is_null() || key.is_null() || (value.is_null() && vlen != 0) {
+            return SegStatus::InvalidArgument;
+        }
+
+        let seg = &mut (*seg).inner;
+        let key = slice::from_raw_parts(key, klen);
+        let value: &[u8] = if vlen == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(value, vlen)
+        };
+        let ttl = Duration::from_secs(ttl.into());
+
+        let result = match std::str::from_utf8(value)
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+        {
+            Some(v) => seg.insert(key, v, None, ttl),
+            None => seg.insert(key, value, None, ttl),
+        };
+
+        match result {
+            Ok(()) => SegStatus::Ok,
+            Err(e) => e.into(),
+        }
+    })
+}
+
+/// Remove an item by key.
+///
+/// # Safety
+///
+/// `seg` must be a valid handle and `key` must point to `klen` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn seg_delete(seg: *mut SegHandle, key: *const u8, klen: usize) -> SegStatus {
+    guard(SegStatus::Error, || {
+        if seg.is_null() || key.is_null() {
+            return SegStatus::InvalidArgument;
+        }
+
+        let seg = &mut (*seg).inner;
+        let key = slice::from_raw_parts(key, klen);
+
+        if seg.delete(key) {
+            SegStatus::Ok
+        } else {
+            SegStatus::NotFound
+        }
+    })
+}
+
+/// Increment a numeric value with wrapping semantics. If `result` is non-null
+/// it receives the new value.
+///
+/// # Safety
+///
+/// `seg` must be a valid handle and `key` must point to `klen` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn seg_incr(
+    seg: *mut SegHandle,
+    key: *const u8,
+    klen: usize,
+    delta: u64,
+    result: *mut u64,
+) -> SegStatus {
+    guard(SegStatus::Error, || {
+        if seg.is_null() || key.is_null() {
+            return SegStatus::InvalidArgument;
+        }
+
+        let seg = &mut (*seg).inner;
+        let key = slice::from_raw_parts(key, klen);
+
+        numeric_result(seg.wrapping_add(key, delta), result)
+    })
+}
+
+/// Decrement a numeric value, saturating at zero. If `result` is non-null it
+/// receives the new value.
+///
+/// # Safety
+///
+/// `seg` must be a valid handle and `key` must point to `klen` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn seg_decr(
+    seg: *mut SegHandle,
+    key: *const u8,
+    klen: usize,
+    delta: u64,
+    result: *mut u64,
+) -> SegStatus {
+    guard(SegStatus::Error, || {
+        if seg.is_null() || key.is_null() {
+            return SegStatus::InvalidArgument;
+        }
+
+        let seg = &mut (*seg).inner;
+        let key = slice::from_raw_parts(key, klen);
+
+        numeric_result(seg.saturating_sub(key, delta), result)
+    })
+}
+
+/// Expire any segments which have reached their TTL. Returns the number of
+/// segments expired.
+///
+/// # Safety
+///
+/// `seg` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn seg_expire(seg: *mut SegHandle) -> usize {
+    guard(0, || {
+        if seg.is_null() {
+            return 0;
+        }
+        (*seg).inner.expire()
+    })
+}
+
+/// Remove all items from the cache. Returns the number of segments cleared.
+///
+/// # Safety
+///
+/// `seg` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn seg_flush(seg: *mut SegHandle) -> usize {
+    guard(0, || {
+        if seg.is_null() {
+            return 0;
+        }
+        (*seg).inner.clear()
+    })
+}
+
+/// Read the current storage metrics into `stats`.
+///
+/// # Safety
+///
+/// `stats` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn seg_stats(stats: *mut SegStats) -> SegStatus {
+    guard(SegStatus::Error, || {
+        if stats.is_null() {
+            return SegStatus::InvalidArgument;
+        }
+
+        *stats = SegStats {
+            item_current: ITEM_CURRENT.value(),
+            item_current_bytes: ITEM_CURRENT_BYTES.value(),
+            item_dead: ITEM_DEAD.value(),
+            item_dead_bytes: ITEM_DEAD_BYTES.value(),
+            item_delete: ITEM_DELETE.value(),
+            item_evict: ITEM_EVICT.value(),
+            item_expire: ITEM_EXPIRE.value(),
+            segment_current: SEGMENT_CURRENT.value(),
+            segment_free: SEGMENT_FREE.value(),
+            segment_evict: SEGMENT_EVICT.value(),
+            segment_expire: SEGMENT_EXPIRE.value(),
+        };
+
+        SegStatus::Ok
+    })
+}
+
+/// Runs the body of an exported function, returning `default` if it panics.
+/// A panic must not unwind across the C boundary, so every `extern "C"`
+/// function which can panic goes through this or `catch_unwind()`.
+fn guard<T>(default: T, f: impl FnOnce() -> T) -> T {
+    match catch_unwind(f) {
+        Ok(v) => v,
+        Err(_) => {
+            error!("panicked in a seg ffi call");
+            default
+        }
+    }
+}
+
+fn catch_unwind<T>(f: impl FnOnce() -> T) -> std::thread::Result<T> {
+    // handles are not shared across the unwind, and a handle which panicked
+    // mid-operation is no less valid than after any other storage error
+    std::panic::catch_unwind(AssertUnwindSafe(f))
+}
+
+/// Checks the options against the limits which the builder would otherwise
+/// enforce by panicking or exiting the process.
+fn valid_options(options: &SegOptions) -> bool {
+    if options.hash_power < 3 || options.hash_power > MAX_HASH_POWER {
+        return false;
+    }
+
+    if !(0.0..=MAX_OVERFLOW_FACTOR).contains(&options.overflow_factor) {
+        return false;
+    }
+
+    if options.segment_size <= 0 {
+        return false;
+    }
+
+    // segment ids are stored in 24 bits
+    options.heap_size / (options.segment_size as usize) < (1 << 24)
+}
+
+unsafe fn numeric_result(result: Result<Item, SegError>, out: *mut u64) -> SegStatus {
+    match result {
+        Ok(item) => {
+            if !out.is_null() {
+                if let Value::U64(v) = item.value() {
+                    *out = v;
+                }
+            }
+            SegStatus::Ok
+        }
+        Err(e) => e.into(),
+    }
+}
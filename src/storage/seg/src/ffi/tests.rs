@@ -0,0 +1,166 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+use super::*;
+
+fn create() -> *mut SegHandle {
+    let mut options = seg_options_default();
+    options.segment_size = 4096;
+    options.heap_size = 4096 * 64;
+    let seg = unsafe { seg_create(&options) };
+    assert!(!seg.is_null());
+    seg
+}
+
+#[test]
+fn create_destroy() {
+    let seg = create();
+    unsafe {
+        seg_destroy(seg);
+        seg_destroy(core::ptr::null_mut());
+    }
+
+    let mut options = seg_options_default();
+    options.hash_power = 0;
+    assert!(unsafe { seg_create(&options) }.is_null());
+
+    // the bucket index would overlap the item tags
+    options.hash_power = 56;
+    assert!(unsafe { seg_create(&options) }.is_null());
+
+    // segment ids only have 24 bits
+    let mut options = seg_options_default();
+    options.segment_size = 1;
+    options.heap_size = 1 << 24;
+    assert!(unsafe { seg_create(&options) }.is_null());
+
+    let mut options = seg_options_default();
+    options.overflow_factor = -1.0;
+    assert!(unsafe { seg_create(&options) }.is_null());
+}
+
+#[test]
+fn set_get_delete() {
+    let seg = create();
+    let mut buf = [0; 64];
+    let mut len = 0;
+    let mut cas = 0;
+
+    unsafe {
+        assert_eq!(
+            seg_get(
+                seg,
+                b"drink".as_ptr(),
+                5,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut len,
+                &mut cas
+            ),
+            SegStatus::NotFound
+        );
+
+        assert_eq!(
+            seg_set(seg, b"drink".as_ptr(), 5, b"coffee".as_ptr(), 6, 0),
+            SegStatus::Ok
+        );
+        assert_eq!(
+            seg_get(
+                seg,
+                b"drink".as_ptr(),
+                5,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut len,
+                &mut cas
+            ),
+            SegStatus::Ok
+        );
+        assert_eq!(&buf[0..len], b"coffee");
+
+        // a buffer which is too small reports the required length
+        assert_eq!(
+            seg_get(
+                seg,
+                b"drink".as_ptr(),
+                5,
+                buf.as_mut_ptr(),
+                2,
+                &mut len,
+                core::ptr::null_mut()
+            ),
+            SegStatus::BufferTooSmall
+        );
+        assert_eq!(len, 6);
+
+        assert_eq!(seg_delete(seg, b"drink".as_ptr(), 5), SegStatus::Ok);
+        assert_eq!(seg_delete(seg, b"drink".as_ptr(), 5), SegStatus::NotFound);
+
+        seg_destroy(seg);
+    }
+}
+
+#[test]
+fn incr_decr() {
+    let seg = create();
+    let mut buf = [0; 64];
+    let mut len = 0;
+    let mut result = 0;
+
+    unsafe {
+        assert_eq!(
+            seg_incr(seg, b"count".as_ptr(), 5, 1, &mut result),
+            SegStatus::NotFound
+        );
+
+        assert_eq!(
+            seg_set(seg, b"count".as_ptr(), 5, b"41".as_ptr(), 2, 0),
+            SegStatus::Ok
+        );
+        assert_eq!(
+            seg_incr(seg, b"count".as_ptr(), 5, 1, &mut result),
+            SegStatus::Ok
+        );
+        assert_eq!(result, 42);
+        assert_eq!(
+            seg_get(
+                seg,
+                b"count".as_ptr(),
+                5,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut len,
+                core::ptr::null_mut()
+            ),
+            SegStatus::Ok
+        );
+        assert_eq!(&buf[0..len], b"42");
+
+        assert_eq!(
+            seg_decr(seg, b"count".as_ptr(), 5, 100, &mut result),
+            SegStatus::Ok
+        );
+        assert_eq!(result, 0);
+
+        assert_eq!(
+            seg_set(seg, b"drink".as_ptr(), 5, b"coffee".as_ptr(), 6, 0),
+            SegStatus::Ok
+        );
+        assert_eq!(
+            seg_incr(seg, b"drink".as_ptr(), 5, 1, &mut result),
+            SegStatus::NotNumeric
+        );
+
+        seg_destroy(seg);
+    }
+}
+
+#[test]
+fn stats() {
+    let mut stats = SegStats::default();
+    unsafe {
+        assert_eq!(seg_stats(core::ptr::null_mut()), SegStatus::InvalidArgument);
+        assert_eq!(seg_stats(&mut stats), SegStatus::Ok);
+    }
+}
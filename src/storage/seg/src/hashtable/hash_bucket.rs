@@ -8,7 +8,7 @@
 //! Bucket Info:
 //! ```text
 //! ┌──────────────────────────────┬──────┬──────┬──────────────┐
-//! │             CAS              │ ---- │CHAIN │  TIMESTAMP   │
+//! │             CAS              │ITEMS │CHAIN │  TIMESTAMP   │
 //! │                              │      │ LEN  │              │
 //! │            32 bit            │8 bit │8 bit │    16 bit    │
 //! │                              │      │ LEN  │              │
@@ -29,6 +29,9 @@
 
 use super::*;
 
+/// A mask to get the bits containing the number of items held in the chain
+/// from the bucket info
+pub(crate) const BUCKET_ITEMS_MASK: u64 = 0x0000_0000_FF00_0000;
 /// A mask to get the bits containing the chain length from the bucket info
 pub(crate) const BUCKET_CHAIN_LEN_MASK: u64 = 0x0000_0000_00FF_0000;
 /// A mask to get the bits containing the timestamp from the bucket info
@@ -39,6 +42,9 @@ pub(crate) const CAS_MASK: u64 = 0xFFFF_FFFF_0000_0000;
 /// Number of bits to shift the bucket info masked with the chain length mask
 /// to get the actual chain length
 pub(crate) const BUCKET_CHAIN_LEN_BIT_SHIFT: u64 = 16;
+/// Number of bits to shift the bucket info masked with the items mask to get
+/// the number of items held in the chain
+pub(crate) const BUCKET_ITEMS_BIT_SHIFT: u64 = 24;
 /// Number of bits to shift the bucket info masked with the cas mask to get the
 /// cas value
 pub(crate) const CAS_BIT_SHIFT: u64 = 32;
@@ -139,6 +145,12 @@ pub const fn chain_len(bucket_info: u64) -> u64 {
     (bucket_info & BUCKET_CHAIN_LEN_MASK) >> BUCKET_CHAIN_LEN_BIT_SHIFT
 }
 
+/// Get the number of items held in the chain from the bucket info
+#[inline]
+pub const fn bucket_items(bucket_info: u64) -> u64 {
+    (bucket_info & BUCKET_ITEMS_MASK) >> BUCKET_ITEMS_BIT_SHIFT
+}
+
 /// Create the item info from the tag, segment id, and offset
 #[inline]
 pub const fn build_item_info(tag: u64, seg_id: NonZeroU32, offset: u64) -> u64 {
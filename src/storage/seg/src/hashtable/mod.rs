@@ -73,6 +73,10 @@ const N_BUCKET_SLOT: usize = 8;
 /// Maximum number of buckets in a chain. Must be <= 255.
 const MAX_CHAIN_LEN: u64 = 16;
 
+/// Maximum number of items held by a primary bucket and its chain, which must
+/// fit in the item count of the bucket info.
+const MAX_CHAIN_ITEMS: usize = (N_BUCKET_SLOT - 1) * (MAX_CHAIN_LEN as usize + 1);
+
 use crate::*;
 use ahash::RandomState;
 use core::marker::PhantomData;
@@ -104,6 +108,25 @@ counter!(ITEM_REPLACE, "number of times items have been replaced");
 counter!(ITEM_DELETE, "number of items removed from the hash table");
 counter!(ITEM_EXPIRE, "number of items removed due to expiration");
 counter!(ITEM_EVICT, "number of items removed due to eviction");
+heatmap!(
+    ITEM_EVICT_AGE,
+    2_592_000,
+    "distribution of the age in seconds of items when they are evicted"
+);
+heatmap!(
+    ITEM_EVICT_TTL_REMAINING,
+    2_592_000,
+    "distribution of the seconds of ttl remaining for items with a ttl when they are evicted"
+);
+heatmap!(
+    ITEM_EVICT_SIZE,
+    1_073_741_824,
+    "distribution of the size in bytes of items when they are evicted"
+);
+counter!(
+    ITEM_STALE,
+    "number of lookups which found an item from before the last flush"
+);
 
 #[derive(Debug)]
 struct IterState {
@@ -210,6 +233,22 @@ impl<'a> Iterator for IterMut<'a> {
     }
 }
 
+/// Returns the initial count of primary buckets by chain length, where every
+/// primary bucket has no chain.
+fn chain_lengths_init(buckets: u64) -> [u64; MAX_CHAIN_LEN as usize + 1] {
+    let mut chain_lengths = [0; MAX_CHAIN_LEN as usize + 1];
+    chain_lengths[0] = buckets;
+    chain_lengths
+}
+
+/// Returns the initial count of primary buckets by the number of items they
+/// hold, where every primary bucket is empty.
+fn occupancy_init(buckets: u64) -> [u64; MAX_CHAIN_ITEMS + 1] {
+    let mut occupancy = [0; MAX_CHAIN_ITEMS + 1];
+    occupancy[0] = buckets;
+    occupancy
+}
+
 /// Main structure for performing item lookup. Contains a contiguous allocation
 /// of [`HashBucket`]s which are used to store item info and metadata.
 #[repr(C)]
@@ -220,6 +259,12 @@ pub(crate) struct HashTable {
     data: Box<[HashBucket]>,
     started: Instant,
     next_to_chain: u64,
+    // running counts of the items and of the primary buckets by chain length
+    // and by the number of items they hold, so that reporting the occupancy
+    // does not need to walk the buckets
+    items: u64,
+    chain_lengths: [u64; MAX_CHAIN_LEN as usize + 1],
+    occupancy: [u64; MAX_CHAIN_ITEMS + 1],
     _pad: [u8; 8],
 }
 
@@ -265,10 +310,107 @@ impl HashTable {
             data: data.into_boxed_slice(),
             started: Instant::now(),
             next_to_chain: buckets as u64,
+            items: 0,
+            chain_lengths: chain_lengths_init(buckets),
+            occupancy: occupancy_init(buckets),
             _pad: [0; 8],
         }
     }
 
+    /// Summarize the occupancy of the hashtable from the running counts.
+    pub(crate) fn info(&self) -> HashTableInfo {
+        let buckets = (self.mask + 1) as usize;
+
+        // omit the trailing zeros of the item distribution, which is sized for
+        // the longest possible chain
+        let occupied = self
+            .occupancy
+            .iter()
+            .rposition(|buckets| *buckets > 0)
+            .map(|last| last + 1)
+            .unwrap_or(0);
+
+        HashTableInfo {
+            power: self.power as u8,
+            buckets,
+            overflow_buckets: self.data.len() - buckets,
+            overflow_used: self.next_to_chain as usize - buckets,
+            items: self.items,
+            slots: ((N_BUCKET_SLOT - 1) * self.data.len()) as u64,
+            chain_lengths: self.chain_lengths.to_vec(),
+            occupancy: self.occupancy[..occupied].to_vec(),
+            tag_collisions: HASH_TAG_COLLISION.value(),
+            lookups: HASH_LOOKUP.value(),
+        }
+    }
+
+    /// Count an item added to the chain of the primary bucket.
+    fn occupy(&mut self, bucket_id: usize) {
+        let items = bucket_items(self.data[bucket_id].data[0]) as usize;
+        self.occupancy[items] -= 1;
+        self.occupancy[items + 1] += 1;
+        self.data[bucket_id].data[0] += 1 << BUCKET_ITEMS_BIT_SHIFT;
+        self.items += 1;
+    }
+
+    /// Count an item removed from the chain of the primary bucket.
+    fn vacate(&mut self, bucket_id: usize) {
+        let items = bucket_items(self.data[bucket_id].data[0]) as usize;
+        self.occupancy[items] -= 1;
+        self.occupancy[items - 1] += 1;
+        self.data[bucket_id].data[0] -= 1 << BUCKET_ITEMS_BIT_SHIFT;
+        self.items -= 1;
+    }
+
+    /// Collects the keys of the live items in up to `count` primary buckets,
+    /// and their chains, starting with the bucket at `cursor`. Returns the
+    /// cursor to continue from, which is zero once the last bucket has been
+    /// visited. Items stay in the bucket for their key when they are moved
+    /// between segments, so a scan which is spread over many calls visits
+    /// every item which is stored for its whole duration.
+    pub(crate) fn scan_keys(
+        &self,
+        cursor: usize,
+        count: usize,
+        segments: &mut Segments,
+        keys: &mut Vec<Box<[u8]>>,
+    ) -> usize {
+        let buckets = (self.mask + 1) as usize;
+        let end = cursor.saturating_add(count.max(1)).min(buckets);
+
+        for primary in cursor..end {
+            let chain_len = chain_len(self.data[primary].data[0]) as usize;
+            let mut bucket_id = primary;
+
+            for chain_idx in 0..=chain_len {
+                let bucket = &self.data[bucket_id];
+                let first = if chain_idx == 0 { 1 } else { 0 };
+                let last = if chain_idx == chain_len {
+                    N_BUCKET_SLOT
+                } else {
+                    N_BUCKET_SLOT - 1
+                };
+                for item_info in bucket.data[first..last].iter() {
+                    if *item_info == 0 || segments.is_stale(*item_info) {
+                        continue;
+                    }
+                    if let Some(item) = segments.get_item(*item_info) {
+                        keys.push(item.key().into());
+                    }
+                }
+                if chain_idx < chain_len {
+                    bucket_id = bucket.data[N_BUCKET_SLOT - 1] as usize;
+                }
+            }
+        }
+
+        if end == buckets {
+            0
+        } else {
+            end
+        }
+    }
+
     /// Lookup an item by key and return it
     pub fn get(&mut self, key: &[u8], time: Instant, segments: &mut Segments) -> Option<Item> {
         let hash = self.hash(key);
@@ -295,6 +437,9 @@ impl HashTable {
                 let current_item = segments.get_item(*item_info).unwrap();
                 if current_item.key() != key {
                     HASH_TAG_COLLISION.increment();
+                } else if segments.is_stale(*item_info) {
+                    ITEM_STALE.increment();
+                    return None;
                 } else {
                     // update item frequency
                     let mut freq = get_freq(*item_info);
@@ -337,6 +482,9 @@ impl HashTable {
                 let current_item = segments.get_item(*item_info).unwrap();
                 if current_item.key() != key {
                     HASH_TAG_COLLISION.increment();
+                } else if segments.is_stale(*item_info) {
+                    ITEM_STALE.increment();
+                    return None;
                 } else {
                     let item = Item::new(
                         current_item,
@@ -401,6 +549,53 @@ impl HashTable {
         Err(())
     }
 
+    /// Return the id of the segment which holds the item with the key, if the
+    /// item is stored and was not removed by a flush.
+    pub(crate) fn get_seg_id(&mut self, key: &[u8], segments: &mut Segments) -> Option<NonZeroU32> {
+        let hash = self.hash(key);
+        let tag = tag_from_hash(hash);
+        let iter = IterMut::new(self, hash);
+
+        for item_info in iter {
+            if get_tag(*item_info) == tag {
+                let current_item = segments.get_item(*item_info).unwrap();
+                if current_item.key() != key {
+                    HASH_TAG_COLLISION.increment();
+                } else if segments.is_stale(*item_info) {
+                    return None;
+                } else {
+                    return get_seg_id(*item_info);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns the frequency of the item with the key, without counting this
+    /// lookup as an access. This is zero for an item which has not been read
+    /// since it was written.
+    pub(crate) fn get_freq_by_key(&mut self, key: &[u8], segments: &mut Segments) -> Option<u64> {
+        let hash = self.hash(key);
+        let tag = tag_from_hash(hash);
+        let iter = IterMut::new(self, hash);
+
+        for item_info in iter {
+            if get_tag(*item_info) == tag {
+                let current_item = segments.get_item(*item_info).unwrap();
+                if current_item.key() != key {
+                    HASH_TAG_COLLISION.increment();
+                } else if segments.is_stale(*item_info) {
+                    return None;
+                } else {
+                    return Some(get_freq(*item_info) & 0x7F);
+                }
+            }
+        }
+
+        None
+    }
+
     pub(crate) fn is_item_at(&mut self, key: &[u8], seg: NonZeroU32, offset: u64) -> bool {
         let hash = self.hash(key);
         let tag = tag_from_hash(hash);
@@ -441,6 +636,7 @@ impl HashTable {
         let mut insert_item_info = build_item_info(tag, seg, offset);
 
         let mut removed: Option<u64> = None;
+        let mut occupied = false;
 
         let iter = IterMut::new(self, hash);
 
@@ -450,6 +646,7 @@ impl HashTable {
                     // found a blank slot
                     *item_info = insert_item_info;
                     insert_item_info = 0;
+                    occupied = true;
                 }
                 continue;
             }
@@ -464,6 +661,10 @@ impl HashTable {
             }
         }
 
+        if occupied {
+            self.occupy((hash & self.mask) as usize);
+        }
+
         if let Some(removed_item) = removed {
             ITEM_REPLACE.increment();
             let _ = segments.remove_item(removed_item, ttl_buckets, self);
@@ -489,6 +690,9 @@ impl HashTable {
                 self.data[bucket_id].data[N_BUCKET_SLOT - 1] = next_id as u64;
 
                 self.data[(hash & self.mask) as usize].data[0] += 0x0000_0000_0001_0000;
+                self.chain_lengths[chain_len as usize] -= 1;
+                self.chain_lengths[chain_len as usize + 1] += 1;
+                self.occupy((hash & self.mask) as usize);
             }
         }
 
@@ -526,6 +730,9 @@ impl HashTable {
                 let item = segments.get_item(*item_info).unwrap();
                 if item.key() != key {
                     HASH_TAG_COLLISION.increment();
+                } else if segments.is_stale(*item_info) {
+                    ITEM_STALE.increment();
+                    return Err(SegError::NotFound);
                 } else {
                     // update item frequency
                     let mut freq = get_freq(*item_info);
@@ -582,9 +789,13 @@ impl HashTable {
         }
 
         if let Some(removed_item) = removed {
+            self.vacate((hash & self.mask) as usize);
             ITEM_DELETE.increment();
+            // an item from before the last flush is removed, but is reported
+            // as not found since it was no longer visible
+            let stale = segments.is_stale(removed_item);
             let _ = segments.remove_item(removed_item, ttl_buckets, self);
-            true
+            !stale
         } else {
             false
         }
@@ -592,9 +803,26 @@ impl HashTable {
 
     /// Evict a single item from the cache
     pub fn evict(&mut self, key: &[u8], offset: i32, segment: &mut Segment) -> bool {
+        let size = segment
+            .get_item_at(offset as usize)
+            .map(|item| item.size())
+            .unwrap_or(0);
         let result = self.remove_from(key, offset, segment);
         if result {
             ITEM_EVICT.increment();
+            common::events::publish(common::events::Event::ItemEvicted { key });
+
+            // items share the creation time and ttl of their segment. items
+            // which are evicted with much of their ttl remaining point to a
+            // lack of capacity rather than to ttls which are too long
+            let now = common::time::Instant::<common::time::Nanoseconds<u64>>::now();
+            let age = segment.create_at().elapsed().as_secs() as u64;
+            ITEM_EVICT_AGE.increment(now, age, 1);
+            ITEM_EVICT_SIZE.increment(now, size as u64, 1);
+            let ttl = segment.ttl().as_secs() as u64;
+            if ttl > 0 {
+                ITEM_EVICT_TTL_REMAINING.increment(now, ttl.saturating_sub(age), 1);
+            }
         }
         result
     }
@@ -613,6 +841,7 @@ impl HashTable {
         let hash = self.hash(key);
         let tag = tag_from_hash(hash);
         let evict_item_info = build_item_info(tag, segment.id(), offset as u64);
+        let mut removed = false;
 
         let iter = IterMut::new(self, hash);
 
@@ -632,11 +861,16 @@ impl HashTable {
             if evict_item_info == current_item_info {
                 segment.remove_item(current_item_info);
                 *item_info = 0;
-                return true;
+                removed = true;
+                break;
             }
         }
 
-        false
+        if removed {
+            self.vacate((hash & self.mask) as usize);
+        }
+
+        removed
     }
 
     /// Internal function used to calculate a hash value for a key
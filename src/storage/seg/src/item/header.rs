@@ -13,9 +13,18 @@
 //! │            32 bit            │        24 bit        │8 bit │ 8bit │
 //! │          0xDECAFBAD          │                      │      │      │
 //! │0                           31│32                  55│56  63│64  71│
-//! └──────────────────────────────┴──────────────────────┴──────┴──────┘
+//! ├──────────────────────────────┴──────────────────────┴──────┴──────┤
+//! │                        VERSION (Optional)                         │
+//! │                                                                   │
+//! │                              64 bit                               │
+//! │                                                                   │
+//! │72                                                              135│
+//! └───────────────────────────────────────────────────────────────────┘
 //! ```
 //!
+//! The version is only stored when the `versions` feature is enabled, as it
+//! more than doubles the size of the header.
+//!
 //! Flags:
 //! ```text
 //! ┌──────────────┬──────────────┬──────────────────────────────┐
@@ -113,6 +122,8 @@ pub struct ItemHeader {
     magic: u32,
     len: u32,  // packs vlen:24 klen:8
     flags: u8, // packs is_num:1, deleted:1, olen:6
+    #[cfg(feature = "versions")]
+    version: u64,
 }
 
 impl ItemHeader {
@@ -159,6 +170,13 @@ impl ItemHeader {
         self.flags & OLEN_MASK
     }
 
+    /// Get the item's version
+    #[cfg(feature = "versions")]
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
     /// Is the item a typed value?
     #[inline]
     fn is_typed(&self) -> bool {
@@ -219,6 +237,11 @@ impl ItemHeader {
 
         self.len = 0;
         self.flags = 0;
+
+        #[cfg(feature = "versions")]
+        {
+            self.version = 0;
+        }
     }
 
     /// Set the optional length
@@ -227,17 +250,31 @@ impl ItemHeader {
         debug_assert!(len <= OLEN_MASK);
         self.flags = (self.flags & !OLEN_MASK) | len;
     }
+
+    /// Set the item's version. This does nothing unless the `versions`
+    /// feature is enabled.
+    #[inline]
+    pub fn set_version(&mut self, version: u64) {
+        #[cfg(feature = "versions")]
+        {
+            self.version = version;
+        }
+        #[cfg(not(feature = "versions"))]
+        let _ = version;
+    }
 }
 
 #[cfg(not(feature = "magic"))]
 impl std::fmt::Debug for ItemHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        f.debug_struct("ItemHeader")
-            .field("klen", &self.klen())
+        let mut s = f.debug_struct("ItemHeader");
+        s.field("klen", &self.klen())
             .field("vlen", &self.vlen())
             .field("type", &self.value_type())
-            .field("olen", &self.olen())
-            .finish()
+            .field("olen", &self.olen());
+        #[cfg(feature = "versions")]
+        s.field("version", &self.version());
+        s.finish()
     }
 }
 
@@ -245,12 +282,14 @@ impl std::fmt::Debug for ItemHeader {
 impl std::fmt::Debug for ItemHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
         let magic = self.magic;
-        f.debug_struct("ItemHeader")
-            .field("magic", &format!("0x{:X}", magic))
+        let mut s = f.debug_struct("ItemHeader");
+        s.field("magic", &format!("0x{:X}", magic))
             .field("klen", &self.klen())
             .field("vlen", &self.vlen())
             .field("typed", &self.is_typed())
-            .field("olen", &self.olen())
-            .finish()
+            .field("olen", &self.olen());
+        #[cfg(feature = "versions")]
+        s.field("version", &self.version());
+        s.finish()
     }
 }
@@ -56,6 +56,21 @@ impl Item {
         self.cas
     }
 
+    /// The item version. Versions are assigned from a counter which increases
+    /// with every write to the cache, so successive writes to the same key
+    /// always have increasing versions, even across deletes. Unlike the CAS
+    /// value, the version is unique to the item and is not shared within a
+    /// hash bucket. Requires the `versions` feature.
+    #[cfg(feature = "versions")]
+    pub fn version(&self) -> u64 {
+        self.raw.version()
+    }
+
+    /// Set the item version, used when the item is updated in place
+    pub(crate) fn set_version(&mut self, version: u64) {
+        self.raw.set_version(version)
+    }
+
     /// Borrow the optional data
     pub fn optional(&self) -> Option<&[u8]> {
         self.raw.optional()
@@ -64,22 +79,59 @@ impl Item {
     /// Perform a wrapping addition on the value. Returns an error if the item
     /// is not a numeric type.
     pub fn wrapping_add(&mut self, rhs: u64) -> Result<(), SegError> {
-        self.raw.wrapping_add(rhs)
+        self.add(rhs, Overflow::Wrap)
     }
 
     /// Perform a saturating subtraction on the value. Returns an error if the
     /// item is not a numeric type.
     pub fn saturating_sub(&mut self, rhs: u64) -> Result<(), SegError> {
-        self.raw.saturating_sub(rhs)
+        self.sub(rhs, Overflow::Saturate)
+    }
+
+    /// Perform an addition on the value, handling overflow as specified.
+    /// Returns an error if the item is not a numeric type, or if the addition
+    /// overflows with [`Overflow::Error`].
+    pub fn add(&mut self, rhs: u64, overflow: Overflow) -> Result<(), SegError> {
+        self.raw.update_numeric(|v| match overflow {
+            Overflow::Wrap => Some(v.wrapping_add(rhs)),
+            Overflow::Saturate => Some(v.saturating_add(rhs)),
+            Overflow::Error => v.checked_add(rhs),
+        })
+    }
+
+    /// Perform a subtraction on the value, handling a result below zero as
+    /// specified. Returns an error if the item is not a numeric type, or if
+    /// the subtraction underflows with [`Overflow::Error`].
+    pub fn sub(&mut self, rhs: u64, underflow: Overflow) -> Result<(), SegError> {
+        self.raw.update_numeric(|v| match underflow {
+            Overflow::Wrap => Some(v.wrapping_sub(rhs)),
+            Overflow::Saturate => Some(v.saturating_sub(rhs)),
+            Overflow::Error => v.checked_sub(rhs),
+        })
     }
 }
 
+/// How arithmetic on a numeric value handles a result which is out of the
+/// range of a `u64`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// The result wraps around, so that incrementing the largest value gives
+    /// zero. This is how memcached increments.
+    Wrap,
+    /// The result is clamped to the largest or smallest value. This is how
+    /// memcached decrements.
+    Saturate,
+    /// The operation fails and the value is unchanged.
+    Error,
+}
+
 impl std::fmt::Debug for Item {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        f.debug_struct("Item")
-            .field("cas", &self.cas())
-            .field("raw", &self.raw)
-            .finish()
+        let mut s = f.debug_struct("Item");
+        s.field("cas", &self.cas());
+        #[cfg(feature = "versions")]
+        s.field("version", &self.version());
+        s.field("raw", &self.raw).finish()
     }
 }
 
@@ -101,6 +101,20 @@ impl RawItem {
         }
     }
 
+    /// Returns the item version
+    #[cfg(feature = "versions")]
+    #[inline]
+    pub(crate) fn version(&self) -> u64 {
+        self.header().version()
+    }
+
+    /// Set the item version
+    pub(crate) fn set_version(&mut self, version: u64) {
+        unsafe {
+            (*self.header_mut()).set_version(version);
+        }
+    }
+
     /// Check the header magic bytes
     #[inline]
     pub(crate) fn check_magic(&self) {
@@ -108,9 +122,10 @@ impl RawItem {
     }
 
     /// Copy data into the item
-    pub(crate) fn define(&mut self, key: &[u8], value: Value, optional: &[u8]) {
+    pub(crate) fn define(&mut self, key: &[u8], value: Value, optional: &[u8], version: u64) {
         unsafe {
             (*self.header_mut()).init();
+            (*self.header_mut()).set_version(version);
         }
         match value {
             Value::Bytes(value) => unsafe {
@@ -184,32 +199,25 @@ impl RawItem {
             << 3
     }
 
-    pub(crate) fn wrapping_add(&mut self, rhs: u64) -> Result<(), SegError> {
-        match self.value() {
-            Value::U64(v) => unsafe {
-                let new = v.wrapping_add(rhs);
-                std::ptr::copy_nonoverlapping(
-                    new.to_be_bytes().as_ptr(),
-                    self.data.add(self.value_offset()),
-                    core::mem::size_of::<u64>(),
-                );
-                Ok(())
-            },
-            _ => Err(SegError::NotNumeric),
-        }
-    }
-
-    pub(crate) fn saturating_sub(&mut self, rhs: u64) -> Result<(), SegError> {
+    /// Applies the operation to the value, leaving it unchanged if the
+    /// operation overflows. Returns an error if the item is not a numeric
+    /// type or the operation overflows.
+    pub(crate) fn update_numeric<F>(&mut self, op: F) -> Result<(), SegError>
+    where
+        F: FnOnce(u64) -> Option<u64>,
+    {
         match self.value() {
-            Value::U64(v) => unsafe {
-                let new = v.saturating_sub(rhs);
-                std::ptr::copy_nonoverlapping(
-                    new.to_be_bytes().as_ptr(),
-                    self.data.add(self.value_offset()),
-                    core::mem::size_of::<u64>(),
-                );
+            Value::U64(v) => {
+                let new = op(v).ok_or(SegError::Overflow)?;
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        new.to_be_bytes().as_ptr(),
+                        self.data.add(self.value_offset()),
+                        core::mem::size_of::<u64>(),
+                    );
+                }
                 Ok(())
-            },
+            }
             _ => Err(SegError::NotNumeric),
         }
     }
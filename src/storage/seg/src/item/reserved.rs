@@ -24,9 +24,9 @@ impl ReservedItem {
         Self { item, seg, offset }
     }
 
-    /// Store the key, value, and optional data into the item
-    pub fn define(&mut self, key: &[u8], value: Value, optional: &[u8]) {
-        self.item.define(key, value, optional)
+    /// Store the key, value, optional data, and version into the item
+    pub fn define(&mut self, key: &[u8], value: Value, optional: &[u8], version: u64) {
+        self.item.define(key, value, optional, version)
     }
 
     /// Get the `RawItem` that backs the `ReservedItem`
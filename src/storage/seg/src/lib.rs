@@ -27,6 +27,7 @@
 extern crate logger;
 
 // external crate includes
+use common::signal::HashTableInfo;
 use common::time::Seconds;
 use rustcommon_metrics::*;
 
@@ -39,7 +40,9 @@ use std::convert::TryInto;
 const VERSION: u64 = 0;
 
 // submodules
+mod admission;
 mod builder;
+mod cardinality;
 mod error;
 mod eviction;
 mod hashtable;
@@ -47,31 +50,44 @@ mod item;
 mod rand;
 mod seg;
 mod segments;
+mod sizes;
 mod ttl_buckets;
 
+// c interface
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
 // tests
 #[cfg(test)]
 mod tests;
 
 // publicly exported items from submodules
 pub use crate::seg::Seg;
+pub use admission::Admission;
 pub use builder::Builder;
 pub use error::SegError;
 pub use eviction::Policy;
-pub use item::Item;
+pub use item::{Item, Overflow};
+pub use ttl_buckets::TtlBucketStats;
 
 // publicly exported items from external crates
 pub use storage_types::Value;
 
+/// The version of the storage engine
+pub const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
 // type aliases
 pub(crate) type Duration = common::time::Duration<Seconds<u32>>;
 pub(crate) type Instant = common::time::Instant<Seconds<u32>>;
 
 // items from submodules which are imported for convenience to the crate level
 pub(crate) use crate::rand::*;
+pub(crate) use admission::*;
+pub(crate) use cardinality::*;
 pub(crate) use hashtable::*;
 pub(crate) use item::*;
 pub(crate) use segments::*;
+pub(crate) use sizes::*;
 pub(crate) use ttl_buckets::*;
 
 common::metrics::test_no_duplicates!();
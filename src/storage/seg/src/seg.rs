@@ -29,6 +29,16 @@ pub struct Seg {
     pub(crate) segments: Segments,
     pub(crate) ttl_buckets: TtlBuckets,
     pub(crate) time: Instant,
+    // the most recently assigned item version
+    pub(crate) version: u64,
+    // tracks distinct keys read and written, if enabled
+    pub(crate) cardinality: Option<Cardinality>,
+    // decides which writes of new items are stored
+    pub(crate) admitter: Admitter,
+    // samples the key and value sizes of writes, if enabled
+    pub(crate) sizes: Option<SizeSampler>,
+    // the time for which segments are kept past their expiry
+    pub(crate) grace: Duration,
 }
 
 impl Seg {
@@ -67,6 +77,28 @@ impl Seg {
         self.segments.items()
     }
 
+    /// Gets a count of the live items in the `Seg` instance. This is a running
+    /// total which is kept as items are written and removed, so it is cheap
+    /// enough to be called on the request path. Items which have expired but
+    /// not yet been reclaimed are included in the count.
+    ///
+    /// ```
+    /// use seg::{Policy, Seg};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// assert_eq!(cache.live_items(), 0);
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::ZERO);
+    /// assert_eq!(cache.live_items(), 1);
+    ///
+    /// cache.flush();
+    /// assert_eq!(cache.live_items(), 0);
+    /// ```
+    pub fn live_items(&self) -> usize {
+        self.segments.live_items()
+    }
+
     /// Get the item in the `Seg` with the provided key
     ///
     /// ```
@@ -81,6 +113,10 @@ impl Seg {
     /// assert_eq!(item.value(), b"strong");
     /// ```
     pub fn get(&mut self, key: &[u8]) -> Option<Item> {
+        if let Some(cardinality) = self.cardinality.as_mut() {
+            cardinality.record_read(key);
+        }
+        self.admitter.record(key);
         self.hashtable.get(key, self.time, &mut self.segments)
     }
 
@@ -98,7 +134,8 @@ impl Seg {
     }
 
     /// Insert a new item into the cache. May return an error indicating that
-    /// the insert was not successful.
+    /// the insert was not successful, including `NotAdmitted` if the key is
+    /// not already stored and the write was rejected by the admission policy.
     /// ```
     /// use seg::{Policy, Seg};
     /// use std::time::Duration;
@@ -121,7 +158,142 @@ impl Seg {
         optional: Option<&[u8]>,
         ttl: std::time::Duration,
     ) -> Result<(), SegError> {
-        let value: Value = value.into();
+        let value = value.into();
+
+        if !self.admitter.always() {
+            self.admitter.record(key);
+            if self
+                .hashtable
+                .get_no_freq_incr(key, &mut self.segments)
+                .is_none()
+            {
+                let size = key.len() + size_of(&value) + optional.map(|o| o.len()).unwrap_or(0);
+                if !self.admitter.admit(key, size) {
+                    return Err(SegError::NotAdmitted);
+                }
+            }
+        }
+
+        let version = self.next_version();
+        self.insert_at_version(key, value, optional, ttl, version)
+    }
+
+    /// Insert an item only if the version of the item currently stored for
+    /// the key matches the provided version. This allows for optimistic
+    /// concurrency control which, unlike CAS, is not affected by writes to
+    /// other keys in the same hash bucket. Requires the `versions` feature.
+    ///
+    /// ```
+    /// use seg::{Seg, SegError};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    ///
+    /// // If the item is not in the cache, the insert fails as 'NotFound'
+    /// assert_eq!(
+    ///     cache.insert_if_version(b"drink", b"coffee", None, Duration::ZERO, 0),
+    ///     Err(SegError::NotFound)
+    /// );
+    ///
+    /// cache.insert(b"drink", b"coffee", None, Duration::ZERO);
+    /// let version = cache.get(b"drink").expect("not found").version();
+    ///
+    /// // A write in the meantime causes the insert to fail as 'Exists'
+    /// cache.insert(b"drink", b"tea", None, Duration::ZERO);
+    /// assert_eq!(
+    ///     cache.insert_if_version(b"drink", b"whisky", None, Duration::ZERO, version),
+    ///     Err(SegError::Exists)
+    /// );
+    ///
+    /// let version = cache.get(b"drink").expect("not found").version();
+    /// assert!(cache
+    ///     .insert_if_version(b"drink", b"whisky", None, Duration::ZERO, version)
+    ///     .is_ok());
+    /// ```
+    #[cfg(feature = "versions")]
+    pub fn insert_if_version<'a, T: Into<Value<'a>>>(
+        &mut self,
+        key: &'a [u8],
+        value: T,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+        version: u64,
+    ) -> Result<(), SegError> {
+        let current = self
+            .hashtable
+            .get_no_freq_incr(key, &mut self.segments)
+            .ok_or(SegError::NotFound)?;
+        if current.version() != version {
+            return Err(SegError::Exists);
+        }
+        self.insert(key, value, optional, ttl)
+    }
+
+    /// Insert an item which was written elsewhere with the provided version,
+    /// such as when applying a write from another cache instance. The insert
+    /// only succeeds if the version is newer than the version of the item
+    /// currently stored for the key, resolving conflicting writes in favor of
+    /// the last writer. Versions assigned afterwards by this instance will be
+    /// greater than the provided version. Requires the `versions` feature.
+    ///
+    /// ```
+    /// use seg::{Seg, SegError};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    ///
+    /// assert!(cache.insert_versioned(b"drink", b"coffee", None, Duration::ZERO, 10).is_ok());
+    /// assert_eq!(cache.get(b"drink").expect("not found").version(), 10);
+    ///
+    /// // An older write is rejected as 'Exists'
+    /// assert_eq!(
+    ///     cache.insert_versioned(b"drink", b"tea", None, Duration::ZERO, 5),
+    ///     Err(SegError::Exists)
+    /// );
+    ///
+    /// // Local writes continue from the newest version seen
+    /// cache.insert(b"drink", b"whisky", None, Duration::ZERO);
+    /// assert_eq!(cache.get(b"drink").expect("not found").version(), 11);
+    /// ```
+    #[cfg(feature = "versions")]
+    pub fn insert_versioned<'a, T: Into<Value<'a>>>(
+        &mut self,
+        key: &'a [u8],
+        value: T,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+        version: u64,
+    ) -> Result<(), SegError> {
+        if let Some(current) = self.hashtable.get_no_freq_incr(key, &mut self.segments) {
+            if current.version() >= version {
+                return Err(SegError::Exists);
+            }
+        }
+        self.version = self.version.max(version);
+        self.insert_at_version(key, value.into(), optional, ttl, version)
+    }
+
+    // Returns the version to assign to the next write
+    fn next_version(&mut self) -> u64 {
+        self.version += 1;
+        self.version
+    }
+
+    fn insert_at_version(
+        &mut self,
+        key: &[u8],
+        value: Value,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+        version: u64,
+    ) -> Result<(), SegError> {
+        if let Some(cardinality) = self.cardinality.as_mut() {
+            cardinality.record_write(key);
+        }
+
+        if let Some(sizes) = self.sizes.as_mut() {
+            sizes.record_write(key, &value);
+        }
 
         // default optional data is empty
         let optional = optional.unwrap_or(&[]);
@@ -141,7 +313,7 @@ impl Seg {
                 .reserve(size, &mut self.segments)
             {
                 Ok(mut reserved_item) => {
-                    reserved_item.define(key, value, optional);
+                    reserved_item.define(key, value, optional, version);
                     reserved = reserved_item;
                     break;
                 }
@@ -290,8 +462,42 @@ impl Seg {
     pub fn expire(&mut self) -> usize {
         common::time::refresh_clock();
         self.time = Instant::recent();
+        if let Some(cardinality) = self.cardinality.as_mut() {
+            cardinality.report(self.time);
+        }
+        self.segments.forecast();
         self.ttl_buckets
-            .expire(&mut self.hashtable, &mut self.segments)
+            .expire(&mut self.hashtable, &mut self.segments, self.grace)
+    }
+
+    /// Sets the time for which items are kept past their expiry. Until they
+    /// are removed, expired items are still returned by reads, which allows
+    /// stale items to be served in place of misses while the origin is
+    /// degraded. Use [`Seg::ttl`] to tell whether an item has expired. A grace
+    /// period of zero, the default, removes items as soon as they expire.
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// cache.set_grace(Duration::from_secs(10));
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::from_secs(1));
+    ///
+    /// // The expired item is still in the cache
+    /// std::thread::sleep(Duration::from_secs(2));
+    /// cache.expire();
+    /// assert!(cache.get(b"coffee").is_some());
+    /// assert!(cache.ttl(b"coffee").is_none());
+    ///
+    /// // Until the grace period is removed
+    /// cache.set_grace(Duration::ZERO);
+    /// std::thread::sleep(Duration::from_secs(1));
+    /// cache.expire();
+    /// assert!(cache.get(b"coffee").is_none());
+    /// ```
+    pub fn set_grace(&mut self, grace: std::time::Duration) {
+        self.grace = Duration::from_secs(min(u32::MAX as u64, grace.as_secs()) as u32);
     }
 
     pub fn clear(&mut self) -> usize {
@@ -301,6 +507,319 @@ impl Seg {
             .clear(&mut self.hashtable, &mut self.segments)
     }
 
+    /// Invalidate all items currently in the cache in constant time. Unlike
+    /// `clear()`, no segments are freed immediately. Instead, the flush
+    /// generation is advanced and items from earlier generations are treated
+    /// as misses. Their segments are reclaimed incrementally by `expire()`.
+    ///
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// cache.insert(b"coffee", b"strong", None, Duration::ZERO);
+    ///
+    /// cache.flush();
+    /// assert!(cache.get(b"coffee").is_none());
+    ///
+    /// cache.insert(b"coffee", b"decaf", None, Duration::ZERO);
+    /// let item = cache.get(b"coffee").expect("didn't get item back");
+    /// assert_eq!(item.value(), b"decaf");
+    /// ```
+    pub fn flush(&mut self) {
+        self.segments.flush();
+    }
+
+    /// Remove all items with keys which begin with the provided prefix. This
+    /// may be used to flush a single namespace without affecting other items.
+    /// Returns the number of items removed.
+    ///
+    /// *NOTE*: this scans all segments and is relatively expensive
+    ///
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// cache.insert(b"tea:green", b"sencha", None, Duration::ZERO);
+    /// cache.insert(b"coffee:dark", b"strong", None, Duration::ZERO);
+    ///
+    /// assert_eq!(cache.clear_prefix(b"tea:"), 1);
+    /// assert!(cache.get(b"tea:green").is_none());
+    /// assert!(cache.get(b"coffee:dark").is_some());
+    /// ```
+    pub fn clear_prefix(&mut self, prefix: &[u8]) -> usize {
+        let keys = self.keys_with_prefix(prefix);
+        keys.iter().filter(|key| self.delete(key)).count()
+    }
+
+    /// Returns the keys of all live items which begin with the provided
+    /// prefix, in no particular order.
+    ///
+    /// *NOTE*: this visits every segment in use and is relatively expensive
+    ///
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// cache.insert(b"tea:green", b"sencha", None, Duration::ZERO);
+    /// cache.insert(b"coffee:dark", b"strong", None, Duration::ZERO);
+    ///
+    /// let keys = cache.keys_with_prefix(b"tea:");
+    /// assert_eq!(keys, vec![b"tea:green".to_vec().into_boxed_slice()]);
+    /// ```
+    pub fn keys_with_prefix(&mut self, prefix: &[u8]) -> Vec<Box<[u8]>> {
+        self.ttl_buckets
+            .keys_with_prefix(&mut self.hashtable, &mut self.segments, prefix)
+    }
+
+    /// Returns the keys of the live items in a part of the hashtable, so that
+    /// all of the keys can be visited a bounded amount at a time. The keys in
+    /// `count` hashtable buckets are returned, starting with the bucket at the
+    /// cursor, along with the cursor for the next call. A scan starts with a
+    /// cursor of zero and is complete once the returned cursor is zero again.
+    /// Every key which is stored for the whole scan is returned, and keys may
+    /// be returned more than once.
+    ///
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// cache.insert(b"tea", b"green", None, Duration::ZERO);
+    /// cache.insert(b"coffee", b"strong", None, Duration::ZERO);
+    ///
+    /// let mut keys = Vec::new();
+    /// let mut cursor = 0;
+    /// loop {
+    ///     let (mut some, next) = cache.scan_keys(cursor, 64);
+    ///     keys.append(&mut some);
+    ///     if next == 0 {
+    ///         break;
+    ///     }
+    ///     cursor = next;
+    /// }
+    /// keys.sort();
+    /// let expected: Vec<Box<[u8]>> = vec![b"coffee".to_vec().into(), b"tea".to_vec().into()];
+    /// assert_eq!(keys, expected);
+    /// ```
+    pub fn scan_keys(&mut self, cursor: usize, count: usize) -> (Vec<Box<[u8]>>, usize) {
+        let mut keys = Vec::new();
+        let next = self
+            .hashtable
+            .scan_keys(cursor, count, &mut self.segments, &mut keys);
+        (keys, next)
+    }
+
+    /// Returns the time remaining until the item with the provided key
+    /// expires, or `None` if the item is not stored or has already expired.
+    /// Items share the ttl of their segment, so items stored without a ttl,
+    /// or with a ttl beyond the longest one, report a ttl of zero. The item
+    /// can then be stored again with the returned ttl and keep its expiry.
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// assert!(cache.ttl(b"coffee").is_none());
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::from_secs(60));
+    /// let ttl = cache.ttl(b"coffee").expect("not found");
+    /// assert!(ttl <= Duration::from_secs(60));
+    ///
+    /// cache.insert(b"tea", b"green", None, Duration::ZERO);
+    /// assert_eq!(cache.ttl(b"tea"), Some(Duration::ZERO));
+    /// ```
+    pub fn ttl(&mut self, key: &[u8]) -> Option<std::time::Duration> {
+        let id = self.hashtable.get_seg_id(key, &mut self.segments)?;
+        let segment = self.segments.get_mut(id).ok()?;
+        let remaining = segment
+            .ttl()
+            .as_secs()
+            .saturating_sub(segment.create_at().elapsed().as_secs());
+        if remaining == 0 {
+            None
+        } else if self.ttl_buckets.get_bucket_index(segment.ttl())
+            == self.ttl_buckets.get_bucket_index(Duration::from_secs(0))
+        {
+            // items stored without a ttl share the segments of the longest
+            Some(std::time::Duration::ZERO)
+        } else {
+            Some(std::time::Duration::from_secs(remaining as u64))
+        }
+    }
+
+    /// Returns the index of the TTL bucket which holds the item with the key,
+    /// or `None` if the item is not stored. The index is that reported by
+    /// [`Seg::ttl_bucket_stats`].
+    ///
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// assert!(cache.ttl_bucket(b"coffee").is_none());
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::from_secs(60));
+    /// let index = cache.ttl_bucket(b"coffee").expect("not found");
+    /// assert_eq!(cache.ttl_bucket_stats()[0].index, index);
+    /// ```
+    pub fn ttl_bucket(&mut self, key: &[u8]) -> Option<usize> {
+        let id = self.hashtable.get_seg_id(key, &mut self.segments)?;
+        let ttl = self.segments.get_mut(id).ok()?.ttl();
+        Some(self.ttl_buckets.get_bucket_index(ttl))
+    }
+
+    /// Returns whether the item with the key has been read since it was
+    /// written, or `None` if the item is not stored. This does not count as a
+    /// read of the item, so it should be checked before the item is read.
+    ///
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// assert!(cache.accessed(b"coffee").is_none());
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::ZERO);
+    /// assert_eq!(cache.accessed(b"coffee"), Some(false));
+    ///
+    /// assert!(cache.get(b"coffee").is_some());
+    /// assert_eq!(cache.accessed(b"coffee"), Some(true));
+    /// ```
+    pub fn accessed(&mut self, key: &[u8]) -> Option<bool> {
+        self.hashtable
+            .get_freq_by_key(key, &mut self.segments)
+            .map(|freq| freq > 0)
+    }
+
+    /// Changes the TTL of an item without changing its value or optional
+    /// data, and returns the updated item. Since items expire with the segment
+    /// which holds them, the item is copied into a segment of the TTL bucket
+    /// for the new TTL. As with any other write, the item is assigned a new
+    /// version and a new CAS value. This differs from memcached, where a touch
+    /// keeps the CAS value, so a CAS read before the touch no longer matches.
+    /// Returns `NotFound` if there is no such item.
+    ///
+    /// ```
+    /// use seg::{Seg, SegError};
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// assert_eq!(
+    ///     cache.touch(b"coffee", Duration::from_secs(60)).map(|_| ()),
+    ///     Err(SegError::NotFound)
+    /// );
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::from_secs(60));
+    /// let cas = cache.get(b"coffee").expect("not found").cas();
+    /// let item = cache.touch(b"coffee", Duration::from_secs(3600)).expect("not found");
+    /// assert_eq!(item.value(), b"strong");
+    /// assert_ne!(item.cas(), cas);
+    /// assert!(cache.ttl(b"coffee").expect("not found") > Duration::from_secs(60));
+    /// ```
+    pub fn touch(&mut self, key: &[u8], ttl: std::time::Duration) -> Result<Item, SegError> {
+        let (value, optional) = {
+            let item = self
+                .hashtable
+                .get_no_freq_incr(key, &mut self.segments)
+                .ok_or(SegError::NotFound)?;
+            (item.value().to_owned(), item.optional().map(|o| o.to_vec()))
+        };
+
+        let version = self.next_version();
+        self.insert_at_version(key, value.as_value(), optional.as_deref(), ttl, version)?;
+        self.hashtable
+            .get_no_freq_incr(key, &mut self.segments)
+            .ok_or(SegError::NotFound)
+    }
+
+    /// Returns a summary of the segments in each TTL bucket which holds any
+    /// segments, ordered by bucket index.
+    ///
+    /// *NOTE*: this visits every segment in use and is relatively expensive
+    ///
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// assert!(cache.ttl_bucket_stats().is_empty());
+    ///
+    /// cache.insert(b"coffee", b"strong", None, Duration::from_secs(60));
+    /// let stats = cache.ttl_bucket_stats();
+    /// assert_eq!(stats.len(), 1);
+    /// assert_eq!(stats[0].segments, 1);
+    /// assert_eq!(stats[0].live_items, 1);
+    /// assert_eq!(stats[0].dead_bytes, 0);
+    /// ```
+    pub fn ttl_bucket_stats(&self) -> Vec<TtlBucketStats> {
+        self.ttl_buckets.stats(&self.segments)
+    }
+
+    /// Returns a summary of the occupancy of the hashtable, including the
+    /// distribution of items and chain lengths across its buckets. These are
+    /// counted as items are inserted and removed, so this does not visit the
+    /// buckets.
+    ///
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().hash_power(8).build().expect("failed to create cache");
+    /// cache.insert(b"coffee", b"strong", None, Duration::ZERO);
+    ///
+    /// let info = cache.hashtable_info();
+    /// assert_eq!(info.power, 8);
+    /// assert_eq!(info.buckets, 32);
+    /// assert_eq!(info.items, 1);
+    /// assert_eq!(info.occupancy, vec![31, 1]);
+    ///
+    /// cache.delete(b"coffee");
+    /// let info = cache.hashtable_info();
+    /// assert_eq!(info.items, 0);
+    /// assert_eq!(info.occupancy, vec![32]);
+    /// ```
+    pub fn hashtable_info(&self) -> HashTableInfo {
+        self.hashtable.info()
+    }
+
+    /// Remove all items stored in the TTL bucket with the given index. Returns
+    /// the number of segments cleared, or `None` if the index does not refer
+    /// to a valid TTL bucket.
+    pub fn clear_ttl_bucket(&mut self, index: usize) -> Option<usize> {
+        common::time::refresh_clock();
+        self.time = Instant::recent();
+        self.ttl_buckets
+            .clear_bucket(index, &mut self.hashtable, &mut self.segments)
+    }
+
+    /// Combine neighboring segments in every TTL bucket when their live items
+    /// fit into a single segment, without evicting any items. This reclaims
+    /// the space held by removed and replaced items ahead of time, rather than
+    /// waiting for eviction. Returns the number of segments freed.
+    ///
+    /// *NOTE*: this visits every segment in use and is relatively expensive
+    ///
+    /// ```
+    /// use seg::Seg;
+    /// use std::time::Duration;
+    ///
+    /// let mut cache = Seg::builder().build().expect("failed to create cache");
+    /// cache.insert(b"coffee", b"strong", None, Duration::from_secs(60));
+    ///
+    /// // there is only one segment, so there is nothing to compact
+    /// assert_eq!(cache.compact(), 0);
+    /// assert!(cache.get(b"coffee").is_some());
+    /// ```
+    pub fn compact(&mut self) -> usize {
+        common::time::refresh_clock();
+        self.time = Instant::recent();
+        self.segments
+            .compact(&mut self.ttl_buckets, &mut self.hashtable)
+    }
+
     /// Checks the integrity of all segments
     /// *NOTE*: this operation is relatively expensive
     #[cfg(feature = "debug")]
@@ -316,23 +835,120 @@ impl Seg {
     /// Returns an error if the key is invalid, the item is not found, or the
     /// stored value is not a numeric type.
     pub fn wrapping_add(&mut self, key: &[u8], rhs: u64) -> Result<Item, SegError> {
+        self.add(key, rhs, Overflow::Wrap)
+    }
+
+    /// Perform a saturating subtraction on the value stored at the supplied
+    /// key. Returns an error if the key is invalid, the item is not found, or
+    /// the stored value is not a numeric type.
+    pub fn saturating_sub(&mut self, key: &[u8], rhs: u64) -> Result<Item, SegError> {
+        self.sub(key, rhs, Overflow::Saturate)
+    }
+
+    /// Perform an addition on the value stored at the supplied key, handling
+    /// overflow as specified. Returns an error if the key is invalid, the item
+    /// is not found, the stored value is not a numeric type, or the addition
+    /// overflows with [`Overflow::Error`], in which case the value is not
+    /// changed.
+    pub fn add(&mut self, key: &[u8], rhs: u64, overflow: Overflow) -> Result<Item, SegError> {
         let mut item = self
             .hashtable
             .get(key, self.time, &mut self.segments)
             .ok_or(SegError::NotFound)?;
-        item.wrapping_add(rhs)?;
+        item.add(rhs, overflow)?;
+        item.set_version(self.next_version());
         Ok(item)
     }
 
-    /// Perform a saturating subtraction on the value stored at the supplied
-    /// key. Returns an error if the key is invalid, the item is not found, or
-    /// the stored value is not a numeric type.
-    pub fn saturating_sub(&mut self, key: &[u8], rhs: u64) -> Result<Item, SegError> {
+    /// Perform a subtraction on the value stored at the supplied key, handling
+    /// a result below zero as specified. Returns an error as with
+    /// [`Seg::add`].
+    pub fn sub(&mut self, key: &[u8], rhs: u64, underflow: Overflow) -> Result<Item, SegError> {
         let mut item = self
             .hashtable
             .get(key, self.time, &mut self.segments)
             .ok_or(SegError::NotFound)?;
-        item.saturating_sub(rhs)?;
+        item.sub(rhs, underflow)?;
+        item.set_version(self.next_version());
         Ok(item)
     }
+
+    /// Perform a wrapping addition on the value stored at the supplied key. If
+    /// the item is not found, the initial value is stored with the supplied
+    /// optional data and TTL instead, and the addition is not applied. This
+    /// allows a counter to be created by its first increment without a
+    /// separate insert which could race with increments from other clients.
+    pub fn wrapping_add_or_insert(
+        &mut self,
+        key: &[u8],
+        rhs: u64,
+        initial: u64,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+    ) -> Result<Item, SegError> {
+        self.add_or_insert(key, rhs, Overflow::Wrap, initial, optional, ttl)
+    }
+
+    /// Perform a saturating subtraction on the value stored at the supplied
+    /// key. If the item is not found, the initial value is stored instead as
+    /// with [`Seg::wrapping_add_or_insert`].
+    pub fn saturating_sub_or_insert(
+        &mut self,
+        key: &[u8],
+        rhs: u64,
+        initial: u64,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+    ) -> Result<Item, SegError> {
+        self.sub_or_insert(key, rhs, Overflow::Saturate, initial, optional, ttl)
+    }
+
+    /// Perform an addition as with [`Seg::add`]. If the item is not found, the
+    /// initial value is stored instead as with [`Seg::wrapping_add_or_insert`].
+    pub fn add_or_insert(
+        &mut self,
+        key: &[u8],
+        rhs: u64,
+        overflow: Overflow,
+        initial: u64,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+    ) -> Result<Item, SegError> {
+        match self.add(key, rhs, overflow) {
+            Err(SegError::NotFound) => self.insert_numeric(key, initial, optional, ttl),
+            result => result,
+        }
+    }
+
+    /// Perform a subtraction as with [`Seg::sub`]. If the item is not found,
+    /// the initial value is stored instead as with
+    /// [`Seg::wrapping_add_or_insert`].
+    pub fn sub_or_insert(
+        &mut self,
+        key: &[u8],
+        rhs: u64,
+        underflow: Overflow,
+        initial: u64,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+    ) -> Result<Item, SegError> {
+        match self.sub(key, rhs, underflow) {
+            Err(SegError::NotFound) => self.insert_numeric(key, initial, optional, ttl),
+            result => result,
+        }
+    }
+
+    // Insert a numeric value and return the newly stored item.
+    fn insert_numeric(
+        &mut self,
+        key: &[u8],
+        value: u64,
+        optional: Option<&[u8]>,
+        ttl: std::time::Duration,
+    ) -> Result<Item, SegError> {
+        self.insert(key, value, optional, ttl)?;
+        self.hashtable
+            .get(key, self.time, &mut self.segments)
+            .ok_or(SegError::NotFound)
+    }
 }
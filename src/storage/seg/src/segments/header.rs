@@ -15,11 +15,11 @@
 //! │   PREV SEG   │   NEXT SEG   │  CREATE AT   │   MERGE AT   │
 //! │              │              │              │              │
 //! │    32 bit    │    32 bit    │    32 bit    │    32 bit    │
-//! ├──────────────┼──┬──┬────────┴──────────────┴──────────────┤
-//! │     TTL      │  │  │               PADDING                │   Accessible
-//! │              │  │◀─┼──────────────────────────────────────┼──    8 bit
-//! │    32 bit    │8b│8b│                80 bit                │
-//! ├──────────────┴──┴──┴──────────────────────────────────────┤    Evictable
+//! ├──────────────┼──────────────┼──┬──┬───────┴──────────────┤
+//! │     TTL      │  GENERATION  │  │  │       PADDING        │   Accessible
+//! │              │              │  │◀─┼──────────────────────┼──    8 bit
+//! │    32 bit    │    32 bit    │8b│8b│        48 bit        │
+//! ├──────────────┴──────────────┴──┴──┴──────────────────────┤    Evictable
 //! │                          PADDING                          │      8 bit
 //! │                                                           │
 //! │                          128 bit                          │
@@ -56,11 +56,13 @@ pub struct SegmentHeader {
     merge_at: Instant,
     /// The TTL of the segment in seconds
     ttl: u32,
+    /// The flush generation the segment was allocated in
+    generation: u32,
     /// Is the segment accessible?
     accessible: bool,
     /// Is the segment evictable?
     evictable: bool,
-    _pad: [u8; 25],
+    _pad: [u8; 22],
 }
 
 impl SegmentHeader {
@@ -74,10 +76,11 @@ impl SegmentHeader {
             next_seg: None,
             create_at: Instant::recent(),
             ttl: 0,
+            generation: 0,
             merge_at: Instant::recent(),
             accessible: false,
             evictable: false,
-            _pad: [0; 25],
+            _pad: [0; 22],
         }
     }
 
@@ -252,6 +255,18 @@ impl SegmentHeader {
         self.create_at = Instant::recent();
     }
 
+    #[inline]
+    /// Returns the flush generation the segment was allocated in
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    #[inline]
+    /// Set the flush generation, used when taking it from the free queue
+    pub fn set_generation(&mut self, generation: u32) {
+        self.generation = generation;
+    }
+
     #[inline]
     /// Returns the instant at which the segment was merged
     pub fn merge_at(&self) -> Instant {
@@ -18,8 +18,10 @@ mod segments;
 pub(crate) use builder::SegmentsBuilder;
 pub(crate) use error::SegmentsError;
 pub(crate) use header::SegmentHeader;
-pub(crate) use segment::Segment;
+pub(crate) use segment::{LiveItems, Segment};
+pub(crate) use segment::{ITEM_CURRENT, ITEM_CURRENT_BYTES, ITEM_DEAD, ITEM_DEAD_BYTES};
 pub(crate) use segments::Segments;
+pub(crate) use segments::{SEGMENT_CURRENT, SEGMENT_EVICT, SEGMENT_FREE};
 
 #[cfg(test)]
 mod test {
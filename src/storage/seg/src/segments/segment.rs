@@ -4,6 +4,7 @@
 
 use super::{SegmentHeader, SegmentsError};
 use crate::*;
+use core::cell::Cell;
 use core::num::NonZeroU32;
 
 gauge!(ITEM_CURRENT, "current number of live items");
@@ -20,12 +21,49 @@ counter!(ITEM_COMPACTED, "number of items which have been compacted");
 
 pub const SEG_MAGIC: u64 = 0xBADC0FFEEBADCAFE;
 
+/// Running totals of the items and bytes in segments from the current flush
+/// generation. These are updated along with the `ITEM_CURRENT` gauges, so
+/// that the number of live items is known without walking the segments.
+#[derive(Default)]
+pub struct LiveItems {
+    items: Cell<i64>,
+    bytes: Cell<i64>,
+}
+
+impl LiveItems {
+    /// Returns the number of live items.
+    pub fn items(&self) -> i64 {
+        self.items.get()
+    }
+
+    /// Adjust the number of live items and bytes.
+    pub fn add(&self, items: i64, bytes: i64) {
+        self.items.set(self.items.get() + items);
+        self.bytes.set(self.bytes.get() + bytes);
+        ITEM_CURRENT.add(items);
+        ITEM_CURRENT_BYTES.add(bytes);
+    }
+
+    /// Resets the totals to zero, returning the number of items and bytes
+    /// which were live.
+    pub fn take(&self) -> (i64, i64) {
+        let items = self.items.take();
+        let bytes = self.bytes.take();
+        ITEM_CURRENT.sub(items);
+        ITEM_CURRENT_BYTES.sub(bytes);
+        (items, bytes)
+    }
+}
+
 /// A `Segment` is a contiguous allocation of bytes and an associated header
 /// which contains metadata. This structure allows us to operate on mutable
 /// borrows of the header and data sections to perform basic operations.
 pub struct Segment<'a> {
     header: &'a mut SegmentHeader,
     data: &'a mut [u8],
+    /// The live item totals, which are `None` for a segment from before the
+    /// most recent flush, as its items were counted as dead by the flush
+    live: Option<&'a LiveItems>,
 }
 
 impl<'a> Segment<'a> {
@@ -33,8 +71,9 @@ impl<'a> Segment<'a> {
     pub fn from_raw_parts(
         header: &'a mut segments::header::SegmentHeader,
         data: &'a mut [u8],
+        live: Option<&'a LiveItems>,
     ) -> Self {
-        Segment { header, data }
+        Segment { header, data, live }
     }
 
     /// Initialize the segment. Sets the magic bytes in the data segment (if the
@@ -219,6 +258,12 @@ impl<'a> Segment<'a> {
         self.header.create_at()
     }
 
+    /// Returns the flush generation the segment was allocated in
+    #[inline]
+    pub fn generation(&self) -> u32 {
+        self.header.generation()
+    }
+
     /// Mark that the segment has been merged
     #[inline]
     pub fn mark_merged(&mut self) {
@@ -289,8 +334,9 @@ impl<'a> Segment<'a> {
     pub(crate) fn alloc_item(&mut self, size: i32) -> RawItem {
         let offset = self.write_offset() as usize;
         self.incr_item(size);
-        ITEM_CURRENT.increment();
-        ITEM_CURRENT_BYTES.add(size as _);
+        if let Some(live) = self.live {
+            live.add(1, size as _);
+        }
 
         let ptr = unsafe { self.data.as_mut_ptr().add(offset) };
         RawItem::from_ptr(ptr)
@@ -309,10 +355,11 @@ impl<'a> Segment<'a> {
 
         let item_size = item.size() as i64;
 
-        ITEM_CURRENT.decrement();
-        ITEM_CURRENT_BYTES.sub(item_size);
-        ITEM_DEAD.increment();
-        ITEM_DEAD_BYTES.add(item_size);
+        if let Some(live) = self.live {
+            live.add(-1, -item_size);
+            ITEM_DEAD.increment();
+            ITEM_DEAD_BYTES.add(item_size);
+        }
 
         self.check_magic();
         self.decr_item(item_size as i32);
@@ -490,8 +537,9 @@ impl<'a> Segment<'a> {
         // We need to increment the current bytes, because removing items from
         // this segment decrements these as it marks the item as removed. This
         // should result in these stats remaining unchanged by this function.
-        ITEM_CURRENT.add(items_copied);
-        ITEM_CURRENT_BYTES.add(bytes_copied as _);
+        if let Some(live) = self.live {
+            live.add(items_copied, bytes_copied as _);
+        }
 
         Ok(())
     }
@@ -597,6 +645,39 @@ impl<'a> Segment<'a> {
         cutoff
     }
 
+    /// Collects the keys of all live items in the segment which begin with the
+    /// provided prefix.
+    pub(crate) fn keys_with_prefix(
+        &mut self,
+        hashtable: &mut HashTable,
+        prefix: &[u8],
+        keys: &mut Vec<Box<[u8]>>,
+    ) {
+        let max_offset = self.max_item_offset();
+        let mut offset = if cfg!(feature = "magic") {
+            std::mem::size_of_val(&SEG_MAGIC)
+        } else {
+            0
+        };
+
+        while offset <= max_offset {
+            let item = self.get_item_at(offset).unwrap();
+            if item.klen() == 0 {
+                break;
+            }
+
+            item.check_magic();
+
+            if item.key().starts_with(prefix)
+                && hashtable.is_item_at(item.key(), self.id(), offset as u64)
+            {
+                keys.push(item.key().into());
+            }
+
+            offset += item.size();
+        }
+    }
+
     /// Remove all items from the segment, unlinking them from the hashtable.
     /// If expire is true, this is treated as an expiration option. Otherwise it
     /// is treated as an eviction.
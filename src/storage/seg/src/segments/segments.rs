@@ -6,6 +6,7 @@ use crate::eviction::*;
 use crate::item::*;
 use crate::seg::{SEGMENT_REQUEST, SEGMENT_REQUEST_SUCCESS};
 use crate::segments::*;
+use crate::ttl_buckets::TtlBucketStats;
 use core::num::NonZeroU32;
 use datapool::*;
 
@@ -21,7 +22,26 @@ counter!(
 );
 gauge!(SEGMENT_FREE, "current number of free segments");
 counter!(SEGMENT_MERGE, "total number of segments merged");
+counter!(
+    SEGMENT_COMPACT,
+    "number of segments freed by a full compaction pass"
+);
 gauge!(SEGMENT_CURRENT, "current number of segments");
+gauge!(
+    SEGMENT_EVICT_AGE,
+    "age, in seconds, of the most recently evicted segment"
+);
+gauge!(
+    SEGMENT_FILL_RATE,
+    "net number of free segments consumed in the last forecast interval"
+);
+gauge!(
+    EVICT_FORECAST,
+    "estimated time, in seconds, until eviction begins at the current fill rate"
+);
+
+// the minimum number of seconds between updates to the eviction forecast
+const FORECAST_INTERVAL: u32 = 60;
 
 /// `Segments` contain all items within the cache. This struct is a collection
 /// of individual `Segment`s which are represented by a `SegmentHeader` and a
@@ -39,10 +59,16 @@ pub(crate) struct Segments {
     cap: u32,
     /// Head of the free segment queue
     free_q: Option<NonZeroU32>,
-    /// Time last flushed
-    flush_at: Instant,
+    /// Current flush generation
+    generation: u32,
+    /// Items and bytes in segments from the current generation
+    live: LiveItems,
     /// Eviction configuration and state
     evict: Box<Eviction>,
+    /// Time of the last update to the eviction forecast
+    forecast_at: Instant,
+    /// Number of free segments at the last update to the eviction forecast
+    forecast_free: u32,
 }
 
 impl Segments {
@@ -91,8 +117,11 @@ impl Segments {
             let begin = segment_size as usize * idx;
             let end = begin + segment_size as usize;
 
-            let mut segment =
-                Segment::from_raw_parts(&mut headers[idx], &mut data.as_mut_slice()[begin..end]);
+            let mut segment = Segment::from_raw_parts(
+                &mut headers[idx],
+                &mut data.as_mut_slice()[begin..end],
+                None,
+            );
             segment.init();
 
             let id = idx as u32 + 1; // we index segments from 1
@@ -104,6 +133,7 @@ impl Segments {
 
         SEGMENT_CURRENT.set(segments as _);
         SEGMENT_FREE.set(segments as _);
+        EVICT_FORECAST.set(-1);
 
         Ok(Self {
             headers,
@@ -112,8 +142,11 @@ impl Segments {
             free: segments as u32,
             free_q: NonZeroU32::new(1),
             data,
-            flush_at: Instant::now(),
+            generation: 0,
+            live: LiveItems::default(),
             evict: Box::new(Eviction::new(segments, evict_policy)),
+            forecast_at: Instant::recent(),
+            forecast_free: segments as u32,
         })
     }
 
@@ -129,14 +162,100 @@ impl Segments {
         self.free as usize
     }
 
-    /// Returns the time the segments were last flushed
-    pub fn flush_at(&self) -> Instant {
-        self.flush_at
+    /// Updates the forecast of the time until eviction begins, based on the net
+    /// rate at which free segments were consumed since the last update. The
+    /// forecast is `-1` while the number of free segments is not decreasing,
+    /// and `0` once there are no free segments remaining.
+    pub fn forecast(&mut self) {
+        let elapsed = self.forecast_at.elapsed().as_secs();
+        if elapsed < FORECAST_INTERVAL {
+            return;
+        }
+
+        let consumed = self.forecast_free.saturating_sub(self.free);
+        let forecast = if self.free == 0 {
+            0
+        } else if consumed == 0 {
+            -1
+        } else {
+            (self.free as u64 * elapsed as u64 / consumed as u64) as i64
+        };
+
+        SEGMENT_FILL_RATE.set(consumed as _);
+        EVICT_FORECAST.set(forecast);
+
+        self.forecast_at = Instant::recent();
+        self.forecast_free = self.free;
+    }
+
+    // Records the age of a segment which is being evicted. This is the
+    // effective retention period of items in the cache.
+    fn record_evict_age(&self, id: NonZeroU32) {
+        let age = self.headers[id.get() as usize - 1].create_at().elapsed();
+        SEGMENT_EVICT_AGE.set(age.as_secs() as _);
+    }
+
+    /// Returns the current flush generation
+    #[inline]
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Begin a new flush generation. All items in segments which were
+    /// allocated in a prior generation are treated as misses, and the
+    /// segments are reclaimed lazily during expiration.
+    pub fn flush(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+
+        // the items are no longer visible, so they are counted as dead until
+        // their segments are reclaimed
+        let (items, bytes) = self.live.take();
+        ITEM_DEAD.add(items);
+        ITEM_DEAD_BYTES.add(bytes);
+    }
+
+    /// Returns the number of live items in segments from the current flush
+    /// generation.
+    pub(crate) fn live_items(&self) -> usize {
+        self.live.items() as usize
+    }
+
+    /// Adds the segments in the chain which begins with `head` to the stats.
+    /// Items in segments from before the last flush are counted as dead.
+    pub(crate) fn chain_stats(&self, head: NonZeroU32, stats: &mut TtlBucketStats) {
+        let mut next = Some(head);
+        while let Some(id) = next {
+            let header = &self.headers[id.get() as usize - 1];
+
+            let written = header.write_offset().max(0) as u64;
+            let (live_items, live_bytes) = if header.generation() == self.generation {
+                (
+                    header.live_items().max(0) as u64,
+                    header.live_bytes().max(0) as u64,
+                )
+            } else {
+                (0, 0)
+            };
+
+            stats.segments += 1;
+            stats.live_items += live_items;
+            stats.live_bytes += live_bytes;
+            stats.dead_bytes += written.saturating_sub(live_bytes);
+            stats.oldest = stats.oldest.max(header.create_at().elapsed().as_secs());
+            if header.merge_at() > header.create_at() {
+                stats.merged += 1;
+            }
+
+            next = header.next_seg();
+        }
     }
 
-    /// Mark the segments as flushed at a given instant
-    pub fn set_flush_at(&mut self, instant: Instant) {
-        self.flush_at = instant;
+    /// Returns true if the segment holding the item was allocated before the
+    /// most recent flush.
+    pub(crate) fn is_stale(&self, item_info: u64) -> bool {
+        get_seg_id(item_info)
+            .map(|id| self.headers[id.get() as usize - 1].generation() != self.generation)
+            .unwrap_or(false)
     }
 
     /// Retrieve a `RawItem` from the segment id and offset encoded in the
@@ -160,9 +279,16 @@ impl Segments {
 
         let seg_begin = self.segment_size() as usize * (seg_id as usize - 1);
         let seg_end = seg_begin + self.segment_size() as usize;
+        let header = &mut self.headers[seg_id as usize - 1];
+        let live = if header.generation() == self.generation {
+            Some(&self.live)
+        } else {
+            None
+        };
         let mut segment = Segment::from_raw_parts(
-            &mut self.headers[seg_id as usize - 1],
+            header,
             &mut self.data.as_mut_slice()[seg_begin..seg_end],
+            live,
         );
 
         segment.get_item_at(offset)
@@ -216,6 +342,7 @@ impl Segments {
                     let ttl_bucket = &mut ttl_buckets.buckets[bucket_id];
                     if let Some(first_seg) = ttl_bucket.head() {
                         let start = ttl_bucket.next_to_merge().unwrap_or(first_seg);
+                        self.record_evict_age(start);
                         match self.merge_evict(start, hashtable) {
                             Ok(next_to_merge) => {
                                 debug!("merged ttl_bucket: {} seg: {}", bucket_id, start);
@@ -242,6 +369,7 @@ impl Segments {
             _ => {
                 SEGMENT_EVICT.increment();
                 if let Some(id) = self.least_valuable_seg(ttl_buckets) {
+                    self.record_evict_age(id);
                     let result = self
                         .clear_segment(id, hashtable, false)
                         .map_err(|_| SegmentsError::EvictFailure);
@@ -268,6 +396,38 @@ impl Segments {
         }
     }
 
+    /// Run a compaction merge along the segment chain of every TTL bucket,
+    /// combining neighboring segments when their live items fit into a single
+    /// segment. Unlike an eviction merge, no items are removed. Returns the
+    /// number of segments which were returned to the free queue.
+    pub(crate) fn compact(
+        &mut self,
+        ttl_buckets: &mut TtlBuckets,
+        hashtable: &mut HashTable,
+    ) -> usize {
+        let now = Instant::now();
+        let free = self.free;
+
+        for ttl_bucket in ttl_buckets.buckets.iter_mut() {
+            let mut next = ttl_bucket.head();
+            while let Some(id) = next {
+                // segments which cannot be merged are skipped over, and the
+                // merged segments are unlinked from the chain
+                let _ = self.merge_compact(id, hashtable);
+                next = self.headers[id.get() as usize - 1].next_seg();
+            }
+
+            // the next eviction merge may have started at a segment which has
+            // now been freed
+            ttl_bucket.set_next_to_merge(None);
+        }
+
+        let compacted = self.free - free;
+        debug!("compacted: {} segments in {:?}", compacted, now.elapsed());
+        SEGMENT_COMPACT.add(compacted as _);
+        compacted
+    }
+
     /// Returns a mutable `Segment` view for the segment with the specified id
     pub(crate) fn get_mut(&mut self, id: NonZeroU32) -> Result<Segment, SegmentsError> {
         let id = id.get() as usize - 1;
@@ -279,7 +439,13 @@ impl Segments {
 
             let seg_data = &mut self.data.as_mut_slice()[seg_start..seg_end];
 
-            let segment = Segment::from_raw_parts(header, seg_data);
+            let live = if header.generation() == self.generation {
+                Some(&self.live)
+            } else {
+                None
+            };
+
+            let segment = Segment::from_raw_parts(header, seg_data, live);
             segment.check_magic();
             Ok(segment)
         } else {
@@ -308,6 +474,17 @@ impl Segments {
             unsafe {
                 let seg_size = self.segment_size() as usize;
 
+                let live_a = if self.headers[a].generation() == self.generation {
+                    Some(&self.live)
+                } else {
+                    None
+                };
+                let live_b = if self.headers[b].generation() == self.generation {
+                    Some(&self.live)
+                } else {
+                    None
+                };
+
                 let header_a = &mut self.headers[a] as *mut _;
                 let header_b = &mut self.headers[b] as *mut _;
 
@@ -335,8 +512,8 @@ impl Segments {
                     (&mut second[start_a..end_a], &mut first[start_b..end_b])
                 };
 
-                let segment_a = Segment::from_raw_parts(&mut *header_a, data_a);
-                let segment_b = Segment::from_raw_parts(&mut *header_b, data_b);
+                let segment_a = Segment::from_raw_parts(&mut *header_a, data_a, live_a);
+                let segment_b = Segment::from_raw_parts(&mut *header_b, data_b, live_b);
 
                 segment_a.check_magic();
                 segment_b.check_magic();
@@ -437,6 +614,7 @@ impl Segments {
             common::time::refresh_clock();
             self.headers[id_idx].mark_created();
             self.headers[id_idx].mark_merged();
+            self.headers[id_idx].set_generation(self.generation);
 
             id
         }
@@ -778,6 +956,13 @@ impl Segments {
 
             let (mut dst, mut src) = self.get_mut_pair(dst_id, src_id)?;
 
+            // items must not be merged across a flush, as that would either
+            // revive flushed items or hide live ones
+            if dst.generation() != src.generation() {
+                trace!("stop merge: source segment is from another generation");
+                break;
+            }
+
             let dst_start_size = dst.live_bytes();
             let src_start_size = src.live_bytes();
 
@@ -884,6 +1069,13 @@ impl Segments {
 
             let (mut dst, mut src) = self.get_mut_pair(dst_id, src_id)?;
 
+            // items must not be merged across a flush, as that would either
+            // revive flushed items or hide live ones
+            if dst.generation() != src.generation() {
+                trace!("stop merge: source segment is from another generation");
+                break;
+            }
+
             let dst_start_size = dst.live_bytes();
             let src_start_size = src.live_bytes();
 
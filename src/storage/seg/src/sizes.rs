@@ -0,0 +1,70 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+//! Sampling of the key and value sizes of writes. The distributions are used
+//! to choose the segment size, item alignment, and buffer sizes for each
+//! deployment. Only one in every `rate` writes is sampled, which keeps the
+//! cost negligible for write-heavy workloads.
+
+use crate::*;
+
+heatmap!(
+    ITEM_KEY_SIZE,
+    256,
+    "distribution of the size in bytes of the keys of sampled writes"
+);
+heatmap!(
+    ITEM_VALUE_SIZE,
+    1_073_741_824,
+    "distribution of the size in bytes of the values of sampled writes"
+);
+
+/// Records the key and value size of one in every `rate` writes.
+pub(crate) struct SizeSampler {
+    rate: u64,
+    // the number of writes to skip before the next sample
+    countdown: u64,
+}
+
+impl SizeSampler {
+    pub fn new(rate: u64) -> Self {
+        Self { rate, countdown: 0 }
+    }
+
+    /// Returns true if the next write should be sampled.
+    fn sample(&mut self) -> bool {
+        if self.countdown == 0 {
+            self.countdown = self.rate - 1;
+            true
+        } else {
+            self.countdown -= 1;
+            false
+        }
+    }
+
+    pub fn record_write(&mut self, key: &[u8], value: &Value) {
+        if !self.sample() {
+            return;
+        }
+
+        let now = common::time::Instant::<common::time::Nanoseconds<u64>>::now();
+        ITEM_KEY_SIZE.increment(now, key.len() as u64, 1);
+        ITEM_VALUE_SIZE.increment(now, size_of(value) as u64, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let mut sampler = SizeSampler::new(1);
+        assert!((0..10).all(|_| sampler.sample()));
+
+        let mut sampler = SizeSampler::new(4);
+        let sampled = (0..100).filter(|_| sampler.sample()).count();
+        assert_eq!(sampled, 25);
+    }
+}
@@ -11,12 +11,18 @@ use std::time::Duration;
 
 #[test]
 fn sizes() {
-    #[cfg(feature = "magic")]
+    #[cfg(all(feature = "magic", not(feature = "versions")))]
     assert_eq!(ITEM_HDR_SIZE, 9);
 
-    #[cfg(not(feature = "magic"))]
+    #[cfg(all(not(feature = "magic"), not(feature = "versions")))]
     assert_eq!(ITEM_HDR_SIZE, 5);
 
+    #[cfg(all(feature = "magic", feature = "versions"))]
+    assert_eq!(ITEM_HDR_SIZE, 17);
+
+    #[cfg(all(not(feature = "magic"), feature = "versions"))]
+    assert_eq!(ITEM_HDR_SIZE, 13);
+
     assert_eq!(std::mem::size_of::<Segments>(), 64);
     assert_eq!(std::mem::size_of::<SegmentHeader>(), 64);
 
@@ -409,6 +415,155 @@ fn clear() {
     assert!(cache.get(b"coffee").is_none());
 }
 
+#[test]
+fn clear_prefix() {
+    let ttl = Duration::ZERO;
+    let segment_size = 4096;
+    let segments = 64;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Seg::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .build()
+        .expect("failed to create cache");
+
+    for i in 0..100 {
+        let key = format!("a:{}", i);
+        assert!(cache.insert(key.as_bytes(), b"value", None, ttl).is_ok());
+        let key = format!("b:{}", i);
+        assert!(cache.insert(key.as_bytes(), b"value", None, ttl).is_ok());
+    }
+    // overwritten items should only be counted once
+    assert!(cache.insert(b"a:0", b"updated", None, ttl).is_ok());
+    assert_eq!(cache.items(), 200);
+
+    assert_eq!(cache.clear_prefix(b"a:"), 100);
+    assert_eq!(cache.items(), 100);
+    assert!(cache.get(b"a:0").is_none());
+    assert!(cache.get(b"b:0").is_some());
+
+    assert_eq!(cache.clear_prefix(b"a:"), 0);
+}
+
+#[test]
+fn flush() {
+    let ttl = Duration::ZERO;
+    let segment_size = 4096;
+    let segments = 64;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Seg::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .build()
+        .expect("failed to create cache");
+
+    assert!(cache.insert(b"coffee", b"strong", None, ttl).is_ok());
+    assert!(cache.insert(b"tea", b"green", None, ttl).is_ok());
+    let cas = cache.get(b"tea").unwrap().cas();
+    assert_eq!(cache.segments.free(), segments - 1);
+    assert_eq!(cache.segments.live_items(), 2);
+
+    // flushing does not free any segments, but items are no longer visible
+    cache.flush();
+    assert_eq!(cache.segments.free(), segments - 1);
+    assert_eq!(cache.segments.live_items(), 0);
+    assert!(cache.get(b"coffee").is_none());
+    assert!(cache.get_no_freq_incr(b"coffee").is_none());
+    assert!(!cache.delete(b"coffee"));
+    assert_eq!(
+        cache.cas(b"tea", b"black", None, ttl, cas),
+        Err(SegError::NotFound)
+    );
+
+    // new items are written into a segment from the new generation
+    assert!(cache.insert(b"juice", b"orange", None, ttl).is_ok());
+    assert_eq!(cache.segments.free(), segments - 2);
+    assert!(cache.get(b"juice").is_some());
+    assert_eq!(cache.segments.live_items(), 1);
+
+    // only the keys of items stored since the flush are listed
+    assert_eq!(
+        cache.keys_with_prefix(b""),
+        vec![b"juice".to_vec().into_boxed_slice()]
+    );
+    assert_eq!(cache.clear_prefix(b"coffee"), 0);
+
+    // the old segment is reclaimed on expiration
+    std::thread::sleep(Duration::from_millis(5));
+    cache.expire();
+    assert_eq!(cache.segments.free(), segments - 1);
+    assert!(cache.get(b"juice").is_some());
+    assert!(cache.get(b"tea").is_none());
+    assert_eq!(cache.segments.live_items(), 1);
+}
+
+#[test]
+fn clear_ttl_bucket() {
+    let segment_size = 4096;
+    let segments = 64;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Seg::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .build()
+        .expect("failed to create cache");
+
+    let short = Duration::from_secs(10);
+    let long = Duration::from_secs(3600);
+    assert!(cache.insert(b"short", b"value", None, short).is_ok());
+    assert!(cache.insert(b"long", b"value", None, long).is_ok());
+    assert_eq!(cache.segments.free(), segments - 2);
+
+    let index = cache.ttl_buckets.get_bucket_index(short);
+    assert_eq!(cache.clear_ttl_bucket(index), Some(1));
+    assert_eq!(cache.segments.free(), segments - 1);
+    assert!(cache.get(b"short").is_none());
+    assert!(cache.get(b"long").is_some());
+
+    assert_eq!(cache.clear_ttl_bucket(usize::MAX), None);
+}
+
+#[test]
+fn compact() {
+    let ttl = Duration::from_secs(3600);
+    let segment_size = 4096;
+    let segments = 64;
+    let heap_size = segments * segment_size as usize;
+
+    let mut cache = Seg::builder()
+        .segment_size(segment_size)
+        .heap_size(heap_size)
+        .build()
+        .expect("failed to create cache");
+
+    let value = [0; 128];
+    for i in 0..256 {
+        let key = format!("{}", i);
+        assert!(cache.insert(key.as_bytes(), &value[..], None, ttl).is_ok());
+    }
+    let used = segments - cache.segments.free();
+    assert!(used > 4);
+
+    // leave each segment mostly empty, but not empty enough to be freed
+    for i in (0..256).filter(|i| i % 4 != 0) {
+        let key = format!("{}", i);
+        assert!(cache.delete(key.as_bytes()));
+    }
+    assert_eq!(segments - cache.segments.free(), used);
+
+    assert!(cache.compact() > 0);
+    assert!(segments - cache.segments.free() < used);
+    assert_eq!(cache.items(), 64);
+    for i in (0..256).filter(|i| i % 4 == 0) {
+        let key = format!("{}", i);
+        let item = cache.get(key.as_bytes()).expect("item was not compacted");
+        assert_eq!(item.value(), &value[..]);
+    }
+}
+
 #[test]
 fn wrapping_add() {
     let ttl = Duration::ZERO;
@@ -483,6 +638,336 @@ fn saturating_sub() {
     assert_eq!(item.value(), 0, "item is: {:?}", item);
 }
 
+#[test]
+fn overflow() {
+    let ttl = Duration::ZERO;
+    let mut cache = Seg::builder()
+        .segment_size(4096)
+        .heap_size(4096 * 64)
+        .build()
+        .expect("failed to create cache");
+
+    // increments at the largest value
+    assert!(cache.insert(b"coffee", u64::MAX - 1, None, ttl).is_ok());
+    let item = cache
+        .add(b"coffee", 1, Overflow::Error)
+        .expect("failed to increment");
+    assert_eq!(item.value(), u64::MAX, "item is: {:?}", item);
+    assert_eq!(
+        cache.add(b"coffee", 1, Overflow::Error).map(|_| ()),
+        Err(SegError::Overflow)
+    );
+    assert_eq!(item.value(), u64::MAX, "item is: {:?}", item);
+    let item = cache
+        .add(b"coffee", 1, Overflow::Saturate)
+        .expect("failed to increment");
+    assert_eq!(item.value(), u64::MAX, "item is: {:?}", item);
+    let item = cache
+        .add(b"coffee", 2, Overflow::Wrap)
+        .expect("failed to increment");
+    assert_eq!(item.value(), 1, "item is: {:?}", item);
+
+    // decrements below zero
+    let item = cache
+        .sub(b"coffee", 1, Overflow::Error)
+        .expect("failed to decrement");
+    assert_eq!(item.value(), 0, "item is: {:?}", item);
+    assert_eq!(
+        cache.sub(b"coffee", 1, Overflow::Error).map(|_| ()),
+        Err(SegError::Overflow)
+    );
+    assert_eq!(item.value(), 0, "item is: {:?}", item);
+    let item = cache
+        .sub(b"coffee", 1, Overflow::Saturate)
+        .expect("failed to decrement");
+    assert_eq!(item.value(), 0, "item is: {:?}", item);
+    let item = cache
+        .sub(b"coffee", 2, Overflow::Wrap)
+        .expect("failed to decrement");
+    assert_eq!(item.value(), u64::MAX - 1, "item is: {:?}", item);
+
+    // an item which is created does not apply the delta, so cannot overflow
+    let item = cache
+        .add_or_insert(b"tea", u64::MAX, Overflow::Error, u64::MAX, None, ttl)
+        .expect("failed to insert");
+    assert_eq!(item.value(), u64::MAX, "item is: {:?}", item);
+}
+
+#[test]
+fn arithmetic_or_insert() {
+    let ttl = Duration::ZERO;
+    let mut cache = Seg::builder()
+        .segment_size(4096)
+        .heap_size(4096 * 64)
+        .build()
+        .expect("failed to create cache");
+
+    // a missing key is created with the initial value
+    let item = cache
+        .wrapping_add_or_insert(b"coffee", 1, 10, None, ttl)
+        .expect("failed to insert");
+    assert_eq!(item.value(), 10, "item is: {:?}", item);
+    assert_eq!(cache.items(), 1);
+
+    // an existing key is updated in place
+    let item = cache
+        .wrapping_add_or_insert(b"coffee", 1, 10, None, ttl)
+        .expect("failed to increment");
+    assert_eq!(item.value(), 11, "item is: {:?}", item);
+
+    let item = cache
+        .saturating_sub_or_insert(b"coffee", 20, 10, None, ttl)
+        .expect("failed to decrement");
+    assert_eq!(item.value(), 0, "item is: {:?}", item);
+
+    let item = cache
+        .saturating_sub_or_insert(b"tea", 1, 5, None, ttl)
+        .expect("failed to insert");
+    assert_eq!(item.value(), 5, "item is: {:?}", item);
+    assert_eq!(cache.items(), 2);
+
+    // values which are not numeric are not replaced
+    assert!(cache.insert(b"water", b"still", None, ttl).is_ok());
+    assert_eq!(
+        cache
+            .wrapping_add_or_insert(b"water", 1, 0, None, ttl)
+            .map(|_| ()),
+        Err(SegError::NotNumeric)
+    );
+}
+
+#[test]
+fn admission() {
+    let ttl = Duration::ZERO;
+    let mut cache = Seg::builder()
+        .segment_size(4096)
+        .heap_size(4096 * 64)
+        .admission(Admission::Frequency { threshold: 2 })
+        .build()
+        .expect("failed to create cache");
+
+    // the first write of a new key is rejected
+    assert_eq!(
+        cache.insert(b"coffee", b"strong", None, ttl),
+        Err(SegError::NotAdmitted)
+    );
+    assert!(cache.get(b"coffee").is_none());
+
+    // the read and the retry make the key frequent enough to be admitted
+    assert!(cache.insert(b"coffee", b"strong", None, ttl).is_ok());
+    assert_eq!(cache.items(), 1);
+
+    // replacing an existing item is always admitted
+    assert!(cache.insert(b"coffee", b"hot", None, ttl).is_ok());
+    let item = cache.get(b"coffee").expect("didn't get item");
+    assert_eq!(item.value(), b"hot", "item is: {:?}", item);
+}
+
+#[cfg(feature = "versions")]
+#[test]
+fn versions() {
+    let ttl = Duration::ZERO;
+    let mut cache = Seg::builder()
+        .segment_size(4096)
+        .heap_size(4096 * 64)
+        .build()
+        .expect("failed to create cache");
+
+    // every write assigns a new, increasing version
+    assert!(cache.insert(b"coffee", 1, None, ttl).is_ok());
+    let first = cache.get(b"coffee").unwrap().version();
+    assert!(cache.insert(b"tea", b"green", None, ttl).is_ok());
+    assert!(cache.get(b"tea").unwrap().version() > first);
+
+    // in-place updates also advance the version
+    let item = cache
+        .wrapping_add(b"coffee", 1)
+        .expect("failed to increment");
+    assert!(item.version() > first);
+    let second = cache.get(b"coffee").unwrap().version();
+    assert_eq!(item.version(), second);
+
+    // versions keep increasing after the key is deleted and rewritten
+    assert!(cache.delete(b"coffee"));
+    assert!(cache.insert(b"coffee", 1, None, ttl).is_ok());
+    assert!(cache.get(b"coffee").unwrap().version() > second);
+
+    // conditional writes require the current version
+    let current = cache.get(b"coffee").unwrap().version();
+    assert_eq!(
+        cache.insert_if_version(b"coffee", 2, None, ttl, second),
+        Err(SegError::Exists)
+    );
+    assert!(cache
+        .insert_if_version(b"coffee", 2, None, ttl, current)
+        .is_ok());
+
+    // writes from elsewhere are only applied if they are newer, and local
+    // writes continue from the newest version seen
+    let current = cache.get(b"coffee").unwrap().version();
+    assert_eq!(
+        cache.insert_versioned(b"coffee", 3, None, ttl, current),
+        Err(SegError::Exists)
+    );
+    assert!(cache
+        .insert_versioned(b"coffee", 3, None, ttl, current + 100)
+        .is_ok());
+    assert_eq!(cache.get(b"coffee").unwrap().version(), current + 100);
+    assert!(cache.insert(b"coffee", 4, None, ttl).is_ok());
+    assert_eq!(cache.get(b"coffee").unwrap().version(), current + 101);
+}
+
+#[test]
+fn touch() {
+    let mut cache = Seg::builder()
+        .segment_size(4096)
+        .heap_size(4096 * 64)
+        .build()
+        .expect("failed to create cache");
+
+    assert_eq!(
+        cache.touch(b"coffee", Duration::from_secs(60)).map(|_| ()),
+        Err(SegError::NotFound)
+    );
+
+    assert!(cache
+        .insert(
+            b"coffee",
+            b"strong",
+            Some(b"flags"),
+            Duration::from_secs(60)
+        )
+        .is_ok());
+    #[cfg(feature = "versions")]
+    let version = cache.get(b"coffee").unwrap().version();
+
+    // the ttl can be both extended and shortened
+    let item = cache
+        .touch(b"coffee", Duration::from_secs(3600))
+        .expect("failed to touch");
+    assert_eq!(item.value(), b"strong");
+    assert_eq!(item.optional(), Some(&b"flags"[..]));
+    #[cfg(feature = "versions")]
+    assert!(item.version() > version);
+    assert!(cache.ttl(b"coffee").unwrap() > Duration::from_secs(60));
+
+    assert!(cache.touch(b"coffee", Duration::from_secs(10)).is_ok());
+    assert!(cache.ttl(b"coffee").unwrap() <= Duration::from_secs(10));
+    assert_eq!(cache.items(), 1);
+
+    // numeric values remain numeric
+    assert!(cache.insert(b"count", 7, None, Duration::ZERO).is_ok());
+    assert!(cache.touch(b"count", Duration::from_secs(60)).is_ok());
+    let item = cache
+        .wrapping_add(b"count", 1)
+        .expect("failed to increment");
+    assert_eq!(item.value(), 8);
+}
+
+#[test]
+fn live_items() {
+    let ttl = Duration::ZERO;
+    let mut cache = Seg::builder()
+        .segment_size(4096)
+        .heap_size(4096 * 64)
+        .hash_power(8)
+        .build()
+        .expect("failed to create cache");
+
+    assert_eq!(cache.live_items(), 0);
+
+    let keys: Vec<Vec<u8>> = (0..32).map(|i| format!("key{}", i).into_bytes()).collect();
+    for key in &keys {
+        assert!(cache.insert(key, b"value", None, ttl).is_ok());
+    }
+    assert_eq!(cache.live_items(), 32);
+
+    // overwrites and deletes are reflected in the count
+    assert!(cache.insert(b"key0", b"other", None, ttl).is_ok());
+    assert!(cache.delete(b"key1"));
+    assert_eq!(cache.live_items(), 31);
+
+    // items from before a flush are not counted
+    cache.flush();
+    assert_eq!(cache.live_items(), 0);
+}
+
+#[test]
+fn hashtable_info() {
+    let ttl = Duration::ZERO;
+    let mut cache = Seg::builder()
+        .segment_size(4096)
+        .heap_size(4096 * 64)
+        .hash_power(3)
+        .overflow_factor(1.0)
+        .build()
+        .expect("failed to create cache");
+
+    let info = cache.hashtable_info();
+    assert_eq!(info.buckets, 1);
+    assert_eq!(info.overflow_buckets, 1);
+    assert_eq!(info.items, 0);
+    assert_eq!(info.chain_lengths[0], 1);
+    assert_eq!(info.occupancy, vec![1]);
+
+    // the eighth item chains the overflow bucket onto the primary bucket
+    for i in 0..14 {
+        let v = format!("{}", i);
+        assert!(cache.insert(v.as_bytes(), v.as_bytes(), None, ttl).is_ok());
+    }
+    let info = cache.hashtable_info();
+    assert_eq!(info.overflow_used, 1);
+    assert_eq!(info.items, 14);
+    assert_eq!(info.chain_lengths[0], 0);
+    assert_eq!(info.chain_lengths[1], 1);
+    assert_eq!(info.occupancy.len(), 15);
+    assert_eq!(info.occupancy[14], 1);
+
+    // overwrites do not change the counts, removals do
+    assert!(cache.insert(b"0", b"other", None, ttl).is_ok());
+    assert!(cache.delete(b"1"));
+    let info = cache.hashtable_info();
+    assert_eq!(info.items, 13);
+    assert_eq!(info.occupancy.len(), 14);
+    assert_eq!(info.occupancy[13], 1);
+
+    for i in 0..14 {
+        let v = format!("{}", i);
+        cache.delete(v.as_bytes());
+    }
+    let info = cache.hashtable_info();
+    assert_eq!(info.items, 0);
+    assert_eq!(info.chain_lengths[1], 1);
+    assert_eq!(info.occupancy, vec![1]);
+}
+
+#[test]
+fn ttl() {
+    let mut cache = Seg::builder()
+        .segment_size(4096)
+        .heap_size(4096 * 64)
+        .build()
+        .expect("failed to create cache");
+
+    assert_eq!(cache.ttl(b"coffee"), None);
+
+    assert!(cache
+        .insert(b"coffee", b"strong", None, Duration::from_secs(60))
+        .is_ok());
+    let ttl = cache.ttl(b"coffee").expect("no ttl");
+    assert!(ttl > Duration::ZERO && ttl <= Duration::from_secs(60));
+
+    // items stored without a ttl report a zero ttl
+    assert!(cache.insert(b"tea", b"green", None, Duration::ZERO).is_ok());
+    assert_eq!(cache.ttl(b"tea"), Some(Duration::ZERO));
+
+    assert!(cache.delete(b"coffee"));
+    assert_eq!(cache.ttl(b"coffee"), None);
+
+    cache.flush();
+    assert_eq!(cache.ttl(b"tea"), None);
+}
+
 #[test]
 // This test caught a case where we interpreted old data as part of an item
 // header. Specifically, the first insert sets bytes that will be in-range for
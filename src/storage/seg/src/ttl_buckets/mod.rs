@@ -13,6 +13,7 @@
 //!
 
 mod error;
+mod stats;
 mod ttl_bucket;
 #[allow(clippy::module_inception)]
 mod ttl_buckets;
@@ -21,6 +22,7 @@ mod ttl_buckets;
 mod tests;
 
 pub use error::TtlBucketsError;
+pub use stats::TtlBucketStats;
 pub use ttl_bucket::TtlBucket;
 pub use ttl_buckets::TtlBuckets;
 
@@ -28,6 +30,10 @@ use rustcommon_metrics::*;
 
 counter!(SEGMENT_CLEAR, "number of segments cleared");
 counter!(SEGMENT_EXPIRE, "number of segments expired");
+counter!(
+    SEGMENT_RECLAIM,
+    "number of segments from before a flush which were reclaimed"
+);
 counter!(
     CLEAR_TIME,
     "amount of time, in nanoseconds, spent clearing segments"
@@ -0,0 +1,31 @@
+// Copyright 2022 Twitter, Inc.
+// Licensed under the Apache License, Version 2.0
+// http://www.apache.org/licenses/LICENSE-2.0
+
+/// A summary of the segments in one [`TtlBucket`], which is useful for
+/// capacity planning and for tuning eviction. Byte counts include the item
+/// headers.
+///
+/// [`TtlBucket`]: crate::ttl_buckets::TtlBucket
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TtlBucketStats {
+    /// The index of the bucket, as used to clear the bucket
+    pub index: usize,
+    /// The TTL, in seconds, of the items stored in the bucket. Items with
+    /// nearby TTLs share a bucket.
+    pub ttl: u32,
+    /// The number of segments in the bucket
+    pub segments: usize,
+    /// The number of items which may still be read
+    pub live_items: u64,
+    /// The number of bytes used by items which may still be read
+    pub live_bytes: u64,
+    /// The number of bytes written for items which have since been removed,
+    /// replaced, or flushed. This is reclaimed when the segment is expired,
+    /// evicted, or merged.
+    pub dead_bytes: u64,
+    /// The age, in seconds, of the oldest segment in the bucket
+    pub oldest: u32,
+    /// The number of segments which have been merged with their neighbors
+    pub merged: usize,
+}
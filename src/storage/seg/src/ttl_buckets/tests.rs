@@ -97,3 +97,31 @@ fn bucket_index() {
         1023
     );
 }
+
+#[test]
+fn bucket_index_far_future() {
+    let ttl_buckets = TtlBuckets::new();
+
+    // memcache treats expiration times beyond 30 days as absolute, so TTLs on
+    // either side of the boundary must be placed consistently
+    let thirty_days = 60 * 60 * 24 * 30;
+    assert_eq!(
+        ttl_buckets.get_bucket_index(Duration::from_secs(thirty_days)),
+        768 + (thirty_days / 32_768) as usize
+    );
+    assert_eq!(
+        ttl_buckets.get_bucket_index(Duration::from_secs(thirty_days + 1)),
+        768 + (thirty_days / 32_768) as usize
+    );
+
+    // TTLs which exceed the range of a signed 32bit integer are clamped to the
+    // max TTL rather than wrapping
+    for ttl in [i32::MAX as u32, i32::MAX as u32 + 1, u32::MAX - 1, u32::MAX] {
+        assert_eq!(
+            ttl_buckets.get_bucket_index(Duration::from_secs(ttl)),
+            1023,
+            "ttl: {}",
+            ttl
+        );
+    }
+}
@@ -26,7 +26,7 @@
 //! └──────────────────────────────────────────────────────────┘
 //! ```
 
-use super::{SEGMENT_CLEAR, SEGMENT_EXPIRE};
+use super::{SEGMENT_CLEAR, SEGMENT_EXPIRE, SEGMENT_RECLAIM};
 use crate::*;
 use core::num::NonZeroU32;
 
@@ -66,6 +66,11 @@ impl TtlBucket {
         self.head = id;
     }
 
+    /// Returns the TTL, in seconds, of items stored in the `TtlBucket`.
+    pub fn ttl(&self) -> u32 {
+        self.ttl as u32
+    }
+
     /// Returns the segment ID of the next segment to merge within the
     /// `TtlBucket`.
     pub fn next_to_merge(&self) -> Option<NonZeroU32> {
@@ -78,8 +83,16 @@ impl TtlBucket {
     }
 
     /// Expire segments from this TtlBucket, returns the number of segments
-    /// expired.
-    pub(super) fn expire(&mut self, hashtable: &mut HashTable, segments: &mut Segments) -> usize {
+    /// expired. Segments from before the most recent flush are also removed,
+    /// limited by the provided reclaim budget which is decremented for each
+    /// segment reclaimed.
+    pub(super) fn expire(
+        &mut self,
+        hashtable: &mut HashTable,
+        segments: &mut Segments,
+        grace: Duration,
+        reclaim: &mut usize,
+    ) -> usize {
         if self.head.is_none() {
             return 0;
         }
@@ -90,9 +103,11 @@ impl TtlBucket {
         loop {
             let seg_id = self.head;
             if let Some(seg_id) = seg_id {
-                let flush_at = segments.flush_at();
+                let generation = segments.generation();
                 let mut segment = segments.get_mut(seg_id).unwrap();
-                if segment.create_at() + segment.ttl() <= ts || segment.create_at() < flush_at {
+                let is_expired = segment.create_at() + segment.ttl() + grace <= ts;
+                let is_stale = segment.generation() != generation && *reclaim > 0;
+                if is_expired || is_stale {
                     if let Some(next) = segment.next_seg() {
                         self.head = Some(next);
                     } else {
@@ -101,7 +116,12 @@ impl TtlBucket {
                     }
                     let _ = segment.clear(hashtable, true);
                     segments.push_free(seg_id);
-                    SEGMENT_EXPIRE.increment();
+                    if is_expired {
+                        SEGMENT_EXPIRE.increment();
+                    } else {
+                        SEGMENT_RECLAIM.increment();
+                        *reclaim -= 1;
+                    }
                     expired += 1;
                 } else {
                     return expired;
@@ -150,6 +170,10 @@ impl TtlBucket {
                 if let Some(tail_id) = self.tail {
                     let mut tail = segments.get_mut(tail_id).unwrap();
                     tail.set_next_seg(Some(id));
+                    // the tail no longer receives writes once it is replaced
+                    common::events::publish(common::events::Event::SegmentSealed {
+                        id: tail_id.get(),
+                    });
                 }
             }
 
@@ -192,13 +216,16 @@ impl TtlBucket {
 
         loop {
             if let Some(id) = self.tail {
+                let generation = segments.generation();
                 if let Ok(mut segment) = segments.get_mut(id) {
                     if !segment.accessible() {
                         continue;
                     }
                     let offset = segment.write_offset() as usize;
                     trace!("offset: {}", offset);
-                    if offset + size <= seg_size {
+                    // items written after a flush must go into a segment from
+                    // the current generation
+                    if segment.generation() == generation && offset + size <= seg_size {
                         let size = size as i32;
                         let item = segment.alloc_item(size);
                         return Ok(ReservedItem::new(item, segment.id(), offset));
@@ -22,6 +22,7 @@
 
 use super::{CLEAR_TIME, EXPIRE_TIME};
 use crate::*;
+use core::cmp::min;
 
 const N_BUCKET_PER_STEP_N_BIT: usize = 8;
 const N_BUCKET_PER_STEP: usize = 1 << N_BUCKET_PER_STEP_N_BIT;
@@ -43,6 +44,10 @@ const TTL_BOUNDARY_3: i32 = 1 << (TTL_BUCKET_INTERVAL_N_BIT_3 + N_BUCKET_PER_STE
 const MAX_N_TTL_BUCKET: usize = N_BUCKET_PER_STEP * 4;
 const MAX_TTL_BUCKET_IDX: usize = MAX_N_TTL_BUCKET - 1;
 
+// the maximum number of segments from before a flush which are reclaimed in a
+// single call to expire, bounding the time spent reclaiming memory
+const RECLAIM_BATCH: usize = 64;
+
 pub struct TtlBuckets {
     pub(crate) buckets: Box<[TtlBucket]>,
     pub(crate) last_expired: Instant,
@@ -81,7 +86,8 @@ impl TtlBuckets {
 
     /// Get the index of the `TtlBucket` for the given TTL.
     pub(crate) fn get_bucket_index(&self, ttl: Duration) -> usize {
-        let ttl = ttl.as_secs() as i32;
+        // far-future TTLs are clamped rather than wrapping to negative values
+        let ttl = min(ttl.as_secs(), i32::MAX as u32) as i32;
         if ttl <= 0 {
             self.buckets.len() - 1
         } else if ttl & !(TTL_BOUNDARY_1 - 1) == 0 {
@@ -100,6 +106,25 @@ impl TtlBuckets {
         }
     }
 
+    /// Summarize the segments in each `TtlBucket` which holds any segments,
+    /// ordered by bucket index.
+    pub(crate) fn stats(&self, segments: &Segments) -> Vec<TtlBucketStats> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter_map(|(index, bucket)| {
+                let head = bucket.head()?;
+                let mut stats = TtlBucketStats {
+                    index,
+                    ttl: bucket.ttl(),
+                    ..Default::default()
+                };
+                segments.chain_stats(head, &mut stats);
+                Some(stats)
+            })
+            .collect()
+    }
+
     // TODO(bmartin): confirm handling for negative TTLs here...
     /// Get a mutable reference to the `TtlBucket` for the given TTL.
     pub(crate) fn get_mut_bucket(&mut self, ttl: Duration) -> &mut TtlBucket {
@@ -110,7 +135,14 @@ impl TtlBuckets {
         unsafe { self.buckets.get_unchecked_mut(index) }
     }
 
-    pub(crate) fn expire(&mut self, hashtable: &mut HashTable, segments: &mut Segments) -> usize {
+    /// Expires the segments which have been expired for longer than the grace
+    /// period, returning the number of segments expired.
+    pub(crate) fn expire(
+        &mut self,
+        hashtable: &mut HashTable,
+        segments: &mut Segments,
+        grace: Duration,
+    ) -> usize {
         let now = Instant::now();
 
         if now == self.last_expired {
@@ -121,8 +153,9 @@ impl TtlBuckets {
 
         let start = Instant::now();
         let mut expired = 0;
+        let mut reclaim = RECLAIM_BATCH;
         for bucket in self.buckets.iter_mut() {
-            expired += bucket.expire(hashtable, segments);
+            expired += bucket.expire(hashtable, segments, grace, &mut reclaim);
         }
         let duration = start.elapsed();
         debug!("expired: {} segments in {:?}", expired, duration);
@@ -136,7 +169,6 @@ impl TtlBuckets {
         for bucket in self.buckets.iter_mut() {
             cleared += bucket.clear(hashtable, segments);
         }
-        segments.set_flush_at(Instant::now());
         let duration = start.elapsed();
         debug!("expired: {} segments in {:?}", cleared, duration);
         CLEAR_TIME.add(duration.as_nanos() as _);
@@ -144,6 +176,52 @@ impl TtlBuckets {
     }
 }
 
+impl TtlBuckets {
+    /// Clear all segments from the `TtlBucket` with the given index. Returns
+    /// the number of segments cleared, or `None` if the index is invalid.
+    pub(crate) fn clear_bucket(
+        &mut self,
+        index: usize,
+        hashtable: &mut HashTable,
+        segments: &mut Segments,
+    ) -> Option<usize> {
+        let start = Instant::now();
+        let cleared = self.buckets.get_mut(index)?.clear(hashtable, segments);
+        let duration = start.elapsed();
+        debug!(
+            "cleared: {} segments from bucket {} in {:?}",
+            cleared, index, duration
+        );
+        CLEAR_TIME.add(duration.as_nanos() as _);
+        Some(cleared)
+    }
+
+    /// Collect the keys of all live items which begin with the provided
+    /// prefix by walking the segment chain of every `TtlBucket`.
+    pub(crate) fn keys_with_prefix(
+        &self,
+        hashtable: &mut HashTable,
+        segments: &mut Segments,
+        prefix: &[u8],
+    ) -> Vec<Box<[u8]>> {
+        let generation = segments.generation();
+        let mut keys = Vec::new();
+        for bucket in self.buckets.iter() {
+            let mut next = bucket.head();
+            while let Some(id) = next {
+                let mut segment = segments.get_mut(id).unwrap();
+                // the items of a segment from before a flush are stale, as
+                // they would be for `Segments::is_stale`, until it is reused
+                if segment.generation() == generation {
+                    segment.keys_with_prefix(hashtable, prefix, &mut keys);
+                }
+                next = segment.next_seg();
+            }
+        }
+        keys
+    }
+}
+
 impl Default for TtlBuckets {
     fn default() -> Self {
         Self::new()